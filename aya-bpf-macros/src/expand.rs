@@ -108,17 +108,34 @@ pub struct Probe {
     kind: ProbeKind,
     item: ItemFn,
     name: String,
+    sleepable: bool,
 }
 
 impl Probe {
     pub fn from_syn(kind: ProbeKind, mut args: Args, item: ItemFn) -> Result<Probe> {
+        let sleepable = sleepable_arg(&mut args)?;
+        if sleepable && matches!(kind, ProbeKind::KProbe | ProbeKind::KRetProbe) {
+            return Err(Error::new_spanned(
+                &item.sig.ident,
+                "kprobes can't be sleepable",
+            ));
+        }
         let name = name_arg(&mut args)?.unwrap_or_else(|| item.sig.ident.to_string());
 
-        Ok(Probe { kind, item, name })
+        Ok(Probe {
+            kind,
+            item,
+            name,
+            sleepable,
+        })
     }
 
     pub fn expand(&self) -> Result<TokenStream> {
-        let section_name = format!("{}/{}", self.kind, self.name);
+        let section_name = if self.sleepable {
+            format!("{}.s/{}", self.kind, self.name)
+        } else {
+            format!("{}/{}", self.kind, self.name)
+        };
         let fn_vis = &self.item.vis;
         let fn_name = &self.item.sig.ident;
         let item = &self.item;
@@ -201,6 +218,7 @@ pub struct Xdp {
     item: ItemFn,
     name: Option<String>,
     frags: bool,
+    map: Option<String>,
 }
 
 impl Xdp {
@@ -217,16 +235,34 @@ impl Xdp {
                 ));
             }
         }
+        let map = pop_arg(&mut args, "map");
+        if let Some(map) = &map {
+            if map != "cpumap" && map != "devmap" {
+                return Err(Error::new_spanned(
+                    map,
+                    "invalid value. should be 'cpumap' or 'devmap'",
+                ));
+            }
+        }
         err_on_unknown_args(&args)?;
-        Ok(Xdp { item, name, frags })
+        Ok(Xdp {
+            item,
+            name,
+            frags,
+            map,
+        })
     }
 
     pub fn expand(&self) -> Result<TokenStream> {
         let section_prefix = if self.frags { "xdp.frags" } else { "xdp" };
+        let section_prefix = match &self.map {
+            Some(map) => format!("{section_prefix}/{map}"),
+            None => section_prefix.to_owned(),
+        };
         let section_name = if let Some(name) = &self.name {
             format!("{section_prefix}/{name}")
         } else {
-            section_prefix.to_string()
+            section_prefix
         };
         let fn_vis = &self.item.vis;
         let fn_name = &self.item.sig.ident;
@@ -500,6 +536,15 @@ fn name_arg(args: &mut Args) -> Result<Option<String>> {
     Ok(name)
 }
 
+fn sleepable_arg(args: &mut Args) -> Result<bool> {
+    match pop_arg(args, "sleepable") {
+        Some(s) => s
+            .parse()
+            .map_err(|_| Error::new_spanned(s, "invalid value. should be 'true' or 'false'")),
+        None => Ok(false),
+    }
+}
+
 #[allow(clippy::enum_variant_names)]
 #[derive(Debug, Copy, Clone)]
 pub enum ProbeKind {
@@ -551,6 +596,36 @@ impl TracePoint {
     }
 }
 
+pub struct Usdt {
+    item: ItemFn,
+    name: String,
+}
+
+impl Usdt {
+    pub fn from_syn(mut args: Args, item: ItemFn) -> Result<Usdt> {
+        let name = name_arg(&mut args)?.unwrap_or_else(|| item.sig.ident.to_string());
+
+        Ok(Usdt { item, name })
+    }
+
+    pub fn expand(&self) -> Result<TokenStream> {
+        let section_name = format!("usdt/{}", self.name);
+        let fn_vis = &self.item.vis;
+        let fn_name = &self.item.sig.ident;
+        let item = &self.item;
+        Ok(quote! {
+            #[no_mangle]
+            #[link_section = #section_name]
+            #fn_vis fn #fn_name(ctx: *mut ::core::ffi::c_void) -> u32 {
+                let _ = #fn_name(::aya_bpf::programs::UsdtContext::new(ctx));
+                return 0;
+
+                #item
+            }
+        })
+    }
+}
+
 pub struct PerfEvent {
     item: ItemFn,
     name: String,
@@ -620,17 +695,7 @@ pub struct Lsm {
 impl Lsm {
     pub fn from_syn(mut args: Args, item: ItemFn) -> Result<Lsm> {
         let name = pop_arg(&mut args, "name");
-        let mut sleepable = false;
-        if let Some(s) = pop_arg(&mut args, "sleepable") {
-            if let Ok(m) = s.parse() {
-                sleepable = m
-            } else {
-                return Err(Error::new_spanned(
-                    s,
-                    "invalid value. should be 'true' or 'false'",
-                ));
-            }
-        }
+        let sleepable = sleepable_arg(&mut args)?;
         err_on_unknown_args(&args)?;
         Ok(Lsm {
             item,
@@ -699,6 +764,7 @@ impl BtfTracePoint {
 pub enum SkSkbKind {
     StreamVerdict,
     StreamParser,
+    Verdict,
 }
 
 impl std::fmt::Display for SkSkbKind {
@@ -707,6 +773,7 @@ impl std::fmt::Display for SkSkbKind {
         match self {
             StreamVerdict => write!(f, "stream_verdict"),
             StreamParser => write!(f, "stream_parser"),
+            Verdict => write!(f, "verdict"),
         }
     }
 }
@@ -784,17 +851,24 @@ impl SocketFilter {
 pub struct FEntry {
     item: ItemFn,
     name: String,
+    sleepable: bool,
 }
 
 impl FEntry {
     pub fn from_syn(mut args: Args, item: ItemFn) -> Result<FEntry> {
+        let sleepable = sleepable_arg(&mut args)?;
         let name = name_arg(&mut args)?.unwrap_or_else(|| item.sig.ident.to_string());
 
-        Ok(FEntry { item, name })
+        Ok(FEntry {
+            item,
+            name,
+            sleepable,
+        })
     }
 
     pub fn expand(&self) -> Result<TokenStream> {
-        let section_name = format!("fentry/{}", self.name);
+        let section_prefix = if self.sleepable { "fentry.s" } else { "fentry" };
+        let section_name = format!("{section_prefix}/{}", self.name);
         let fn_vis = &self.item.vis;
         let fn_name = &self.item.sig.ident;
         let item = &self.item;
@@ -814,17 +888,24 @@ impl FEntry {
 pub struct FExit {
     item: ItemFn,
     name: String,
+    sleepable: bool,
 }
 
 impl FExit {
     pub fn from_syn(mut args: Args, item: ItemFn) -> Result<FExit> {
+        let sleepable = sleepable_arg(&mut args)?;
         let name = name_arg(&mut args)?.unwrap_or_else(|| item.sig.ident.to_string());
 
-        Ok(FExit { item, name })
+        Ok(FExit {
+            item,
+            name,
+            sleepable,
+        })
     }
 
     pub fn expand(&self) -> Result<TokenStream> {
-        let section_name = format!("fexit/{}", self.name);
+        let section_prefix = if self.sleepable { "fexit.s" } else { "fexit" };
+        let section_name = format!("{section_prefix}/{}", self.name);
         let fn_vis = &self.item.vis;
         let fn_name = &self.item.sig.ident;
         let item = &self.item;
@@ -841,6 +922,37 @@ impl FExit {
     }
 }
 
+pub struct Iter {
+    item: ItemFn,
+    iter_type: String,
+}
+
+impl Iter {
+    pub fn from_syn(mut args: Args, item: ItemFn) -> Result<Iter> {
+        let iter_type = pop_arg(&mut args, "iter_type")
+            .ok_or_else(|| Error::new_spanned(&item.sig.ident, "missing argument: iter_type"))?;
+        err_on_unknown_args(&args)?;
+
+        Ok(Iter { item, iter_type })
+    }
+
+    pub fn expand(&self) -> Result<TokenStream> {
+        let section_name = format!("iter/{}", self.iter_type);
+        let fn_vis = &self.item.vis;
+        let fn_name = &self.item.sig.ident;
+        let item = &self.item;
+        Ok(quote! {
+            #[no_mangle]
+            #[link_section = #section_name]
+            #fn_vis fn #fn_name(ctx: *mut ::core::ffi::c_void) -> i32 {
+                return #fn_name(::aya_bpf::programs::IterContext::new(ctx));
+
+                #item
+            }
+        })
+    }
+}
+
 pub struct SkLookup {
     item: ItemFn,
     name: Option<String>,
@@ -912,6 +1024,138 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn xdp_with_map_and_name() {
+        let prog = Xdp::from_syn(
+            parse_quote!(name = "foo", map = "devmap"),
+            parse_quote!(
+                fn foo(ctx: XdpContext) -> u32 {
+                    0
+                }
+            ),
+        )
+        .unwrap();
+        let stream = prog.expand().unwrap();
+        assert!(stream
+            .to_string()
+            .contains("[link_section = \"xdp/devmap/foo\"]"));
+    }
+
+    #[test]
+    fn xdp_with_map_no_name() {
+        let prog = Xdp::from_syn(
+            parse_quote!(map = "cpumap"),
+            parse_quote!(
+                fn foo(ctx: XdpContext) -> u32 {
+                    0
+                }
+            ),
+        )
+        .unwrap();
+        let stream = prog.expand().unwrap();
+        assert!(stream
+            .to_string()
+            .contains("[link_section = \"xdp/cpumap\"]"));
+    }
+
+    #[test]
+    fn sleepable_uprobe() {
+        let prog = Probe::from_syn(
+            ProbeKind::UProbe,
+            parse_quote!(name = "foo", sleepable = "true"),
+            parse_quote!(
+                fn foo(ctx: ProbeContext) -> u32 {
+                    0
+                }
+            ),
+        )
+        .unwrap();
+        let stream = prog.expand().unwrap();
+        assert!(stream
+            .to_string()
+            .contains("[link_section = \"uprobe.s/foo\"]"));
+    }
+
+    #[test]
+    fn usdt_with_name() {
+        let prog = Usdt::from_syn(
+            parse_quote!(name = "function_entry"),
+            parse_quote!(
+                fn foo(ctx: UsdtContext) -> u32 {
+                    0
+                }
+            ),
+        )
+        .unwrap();
+        let stream = prog.expand().unwrap().to_string();
+        assert!(stream.contains("[link_section = \"usdt/function_entry\"]"));
+        assert!(stream.contains("UsdtContext :: new (ctx)"));
+    }
+
+    #[test]
+    fn sleepable_kprobe() {
+        assert!(Probe::from_syn(
+            ProbeKind::KProbe,
+            parse_quote!(sleepable = "true"),
+            parse_quote!(
+                fn foo(ctx: ProbeContext) -> u32 {
+                    0
+                }
+            ),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn iter() {
+        let prog = Iter::from_syn(
+            parse_quote!(iter_type = "task"),
+            parse_quote!(
+                fn dump_task(ctx: IterContext) -> i32 {
+                    0
+                }
+            ),
+        )
+        .unwrap();
+        let stream = prog.expand().unwrap();
+        assert!(stream
+            .to_string()
+            .contains("[link_section = \"iter/task\"]"));
+        assert!(stream
+            .to_string()
+            .contains("return dump_task (:: aya_bpf :: programs :: IterContext :: new (ctx))"));
+    }
+
+    #[test]
+    fn iter_without_type() {
+        assert!(Iter::from_syn(
+            parse_quote!(),
+            parse_quote!(
+                fn dump_task(ctx: IterContext) -> i32 {
+                    0
+                }
+            ),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn sleepable_fentry() {
+        let prog = FEntry::from_syn(
+            parse_quote!(name = "foo", sleepable = "true"),
+            parse_quote!(
+                fn foo(ctx: FEntryContext) -> i32 {
+                    0
+                }
+            ),
+        )
+        .unwrap();
+        let stream = prog.expand().unwrap();
+        assert!(stream
+            .to_string()
+            .contains("[link_section = \"fentry.s/foo\"]"));
+    }
+
     #[test]
     fn cgroup_skb_with_attach_and_name() {
         let prog = CgroupSkb::from_syn(
@@ -2,9 +2,9 @@ mod expand;
 
 use expand::{
     Args, BtfTracePoint, CgroupDevice, CgroupSkb, CgroupSock, CgroupSockAddr, CgroupSockopt,
-    CgroupSysctl, FEntry, FExit, Lsm, Map, PerfEvent, Probe, ProbeKind, RawTracePoint,
+    CgroupSysctl, FEntry, FExit, Iter, Lsm, Map, PerfEvent, Probe, ProbeKind, RawTracePoint,
     SchedClassifier, SkLookup, SkMsg, SkSkb, SkSkbKind, SockAddrArgs, SockOps, SocketFilter,
-    SockoptArgs, TracePoint, Xdp,
+    SockoptArgs, TracePoint, Usdt, Xdp,
 };
 use proc_macro::TokenStream;
 use syn::{parse_macro_input, ItemFn, ItemStatic};
@@ -196,6 +196,40 @@ pub fn tracepoint(attrs: TokenStream, item: TokenStream) -> TokenStream {
         .into()
 }
 
+/// Marks a function as a USDT eBPF program that can be attached to the user
+/// statically-defined tracepoints of a binary.
+///
+/// The arguments of the probe are read with `UsdtContext::arg`.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to read the arguments of the probe is
+/// 5.15.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya_bpf::{macros::usdt, programs::UsdtContext};
+///
+/// #[usdt(name = "function_entry")]
+/// pub fn function_entry(ctx: UsdtContext) -> u32 {
+///     match ctx.arg(2) {
+///         Some(_lineno) => 0,
+///         None => 1,
+///     }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn usdt(attrs: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attrs as Args);
+    let item = parse_macro_input!(item as ItemFn);
+
+    Usdt::from_syn(args, item)
+        .and_then(|u| u.expand())
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
 #[proc_macro_attribute]
 pub fn perf_event(attrs: TokenStream, item: TokenStream) -> TokenStream {
     let args = parse_macro_input!(attrs as Args);
@@ -389,6 +423,39 @@ pub fn stream_verdict(attrs: TokenStream, item: TokenStream) -> TokenStream {
     sk_skb(SkSkbKind::StreamVerdict, attrs, item)
 }
 
+/// Marks a function as a SK_SKB Verdict eBPF program that can be attached
+/// to a SockMap
+///
+/// Unlike [`macro@stream_verdict`] programs, verdict programs don't need a
+/// stream parser and are also run for UDP sockets.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.13
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya_bpf::{macros::sk_skb_verdict, programs::SkBuffContext, bindings::sk_action};
+///
+///
+///#[sk_skb_verdict]
+///fn sk_skb_verdict(ctx: SkBuffContext) -> u32 {
+///    match { try_sk_skb_verdict(ctx) } {
+///        Ok(ret) => ret,
+///        Err(ret) => ret,
+///    }
+///}
+///
+///fn try_sk_skb_verdict(_ctx: SkBuffContext) -> Result<u32, u32> {
+///    Ok(sk_action::SK_PASS)
+///}
+/// ```
+#[proc_macro_attribute]
+pub fn sk_skb_verdict(attrs: TokenStream, item: TokenStream) -> TokenStream {
+    sk_skb(SkSkbKind::Verdict, attrs, item)
+}
+
 fn sk_skb(kind: SkSkbKind, attrs: TokenStream, item: TokenStream) -> TokenStream {
     let args = parse_macro_input!(attrs as Args);
     let item = parse_macro_input!(item as ItemFn);
@@ -432,6 +499,9 @@ pub fn socket_filter(attrs: TokenStream, item: TokenStream) -> TokenStream {
 /// is that fexit has practically zero overhead to call before kernel function.
 /// fentry programs can be also attached to other eBPF programs.
 ///
+/// With `sleepable = "true"`, the program is loaded as sleepable and can call
+/// helpers that may fault, like `bpf_copy_from_user`.
+///
 /// # Minimumm kernel version
 ///
 /// The minimum kernel version required to use this feature is 5.5.
@@ -476,6 +546,9 @@ pub fn fentry(attrs: TokenStream, item: TokenStream) -> TokenStream {
 /// and it focuses on access to arguments rather than the return value. fexit
 /// programs can be also attached to other eBPF programs
 ///
+/// With `sleepable = "true"`, the program is loaded as sleepable and can call
+/// helpers that may fault, like `bpf_copy_from_user`.
+///
 /// # Minimumm kernel version
 ///
 /// The minimum kernel version required to use this feature is 5.5.
@@ -514,6 +587,57 @@ pub fn fexit(attrs: TokenStream, item: TokenStream) -> TokenStream {
         .into()
 }
 
+/// Marks a function as a BPF iterator program, which walks kernel objects of
+/// the given `iter_type`, such as `task`, `tcp` or `bpf_map_elem`, and writes
+/// its output to a file read by userspace.
+///
+/// The program is run once per object, and once more at the end of the
+/// iteration with a null object. The value it returns must be 0, 1 skips the
+/// object again on the next read.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.8.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #![allow(non_camel_case_types)]
+/// use aya_bpf::{cty::c_int, macros::iter, programs::IterContext};
+/// # type pid_t = c_int;
+/// # struct task_struct {
+/// #     pid: pid_t,
+/// # }
+///
+/// #[iter(iter_type = "task")]
+/// fn dump_task(ctx: IterContext) -> i32 {
+///     match unsafe { try_dump_task(ctx) } {
+///         Ok(ret) => ret,
+///         Err(ret) => ret,
+///     }
+/// }
+///
+/// unsafe fn try_dump_task(ctx: IterContext) -> Result<i32, i32> {
+///     let task: *const task_struct = ctx.arg(0);
+///     if task.is_null() {
+///         return Ok(0);
+///     }
+///     ctx.printf(b"%d\n\0", &[(*task).pid as u64]).map_err(|_| 0)?;
+///
+///     Ok(0)
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn iter(attrs: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attrs as Args);
+    let item = parse_macro_input!(item as ItemFn);
+
+    Iter::from_syn(args, item)
+        .and_then(|u| u.expand())
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
 /// Marks a function as an eBPF Socket Lookup program that can be attached to
 /// a network namespace.
 ///
@@ -0,0 +1,218 @@
+//! Host-side encoding of log records.
+
+extern crate std;
+
+use std::{string::String, vec, vec::Vec};
+
+use crate::{
+    set_record_flags, write_record_cgroup_id, write_record_checksum, write_record_header,
+    write_record_netns_cookie, write_record_pid, write_record_program_id, write_record_sequence,
+    write_record_timestamp, Clock, Level, WriteToBuf, LOG_BUF_CAPACITY, RECORD_FLAG_TRUNCATED,
+};
+
+/// Builds log records in the format written by `aya-log-ebpf`.
+///
+/// This lets tools and tests synthesize records on the host, for example to
+/// check that they round-trip through `aya_log::decode_record` without
+/// loading an eBPF program.
+///
+/// Arguments are written in order, like the `aya-log-ebpf` macros do: a
+/// display hint such as `{:x}` is an argument of its own, written before the
+/// value it applies to.
+///
+/// # Example
+///
+/// ```
+/// use aya_log_common::{DisplayHint, Level, RecordBuilder};
+///
+/// let record = RecordBuilder::new(Level::Info, "my_target")
+///     .arg("received ")
+///     .arg(DisplayHint::LowerHex)
+///     .arg(42u32)
+///     .pid(1234)
+///     .checksum(true)
+///     .build()
+///     .unwrap();
+/// assert!(record.len() <= aya_log_common::LOG_BUF_CAPACITY);
+/// ```
+#[derive(Clone, Debug)]
+pub struct RecordBuilder {
+    level: Level,
+    target: String,
+    module: String,
+    file: String,
+    line: u32,
+    args: Vec<u8>,
+    num_args: usize,
+    overflow: bool,
+    truncated: bool,
+    pid: Option<u32>,
+    cgroup_id: Option<u64>,
+    netns_cookie: Option<u64>,
+    timestamp: Option<(Clock, u64)>,
+    program_id: Option<u32>,
+    sequence: Option<u64>,
+    checksum: bool,
+}
+
+impl RecordBuilder {
+    /// Starts a record of the given level and target, without arguments.
+    ///
+    /// The module defaults to `target`, the file to an empty string and the
+    /// line to 0.
+    pub fn new(level: Level, target: &str) -> RecordBuilder {
+        RecordBuilder {
+            level,
+            target: target.into(),
+            module: target.into(),
+            file: String::new(),
+            line: 0,
+            args: Vec::new(),
+            num_args: 0,
+            overflow: false,
+            truncated: false,
+            pid: None,
+            cgroup_id: None,
+            netns_cookie: None,
+            timestamp: None,
+            program_id: None,
+            sequence: None,
+            checksum: false,
+        }
+    }
+
+    /// Sets the module path of the record.
+    pub fn module(&mut self, module: &str) -> &mut RecordBuilder {
+        self.module = module.into();
+        self
+    }
+
+    /// Sets the source location of the record.
+    pub fn location(&mut self, file: &str, line: u32) -> &mut RecordBuilder {
+        self.file = file.into();
+        self.line = line;
+        self
+    }
+
+    /// Appends an argument, or a display hint applying to the next argument.
+    ///
+    /// Arguments which don't fit in [`LOG_BUF_CAPACITY`] make
+    /// [`RecordBuilder::build`] fail.
+    pub fn arg(&mut self, arg: impl WriteToBuf) -> &mut RecordBuilder {
+        let mut buf = [0u8; LOG_BUF_CAPACITY];
+        match arg.write(&mut buf) {
+            Ok(len) => {
+                self.args.extend_from_slice(&buf[..len]);
+                self.num_args += 1;
+            }
+            Err(()) => self.overflow = true,
+        }
+        self
+    }
+
+    /// Marks the record as truncated, like records whose last argument didn't
+    /// fit in the eBPF log buffer.
+    pub fn truncated(&mut self, truncated: bool) -> &mut RecordBuilder {
+        self.truncated = truncated;
+        self
+    }
+
+    /// Sets the id of the process which logged the record.
+    pub fn pid(&mut self, pid: u32) -> &mut RecordBuilder {
+        self.pid = Some(pid);
+        self
+    }
+
+    /// Sets the id of the cgroup of the process which logged the record.
+    pub fn cgroup_id(&mut self, cgroup_id: u64) -> &mut RecordBuilder {
+        self.cgroup_id = Some(cgroup_id);
+        self
+    }
+
+    /// Sets the cookie of the network namespace which logged the record.
+    pub fn netns_cookie(&mut self, cookie: u64) -> &mut RecordBuilder {
+        self.netns_cookie = Some(cookie);
+        self
+    }
+
+    /// Sets the time at which the record was logged, in nanoseconds of
+    /// `clock`.
+    pub fn timestamp(&mut self, clock: Clock, ns: u64) -> &mut RecordBuilder {
+        self.timestamp = Some((clock, ns));
+        self
+    }
+
+    /// Sets the identifier of the program instance which logged the record.
+    pub fn program_id(&mut self, id: u32) -> &mut RecordBuilder {
+        self.program_id = Some(id);
+        self
+    }
+
+    /// Sets the number of records logged before this one on the same CPU.
+    pub fn sequence(&mut self, sequence: u64) -> &mut RecordBuilder {
+        self.sequence = Some(sequence);
+        self
+    }
+
+    /// Appends a checksum trailer to the record, like the `checksum` feature
+    /// of `aya-log-ebpf` does.
+    pub fn checksum(&mut self, checksum: bool) -> &mut RecordBuilder {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Returns the encoded record.
+    ///
+    /// Fails if the record doesn't fit in [`LOG_BUF_CAPACITY`] bytes.
+    #[allow(clippy::result_unit_err)]
+    pub fn build(&self) -> Result<Vec<u8>, ()> {
+        if self.overflow {
+            return Err(());
+        }
+        let mut buf = vec![0u8; LOG_BUF_CAPACITY];
+        let mut len = write_record_header(
+            &mut buf,
+            &self.target,
+            self.level,
+            &self.module,
+            &self.file,
+            self.line,
+            self.num_args,
+        )?;
+        // the context fields are silently dropped when they don't fit, which
+        // can't happen right after a header that fits
+        if let Some(pid) = self.pid {
+            len = write_record_pid(&mut buf, len, pid);
+        }
+        if let Some(cgroup_id) = self.cgroup_id {
+            len = write_record_cgroup_id(&mut buf, len, cgroup_id);
+        }
+        if let Some(cookie) = self.netns_cookie {
+            len = write_record_netns_cookie(&mut buf, len, cookie);
+        }
+        if let Some((clock, ns)) = self.timestamp {
+            len = write_record_timestamp(&mut buf, len, clock, ns);
+        }
+        if let Some(id) = self.program_id {
+            len = write_record_program_id(&mut buf, len, id);
+        }
+        if let Some(sequence) = self.sequence {
+            len = write_record_sequence(&mut buf, len, sequence);
+        }
+
+        let args = buf.get_mut(len..len + self.args.len()).ok_or(())?;
+        args.copy_from_slice(&self.args);
+        len += self.args.len();
+
+        if self.truncated {
+            set_record_flags(&mut buf, RECORD_FLAG_TRUNCATED);
+        }
+        if self.checksum {
+            // the eBPF side reserves room for the trailer past the log buffer
+            buf.resize(len + crate::RECORD_CHECKSUM_LEN, 0);
+            len = write_record_checksum(&mut buf, len);
+        }
+        buf.truncate(len);
+        Ok(buf)
+    }
+}
@@ -0,0 +1,813 @@
+//! The wire format of log records and format entries.
+//!
+//! Records are sequences of fields, each made of a one byte tag, a
+//! [`LogValueLength`] length and the bytes of the value, all in native endian.
+//! The header fields are tagged with [`RecordField`](crate::RecordField) and
+//! the arguments with [`Argument`](crate::Argument).
+//!
+//! Each `write_*` or `encode_*` function here is paired with the `read_*` or
+//! `decode_*` function reading its output back, so that the eBPF side and
+//! userspace can't disagree on the format. The header fields and arguments
+//! read with [`read_field`] are decoded with [`decode_header_field`] and
+//! [`decode_argument`]. They are all usable without `std`.
+
+use core::{mem, num, ptr};
+
+use crate::{
+    Argument, Clock, DisplayHint, Level, LogValueLength, RecordField, RecordFlags, LOG_BUF_CAPACITY,
+};
+
+/// Length of the tag and the length of a field.
+pub const FIELD_HEADER_LEN: usize = mem::size_of::<u8>() + mem::size_of::<LogValueLength>();
+
+pub(crate) struct TagLenValue<T, V> {
+    pub tag: T,
+    pub value: V,
+}
+
+impl<T, V> TagLenValue<T, V>
+where
+    V: IntoIterator<Item = u8>,
+    <V as IntoIterator>::IntoIter: ExactSizeIterator,
+{
+    pub(crate) fn write(self, mut buf: &mut [u8]) -> Result<usize, ()> {
+        // Break the abstraction to please the verifier.
+        if buf.len() > LOG_BUF_CAPACITY {
+            buf = &mut buf[..LOG_BUF_CAPACITY];
+        }
+        let Self { tag, value } = self;
+        let value = value.into_iter();
+        let len = value.len();
+        let wire_len: LogValueLength = value
+            .len()
+            .try_into()
+            .map_err(|num::TryFromIntError { .. }| ())?;
+        let size = mem::size_of_val(&tag) + mem::size_of_val(&wire_len) + len;
+        if size > buf.len() {
+            return Err(());
+        }
+
+        let tag_size = mem::size_of_val(&tag);
+        unsafe { ptr::write_unaligned(buf.as_mut_ptr() as *mut _, tag) };
+        buf = &mut buf[tag_size..];
+
+        unsafe { ptr::write_unaligned(buf.as_mut_ptr() as *mut _, wire_len) };
+        buf = &mut buf[mem::size_of_val(&wire_len)..];
+
+        buf.iter_mut().zip(value).for_each(|(dst, src)| {
+            *dst = src;
+        });
+
+        Ok(size)
+    }
+}
+
+impl<T, V> TagLenValue<T, V> {
+    #[inline(always)]
+    pub(crate) fn new(tag: T, value: V) -> TagLenValue<T, V> {
+        TagLenValue { tag, value }
+    }
+}
+
+/// Writes a field with the given tag and value to the start of `buf`,
+/// returning its length, see [`read_field`].
+///
+/// Fails if the field doesn't fit in `buf` or its value is longer than
+/// [`LogValueLength::MAX`].
+#[allow(clippy::result_unit_err)]
+#[inline(always)]
+pub fn write_field(buf: &mut [u8], tag: u8, value: &[u8]) -> Result<usize, ()> {
+    TagLenValue::new(tag, value.iter().copied()).write(buf)
+}
+
+/// A field ends past the end of the buffer it's read from.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Truncated {
+    /// The number of bytes needed to read the tag and length, or the value.
+    pub needed: usize,
+    /// The number of bytes left in the buffer.
+    pub available: usize,
+}
+
+/// Reads the field at the start of `buf` written by [`write_field`] or any of
+/// the `write_*` functions of this crate.
+///
+/// Returns the tag, the value and the rest of `buf`.
+pub fn read_field(buf: &[u8]) -> Result<(u8, &[u8], &[u8]), Truncated> {
+    if buf.len() < FIELD_HEADER_LEN {
+        return Err(Truncated {
+            needed: FIELD_HEADER_LEN,
+            available: buf.len(),
+        });
+    }
+    let (header, buf) = buf.split_at(FIELD_HEADER_LEN);
+    let tag = header[0];
+    let len: usize = LogValueLength::from_ne_bytes([header[1], header[2]]).into();
+    if buf.len() < len {
+        return Err(Truncated {
+            needed: len,
+            available: buf.len(),
+        });
+    }
+    let (value, rest) = buf.split_at(len);
+    Ok((tag, value, rest))
+}
+
+/// Length of the value of a [`RecordField::Timestamp`](crate::RecordField::Timestamp)
+/// field.
+pub const TIMESTAMP_LEN: usize = mem::size_of::<u64>() + mem::size_of::<Clock>();
+
+/// Encodes the value of a timestamp field: the nanoseconds followed by the
+/// clock they were read from.
+#[inline(always)]
+pub fn encode_timestamp(clock: Clock, ns: u64) -> [u8; TIMESTAMP_LEN] {
+    let mut value = [0u8; TIMESTAMP_LEN];
+    value[..mem::size_of::<u64>()].copy_from_slice(&ns.to_ne_bytes());
+    value[mem::size_of::<u64>()] = clock.into();
+    value
+}
+
+/// Decodes the value written by [`encode_timestamp`], failing with the clock
+/// byte when it's unknown.
+pub fn decode_timestamp(value: [u8; TIMESTAMP_LEN]) -> Result<(Clock, u64), u8> {
+    let (ns, clock) = value.split_at(mem::size_of::<u64>());
+    let clock = Clock::try_from(clock[0]).map_err(|_| clock[0])?;
+    Ok((clock, u64::from_ne_bytes(ns.try_into().unwrap())))
+}
+
+/// Length of the value of a [`RecordField::Checksum`](crate::RecordField::Checksum)
+/// field.
+pub const CHECKSUM_LEN: usize = 2 * mem::size_of::<u32>();
+
+/// Encodes the value of a checksum trailer: the checksum of the record
+/// followed by the length of the record it covers.
+#[inline(always)]
+pub fn encode_checksum(checksum: u32, record_len: u32) -> [u8; CHECKSUM_LEN] {
+    let mut value = [0u8; CHECKSUM_LEN];
+    value[..mem::size_of::<u32>()].copy_from_slice(&checksum.to_ne_bytes());
+    value[mem::size_of::<u32>()..].copy_from_slice(&record_len.to_ne_bytes());
+    value
+}
+
+/// Decodes the checksum and record length written by [`encode_checksum`].
+pub fn decode_checksum(value: [u8; CHECKSUM_LEN]) -> (u32, u32) {
+    let (checksum, len) = value.split_at(mem::size_of::<u32>());
+    (
+        u32::from_ne_bytes(checksum.try_into().unwrap()),
+        u32::from_ne_bytes(len.try_into().unwrap()),
+    )
+}
+
+/// Length of the value of an [`Argument::Enum`](crate::Argument::Enum).
+pub const ENUM_LEN: usize = mem::size_of::<u32>() + mem::size_of::<u64>();
+
+/// Encodes the value of an enum argument: the
+/// [`LogEnum::TYPE_ID`](crate::LogEnum::TYPE_ID) followed by the discriminant.
+#[inline(always)]
+pub fn encode_enum(type_id: u32, discriminant: u64) -> [u8; ENUM_LEN] {
+    let mut value = [0u8; ENUM_LEN];
+    value[..mem::size_of::<u32>()].copy_from_slice(&type_id.to_ne_bytes());
+    value[mem::size_of::<u32>()..].copy_from_slice(&discriminant.to_ne_bytes());
+    value
+}
+
+/// Decodes the type id and discriminant written by [`encode_enum`].
+pub fn decode_enum(value: [u8; ENUM_LEN]) -> (u32, u64) {
+    let (type_id, discriminant) = value.split_at(mem::size_of::<u32>());
+    (
+        u32::from_ne_bytes(type_id.try_into().unwrap()),
+        u64::from_ne_bytes(discriminant.try_into().unwrap()),
+    )
+}
+
+/// A field which couldn't be decoded by [`decode_header_field`] or
+/// [`decode_argument`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FieldError {
+    /// A header field has an unknown tag.
+    InvalidField(u8),
+    /// An argument has an unknown type tag.
+    InvalidArgument(u8),
+    /// The level of the record is unknown.
+    InvalidLevel(u8),
+    /// A display hint is unknown.
+    InvalidDisplayHint(u8),
+    /// A timestamp was read from an unknown clock.
+    InvalidClock(u8),
+    /// A value doesn't have the length required by its type.
+    InvalidLength {
+        name: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+/// Reads a fixed size value.
+fn read_value<const N: usize>(name: &'static str, value: &[u8]) -> Result<[u8; N], FieldError> {
+    value.try_into().map_err(|_| FieldError::InvalidLength {
+        name,
+        expected: N,
+        actual: value.len(),
+    })
+}
+
+/// Checks that an array holds a whole number of elements.
+fn read_array(value: &[u8], element_size: usize) -> Result<&[u8], FieldError> {
+    let remainder = value.len() % element_size;
+    if remainder != 0 {
+        return Err(FieldError::InvalidLength {
+            name: "array",
+            expected: value.len() - remainder,
+            actual: value.len(),
+        });
+    }
+    Ok(value)
+}
+
+/// A header field of a record, decoded by [`decode_header_field`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HeaderField<'a> {
+    Target(&'a [u8]),
+    Level(Level),
+    Module(&'a [u8]),
+    File(&'a [u8]),
+    Line(u32),
+    NumArgs(usize),
+    Flags(RecordFlags),
+    FormatId(u32),
+    /// The checksum and the length of the record it covers, see
+    /// [`decode_checksum`].
+    Checksum {
+        checksum: u32,
+        record_len: u32,
+    },
+    Pid(u32),
+    CgroupId(u64),
+    NetnsCookie(u64),
+    /// See [`decode_timestamp`].
+    Timestamp {
+        clock: Clock,
+        ns: u64,
+    },
+    ProgramId(u32),
+    Sequence(u64),
+}
+
+/// Decodes a header field written by the `write_record_*` functions of this
+/// crate, from the tag and value returned by [`read_field`].
+pub fn decode_header_field(tag: u8, value: &[u8]) -> Result<HeaderField<'_>, FieldError> {
+    let field = RecordField::try_from(tag).map_err(|_| FieldError::InvalidField(tag))?;
+    Ok(match field {
+        RecordField::Target => HeaderField::Target(value),
+        RecordField::Level => {
+            let [level] = read_value("level", value)?;
+            HeaderField::Level(Level::try_from(level).map_err(|_| FieldError::InvalidLevel(level))?)
+        }
+        RecordField::Module => HeaderField::Module(value),
+        RecordField::File => HeaderField::File(value),
+        RecordField::Line => HeaderField::Line(u32::from_ne_bytes(read_value("line", value)?)),
+        RecordField::NumArgs => HeaderField::NumArgs(usize::from_ne_bytes(read_value(
+            "number of arguments",
+            value,
+        )?)),
+        RecordField::Flags => {
+            HeaderField::Flags(RecordFlags::from_ne_bytes(read_value("flags", value)?))
+        }
+        RecordField::FormatId => {
+            HeaderField::FormatId(u32::from_ne_bytes(read_value("format id", value)?))
+        }
+        RecordField::Checksum => {
+            let (checksum, record_len) = decode_checksum(read_value("checksum", value)?);
+            HeaderField::Checksum {
+                checksum,
+                record_len,
+            }
+        }
+        RecordField::Pid => HeaderField::Pid(u32::from_ne_bytes(read_value("pid", value)?)),
+        RecordField::CgroupId => {
+            HeaderField::CgroupId(u64::from_ne_bytes(read_value("cgroup id", value)?))
+        }
+        RecordField::NetnsCookie => {
+            HeaderField::NetnsCookie(u64::from_ne_bytes(read_value("netns cookie", value)?))
+        }
+        RecordField::Timestamp => {
+            let (clock, ns) = decode_timestamp(read_value("timestamp", value)?)
+                .map_err(FieldError::InvalidClock)?;
+            HeaderField::Timestamp { clock, ns }
+        }
+        RecordField::ProgramId => {
+            HeaderField::ProgramId(u32::from_ne_bytes(read_value("program id", value)?))
+        }
+        RecordField::Sequence => {
+            HeaderField::Sequence(u64::from_ne_bytes(read_value("sequence", value)?))
+        }
+    })
+}
+
+/// An argument of a record, decoded by [`decode_argument`].
+///
+/// Arrays hold the native endian bytes of their elements, the legacy
+/// fixed length array arguments are decoded like the arrays of any length.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ArgumentValue<'a> {
+    DisplayHint(DisplayHint),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Isize(isize),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    Usize(usize),
+    F32(f32),
+    F64(f64),
+    ArrU8(&'a [u8]),
+    ArrU16(&'a [u8]),
+    ArrU32(&'a [u8]),
+    ArrU64(&'a [u8]),
+    Bytes(&'a [u8]),
+    /// A string, which hasn't been checked to be valid UTF-8.
+    Str(&'a [u8]),
+    /// See [`decode_enum`].
+    Enum {
+        type_id: u32,
+        discriminant: u64,
+    },
+}
+
+impl ArgumentValue<'_> {
+    /// Returns the type of the argument.
+    pub fn argument(&self) -> Argument {
+        match self {
+            ArgumentValue::DisplayHint(_) => Argument::DisplayHint,
+            ArgumentValue::I8(_) => Argument::I8,
+            ArgumentValue::I16(_) => Argument::I16,
+            ArgumentValue::I32(_) => Argument::I32,
+            ArgumentValue::I64(_) => Argument::I64,
+            ArgumentValue::Isize(_) => Argument::Isize,
+            ArgumentValue::U8(_) => Argument::U8,
+            ArgumentValue::U16(_) => Argument::U16,
+            ArgumentValue::U32(_) => Argument::U32,
+            ArgumentValue::U64(_) => Argument::U64,
+            ArgumentValue::Usize(_) => Argument::Usize,
+            ArgumentValue::F32(_) => Argument::F32,
+            ArgumentValue::F64(_) => Argument::F64,
+            ArgumentValue::ArrU8(_) => Argument::ArrU8,
+            ArgumentValue::ArrU16(_) => Argument::ArrU16,
+            ArgumentValue::ArrU32(_) => Argument::ArrU32,
+            ArgumentValue::ArrU64(_) => Argument::ArrU64,
+            ArgumentValue::Bytes(_) => Argument::Bytes,
+            ArgumentValue::Str(_) => Argument::Str,
+            ArgumentValue::Enum { .. } => Argument::Enum,
+        }
+    }
+}
+
+/// Decodes an argument written with [`WriteToBuf`](crate::WriteToBuf), from
+/// the tag and value returned by [`read_field`].
+pub fn decode_argument(tag: u8, value: &[u8]) -> Result<ArgumentValue<'_>, FieldError> {
+    let argument = Argument::try_from(tag).map_err(|_| FieldError::InvalidArgument(tag))?;
+    Ok(match argument {
+        Argument::DisplayHint => {
+            let [hint] = read_value("display hint", value)?;
+            ArgumentValue::DisplayHint(
+                DisplayHint::try_from(hint).map_err(|_| FieldError::InvalidDisplayHint(hint))?,
+            )
+        }
+        Argument::I8 => ArgumentValue::I8(i8::from_ne_bytes(read_value("i8", value)?)),
+        Argument::I16 => ArgumentValue::I16(i16::from_ne_bytes(read_value("i16", value)?)),
+        Argument::I32 => ArgumentValue::I32(i32::from_ne_bytes(read_value("i32", value)?)),
+        Argument::I64 => ArgumentValue::I64(i64::from_ne_bytes(read_value("i64", value)?)),
+        Argument::Isize => ArgumentValue::Isize(isize::from_ne_bytes(read_value("isize", value)?)),
+        Argument::U8 => ArgumentValue::U8(u8::from_ne_bytes(read_value("u8", value)?)),
+        Argument::U16 => ArgumentValue::U16(u16::from_ne_bytes(read_value("u16", value)?)),
+        Argument::U32 => ArgumentValue::U32(u32::from_ne_bytes(read_value("u32", value)?)),
+        Argument::U64 => ArgumentValue::U64(u64::from_ne_bytes(read_value("u64", value)?)),
+        Argument::Usize => ArgumentValue::Usize(usize::from_ne_bytes(read_value("usize", value)?)),
+        Argument::F32 => ArgumentValue::F32(f32::from_ne_bytes(read_value("f32", value)?)),
+        Argument::F64 => ArgumentValue::F64(f64::from_ne_bytes(read_value("f64", value)?)),
+        Argument::ArrU8 | Argument::ArrU8Len6 | Argument::ArrU8Len16 => ArgumentValue::ArrU8(value),
+        Argument::ArrU16 | Argument::ArrU16Len8 => {
+            ArgumentValue::ArrU16(read_array(value, mem::size_of::<u16>())?)
+        }
+        Argument::ArrU32 => ArgumentValue::ArrU32(read_array(value, mem::size_of::<u32>())?),
+        Argument::ArrU64 => ArgumentValue::ArrU64(read_array(value, mem::size_of::<u64>())?),
+        Argument::Bytes => ArgumentValue::Bytes(value),
+        Argument::Str => ArgumentValue::Str(value),
+        Argument::Enum => {
+            let (type_id, discriminant) = decode_enum(read_value("enum", value)?);
+            ArgumentValue::Enum {
+                type_id,
+                discriminant,
+            }
+        }
+    })
+}
+
+/// Fields of a format entry: the target, module, file and format string of a
+/// log statement.
+pub const FORMAT_ENTRY_FIELDS: usize = 4;
+
+/// Returns the length of the format entry of `fields`, see [`format_entry`].
+pub const fn format_entry_len(fields: &[&str; FORMAT_ENTRY_FIELDS]) -> usize {
+    let mut len = mem::size_of::<LogValueLength>() + mem::size_of::<u32>();
+    let mut i = 0;
+    while i < fields.len() {
+        len += mem::size_of::<LogValueLength>() + fields[i].len();
+        i += 1;
+    }
+    len
+}
+
+/// Encodes the format entry of a log statement, which is stored in the
+/// [`FORMATS_SECTION`](crate::FORMATS_SECTION) of the eBPF object for userspace
+/// to resolve interned records, see [`read_format_entry`].
+///
+/// The entry is made of its length, not including the length itself, the line
+/// and then the length and bytes of each of the `fields`, in native endian.
+/// `N` must be [`format_entry_len`] of `fields`, entries are identified by
+/// their [`format_id`].
+pub const fn format_entry<const N: usize>(
+    line: u32,
+    fields: &[&str; FORMAT_ENTRY_FIELDS],
+) -> [u8; N] {
+    // Mutable references aren't allowed in const functions on older
+    // toolchains, so the entry is moved in and out.
+    const fn put<const N: usize>(
+        mut entry: [u8; N],
+        mut pos: usize,
+        bytes: &[u8],
+    ) -> ([u8; N], usize) {
+        let mut i = 0;
+        while i < bytes.len() {
+            entry[pos] = bytes[i];
+            pos += 1;
+            i += 1;
+        }
+        (entry, pos)
+    }
+
+    let len = (N - mem::size_of::<LogValueLength>()) as LogValueLength;
+    let (mut entry, mut pos) = put([0u8; N], 0, &len.to_ne_bytes());
+    (entry, pos) = put(entry, pos, &line.to_ne_bytes());
+    let mut i = 0;
+    while i < fields.len() {
+        let field = fields[i].as_bytes();
+        (entry, pos) = put(entry, pos, &(field.len() as LogValueLength).to_ne_bytes());
+        (entry, pos) = put(entry, pos, field);
+        i += 1;
+    }
+    entry
+}
+
+/// Identifies a format entry in interned records.
+pub const fn format_id(entry: &[u8]) -> u32 {
+    crate::fnv1a(entry)
+}
+
+/// A format entry read by [`read_format_entry`], whose fields haven't been
+/// checked to be valid UTF-8.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RawFormatEntry<'a> {
+    /// The [`format_id`] of the entry.
+    pub id: u32,
+    pub line: u32,
+    /// The target, module, file and format string of the log statement.
+    pub fields: [&'a [u8]; FORMAT_ENTRY_FIELDS],
+}
+
+/// Reads the entry written by [`format_entry`] at the start of `data`.
+///
+/// Returns the entry, or `None` for the zero length padding the linker may
+/// insert between entries, and the rest of `data`.
+pub fn read_format_entry(data: &[u8]) -> Result<(Option<RawFormatEntry<'_>>, &[u8]), Truncated> {
+    fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], Truncated> {
+        if data.len() < len {
+            return Err(Truncated {
+                needed: len,
+                available: data.len(),
+            });
+        }
+        let (value, rest) = data.split_at(len);
+        *data = rest;
+        Ok(value)
+    }
+
+    fn take_len(data: &mut &[u8]) -> Result<usize, Truncated> {
+        let len = take(data, mem::size_of::<LogValueLength>())?;
+        Ok(LogValueLength::from_ne_bytes([len[0], len[1]]).into())
+    }
+
+    let start = data;
+    let mut data = data;
+    let len = take_len(&mut data)?;
+    if len == 0 {
+        return Ok((None, data));
+    }
+    let mut entry = take(&mut data, len)?;
+    let id = format_id(&start[..mem::size_of::<LogValueLength>() + len]);
+    let line = u32::from_ne_bytes(take(&mut entry, mem::size_of::<u32>())?.try_into().unwrap());
+    let mut fields = [&[][..]; FORMAT_ENTRY_FIELDS];
+    for field in &mut fields {
+        let len = take_len(&mut entry)?;
+        *field = take(&mut entry, len)?;
+    }
+    Ok((Some(RawFormatEntry { id, line, fields }), data))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Argument, DisplayHint, RecordField, WriteToBuf};
+
+    #[test]
+    fn field_round_trip() {
+        let mut buf = [0u8; 64];
+        let value = [0xa5u8; 32];
+        for tag in 0..=u8::MAX {
+            for len in 0..=value.len() {
+                let size = write_field(&mut buf, tag, &value[..len]).unwrap();
+                assert_eq!(size, FIELD_HEADER_LEN + len);
+                assert_eq!(
+                    read_field(&buf[..size + 1]),
+                    Ok((tag, &value[..len], &buf[size..size + 1]))
+                );
+                for end in 0..size {
+                    assert!(read_field(&buf[..end]).is_err(), "{tag} {len} {end}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn field_too_long() {
+        let mut buf = [0u8; 8];
+        assert_eq!(write_field(&mut buf, 0, &[0; 6]), Err(()));
+        assert_eq!(
+            read_field(&[0, 6, 0]),
+            Err(Truncated {
+                needed: 6,
+                available: 0
+            })
+        );
+    }
+
+    fn decode(buf: &[u8], len: usize) -> Result<ArgumentValue<'_>, FieldError> {
+        let (tag, value, rest) = read_field(&buf[..len]).unwrap();
+        assert!(rest.is_empty());
+        decode_argument(tag, value)
+    }
+
+    #[test]
+    fn arguments_round_trip() {
+        let mut buf = [0u8; 64];
+        let mut covered = [false; Argument::Enum as usize + 1];
+        let mut check = |buf: &[u8], len, expected: ArgumentValue<'_>| {
+            let (tag, _, _) = read_field(&buf[..len]).unwrap();
+            covered[tag as usize] = true;
+            assert_eq!(decode(buf, len), Ok(expected));
+        };
+
+        let len = DisplayHint::LowerMac.write(&mut buf).unwrap();
+        check(&buf, len, ArgumentValue::DisplayHint(DisplayHint::LowerMac));
+        let len = (-1i8).write(&mut buf).unwrap();
+        check(&buf, len, ArgumentValue::I8(-1));
+        let len = (-2i16).write(&mut buf).unwrap();
+        check(&buf, len, ArgumentValue::I16(-2));
+        let len = (-3i32).write(&mut buf).unwrap();
+        check(&buf, len, ArgumentValue::I32(-3));
+        let len = (-4i64).write(&mut buf).unwrap();
+        check(&buf, len, ArgumentValue::I64(-4));
+        let len = (-5isize).write(&mut buf).unwrap();
+        check(&buf, len, ArgumentValue::Isize(-5));
+        let len = 1u8.write(&mut buf).unwrap();
+        check(&buf, len, ArgumentValue::U8(1));
+        let len = 2u16.write(&mut buf).unwrap();
+        check(&buf, len, ArgumentValue::U16(2));
+        let len = 0x1234_5678u32.write(&mut buf).unwrap();
+        check(&buf, len, ArgumentValue::U32(0x1234_5678));
+        let len = u64::MAX.write(&mut buf).unwrap();
+        check(&buf, len, ArgumentValue::U64(u64::MAX));
+        let len = 5usize.write(&mut buf).unwrap();
+        check(&buf, len, ArgumentValue::Usize(5));
+        let len = 1.5f32.write(&mut buf).unwrap();
+        check(&buf, len, ArgumentValue::F32(1.5));
+        let len = (-2.5f64).write(&mut buf).unwrap();
+        check(&buf, len, ArgumentValue::F64(-2.5));
+        let len = [1u8, 2, 3].write(&mut buf).unwrap();
+        check(&buf, len, ArgumentValue::ArrU8(&[1, 2, 3]));
+        // Elements made of identical bytes don't depend on the endianness
+        let len = [0x0101u16, 0x0202].write(&mut buf).unwrap();
+        check(&buf, len, ArgumentValue::ArrU16(&[1, 1, 2, 2]));
+        let len = [0x0303_0303u32].write(&mut buf).unwrap();
+        check(&buf, len, ArgumentValue::ArrU32(&[3; 4]));
+        let len = [7u64].write(&mut buf).unwrap();
+        check(&buf, len, ArgumentValue::ArrU64(&7u64.to_ne_bytes()));
+        let len = [0xffu8, 0].as_slice().write(&mut buf).unwrap();
+        check(&buf, len, ArgumentValue::Bytes(&[0xff, 0]));
+        let len = "héllo".write(&mut buf).unwrap();
+        check(&buf, len, ArgumentValue::Str("héllo".as_bytes()));
+        let len = write_field(&mut buf, Argument::Enum as u8, &encode_enum(3, 4)).unwrap();
+        check(
+            &buf,
+            len,
+            ArgumentValue::Enum {
+                type_id: 3,
+                discriminant: 4,
+            },
+        );
+
+        // Legacy arrays written by older programs
+        let mac = [1u8, 2, 3, 4, 5, 6];
+        let len = write_field(&mut buf, Argument::ArrU8Len6 as u8, &mac).unwrap();
+        check(&buf, len, ArgumentValue::ArrU8(&mac));
+        let len = write_field(&mut buf, Argument::ArrU8Len16 as u8, &[9; 16]).unwrap();
+        check(&buf, len, ArgumentValue::ArrU8(&[9; 16]));
+        let len = write_field(&mut buf, Argument::ArrU16Len8 as u8, &[0; 16]).unwrap();
+        check(&buf, len, ArgumentValue::ArrU16(&[0; 16]));
+
+        assert_eq!(covered, [true; Argument::Enum as usize + 1]);
+        for tag in 0..=Argument::Enum as u8 {
+            assert_eq!(
+                Argument::try_from(tag).map(|argument| argument as u8),
+                Ok(tag)
+            );
+        }
+
+        for hint in [
+            DisplayHint::Default,
+            DisplayHint::LowerHex,
+            DisplayHint::UpperHex,
+            DisplayHint::Ipv4,
+            DisplayHint::Ipv6,
+            DisplayHint::Ip,
+            DisplayHint::LowerMac,
+            DisplayHint::UpperMac,
+        ] {
+            let len = hint.write(&mut buf).unwrap();
+            assert_eq!(decode(&buf, len), Ok(ArgumentValue::DisplayHint(hint)));
+        }
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert_eq!(
+            decode_argument(Argument::Enum as u8 + 1, &[]),
+            Err(FieldError::InvalidArgument(Argument::Enum as u8 + 1))
+        );
+        assert_eq!(
+            decode_argument(Argument::U32 as u8, &[0; 2]),
+            Err(FieldError::InvalidLength {
+                name: "u32",
+                expected: 4,
+                actual: 2
+            })
+        );
+        assert_eq!(
+            decode_argument(Argument::ArrU16 as u8, &[0; 3]),
+            Err(FieldError::InvalidLength {
+                name: "array",
+                expected: 2,
+                actual: 3
+            })
+        );
+        assert_eq!(
+            decode_argument(Argument::DisplayHint as u8, &[0]),
+            Err(FieldError::InvalidDisplayHint(0))
+        );
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let mut buf = [0u8; 128];
+        let len =
+            crate::write_record_header(&mut buf, "t", crate::Level::Warn, "m", "f", 7, 2).unwrap();
+        let len = crate::write_record_pid(&mut buf, len, 42);
+        let len = crate::write_record_timestamp(&mut buf, len, Clock::Boottime, 43);
+
+        let mut fields = [HeaderField::Line(0); 16];
+        let mut count = 0;
+        let mut rest = &buf[..len];
+        while !rest.is_empty() {
+            let (tag, value, next) = read_field(rest).unwrap();
+            fields[count] = decode_header_field(tag, value).unwrap();
+            count += 1;
+            rest = next;
+        }
+        let fields = &fields[..count];
+        assert!(fields.contains(&HeaderField::Target(b"t")));
+        assert!(fields.contains(&HeaderField::Level(Level::Warn)));
+        assert!(fields.contains(&HeaderField::Module(b"m")));
+        assert!(fields.contains(&HeaderField::File(b"f")));
+        assert!(fields.contains(&HeaderField::Line(7)));
+        assert!(fields.contains(&HeaderField::NumArgs(2)));
+        assert!(fields.contains(&HeaderField::Pid(42)));
+        assert!(fields.contains(&HeaderField::Timestamp {
+            clock: Clock::Boottime,
+            ns: 43
+        }));
+        assert!(
+            matches!(fields[0], HeaderField::Flags(flags) if flags & crate::RECORD_FLAG_PID != 0)
+        );
+
+        assert_eq!(
+            decode_header_field(RecordField::Checksum as u8, &encode_checksum(1, 2)),
+            Ok(HeaderField::Checksum {
+                checksum: 1,
+                record_len: 2
+            })
+        );
+        assert_eq!(
+            decode_header_field(0, &[]),
+            Err(FieldError::InvalidField(0))
+        );
+        assert_eq!(
+            decode_header_field(RecordField::Level as u8, &[0]),
+            Err(FieldError::InvalidLevel(0))
+        );
+        let mut timestamp = encode_timestamp(Clock::Monotonic, 1);
+        timestamp[TIMESTAMP_LEN - 1] = 0xff;
+        assert_eq!(
+            decode_header_field(RecordField::Timestamp as u8, &timestamp),
+            Err(FieldError::InvalidClock(0xff))
+        );
+    }
+
+    #[test]
+    fn timestamp_round_trip() {
+        for clock in [Clock::Monotonic, Clock::Boottime] {
+            for ns in [0, 1, u64::MAX] {
+                assert_eq!(
+                    decode_timestamp(encode_timestamp(clock, ns)),
+                    Ok((clock, ns))
+                );
+            }
+        }
+        let mut value = encode_timestamp(Clock::Monotonic, 1);
+        value[TIMESTAMP_LEN - 1] = 0xff;
+        assert_eq!(decode_timestamp(value), Err(0xff));
+
+        let mut buf = [0u8; 64];
+        let len =
+            crate::write_record_header(&mut buf, "t", crate::Level::Info, "m", "f", 1, 0).unwrap();
+        let len = crate::write_record_timestamp(&mut buf, len, Clock::Boottime, 42);
+        let mut rest = &buf[..len];
+        let mut timestamp = None;
+        while !rest.is_empty() {
+            let (tag, value, next) = read_field(rest).unwrap();
+            if tag == RecordField::Timestamp as u8 {
+                timestamp = Some(decode_timestamp(value.try_into().unwrap()));
+            }
+            rest = next;
+        }
+        assert_eq!(timestamp, Some(Ok((Clock::Boottime, 42))));
+    }
+
+    #[test]
+    fn checksum_round_trip() {
+        for (checksum, len) in [(0, 0), (0xdead_beef, 42), (u32::MAX, u32::MAX)] {
+            assert_eq!(
+                decode_checksum(encode_checksum(checksum, len)),
+                (checksum, len)
+            );
+        }
+    }
+
+    #[test]
+    fn enum_round_trip() {
+        for (type_id, discriminant) in [(0, 0), (0xdead_beef, 42), (u32::MAX, u64::MAX)] {
+            assert_eq!(
+                decode_enum(encode_enum(type_id, discriminant)),
+                (type_id, discriminant)
+            );
+        }
+    }
+
+    #[test]
+    fn format_entry_round_trip() {
+        const FIELDS: [&str; FORMAT_ENTRY_FIELDS] = ["target", "", "file.rs", "{} {:x}"];
+        const ENTRY: [u8; format_entry_len(&FIELDS)] = format_entry(42, &FIELDS);
+
+        let mut data = [0u8; ENTRY.len() + 2];
+        data[2..].copy_from_slice(&ENTRY);
+
+        let (padding, rest) = read_format_entry(&data).unwrap();
+        assert_eq!(padding, None);
+        let (entry, rest) = read_format_entry(rest).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(
+            entry,
+            Some(RawFormatEntry {
+                id: format_id(&ENTRY),
+                line: 42,
+                fields: FIELDS.map(str::as_bytes),
+            })
+        );
+
+        for end in 1..ENTRY.len() {
+            assert!(read_format_entry(&ENTRY[..end]).is_err(), "{end}");
+        }
+    }
+}
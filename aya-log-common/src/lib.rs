@@ -2,16 +2,114 @@
 
 use core::{mem, num, ptr};
 
-use num_enum::IntoPrimitive;
+use format::TagLenValue;
+
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+#[cfg(feature = "std")]
+mod encode;
+pub mod format;
+#[cfg(feature = "printk")]
+mod printk;
+
+#[cfg(feature = "std")]
+pub use encode::RecordBuilder;
+pub use format::{format_entry, format_entry_len, format_id, FORMAT_ENTRY_FIELDS};
+#[cfg(feature = "printk")]
+#[doc(hidden)]
+pub use printk::{PrintkArg, PrintkWriter};
+
+/// `PrintkArg` isn't available without the `printk` feature, see the
+/// definition in `printk.rs`.
+#[cfg(not(feature = "printk"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! impl_printk_enum {
+    ($name:ident) => {};
+}
 
 pub const LOG_BUF_CAPACITY: usize = 8192;
 
-pub const LOG_FIELDS: usize = 6;
+pub const LOG_FIELDS: usize = 7;
 
 pub type LogValueLength = u16;
 
+/// The value of the [`RecordField::Flags`] header field, a combination of the
+/// `RECORD_FLAG_*` constants.
+pub type RecordFlags = u16;
+
+/// Set in the [`RecordField::Flags`] header field when the record didn't fit in
+/// the log buffer and its last argument was truncated.
+pub const RECORD_FLAG_TRUNCATED: RecordFlags = 1;
+
+/// Set in the [`RecordField::Flags`] header field of records whose target,
+/// module, file, line and format string are replaced by a
+/// [`RecordField::FormatId`], see [`write_interned_record_header`].
+pub const RECORD_FLAG_INTERNED: RecordFlags = 2;
+
+/// Set in the [`RecordField::Flags`] header field of records followed by a
+/// [`RecordField::Checksum`] trailer, see [`write_record_checksum`].
+pub const RECORD_FLAG_CHECKSUM: RecordFlags = 4;
+
+/// Set in the [`RecordField::Flags`] header field of records whose header is
+/// followed by a [`RecordField::Pid`] field, see [`write_record_pid`].
+pub const RECORD_FLAG_PID: RecordFlags = 8;
+
+/// Set in the [`RecordField::Flags`] header field of records whose header is
+/// followed by a [`RecordField::CgroupId`] field, see
+/// [`write_record_cgroup_id`].
+pub const RECORD_FLAG_CGROUP_ID: RecordFlags = 16;
+
+/// Set in the [`RecordField::Flags`] header field of records whose header is
+/// followed by a [`RecordField::NetnsCookie`] field, see
+/// [`write_record_netns_cookie`].
+pub const RECORD_FLAG_NETNS_COOKIE: RecordFlags = 32;
+
+/// Set in the [`RecordField::Flags`] header field of records whose header is
+/// followed by a [`RecordField::Timestamp`] field, see
+/// [`write_record_timestamp`].
+pub const RECORD_FLAG_TIMESTAMP: RecordFlags = 64;
+
+/// Set in the [`RecordField::Flags`] header field of records whose header is
+/// followed by a [`RecordField::ProgramId`] field, see
+/// [`write_record_program_id`].
+pub const RECORD_FLAG_PROGRAM_ID: RecordFlags = 128;
+
+/// Set in the [`RecordField::Flags`] header field of records whose header is
+/// followed by a [`RecordField::Sequence`] field, see
+/// [`write_record_sequence`].
+pub const RECORD_FLAG_SEQUENCE: RecordFlags = 256;
+
+/// Length of the [`RecordField::Checksum`] trailer.
+pub const RECORD_CHECKSUM_LEN: usize = RECORD_FIELD_HEADER_LEN + format::CHECKSUM_LEN;
+
+/// Number of header fields of records carrying [`RECORD_FLAG_INTERNED`].
+pub const INTERNED_LOG_FIELDS: usize = 4;
+
+/// Name of the ELF section holding the format entries of interned records, see
+/// [`format_entry`].
+pub const FORMATS_SECTION: &str = "aya_log_fmt";
+
+/// Appended by userspace to the message of records carrying
+/// [`RECORD_FLAG_TRUNCATED`].
+pub const TRUNCATION_MARKER: &str = "…[truncated]";
+
+// The flags and number of arguments are always written first, at fixed offsets,
+// so that they can be updated after the arguments have been written.
+const RECORD_FIELD_HEADER_LEN: usize = format::FIELD_HEADER_LEN;
+/// Offset of the value of the [`RecordField::Flags`] field.
+pub const RECORD_FLAGS_OFFSET: usize = RECORD_FIELD_HEADER_LEN;
+const RECORD_NUM_ARGS_OFFSET: usize =
+    RECORD_FLAGS_OFFSET + mem::size_of::<RecordFlags>() + RECORD_FIELD_HEADER_LEN;
+
+/// Offset of the [`RecordField::Level`] field, which follows the number of
+/// arguments and is itself followed by the [`RecordField::Target`] field, or
+/// the [`RecordField::FormatId`] field of interned records, so that userspace
+/// can filter records without decoding their whole header.
+pub const RECORD_LEVEL_OFFSET: usize = RECORD_NUM_ARGS_OFFSET + mem::size_of::<usize>();
+
 #[repr(u8)]
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash, IntoPrimitive)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash, IntoPrimitive, TryFromPrimitive)]
 pub enum Level {
     /// The "error" level.
     ///
@@ -36,7 +134,7 @@ pub enum Level {
 }
 
 #[repr(u8)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, TryFromPrimitive)]
 pub enum RecordField {
     Target = 1,
     Level,
@@ -44,12 +142,34 @@ pub enum RecordField {
     File,
     Line,
     NumArgs,
+    Flags,
+    /// The [`format_id`] of the entry describing an interned record.
+    FormatId,
+    /// Trailer holding the [`record_checksum`] and the length of the record
+    /// it follows.
+    Checksum,
+    /// The id of the process which logged the record.
+    Pid,
+    /// The id of the cgroup v2 of the process which logged the record.
+    CgroupId,
+    /// The cookie of the network namespace of the program context which
+    /// logged the record.
+    NetnsCookie,
+    /// The time at which the record was logged, in nanoseconds of a [`Clock`]
+    /// followed by the clock.
+    Timestamp,
+    /// The identifier of the program instance which logged the record,
+    /// configured by userspace when loading the program.
+    ProgramId,
+    /// The number of records logged before this one on the same CPU, which
+    /// tells userspace how many records were lost in between.
+    Sequence,
 }
 
 /// Types which are supported by aya-log and can be safely sent from eBPF
 /// programs to userspace.
 #[repr(u8)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, TryFromPrimitive)]
 pub enum Argument {
     DisplayHint,
 
@@ -69,19 +189,41 @@ pub enum Argument {
     F64,
 
     /// `[u8; 6]` array which represents a MAC address.
+    ///
+    /// No longer written, arrays of any length are sent as [`Argument::ArrU8`].
+    /// Kept so that userspace can still read records of older programs.
     ArrU8Len6,
     /// `[u8; 16]` array which represents an IPv6 address.
+    ///
+    /// No longer written, see [`Argument::ArrU8Len6`].
     ArrU8Len16,
     /// `[u16; 8]` array which represents an IPv6 address.
+    ///
+    /// No longer written, see [`Argument::ArrU8Len6`].
     ArrU16Len8,
 
     Bytes,
     Str,
+
+    // New variants must be appended below, the discriminants are part of the
+    // format shared with already compiled eBPF programs.
+    /// `[u8; N]` array, e.g. a MAC or an IP address.
+    ArrU8,
+    /// `[u16; N]` array, e.g. an IPv6 address.
+    ArrU16,
+    /// `[u32; N]` array, e.g. an IPv6 address.
+    ArrU32,
+    /// `[u64; N]` array.
+    ArrU64,
+
+    /// A [`LogEnum`], sent as its [`LogEnum::TYPE_ID`] followed by its
+    /// discriminant as a `u64`.
+    Enum,
 }
 
 /// All display hints
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, PartialEq, Eq, IntoPrimitive)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
 pub enum DisplayHint {
     /// Default string representation.
     Default = 1,
@@ -97,60 +239,50 @@ pub enum DisplayHint {
     LowerMac,
     /// `:MAC`
     UpperMac,
+    /// `:i`, an IPv4 or IPv6 address depending on the type of the argument.
+    Ip,
 }
 
-struct TagLenValue<T, V> {
-    pub tag: T,
-    pub value: V,
+/// Kernel clocks the timestamps of the records are read from.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, IntoPrimitive, TryFromPrimitive)]
+pub enum Clock {
+    /// `CLOCK_MONOTONIC`, read with `bpf_ktime_get_ns`, which doesn't count
+    /// the time the system spends suspended.
+    Monotonic,
+    /// `CLOCK_BOOTTIME`, read with `bpf_ktime_get_boot_ns`, which counts the
+    /// time the system spends suspended.
+    Boottime,
 }
 
-impl<T, V> TagLenValue<T, V>
-where
-    V: IntoIterator<Item = u8>,
-    <V as IntoIterator>::IntoIter: ExactSizeIterator,
-{
-    pub(crate) fn write(self, mut buf: &mut [u8]) -> Result<usize, ()> {
-        // Break the abstraction to please the verifier.
-        if buf.len() > LOG_BUF_CAPACITY {
-            buf = &mut buf[..LOG_BUF_CAPACITY];
-        }
-        let Self { tag, value } = self;
-        let value = value.into_iter();
-        let len = value.len();
-        let wire_len: LogValueLength = value
-            .len()
-            .try_into()
-            .map_err(|num::TryFromIntError { .. }| ())?;
-        let size = mem::size_of_val(&tag) + mem::size_of_val(&wire_len) + len;
-        if size > buf.len() {
-            return Err(());
-        }
-
-        let tag_size = mem::size_of_val(&tag);
-        unsafe { ptr::write_unaligned(buf.as_mut_ptr() as *mut _, tag) };
-        buf = &mut buf[tag_size..];
-
-        unsafe { ptr::write_unaligned(buf.as_mut_ptr() as *mut _, wire_len) };
-        buf = &mut buf[mem::size_of_val(&wire_len)..];
-
-        buf.iter_mut().zip(value).for_each(|(dst, src)| {
-            *dst = src;
-        });
-
-        Ok(size)
-    }
-}
+/// A value which can be written as a log record argument.
+pub trait WriteToBuf {
+    /// Writes `self` to the start of `buf`, returning the number of bytes
+    /// written, or an error if it doesn't fit.
+    #[allow(clippy::result_unit_err)]
+    fn write(self, buf: &mut [u8]) -> Result<usize, ()>;
 
-impl<T, V> TagLenValue<T, V> {
-    #[inline(always)]
-    pub(crate) fn new(tag: T, value: V) -> TagLenValue<T, V> {
-        TagLenValue { tag, value }
+    /// Writes `self` like [`WriteToBuf::write`], but when a variable length
+    /// value doesn't fit writes as much of it as possible instead of failing.
+    ///
+    /// Returns the number of bytes written and whether the value was truncated.
+    #[allow(clippy::result_unit_err)]
+    fn write_or_truncate(self, buf: &mut [u8]) -> Result<(usize, bool), ()>
+    where
+        Self: Sized,
+    {
+        self.write(buf).map(|len| (len, false))
     }
 }
 
-pub trait WriteToBuf {
-    #[allow(clippy::result_unit_err)]
-    fn write(self, buf: &mut [u8]) -> Result<usize, ()>;
+/// Returns how many bytes of a variable length argument fit in `buf`.
+#[inline(always)]
+fn truncated_len(buf: &[u8], len: usize) -> usize {
+    let avail = buf
+        .len()
+        .min(LOG_BUF_CAPACITY)
+        .saturating_sub(mem::size_of::<Argument>() + mem::size_of::<LogValueLength>());
+    avail.min(len)
 }
 
 macro_rules! impl_write_to_buf {
@@ -178,35 +310,65 @@ impl_write_to_buf!(usize, Argument::Usize);
 impl_write_to_buf!(f32, Argument::F32);
 impl_write_to_buf!(f64, Argument::F64);
 
-impl WriteToBuf for [u8; 16] {
-    fn write(self, buf: &mut [u8]) -> Result<usize, ()> {
-        TagLenValue::new(Argument::ArrU8Len16, self).write(buf)
-    }
-}
-
-impl WriteToBuf for [u16; 8] {
-    fn write(self, buf: &mut [u8]) -> Result<usize, ()> {
-        let bytes = unsafe { core::mem::transmute::<_, [u8; 16]>(self) };
-        TagLenValue::new(Argument::ArrU16Len8, bytes).write(buf)
-    }
+// Arrays are sent as their native endian bytes, the number of elements is
+// derived from the length of the value.
+macro_rules! impl_write_to_buf_array {
+    ($type:ident, $arg_type:expr) => {
+        impl<const N: usize> WriteToBuf for [$type; N] {
+            fn write(self, buf: &mut [u8]) -> Result<usize, ()> {
+                let bytes = unsafe {
+                    core::slice::from_raw_parts(self.as_ptr() as *const u8, mem::size_of_val(&self))
+                };
+                TagLenValue::new($arg_type, bytes.iter().copied()).write(buf)
+            }
+        }
+    };
 }
 
-impl WriteToBuf for [u8; 6] {
-    fn write(self, buf: &mut [u8]) -> Result<usize, ()> {
-        TagLenValue::new(Argument::ArrU8Len6, self).write(buf)
-    }
-}
+impl_write_to_buf_array!(u8, Argument::ArrU8);
+impl_write_to_buf_array!(u16, Argument::ArrU16);
+impl_write_to_buf_array!(u32, Argument::ArrU32);
+impl_write_to_buf_array!(u64, Argument::ArrU64);
 
 impl WriteToBuf for &[u8] {
     fn write(self, buf: &mut [u8]) -> Result<usize, ()> {
         TagLenValue::new(Argument::Bytes, self.iter().copied()).write(buf)
     }
+
+    fn write_or_truncate(self, buf: &mut [u8]) -> Result<(usize, bool), ()> {
+        match self.write(buf) {
+            Ok(len) => Ok((len, false)),
+            Err(()) => {
+                let end = truncated_len(buf, self.len());
+                self[..end].write(buf).map(|len| (len, true))
+            }
+        }
+    }
 }
 
 impl WriteToBuf for &str {
     fn write(self, buf: &mut [u8]) -> Result<usize, ()> {
         TagLenValue::new(Argument::Str, self.as_bytes().iter().copied()).write(buf)
     }
+
+    fn write_or_truncate(self, buf: &mut [u8]) -> Result<(usize, bool), ()> {
+        match self.write(buf) {
+            Ok(len) => Ok((len, false)),
+            Err(()) => {
+                // Never split a multi-byte character so that userspace still
+                // receives valid UTF-8. Characters are 4 bytes long at most,
+                // the loop is bounded so that the verifier accepts it.
+                let mut end = truncated_len(buf, self.len());
+                for _ in 0..3 {
+                    if self.is_char_boundary(end) {
+                        break;
+                    }
+                    end -= 1;
+                }
+                self[..end].write(buf).map(|len| (len, true))
+            }
+        }
+    }
 }
 
 impl WriteToBuf for DisplayHint {
@@ -216,9 +378,95 @@ impl WriteToBuf for DisplayHint {
     }
 }
 
+/// Writes an [`Argument::Str`] whose value is read straight into `buf` by
+/// `read`, for strings which aren't directly accessible to the eBPF program.
+///
+/// `read` is given the space available for the value and returns the length of
+/// the string it wrote and whether it was truncated.
 #[allow(clippy::result_unit_err)]
 #[doc(hidden)]
 #[inline(always)]
+pub fn write_str_with(
+    mut buf: &mut [u8],
+    read: impl FnOnce(&mut [u8]) -> (usize, bool),
+) -> Result<(usize, bool), ()> {
+    if buf.len() > LOG_BUF_CAPACITY {
+        buf = &mut buf[..LOG_BUF_CAPACITY];
+    }
+    let header_len = mem::size_of::<Argument>() + mem::size_of::<LogValueLength>();
+    if buf.len() < header_len {
+        return Err(());
+    }
+    let (header, value) = buf.split_at_mut(header_len);
+    let (len, truncated) = read(value);
+    if len > value.len() {
+        return Err(());
+    }
+    let wire_len: LogValueLength = len.try_into().map_err(|num::TryFromIntError { .. }| ())?;
+
+    unsafe { ptr::write_unaligned(header.as_mut_ptr() as *mut _, Argument::Str) };
+    unsafe {
+        ptr::write_unaligned(
+            header[mem::size_of::<Argument>()..].as_mut_ptr() as *mut _,
+            wire_len,
+        )
+    };
+    Ok((header_len + len, truncated))
+}
+
+/// Fieldless enums which are logged by their discriminant and formatted with the
+/// name of their variant.
+///
+/// Implement it with `#[derive(LogEnum)]` from `aya-log-ebpf-macros`, in a crate
+/// shared by the eBPF program and userspace so that the enum can be registered
+/// with `aya_log::BpfLogger::register_enum`.
+pub trait LogEnum {
+    /// Identifies the enum in log records, see [`enum_type_id`].
+    const TYPE_ID: u32;
+
+    /// Returns the discriminant of `self`.
+    fn discriminant(&self) -> u64;
+
+    /// Returns the name of the variant with the given discriminant.
+    fn variant_name(discriminant: u64) -> Option<&'static str>;
+}
+
+/// Hashes the path of an enum into its [`LogEnum::TYPE_ID`] using FNV-1a.
+pub const fn enum_type_id(path: &str) -> u32 {
+    fnv1a(path.as_bytes())
+}
+
+const FNV_OFFSET_BASIS: u32 = 0x811c9dc5;
+const FNV_PRIME: u32 = 0x01000193;
+
+pub(crate) const fn fnv1a(bytes: &[u8]) -> u32 {
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u32;
+        hash = hash.wrapping_mul(FNV_PRIME);
+        i += 1;
+    }
+    hash
+}
+
+/// Implementation of [`WriteToBuf::write`] for [`LogEnum`] types.
+#[allow(clippy::result_unit_err)]
+#[doc(hidden)]
+#[inline(always)]
+pub fn write_enum<T: LogEnum>(v: &T, buf: &mut [u8]) -> Result<usize, ()> {
+    let value = format::encode_enum(T::TYPE_ID, v.discriminant());
+    TagLenValue::new(Argument::Enum, value).write(buf)
+}
+
+/// Writes the header of a record with `num_args` arguments to the start of
+/// `buf`, returning its length.
+///
+/// The arguments are written with [`WriteToBuf`] right after the header, or
+/// after the context fields when there are any. Fails if the header doesn't
+/// fit in `buf`.
+#[allow(clippy::result_unit_err)]
+#[inline(always)]
 pub fn write_record_header(
     buf: &mut [u8],
     target: &str,
@@ -230,19 +478,215 @@ pub fn write_record_header(
 ) -> Result<usize, ()> {
     let level: u8 = level.into();
     let mut size = 0;
-    size += TagLenValue::new(RecordField::Target, target.as_bytes().iter().copied())
+    size += TagLenValue::new(RecordField::Flags, (0 as RecordFlags).to_ne_bytes())
         .write(&mut buf[size..])?;
+    size +=
+        TagLenValue::new(RecordField::NumArgs, num_args.to_ne_bytes()).write(&mut buf[size..])?;
     size += TagLenValue::new(RecordField::Level, level.to_ne_bytes()).write(&mut buf[size..])?;
+    size += TagLenValue::new(RecordField::Target, target.as_bytes().iter().copied())
+        .write(&mut buf[size..])?;
     size += TagLenValue::new(RecordField::Module, module.as_bytes().iter().copied())
         .write(&mut buf[size..])?;
     size += TagLenValue::new(RecordField::File, file.as_bytes().iter().copied())
         .write(&mut buf[size..])?;
     size += TagLenValue::new(RecordField::Line, line.to_ne_bytes()).write(&mut buf[size..])?;
+    Ok(size)
+}
+
+/// Writes the header of an interned record, whose target, module, file, line
+/// and format string are looked up by userspace from its format entry.
+#[allow(clippy::result_unit_err)]
+#[doc(hidden)]
+#[inline(always)]
+pub fn write_interned_record_header(
+    buf: &mut [u8],
+    format_id: u32,
+    level: Level,
+    num_args: usize,
+) -> Result<usize, ()> {
+    let level: u8 = level.into();
+    let mut size = 0;
+    size += TagLenValue::new(RecordField::Flags, RECORD_FLAG_INTERNED.to_ne_bytes())
+        .write(&mut buf[size..])?;
     size +=
         TagLenValue::new(RecordField::NumArgs, num_args.to_ne_bytes()).write(&mut buf[size..])?;
+    size += TagLenValue::new(RecordField::Level, level.to_ne_bytes()).write(&mut buf[size..])?;
+    size +=
+        TagLenValue::new(RecordField::FormatId, format_id.to_ne_bytes()).write(&mut buf[size..])?;
     Ok(size)
 }
 
+/// Appends a [`RecordField::Pid`] field to the header of `header_len` bytes
+/// written to `buf`.
+///
+/// Returns the length of the header including the field, headers which leave
+/// no room for it are sent without.
+#[doc(hidden)]
+#[inline(always)]
+pub fn write_record_pid(buf: &mut [u8], header_len: usize, pid: u32) -> usize {
+    write_optional_field(
+        buf,
+        header_len,
+        RecordField::Pid,
+        RECORD_FLAG_PID,
+        pid.to_ne_bytes(),
+    )
+}
+
+/// Appends a [`RecordField::CgroupId`] field to the header of `header_len`
+/// bytes written to `buf`, see [`write_record_pid`].
+#[doc(hidden)]
+#[inline(always)]
+pub fn write_record_cgroup_id(buf: &mut [u8], header_len: usize, cgroup_id: u64) -> usize {
+    write_optional_field(
+        buf,
+        header_len,
+        RecordField::CgroupId,
+        RECORD_FLAG_CGROUP_ID,
+        cgroup_id.to_ne_bytes(),
+    )
+}
+
+/// Appends a [`RecordField::NetnsCookie`] field to the header of
+/// `header_len` bytes written to `buf`, see [`write_record_pid`].
+#[doc(hidden)]
+#[inline(always)]
+pub fn write_record_netns_cookie(buf: &mut [u8], header_len: usize, cookie: u64) -> usize {
+    write_optional_field(
+        buf,
+        header_len,
+        RecordField::NetnsCookie,
+        RECORD_FLAG_NETNS_COOKIE,
+        cookie.to_ne_bytes(),
+    )
+}
+
+/// Appends a [`RecordField::Timestamp`] field to the header of `header_len`
+/// bytes written to `buf`, see [`write_record_pid`].
+#[doc(hidden)]
+#[inline(always)]
+pub fn write_record_timestamp(buf: &mut [u8], header_len: usize, clock: Clock, ns: u64) -> usize {
+    let value = format::encode_timestamp(clock, ns);
+    write_optional_field(
+        buf,
+        header_len,
+        RecordField::Timestamp,
+        RECORD_FLAG_TIMESTAMP,
+        value,
+    )
+}
+
+/// Appends a [`RecordField::ProgramId`] field to the header of `header_len`
+/// bytes written to `buf`, see [`write_record_pid`].
+#[doc(hidden)]
+#[inline(always)]
+pub fn write_record_program_id(buf: &mut [u8], header_len: usize, id: u32) -> usize {
+    write_optional_field(
+        buf,
+        header_len,
+        RecordField::ProgramId,
+        RECORD_FLAG_PROGRAM_ID,
+        id.to_ne_bytes(),
+    )
+}
+
+/// Appends a [`RecordField::Sequence`] field to the header of `header_len`
+/// bytes written to `buf`, see [`write_record_pid`].
+#[doc(hidden)]
+#[inline(always)]
+pub fn write_record_sequence(buf: &mut [u8], header_len: usize, sequence: u64) -> usize {
+    write_optional_field(
+        buf,
+        header_len,
+        RecordField::Sequence,
+        RECORD_FLAG_SEQUENCE,
+        sequence.to_ne_bytes(),
+    )
+}
+
+#[inline(always)]
+fn write_optional_field<const N: usize>(
+    buf: &mut [u8],
+    header_len: usize,
+    tag: RecordField,
+    flag: RecordFlags,
+    value: [u8; N],
+) -> usize {
+    let field = match buf.get_mut(header_len..) {
+        Some(field) => field,
+        None => return header_len,
+    };
+    match TagLenValue::new(tag, value).write(field) {
+        Ok(len) => {
+            set_record_flags(buf, flag);
+            header_len + len
+        }
+        Err(()) => header_len,
+    }
+}
+
+/// Checksums the bytes of a record, see [`write_record_checksum`].
+pub fn record_checksum(record: &[u8]) -> u32 {
+    record
+        .iter()
+        .take(LOG_BUF_CAPACITY)
+        .fold(FNV_OFFSET_BASIS, |hash, b| {
+            (hash ^ *b as u32).wrapping_mul(FNV_PRIME)
+        })
+}
+
+/// Appends a [`RecordField::Checksum`] trailer to the record of `record_len`
+/// bytes written to `buf`, so that userspace can tell corrupted records apart
+/// from invalid ones.
+///
+/// Returns the length of the record including the trailer, records which
+/// leave no room for it are sent without.
+#[doc(hidden)]
+#[inline(always)]
+pub fn write_record_checksum(buf: &mut [u8], record_len: usize) -> usize {
+    if record_len > LOG_BUF_CAPACITY || buf.len() < record_len + RECORD_CHECKSUM_LEN {
+        return record_len;
+    }
+    set_record_flags(buf, RECORD_FLAG_CHECKSUM);
+    let checksum = record_checksum(&buf[..record_len]);
+    let value = format::encode_checksum(checksum, record_len as u32);
+    match TagLenValue::new(RecordField::Checksum, value).write(&mut buf[record_len..]) {
+        Ok(len) => record_len + len,
+        Err(()) => record_len,
+    }
+}
+
+/// Sets `flags` in the header of the record previously written to `buf` with
+/// [`write_record_header`].
+#[doc(hidden)]
+#[inline(always)]
+pub fn set_record_flags(buf: &mut [u8], flags: RecordFlags) {
+    if let Some(v) =
+        buf.get_mut(RECORD_FLAGS_OFFSET..RECORD_FLAGS_OFFSET + mem::size_of::<RecordFlags>())
+    {
+        let current = RecordFlags::from_ne_bytes([v[0], v[1]]);
+        v.copy_from_slice(&(current | flags).to_ne_bytes());
+    }
+}
+
+/// Returns the flags of the record in `buf`, see [`set_record_flags`].
+pub fn record_flags(buf: &[u8]) -> Option<RecordFlags> {
+    let v = buf.get(RECORD_FLAGS_OFFSET..RECORD_FLAGS_OFFSET + mem::size_of::<RecordFlags>())?;
+    Some(RecordFlags::from_ne_bytes([v[0], v[1]]))
+}
+
+/// Overwrites the number of arguments in the header of the record previously
+/// written to `buf` with [`write_record_header`].
+#[doc(hidden)]
+#[inline(always)]
+pub fn set_record_num_args(buf: &mut [u8], num_args: usize) {
+    if let Some(v) =
+        buf.get_mut(RECORD_NUM_ARGS_OFFSET..RECORD_NUM_ARGS_OFFSET + mem::size_of::<usize>())
+    {
+        v.copy_from_slice(&num_args.to_ne_bytes());
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -255,4 +699,128 @@ mod test {
             LogValueLength::MAX
         );
     }
+
+    #[test]
+    fn argument_discriminants_stable() {
+        // Already compiled eBPF programs write these values, changing them
+        // breaks decoding of their records.
+        assert_eq!(Argument::DisplayHint as u8, 0);
+        assert_eq!(Argument::F64 as u8, 12);
+        assert_eq!(Argument::ArrU8Len6 as u8, 13);
+        assert_eq!(Argument::ArrU8Len16 as u8, 14);
+        assert_eq!(Argument::ArrU16Len8 as u8, 15);
+        assert_eq!(Argument::Bytes as u8, 16);
+        assert_eq!(Argument::Str as u8, 17);
+        assert_eq!(Argument::ArrU8 as u8, 18);
+        assert_eq!(Argument::Enum as u8, 22);
+    }
+
+    #[test]
+    fn write_or_truncate_fits() {
+        let mut buf = [0u8; 16];
+        assert_eq!("abc".write_or_truncate(&mut buf), Ok((6, false)));
+        assert_eq!(&buf[3..6], b"abc");
+    }
+
+    #[test]
+    fn write_or_truncate_char_boundary() {
+        // Room for the tag, the length and 2 bytes of the value, which would
+        // split the 3 bytes of `…` in half.
+        let mut buf = [0u8; 5];
+        assert_eq!("a…".write_or_truncate(&mut buf), Ok((4, true)));
+        assert_eq!(buf[3], b'a');
+
+        // Room for 3 bytes of the 4 bytes of `🦀`.
+        let mut buf = [0u8; 7];
+        assert_eq!("a🦀".write_or_truncate(&mut buf), Ok((4, true)));
+        assert_eq!(buf[3], b'a');
+    }
+
+    #[test]
+    fn write_str_with() {
+        let mut buf = [0u8; 8];
+        let ret = super::write_str_with(&mut buf, |dest| {
+            assert_eq!(dest.len(), 5);
+            dest[..2].copy_from_slice(b"ab");
+            (2, false)
+        });
+        assert_eq!(ret, Ok((5, false)));
+        assert_eq!(buf[0], Argument::Str as u8);
+        assert_eq!(LogValueLength::from_ne_bytes([buf[1], buf[2]]), 2);
+        assert_eq!(&buf[3..5], b"ab");
+
+        assert_eq!(
+            super::write_str_with(&mut buf[..2], |_| (0, false)),
+            Err(())
+        );
+    }
+
+    #[test]
+    fn record_header_offsets() {
+        let mut buf = [0u8; 64];
+        let target_offset = RECORD_LEVEL_OFFSET + RECORD_FIELD_HEADER_LEN + 1;
+
+        write_record_header(&mut buf, "tgt", Level::Warn, "m", "f", 1, 0).unwrap();
+        assert_eq!(buf[RECORD_LEVEL_OFFSET], RecordField::Level as u8);
+        assert_eq!(
+            buf[RECORD_LEVEL_OFFSET + RECORD_FIELD_HEADER_LEN],
+            Level::Warn as u8
+        );
+        assert_eq!(buf[target_offset], RecordField::Target as u8);
+        assert_eq!(&buf[target_offset + RECORD_FIELD_HEADER_LEN..][..3], b"tgt");
+
+        write_interned_record_header(&mut buf, 1, Level::Warn, 0).unwrap();
+        assert_eq!(buf[RECORD_LEVEL_OFFSET], RecordField::Level as u8);
+        assert_eq!(buf[target_offset], RecordField::FormatId as u8);
+
+        assert_eq!(record_flags(&buf), Some(RECORD_FLAG_INTERNED));
+        set_record_flags(&mut buf, RECORD_FLAG_SEQUENCE);
+        assert_eq!(
+            record_flags(&buf),
+            Some(RECORD_FLAG_INTERNED | RECORD_FLAG_SEQUENCE)
+        );
+    }
+
+    #[test]
+    fn write_record_checksum() {
+        let mut buf = [0u8; 64];
+        let len = write_record_header(&mut buf, "t", Level::Info, "m", "f", 1, 0).unwrap();
+        let record_len = super::write_record_checksum(&mut buf, len);
+        assert_eq!(record_len, len + RECORD_CHECKSUM_LEN);
+        assert_eq!(
+            record_flags(&buf).unwrap() & RECORD_FLAG_CHECKSUM,
+            RECORD_FLAG_CHECKSUM
+        );
+        assert_eq!(buf[len], RecordField::Checksum as u8);
+        let checksum = u32::from_ne_bytes(buf[len + 3..len + 7].try_into().unwrap());
+        assert_eq!(checksum, record_checksum(&buf[..len]));
+        assert_eq!(
+            u32::from_ne_bytes(buf[len + 7..len + 11].try_into().unwrap()) as usize,
+            len
+        );
+
+        // No room left for the trailer.
+        assert_eq!(super::write_record_checksum(&mut buf[..len + 1], len), len);
+    }
+
+    #[test]
+    fn format_entry() {
+        const FIELDS: [&str; FORMAT_ENTRY_FIELDS] = ["target", "module", "file.rs", "{}"];
+        const ENTRY: [u8; format_entry_len(&FIELDS)] = super::format_entry(42, &FIELDS);
+
+        assert_eq!(ENTRY.len(), 2 + 4 + 2 * 4 + 6 + 6 + 7 + 2);
+        assert_eq!(
+            LogValueLength::from_ne_bytes([ENTRY[0], ENTRY[1]]) as usize,
+            ENTRY.len() - 2
+        );
+        assert_eq!(u32::from_ne_bytes(ENTRY[2..6].try_into().unwrap()), 42);
+        assert_eq!(&ENTRY[8..14], b"target");
+        assert_eq!(&ENTRY[ENTRY.len() - 2..], b"{}");
+    }
+
+    #[test]
+    fn write_or_truncate_fixed_size() {
+        let mut buf = [0u8; 4];
+        assert_eq!(1u32.write_or_truncate(&mut buf), Err(()));
+    }
 }
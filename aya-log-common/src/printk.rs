@@ -0,0 +1,415 @@
+//! Formatting of log records into `bpf_trace_printk` messages.
+//!
+//! When the `printk` feature of `aya-log-ebpf` is enabled the macros render
+//! each record into a text message in the eBPF program itself and write it to
+//! the kernel trace pipe, so that logs are visible before a userspace reader is
+//! running.
+use crate::{DisplayHint, Level, LogEnum};
+
+const HEX_DIGITS_LOWER: &[u8; 16] = b"0123456789abcdef";
+const HEX_DIGITS_UPPER: &[u8; 16] = b"0123456789ABCDEF";
+
+/// Printed in place of arguments whose type and display hint can't be rendered
+/// in the eBPF program.
+const UNSUPPORTED: &str = "?";
+
+/// A NUL terminated text message built in the log buffer.
+pub struct PrintkWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> PrintkWriter<'a> {
+    #[inline(always)]
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        PrintkWriter { buf, len: 0 }
+    }
+
+    #[inline(always)]
+    pub fn write_header(&mut self, level: Level, target: &str) {
+        self.write_str(match level {
+            Level::Error => "[ERROR] ",
+            Level::Warn => "[WARN] ",
+            Level::Info => "[INFO] ",
+            Level::Debug => "[DEBUG] ",
+            Level::Trace => "[TRACE] ",
+        });
+        self.write_str(target);
+        self.write_str(": ");
+    }
+
+    #[inline(always)]
+    pub fn write_str(&mut self, s: &str) {
+        self.write_bytes(s.as_bytes())
+    }
+
+    /// Lets `read` write directly to the space left in the message and
+    /// returns the number of bytes it wrote.
+    #[inline(always)]
+    pub fn write_with(&mut self, read: impl FnOnce(&mut [u8]) -> usize) {
+        // Always keep room for the terminating NUL byte.
+        let end = self.buf.len().saturating_sub(1);
+        if self.len < end {
+            let len = read(&mut self.buf[self.len..end]);
+            self.len += len.min(end - self.len);
+        }
+    }
+
+    #[inline(always)]
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.push(*b);
+        }
+    }
+
+    #[inline(always)]
+    fn push(&mut self, b: u8) {
+        // Always keep room for the terminating NUL byte.
+        if self.len + 1 < self.buf.len() {
+            self.buf[self.len] = b;
+            self.len += 1;
+        }
+    }
+
+    #[inline(always)]
+    fn write_unsigned(&mut self, mut v: u64, radix: u64, digits: &[u8; 16]) {
+        let mut tmp = [0u8; 20];
+        let mut i = tmp.len();
+        loop {
+            i -= 1;
+            tmp[i] = digits[(v % radix) as usize];
+            v /= radix;
+            if v == 0 || i == 0 {
+                break;
+            }
+        }
+        self.write_bytes(&tmp[i..]);
+    }
+
+    #[inline(always)]
+    fn write_decimal(&mut self, v: u64) {
+        self.write_unsigned(v, 10, HEX_DIGITS_LOWER)
+    }
+
+    #[inline(always)]
+    fn write_signed(&mut self, v: i64) {
+        if v < 0 {
+            self.push(b'-');
+        }
+        self.write_unsigned(v.unsigned_abs(), 10, HEX_DIGITS_LOWER);
+    }
+
+    #[inline(always)]
+    fn write_hex_byte(&mut self, b: u8, digits: &[u8; 16]) {
+        self.push(digits[(b >> 4) as usize]);
+        self.push(digits[(b & 0xf) as usize]);
+    }
+
+    #[inline(always)]
+    fn write_list<T: PrintkArg + Copy>(&mut self, v: &[T], hint: DisplayHint) {
+        if !matches!(
+            hint,
+            DisplayHint::Default | DisplayHint::LowerHex | DisplayHint::UpperHex
+        ) {
+            return self.write_str(UNSUPPORTED);
+        }
+        self.push(b'[');
+        for (i, e) in v.iter().enumerate() {
+            if i != 0 {
+                self.write_str(", ");
+            }
+            e.write_printk(hint, self);
+        }
+        self.push(b']');
+    }
+
+    /// Terminates the message and returns it, including the NUL byte.
+    #[inline(always)]
+    pub fn finish(self) -> &'a [u8] {
+        let len = self.len.min(self.buf.len() - 1);
+        self.buf[len] = 0;
+        &self.buf[..=len]
+    }
+
+    /// Writes the name of the variant of a [`LogEnum`], or its discriminant if
+    /// the variant is unknown.
+    #[inline(always)]
+    pub fn write_enum<T: LogEnum>(&mut self, v: &T, hint: DisplayHint) {
+        let discriminant = v.discriminant();
+        match hint {
+            DisplayHint::Default => match T::variant_name(discriminant) {
+                Some(name) => self.write_str(name),
+                None => self.write_unsigned(discriminant, 10, HEX_DIGITS_LOWER),
+            },
+            DisplayHint::LowerHex => self.write_unsigned(discriminant, 16, HEX_DIGITS_LOWER),
+            DisplayHint::UpperHex => self.write_unsigned(discriminant, 16, HEX_DIGITS_UPPER),
+            _ => self.write_str(UNSUPPORTED),
+        }
+    }
+}
+
+/// Types which can be rendered into a [`PrintkWriter`].
+pub trait PrintkArg {
+    fn write_printk(self, hint: DisplayHint, w: &mut PrintkWriter);
+}
+
+macro_rules! impl_printk_arg {
+    ($type:ident, $unsigned:ident, $wide:ident, $write_decimal:ident) => {
+        impl PrintkArg for $type {
+            #[inline(always)]
+            fn write_printk(self, hint: DisplayHint, w: &mut PrintkWriter) {
+                match hint {
+                    DisplayHint::Default => w.$write_decimal(self as $wide),
+                    DisplayHint::LowerHex => {
+                        w.write_unsigned(self as $unsigned as u64, 16, HEX_DIGITS_LOWER)
+                    }
+                    DisplayHint::UpperHex => {
+                        w.write_unsigned(self as $unsigned as u64, 16, HEX_DIGITS_UPPER)
+                    }
+                    _ => w.write_str(UNSUPPORTED),
+                }
+            }
+        }
+    };
+}
+
+impl_printk_arg!(i8, u8, i64, write_signed);
+impl_printk_arg!(i16, u16, i64, write_signed);
+impl_printk_arg!(i32, u32, i64, write_signed);
+impl_printk_arg!(i64, u64, i64, write_signed);
+impl_printk_arg!(isize, usize, i64, write_signed);
+
+impl_printk_arg!(u8, u8, u64, write_decimal);
+impl_printk_arg!(u16, u16, u64, write_decimal);
+impl_printk_arg!(u64, u64, u64, write_decimal);
+impl_printk_arg!(usize, usize, u64, write_decimal);
+
+impl PrintkArg for u32 {
+    #[inline(always)]
+    fn write_printk(self, hint: DisplayHint, w: &mut PrintkWriter) {
+        match hint {
+            DisplayHint::Default => w.write_unsigned(self as u64, 10, HEX_DIGITS_LOWER),
+            DisplayHint::LowerHex => w.write_unsigned(self as u64, 16, HEX_DIGITS_LOWER),
+            DisplayHint::UpperHex => w.write_unsigned(self as u64, 16, HEX_DIGITS_UPPER),
+            DisplayHint::Ipv4 | DisplayHint::Ip => {
+                for (i, b) in self.to_be_bytes().iter().enumerate() {
+                    if i != 0 {
+                        w.push(b'.');
+                    }
+                    w.write_unsigned(*b as u64, 10, HEX_DIGITS_LOWER);
+                }
+            }
+            _ => w.write_str(UNSUPPORTED),
+        }
+    }
+}
+
+// eBPF has no floating point support, floats can only be forwarded as raw bits.
+impl PrintkArg for f32 {
+    #[inline(always)]
+    fn write_printk(self, _hint: DisplayHint, w: &mut PrintkWriter) {
+        w.write_str(UNSUPPORTED)
+    }
+}
+
+impl PrintkArg for f64 {
+    #[inline(always)]
+    fn write_printk(self, _hint: DisplayHint, w: &mut PrintkWriter) {
+        w.write_str(UNSUPPORTED)
+    }
+}
+
+impl<const N: usize> PrintkArg for [u8; N] {
+    #[inline(always)]
+    fn write_printk(self, hint: DisplayHint, w: &mut PrintkWriter) {
+        match hint {
+            DisplayHint::LowerHex => self.as_slice().write_printk(hint, w),
+            DisplayHint::UpperHex => self.as_slice().write_printk(hint, w),
+            DisplayHint::LowerMac | DisplayHint::UpperMac if N == 6 => {
+                let digits = if matches!(hint, DisplayHint::LowerMac) {
+                    HEX_DIGITS_LOWER
+                } else {
+                    HEX_DIGITS_UPPER
+                };
+                for (i, b) in self.iter().enumerate() {
+                    if i != 0 {
+                        w.push(b':');
+                    }
+                    w.write_hex_byte(*b, digits);
+                }
+            }
+            DisplayHint::Ipv4 | DisplayHint::Ip if N == 4 => {
+                for (i, b) in self.iter().enumerate() {
+                    if i != 0 {
+                        w.push(b'.');
+                    }
+                    w.write_unsigned(*b as u64, 10, HEX_DIGITS_LOWER);
+                }
+            }
+            // IPv6 addresses are written in their full, uncompressed form.
+            DisplayHint::Ipv6 | DisplayHint::Ip if N == 16 => {
+                for (i, s) in self.chunks_exact(2).enumerate() {
+                    if i != 0 {
+                        w.push(b':');
+                    }
+                    let v = u16::from_be_bytes([s[0], s[1]]);
+                    w.write_unsigned(v as u64, 16, HEX_DIGITS_LOWER);
+                }
+            }
+            _ => w.write_list(&self, hint),
+        }
+    }
+}
+
+impl<const N: usize> PrintkArg for [u16; N] {
+    #[inline(always)]
+    fn write_printk(self, hint: DisplayHint, w: &mut PrintkWriter) {
+        match hint {
+            DisplayHint::Ipv6 | DisplayHint::Ip if N == 8 => {
+                for (i, v) in self.iter().enumerate() {
+                    if i != 0 {
+                        w.push(b':');
+                    }
+                    w.write_unsigned(*v as u64, 16, HEX_DIGITS_LOWER);
+                }
+            }
+            _ => w.write_list(&self, hint),
+        }
+    }
+}
+
+impl<const N: usize> PrintkArg for [u32; N] {
+    #[inline(always)]
+    fn write_printk(self, hint: DisplayHint, w: &mut PrintkWriter) {
+        match hint {
+            DisplayHint::Ipv4 | DisplayHint::Ip if N == 1 => self[0].write_printk(hint, w),
+            DisplayHint::Ipv6 | DisplayHint::Ip if N == 4 => {
+                for (i, v) in self.iter().enumerate() {
+                    if i != 0 {
+                        w.push(b':');
+                    }
+                    w.write_unsigned((*v >> 16) as u64, 16, HEX_DIGITS_LOWER);
+                    w.push(b':');
+                    w.write_unsigned((*v & 0xffff) as u64, 16, HEX_DIGITS_LOWER);
+                }
+            }
+            _ => w.write_list(&self, hint),
+        }
+    }
+}
+
+impl<const N: usize> PrintkArg for [u64; N] {
+    #[inline(always)]
+    fn write_printk(self, hint: DisplayHint, w: &mut PrintkWriter) {
+        w.write_list(&self, hint)
+    }
+}
+
+impl PrintkArg for &[u8] {
+    #[inline(always)]
+    fn write_printk(self, hint: DisplayHint, w: &mut PrintkWriter) {
+        let digits = match hint {
+            DisplayHint::LowerHex => HEX_DIGITS_LOWER,
+            DisplayHint::UpperHex => HEX_DIGITS_UPPER,
+            _ => return w.write_str(UNSUPPORTED),
+        };
+        for b in self {
+            w.write_hex_byte(*b, digits);
+        }
+    }
+}
+
+impl PrintkArg for &str {
+    #[inline(always)]
+    fn write_printk(self, _hint: DisplayHint, w: &mut PrintkWriter) {
+        w.write_str(self)
+    }
+}
+
+/// Implements [`PrintkArg`] for a type deriving `LogEnum`.
+///
+/// Used by the derive, which can't know whether the `printk` feature is
+/// enabled. Expands to nothing when it isn't, see the definition in `lib.rs`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! impl_printk_enum {
+    ($name:ident) => {
+        impl $crate::PrintkArg for $name {
+            #[inline(always)]
+            fn write_printk(self, hint: $crate::DisplayHint, w: &mut $crate::PrintkWriter) {
+                w.write_enum(&self, hint)
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn printk(arg: impl PrintkArg, hint: DisplayHint) -> [u8; 64] {
+        let mut buf = [0u8; 64];
+        let mut w = PrintkWriter::new(&mut buf);
+        arg.write_printk(hint, &mut w);
+        let len = w.finish().len();
+        // Compared as NUL padded arrays, the crate doesn't link `std`.
+        buf[len..].fill(0);
+        buf
+    }
+
+    fn message(s: &str) -> [u8; 64] {
+        let mut buf = [0u8; 64];
+        buf[..s.len()].copy_from_slice(s.as_bytes());
+        buf
+    }
+
+    #[test]
+    fn unsigned() {
+        for (arg, hint, expected) in [
+            (u64::MAX, DisplayHint::Default, "18446744073709551615"),
+            (u64::MAX, DisplayHint::LowerHex, "ffffffffffffffff"),
+            (u64::MAX, DisplayHint::UpperHex, "FFFFFFFFFFFFFFFF"),
+            (u64::MAX, DisplayHint::Ipv4, UNSUPPORTED),
+            (1 << 63, DisplayHint::Default, "9223372036854775808"),
+        ] {
+            assert_eq!(printk(arg, hint), message(expected), "{arg} {hint:?}");
+        }
+        for (arg, hint, expected) in [
+            (usize::MAX, DisplayHint::Default, "18446744073709551615"),
+            (usize::MAX, DisplayHint::LowerHex, "ffffffffffffffff"),
+            (usize::MAX, DisplayHint::UpperHex, "FFFFFFFFFFFFFFFF"),
+            (usize::MAX, DisplayHint::LowerMac, UNSUPPORTED),
+        ] {
+            assert_eq!(printk(arg, hint), message(expected), "{arg} {hint:?}");
+        }
+        assert_eq!(printk(u8::MAX, DisplayHint::Default), message("255"));
+        assert_eq!(printk(u16::MAX, DisplayHint::UpperHex), message("FFFF"));
+        assert_eq!(
+            printk(u32::MAX, DisplayHint::Default),
+            message("4294967295")
+        );
+        assert_eq!(
+            printk(u32::MAX, DisplayHint::Ipv4),
+            message("255.255.255.255")
+        );
+    }
+
+    #[test]
+    fn signed() {
+        for (arg, hint, expected) in [
+            (i64::MIN, DisplayHint::Default, "-9223372036854775808"),
+            (i64::MAX, DisplayHint::Default, "9223372036854775807"),
+            (-1, DisplayHint::LowerHex, "ffffffffffffffff"),
+            (-1, DisplayHint::UpperHex, "FFFFFFFFFFFFFFFF"),
+            (-1, DisplayHint::Ip, UNSUPPORTED),
+        ] {
+            assert_eq!(printk(arg, hint), message(expected), "{arg} {hint:?}");
+        }
+        assert_eq!(printk(-1i8, DisplayHint::Default), message("-1"));
+        assert_eq!(printk(-1i8, DisplayHint::LowerHex), message("ff"));
+        assert_eq!(
+            printk(isize::MIN, DisplayHint::Default),
+            message("-9223372036854775808")
+        );
+    }
+}
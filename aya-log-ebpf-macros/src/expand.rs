@@ -4,14 +4,14 @@ use syn::{
     parse::{Parse, ParseStream},
     parse_str,
     punctuated::Punctuated,
-    Error, Expr, LitStr, Result, Token,
+    Error, Expr, ExprLit, Lit, LitStr, Result, Token,
 };
 
 use aya_log_common::DisplayHint;
 use aya_log_parser::{parse, Fragment};
 
 pub(crate) struct LogArgs {
-    pub(crate) ctx: Expr,
+    pub(crate) ctx: Option<Expr>,
     pub(crate) target: Option<Expr>,
     pub(crate) level: Option<Expr>,
     pub(crate) format_string: LitStr,
@@ -20,12 +20,27 @@ pub(crate) struct LogArgs {
 
 mod kw {
     syn::custom_keyword!(target);
+    syn::custom_keyword!(printk);
 }
 
 impl Parse for LogArgs {
     fn parse(input: ParseStream) -> Result<Self> {
-        let ctx: Expr = input.parse()?;
-        input.parse::<Token![,]>()?;
+        // Programs without a context pass `printk` instead, to write the
+        // record to the kernel trace pipe.
+        let ctx: Option<Expr> = if input.peek(kw::printk) && input.peek2(Token![,]) {
+            input.parse::<kw::printk>()?;
+            input.parse::<Token![,]>()?;
+            None
+        } else if input.peek(LitStr) || (input.peek(kw::target) && input.peek2(Token![:])) {
+            return Err(input.error(
+                "missing program context: pass the context of the program as the first \
+                 argument, or `printk` to write the record to the kernel trace pipe",
+            ));
+        } else {
+            let ctx: Expr = input.parse()?;
+            input.parse::<Token![,]>()?;
+            Some(ctx)
+        };
 
         // Parse `target: &str`, which is an optional argument.
         let target: Option<Expr> = if input.peek(kw::target) {
@@ -83,11 +98,20 @@ fn hint_to_expr(hint: DisplayHint) -> Result<Expr> {
         DisplayHint::Ipv6 => parse_str("::aya_log_ebpf::macro_support::DisplayHint::Ipv6"),
         DisplayHint::LowerMac => parse_str("::aya_log_ebpf::macro_support::DisplayHint::LowerMac"),
         DisplayHint::UpperMac => parse_str("::aya_log_ebpf::macro_support::DisplayHint::UpperMac"),
+        DisplayHint::Ip => parse_str("::aya_log_ebpf::macro_support::DisplayHint::Ip"),
     }
 }
 
 pub(crate) fn log(args: LogArgs, level: Option<TokenStream>) -> Result<TokenStream> {
     let ctx = args.ctx;
+    // Interned records need the target to be known at compile time.
+    let const_target = matches!(
+        args.target,
+        None | Some(Expr::Lit(ExprLit {
+            lit: Lit::Str(_),
+            ..
+        }))
+    );
     let target = match args.target {
         Some(t) => quote! { #t },
         None => quote! { module_path!() },
@@ -115,54 +139,176 @@ pub(crate) fn log(args: LogArgs, level: Option<TokenStream>) -> Result<TokenStre
     let mut arg_i = 0;
 
     let mut values = Vec::new();
+    let mut interned_values = Vec::new();
+    let mut printk_values = Vec::new();
     for fragment in fragments {
         match fragment {
             Fragment::Literal(s) => {
-                values.push(string_to_expr(s)?);
+                let s = string_to_expr(s)?;
+                printk_values.push(quote! { w.write_str(#s) });
+                values.push(s);
             }
             Fragment::Parameter(p) => {
-                let arg = match args.formatting_args {
-                    Some(ref args) => args[arg_i].clone(),
-                    None => return Err(Error::new(format_string.span(), "no arguments provided")),
+                let arg = match args
+                    .formatting_args
+                    .as_ref()
+                    .and_then(|args| args.iter().nth(arg_i))
+                {
+                    Some(arg) => arg.clone(),
+                    None => {
+                        return Err(Error::new(
+                            format_string.span(),
+                            format!("the format string requires more than {arg_i} argument(s)"),
+                        ))
+                    }
                 };
-                values.push(hint_to_expr(p.hint)?);
-                values.push(arg);
+                let hint = hint_to_expr(p.hint)?;
+                printk_values.push(quote! {
+                    ::aya_log_ebpf::macro_support::PrintkArg::write_printk({ #arg }, #hint, &mut w)
+                });
+                values.push(hint);
+                values.push(arg.clone());
+                interned_values.push(arg);
                 arg_i += 1;
             }
         }
     }
 
-    let num_args = values.len();
+    if let Some(extra) = args.formatting_args.iter().flatten().nth(arg_i) {
+        return Err(Error::new_spanned(
+            extra,
+            format!("argument never used: the format string only has {arg_i} parameter(s)"),
+        ));
+    }
+
+    if cfg!(feature = "disable") {
+        // Keep the arguments type checked and "used" without evaluating them,
+        // so that disabling logging doesn't introduce warnings.
+        let formatting_args = args.formatting_args.iter().flatten();
+        let ctx = ctx.iter();
+        return Ok(quote! {
+            {
+                let _ = || {
+                    #( let _ = &#ctx; )*
+                    let _ = &#target;
+                    let _ = &#lvl;
+                    #( let _ = &#formatting_args; )*
+                };
+            }
+        });
+    }
+
+    // Sending records to userspace requires the program context, without it
+    // the record is written with `bpf_trace_printk`.
+    let ctx = match ctx {
+        Some(ctx) if !cfg!(feature = "printk") => ctx,
+        ctx => {
+            let ctx = ctx.iter();
+            return Ok(quote! {
+                {
+                    // The context isn't needed by `bpf_trace_printk`.
+                    #( let _ = &#ctx; )*
+                    if let Some(buf_ptr) = unsafe { ::aya_log_ebpf::AYA_LOG_BUF.get_ptr_mut(0) } {
+                        let buf = unsafe { &mut *buf_ptr };
+                        let mut w = ::aya_log_ebpf::macro_support::PrintkWriter::new(&mut buf.buf);
+                        w.write_header(#lvl, #target);
+                        #( #printk_values; )*
+                        ::aya_log_ebpf::macro_support::output_printk(w);
+                    }
+                }
+            });
+        }
+    };
+
+    // Interned records only carry the arguments, the rest of the statement is
+    // stored in the object file and looked up by userspace.
+    let (header, values) = if cfg!(feature = "interned-formats") && const_target {
+        let num_args = interned_values.len();
+        let header = quote! {
+            const FIELDS: [&str; ::aya_log_ebpf::macro_support::FORMAT_ENTRY_FIELDS] =
+                [#target, module_path!(), file!(), #format_string];
+            const ENTRY_LEN: usize = ::aya_log_ebpf::macro_support::format_entry_len(&FIELDS);
+            const ENTRY: [u8; ENTRY_LEN] =
+                ::aya_log_ebpf::macro_support::format_entry(line!(), &FIELDS);
+            #[link_section = "aya_log_fmt"]
+            #[used]
+            static FORMAT_ENTRY: [u8; ENTRY_LEN] = ENTRY;
+            const FORMAT_ID: u32 = ::aya_log_ebpf::macro_support::format_id(&ENTRY);
+
+            ::aya_log_ebpf::macro_support::write_interned_record_header(
+                &mut buf.buf,
+                FORMAT_ID,
+                #lvl,
+                #num_args,
+            )
+        };
+        (header, interned_values)
+    } else {
+        let num_args = values.len();
+        let header = quote! {
+            ::aya_log_ebpf::write_record_header(
+                &mut buf.buf,
+                #target,
+                #lvl,
+                module_path!(),
+                file!(),
+                line!(),
+                #num_args,
+            )
+        };
+        (header, values)
+    };
     let values_iter = values.iter();
 
     Ok(quote! {
         {
             if let Some(buf_ptr) = unsafe { ::aya_log_ebpf::AYA_LOG_BUF.get_ptr_mut(0) } {
                 let buf = unsafe { &mut *buf_ptr };
-                if let Ok(header_len) = ::aya_log_ebpf::write_record_header(
-                    &mut buf.buf,
-                    #target,
-                    #lvl,
-                    module_path!(),
-                    file!(),
-                    line!(),
-                    #num_args,
-                ) {
-                    let record_len = header_len;
-
-                    if let Ok(record_len) = {
-                        Ok::<_, ()>(record_len) #( .and_then(|record_len| {
+                if let Ok(header_len) = { #header } {
+                    let mut record_len = ::aya_log_ebpf::macro_support::write_record_context(
+                        #ctx,
+                        &mut buf.buf,
+                        header_len,
+                    );
+                    let mut written_args = 0usize;
+
+                    // Stop at the first argument which doesn't fit. Breaking
+                    // out instead of checking a flag before every argument
+                    // keeps a single path through the arguments, so that long
+                    // records don't blow up the verifier's state.
+                    let truncated = 'args: {
+                        #(
                             if record_len >= buf.buf.len() {
-                                return Err(());
+                                break 'args true;
                             }
-                            aya_log_ebpf::WriteToBuf::write({ #values_iter }, &mut buf.buf[record_len..]).map(|len| record_len + len)
-                        }) )*
-                    } {
-                        unsafe { ::aya_log_ebpf::AYA_LOGS.output(
-                            #ctx,
-                            &buf.buf[..record_len], 0
-                        )}
+                            match ::aya_log_ebpf::WriteToBuf::write_or_truncate({ #values_iter }, &mut buf.buf[record_len..]) {
+                                Ok((len, truncated)) => {
+                                    record_len += len;
+                                    written_args += 1;
+                                    if truncated {
+                                        break 'args true;
+                                    }
+                                }
+                                Err(()) => break 'args true,
+                            }
+                        )*
+                        false
+                    };
+
+                    if truncated {
+                        ::aya_log_ebpf::macro_support::set_record_flags(
+                            &mut buf.buf,
+                            ::aya_log_ebpf::macro_support::RECORD_FLAG_TRUNCATED,
+                        );
+                        ::aya_log_ebpf::macro_support::set_record_num_args(
+                            &mut buf.buf,
+                            written_args,
+                        );
                     }
+
+                    let record_len =
+                        ::aya_log_ebpf::macro_support::finish_record(&mut buf.buf, record_len);
+                    ::aya_log_ebpf::output_record(#ctx, &buf.buf[..record_len]);
                 }
             }
         }
@@ -1,7 +1,8 @@
 use proc_macro::TokenStream;
-use syn::parse_macro_input;
+use syn::{parse_macro_input, DeriveInput};
 
 mod expand;
+mod log_enum;
 
 #[proc_macro]
 pub fn log(args: TokenStream) -> TokenStream {
@@ -50,3 +51,18 @@ pub fn trace(args: TokenStream) -> TokenStream {
         .unwrap_or_else(|err| err.to_compile_error())
         .into()
 }
+
+/// Derives `aya_log_common::LogEnum` for a fieldless enum with an integer
+/// `#[repr]`, so that its values can be passed to the logging macros.
+///
+/// Only the discriminant is sent to userspace, which formats it with the name
+/// of the variant once the enum has been registered with
+/// `aya_log::BpfLogger::register_enum`. The crate defining the enum must depend
+/// on `aya-log-common`.
+#[proc_macro_derive(LogEnum)]
+pub fn log_enum(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    log_enum::log_enum(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
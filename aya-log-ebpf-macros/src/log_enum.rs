@@ -0,0 +1,87 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Fields, Result};
+
+const REPR_TYPES: &[&str] = &[
+    "u8", "u16", "u32", "u64", "usize", "i8", "i16", "i32", "i64", "isize",
+];
+
+pub(crate) fn log_enum(input: DeriveInput) -> Result<TokenStream> {
+    let data = match &input.data {
+        Data::Enum(data) => data,
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "LogEnum can only be derived for enums",
+            ))
+        }
+    };
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "LogEnum can't be derived for generic enums",
+        ));
+    }
+
+    let mut has_repr = false;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("repr")) {
+        attr.parse_nested_meta(|meta| {
+            if REPR_TYPES.iter().any(|t| meta.path.is_ident(t)) {
+                has_repr = true;
+            }
+            Ok(())
+        })?;
+    }
+    if !has_repr {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "LogEnum requires an integer `#[repr]`, e.g. `#[repr(u8)]`",
+        ));
+    }
+
+    let name = &input.ident;
+    let mut variants = Vec::new();
+    for variant in &data.variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(Error::new_spanned(
+                variant,
+                "LogEnum can only be derived for fieldless enums",
+            ));
+        }
+        variants.push(&variant.ident);
+    }
+    let variant_names = variants.iter().map(|v| v.to_string());
+
+    Ok(quote! {
+        impl ::aya_log_common::LogEnum for #name {
+            const TYPE_ID: u32 = ::aya_log_common::enum_type_id(
+                ::core::concat!(::core::module_path!(), "::", ::core::stringify!(#name)),
+            );
+
+            #[inline(always)]
+            fn discriminant(&self) -> u64 {
+                match self {
+                    #( #name::#variants => #name::#variants as u64, )*
+                }
+            }
+
+            fn variant_name(discriminant: u64) -> ::core::option::Option<&'static str> {
+                #(
+                    if discriminant == #name::#variants as u64 {
+                        return ::core::option::Option::Some(#variant_names);
+                    }
+                )*
+                ::core::option::Option::None
+            }
+        }
+
+        impl ::aya_log_common::WriteToBuf for #name {
+            #[inline(always)]
+            fn write(self, buf: &mut [u8]) -> ::core::result::Result<usize, ()> {
+                ::aya_log_common::write_enum(&self, buf)
+            }
+        }
+
+        ::aya_log_common::impl_printk_enum!(#name);
+    })
+}
@@ -56,6 +56,7 @@ fn push_literal(frag: &mut Vec<Fragment>, unescaped_literal: &str) -> Result<(),
 fn parse_display_hint(s: &str) -> Result<DisplayHint, String> {
     Ok(match s {
         "x" => DisplayHint::LowerHex,
+        "i" => DisplayHint::Ip,
         "X" => DisplayHint::UpperHex,
         "ipv4" => DisplayHint::Ipv4,
         "ipv6" => DisplayHint::Ipv6,
@@ -176,4 +177,20 @@ mod test {
         assert!(parse("foo } bar").is_err());
         assert!(parse("foo { bar }").is_err());
     }
+
+    #[test]
+    fn test_parse_ip() {
+        assert_eq!(
+            parse("{:i} {:mac}"),
+            Ok(vec![
+                Fragment::Parameter(Parameter {
+                    hint: DisplayHint::Ip
+                }),
+                Fragment::Literal(" ".into()),
+                Fragment::Parameter(Parameter {
+                    hint: DisplayHint::LowerMac
+                }),
+            ])
+        );
+    }
 }
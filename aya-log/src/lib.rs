@@ -44,103 +44,1254 @@
 //! 21:58:55 [TRACE] (7) xxx: [src/main.rs:39] this is a trace message 🔍
 //! ```
 //!
+//! Records which eBPF programs fail to submit, for example because the perf
+//! buffers are full, are counted in the kernel and periodically reported as
+//! warnings through the same logger.
+//!
+//! # Features
+//!
+//! With the default `async_tokio` feature the perf buffers are read by tokio
+//! tasks, so the logger must be started from within a tokio runtime. Without
+//! it, the buffers are read by a single `aya-log` thread and no async runtime
+//! is linked.
+//!
 //! [Aya]: https://docs.rs/aya
 //! [env_logger]: https://docs.rs/env_logger
 //! [Log]: https://docs.rs/log/0.4.14/log/trait.Log.html
 //! [log]: https://docs.rs/log
 //!
+#[cfg(feature = "async_tokio")]
+use std::sync::Mutex;
 use std::{
-    fmt::{LowerHex, UpperHex},
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    fmt::{self, LowerHex, UpperHex},
     io, mem,
-    net::{Ipv4Addr, Ipv6Addr},
-    ptr, str,
-    sync::Arc,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::Path,
+    str::{self, Utf8Error},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
+#[cfg(not(feature = "async_tokio"))]
+use std::{os::unix::io::AsRawFd, thread};
 
 const MAP_NAME: &str = "AYA_LOGS";
 
+const DROPS_MAP_NAME: &str = "AYA_LOG_DROPS";
+
+const CLOCK_MAP_NAME: &str = "AYA_LOG_CLOCK";
+
+/// Size in pages of the perf buffer of each CPU.
+const PERF_BUFFER_PAGES: usize = 2;
+
+/// Number of buffers each CPU reads its perf buffer into.
+const READ_BUFFERS: usize = 10;
+
+/// Maximum number of target, module and file strings cached by each reader.
+const MAX_INTERNED_STRINGS: usize = 1024;
+
+/// Mount point of the cgroup v2 hierarchy.
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// How often the offsets of the kernel clocks from the wall clock are measured
+/// by default, see [`BpfLoggerBuilder::clock_offset_interval`].
+const CLOCK_OFFSET_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Name of the global holding the identifier sent by the `program-id` feature
+/// of `aya-log-ebpf`.
+const PROGRAM_ID_GLOBAL: &str = "AYA_LOG_PROGRAM_ID";
+
+/// Directory where `ip netns` bind mounts the named network namespaces.
+const NETNS_RUN_DIR: &str = "/run/netns";
+
+/// How often the number of records dropped in the kernel is checked.
+const DROPS_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Variant names of the enums registered with [`BpfLogger::register_enum`], by
+/// [`LogEnum::TYPE_ID`].
+static ENUMS: RwLock<BTreeMap<u32, VariantName>> = RwLock::new(BTreeMap::new());
+
+type VariantName = fn(u64) -> Option<&'static str>;
+
+/// Log statements registered with [`BpfLogger::register_formats`], by
+/// [`format_id`](aya_log_common::format_id).
+static FORMATS: RwLock<BTreeMap<u32, Arc<FormatEntry>>> = RwLock::new(BTreeMap::new());
+
+pub use aya_log_common::Clock;
 use aya_log_common::{
-    Argument, DisplayHint, Level, LogValueLength, RecordField, LOG_BUF_CAPACITY, LOG_FIELDS,
+    format::{self, ArgumentValue, FieldError, HeaderField},
+    record_checksum, Argument, DisplayHint, Level, LogEnum, FORMATS_SECTION, INTERNED_LOG_FIELDS,
+    LOG_BUF_CAPACITY, LOG_FIELDS, RECORD_CHECKSUM_LEN, RECORD_FLAG_CGROUP_ID, RECORD_FLAG_CHECKSUM,
+    RECORD_FLAG_INTERNED, RECORD_FLAG_NETNS_COOKIE, RECORD_FLAG_PID, RECORD_FLAG_PROGRAM_ID,
+    RECORD_FLAG_SEQUENCE, RECORD_FLAG_TIMESTAMP, RECORD_FLAG_TRUNCATED, RECORD_LEVEL_OFFSET,
+    TRUNCATION_MARKER,
 };
+use aya_log_parser::{Fragment, Parameter};
 use bytes::BytesMut;
 use log::{error, Log, Record};
+use object::{Object, ObjectSection};
 use thiserror::Error;
 
+#[cfg(feature = "async_tokio")]
+use aya::maps::perf::AsyncPerfEventArray;
+#[cfg(not(feature = "async_tokio"))]
+use aya::maps::perf::{PerfEventArray, PerfEventArrayBuffer};
 use aya::{
     maps::{
-        perf::{AsyncPerfEventArray, PerfBufferError},
-        MapError,
+        perf::{Events, PerfBufferError},
+        Array, Map, MapData, MapError, PerCpuArray,
     },
-    util::online_cpus,
-    Bpf, Pod,
+    util::{nr_possible_cpus, online_cpus},
+    Bpf, BpfLoader,
 };
 
-#[derive(Copy, Clone)]
-#[repr(transparent)]
-struct RecordFieldWrapper(RecordField);
-#[derive(Copy, Clone)]
-#[repr(transparent)]
-struct ArgumentWrapper(Argument);
+mod lookup;
+mod reorder;
+#[cfg(feature = "async_tokio")]
+mod sink;
+
+use lookup::{netns_name, process_name, Cgroup, LookupWorker, Resolver, ReverseDns};
+use reorder::{OwnedValue, PendingRecord, Reorderer};
+#[cfg(feature = "async_tokio")]
+use sink::BlockingSink;
+
+/// Host-side encoding of records, to synthesize the input of [`decode_record`]
+/// without loading an eBPF program, for example in unit tests.
+pub mod encode {
+    pub use aya_log_common::{
+        write_record_header, Clock, DisplayHint, Level, RecordBuilder, WriteToBuf,
+    };
+}
+
 #[derive(Copy, Clone)]
 #[repr(transparent)]
 struct DisplayHintWrapper(DisplayHint);
 
-unsafe impl aya::Pod for RecordFieldWrapper {}
-unsafe impl aya::Pod for ArgumentWrapper {}
-unsafe impl aya::Pod for DisplayHintWrapper {}
-
 /// Log messages generated by `aya_log_ebpf` using the [log] crate.
 ///
 /// For more details see the [module level documentation](crate).
-pub struct BpfLogger;
+///
+/// The logger is a handle on the readers started for each CPU, which can be
+/// kept in the state of the application to inspect them.
+pub struct BpfLogger {
+    stats: Arc<Stats>,
+    readers: Vec<Arc<ReaderState>>,
+}
+
+/// State of the reader of a CPU, shared with its task.
+struct ReaderState {
+    cpu_id: u32,
+    running: AtomicBool,
+    read_buffers_capacity: AtomicUsize,
+}
+
+/// Information about the reader started by a [`BpfLogger`] for a CPU.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ReaderInfo {
+    /// The CPU whose perf buffer is read.
+    pub cpu_id: u32,
+    /// Whether the reader is still running, readers stop when reading their
+    /// perf buffer fails.
+    pub running: bool,
+    /// Size in pages of the perf buffer.
+    pub perf_buffer_pages: usize,
+    /// Total capacity in bytes of the buffers records are read into, which
+    /// grow to fit the largest records.
+    pub read_buffers_capacity: usize,
+}
+
+/// Counters of the records processed by a [`BpfLogger`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LogStats {
+    /// Number of records received from the eBPF programs.
+    pub records: u64,
+    /// Number of errors reported while decoding records, see [`DecodeError`].
+    pub decode_errors: u64,
+    /// Number of records lost because the perf buffers were full.
+    pub lost_records: u64,
+    /// Size in bytes of the largest record received.
+    pub max_record_len: u64,
+    /// Number of records missing from the sequence numbers sent by the
+    /// `sequence` feature of `aya-log-ebpf`, whether they were dropped in the
+    /// kernel or lost in the perf buffers.
+    pub missing_records: u64,
+    /// Number of records dropped by the logger because its buffers reached
+    /// [`BpfLoggerBuilder::max_buffered_bytes`], or too many records were
+    /// waiting for the `BpfLoggerBuilder::blocking_sink`.
+    pub dropped_records: u64,
+}
+
+/// The records dropped by a [`BpfLogger`] whose buffers are full, see
+/// [`BpfLoggerBuilder::drop_policy`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Drops the records read once the limit is reached, keeping the records
+    /// already queued.
+    #[default]
+    Newest,
+    /// Drops the queued records with the earliest timestamps to make room for
+    /// the records read.
+    Oldest,
+}
+
+#[derive(Default)]
+struct Stats {
+    records: AtomicU64,
+    decode_errors: AtomicU64,
+    lost_records: AtomicU64,
+    max_record_len: AtomicU64,
+    missing_records: AtomicU64,
+    dropped_records: AtomicU64,
+    truncated_reported: AtomicBool,
+    drops_reported: AtomicBool,
+}
+
+type DecodeErrorCallback = dyn Fn(&DecodeError) + Send + Sync;
+
+type RecordFilter = dyn Fn(&RecordMeta<'_>) -> bool + Send + Sync;
 
 impl BpfLogger {
     /// Starts reading log records created with `aya-log-ebpf` and logs them
     /// with the default logger. See [log::logger].
     pub fn init(bpf: &mut Bpf) -> Result<BpfLogger, Error> {
-        BpfLogger::init_with_logger(bpf, DefaultLogger {})
+        BpfLoggerBuilder::new().init(bpf)
+    }
+
+    /// Starts reading log records created with `aya-log-ebpf` and logs them
+    /// with the given logger.
+    pub fn init_with_logger<T: Log + 'static>(
+        bpf: &mut Bpf,
+        logger: T,
+    ) -> Result<BpfLogger, Error> {
+        BpfLoggerBuilder::new().init_with_logger(bpf, logger)
+    }
+
+    /// Returns a builder to configure how log records are processed.
+    pub fn builder() -> BpfLoggerBuilder {
+        BpfLoggerBuilder::new()
+    }
+
+    /// Sizes the log event array for every possible CPU before the object is
+    /// loaded.
+    ///
+    /// Records sent from CPUs without an entry in the array are dropped. The
+    /// array declared by `aya-log-ebpf` is already sized when loading, this
+    /// raises a smaller size set by the object or the loader and keeps a
+    /// larger one.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use aya::BpfLoader;
+    /// # use aya_log::BpfLogger;
+    /// let mut loader = BpfLoader::new();
+    /// BpfLogger::configure_loader(&mut loader).unwrap();
+    /// let mut bpf = loader.load_file("file.o").unwrap();
+    /// BpfLogger::init(&mut bpf).unwrap();
+    /// ```
+    pub fn configure_loader(loader: &mut BpfLoader<'_>) -> Result<(), Error> {
+        let cpus = nr_possible_cpus().map_err(Error::InvalidPossibleCpu)?;
+        loader.set_min_max_entries(MAP_NAME, cpus as u32);
+        Ok(())
+    }
+
+    /// Sets the identifier of the program instance sent with the records by
+    /// eBPF programs built with the `program-id` feature of `aya-log-ebpf`.
+    ///
+    /// Loading the same object once per interface it is attached to, each with
+    /// its own identifier, allows attributing the records to the instance which
+    /// logged them through their `program_id` key-value.
+    ///
+    /// ```no_run
+    /// # use aya::BpfLoader;
+    /// # use aya_log::BpfLogger;
+    /// for (id, iface) in [(1, "eth0"), (2, "eth1")] {
+    ///     let mut loader = BpfLoader::new();
+    ///     BpfLogger::set_program_id(&mut loader, &id);
+    ///     let mut bpf = loader.load_file("file.o").unwrap();
+    ///     BpfLogger::init(&mut bpf).unwrap();
+    /// }
+    /// ```
+    pub fn set_program_id<'a>(loader: &mut BpfLoader<'a>, id: &'a u32) {
+        loader.set_global(PROGRAM_ID_GLOBAL, id);
+    }
+
+    /// Returns the name of the map records are read from.
+    pub fn map_name(&self) -> &'static str {
+        MAP_NAME
+    }
+
+    /// Returns the readers started for each online CPU.
+    pub fn readers(&self) -> Vec<ReaderInfo> {
+        self.readers
+            .iter()
+            .map(|reader| ReaderInfo {
+                cpu_id: reader.cpu_id,
+                running: reader.running.load(Ordering::Relaxed),
+                perf_buffer_pages: PERF_BUFFER_PAGES,
+                read_buffers_capacity: reader.read_buffers_capacity.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// Returns the counters of the records processed so far.
+    pub fn stats(&self) -> LogStats {
+        LogStats {
+            records: self.stats.records.load(Ordering::Relaxed),
+            decode_errors: self.stats.decode_errors.load(Ordering::Relaxed),
+            lost_records: self.stats.lost_records.load(Ordering::Relaxed),
+            max_record_len: self.stats.max_record_len.load(Ordering::Relaxed),
+            missing_records: self.stats.missing_records.load(Ordering::Relaxed),
+            dropped_records: self.stats.dropped_records.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Registers an enum deriving `LogEnum`, so that its values are formatted
+    /// with the names of their variants instead of their discriminants.
+    pub fn register_enum<T: LogEnum>() {
+        ENUMS.write().unwrap().insert(T::TYPE_ID, T::variant_name);
+    }
+
+    /// Registers the log statements of an eBPF object built with the
+    /// `interned-formats` feature of `aya-log-ebpf`, whose records only carry
+    /// an identifier of their statement.
+    ///
+    /// `object` is the object file the programs are loaded from, objects
+    /// without interned statements are ignored. Records of unregistered
+    /// statements are reported as [`DecodeError::UnknownFormat`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # let data = [];
+    /// use aya::Bpf;
+    /// use aya_log::BpfLogger;
+    ///
+    /// let mut bpf = Bpf::load(&data)?;
+    /// BpfLogger::register_formats(&data)?;
+    /// BpfLogger::init(&mut bpf)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn register_formats(object: &[u8]) -> Result<(), Error> {
+        let object = object::File::parse(object)
+            .map_err(|e| Error::InvalidFormats(format!("invalid object file: {e}")))?;
+        let section = match object.section_by_name(FORMATS_SECTION) {
+            Some(section) => section,
+            None => return Ok(()),
+        };
+        let data = section.data().map_err(|e| {
+            Error::InvalidFormats(format!("invalid {FORMATS_SECTION} section: {e}"))
+        })?;
+
+        register_format_entries(data)
+    }
+}
+
+fn register_format_entries(data: &[u8]) -> Result<(), Error> {
+    let entries = parse_formats(data).map_err(Error::InvalidFormats)?;
+    let mut formats = FORMATS.write().unwrap();
+    for (id, entry) in entries {
+        match formats.get(&id) {
+            Some(existing) if **existing != entry => {
+                return Err(Error::InvalidFormats(format!(
+                    "{}:{} and {}:{} have the same format id {id:#x}",
+                    existing.file, existing.line, entry.file, entry.line
+                )))
+            }
+            _ => {
+                formats.insert(id, Arc::new(entry));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A log statement of an eBPF program built with the `interned-formats`
+/// feature of `aya-log-ebpf`.
+#[derive(Debug, PartialEq)]
+struct FormatEntry {
+    target: Arc<str>,
+    module: Arc<str>,
+    file: Arc<str>,
+    line: u32,
+    fragments: Vec<Fragment>,
+    num_params: usize,
+}
+
+/// Parses the format entries written by `aya_log_common::format_entry`.
+fn parse_formats(mut data: &[u8]) -> Result<Vec<(u32, FormatEntry)>, String> {
+    let mut entries = Vec::new();
+    while !data.is_empty() {
+        let (entry, rest) = format::read_format_entry(data).map_err(
+            |format::Truncated { needed, available }| {
+                format!("format entry truncated: {needed} bytes needed, {available} available")
+            },
+        )?;
+        data = rest;
+        let format::RawFormatEntry { id, line, fields } = match entry {
+            Some(entry) => entry,
+            // Padding between entries.
+            None => continue,
+        };
+        let field = |bytes| {
+            str::from_utf8(bytes).map_err(|e| format!("invalid UTF-8 in format entry: {e}"))
+        };
+        let [target, module, file, format] = fields;
+        let (target, module, file, format) =
+            (field(target)?, field(module)?, field(file)?, field(format)?);
+        let fragments = aya_log_parser::parse(format)
+            .map_err(|e| format!("invalid format string at {file}:{line}: {e}"))?;
+        let num_params = fragments
+            .iter()
+            .filter(|fragment| matches!(fragment, Fragment::Parameter(_)))
+            .count();
+        entries.push((
+            id,
+            FormatEntry {
+                target: target.into(),
+                module: module.into(),
+                file: file.into(),
+                line,
+                fragments,
+                num_params,
+            },
+        ));
+    }
+    Ok(entries)
+}
+
+/// Builder to configure a [`BpfLogger`].
+///
+/// # Example
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[]).unwrap();
+/// use aya_log::BpfLogger;
+///
+/// BpfLogger::builder()
+///     .on_decode_error(|e| eprintln!("malformed log record: {e}"))
+///     .init(&mut bpf)
+///     .unwrap();
+/// ```
+pub struct BpfLoggerBuilder {
+    on_decode_error: Arc<DecodeErrorCallback>,
+    process_names: Option<Duration>,
+    cgroups: Option<Duration>,
+    netns_names: Option<Duration>,
+    clock: Clock,
+    clock_offset_interval: Duration,
+    static_fields: Vec<(String, String)>,
+    cpu_key_value: bool,
+    reverse_dns: bool,
+    ip_annotators: Vec<Arc<dyn IpAnnotator>>,
+    object_name: Option<String>,
+    filter: Option<Arc<RecordFilter>>,
+    reorder_window: Option<Duration>,
+    max_buffered_bytes: Option<usize>,
+    drop_policy: DropPolicy,
+    #[cfg(feature = "async_tokio")]
+    blocking_sink: Option<usize>,
+}
+
+impl Default for BpfLoggerBuilder {
+    fn default() -> Self {
+        BpfLoggerBuilder::new()
+    }
+}
+
+impl BpfLoggerBuilder {
+    /// Creates a new builder with the default configuration.
+    pub fn new() -> BpfLoggerBuilder {
+        BpfLoggerBuilder {
+            on_decode_error: Arc::new(|e| error!("invalid log record: {e}")),
+            process_names: None,
+            cgroups: None,
+            netns_names: None,
+            clock: Clock::Monotonic,
+            clock_offset_interval: CLOCK_OFFSET_INTERVAL,
+            static_fields: Vec::new(),
+            cpu_key_value: false,
+            reverse_dns: false,
+            ip_annotators: Vec::new(),
+            object_name: None,
+            filter: None,
+            reorder_window: None,
+            max_buffered_bytes: None,
+            drop_policy: DropPolicy::Newest,
+            #[cfg(feature = "async_tokio")]
+            blocking_sink: None,
+        }
+    }
+
+    /// Sets the function called with the errors found while decoding records.
+    ///
+    /// By default errors are logged with [log::error].
+    pub fn on_decode_error(
+        &mut self,
+        f: impl Fn(&DecodeError) + Send + Sync + 'static,
+    ) -> &mut BpfLoggerBuilder {
+        self.on_decode_error = Arc::new(f);
+        self
+    }
+
+    /// Sets a function deciding from the [`RecordMeta`] of each record whether
+    /// it is logged.
+    ///
+    /// The metadata is decoded from the header of the record, so records which
+    /// are filtered out don't pay for formatting their message. The logger's
+    /// [`Log::enabled`] is still checked first.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[]).unwrap();
+    /// use aya_log::BpfLoggerBuilder;
+    ///
+    /// // only log the records of CPU 0 and of process 1234
+    /// BpfLoggerBuilder::new()
+    ///     .filter(|meta| meta.cpu == Some(0) || meta.pid == Some(1234))
+    ///     .init(&mut bpf)
+    ///     .unwrap();
+    /// ```
+    pub fn filter(
+        &mut self,
+        f: impl Fn(&RecordMeta<'_>) -> bool + Send + Sync + 'static,
+    ) -> &mut BpfLoggerBuilder {
+        self.filter = Some(Arc::new(f));
+        self
+    }
+
+    /// Resolves the name of the process which logged each record and attaches
+    /// it to the record as the `comm` key-value.
+    ///
+    /// Records only carry the id of their process when the eBPF program is
+    /// built with the `pid` feature of `aya-log-ebpf`. Names are read from
+    /// `/proc/<pid>/comm` from a thread of their own, and cached for `ttl`,
+    /// since ids are reused once processes exit. Records are logged without
+    /// the name until it is read.
+    pub fn process_names(&mut self, ttl: Duration) -> &mut BpfLoggerBuilder {
+        self.process_names = Some(ttl);
+        self
+    }
+
+    /// Resolves the cgroup of each record to its path under `/sys/fs/cgroup`
+    /// and attaches it as the `cgroup` key-value, along with the
+    /// `container_id` and Kubernetes `pod_uid` found in the path.
+    ///
+    /// Records only carry the id of their cgroup when the eBPF program is
+    /// built with the `cgroup` feature of `aya-log-ebpf`. Unknown ids are
+    /// looked up by walking the cgroup v2 hierarchy from a thread of their
+    /// own, and cached for `ttl`. Records are logged with the id only until
+    /// their cgroup is resolved.
+    pub fn cgroups(&mut self, ttl: Duration) -> &mut BpfLoggerBuilder {
+        self.cgroups = Some(ttl);
+        self
+    }
+
+    /// Resolves the network namespace of each record to its name, as given to
+    /// `ip netns add`, and attaches it as the `netns` key-value.
+    ///
+    /// Records only carry the cookie of their namespace when the eBPF program
+    /// is built with the `netns` feature of `aya-log-ebpf`. Unknown cookies are
+    /// looked up by entering each namespace under `/run/netns` from a thread of
+    /// their own, which requires `CAP_SYS_ADMIN` and Linux 5.14, and cached for
+    /// `ttl`. Records are logged with the cookie only until their namespace is
+    /// resolved.
+    pub fn netns_names(&mut self, ttl: Duration) -> &mut BpfLoggerBuilder {
+        self.netns_names = Some(ttl);
+        self
+    }
+
+    /// Sets the kernel clock the eBPF programs read the timestamps of the
+    /// records from, [`Clock::Monotonic`] by default.
+    ///
+    /// Records only carry a timestamp when the eBPF program is built with the
+    /// `timestamp` feature of `aya-log-ebpf`. The clock is written to the
+    /// `AYA_LOG_CLOCK` map of the object by [`init`](Self::init), records
+    /// logged before keep the default clock. `CLOCK_BOOTTIME` keeps counting
+    /// while the system is suspended, so the timestamps of records logged
+    /// around a suspend stay consistent with each other.
+    pub fn clock(&mut self, clock: Clock) -> &mut BpfLoggerBuilder {
+        self.clock = clock;
+        self
+    }
+
+    /// Sets how often the offsets of the kernel clocks from the wall clock are
+    /// measured, one second by default.
+    ///
+    /// Records only carry a timestamp when the eBPF program is built with the
+    /// `timestamp` feature of `aya-log-ebpf`, see [`clock`](Self::clock).
+    /// Timestamps are converted to wall clock time with the offsets of their
+    /// clock measured periodically, so that they don't drift from the system
+    /// time, which is adjusted by NTP, and follow suspends for
+    /// `CLOCK_MONOTONIC`. Timestamps between the last two measurements are
+    /// interpolated.
+    pub fn clock_offset_interval(&mut self, interval: Duration) -> &mut BpfLoggerBuilder {
+        self.clock_offset_interval = interval;
+        self
+    }
+
+    /// Attaches the `key` key-value to every record, for context like the
+    /// hostname, region or version of the agent which structured loggers
+    /// should forward with the records.
+    ///
+    /// Setting a key again replaces its value.
+    pub fn static_field(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> &mut BpfLoggerBuilder {
+        let (key, value) = (key.into(), value.into());
+        match self.static_fields.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => *v = value,
+            None => self.static_fields.push((key, value)),
+        }
+        self
+    }
+
+    /// Attaches the CPU whose perf buffer a record was read from to the
+    /// record, as the `cpu` key-value.
+    ///
+    /// This is disabled by default. The CPU is the one the eBPF program ran
+    /// on, which is mostly useful to debug the ordering of records, see
+    /// [`reorder_window`](BpfLoggerBuilder::reorder_window).
+    pub fn cpu_key_value(&mut self, enable: bool) -> &mut BpfLoggerBuilder {
+        self.cpu_key_value = enable;
+        self
+    }
+
+    /// Resolves the IP addresses logged with the `{:i}`, `{:ipv4}` and
+    /// `{:ipv6}` display hints to hostnames, which are appended to the
+    /// addresses like `1.2.3.4 (host.example)`.
+    ///
+    /// Lookups run in the background so that they never hold up the records:
+    /// an address is only annotated once its hostname has been resolved, the
+    /// records logged before are left as they are. This is meant for
+    /// debugging, every new address triggers a DNS query. Hostnames are cached
+    /// for an hour, addresses without hostname for a minute.
+    pub fn reverse_dns(&mut self, enable: bool) -> &mut BpfLoggerBuilder {
+        self.reverse_dns = enable;
+        self
+    }
+
+    /// Adds an annotator of the IP addresses logged with the `{:i}`,
+    /// `{:ipv4}` and `{:ipv6}` display hints, for example with their country
+    /// or autonomous system from a GeoIP database.
+    ///
+    /// The annotations are appended to the addresses in the order the
+    /// annotators were added, before the hostnames of
+    /// [`reverse_dns`](BpfLoggerBuilder::reverse_dns).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::{fmt, net::IpAddr};
+    /// # use aya::Bpf;
+    /// # use aya_log::{BpfLogger, IpAnnotator};
+    /// struct Private;
+    ///
+    /// impl IpAnnotator for Private {
+    ///     fn annotate(&self, ip: IpAddr, out: &mut dyn fmt::Write) -> fmt::Result {
+    ///         match ip {
+    ///             IpAddr::V4(ip) if ip.is_private() => out.write_str(" (private)"),
+    ///             _ => Ok(()),
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// # let mut bpf = Bpf::load(&[]).unwrap();
+    /// BpfLogger::builder()
+    ///     .ip_annotator(Private)
+    ///     .init(&mut bpf)
+    ///     .unwrap();
+    /// ```
+    pub fn ip_annotator(&mut self, annotator: impl IpAnnotator + 'static) -> &mut BpfLoggerBuilder {
+        self.ip_annotators.push(Arc::new(annotator));
+        self
+    }
+
+    /// Prefixes the target of the records with `name`, giving `name::target`.
+    ///
+    /// The target of the records defaults to the module path of the eBPF
+    /// program, which looks alike for all the objects loaded by a process.
+    /// Naming each object's logger, e.g. after the file it was loaded from,
+    /// allows filtering records by object without changing the eBPF source.
+    ///
+    /// The name isn't derived by default: [`Bpf`] doesn't keep the name of the
+    /// file an object was loaded from, and objects embedded with
+    /// `include_bytes_aligned!` and loaded with [`Bpf::load`] have none.
+    /// Prefixing the targets by default would also stop the `log` filters
+    /// already matching on them from matching.
+    ///
+    /// ```no_run
+    /// # use aya::Bpf;
+    /// # use aya_log::BpfLogger;
+    /// # let mut bpf = Bpf::load(&[]).unwrap();
+    /// BpfLogger::builder()
+    ///     .object_name("xdp_firewall")
+    ///     .init(&mut bpf)
+    ///     .unwrap();
+    /// ```
+    pub fn object_name(&mut self, name: impl Into<String>) -> &mut BpfLoggerBuilder {
+        self.object_name = Some(name.into());
+        self
+    }
+
+    /// Holds records for `window` before logging them, so that the records of
+    /// all CPUs are logged in the order of their timestamps rather than in the
+    /// order each CPU's perf buffer was read.
+    ///
+    /// Records only carry a timestamp when the eBPF program is built with the
+    /// `timestamp` feature of `aya-log-ebpf`, others are ordered by the time
+    /// they were read. Records read more than `window` after records logged
+    /// later than them are still logged out of order, so the window should
+    /// exceed the latency of the readers, a few milliseconds usually do.
+    pub fn reorder_window(&mut self, window: Duration) -> &mut BpfLoggerBuilder {
+        self.reorder_window = Some(window);
+        self
+    }
+
+    /// Bounds the memory held by the logger across all CPUs: the buffers the
+    /// perf buffers are read into, and the records held by the
+    /// [`reorder_window`](BpfLoggerBuilder::reorder_window).
+    ///
+    /// Once the limit is reached, read buffers which grew to fit large records
+    /// are shrunk back so that a flood of records doesn't grow the memory of
+    /// the process. Records held by the `reorder_window` or waiting for the
+    /// `blocking_sink` are then dropped according to the
+    /// [`drop_policy`](BpfLoggerBuilder::drop_policy) and counted in
+    /// [`LogStats::dropped_records`]. Without either, no record is dropped.
+    ///
+    /// The read buffers of each CPU take at least 80 KiB, see
+    /// [`BpfLogger::readers`]. [`init`](BpfLoggerBuilder::init) fails with
+    /// [`Error::BufferLimitTooSmall`] if the limit doesn't leave room for them.
+    pub fn max_buffered_bytes(&mut self, bytes: usize) -> &mut BpfLoggerBuilder {
+        self.max_buffered_bytes = Some(bytes);
+        self
+    }
+
+    /// Sets which records are dropped once the buffers reach
+    /// [`max_buffered_bytes`](BpfLoggerBuilder::max_buffered_bytes), or once
+    /// too many records wait for the `blocking_sink`, [`DropPolicy::Newest`] by
+    /// default.
+    pub fn drop_policy(&mut self, policy: DropPolicy) -> &mut BpfLoggerBuilder {
+        self.drop_policy = policy;
+        self
+    }
+
+    /// Logs the records from tokio's blocking pool rather than from the tasks
+    /// reading the perf buffers, for loggers doing blocking I/O like writing
+    /// to files or syslog, so that a slow logger doesn't hold up the reads and
+    /// overflow the perf buffers.
+    ///
+    /// At most `max_in_flight` records wait to be logged, further records are
+    /// dropped according to the [`drop_policy`](BpfLoggerBuilder::drop_policy)
+    /// and counted in [`LogStats::dropped_records`]. The warnings about the
+    /// logging pipeline itself are still logged from the readers.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[]).unwrap();
+    /// use aya_log::BpfLogger;
+    ///
+    /// env_logger::init();
+    /// BpfLogger::builder()
+    ///     .blocking_sink(4096)
+    ///     .init(&mut bpf)
+    ///     .unwrap();
+    /// ```
+    #[cfg(feature = "async_tokio")]
+    pub fn blocking_sink(&mut self, max_in_flight: usize) -> &mut BpfLoggerBuilder {
+        self.blocking_sink = Some(max_in_flight);
+        self
+    }
+
+    /// Starts reading log records created with `aya-log-ebpf` and logs them
+    /// with the default logger. See [log::logger].
+    pub fn init(&mut self, bpf: &mut Bpf) -> Result<BpfLogger, Error> {
+        self.init_with_logger(bpf, DefaultLogger {})
     }
 
     /// Starts reading log records created with `aya-log-ebpf` and logs them
     /// with the given logger.
     pub fn init_with_logger<T: Log + 'static>(
+        &mut self,
         bpf: &mut Bpf,
         logger: T,
     ) -> Result<BpfLogger, Error> {
         let logger = Arc::new(logger);
-        let mut logs: AsyncPerfEventArray<_> = bpf
-            .take_map(MAP_NAME)
-            .ok_or(Error::MapNotFound)?
-            .try_into()?;
+        let stats = Arc::new(Stats::default());
+        let max_entries = match bpf.map(MAP_NAME).ok_or(Error::MapNotFound)? {
+            Map::PerfEventArray(data) => Some(data.max_entries()),
+            _ => None,
+        };
+
+        let mut cpu_ids = online_cpus().map_err(Error::InvalidOnlineCpu)?;
+        if let Some(max_entries) = max_entries {
+            let dropped = cpu_ids
+                .iter()
+                .copied()
+                .filter(|&id| id >= max_entries)
+                .collect::<Vec<_>>();
+            if !dropped.is_empty() {
+                cpu_ids.retain(|&id| id < max_entries);
+                log_warning(
+                    &*logger,
+                    format_args!(
+                        "{MAP_NAME} has {max_entries} entries, records from CPUs {dropped:?} will \
+                         be dropped. Use BpfLogger::configure_loader to size it before loading."
+                    ),
+                );
+            }
+        }
+        if let Some(max) = self.max_buffered_bytes {
+            check_max_buffered_bytes(max, cpu_ids.len())?;
+        }
 
-        for cpu_id in online_cpus().map_err(Error::InvalidOnlineCpu)? {
-            let mut buf = logs.open(cpu_id, None)?;
+        let logs = bpf.take_map(MAP_NAME).ok_or(Error::MapNotFound)?;
+        #[cfg(feature = "async_tokio")]
+        let mut logs: AsyncPerfEventArray<_> = logs.try_into()?;
+        #[cfg(not(feature = "async_tokio"))]
+        let mut logs: PerfEventArray<_> = logs.try_into()?;
+
+        // Objects built without the `timestamp` feature of aya-log-ebpf don't
+        // read a clock.
+        if let Some(clock) = bpf.map_mut(CLOCK_MAP_NAME) {
+            let mut clock: Array<_, u8> = clock.try_into()?;
+            clock.set(0, u8::from(self.clock), 0)?;
+        }
+
+        let static_fields: Arc<[(String, String)]> = self.static_fields.clone().into();
+        let mut ip_annotators = self.ip_annotators.clone();
+        if self.reverse_dns {
+            ip_annotators.push(Arc::new(ReverseDns::new().map_err(Error::SpawnLookup)?));
+        }
+        let ip_annotators: Arc<[Arc<dyn IpAnnotator>]> = ip_annotators.into();
+        let object_name: Option<Arc<str>> = self.object_name.as_deref().map(Into::into);
+        let worker =
+            if self.process_names.is_some() || self.cgroups.is_some() || self.netns_names.is_some()
+            {
+                Some(LookupWorker::new().map_err(Error::SpawnLookup)?)
+            } else {
+                None
+            };
+        let processes = self
+            .process_names
+            .zip(worker.clone())
+            .map(|(ttl, worker)| Arc::new(Resolver::new(ttl, worker, |pid| process_name(*pid))));
+        let cgroups = self.cgroups.zip(worker.clone()).map(|(ttl, worker)| {
+            Arc::new(Resolver::new(ttl, worker, |id| {
+                Cgroup::resolve(Path::new(CGROUP_ROOT), *id)
+            }))
+        });
+        let namespaces = self.netns_names.zip(worker).map(|(ttl, worker)| {
+            Arc::new(Resolver::new(ttl, worker, |cookie| {
+                netns_name(Path::new(NETNS_RUN_DIR), *cookie)
+            }))
+        });
+        let budget = self.max_buffered_bytes.map(|max| {
+            Arc::new(MemoryBudget {
+                max,
+                used: AtomicUsize::new(0),
+                policy: self.drop_policy,
+            })
+        });
+        #[cfg(feature = "async_tokio")]
+        let sink = self.blocking_sink.map(|max_in_flight| {
+            Arc::new(BlockingSink {
+                logger: logger.clone(),
+                static_fields: static_fields.clone(),
+                max_in_flight,
+                policy: self.drop_policy,
+                budget: budget.clone(),
+                stats: stats.clone(),
+                runtime: tokio::runtime::Handle::current(),
+                queue: Mutex::default(),
+            })
+        });
+        let reorder = self.reorder_window.map(|window| {
+            Arc::new(Reorderer {
+                #[cfg(feature = "async_tokio")]
+                sink: sink.clone(),
+                ..Reorderer::new(
+                    window,
+                    logger.clone(),
+                    static_fields.clone(),
+                    budget.clone(),
+                    stats.clone(),
+                )
+            })
+        });
+        let mut readers = Vec::new();
+        #[cfg(not(feature = "async_tokio"))]
+        let mut cpu_readers = Vec::new();
+        for cpu_id in cpu_ids {
+            let buf = logs.open(cpu_id, Some(PERF_BUFFER_PAGES))?;
+            let state = Arc::new(ReaderState {
+                cpu_id,
+                running: AtomicBool::new(true),
+                read_buffers_capacity: AtomicUsize::new(0),
+            });
+            readers.push(state.clone());
+            let reader = CpuReader {
+                // `read_events` grows the buffers when a record doesn't fit,
+                // they keep their capacity across reads.
+                buffers: (0..READ_BUFFERS)
+                    .map(|_| BytesMut::with_capacity(LOG_BUF_CAPACITY))
+                    .collect(),
+                scratch: Scratch {
+                    targets: Interner::with_prefix(object_name.clone()),
+                    processes: processes.clone(),
+                    cgroups: cgroups.clone(),
+                    namespaces: namespaces.clone(),
+                    clock: WallClock::new(self.clock_offset_interval),
+                    static_fields: static_fields.clone(),
+                    cpu_key_value: self.cpu_key_value,
+                    ip_annotators: ip_annotators.clone(),
+                    filter: self.filter.clone(),
+                    reorder: reorder.clone(),
+                    #[cfg(feature = "async_tokio")]
+                    sink: sink.clone(),
+                    cpu_id: Some(cpu_id),
+                    stats: stats.clone(),
+                    ..Default::default()
+                },
+                state,
+                stats: stats.clone(),
+                budget: budget.clone(),
+                logger: logger.clone(),
+                on_decode_error: self.on_decode_error.clone(),
+            };
+            reader.update_capacity();
 
-            let log = logger.clone();
+            #[cfg(feature = "async_tokio")]
             tokio::spawn(async move {
-                let mut buffers = vec![BytesMut::with_capacity(LOG_BUF_CAPACITY); 10];
+                let (mut buf, mut reader) = (buf, reader);
+                loop {
+                    let events = buf.read_events(&mut reader.buffers).await;
+                    if !reader.process(events) {
+                        break;
+                    }
+                }
+            });
+            #[cfg(not(feature = "async_tokio"))]
+            cpu_readers.push((buf, reader));
+        }
 
+        // Objects built against older versions of aya-log-ebpf don't count
+        // dropped records.
+        let drops = match bpf.take_map(DROPS_MAP_NAME) {
+            Some(drops) => Some(DropsReporter {
+                drops: drops.try_into()?,
+                logger,
+                reported: 0,
+            }),
+            None => None,
+        };
+        #[cfg(feature = "async_tokio")]
+        if let Some(reorder) = reorder {
+            // Flushes the records held once no more records are read.
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(reorder.window);
                 loop {
-                    let events = buf.read_events(&mut buffers).await.unwrap();
+                    interval.tick().await;
+                    reorder.flush(Instant::now());
+                }
+            });
+        }
+        #[cfg(feature = "async_tokio")]
+        if let Some(mut drops) = drops {
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(DROPS_REPORT_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    drops.report();
+                }
+            });
+        }
+        #[cfg(not(feature = "async_tokio"))]
+        thread::Builder::new()
+            .name("aya-log".to_owned())
+            .spawn(move || read_blocking(cpu_readers, drops, reorder))
+            .map_err(Error::SpawnReader)?;
 
-                    #[allow(clippy::needless_range_loop)]
-                    for i in 0..events.read {
-                        let buf = &mut buffers[i];
-                        log_buf(buf, &*log).unwrap();
+        Ok(BpfLogger { stats, readers })
+    }
+}
+
+/// Checks that `max` bytes leave room for the initial read buffers of `cpus`
+/// CPUs, see [`BpfLoggerBuilder::max_buffered_bytes`].
+fn check_max_buffered_bytes(max: usize, cpus: usize) -> Result<(), Error> {
+    let min = cpus * READ_BUFFERS * LOG_BUF_CAPACITY;
+    if max < min {
+        return Err(Error::BufferLimitTooSmall { max, min });
+    }
+    Ok(())
+}
+
+/// Reads the records of the perf buffer of a CPU and logs them.
+struct CpuReader {
+    buffers: Vec<BytesMut>,
+    scratch: Scratch,
+    state: Arc<ReaderState>,
+    stats: Arc<Stats>,
+    budget: Option<Arc<MemoryBudget>>,
+    logger: Arc<dyn Log>,
+    on_decode_error: Arc<DecodeErrorCallback>,
+}
+
+impl CpuReader {
+    fn update_capacity(&self) {
+        let capacity = self.buffers.iter().map(BytesMut::capacity).sum::<usize>();
+        let previous = self
+            .state
+            .read_buffers_capacity
+            .swap(capacity, Ordering::Relaxed);
+        if let Some(budget) = &self.budget {
+            budget.resize(previous, capacity);
+        }
+    }
+
+    /// Shrinks the read buffers which grew to fit large records back to their
+    /// initial capacity once the [`MemoryBudget`] is exceeded.
+    fn shrink_buffers(&mut self) {
+        match &self.budget {
+            Some(budget) if budget.exceeded() => {}
+            _ => return,
+        }
+        for buf in &mut self.buffers {
+            if buf.capacity() > LOG_BUF_CAPACITY {
+                *buf = BytesMut::with_capacity(LOG_BUF_CAPACITY);
+            }
+        }
+        self.update_capacity();
+    }
+
+    /// Logs the records of a read of the perf buffer. Returns false, marking
+    /// the reader as stopped, if the read failed.
+    fn process(&mut self, events: Result<Events, PerfBufferError>) -> bool {
+        let cpu_id = self.state.cpu_id;
+        let events = match events {
+            Ok(events) => events,
+            Err(e) => {
+                error!("failed to read log records on CPU {cpu_id}: {e}");
+                self.state.running.store(false, Ordering::Relaxed);
+                return false;
+            }
+        };
+        self.update_capacity();
+
+        let CpuReader {
+            buffers,
+            scratch,
+            stats,
+            logger,
+            on_decode_error,
+            ..
+        } = self;
+
+        if events.lost > 0 {
+            stats
+                .lost_records
+                .fetch_add(events.lost as u64, Ordering::Relaxed);
+            log_warning(
+                &**logger,
+                format_args!(
+                    "{} log records were lost on CPU {cpu_id}, the perf buffer was full",
+                    events.lost
+                ),
+            );
+        }
+
+        let on_error = |e: &DecodeError| {
+            stats.decode_errors.fetch_add(1, Ordering::Relaxed);
+            on_decode_error(e);
+        };
+        for buf in &buffers[..events.read] {
+            stats.records.fetch_add(1, Ordering::Relaxed);
+            stats
+                .max_record_len
+                .fetch_max(buf.len() as u64, Ordering::Relaxed);
+            if let Err(e) = log_buf(buf, &**logger, scratch, &on_error) {
+                if let DecodeError::Truncated { needed, available } = e {
+                    // Usually caused by mismatched versions of aya-log and
+                    // aya-log-ebpf, so warn only once.
+                    if !stats.truncated_reported.swap(true, Ordering::Relaxed) {
+                        log_warning(
+                            &**logger,
+                            format_args!(
+                                "received a partial log record of {} bytes: {needed} more bytes were needed, {available} available",
+                                buf.len()
+                            ),
+                        );
                     }
                 }
-            });
+                on_error(&e);
+            }
+        }
+        if let Some(reorder) = &scratch.reorder {
+            reorder.flush(Instant::now());
+        }
+        self.shrink_buffers();
+        true
+    }
+}
+
+/// Logs the number of records which the eBPF programs failed to submit since
+/// the last report.
+struct DropsReporter {
+    drops: PerCpuArray<MapData, u64>,
+    logger: Arc<dyn Log>,
+    reported: u64,
+}
+
+impl DropsReporter {
+    fn report(&mut self) {
+        let dropped = match self.drops.get(&0, 0) {
+            Ok(values) => values.iter().sum::<u64>(),
+            Err(e) => {
+                error!("failed to read {DROPS_MAP_NAME}: {e}");
+                return;
+            }
+        };
+        if dropped > self.reported {
+            log_warning(
+                &*self.logger,
+                format_args!(
+                    "{} log records were dropped by eBPF programs",
+                    dropped - self.reported
+                ),
+            );
+            self.reported = dropped;
         }
+    }
+}
+
+/// Reads the perf buffers of all the CPUs from a single thread, used when the
+/// `async_tokio` feature is disabled.
+#[cfg(not(feature = "async_tokio"))]
+fn read_blocking(
+    mut readers: Vec<(PerfEventArrayBuffer<MapData>, CpuReader)>,
+    mut drops: Option<DropsReporter>,
+    reorder: Option<Arc<Reorderer>>,
+) {
+    let mut next_report = Instant::now() + DROPS_REPORT_INTERVAL;
+    let mut next_flush = reorder
+        .as_ref()
+        .map(|reorder| Instant::now() + reorder.window);
+    loop {
+        let mut fds = readers
+            .iter()
+            .map(|(buf, _)| libc::pollfd {
+                fd: buf.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect::<Vec<_>>();
+        let deadline = [drops.as_ref().map(|_| next_report), next_flush]
+            .into_iter()
+            .flatten()
+            .min();
+        let timeout = match deadline {
+            Some(deadline) => deadline
+                .saturating_duration_since(Instant::now())
+                .as_millis() as libc::c_int,
+            None if readers.is_empty() => return,
+            None => -1,
+        };
+        let ret = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+        if ret < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::Interrupted {
+                error!("failed to poll the log perf buffers: {e}");
+                for (_, reader) in &readers {
+                    reader.state.running.store(false, Ordering::Relaxed);
+                }
+                return;
+            }
+        }
+
+        let mut i = 0;
+        readers.retain_mut(|(buf, reader)| {
+            let ready = fds[i].revents != 0;
+            i += 1;
+            if !ready {
+                return true;
+            }
+            loop {
+                let events = buf.read_events(&mut reader.buffers);
+                if matches!(events, Ok(Events { read: 0, lost: 0 })) {
+                    return true;
+                }
+                if !reader.process(events) {
+                    return false;
+                }
+            }
+        });
 
-        Ok(BpfLogger {})
+        if let Some(drops) = &mut drops {
+            if Instant::now() >= next_report {
+                drops.report();
+                next_report += DROPS_REPORT_INTERVAL;
+            }
+        }
+        if let (Some(reorder), Some(next_flush)) = (&reorder, &mut next_flush) {
+            let now = Instant::now();
+            if now >= *next_flush {
+                reorder.flush(now);
+                *next_flush = now + reorder.window;
+            }
+        }
     }
 }
 
+/// Logs a warning about the logging pipeline itself through `logger`.
+fn log_warning(logger: &dyn Log, args: fmt::Arguments) {
+    logger.log(
+        &Record::builder()
+            .args(args)
+            .target(module_path!())
+            .level(log::Level::Warn)
+            .module_path(Some(module_path!()))
+            .file(Some(file!()))
+            .line(Some(line!()))
+            .build(),
+    );
+}
+
 pub trait Formatter<T> {
     fn format(v: T) -> String;
 }
 
+/// Writes formatted values in place, so that the arguments of a record are
+/// formatted without allocating a string each.
+trait WriteFormatter<T> {
+    fn write(v: T, out: &mut impl fmt::Write) -> fmt::Result;
+}
+
+fn format_with<F: WriteFormatter<T>, T>(v: T) -> String {
+    let mut s = String::new();
+    F::write(v, &mut s).unwrap();
+    s
+}
+
 pub struct DefaultFormatter;
+impl<T> WriteFormatter<T> for DefaultFormatter
+where
+    T: fmt::Display,
+{
+    fn write(v: T, out: &mut impl fmt::Write) -> fmt::Result {
+        write!(out, "{v}")
+    }
+}
+
 impl<T> Formatter<T> for DefaultFormatter
 where
     T: ToString,
@@ -151,181 +1302,326 @@ where
 }
 
 pub struct LowerHexFormatter;
+impl<T> WriteFormatter<T> for LowerHexFormatter
+where
+    T: LowerHex,
+{
+    fn write(v: T, out: &mut impl fmt::Write) -> fmt::Result {
+        write!(out, "{v:x}")
+    }
+}
+
 impl<T> Formatter<T> for LowerHexFormatter
 where
     T: LowerHex,
 {
     fn format(v: T) -> String {
-        format!("{v:x}")
+        format_with::<Self, _>(v)
     }
 }
 
 pub struct LowerHexDebugFormatter;
-impl<T> Formatter<&[T]> for LowerHexDebugFormatter
+impl<T> WriteFormatter<&[T]> for LowerHexDebugFormatter
 where
     T: LowerHex,
 {
-    fn format(v: &[T]) -> String {
-        let mut s = String::new();
+    fn write(v: &[T], out: &mut impl fmt::Write) -> fmt::Result {
         for v in v {
-            let () = core::fmt::write(&mut s, format_args!("{v:x}")).unwrap();
+            write!(out, "{v:x}")?;
         }
-        s
+        Ok(())
+    }
+}
+
+impl<T> Formatter<&[T]> for LowerHexDebugFormatter
+where
+    T: LowerHex,
+{
+    fn format(v: &[T]) -> String {
+        format_with::<Self, _>(v)
     }
 }
 
 pub struct UpperHexFormatter;
+impl<T> WriteFormatter<T> for UpperHexFormatter
+where
+    T: UpperHex,
+{
+    fn write(v: T, out: &mut impl fmt::Write) -> fmt::Result {
+        write!(out, "{v:X}")
+    }
+}
+
 impl<T> Formatter<T> for UpperHexFormatter
 where
     T: UpperHex,
 {
     fn format(v: T) -> String {
-        format!("{v:X}")
+        format_with::<Self, _>(v)
     }
 }
 
 pub struct UpperHexDebugFormatter;
-impl<T> Formatter<&[T]> for UpperHexDebugFormatter
+impl<T> WriteFormatter<&[T]> for UpperHexDebugFormatter
 where
     T: UpperHex,
 {
-    fn format(v: &[T]) -> String {
-        let mut s = String::new();
+    fn write(v: &[T], out: &mut impl fmt::Write) -> fmt::Result {
         for v in v {
-            let () = core::fmt::write(&mut s, format_args!("{v:X}")).unwrap();
+            write!(out, "{v:X}")?;
         }
-        s
+        Ok(())
     }
 }
 
-pub struct Ipv4Formatter;
-impl<T> Formatter<T> for Ipv4Formatter
+impl<T> Formatter<&[T]> for UpperHexDebugFormatter
 where
-    T: Into<Ipv4Addr>,
+    T: UpperHex,
 {
-    fn format(v: T) -> String {
-        v.into().to_string()
+    fn format(v: &[T]) -> String {
+        format_with::<Self, _>(v)
+    }
+}
+
+pub struct Ipv4Formatter;
+impl<T> WriteFormatter<T> for Ipv4Formatter
+where
+    T: Into<Ipv4Addr>,
+{
+    fn write(v: T, out: &mut impl fmt::Write) -> fmt::Result {
+        write!(out, "{}", v.into())
+    }
+}
+
+impl<T> Formatter<T> for Ipv4Formatter
+where
+    T: Into<Ipv4Addr>,
+{
+    fn format(v: T) -> String {
+        format_with::<Self, _>(v)
     }
 }
 
 pub struct Ipv6Formatter;
+impl<T> WriteFormatter<T> for Ipv6Formatter
+where
+    T: Into<Ipv6Addr>,
+{
+    fn write(v: T, out: &mut impl fmt::Write) -> fmt::Result {
+        write!(out, "{}", v.into())
+    }
+}
+
 impl<T> Formatter<T> for Ipv6Formatter
 where
     T: Into<Ipv6Addr>,
 {
     fn format(v: T) -> String {
-        v.into().to_string()
+        format_with::<Self, _>(v)
     }
 }
 
 pub struct LowerMacFormatter;
-impl Formatter<[u8; 6]> for LowerMacFormatter {
-    fn format(v: [u8; 6]) -> String {
-        format!(
+impl WriteFormatter<[u8; 6]> for LowerMacFormatter {
+    fn write(v: [u8; 6], out: &mut impl fmt::Write) -> fmt::Result {
+        write!(
+            out,
             "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
             v[0], v[1], v[2], v[3], v[4], v[5]
         )
     }
 }
 
-pub struct UpperMacFormatter;
-impl Formatter<[u8; 6]> for UpperMacFormatter {
+impl Formatter<[u8; 6]> for LowerMacFormatter {
     fn format(v: [u8; 6]) -> String {
-        format!(
+        format_with::<Self, _>(v)
+    }
+}
+
+pub struct UpperMacFormatter;
+impl WriteFormatter<[u8; 6]> for UpperMacFormatter {
+    fn write(v: [u8; 6], out: &mut impl fmt::Write) -> fmt::Result {
+        write!(
+            out,
             "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}",
             v[0], v[1], v[2], v[3], v[4], v[5]
         )
     }
 }
 
+impl Formatter<[u8; 6]> for UpperMacFormatter {
+    fn format(v: [u8; 6]) -> String {
+        format_with::<Self, _>(v)
+    }
+}
+
+/// Formats arguments into the message of the record being decoded, which is
+/// left untouched when the display hint isn't supported.
 trait Format {
-    fn format(&self, last_hint: Option<DisplayHintWrapper>) -> Result<String, ()>;
+    fn format(
+        &self,
+        last_hint: Option<DisplayHintWrapper>,
+        out: &mut impl fmt::Write,
+    ) -> Result<(), ()>;
 }
 
 impl Format for &[u8] {
-    fn format(&self, last_hint: Option<DisplayHintWrapper>) -> Result<String, ()> {
+    fn format(
+        &self,
+        last_hint: Option<DisplayHintWrapper>,
+        out: &mut impl fmt::Write,
+    ) -> Result<(), ()> {
         match last_hint.map(|DisplayHintWrapper(dh)| dh) {
-            Some(DisplayHint::LowerHex) => Ok(LowerHexDebugFormatter::format(self)),
-            Some(DisplayHint::UpperHex) => Ok(UpperHexDebugFormatter::format(self)),
-            _ => Err(()),
+            Some(DisplayHint::LowerHex) => LowerHexDebugFormatter::write(self, out),
+            Some(DisplayHint::UpperHex) => UpperHexDebugFormatter::write(self, out),
+            _ => return Err(()),
         }
+        .map_err(|fmt::Error| ())
     }
 }
 
 impl Format for u32 {
-    fn format(&self, last_hint: Option<DisplayHintWrapper>) -> Result<String, ()> {
+    fn format(
+        &self,
+        last_hint: Option<DisplayHintWrapper>,
+        out: &mut impl fmt::Write,
+    ) -> Result<(), ()> {
         match last_hint.map(|DisplayHintWrapper(dh)| dh) {
-            Some(DisplayHint::Default) => Ok(DefaultFormatter::format(self)),
-            Some(DisplayHint::LowerHex) => Ok(LowerHexFormatter::format(self)),
-            Some(DisplayHint::UpperHex) => Ok(UpperHexFormatter::format(self)),
-            Some(DisplayHint::Ipv4) => Ok(Ipv4Formatter::format(*self)),
-            Some(DisplayHint::Ipv6) => Err(()),
-            Some(DisplayHint::LowerMac) => Err(()),
-            Some(DisplayHint::UpperMac) => Err(()),
-            _ => Ok(DefaultFormatter::format(self)),
+            Some(DisplayHint::Default) => DefaultFormatter::write(self, out),
+            Some(DisplayHint::LowerHex) => LowerHexFormatter::write(self, out),
+            Some(DisplayHint::UpperHex) => UpperHexFormatter::write(self, out),
+            Some(DisplayHint::Ipv4) => Ipv4Formatter::write(*self, out),
+            Some(DisplayHint::Ipv6) => return Err(()),
+            Some(DisplayHint::LowerMac) => return Err(()),
+            Some(DisplayHint::UpperMac) => return Err(()),
+            Some(DisplayHint::Ip) => Ipv4Formatter::write(*self, out),
+            _ => DefaultFormatter::write(self, out),
         }
+        .map_err(|fmt::Error| ())
     }
 }
 
-impl Format for [u8; 6] {
-    fn format(&self, last_hint: Option<DisplayHintWrapper>) -> Result<String, ()> {
-        match last_hint.map(|DisplayHintWrapper(dh)| dh) {
-            Some(DisplayHint::Default) => Err(()),
-            Some(DisplayHint::LowerHex) => Err(()),
-            Some(DisplayHint::UpperHex) => Err(()),
-            Some(DisplayHint::Ipv4) => Err(()),
-            Some(DisplayHint::Ipv6) => Err(()),
-            Some(DisplayHint::LowerMac) => Ok(LowerMacFormatter::format(*self)),
-            Some(DisplayHint::UpperMac) => Ok(UpperMacFormatter::format(*self)),
-            _ => Err(()),
+/// Element types of array arguments.
+trait ArrayElement: Copy + fmt::Display + LowerHex + UpperHex + Into<u128> {
+    const SIZE: usize;
+
+    fn from_ne_slice(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_array_element {
+    ($type:ident) => {
+        impl ArrayElement for $type {
+            const SIZE: usize = mem::size_of::<$type>();
+
+            fn from_ne_slice(bytes: &[u8]) -> Self {
+                $type::from_ne_bytes(bytes.try_into().unwrap())
+            }
+        }
+    };
+}
+
+impl_array_element!(u8);
+impl_array_element!(u16);
+impl_array_element!(u32);
+impl_array_element!(u64);
+
+fn write_list<T, W: fmt::Write>(
+    v: impl Iterator<Item = T>,
+    out: &mut W,
+    f: impl Fn(T, &mut W) -> fmt::Result,
+) -> fmt::Result {
+    out.write_char('[')?;
+    for (i, v) in v.enumerate() {
+        if i != 0 {
+            out.write_str(", ")?;
         }
+        f(v, out)?;
     }
+    out.write_char(']')
 }
 
-impl Format for [u8; 16] {
-    fn format(&self, last_hint: Option<DisplayHintWrapper>) -> Result<String, ()> {
-        match last_hint.map(|DisplayHintWrapper(dh)| dh) {
-            Some(DisplayHint::Default) => Err(()),
-            Some(DisplayHint::LowerHex) => Err(()),
-            Some(DisplayHint::UpperHex) => Err(()),
-            Some(DisplayHint::Ipv4) => Err(()),
-            Some(DisplayHint::Ipv6) => Ok(Ipv6Formatter::format(*self)),
-            Some(DisplayHint::LowerMac) => Err(()),
-            Some(DisplayHint::UpperMac) => Err(()),
-            _ => Err(()),
+/// Formats an array argument of any length received as native endian bytes.
+///
+/// Arrays of 32 and 128 bits can be formatted as IPv4 and IPv6 addresses, the
+/// first element holding the most significant bits of the address.
+fn format_array<T: ArrayElement>(
+    value: &[u8],
+    last_hint: Option<DisplayHintWrapper>,
+    out: &mut impl fmt::Write,
+) -> Result<(), ()> {
+    let v = || value.chunks_exact(T::SIZE).map(T::from_ne_slice);
+    let bits = value.len() * 8;
+    let addr = || array_addr::<T>(value);
+    match last_hint.map(|DisplayHintWrapper(dh)| dh) {
+        Some(DisplayHint::Default) | None => write_list(v(), out, DefaultFormatter::write),
+        // Byte arrays are formatted like byte slices.
+        Some(DisplayHint::LowerHex) if T::SIZE == 1 => LowerHexDebugFormatter::write(value, out),
+        Some(DisplayHint::UpperHex) if T::SIZE == 1 => UpperHexDebugFormatter::write(value, out),
+        Some(DisplayHint::LowerHex) => write_list(v(), out, LowerHexFormatter::write),
+        Some(DisplayHint::UpperHex) => write_list(v(), out, UpperHexFormatter::write),
+        Some(DisplayHint::Ipv4 | DisplayHint::Ip) if bits == 32 => {
+            Ipv4Formatter::write(addr() as u32, out)
+        }
+        Some(DisplayHint::Ipv6 | DisplayHint::Ip) if bits == 128 => {
+            Ipv6Formatter::write(addr(), out)
         }
+        Some(DisplayHint::LowerMac) if T::SIZE == 1 => {
+            LowerMacFormatter::write(value.try_into().map_err(|_| ())?, out)
+        }
+        Some(DisplayHint::UpperMac) if T::SIZE == 1 => {
+            UpperMacFormatter::write(value.try_into().map_err(|_| ())?, out)
+        }
+        _ => return Err(()),
     }
+    .map_err(|fmt::Error| ())
 }
 
-impl Format for [u16; 8] {
-    fn format(&self, last_hint: Option<DisplayHintWrapper>) -> Result<String, ()> {
-        match last_hint.map(|DisplayHintWrapper(dh)| dh) {
-            Some(DisplayHint::Default) => Err(()),
-            Some(DisplayHint::LowerHex) => Err(()),
-            Some(DisplayHint::UpperHex) => Err(()),
-            Some(DisplayHint::Ipv4) => Err(()),
-            Some(DisplayHint::Ipv6) => Ok(Ipv6Formatter::format(*self)),
-            Some(DisplayHint::LowerMac) => Err(()),
-            Some(DisplayHint::UpperMac) => Err(()),
-            _ => Err(()),
+/// Formats a [`LogEnum`] received as its type id followed by its discriminant.
+fn format_enum(
+    type_id: u32,
+    discriminant: u64,
+    last_hint: Option<DisplayHintWrapper>,
+    out: &mut impl fmt::Write,
+) -> Result<(), ()> {
+    match last_hint.map(|DisplayHintWrapper(dh)| dh) {
+        Some(DisplayHint::Default) | None => {
+            match ENUMS
+                .read()
+                .unwrap()
+                .get(&type_id)
+                .and_then(|variant_name| variant_name(discriminant))
+            {
+                Some(name) => out.write_str(name),
+                None => DefaultFormatter::write(discriminant, out),
+            }
         }
+        Some(DisplayHint::LowerHex) => LowerHexFormatter::write(discriminant, out),
+        Some(DisplayHint::UpperHex) => UpperHexFormatter::write(discriminant, out),
+        _ => return Err(()),
     }
+    .map_err(|fmt::Error| ())
 }
 
 macro_rules! impl_format {
     ($type:ident) => {
         impl Format for $type {
-            fn format(&self, last_hint: Option<DisplayHintWrapper>) -> Result<String, ()> {
+            fn format(
+                &self,
+                last_hint: Option<DisplayHintWrapper>,
+                out: &mut impl fmt::Write,
+            ) -> Result<(), ()> {
                 match last_hint.map(|DisplayHintWrapper(dh)| dh) {
-                    Some(DisplayHint::Default) => Ok(DefaultFormatter::format(self)),
-                    Some(DisplayHint::LowerHex) => Ok(LowerHexFormatter::format(self)),
-                    Some(DisplayHint::UpperHex) => Ok(UpperHexFormatter::format(self)),
-                    Some(DisplayHint::Ipv4) => Err(()),
-                    Some(DisplayHint::Ipv6) => Err(()),
-                    Some(DisplayHint::LowerMac) => Err(()),
-                    Some(DisplayHint::UpperMac) => Err(()),
-                    _ => Ok(DefaultFormatter::format(self)),
+                    Some(DisplayHint::Default) => DefaultFormatter::write(self, out),
+                    Some(DisplayHint::LowerHex) => LowerHexFormatter::write(self, out),
+                    Some(DisplayHint::UpperHex) => UpperHexFormatter::write(self, out),
+                    Some(DisplayHint::Ipv4) => return Err(()),
+                    Some(DisplayHint::Ipv6) => return Err(()),
+                    Some(DisplayHint::LowerMac) => return Err(()),
+                    Some(DisplayHint::UpperMac) => return Err(()),
+                    Some(DisplayHint::Ip) => return Err(()),
+                    _ => DefaultFormatter::write(self, out),
                 }
+                .map_err(|fmt::Error| ())
             }
         }
     };
@@ -345,17 +1641,23 @@ impl_format!(usize);
 macro_rules! impl_format_float {
     ($type:ident) => {
         impl Format for $type {
-            fn format(&self, last_hint: Option<DisplayHintWrapper>) -> Result<String, ()> {
+            fn format(
+                &self,
+                last_hint: Option<DisplayHintWrapper>,
+                out: &mut impl fmt::Write,
+            ) -> Result<(), ()> {
                 match last_hint.map(|DisplayHintWrapper(dh)| dh) {
-                    Some(DisplayHint::Default) => Ok(DefaultFormatter::format(self)),
-                    Some(DisplayHint::LowerHex) => Err(()),
-                    Some(DisplayHint::UpperHex) => Err(()),
-                    Some(DisplayHint::Ipv4) => Err(()),
-                    Some(DisplayHint::Ipv6) => Err(()),
-                    Some(DisplayHint::LowerMac) => Err(()),
-                    Some(DisplayHint::UpperMac) => Err(()),
-                    _ => Ok(DefaultFormatter::format(self)),
+                    Some(DisplayHint::Default) => DefaultFormatter::write(self, out),
+                    Some(DisplayHint::LowerHex) => return Err(()),
+                    Some(DisplayHint::UpperHex) => return Err(()),
+                    Some(DisplayHint::Ipv4) => return Err(()),
+                    Some(DisplayHint::Ipv6) => return Err(()),
+                    Some(DisplayHint::LowerMac) => return Err(()),
+                    Some(DisplayHint::UpperMac) => return Err(()),
+                    Some(DisplayHint::Ip) => return Err(()),
+                    _ => DefaultFormatter::write(self, out),
                 }
+                .map_err(|fmt::Error| ())
             }
         }
     };
@@ -376,241 +1678,1996 @@ impl Log for DefaultLogger {
         log::logger().log(record)
     }
 
-    fn flush(&self) {
-        log::logger().flush()
+    fn flush(&self) {
+        log::logger().flush()
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("log event array {} doesn't exist", MAP_NAME)]
+    MapNotFound,
+
+    #[error("error opening log event array")]
+    MapError(#[from] MapError),
+
+    #[error("error opening log buffer")]
+    PerfBufferError(#[from] PerfBufferError),
+
+    #[error("invalid /sys/devices/system/cpu/online format")]
+    InvalidOnlineCpu(#[source] io::Error),
+
+    #[error("invalid /sys/devices/system/cpu/possible format")]
+    InvalidPossibleCpu(#[source] io::Error),
+
+    #[error("error registering log statements: {0}")]
+    InvalidFormats(String),
+
+    #[error("error starting the log reader thread")]
+    SpawnReader(#[source] io::Error),
+
+    #[error("error starting the lookup thread")]
+    SpawnLookup(#[source] io::Error),
+
+    #[error("max_buffered_bytes {max} is less than the {min} bytes of the read buffers")]
+    BufferLimitTooSmall { max: usize, min: usize },
+}
+
+/// Errors decoding the log records sent by eBPF programs.
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The record ends in the middle of a field or an argument.
+    #[error("record truncated: {needed} bytes needed, {available} available")]
+    Truncated { needed: usize, available: usize },
+
+    /// An interned record refers to a log statement which wasn't registered
+    /// with [`BpfLogger::register_formats`].
+    #[error(
+        "unknown log statement {0:#x}, register the object file with BpfLogger::register_formats"
+    )]
+    UnknownFormat(u32),
+
+    /// The checksum of the record doesn't match its contents, it was
+    /// corrupted or misread from the perf buffer.
+    #[error("corrupted record: checksum {actual:#010x} doesn't match {expected:#010x}")]
+    ChecksumMismatch { expected: u32, actual: u32 },
+
+    /// The record should end with a checksum which wasn't found, it was
+    /// corrupted or misread from the perf buffer.
+    #[error("corrupted record: checksum not found")]
+    MissingChecksum,
+
+    /// A header field has an unknown tag.
+    #[error("invalid record field tag {0}")]
+    InvalidField(u8),
+
+    /// An argument has an unknown type tag.
+    #[error("invalid argument type tag {0}")]
+    InvalidArgument(u8),
+
+    /// The level of the record is unknown.
+    #[error("invalid log level {0}")]
+    InvalidLevel(u8),
+
+    /// A display hint is unknown.
+    #[error("invalid display hint {0}")]
+    InvalidDisplayHint(u8),
+
+    /// The timestamp of the record was read from an unknown clock.
+    #[error("invalid clock {0}")]
+    InvalidClock(u8),
+
+    /// A value doesn't have the length required by its type.
+    #[error("invalid length {actual} for {name}, expected {expected}")]
+    InvalidLength {
+        name: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+
+    /// A header field required to log the record is missing.
+    #[error("missing record field {0}")]
+    MissingField(&'static str),
+
+    /// A string isn't valid UTF-8.
+    ///
+    /// This error isn't fatal: invalid sequences are replaced with
+    /// `U+FFFD REPLACEMENT CHARACTER` and the record is still logged.
+    #[error("invalid UTF-8 in {field}")]
+    InvalidUtf8 {
+        field: &'static str,
+        #[source]
+        error: Utf8Error,
+    },
+
+    /// The display hint isn't supported by the type of the argument.
+    #[error("display hint {hint:?} isn't supported for arguments of type {argument:?}")]
+    UnsupportedDisplayHint {
+        hint: Option<DisplayHint>,
+        argument: Argument,
+    },
+}
+
+impl From<FieldError> for DecodeError {
+    fn from(error: FieldError) -> DecodeError {
+        match error {
+            FieldError::InvalidField(tag) => DecodeError::InvalidField(tag),
+            FieldError::InvalidArgument(tag) => DecodeError::InvalidArgument(tag),
+            FieldError::InvalidLevel(level) => DecodeError::InvalidLevel(level),
+            FieldError::InvalidDisplayHint(hint) => DecodeError::InvalidDisplayHint(hint),
+            FieldError::InvalidClock(clock) => DecodeError::InvalidClock(clock),
+            FieldError::InvalidLength {
+                name,
+                expected,
+                actual,
+            } => DecodeError::InvalidLength {
+                name,
+                expected,
+                actual,
+            },
+        }
+    }
+}
+
+/// A log record decoded from the format written by `aya-log-ebpf`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LogRecord<'a> {
+    pub target: Cow<'a, str>,
+    pub level: log::Level,
+    pub module: Option<Cow<'a, str>>,
+    pub file: Option<Cow<'a, str>>,
+    pub line: Option<u32>,
+    /// The formatted message.
+    pub message: String,
+    /// Whether the record didn't fit in the eBPF log buffer and its last
+    /// argument was truncated.
+    pub truncated: bool,
+    /// The id of the process which logged the record, sent when the eBPF
+    /// program is built with the `pid` feature of `aya-log-ebpf`.
+    pub pid: Option<u32>,
+    /// The id of the cgroup of the process which logged the record, sent when
+    /// the eBPF program is built with the `cgroup` feature of `aya-log-ebpf`.
+    pub cgroup_id: Option<u64>,
+    /// The cookie of the network namespace of the program which logged the
+    /// record, sent when the eBPF program is built with the `netns` feature of
+    /// `aya-log-ebpf`.
+    pub netns_cookie: Option<u64>,
+    /// The time at which the record was logged, sent when the eBPF program is
+    /// built with the `timestamp` feature of `aya-log-ebpf`, from the
+    /// [`BpfLoggerBuilder::clock`].
+    pub timestamp: Option<SystemTime>,
+    /// The identifier of the program instance which logged the record, sent
+    /// when the eBPF program is built with the `program-id` feature of
+    /// `aya-log-ebpf`. See [`BpfLogger::set_program_id`].
+    pub program_id: Option<u32>,
+    /// The number of records logged before this one on the same CPU, sent
+    /// when the eBPF program is built with the `sequence` feature of
+    /// `aya-log-ebpf`.
+    pub sequence: Option<u64>,
+    /// Errors which didn't prevent the record from being decoded, like
+    /// invalid UTF-8.
+    pub errors: Vec<DecodeError>,
+}
+
+/// The metadata of a record, decoded from its header before its message is
+/// formatted, see [`BpfLoggerBuilder::filter`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct RecordMeta<'a> {
+    /// The level the record was logged at.
+    pub level: log::Level,
+    /// The target of the record, prefixed with the
+    /// [`BpfLoggerBuilder::object_name`] if any.
+    pub target: &'a str,
+    /// The CPU whose perf buffer the record was read from, see
+    /// [`BpfLoggerBuilder::cpu_key_value`].
+    pub cpu: Option<u32>,
+    /// The id of the process which logged the record, sent when the eBPF
+    /// program is built with the `pid` feature of `aya-log-ebpf`.
+    pub pid: Option<u32>,
+    /// The time at which the record was logged, sent when the eBPF program is
+    /// built with the `timestamp` feature of `aya-log-ebpf`, from the
+    /// [`BpfLoggerBuilder::clock`].
+    pub timestamp: Option<SystemTime>,
+}
+
+/// Decodes a log record sent by an eBPF program using `aya-log-ebpf`.
+///
+/// This is what [`BpfLogger`] does with each record before logging it, and can
+/// be used by tools which read or replay the records themselves.
+///
+/// Enums are formatted with the names of their variants once registered with
+/// [`BpfLogger::register_enum`].
+pub fn decode_record(buf: &[u8]) -> Result<LogRecord<'_>, DecodeError> {
+    let record = decode_raw(buf)?;
+    let mut message = String::new();
+    let mut errors = Vec::new();
+    record.format_message(&mut message, &mut errors, &[])?;
+    let RawRecord {
+        metadata,
+        level,
+        truncated,
+        pid,
+        cgroup_id,
+        netns_cookie,
+        timestamp,
+        program_id,
+        sequence,
+        ..
+    } = record;
+    let (target, module, file, line) = match metadata {
+        Metadata::Inline {
+            target,
+            module,
+            file,
+            line,
+        } => (
+            str_lossy("target", target, &mut errors),
+            module.map(|module| str_lossy("module", module, &mut errors)),
+            file.map(|file| str_lossy("file", file, &mut errors)),
+            line,
+        ),
+        Metadata::Interned(format) => (
+            Cow::Owned(format.target.to_string()),
+            Some(Cow::Owned(format.module.to_string())),
+            Some(Cow::Owned(format.file.to_string())),
+            Some(format.line),
+        ),
+    };
+    Ok(LogRecord {
+        target,
+        level,
+        module,
+        file,
+        line,
+        message,
+        truncated,
+        pid,
+        cgroup_id,
+        netns_cookie,
+        timestamp: timestamp
+            .and_then(|(clock, ns)| WallClock::new(Duration::ZERO).system_time(clock, ns)),
+        program_id,
+        sequence,
+        errors,
+    })
+}
+
+/// A record whose header has been decoded, but whose target, module and file
+/// haven't been checked to be valid UTF-8 yet and whose message hasn't been
+/// formatted.
+struct RawRecord<'a> {
+    metadata: Metadata<'a>,
+    level: log::Level,
+    truncated: bool,
+    pid: Option<u32>,
+    cgroup_id: Option<u64>,
+    netns_cookie: Option<u64>,
+    timestamp: Option<(Clock, u64)>,
+    program_id: Option<u32>,
+    sequence: Option<u64>,
+    num_args: usize,
+    args: &'a [u8],
+}
+
+impl RawRecord<'_> {
+    /// Formats the message of the record into `out`, straight from the
+    /// arguments in the record buffer.
+    fn format_message(
+        &self,
+        out: &mut impl fmt::Write,
+        errors: &mut Vec<DecodeError>,
+        annotators: &[Arc<dyn IpAnnotator>],
+    ) -> Result<(), DecodeError> {
+        match &self.metadata {
+            Metadata::Inline { .. } => {
+                format_inline(self.args, self.num_args, out, errors, annotators)
+            }
+            Metadata::Interned(format) => format_interned(
+                self.args,
+                self.num_args,
+                self.truncated,
+                format,
+                out,
+                errors,
+                annotators,
+            ),
+        }
+    }
+}
+
+enum Metadata<'a> {
+    /// Sent with the record.
+    Inline {
+        target: &'a [u8],
+        module: Option<&'a [u8]>,
+        file: Option<&'a [u8]>,
+        line: Option<u32>,
+    },
+    /// Looked up from the format entry of an interned record.
+    Interned(Arc<FormatEntry>),
+}
+
+fn decode_raw(buf: &[u8]) -> Result<RawRecord<'_>, DecodeError> {
+    let mut buf = verify_checksum(buf)?;
+    let mut target = None;
+    let mut level = None;
+    let mut module = None;
+    let mut file = None;
+    let mut line = None;
+    let mut num_args = None;
+    let mut format_id = None;
+    let mut pid = None;
+    let mut cgroup_id = None;
+    let mut netns_cookie = None;
+    let mut timestamp = None;
+    let mut program_id = None;
+    let mut sequence = None;
+    let mut flags = 0;
+
+    // The flags are always the first field and tell how many follow.
+    let mut fields = LOG_FIELDS;
+    let mut i = 0;
+    while i < fields {
+        let (tag, value, rest) = try_read(buf)?;
+
+        match format::decode_header_field(tag, value)? {
+            HeaderField::Target(value) => target = Some(value),
+            HeaderField::Level(value) => level = Some(log_level(value)),
+            HeaderField::Module(value) => module = Some(value),
+            HeaderField::File(value) => file = Some(value),
+            HeaderField::Line(value) => line = Some(value),
+            HeaderField::NumArgs(value) => num_args = Some(value),
+            HeaderField::Flags(value) => {
+                flags = value;
+                if flags & RECORD_FLAG_INTERNED != 0 {
+                    fields = INTERNED_LOG_FIELDS;
+                }
+                if flags & RECORD_FLAG_PID != 0 {
+                    fields += 1;
+                }
+                if flags & RECORD_FLAG_CGROUP_ID != 0 {
+                    fields += 1;
+                }
+                if flags & RECORD_FLAG_NETNS_COOKIE != 0 {
+                    fields += 1;
+                }
+                if flags & RECORD_FLAG_TIMESTAMP != 0 {
+                    fields += 1;
+                }
+                if flags & RECORD_FLAG_PROGRAM_ID != 0 {
+                    fields += 1;
+                }
+                if flags & RECORD_FLAG_SEQUENCE != 0 {
+                    fields += 1;
+                }
+            }
+            HeaderField::FormatId(value) => format_id = Some(value),
+            HeaderField::Pid(value) => pid = Some(value),
+            HeaderField::CgroupId(value) => cgroup_id = Some(value),
+            HeaderField::NetnsCookie(value) => netns_cookie = Some(value),
+            HeaderField::Timestamp { clock, ns } => timestamp = Some((clock, ns)),
+            HeaderField::ProgramId(value) => program_id = Some(value),
+            HeaderField::Sequence(value) => sequence = Some(value),
+            // Only found after the arguments.
+            HeaderField::Checksum { .. } => return Err(DecodeError::InvalidField(tag)),
+        }
+
+        buf = rest;
+        i += 1;
+    }
+
+    let num_args = num_args.ok_or(DecodeError::MissingField("number of arguments"))?;
+    let level = level.ok_or(DecodeError::MissingField("level"))?;
+    let metadata = if flags & RECORD_FLAG_INTERNED != 0 {
+        let id = format_id.ok_or(DecodeError::MissingField("format id"))?;
+        let format = FORMATS
+            .read()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or(DecodeError::UnknownFormat(id))?;
+        Metadata::Interned(format)
+    } else {
+        Metadata::Inline {
+            target: target.ok_or(DecodeError::MissingField("target"))?,
+            module,
+            file,
+            line,
+        }
+    };
+
+    Ok(RawRecord {
+        metadata,
+        level,
+        truncated: flags & RECORD_FLAG_TRUNCATED != 0,
+        pid,
+        cgroup_id,
+        netns_cookie,
+        timestamp,
+        program_id,
+        sequence,
+        num_args,
+        args: buf,
+    })
+}
+
+/// Checks the trailer of records carrying [`RECORD_FLAG_CHECKSUM`] and returns
+/// the record without it.
+///
+/// Perf buffers pad records to 8 bytes, so the trailer is looked for at the end
+/// of the record and up to 7 bytes before.
+fn verify_checksum(buf: &[u8]) -> Result<&[u8], DecodeError> {
+    match aya_log_common::record_flags(buf) {
+        Some(flags) if flags & RECORD_FLAG_CHECKSUM != 0 => {}
+        _ => return Ok(buf),
+    }
+    for padding in 0..mem::size_of::<u64>() {
+        let start = match buf.len().checked_sub(padding + RECORD_CHECKSUM_LEN) {
+            Some(start) => start,
+            None => break,
+        };
+        let (expected, len) = match try_read(&buf[start..])
+            .ok()
+            .and_then(|(tag, value, _)| format::decode_header_field(tag, value).ok())
+        {
+            Some(HeaderField::Checksum {
+                checksum,
+                record_len,
+            }) => (checksum, record_len),
+            _ => continue,
+        };
+        if len as usize != start {
+            continue;
+        }
+        let record = &buf[..start];
+        let actual = record_checksum(record);
+        if actual != expected {
+            return Err(DecodeError::ChecksumMismatch { expected, actual });
+        }
+        return Ok(record);
+    }
+    Err(DecodeError::MissingChecksum)
+}
+
+fn log_level(level: Level) -> log::Level {
+    match level {
+        Level::Error => log::Level::Error,
+        Level::Warn => log::Level::Warn,
+        Level::Info => log::Level::Info,
+        Level::Debug => log::Level::Debug,
+        Level::Trace => log::Level::Trace,
+    }
+}
+
+/// Reads the level and target of a record, which are written at a fixed
+/// position right after the flags and the number of arguments, so that records
+/// filtered out by the logger are dropped without decoding the rest.
+///
+/// Returns `None` when they aren't found there, the record is then decoded in
+/// full and any error is reported.
+fn peek_metadata(buf: &[u8], targets: &mut Interner) -> Option<(log::Level, Arc<str>)> {
+    let (tag, value, rest) = try_read(buf.get(RECORD_LEVEL_OFFSET..)?).ok()?;
+    let level = match format::decode_header_field(tag, value).ok()? {
+        HeaderField::Level(level) => log_level(level),
+        _ => return None,
+    };
+    let (tag, value, _) = try_read(rest).ok()?;
+    let target = match format::decode_header_field(tag, value).ok()? {
+        HeaderField::Target(value) => targets.intern("target", value, &mut Vec::new()),
+        HeaderField::FormatId(id) => {
+            let target = FORMATS.read().unwrap().get(&id)?.target.clone();
+            targets.intern("target", target.as_bytes(), &mut Vec::new())
+        }
+        _ => return None,
+    };
+    Some((level, target))
+}
+
+/// Formats the arguments of a record carrying the literal parts of its format
+/// string as arguments, each parameter being preceded by its display hint.
+fn format_inline(
+    mut buf: &[u8],
+    num_args: usize,
+    message: &mut impl fmt::Write,
+    errors: &mut Vec<DecodeError>,
+    annotators: &[Arc<dyn IpAnnotator>],
+) -> Result<(), DecodeError> {
+    let mut last_hint: Option<DisplayHintWrapper> = None;
+    for _ in 0..num_args {
+        let (tag, value, rest) = try_read(buf)?;
+        buf = rest;
+        match format::decode_argument(tag, value)? {
+            ArgumentValue::DisplayHint(hint) => last_hint = Some(DisplayHintWrapper(hint)),
+            arg => format_argument(arg, last_hint.take(), message, errors, annotators)?,
+        }
+    }
+    Ok(())
+}
+
+/// Formats the arguments of an interned record with the format string of its
+/// entry.
+fn format_interned(
+    mut buf: &[u8],
+    num_args: usize,
+    truncated: bool,
+    format: &FormatEntry,
+    message: &mut impl fmt::Write,
+    errors: &mut Vec<DecodeError>,
+    annotators: &[Arc<dyn IpAnnotator>],
+) -> Result<(), DecodeError> {
+    // Truncated records stop at the last argument which fit.
+    if num_args > format.num_params || (num_args < format.num_params && !truncated) {
+        return Err(DecodeError::InvalidLength {
+            name: "number of arguments",
+            expected: format.num_params,
+            actual: num_args,
+        });
+    }
+    let mut args = 0;
+    for fragment in &format.fragments {
+        match fragment {
+            Fragment::Literal(literal) => {
+                // Writing the message never fails, see `Sink`.
+                let _: fmt::Result = message.write_str(literal);
+            }
+            Fragment::Parameter(Parameter { hint }) => {
+                if args == num_args {
+                    break;
+                }
+                let (tag, value, rest) = try_read(buf)?;
+                buf = rest;
+                format_argument(
+                    format::decode_argument(tag, value)?,
+                    Some(DisplayHintWrapper(*hint)),
+                    message,
+                    errors,
+                    annotators,
+                )?;
+                args += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn format_argument(
+    arg: ArgumentValue<'_>,
+    last_hint: Option<DisplayHintWrapper>,
+    full_log_msg: &mut impl fmt::Write,
+    errors: &mut Vec<DecodeError>,
+    annotators: &[Arc<dyn IpAnnotator>],
+) -> Result<(), DecodeError> {
+    let formatted = match arg {
+        ArgumentValue::DisplayHint(_) => Err(()),
+        ArgumentValue::I8(v) => v.format(last_hint, full_log_msg),
+        ArgumentValue::I16(v) => v.format(last_hint, full_log_msg),
+        ArgumentValue::I32(v) => v.format(last_hint, full_log_msg),
+        ArgumentValue::I64(v) => v.format(last_hint, full_log_msg),
+        ArgumentValue::Isize(v) => v.format(last_hint, full_log_msg),
+        ArgumentValue::U8(v) => v.format(last_hint, full_log_msg),
+        ArgumentValue::U16(v) => v.format(last_hint, full_log_msg),
+        ArgumentValue::U32(v) => v.format(last_hint, full_log_msg),
+        ArgumentValue::U64(v) => v.format(last_hint, full_log_msg),
+        ArgumentValue::Usize(v) => v.format(last_hint, full_log_msg),
+        ArgumentValue::F32(v) => v.format(last_hint, full_log_msg),
+        ArgumentValue::F64(v) => v.format(last_hint, full_log_msg),
+        ArgumentValue::ArrU8(v) => format_array::<u8>(v, last_hint, full_log_msg),
+        ArgumentValue::ArrU16(v) => format_array::<u16>(v, last_hint, full_log_msg),
+        ArgumentValue::ArrU32(v) => format_array::<u32>(v, last_hint, full_log_msg),
+        ArgumentValue::ArrU64(v) => format_array::<u64>(v, last_hint, full_log_msg),
+        ArgumentValue::Bytes(v) => v.format(last_hint, full_log_msg),
+        ArgumentValue::Str(v) => full_log_msg
+            .write_str(&str_lossy("string", v, errors))
+            .map_err(|fmt::Error| ()),
+        ArgumentValue::Enum {
+            type_id,
+            discriminant,
+        } => format_enum(type_id, discriminant, last_hint, full_log_msg),
+    };
+    formatted.map_err(|()| DecodeError::UnsupportedDisplayHint {
+        hint: last_hint.map(|DisplayHintWrapper(dh)| dh),
+        argument: arg.argument(),
+    })?;
+    if !annotators.is_empty() {
+        if let Some(ip) = ip_argument(arg, last_hint) {
+            for annotator in annotators {
+                // Writing the message never fails, see `Sink`.
+                let _: fmt::Result = annotator.annotate(ip, full_log_msg);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the address of an argument formatted as an IP address.
+fn ip_argument(arg: ArgumentValue<'_>, hint: Option<DisplayHintWrapper>) -> Option<IpAddr> {
+    let hint = hint.map(|DisplayHintWrapper(dh)| dh)?;
+    let (addr, len) = match arg {
+        ArgumentValue::U32(v) => (v.into(), mem::size_of::<u32>()),
+        ArgumentValue::ArrU8(v) => (array_addr::<u8>(v), v.len()),
+        ArgumentValue::ArrU16(v) => (array_addr::<u16>(v), v.len()),
+        ArgumentValue::ArrU32(v) => (array_addr::<u32>(v), v.len()),
+        ArgumentValue::ArrU64(v) => (array_addr::<u64>(v), v.len()),
+        _ => return None,
+    };
+    match (hint, len) {
+        (DisplayHint::Ipv4 | DisplayHint::Ip, 4) => Some(Ipv4Addr::from(addr as u32).into()),
+        (DisplayHint::Ipv6 | DisplayHint::Ip, 16) => Some(Ipv6Addr::from(addr).into()),
+        _ => None,
+    }
+}
+
+/// Returns the address held by an array argument, the first element holding
+/// the most significant bits of the address.
+fn array_addr<T: ArrayElement>(value: &[u8]) -> u128 {
+    value
+        .chunks_exact(T::SIZE)
+        .map(T::from_ne_slice)
+        .fold(0u128, |acc, e| (acc << (T::SIZE * 8)) | e.into())
+}
+
+/// Decodes the record in `buf` and logs it with `logger`.
+///
+/// Errors which don't prevent the record from being logged, like invalid
+/// UTF-8, are reported to `on_error`.
+fn log_buf(
+    buf: &[u8],
+    logger: &dyn Log,
+    scratch: &mut Scratch,
+    on_error: &dyn Fn(&DecodeError),
+) -> Result<(), DecodeError> {
+    let Scratch {
+        strings,
+        targets,
+        processes,
+        cgroups,
+        namespaces,
+        clock,
+        static_fields,
+        cpu_key_value,
+        ip_annotators,
+        filter,
+        reorder,
+        #[cfg(feature = "async_tokio")]
+        sink,
+        cpu_id,
+        last_sequence,
+        stats,
+        message,
+        errors,
+    } = scratch;
+    message.clear();
+    errors.clear();
+
+    if let Some((level, target)) = peek_metadata(buf, targets) {
+        let metadata = log::Metadata::builder()
+            .level(level)
+            .target(&target)
+            .build();
+        if !logger.enabled(&metadata) {
+            // Skipped records still advance the sequence, so that they aren't
+            // reported as missing.
+            if matches!(aya_log_common::record_flags(buf), Some(flags) if flags & RECORD_FLAG_SEQUENCE != 0)
+            {
+                if let Ok(record) = decode_raw(buf) {
+                    let time = record
+                        .timestamp
+                        .and_then(|(source, ns)| clock.system_time(source, ns));
+                    check_sequence(
+                        &record,
+                        time,
+                        last_sequence,
+                        *cpu_id,
+                        stats,
+                        reorder.as_deref(),
+                        logger,
+                    );
+                }
+            }
+            return Ok(());
+        }
+    }
+
+    let record = decode_raw(buf)?;
+    let (target, module, file, line) = match &record.metadata {
+        Metadata::Inline {
+            target,
+            module,
+            file,
+            line,
+        } => (
+            targets.intern("target", target, errors),
+            module.map(|module| strings.intern("module", module, errors)),
+            file.map(|file| strings.intern("file", file, errors)),
+            *line,
+        ),
+        Metadata::Interned(format) => (
+            targets.intern("target", format.target.as_bytes(), errors),
+            Some(format.module.clone()),
+            Some(format.file.clone()),
+            Some(format.line),
+        ),
+    };
+    let time = record
+        .timestamp
+        .and_then(|(source, ns)| clock.system_time(source, ns));
+    check_sequence(
+        &record,
+        time,
+        last_sequence,
+        *cpu_id,
+        stats,
+        reorder.as_deref(),
+        logger,
+    );
+    if let Some(filter) = filter {
+        let meta = RecordMeta {
+            level: record.level,
+            target: &target,
+            cpu: *cpu_id,
+            pid: record.pid,
+            timestamp: time,
+        };
+        if !filter(&meta) {
+            return Ok(());
+        }
+    }
+
+    let truncation_marker = if record.truncated {
+        TRUNCATION_MARKER
+    } else {
+        ""
+    };
+    #[cfg(feature = "async_tokio")]
+    let queued = reorder.is_some() || sink.is_some();
+    #[cfg(not(feature = "async_tokio"))]
+    let queued = reorder.is_some();
+    if queued {
+        // Queued records outlive the buffer, their message is formatted into
+        // the reused string and copied once to fit.
+        record.format_message(message, errors, ip_annotators)?;
+        message.push_str(truncation_marker);
+    } else {
+        // Check the arguments before logging anything, the message is then
+        // formatted again straight into the output of the logger instead of
+        // being collected into a string. The annotators never fail.
+        record.format_message(&mut Discard, errors, &[])?;
+    }
+    errors.iter().for_each(on_error);
+    let comm = record
+        .pid
+        .zip(processes.as_ref())
+        .and_then(|(pid, processes)| processes.get(&pid));
+    let cgroup = record
+        .cgroup_id
+        .zip(cgroups.as_ref())
+        .and_then(|(id, cgroups)| cgroups.get(&id));
+    let cgroup = cgroup.as_deref();
+    let timestamp = time
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .and_then(|time| u64::try_from(time.as_nanos()).ok());
+    let netns = record
+        .netns_cookie
+        .zip(namespaces.as_ref())
+        .and_then(|(cookie, namespaces)| namespaces.get(&cookie));
+    let key_values = [
+        timestamp.map(|ns| ("timestamp_ns", log::kv::Value::from(ns))),
+        cpu_id
+            .filter(|_| *cpu_key_value)
+            .map(|cpu| ("cpu", log::kv::Value::from(cpu))),
+        record.pid.map(|pid| ("pid", log::kv::Value::from(pid))),
+        comm.as_deref()
+            .map(|comm| ("comm", log::kv::Value::from(comm))),
+        record
+            .cgroup_id
+            .map(|id| ("cgroup_id", log::kv::Value::from(id))),
+        cgroup.map(|cgroup| ("cgroup", log::kv::Value::from(&*cgroup.path))),
+        cgroup
+            .and_then(|cgroup| cgroup.container_id.as_deref())
+            .map(|id| ("container_id", log::kv::Value::from(id))),
+        cgroup
+            .and_then(|cgroup| cgroup.pod_uid.as_deref())
+            .map(|uid| ("pod_uid", log::kv::Value::from(uid))),
+        record
+            .netns_cookie
+            .map(|cookie| ("netns_cookie", log::kv::Value::from(cookie))),
+        netns
+            .as_deref()
+            .map(|netns| ("netns", log::kv::Value::from(netns))),
+        record
+            .program_id
+            .map(|id| ("program_id", log::kv::Value::from(id))),
+        record
+            .sequence
+            .map(|sequence| ("sequence", log::kv::Value::from(sequence))),
+    ];
+
+    let pending = || PendingRecord {
+        time: time.unwrap_or_else(SystemTime::now),
+        read: Instant::now(),
+        seq: 0,
+        level: record.level,
+        target: target.clone(),
+        module: module.clone(),
+        file: file.clone(),
+        line,
+        message: message.as_str().to_owned(),
+        key_values: key_values
+            .iter()
+            .flatten()
+            .filter_map(|(key, value)| Some((*key, OwnedValue::new(value)?)))
+            .collect(),
+    };
+    if let Some(reorder) = reorder {
+        reorder.push(pending());
+        return Ok(());
+    }
+    #[cfg(feature = "async_tokio")]
+    if let Some(sink) = sink {
+        sink.push(pending());
+        return Ok(());
+    }
+    let message = Message {
+        record: &record,
+        annotators: ip_annotators,
+    };
+    logger.log(
+        &Record::builder()
+            .args(format_args!("{message}{truncation_marker}"))
+            .target(&target)
+            .level(record.level)
+            .module_path(module.as_deref())
+            .file(file.as_deref())
+            .line(line)
+            .key_values(&KeyValues(&key_values, static_fields))
+            .build(),
+    );
+    logger.flush();
+    Ok(())
+}
+
+/// The message of a record which was already checked by formatting it into
+/// [`Discard`].
+struct Message<'a> {
+    record: &'a RawRecord<'a>,
+    annotators: &'a [Arc<dyn IpAnnotator>],
+}
+
+impl fmt::Display for Message<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sink = Sink { f, result: Ok(()) };
+        // The errors were reported when the record was checked.
+        let _: Result<(), DecodeError> =
+            self.record
+                .format_message(&mut sink, &mut Vec::new(), self.annotators);
+        sink.result
+    }
+}
+
+/// Writes the message to a formatter without ever failing, so that errors
+/// returned while formatting a message only come from invalid records.
+struct Sink<'a, 'b> {
+    f: &'a mut fmt::Formatter<'b>,
+    result: fmt::Result,
+}
+
+impl fmt::Write for Sink<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.result.is_ok() {
+            self.result = self.f.write_str(s);
+        }
+        Ok(())
+    }
+}
+
+/// Drops the message, to check a record without allocating its message.
+struct Discard;
+
+impl fmt::Write for Discard {
+    fn write_str(&mut self, _: &str) -> fmt::Result {
+        Ok(())
+    }
+}
+
+/// Warns about the records missing before `record` on its CPU, found from the
+/// sequence numbers sent by the `sequence` feature of `aya-log-ebpf`.
+///
+/// A sequence going backwards, like after the program was reloaded, restarts
+/// the tracking.
+fn check_sequence(
+    record: &RawRecord<'_>,
+    time: Option<SystemTime>,
+    last_sequence: &mut Option<u64>,
+    cpu_id: Option<u32>,
+    stats: &Stats,
+    reorder: Option<&Reorderer>,
+    logger: &dyn Log,
+) {
+    let sequence = match record.sequence {
+        Some(sequence) => sequence,
+        None => return,
+    };
+    let missing = match last_sequence.replace(sequence) {
+        Some(last) if sequence > last => sequence - last - 1,
+        _ => 0,
+    };
+    if missing == 0 {
+        return;
+    }
+    stats.missing_records.fetch_add(missing, Ordering::Relaxed);
+    let cpu = cpu_id.map_or_else(String::new, |cpu| format!(" on CPU {cpu}"));
+    let message = format!("{missing} log records are missing before this one{cpu}");
+    match reorder {
+        // Queued with the time of the record, so that it's logged right before
+        // it.
+        Some(reorder) => reorder.push(PendingRecord {
+            time: time.unwrap_or_else(SystemTime::now),
+            read: Instant::now(),
+            seq: 0,
+            level: log::Level::Warn,
+            target: module_path!().into(),
+            module: Some(module_path!().into()),
+            file: Some(file!().into()),
+            line: Some(line!()),
+            message,
+            key_values: Vec::new(),
+        }),
+        None => log_warning(logger, format_args!("{message}")),
+    }
+}
+
+/// Decodes and formats arbitrary input, for fuzzing the decoder.
+///
+/// Run with `cargo fuzz run decode_record` in `aya-log/`.
+#[doc(hidden)]
+pub fn fuzz_log_buf(data: &[u8]) {
+    let _: Result<(), DecodeError> = log_buf(data, log::logger(), &mut Scratch::default(), &|_| {});
+}
+
+/// Buffers reused by a reader across records, cleared instead of reallocated
+/// so that decoding doesn't allocate once they've grown to fit the records,
+/// along with the caches and fields of the reader.
+#[derive(Default)]
+struct Scratch {
+    strings: Interner,
+    targets: Interner,
+    processes: Option<Arc<Resolver<u32, Arc<str>>>>,
+    cgroups: Option<Arc<Resolver<u64, Arc<Cgroup>>>>,
+    namespaces: Option<Arc<Resolver<u64, Arc<str>>>>,
+    clock: WallClock,
+    static_fields: Arc<[(String, String)]>,
+    /// Whether `cpu_id` is attached to the records, see
+    /// [`BpfLoggerBuilder::cpu_key_value`].
+    cpu_key_value: bool,
+    ip_annotators: Arc<[Arc<dyn IpAnnotator>]>,
+    filter: Option<Arc<RecordFilter>>,
+    reorder: Option<Arc<Reorderer>>,
+    #[cfg(feature = "async_tokio")]
+    sink: Option<Arc<BlockingSink>>,
+    /// The CPU whose perf buffer the records are read from.
+    cpu_id: Option<u32>,
+    /// The sequence number of the last record, see [`check_sequence`].
+    last_sequence: Option<u64>,
+    stats: Arc<Stats>,
+    /// The message of the records held by the reorder window or the blocking
+    /// sink, other records are formatted straight into the logger.
+    message: String,
+    errors: Vec<DecodeError>,
+}
+
+/// Cache of the target, module and file strings of the records, which are
+/// sent again with every record.
+///
+/// Strings are looked up by their raw bytes so that repeated records skip the
+/// UTF-8 validation and share the same allocation.
+#[derive(Default)]
+struct Interner {
+    strings: HashMap<Box<[u8]>, Arc<str>>,
+    /// Prepended to the strings as `prefix::string`, see
+    /// [`BpfLoggerBuilder::object_name`].
+    prefix: Option<Arc<str>>,
+}
+
+impl Interner {
+    fn with_prefix(prefix: Option<Arc<str>>) -> Interner {
+        Interner {
+            prefix,
+            ..Default::default()
+        }
+    }
+
+    fn intern(
+        &mut self,
+        field: &'static str,
+        value: &[u8],
+        errors: &mut Vec<DecodeError>,
+    ) -> Arc<str> {
+        if let Some(s) = self.strings.get(value) {
+            return s.clone();
+        }
+        let prefixed = |s: &str| -> Arc<str> {
+            match &self.prefix {
+                Some(prefix) => format!("{prefix}::{s}").into(),
+                None => s.into(),
+            }
+        };
+        let s = match str::from_utf8(value) {
+            Ok(s) => prefixed(s),
+            // Invalid strings aren't cached so that their errors are reported
+            // with every record.
+            Err(_) => return prefixed(&str_lossy(field, value, errors)),
+        };
+        // Corrupted records could otherwise grow the cache without bound.
+        if self.strings.len() < MAX_INTERNED_STRINGS {
+            self.strings.insert(value.into(), s.clone());
+        }
+        s
+    }
+}
+
+/// The key-values attached to a record: the context sent with the record and
+/// the [`BpfLoggerBuilder::static_field`]s.
+struct KeyValues<'a>(
+    &'a [Option<(&'a str, log::kv::Value<'a>)>],
+    &'a [(String, String)],
+);
+
+impl log::kv::Source for KeyValues<'_> {
+    fn visit<'kvs>(
+        &'kvs self,
+        visitor: &mut dyn log::kv::VisitSource<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        self.0.visit(visitor)?;
+        for (key, value) in self.1 {
+            visitor.visit_pair(key.as_str().into(), value.as_str().into())?;
+        }
+        Ok(())
+    }
+}
+
+/// The memory held by the readers of a [`BpfLogger`] and the records they
+/// queue, see [`BpfLoggerBuilder::max_buffered_bytes`].
+struct MemoryBudget {
+    max: usize,
+    used: AtomicUsize,
+    policy: DropPolicy,
+}
+
+impl MemoryBudget {
+    /// Accounts `bytes` more, unless that would exceed the budget.
+    fn reserve(&self, bytes: usize) -> bool {
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(bytes).filter(|&used| used <= self.max)
+            })
+            .is_ok()
+    }
+
+    fn release(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    /// Accounts memory which is already allocated, like the read buffers
+    /// grown by the perf buffer reads, even when it exceeds the budget.
+    fn resize(&self, previous: usize, bytes: usize) {
+        if bytes >= previous {
+            self.used.fetch_add(bytes - previous, Ordering::Relaxed);
+        } else {
+            self.release(previous - bytes);
+        }
+    }
+
+    fn exceeded(&self) -> bool {
+        self.used.load(Ordering::Relaxed) > self.max
+    }
+}
+
+/// Counts a record dropped because the buffers of the logger are full, warning
+/// about the first one.
+fn record_dropped(stats: &Stats, logger: &dyn Log) {
+    stats.dropped_records.fetch_add(1, Ordering::Relaxed);
+    if !stats.drops_reported.swap(true, Ordering::Relaxed) {
+        log_warning(
+            logger,
+            format_args!("dropping log records, the buffers of the logger are full"),
+        );
+    }
+}
+
+/// Annotates the IP addresses logged by eBPF programs with metadata, like their
+/// country or autonomous system, see [`BpfLoggerBuilder::ip_annotator`].
+pub trait IpAnnotator: Send + Sync {
+    /// Writes the annotation of `ip`, which is appended to the address in the
+    /// message, to `out`.
+    ///
+    /// Annotations should start with a space, like ` (US, AS64496)`. Nothing
+    /// should be written for unknown addresses. This is called for every
+    /// address logged by the readers, lookups which may block should be
+    /// cached.
+    fn annotate(&self, ip: IpAddr, out: &mut dyn fmt::Write) -> fmt::Result;
+}
+
+/// Converts the timestamps of the records to wall clock time, see
+/// [`BpfLoggerBuilder::clock_offset_interval`].
+struct WallClock {
+    interval: Duration,
+    /// The offsets of the kernel clocks from `CLOCK_REALTIME`, and when they
+    /// were last measured.
+    offsets: [Option<(Instant, ClockOffsets)>; 2],
+}
+
+/// The offset of a kernel clock from `CLOCK_REALTIME` at some time of the
+/// kernel clock, in nanoseconds.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct ClockAnchor {
+    clock_ns: i128,
+    offset: i128,
+}
+
+impl ClockAnchor {
+    fn measure(clock_id: libc::clockid_t) -> Option<ClockAnchor> {
+        // The wall clock is read before and after the kernel clock, so that
+        // the offset is off by half the time between the reads at most.
+        let before = clock_ns(libc::CLOCK_REALTIME)?;
+        let clock = clock_ns(clock_id)?;
+        let after = clock_ns(libc::CLOCK_REALTIME)?;
+        Some(ClockAnchor {
+            clock_ns: clock,
+            offset: before + (after - before) / 2 - clock,
+        })
+    }
+}
+
+/// The last two offsets measured for a kernel clock.
+///
+/// Records are read a while after being logged, those logged between the
+/// measurements get an offset interpolated between the two, so that the
+/// timestamps follow the wall clock smoothly instead of jumping when the offset
+/// is measured again.
+#[derive(Copy, Clone, Debug)]
+struct ClockOffsets {
+    previous: Option<ClockAnchor>,
+    current: ClockAnchor,
+}
+
+impl ClockOffsets {
+    fn offset(&self, clock_ns: i128) -> i128 {
+        let ClockOffsets { previous, current } = *self;
+        match previous {
+            Some(previous) if clock_ns <= previous.clock_ns => previous.offset,
+            Some(previous) if clock_ns < current.clock_ns => {
+                previous.offset
+                    + (current.offset - previous.offset) * (clock_ns - previous.clock_ns)
+                        / (current.clock_ns - previous.clock_ns)
+            }
+            _ => current.offset,
+        }
+    }
+}
+
+impl Default for WallClock {
+    fn default() -> Self {
+        WallClock::new(CLOCK_OFFSET_INTERVAL)
+    }
+}
+
+impl WallClock {
+    fn new(interval: Duration) -> WallClock {
+        WallClock {
+            interval,
+            offsets: [None; 2],
+        }
+    }
+
+    fn system_time(&mut self, clock: Clock, ns: u64) -> Option<SystemTime> {
+        let now = Instant::now();
+        let offsets = match self.offsets[clock as usize] {
+            Some((measured, offsets)) if now.duration_since(measured) < self.interval => offsets,
+            offsets => {
+                let clock_id = match clock {
+                    Clock::Monotonic => libc::CLOCK_MONOTONIC,
+                    Clock::Boottime => libc::CLOCK_BOOTTIME,
+                };
+                let offsets = ClockOffsets {
+                    previous: offsets.map(|(_, offsets)| offsets.current),
+                    current: ClockAnchor::measure(clock_id)?,
+                };
+                self.offsets[clock as usize] = Some((now, offsets));
+                offsets
+            }
+        };
+        let ns = ns as i128;
+        let ns = u64::try_from(ns + offsets.offset(ns)).ok()?;
+        Some(UNIX_EPOCH + Duration::from_nanos(ns))
+    }
+}
+
+/// Reads the current time of a clock, in nanoseconds.
+fn clock_ns(clock_id: libc::clockid_t) -> Option<i128> {
+    let mut ts: libc::timespec = unsafe { mem::zeroed() };
+    if unsafe { libc::clock_gettime(clock_id, &mut ts) } != 0 {
+        return None;
+    }
+    Some(ts.tv_sec as i128 * 1_000_000_000 + ts.tv_nsec as i128)
+}
+
+/// Decodes a string field, replacing invalid UTF-8 sequences with
+/// `U+FFFD REPLACEMENT CHARACTER` so that the rest of the record is still logged.
+fn str_lossy<'a>(
+    field: &'static str,
+    value: &'a [u8],
+    errors: &mut Vec<DecodeError>,
+) -> Cow<'a, str> {
+    match str::from_utf8(value) {
+        Ok(v) => Cow::Borrowed(v),
+        Err(error) => {
+            errors.push(DecodeError::InvalidUtf8 { field, error });
+            String::from_utf8_lossy(value)
+        }
+    }
+}
+
+fn try_read(buf: &[u8]) -> Result<(u8, &[u8], &[u8]), DecodeError> {
+    format::read_field(buf).map_err(|format::Truncated { needed, available }| {
+        DecodeError::Truncated { needed, available }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, os::unix::fs::MetadataExt, sync::Mutex};
+
+    use super::*;
+    use aya_log_common::{
+        format_entry, format_entry_len, format_id, set_record_flags, set_record_num_args,
+        write_interned_record_header, write_record_cgroup_id, write_record_checksum,
+        write_record_header, write_record_netns_cookie, write_record_pid, write_record_program_id,
+        write_record_sequence, write_record_timestamp, RecordBuilder, WriteToBuf,
+        FORMAT_ENTRY_FIELDS,
+    };
+    use aya_log_ebpf_macros::LogEnum;
+    use log::{logger, Level};
+    use lookup::Resolution;
+
+    #[derive(LogEnum)]
+    #[repr(u8)]
+    enum TcpState {
+        Established = 1,
+        #[allow(dead_code)]
+        SynSent,
+    }
+
+    #[derive(LogEnum)]
+    #[repr(u32)]
+    enum Unregistered {
+        Variant = 7,
+    }
+
+    fn log_buf(buf: &[u8], logger: &dyn Log) -> Result<(), DecodeError> {
+        super::log_buf(buf, logger, &mut Scratch::default(), &|e| {
+            panic!("unexpected decode error: {e}")
+        })
+    }
+
+    fn new_log(args: usize) -> Result<(usize, Vec<u8>), ()> {
+        let mut buf = vec![0; 8192];
+        let len = write_record_header(
+            &mut buf,
+            "test",
+            aya_log_common::Level::Info,
+            "test",
+            "test.rs",
+            123,
+            args,
+        )?;
+        Ok((len, buf))
+    }
+
+    #[test]
+    fn test_str() {
+        testing_logger::setup();
+        let (mut len, mut input) = new_log(1).unwrap();
+
+        len += "test".write(&mut input[len..]).unwrap();
+
+        _ = len;
+
+        let logger = logger();
+        let () = log_buf(&input, logger).unwrap();
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(captured_logs.len(), 1);
+            assert_eq!(captured_logs[0].body, "test");
+            assert_eq!(captured_logs[0].level, Level::Info);
+        });
+    }
+
+    #[test]
+    fn test_str_invalid_utf8() {
+        testing_logger::setup();
+        let (mut len, mut input) = new_log(2).unwrap();
+
+        len += "invalid: ".write(&mut input[len..]).unwrap();
+        len += "a-b".write(&mut input[len..]).unwrap();
+        // Corrupt the string, as a bogus kernel pointer could.
+        input[len - 2] = 0xff;
+
+        _ = len;
+
+        let logger = logger();
+        let errors = std::cell::RefCell::new(Vec::new());
+        let () = super::log_buf(&input, logger, &mut Scratch::default(), &|e| {
+            errors.borrow_mut().push(e.to_string())
+        })
+        .unwrap();
+        assert_eq!(errors.into_inner(), ["invalid UTF-8 in string"]);
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(captured_logs.len(), 1);
+            assert_eq!(captured_logs[0].body, "invalid: a\u{fffd}b");
+        });
+    }
+
+    #[test]
+    fn test_interner() {
+        let mut strings = Interner::default();
+        let mut errors = Vec::new();
+        let a = strings.intern("target", b"test", &mut errors);
+        let b = strings.intern("target", b"test", &mut errors);
+        assert!(Arc::ptr_eq(&a, &b));
+
+        // Invalid strings are reported every time.
+        for _ in 0..2 {
+            assert_eq!(
+                &*strings.intern("file", b"a\xffb", &mut errors),
+                "a\u{fffd}b"
+            );
+        }
+        assert_eq!(errors.len(), 2);
+        assert_eq!(strings.strings.len(), 1);
+    }
+
+    #[test]
+    fn test_target_prefix() {
+        testing_logger::setup();
+        let (len, input) = new_log(0).unwrap();
+        let mut scratch = Scratch {
+            targets: Interner::with_prefix(Some("obj".into())),
+            ..Default::default()
+        };
+        for _ in 0..2 {
+            super::log_buf(&input[..len], logger(), &mut scratch, &|e| {
+                panic!("unexpected decode error: {e}")
+            })
+            .unwrap();
+        }
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(captured_logs.len(), 2);
+            assert_eq!(captured_logs[0].target, "obj::test");
+            assert_eq!(captured_logs[1].target, "obj::test");
+        });
+    }
+
+    #[test]
+    fn test_scratch_reused() {
+        let (mut len, mut input) = new_log(1).unwrap();
+        len += "test".write(&mut input[len..]).unwrap();
+
+        // Only queued records are formatted into the scratch string.
+        let mut scratch = Scratch {
+            reorder: Some(Arc::new(Reorderer::new(
+                Duration::from_secs(60),
+                Arc::new(DefaultLogger),
+                Arc::default(),
+                None,
+                Arc::default(),
+            ))),
+            ..Default::default()
+        };
+        let mut log = || {
+            super::log_buf(&input[..len], logger(), &mut scratch, &|e| {
+                panic!("unexpected decode error: {e}")
+            })
+            .unwrap();
+            (scratch.message.as_ptr(), scratch.message.capacity())
+        };
+        let first = log();
+        assert_ne!(first.1, 0);
+        assert_eq!(log(), first);
+    }
+
+    #[test]
+    fn test_decode_record() {
+        let (mut len, mut input) = new_log(2).unwrap();
+        len += DisplayHint::Default.write(&mut input[len..]).unwrap();
+        len += 42u32.write(&mut input[len..]).unwrap();
+        set_record_flags(&mut input, RECORD_FLAG_TRUNCATED);
+
+        assert_eq!(
+            decode_record(&input[..len]),
+            Ok(LogRecord {
+                target: "test".into(),
+                level: Level::Info,
+                module: Some("test".into()),
+                file: Some("test.rs".into()),
+                line: Some(123),
+                message: "42".into(),
+                truncated: true,
+                pid: None,
+                cgroup_id: None,
+                netns_cookie: None,
+                timestamp: None,
+                program_id: None,
+                sequence: None,
+                errors: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn test_decode_built_record() {
+        let record = RecordBuilder::new(aya_log_common::Level::Warn, "test")
+            .location("test.rs", 7)
+            .arg("value: ")
+            .arg(DisplayHint::LowerHex)
+            .arg(0xbeefu32)
+            .pid(42)
+            .program_id(3)
+            .checksum(true)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            decode_record(&record),
+            Ok(LogRecord {
+                target: "test".into(),
+                level: Level::Warn,
+                module: Some("test".into()),
+                file: Some("test.rs".into()),
+                line: Some(7),
+                message: "value: beef".into(),
+                truncated: false,
+                pid: Some(42),
+                cgroup_id: None,
+                netns_cookie: None,
+                timestamp: None,
+                program_id: Some(3),
+                sequence: None,
+                errors: vec![],
+            })
+        );
+
+        let mut builder = RecordBuilder::new(aya_log_common::Level::Info, "test");
+        builder.arg([0u8; LOG_BUF_CAPACITY].as_slice());
+        assert!(builder.build().is_err());
+    }
+
+    #[test]
+    fn test_decode_corrupted() {
+        let (mut len, mut input) = new_log(6).unwrap();
+        len += DisplayHint::Ip.write(&mut input[len..]).unwrap();
+        len += [1u16, 2, 3, 4, 5, 6, 7, 8]
+            .write(&mut input[len..])
+            .unwrap();
+        len += DisplayHint::Default.write(&mut input[len..]).unwrap();
+        len += "test".write(&mut input[len..]).unwrap();
+        len += DisplayHint::LowerHex.write(&mut input[len..]).unwrap();
+        len += [0xde, 0xad].as_slice().write(&mut input[len..]).unwrap();
+        let input = &input[..len];
+
+        // Truncated records and any corrupted byte must never panic.
+        for end in 0..len {
+            assert!(decode_record(&input[..end]).is_err());
+        }
+        for i in 0..len {
+            for b in [0, 1, 0x7f, 0x80, 0xff] {
+                let mut input = input.to_vec();
+                input[i] = b;
+                fuzz_log_buf(&input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_interned() {
+        testing_logger::setup();
+        const FIELDS: [&str; FORMAT_ENTRY_FIELDS] =
+            ["test", "test", "test.rs", "addr {:i} port {}"];
+        const ENTRY: [u8; format_entry_len(&FIELDS)] = format_entry(123, &FIELDS);
+        let () = register_format_entries(&ENTRY).unwrap();
+
+        let mut input = vec![0; 8192];
+        let mut len = write_interned_record_header(
+            &mut input,
+            format_id(&ENTRY),
+            aya_log_common::Level::Info,
+            2,
+        )
+        .unwrap();
+        len += 0x7f000001u32.write(&mut input[len..]).unwrap();
+        len += 80u16.write(&mut input[len..]).unwrap();
+
+        let logger = logger();
+        let () = log_buf(&input[..len], logger).unwrap();
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(captured_logs.len(), 1);
+            assert_eq!(captured_logs[0].body, "addr 127.0.0.1 port 80");
+            assert_eq!(captured_logs[0].target, "test");
+        });
+
+        // Truncated records stop at the last argument which fit.
+        set_record_num_args(&mut input, 1);
+        set_record_flags(&mut input, RECORD_FLAG_TRUNCATED);
+        let record = decode_record(&input[..len]).unwrap();
+        assert_eq!(record.message, "addr 127.0.0.1 port ");
+        assert_eq!(record.file.as_deref(), Some("test.rs"));
+        assert_eq!(record.line, Some(123));
+
+        let mut input = vec![0; 8192];
+        let len = write_interned_record_header(
+            &mut input,
+            format_id(&ENTRY) ^ 1,
+            aya_log_common::Level::Info,
+            0,
+        )
+        .unwrap();
+        assert_eq!(
+            decode_record(&input[..len]),
+            Err(DecodeError::UnknownFormat(format_id(&ENTRY) ^ 1))
+        );
+    }
+
+    #[test]
+    fn test_checksum() {
+        let (mut len, mut input) = new_log(1).unwrap();
+        len += "test".write(&mut input[len..]).unwrap();
+        let arg = len - 1;
+        len = write_record_checksum(&mut input, len);
+        // Padding added by the perf buffer.
+        len += 5;
+        assert_eq!(decode_record(&input[..len]).unwrap().message, "test");
+
+        input[arg] = b'x';
+        assert!(matches!(
+            decode_record(&input[..len]),
+            Err(DecodeError::ChecksumMismatch { .. })
+        ));
+        assert_eq!(
+            decode_record(&input[..arg]),
+            Err(DecodeError::MissingChecksum)
+        );
+    }
+
+    #[test]
+    fn test_pid() {
+        let (len, mut input) = new_log(1).unwrap();
+        let mut len = write_record_pid(&mut input, len, 1234);
+        len += "test".write(&mut input[len..]).unwrap();
+        let record = decode_record(&input[..len]).unwrap();
+        assert_eq!(record.pid, Some(1234));
+        assert_eq!(record.message, "test");
+
+        let comm = fs::read_to_string("/proc/self/comm").unwrap();
+        assert_eq!(
+            process_name(std::process::id()).as_deref(),
+            Some(comm.trim_end_matches('\n'))
+        );
+        assert_eq!(process_name(u32::MAX), None);
+    }
+
+    #[test]
+    fn test_cgroup() {
+        let (len, mut input) = new_log(0).unwrap();
+        let len = write_record_pid(&mut input, len, 1234);
+        let len = write_record_cgroup_id(&mut input, len, 5678);
+        let record = decode_record(&input[..len]).unwrap();
+        assert_eq!(record.pid, Some(1234));
+        assert_eq!(record.cgroup_id, Some(5678));
+
+        let container_id = "0123456789abcdef".repeat(4);
+        let root = std::env::temp_dir().join(format!("aya-log-cgroup-{}", std::process::id()));
+        let path = root
+            .join("kubepods.slice")
+            .join("kubepods-besteffort.slice")
+            .join("kubepods-besteffort-pod1b4e28ba_2fa1_11d2_883f_0016d3cca427.slice")
+            .join(format!("cri-containerd-{container_id}.scope"));
+        fs::create_dir_all(&path).unwrap();
+        let id = fs::metadata(&path).unwrap().ino();
+        let cgroup = Cgroup::resolve(&root, id);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            cgroup.as_deref(),
+            Some(&Cgroup {
+                path: path
+                    .strip_prefix(&root)
+                    .map(|path| format!("/{}", path.display()))
+                    .unwrap()
+                    .into(),
+                container_id: Some(container_id.into()),
+                pod_uid: Some("1b4e28ba-2fa1-11d2-883f-0016d3cca427".into()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_filtered() {
+        struct WarnLogger;
+
+        impl Log for WarnLogger {
+            fn enabled(&self, metadata: &log::Metadata) -> bool {
+                metadata.level() <= Level::Warn && metadata.target() == "test"
+            }
+
+            fn log(&self, record: &Record) {
+                panic!("filtered record logged: {}", record.args());
+            }
+
+            fn flush(&self) {}
+        }
+
+        // The arguments of filtered records aren't even decoded, an invalid
+        // argument tag would be an error otherwise.
+        let (mut len, mut input) = new_log(1).unwrap();
+        let arg = len;
+        len += 1u32.write(&mut input[len..]).unwrap();
+        input[arg] = 0xff;
+        assert_eq!(log_buf(&input[..len], &WarnLogger), Ok(()));
+    }
+
+    #[test]
+    fn test_record_filter() {
+        let (len, mut input) = new_log(1).unwrap();
+        let mut len = write_record_pid(&mut input, len, 1234);
+        let arg = len;
+        len += 1u32.write(&mut input[len..]).unwrap();
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut scratch = Scratch {
+            filter: Some(Arc::new({
+                let seen = seen.clone();
+                move |meta: &RecordMeta<'_>| {
+                    seen.lock().unwrap().push((
+                        meta.level,
+                        meta.target.to_owned(),
+                        meta.cpu,
+                        meta.pid,
+                    ));
+                    meta.pid != Some(1234)
+                }
+            })),
+            cpu_id: Some(3),
+            ..Default::default()
+        };
+
+        // The arguments of filtered records aren't decoded either.
+        input[arg] = 0xff;
+        let log = |scratch: &mut Scratch| {
+            super::log_buf(&input[..len], logger(), scratch, &|e| {
+                panic!("unexpected decode error: {e}")
+            })
+        };
+        assert_eq!(log(&mut scratch), Ok(()));
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [(Level::Info, "test".to_owned(), Some(3), Some(1234))]
+        );
+
+        scratch.filter = Some(Arc::new(|_: &RecordMeta<'_>| true));
+        assert_eq!(log(&mut scratch), Err(DecodeError::InvalidArgument(0xff)));
+    }
+
+    #[test]
+    fn test_reorder() {
+        type Pairs = Vec<(String, String)>;
+
+        #[derive(Default)]
+        struct Capture(Mutex<Vec<(String, Pairs)>>);
+
+        impl Log for Capture {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &Record) {
+                struct Collect<'a>(&'a mut Vec<(String, String)>);
+
+                impl<'kvs> log::kv::VisitSource<'kvs> for Collect<'_> {
+                    fn visit_pair(
+                        &mut self,
+                        key: log::kv::Key<'kvs>,
+                        value: log::kv::Value<'kvs>,
+                    ) -> Result<(), log::kv::Error> {
+                        self.0.push((key.to_string(), value.to_string()));
+                        Ok(())
+                    }
+                }
+
+                let mut key_values = Vec::new();
+                record
+                    .key_values()
+                    .visit(&mut Collect(&mut key_values))
+                    .unwrap();
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((record.args().to_string(), key_values));
+            }
+
+            fn flush(&self) {}
+        }
+
+        let logger = Arc::new(Capture::default());
+        let window = Duration::from_millis(10);
+        let reorder = Arc::new(Reorderer::new(
+            window,
+            logger.clone(),
+            vec![("hostname".into(), "node-1".into())].into(),
+            None,
+            Arc::default(),
+        ));
+        let mut scratch = Scratch {
+            reorder: Some(reorder.clone()),
+            cpu_id: Some(1),
+            cpu_key_value: true,
+            ..Default::default()
+        };
+
+        // Records logged 2s, 1s and 2s again from now, read in that order.
+        let start = Instant::now();
+        let now_ns = clock_ns(libc::CLOCK_MONOTONIC).unwrap() as u64;
+        for (i, s) in [2, 1, 2].into_iter().enumerate() {
+            let (len, mut input) = new_log(1).unwrap();
+            let ns = now_ns + s * 1_000_000_000;
+            let mut len = write_record_timestamp(&mut input, len, Clock::Monotonic, ns);
+            len += i.to_string().as_str().write(&mut input[len..]).unwrap();
+            let () = super::log_buf(&input[..len], logger.as_ref(), &mut scratch, &|e| {
+                panic!("unexpected decode error: {e}")
+            })
+            .unwrap();
+        }
+        assert!(logger.0.lock().unwrap().is_empty());
+
+        reorder.flush(start);
+        assert!(logger.0.lock().unwrap().is_empty());
+
+        reorder.flush(Instant::now() + window);
+        let logged = logger.0.lock().unwrap();
+        let messages = logged
+            .iter()
+            .map(|(message, _)| message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(messages, ["1", "0", "2"]);
+        let keys = logged[0]
+            .1
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["timestamp_ns", "cpu", "hostname"]);
+        assert_eq!(logged[0].1[1].1, "1");
+    }
+
+    #[test]
+    fn test_check_max_buffered_bytes() {
+        let min = 4 * READ_BUFFERS * LOG_BUF_CAPACITY;
+        assert_eq!(min, 4 * 80 * 1024);
+        assert!(check_max_buffered_bytes(min, 4).is_ok());
+        assert!(matches!(
+            check_max_buffered_bytes(min - 1, 4),
+            Err(Error::BufferLimitTooSmall { max, min: m }) if max == min - 1 && m == min
+        ));
+    }
+
+    #[test]
+    fn test_netns_cookie() {
+        let (len, mut input) = new_log(1).unwrap();
+        let len = write_record_cgroup_id(&mut input, len, 5678);
+        let mut len = write_record_netns_cookie(&mut input, len, 4096);
+        len += "test".write(&mut input[len..]).unwrap();
+        let record = decode_record(&input[..len]).unwrap();
+        assert_eq!(record.cgroup_id, Some(5678));
+        assert_eq!(record.netns_cookie, Some(4096));
+        assert_eq!(record.message, "test");
+    }
+
+    #[test]
+    fn test_program_id() {
+        let (len, mut input) = new_log(1).unwrap();
+        let mut len = write_record_program_id(&mut input, len, 7);
+        len += "test".write(&mut input[len..]).unwrap();
+        let record = decode_record(&input[..len]).unwrap();
+        assert_eq!(record.program_id, Some(7));
+        assert_eq!(record.message, "test");
     }
-}
-
-#[derive(Error, Debug)]
-pub enum Error {
-    #[error("log event array {} doesn't exist", MAP_NAME)]
-    MapNotFound,
 
-    #[error("error opening log event array")]
-    MapError(#[from] MapError),
+    #[test]
+    fn test_sequence() {
+        #[derive(Default)]
+        struct Capture(Mutex<Vec<(log::Level, String)>>);
 
-    #[error("error opening log buffer")]
-    PerfBufferError(#[from] PerfBufferError),
+        impl Log for Capture {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
 
-    #[error("invalid /sys/devices/system/cpu/online format")]
-    InvalidOnlineCpu(#[source] io::Error),
-}
+            fn log(&self, record: &Record) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((record.level(), record.args().to_string()));
+            }
 
-fn log_buf(mut buf: &[u8], logger: &dyn Log) -> Result<(), ()> {
-    let mut target = None;
-    let mut level = None;
-    let mut module = None;
-    let mut file = None;
-    let mut line = None;
-    let mut num_args = None;
+            fn flush(&self) {}
+        }
 
-    for _ in 0..LOG_FIELDS {
-        let (RecordFieldWrapper(tag), value, rest) = try_read(buf)?;
-
-        match tag {
-            RecordField::Target => {
-                target = Some(str::from_utf8(value).map_err(|_| ())?);
-            }
-            RecordField::Level => {
-                level = Some({
-                    let level = unsafe { ptr::read_unaligned(value.as_ptr() as *const _) };
-                    match level {
-                        Level::Error => log::Level::Error,
-                        Level::Warn => log::Level::Warn,
-                        Level::Info => log::Level::Info,
-                        Level::Debug => log::Level::Debug,
-                        Level::Trace => log::Level::Trace,
-                    }
-                })
-            }
-            RecordField::Module => {
-                module = Some(str::from_utf8(value).map_err(|_| ())?);
-            }
-            RecordField::File => {
-                file = Some(str::from_utf8(value).map_err(|_| ())?);
-            }
-            RecordField::Line => {
-                line = Some(u32::from_ne_bytes(value.try_into().map_err(|_| ())?));
-            }
-            RecordField::NumArgs => {
-                num_args = Some(usize::from_ne_bytes(value.try_into().map_err(|_| ())?));
-            }
+        let capture = Capture::default();
+        let mut scratch = Scratch {
+            cpu_id: Some(2),
+            ..Default::default()
+        };
+        for sequence in [0, 1, 4, 2] {
+            let (len, mut input) = new_log(1).unwrap();
+            let mut len = write_record_sequence(&mut input, len, sequence);
+            len += "test".write(&mut input[len..]).unwrap();
+            assert_eq!(
+                decode_record(&input[..len]).unwrap().sequence,
+                Some(sequence)
+            );
+            super::log_buf(&input[..len], &capture, &mut scratch, &|e| {
+                panic!("unexpected decode error: {e}")
+            })
+            .unwrap();
         }
 
-        buf = rest;
+        assert_eq!(
+            *capture.0.lock().unwrap(),
+            [
+                (Level::Info, "test".to_owned()),
+                (Level::Info, "test".to_owned()),
+                (
+                    Level::Warn,
+                    "2 log records are missing before this one on CPU 2".to_owned()
+                ),
+                (Level::Info, "test".to_owned()),
+                // Going backwards restarts the tracking.
+                (Level::Info, "test".to_owned()),
+            ]
+        );
+        assert_eq!(scratch.stats.missing_records.load(Ordering::Relaxed), 2);
     }
 
-    let mut full_log_msg = String::new();
-    let mut last_hint: Option<DisplayHintWrapper> = None;
-    for _ in 0..num_args.ok_or(())? {
-        let (ArgumentWrapper(tag), value, rest) = try_read(buf)?;
+    #[test]
+    fn test_timestamp() {
+        for (clock, clock_id) in [
+            (Clock::Monotonic, libc::CLOCK_MONOTONIC),
+            (Clock::Boottime, libc::CLOCK_BOOTTIME),
+        ] {
+            let (len, mut input) = new_log(0).unwrap();
+            let ns = clock_ns(clock_id).unwrap() as u64;
+            let len = write_record_timestamp(&mut input, len, clock, ns);
+            let timestamp = decode_record(&input[..len]).unwrap().timestamp.unwrap();
+            let now = SystemTime::now();
+            assert!(now.duration_since(timestamp).unwrap() < Duration::from_secs(1));
 
-        match tag {
-            Argument::DisplayHint => {
-                last_hint = Some(unsafe { ptr::read_unaligned(value.as_ptr() as *const _) });
-            }
-            Argument::I8 => {
-                full_log_msg.push_str(
-                    &i8::from_ne_bytes(value.try_into().map_err(|_| ())?)
-                        .format(last_hint.take())?,
-                );
-            }
-            Argument::I16 => {
-                full_log_msg.push_str(
-                    &i16::from_ne_bytes(value.try_into().map_err(|_| ())?)
-                        .format(last_hint.take())?,
-                );
-            }
-            Argument::I32 => {
-                full_log_msg.push_str(
-                    &i32::from_ne_bytes(value.try_into().map_err(|_| ())?)
-                        .format(last_hint.take())?,
-                );
-            }
-            Argument::I64 => {
-                full_log_msg.push_str(
-                    &i64::from_ne_bytes(value.try_into().map_err(|_| ())?)
-                        .format(last_hint.take())?,
-                );
-            }
-            Argument::Isize => {
-                full_log_msg.push_str(
-                    &isize::from_ne_bytes(value.try_into().map_err(|_| ())?)
-                        .format(last_hint.take())?,
-                );
-            }
-            Argument::U8 => {
-                full_log_msg.push_str(
-                    &u8::from_ne_bytes(value.try_into().map_err(|_| ())?)
-                        .format(last_hint.take())?,
-                );
-            }
-            Argument::U16 => {
-                full_log_msg.push_str(
-                    &u16::from_ne_bytes(value.try_into().map_err(|_| ())?)
-                        .format(last_hint.take())?,
-                );
-            }
-            Argument::U32 => {
-                full_log_msg.push_str(
-                    &u32::from_ne_bytes(value.try_into().map_err(|_| ())?)
-                        .format(last_hint.take())?,
-                );
-            }
-            Argument::U64 => {
-                full_log_msg.push_str(
-                    &u64::from_ne_bytes(value.try_into().map_err(|_| ())?)
-                        .format(last_hint.take())?,
-                );
-            }
-            Argument::Usize => {
-                full_log_msg.push_str(
-                    &usize::from_ne_bytes(value.try_into().map_err(|_| ())?)
-                        .format(last_hint.take())?,
-                );
-            }
-            Argument::F32 => {
-                full_log_msg.push_str(
-                    &f32::from_ne_bytes(value.try_into().map_err(|_| ())?)
-                        .format(last_hint.take())?,
-                );
-            }
-            Argument::F64 => {
-                full_log_msg.push_str(
-                    &f64::from_ne_bytes(value.try_into().map_err(|_| ())?)
-                        .format(last_hint.take())?,
-                );
-            }
-            Argument::ArrU8Len6 => {
-                let value: [u8; 6] = value.try_into().map_err(|_| ())?;
-                full_log_msg.push_str(&value.format(last_hint.take())?);
-            }
-            Argument::ArrU8Len16 => {
-                let value: [u8; 16] = value.try_into().map_err(|_| ())?;
-                full_log_msg.push_str(&value.format(last_hint.take())?);
-            }
-            Argument::ArrU16Len8 => {
-                let data: [u8; 16] = value.try_into().map_err(|_| ())?;
-                let mut value: [u16; 8] = Default::default();
-                for (i, s) in data.chunks_exact(2).enumerate() {
-                    value[i] = ((s[1] as u16) << 8) | s[0] as u16;
-                }
-                full_log_msg.push_str(&value.format(last_hint.take())?);
-            }
-            Argument::Bytes => {
-                full_log_msg.push_str(&value.format(last_hint.take())?);
-            }
-            Argument::Str => match str::from_utf8(value) {
-                Ok(v) => {
-                    full_log_msg.push_str(v);
-                }
-                Err(e) => error!("received invalid utf8 string: {}", e),
-            },
+            // The clock is the last byte of the field.
+            input[len - 1] = 0xff;
+            assert_eq!(
+                decode_record(&input[..len]),
+                Err(DecodeError::InvalidClock(0xff))
+            );
         }
-
-        buf = rest;
     }
 
-    logger.log(
-        &Record::builder()
-            .args(format_args!("{full_log_msg}"))
-            .target(target.ok_or(())?)
-            .level(level.ok_or(())?)
-            .module_path(module)
-            .file(file)
-            .line(line)
-            .build(),
-    );
-    logger.flush();
-    Ok(())
-}
+    #[test]
+    fn test_clock_offsets() {
+        let anchor = |clock_ns, offset| ClockAnchor { clock_ns, offset };
+        let offsets = ClockOffsets {
+            previous: Some(anchor(1000, 0)),
+            current: anchor(2000, 1000),
+        };
+        assert_eq!(offsets.offset(500), 0);
+        assert_eq!(offsets.offset(1500), 500);
+        assert_eq!(offsets.offset(2500), 1000);
 
-fn try_read<T: Pod>(mut buf: &[u8]) -> Result<(T, &[u8], &[u8]), ()> {
-    if buf.len() < mem::size_of::<T>() + mem::size_of::<LogValueLength>() {
-        return Err(());
+        let mut clock = WallClock::new(Duration::from_secs(3600));
+        clock.offsets[Clock::Monotonic as usize] = Some((Instant::now(), offsets));
+        assert_eq!(
+            clock.system_time(Clock::Monotonic, 1500),
+            Some(UNIX_EPOCH + Duration::from_nanos(2000))
+        );
     }
 
-    let tag = unsafe { ptr::read_unaligned(buf.as_ptr() as *const T) };
-    buf = &buf[mem::size_of::<T>()..];
+    #[test]
+    fn test_key_values() {
+        #[derive(Default)]
+        struct KvLogger(std::sync::Mutex<Vec<(String, String)>>);
+
+        impl Log for KvLogger {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &Record) {
+                let mut key_values = self.0.lock().unwrap();
+                record
+                    .key_values()
+                    .visit(&mut Collect(&mut key_values))
+                    .unwrap();
+            }
+
+            fn flush(&self) {}
+        }
+
+        struct Collect<'a>(&'a mut Vec<(String, String)>);
 
-    let len =
-        LogValueLength::from_ne_bytes(buf[..mem::size_of::<LogValueLength>()].try_into().unwrap());
-    buf = &buf[mem::size_of::<LogValueLength>()..];
+        impl<'kvs> log::kv::VisitSource<'kvs> for Collect<'_> {
+            fn visit_pair(
+                &mut self,
+                key: log::kv::Key<'kvs>,
+                value: log::kv::Value<'kvs>,
+            ) -> Result<(), log::kv::Error> {
+                self.0.push((key.to_string(), value.to_string()));
+                Ok(())
+            }
+        }
 
-    let len: usize = len.into();
-    if buf.len() < len {
-        return Err(());
+        let (len, mut input) = new_log(0).unwrap();
+        let len = write_record_pid(&mut input, len, 1234);
+        let logger = KvLogger::default();
+        // The CPU is only attached when enabled with `cpu_key_value`.
+        let mut scratch = Scratch {
+            static_fields: vec![("hostname".into(), "node-1".into())].into(),
+            cpu_id: Some(2),
+            ..Default::default()
+        };
+        let () = super::log_buf(&input[..len], &logger, &mut scratch, &|e| {
+            panic!("unexpected decode error: {e}")
+        })
+        .unwrap();
+        assert_eq!(
+            logger.0.into_inner().unwrap(),
+            [
+                ("pid".into(), "1234".into()),
+                ("hostname".into(), "node-1".into())
+            ]
+        );
     }
 
-    let (value, rest) = buf.split_at(len);
-    Ok((tag, value, rest))
-}
+    #[test]
+    fn test_decode_errors() {
+        let (mut len, mut input) = new_log(2).unwrap();
+        len += DisplayHint::LowerMac.write(&mut input[len..]).unwrap();
+        len += 1u32.write(&mut input[len..]).unwrap();
+        assert!(matches!(
+            log_buf(&input, logger()),
+            Err(DecodeError::UnsupportedDisplayHint {
+                hint: Some(DisplayHint::LowerMac),
+                argument: Argument::U32,
+            })
+        ));
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use aya_log_common::{write_record_header, WriteToBuf};
-    use log::{logger, Level};
+        // Unknown argument type.
+        input[len - 7] = 0xff;
+        assert!(matches!(
+            log_buf(&input, logger()),
+            Err(DecodeError::InvalidArgument(0xff))
+        ));
 
-    fn new_log(args: usize) -> Result<(usize, Vec<u8>), ()> {
-        let mut buf = vec![0; 8192];
-        let len = write_record_header(
-            &mut buf,
-            "test",
-            aya_log_common::Level::Info,
-            "test",
-            "test.rs",
-            123,
-            args,
-        )?;
-        Ok((len, buf))
+        // The record ends in the middle of the last argument.
+        assert!(matches!(
+            log_buf(&input[..len - 1], logger()),
+            Err(DecodeError::Truncated {
+                needed: 4,
+                available: 3
+            })
+        ));
     }
 
     #[test]
-    fn test_str() {
+    fn test_str_with_args() {
         testing_logger::setup();
-        let (mut len, mut input) = new_log(1).unwrap();
+        let (mut len, mut input) = new_log(2).unwrap();
 
+        len += "hello ".write(&mut input[len..]).unwrap();
         len += "test".write(&mut input[len..]).unwrap();
 
         _ = len;
@@ -619,26 +3676,30 @@ mod test {
         let () = log_buf(&input, logger).unwrap();
         testing_logger::validate(|captured_logs| {
             assert_eq!(captured_logs.len(), 1);
-            assert_eq!(captured_logs[0].body, "test");
+            assert_eq!(captured_logs[0].body, "hello test");
             assert_eq!(captured_logs[0].level, Level::Info);
         });
     }
 
     #[test]
-    fn test_str_with_args() {
+    fn test_str_truncated() {
         testing_logger::setup();
-        let (mut len, mut input) = new_log(2).unwrap();
+        let (mut len, mut input) = new_log(3).unwrap();
 
         len += "hello ".write(&mut input[len..]).unwrap();
-        len += "test".write(&mut input[len..]).unwrap();
-
-        _ = len;
+        // Only leave room for the first 4 bytes of the value.
+        let end = len + 3 + 4;
+        let (arg_len, truncated) = "truncated".write_or_truncate(&mut input[len..end]).unwrap();
+        assert!(truncated);
+        len += arg_len;
+        set_record_flags(&mut input, RECORD_FLAG_TRUNCATED);
+        set_record_num_args(&mut input, 2);
 
         let logger = logger();
-        let () = log_buf(&input, logger).unwrap();
+        let () = log_buf(&input[..len], logger).unwrap();
         testing_logger::validate(|captured_logs| {
             assert_eq!(captured_logs.len(), 1);
-            assert_eq!(captured_logs[0].body, "hello test");
+            assert_eq!(captured_logs[0].body, "hello trun…[truncated]");
             assert_eq!(captured_logs[0].level, Level::Info);
         });
     }
@@ -649,7 +3710,7 @@ mod test {
         let (mut len, mut input) = new_log(2).unwrap();
 
         len += DisplayHint::LowerHex.write(&mut input[len..]).unwrap();
-        len += [0xde, 0xad].write(&mut input[len..]).unwrap();
+        len += [0xde, 0xad].as_slice().write(&mut input[len..]).unwrap();
 
         _ = len;
 
@@ -668,12 +3729,12 @@ mod test {
         let (mut len, mut input) = new_log(5).unwrap();
 
         len += DisplayHint::LowerHex.write(&mut input[len..]).unwrap();
-        len += [0xde, 0xad].write(&mut input[len..]).unwrap();
+        len += [0xde, 0xad].as_slice().write(&mut input[len..]).unwrap();
 
         len += " ".write(&mut input[len..]).unwrap();
 
         len += DisplayHint::UpperHex.write(&mut input[len..]).unwrap();
-        len += [0xbe, 0xef].write(&mut input[len..]).unwrap();
+        len += [0xbe, 0xef].as_slice().write(&mut input[len..]).unwrap();
 
         _ = len;
 
@@ -686,6 +3747,27 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_many_args() {
+        testing_logger::setup();
+        let (mut len, mut input) = new_log(40).unwrap();
+
+        for i in 0..20u32 {
+            len += DisplayHint::Default.write(&mut input[len..]).unwrap();
+            len += i.write(&mut input[len..]).unwrap();
+        }
+
+        _ = len;
+
+        let logger = logger();
+        let () = log_buf(&input, logger).unwrap();
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(captured_logs.len(), 1);
+            assert_eq!(captured_logs[0].body, "012345678910111213141516171819");
+            assert_eq!(captured_logs[0].level, Level::Info);
+        });
+    }
+
     #[test]
     fn test_display_hint_default() {
         testing_logger::setup();
@@ -816,6 +3898,144 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_display_hint_ip() {
+        testing_logger::setup();
+        let (mut len, mut input) = new_log(6).unwrap();
+
+        len += "ip: ".write(&mut input[len..]).unwrap();
+        len += DisplayHint::Ip.write(&mut input[len..]).unwrap();
+        // 10.0.0.1 as u32
+        len += 167772161u32.write(&mut input[len..]).unwrap();
+        len += ", ".write(&mut input[len..]).unwrap();
+        len += DisplayHint::Ip.write(&mut input[len..]).unwrap();
+        // 2001:db8::1:1 as u16 array
+        let ipv6_arr: [u16; 8] = [
+            0x2001, 0x0db8, 0x0000, 0x0000, 0x0000, 0x0000, 0x0001, 0x0001,
+        ];
+        len += ipv6_arr.write(&mut input[len..]).unwrap();
+
+        _ = len;
+
+        let logger = logger();
+        let () = log_buf(&input, logger).unwrap();
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(captured_logs.len(), 1);
+            assert_eq!(captured_logs[0].body, "ip: 10.0.0.1, 2001:db8::1:1");
+            assert_eq!(captured_logs[0].level, Level::Info);
+        });
+    }
+
+    #[test]
+    fn test_ip_annotators() {
+        let (mut len, mut input) = new_log(4).unwrap();
+        len += DisplayHint::Ip.write(&mut input[len..]).unwrap();
+        len += 0x7f000001u32.write(&mut input[len..]).unwrap();
+        len += DisplayHint::Ipv6.write(&mut input[len..]).unwrap();
+        len += [0u16, 0, 0, 0, 0, 0, 0, 1]
+            .write(&mut input[len..])
+            .unwrap();
+
+        struct Loopback;
+
+        impl IpAnnotator for Loopback {
+            fn annotate(&self, ip: IpAddr, out: &mut dyn fmt::Write) -> fmt::Result {
+                if ip.is_loopback() {
+                    out.write_str(" (loopback)")?;
+                }
+                Ok(())
+            }
+        }
+
+        let hosts = ReverseDns::new().unwrap();
+        {
+            let mut known = hosts.0.entries.write().unwrap();
+            known.insert(
+                Ipv4Addr::LOCALHOST.into(),
+                Resolution::Resolved(Some("localhost".into()), Instant::now()),
+            );
+            // Still being resolved.
+            known.insert(Ipv6Addr::LOCALHOST.into(), Resolution::Pending);
+        }
+        let annotators: [Arc<dyn IpAnnotator>; 2] = [Arc::new(Loopback), Arc::new(hosts)];
+        let mut message = String::new();
+        decode_raw(&input[..len])
+            .unwrap()
+            .format_message(&mut message, &mut Vec::new(), &annotators)
+            .unwrap();
+        assert_eq!(message, "127.0.0.1 (loopback) (localhost)::1 (loopback)");
+    }
+
+    #[test]
+    fn test_display_hint_ipv6_arr_u32_len_4() {
+        testing_logger::setup();
+        let (mut len, mut input) = new_log(3).unwrap();
+
+        len += "ipv6: ".write(&mut input[len..]).unwrap();
+        len += DisplayHint::Ipv6.write(&mut input[len..]).unwrap();
+        // 2001:db8::1:1 as u32 array
+        let ipv6_arr: [u32; 4] = [0x20010db8, 0x00000000, 0x00000000, 0x00010001];
+        len += ipv6_arr.write(&mut input[len..]).unwrap();
+
+        _ = len;
+
+        let logger = logger();
+        let () = log_buf(&input, logger).unwrap();
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(captured_logs.len(), 1);
+            assert_eq!(captured_logs[0].body, "ipv6: 2001:db8::1:1");
+            assert_eq!(captured_logs[0].level, Level::Info);
+        });
+    }
+
+    #[test]
+    fn test_arrays() {
+        testing_logger::setup();
+        let (mut len, mut input) = new_log(6).unwrap();
+
+        len += DisplayHint::Default.write(&mut input[len..]).unwrap();
+        len += [1u32, 2, 3].write(&mut input[len..]).unwrap();
+        len += " ".write(&mut input[len..]).unwrap();
+        len += DisplayHint::LowerHex.write(&mut input[len..]).unwrap();
+        len += [0xdeu8, 0xad, 0xbe, 0xef, 0xca, 0xfe, 0xba, 0xbe]
+            .write(&mut input[len..])
+            .unwrap();
+        len += " ".write(&mut input[len..]).unwrap();
+
+        _ = len;
+
+        let logger = logger();
+        let () = log_buf(&input, logger).unwrap();
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(captured_logs.len(), 1);
+            assert_eq!(captured_logs[0].body, "[1, 2, 3] deadbeefcafebabe ");
+            assert_eq!(captured_logs[0].level, Level::Info);
+        });
+    }
+
+    #[test]
+    fn test_enum() {
+        testing_logger::setup();
+        BpfLogger::register_enum::<TcpState>();
+        let (mut len, mut input) = new_log(5).unwrap();
+
+        len += DisplayHint::Default.write(&mut input[len..]).unwrap();
+        len += TcpState::Established.write(&mut input[len..]).unwrap();
+        len += " ".write(&mut input[len..]).unwrap();
+        len += DisplayHint::Default.write(&mut input[len..]).unwrap();
+        len += Unregistered::Variant.write(&mut input[len..]).unwrap();
+
+        _ = len;
+
+        let logger = logger();
+        let () = log_buf(&input, logger).unwrap();
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(captured_logs.len(), 1);
+            assert_eq!(captured_logs[0].body, "Established 7");
+            assert_eq!(captured_logs[0].level, Level::Info);
+        });
+    }
+
     #[test]
     fn test_display_hint_lower_mac() {
         testing_logger::setup();
@@ -859,4 +4079,55 @@ mod test {
             assert_eq!(captured_logs[0].level, Level::Info);
         });
     }
+
+    #[test]
+    fn test_legacy_array_tags() {
+        testing_logger::setup();
+        let (mut len, mut input) = new_log(5).unwrap();
+
+        len += "mac: ".write(&mut input[len..]).unwrap();
+        len += DisplayHint::LowerMac.write(&mut input[len..]).unwrap();
+        // programs built against older versions tag fixed size arrays with
+        // dedicated arguments
+        let mac_arr: [u8; 6] = [0x00, 0x00, 0x5e, 0x00, 0x53, 0xaf];
+        let tag = len;
+        len += mac_arr.write(&mut input[len..]).unwrap();
+        input[tag] = Argument::ArrU8Len6 as u8;
+        len += DisplayHint::Ipv6.write(&mut input[len..]).unwrap();
+        let ipv6_arr: [u16; 8] = [0x2001, 0x0db8, 0, 0, 0, 0, 0, 1];
+        let tag = len;
+        len += ipv6_arr.write(&mut input[len..]).unwrap();
+        input[tag] = Argument::ArrU16Len8 as u8;
+
+        _ = len;
+
+        let logger = logger();
+        let () = log_buf(&input, logger).unwrap();
+        testing_logger::validate(|captured_logs| {
+            assert_eq!(captured_logs.len(), 1);
+            assert_eq!(captured_logs[0].body, "mac: 00:00:5e:00:53:af2001:db8::1");
+        });
+    }
+
+    #[test]
+    fn test_formatter() {
+        struct ShoutFormatter;
+        impl Formatter<&str> for ShoutFormatter {
+            fn format(v: &str) -> String {
+                v.to_uppercase()
+            }
+        }
+
+        assert_eq!(ShoutFormatter::format("mac"), "MAC");
+        assert_eq!(
+            UpperMacFormatter::format([0x00, 0x00, 0x5e, 0x00, 0x53, 0xaf]),
+            "00:00:5E:00:53:AF"
+        );
+    }
+
+    #[test]
+    fn test_logger_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<BpfLogger>();
+    }
 }
@@ -0,0 +1,441 @@
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    fmt, fs,
+    hash::Hash,
+    io, mem,
+    net::IpAddr,
+    os::unix::{
+        fs::{DirEntryExt, MetadataExt},
+        io::AsRawFd,
+    },
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::error;
+
+use crate::IpAnnotator;
+
+/// Maximum number of contexts cached by a [`Resolver`].
+const MAX_CACHED_CONTEXTS: usize = 1024;
+
+/// Maximum number of hostnames cached by
+/// [`BpfLoggerBuilder::reverse_dns`](crate::BpfLoggerBuilder::reverse_dns).
+const MAX_RESOLVED_HOSTS: usize = 4096;
+
+/// How long the hostnames resolved by
+/// [`BpfLoggerBuilder::reverse_dns`](crate::BpfLoggerBuilder::reverse_dns) are
+/// cached.
+const REVERSE_DNS_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// How long the addresses without hostname are cached by
+/// [`BpfLoggerBuilder::reverse_dns`](crate::BpfLoggerBuilder::reverse_dns).
+const REVERSE_DNS_NEGATIVE_TTL: Duration = Duration::from_secs(60);
+
+/// Maximum number of lookups waiting for the [`LookupWorker`].
+const MAX_QUEUED_LOOKUPS: usize = 64;
+
+/// Socket option returning the cookie of the network namespace of the socket
+/// (Linux 5.14), not exposed by `libc`.
+const SO_NETNS_COOKIE: libc::c_int = 71;
+
+/// Runs the lookups of the [`Resolver`]s one after the other on a thread of its
+/// own, so that lookups which may block don't hold up the readers.
+#[derive(Clone)]
+pub(crate) struct LookupWorker(mpsc::SyncSender<Box<dyn FnOnce() + Send>>);
+
+impl LookupWorker {
+    pub(crate) fn new() -> io::Result<LookupWorker> {
+        let (sender, receiver) = mpsc::sync_channel::<Box<dyn FnOnce() + Send>>(MAX_QUEUED_LOOKUPS);
+        // Exits once the resolvers are dropped.
+        thread::Builder::new()
+            .name("aya-log-lookup".to_owned())
+            .spawn(move || receiver.into_iter().for_each(|lookup| lookup()))?;
+        Ok(LookupWorker(sender))
+    }
+
+    /// Queues `lookup`, returning false if too many lookups are waiting.
+    fn spawn(&self, lookup: impl FnOnce() + Send + 'static) -> bool {
+        self.0.try_send(Box::new(lookup)).is_ok()
+    }
+}
+
+/// Cache of the context resolved from the ids sent with the records by lookups
+/// which may block, like walking the cgroup hierarchy. Shared by the readers.
+///
+/// Unknown ids are looked up by a [`LookupWorker`], the records are logged
+/// without their context until it is resolved. Failed lookups are cached too,
+/// for `negative_ttl`.
+pub(crate) struct Resolver<K, V> {
+    pub(crate) ttl: Duration,
+    pub(crate) negative_ttl: Duration,
+    pub(crate) max_entries: usize,
+    pub(crate) worker: LookupWorker,
+    pub(crate) resolve: Box<ResolveFn<K, V>>,
+    pub(crate) entries: RwLock<HashMap<K, Resolution<V>>>,
+}
+
+type ResolveFn<K, V> = dyn Fn(&K) -> Option<V> + Send + Sync;
+
+pub(crate) enum Resolution<V> {
+    Pending,
+    Resolved(Option<V>, Instant),
+}
+
+impl<K, V> Resolver<K, V>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    pub(crate) fn new(
+        ttl: Duration,
+        worker: LookupWorker,
+        resolve: impl Fn(&K) -> Option<V> + Send + Sync + 'static,
+    ) -> Resolver<K, V> {
+        Resolver {
+            ttl,
+            negative_ttl: ttl,
+            max_entries: MAX_CACHED_CONTEXTS,
+            worker,
+            resolve: Box::new(resolve),
+            entries: RwLock::default(),
+        }
+    }
+
+    /// Returns the context of `key` if it's known, or starts looking it up.
+    pub(crate) fn get(self: &Arc<Self>, key: &K) -> Option<V> {
+        let now = Instant::now();
+        if let Some(value) = self.cached(&self.entries.read().unwrap(), key, now) {
+            return value;
+        }
+        let mut entries = self.entries.write().unwrap();
+        // Another reader may have started the lookup in the meantime.
+        if let Some(value) = self.cached(&entries, key, now) {
+            return value;
+        }
+        if entries.len() >= self.max_entries {
+            entries.retain(|_, resolution| match resolution {
+                Resolution::Pending => true,
+                Resolution::Resolved(value, resolved) => self.fresh(value, *resolved, now),
+            });
+            if entries.len() >= self.max_entries {
+                entries.clear();
+            }
+        }
+        let (resolver, lookup_key) = (self.clone(), key.clone());
+        // Keys are looked up again on later records when too many lookups are
+        // already waiting.
+        if self.worker.spawn(move || {
+            let value = (resolver.resolve)(&lookup_key);
+            let resolution = Resolution::Resolved(value, Instant::now());
+            resolver
+                .entries
+                .write()
+                .unwrap()
+                .insert(lookup_key, resolution);
+        }) {
+            entries.insert(key.clone(), Resolution::Pending);
+        }
+        None
+    }
+
+    /// Returns the cached context of `key`, `Some(None)` if it's being looked
+    /// up or wasn't found, or `None` if it has to be looked up.
+    fn cached(
+        &self,
+        entries: &HashMap<K, Resolution<V>>,
+        key: &K,
+        now: Instant,
+    ) -> Option<Option<V>> {
+        match entries.get(key)? {
+            Resolution::Pending => Some(None),
+            Resolution::Resolved(value, resolved) => {
+                self.fresh(value, *resolved, now).then(|| value.clone())
+            }
+        }
+    }
+
+    fn fresh(&self, value: &Option<V>, resolved: Instant, now: Instant) -> bool {
+        let ttl = match value {
+            Some(_) => self.ttl,
+            None => self.negative_ttl,
+        };
+        now.duration_since(resolved) < ttl
+    }
+}
+
+/// Reads the name of a process, see
+/// [`BpfLoggerBuilder::process_names`](crate::BpfLoggerBuilder::process_names).
+pub(crate) fn process_name(pid: u32) -> Option<Arc<str>> {
+    fs::read_to_string(format!("/proc/{pid}/comm"))
+        .ok()
+        .map(|comm| comm.trim_end_matches('\n').into())
+}
+
+/// Hostnames of the IP addresses logged, resolved in the background, see
+/// [`BpfLoggerBuilder::reverse_dns`](crate::BpfLoggerBuilder::reverse_dns).
+/// Shared by the readers.
+///
+/// The lookups run on a [`LookupWorker`] of their own, so that slow DNS
+/// queries don't hold up the other lookups.
+pub(crate) struct ReverseDns(pub(crate) Arc<Resolver<IpAddr, Arc<str>>>);
+
+impl ReverseDns {
+    pub(crate) fn new() -> io::Result<ReverseDns> {
+        let resolver = Resolver::new(REVERSE_DNS_TTL, LookupWorker::new()?, |ip| {
+            lookup_host(*ip).map(Into::into)
+        });
+        Ok(ReverseDns(Arc::new(Resolver {
+            // Addresses without hostname are retried sooner, in case the
+            // lookup failed because of a transient DNS failure.
+            negative_ttl: REVERSE_DNS_NEGATIVE_TTL,
+            max_entries: MAX_RESOLVED_HOSTS,
+            ..resolver
+        })))
+    }
+}
+
+/// Appends the hostname of the address if it's known, or starts looking it up.
+impl IpAnnotator for ReverseDns {
+    fn annotate(&self, ip: IpAddr, out: &mut dyn fmt::Write) -> fmt::Result {
+        match self.0.get(&ip) {
+            Some(host) => write!(out, " ({host})"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Resolves the hostname of `ip` with `getnameinfo`, which blocks.
+fn lookup_host(ip: IpAddr) -> Option<String> {
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let len = match ip {
+        IpAddr::V4(ip) => {
+            let addr = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in) };
+            addr.sin_family = libc::AF_INET as libc::sa_family_t;
+            addr.sin_addr.s_addr = u32::from(ip).to_be();
+            mem::size_of::<libc::sockaddr_in>()
+        }
+        IpAddr::V6(ip) => {
+            let addr = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in6) };
+            addr.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            addr.sin6_addr.s6_addr = ip.octets();
+            mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+    let mut host = [0; 1025];
+    let ret = unsafe {
+        libc::getnameinfo(
+            &storage as *const _ as *const libc::sockaddr,
+            len as libc::socklen_t,
+            host.as_mut_ptr(),
+            host.len() as libc::socklen_t,
+            std::ptr::null_mut(),
+            0,
+            libc::NI_NAMEREQD,
+        )
+    };
+    if ret != 0 {
+        return None;
+    }
+    unsafe { CStr::from_ptr(host.as_ptr()) }
+        .to_str()
+        .ok()
+        .map(Into::into)
+}
+
+/// Looks for the network namespace bind mounted in `dir` whose cookie is
+/// `cookie`, see
+/// [`BpfLoggerBuilder::netns_names`](crate::BpfLoggerBuilder::netns_names).
+///
+/// Namespaces are entered from the calling thread, which is moved back to its
+/// own namespace afterwards.
+pub(crate) fn netns_name(dir: &Path, cookie: u64) -> Option<Arc<str>> {
+    let own = fs::File::open("/proc/thread-self/ns/net").ok()?;
+    let name = fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .find_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let netns = fs::File::open(entry.path()).ok()?;
+            let entered = unsafe { libc::setns(netns.as_raw_fd(), libc::CLONE_NEWNET) } == 0;
+            (entered && current_netns_cookie() == Some(cookie)).then(|| name.into())
+        });
+    if unsafe { libc::setns(own.as_raw_fd(), libc::CLONE_NEWNET) } != 0 {
+        error!(
+            "failed to return to the network namespace of the lookup thread: {}",
+            io::Error::last_os_error()
+        );
+    }
+    name
+}
+
+/// Returns the cookie of the network namespace of the calling thread.
+pub(crate) fn current_netns_cookie() -> Option<u64> {
+    let fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return None;
+    }
+    let mut cookie = 0u64;
+    let mut len = mem::size_of_val(&cookie) as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            SO_NETNS_COOKIE,
+            &mut cookie as *mut _ as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    unsafe { libc::close(fd) };
+    (ret == 0).then_some(cookie)
+}
+
+/// A cgroup resolved from its id, see
+/// [`BpfLoggerBuilder::cgroups`](crate::BpfLoggerBuilder::cgroups).
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Cgroup {
+    /// Path of the cgroup relative to the root of the hierarchy.
+    pub(crate) path: Box<str>,
+    pub(crate) container_id: Option<Box<str>>,
+    pub(crate) pod_uid: Option<Box<str>>,
+}
+
+impl Cgroup {
+    /// Looks for the cgroup whose directory under `root` has `id` as inode
+    /// number, which is how cgroup v2 ids are assigned.
+    pub(crate) fn resolve(root: &Path, id: u64) -> Option<Arc<Cgroup>> {
+        let path = find_inode(root, id)?;
+        let path = path.strip_prefix(root).ok()?;
+        let mut container_id = None;
+        let mut pod_uid = None;
+        for component in path.iter().filter_map(|c| c.to_str()) {
+            container_id = container_id.or_else(|| parse_container_id(component));
+            pod_uid = pod_uid.or_else(|| parse_pod_uid(component));
+        }
+        Some(Arc::new(Cgroup {
+            path: format!("/{}", path.display()).into(),
+            container_id: container_id.map(Into::into),
+            pod_uid: pod_uid.map(Into::into),
+        }))
+    }
+}
+
+fn find_inode(dir: &Path, ino: u64) -> Option<PathBuf> {
+    if fs::metadata(dir).ok()?.ino() == ino {
+        return Some(dir.to_path_buf());
+    }
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| matches!(entry.file_type(), Ok(t) if t.is_dir()))
+        .find_map(|entry| {
+            if entry.ino() == ino {
+                Some(entry.path())
+            } else {
+                find_inode(&entry.path(), ino)
+            }
+        })
+}
+
+/// Parses the container id from a cgroup named after it by the container
+/// runtime, like `docker-<id>.scope`, `cri-containerd-<id>.scope` or `<id>`.
+fn parse_container_id(component: &str) -> Option<&str> {
+    let name = component.strip_suffix(".scope").unwrap_or(component);
+    let id = name.rsplit('-').next()?;
+    (id.len() == 64 && id.bytes().all(|b| b.is_ascii_hexdigit())).then_some(id)
+}
+
+/// Parses the pod uid from a cgroup created by the kubelet, like
+/// `pod<uid>` or `kubepods-besteffort-pod<uid>.slice` where the dashes of the
+/// uid are replaced with underscores.
+fn parse_pod_uid(component: &str) -> Option<String> {
+    let name = component.strip_suffix(".slice").unwrap_or(component);
+    let uid = match name.rfind("-pod") {
+        Some(i) => &name[i + "-pod".len()..],
+        None => name.strip_prefix("pod")?,
+    };
+    (uid.len() == 36).then(|| uid.replace('_', "-"))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_resolver() {
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let resolver = Resolver::new(Duration::from_secs(60), LookupWorker::new().unwrap(), {
+            let lookups = lookups.clone();
+            move |id: &u64| {
+                lookups.fetch_add(1, Ordering::Relaxed);
+                (*id != 0).then(|| Arc::<str>::from(id.to_string()))
+            }
+        });
+        let resolver = Arc::new(Resolver {
+            negative_ttl: Duration::ZERO,
+            ..resolver
+        });
+        let wait = |count| {
+            let start = Instant::now();
+            while lookups.load(Ordering::Relaxed) < count
+                || resolver
+                    .entries
+                    .read()
+                    .unwrap()
+                    .values()
+                    .any(|resolution| matches!(resolution, Resolution::Pending))
+            {
+                assert!(start.elapsed() < Duration::from_secs(10));
+                thread::sleep(Duration::from_millis(1));
+            }
+        };
+
+        // Records are logged without the context until it's resolved.
+        assert_eq!(resolver.get(&1), None);
+        assert_eq!(resolver.get(&0), None);
+        wait(2);
+        assert_eq!(resolver.get(&1).as_deref(), Some("1"));
+        assert_eq!(lookups.load(Ordering::Relaxed), 2);
+
+        // Failed lookups expire after the negative time to live.
+        assert_eq!(resolver.get(&0), None);
+        wait(3);
+        assert_eq!(resolver.get(&1).as_deref(), Some("1"));
+        assert_eq!(lookups.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_netns_name() {
+        // SO_NETNS_COOKIE requires Linux 5.14.
+        let cookie = match current_netns_cookie() {
+            Some(cookie) => cookie,
+            None => return,
+        };
+        let dir = std::env::temp_dir().join(format!("aya-log-netns-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        std::os::unix::fs::symlink("/proc/self/ns/net", dir.join("host")).unwrap();
+        let names = thread::spawn({
+            let dir = dir.clone();
+            move || {
+                let names = [cookie, cookie + 1].map(|cookie| netns_name(&dir, cookie));
+                (names, current_netns_cookie())
+            }
+        })
+        .join()
+        .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let ([found, unknown], after) = names;
+        // Entering a namespace, even the current one, requires CAP_SYS_ADMIN.
+        if unsafe { libc::geteuid() } == 0 {
+            assert_eq!(found.as_deref(), Some("host"));
+        }
+        assert_eq!(unknown, None);
+        assert_eq!(after, Some(cookie));
+    }
+}
@@ -0,0 +1,271 @@
+use std::{
+    cmp::{Ordering as CmpOrdering, Reverse},
+    collections::BinaryHeap,
+    mem,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::{Log, Record};
+
+#[cfg(feature = "async_tokio")]
+use crate::BlockingSink;
+use crate::{record_dropped, DropPolicy, KeyValues, MemoryBudget, Stats};
+
+/// Merges the records of all CPUs to log them in the order of their
+/// timestamps, see
+/// [`BpfLoggerBuilder::reorder_window`](crate::BpfLoggerBuilder::reorder_window).
+pub(crate) struct Reorderer {
+    pub(crate) window: Duration,
+    pub(crate) logger: Arc<dyn Log>,
+    pub(crate) static_fields: Arc<[(String, String)]>,
+    pub(crate) budget: Option<Arc<MemoryBudget>>,
+    pub(crate) stats: Arc<Stats>,
+    /// Logs the records once they leave the window, see
+    /// [`BpfLoggerBuilder::blocking_sink`](crate::BpfLoggerBuilder::blocking_sink).
+    #[cfg(feature = "async_tokio")]
+    pub(crate) sink: Option<Arc<BlockingSink>>,
+    pub(crate) pending: Mutex<ReorderQueue>,
+}
+
+#[derive(Default)]
+pub(crate) struct ReorderQueue {
+    pub(crate) records: BinaryHeap<Reverse<PendingRecord>>,
+    /// Keeps the records of equal timestamps in the order they were read.
+    pub(crate) next_seq: u64,
+}
+
+impl Reorderer {
+    pub(crate) fn new(
+        window: Duration,
+        logger: Arc<dyn Log>,
+        static_fields: Arc<[(String, String)]>,
+        budget: Option<Arc<MemoryBudget>>,
+        stats: Arc<Stats>,
+    ) -> Reorderer {
+        Reorderer {
+            window,
+            logger,
+            static_fields,
+            budget,
+            stats,
+            #[cfg(feature = "async_tokio")]
+            sink: None,
+            pending: Mutex::default(),
+        }
+    }
+
+    pub(crate) fn push(&self, mut record: PendingRecord) {
+        let mut pending = self.pending.lock().unwrap();
+        if let Some(budget) = &self.budget {
+            let size = record.size();
+            while !budget.reserve(size) {
+                let evicted = match budget.policy {
+                    DropPolicy::Newest => None,
+                    DropPolicy::Oldest => pending.records.pop(),
+                };
+                record_dropped(&self.stats, &*self.logger);
+                match evicted {
+                    Some(Reverse(evicted)) => budget.release(evicted.size()),
+                    None => return,
+                }
+            }
+        }
+        record.seq = pending.next_seq;
+        pending.next_seq += 1;
+        pending.records.push(Reverse(record));
+    }
+
+    /// Logs the records held for the whole window by `now`, along with the
+    /// records with earlier timestamps.
+    pub(crate) fn flush(&self, now: Instant) {
+        // The lock is held while logging so that concurrent flushes don't
+        // interleave their records.
+        let mut pending = self.pending.lock().unwrap();
+        while let Some(Reverse(record)) = pending.records.peek() {
+            if now.saturating_duration_since(record.read) < self.window {
+                break;
+            }
+            let Reverse(record) = pending.records.pop().unwrap();
+            if let Some(budget) = &self.budget {
+                budget.release(record.size());
+            }
+            #[cfg(feature = "async_tokio")]
+            if let Some(sink) = &self.sink {
+                sink.push(record);
+                continue;
+            }
+            record.log(&*self.logger, &self.static_fields);
+        }
+    }
+}
+
+/// A formatted record held by a [`Reorderer`].
+pub(crate) struct PendingRecord {
+    pub(crate) time: SystemTime,
+    pub(crate) read: Instant,
+    pub(crate) seq: u64,
+    pub(crate) level: log::Level,
+    pub(crate) target: Arc<str>,
+    pub(crate) module: Option<Arc<str>>,
+    pub(crate) file: Option<Arc<str>>,
+    pub(crate) line: Option<u32>,
+    pub(crate) message: String,
+    pub(crate) key_values: Vec<(&'static str, OwnedValue)>,
+}
+
+impl PendingRecord {
+    /// The number of bytes held by the record, not counting the interned
+    /// target, module and file.
+    pub(crate) fn size(&self) -> usize {
+        mem::size_of::<PendingRecord>()
+            + self.message.capacity()
+            + self.key_values.capacity() * mem::size_of::<(&str, OwnedValue)>()
+            + self
+                .key_values
+                .iter()
+                .map(|(_, value)| match value {
+                    OwnedValue::U64(_) => 0,
+                    OwnedValue::Str(s) => s.len(),
+                })
+                .sum::<usize>()
+    }
+
+    pub(crate) fn log(&self, logger: &dyn Log, static_fields: &[(String, String)]) {
+        let key_values = self
+            .key_values
+            .iter()
+            .map(|(key, value)| Some((*key, value.to_value())))
+            .collect::<Vec<_>>();
+        logger.log(
+            &Record::builder()
+                .args(format_args!("{}", self.message))
+                .target(&self.target)
+                .level(self.level)
+                .module_path(self.module.as_deref())
+                .file(self.file.as_deref())
+                .line(self.line)
+                .key_values(&KeyValues(&key_values, static_fields))
+                .build(),
+        );
+        logger.flush();
+    }
+}
+
+impl PartialEq for PendingRecord {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == CmpOrdering::Equal
+    }
+}
+
+impl Eq for PendingRecord {}
+
+impl PartialOrd for PendingRecord {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PendingRecord {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        (self.time, self.seq).cmp(&(other.time, other.seq))
+    }
+}
+
+/// The value of a key-value of a [`PendingRecord`], which outlives the
+/// buffers the record was decoded from.
+pub(crate) enum OwnedValue {
+    U64(u64),
+    Str(Arc<str>),
+}
+
+impl OwnedValue {
+    pub(crate) fn new(value: &log::kv::Value<'_>) -> Option<OwnedValue> {
+        value
+            .to_u64()
+            .map(OwnedValue::U64)
+            .or_else(|| value.to_borrowed_str().map(|s| OwnedValue::Str(s.into())))
+    }
+
+    pub(crate) fn to_value(&self) -> log::kv::Value<'_> {
+        match self {
+            OwnedValue::U64(v) => log::kv::Value::from(*v),
+            OwnedValue::Str(s) => log::kv::Value::from(&**s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::UNIX_EPOCH,
+    };
+
+    use log::Level;
+
+    use super::*;
+
+    #[test]
+    fn test_max_buffered_bytes() {
+        #[derive(Default)]
+        struct Capture(Mutex<Vec<String>>);
+
+        impl Log for Capture {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &Record) {
+                self.0.lock().unwrap().push(record.args().to_string());
+            }
+
+            fn flush(&self) {}
+        }
+
+        let record = |s: u64| PendingRecord {
+            time: UNIX_EPOCH + Duration::from_secs(s),
+            read: Instant::now(),
+            seq: 0,
+            level: Level::Info,
+            target: "test".into(),
+            module: None,
+            file: None,
+            line: None,
+            message: s.to_string(),
+            key_values: vec![("program_id", OwnedValue::U64(s))],
+        };
+        // Room for two records out of three.
+        let size = record(0).size();
+        for (policy, expected) in [
+            (DropPolicy::Newest, ["1", "2"]),
+            (DropPolicy::Oldest, ["2", "3"]),
+        ] {
+            let logger = Arc::new(Capture::default());
+            let stats = Arc::new(Stats::default());
+            let budget = Arc::new(MemoryBudget {
+                max: 2 * size,
+                used: AtomicUsize::new(0),
+                policy,
+            });
+            let reorder = Reorderer::new(
+                Duration::ZERO,
+                logger.clone(),
+                Arc::default(),
+                Some(budget.clone()),
+                stats.clone(),
+            );
+            for s in [1, 2, 3] {
+                reorder.push(record(s));
+            }
+            assert_eq!(stats.dropped_records.load(Ordering::Relaxed), 1);
+            assert_eq!(budget.used.load(Ordering::Relaxed), 2 * size);
+
+            reorder.flush(Instant::now());
+            let logged = logger.0.lock().unwrap();
+            assert!(logged[0].starts_with("dropping log records"), "{logged:?}");
+            assert_eq!(logged[1..], expected.map(String::from));
+            assert_eq!(budget.used.load(Ordering::Relaxed), 0);
+        }
+    }
+}
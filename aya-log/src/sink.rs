@@ -0,0 +1,194 @@
+use std::{
+    collections::VecDeque,
+    mem,
+    sync::{Arc, Mutex},
+};
+
+use log::Log;
+
+use crate::{record_dropped, DropPolicy, MemoryBudget, PendingRecord, Stats};
+
+/// Logs the records from tokio's blocking pool, see
+/// [`BpfLoggerBuilder::blocking_sink`](crate::BpfLoggerBuilder::blocking_sink).
+pub(crate) struct BlockingSink {
+    pub(crate) logger: Arc<dyn Log>,
+    pub(crate) static_fields: Arc<[(String, String)]>,
+    pub(crate) max_in_flight: usize,
+    pub(crate) policy: DropPolicy,
+    pub(crate) budget: Option<Arc<MemoryBudget>>,
+    pub(crate) stats: Arc<Stats>,
+    pub(crate) runtime: tokio::runtime::Handle,
+    pub(crate) queue: Mutex<SinkQueue>,
+}
+
+#[derive(Default)]
+pub(crate) struct SinkQueue {
+    pub(crate) records: VecDeque<PendingRecord>,
+    /// The records queued or being logged.
+    pub(crate) in_flight: usize,
+    /// Whether a blocking task is logging the records.
+    pub(crate) draining: bool,
+}
+
+impl BlockingSink {
+    pub(crate) fn push(self: &Arc<Self>, record: PendingRecord) {
+        if self.enqueue(record) {
+            let sink = self.clone();
+            self.runtime.spawn_blocking(move || sink.drain());
+        }
+    }
+
+    /// Queues `record`, dropping records according to the [`DropPolicy`] when
+    /// too many are in flight. Returns whether a task has to be started to log
+    /// the records.
+    pub(crate) fn enqueue(&self, record: PendingRecord) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+        let size = record.size();
+        loop {
+            let reserved = queue.in_flight < self.max_in_flight
+                && match &self.budget {
+                    Some(budget) => budget.reserve(size),
+                    None => true,
+                };
+            if reserved {
+                break;
+            }
+            if self.policy == DropPolicy::Newest {
+                record_dropped(&self.stats, &*self.logger);
+                return false;
+            }
+            match queue.records.pop_front() {
+                Some(evicted) => {
+                    record_dropped(&self.stats, &*self.logger);
+                    queue.in_flight -= 1;
+                    if let Some(budget) = &self.budget {
+                        budget.release(evicted.size());
+                    }
+                }
+                // The older records are being logged and can't be dropped,
+                // the newest one is kept over the limits until they are.
+                None => {
+                    if let Some(budget) = &self.budget {
+                        budget.resize(0, size);
+                    }
+                    break;
+                }
+            }
+        }
+        queue.records.push_back(record);
+        queue.in_flight += 1;
+        !mem::replace(&mut queue.draining, true)
+    }
+
+    /// Logs the queued records until the queue is empty.
+    pub(crate) fn drain(&self) {
+        let mut queue = self.queue.lock().unwrap();
+        while let Some(record) = queue.records.pop_front() {
+            drop(queue);
+            record.log(&*self.logger, &self.static_fields);
+            if let Some(budget) = &self.budget {
+                budget.release(record.size());
+            }
+            queue = self.queue.lock().unwrap();
+            queue.in_flight -= 1;
+        }
+        queue.draining = false;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::atomic::Ordering,
+        time::{Duration, Instant, UNIX_EPOCH},
+    };
+
+    use log::{Level, Record};
+
+    use super::*;
+
+    #[test]
+    fn test_blocking_sink() {
+        #[derive(Default)]
+        struct Capture(Mutex<Vec<String>>);
+
+        impl Log for Capture {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &Record) {
+                self.0.lock().unwrap().push(record.args().to_string());
+            }
+
+            fn flush(&self) {}
+        }
+
+        let record = |s: u64| PendingRecord {
+            time: UNIX_EPOCH + Duration::from_secs(s),
+            read: Instant::now(),
+            seq: 0,
+            level: Level::Info,
+            target: "test".into(),
+            module: None,
+            file: None,
+            line: None,
+            message: s.to_string(),
+            key_values: Vec::new(),
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        for (policy, expected) in [
+            (DropPolicy::Newest, ["1", "2"]),
+            (DropPolicy::Oldest, ["2", "3"]),
+        ] {
+            let logger = Arc::new(Capture::default());
+            let stats = Arc::new(Stats::default());
+            let sink = BlockingSink {
+                logger: logger.clone(),
+                static_fields: Arc::default(),
+                max_in_flight: 2,
+                policy,
+                budget: None,
+                stats: stats.clone(),
+                runtime: runtime.handle().clone(),
+                queue: Mutex::default(),
+            };
+            // Only the first record starts a task, which is run below.
+            assert!(sink.enqueue(record(1)));
+            assert!(!sink.enqueue(record(2)));
+            assert!(!sink.enqueue(record(3)));
+            assert_eq!(stats.dropped_records.load(Ordering::Relaxed), 1);
+
+            sink.drain();
+            let logged = logger.0.lock().unwrap();
+            assert!(logged[0].starts_with("dropping log records"), "{logged:?}");
+            assert_eq!(logged[1..], expected.map(String::from));
+            let queue = sink.queue.lock().unwrap();
+            assert_eq!((queue.in_flight, queue.draining), (0, false));
+        }
+
+        // The queue is empty while the records in flight are being logged
+        for (policy, queued, dropped) in [(DropPolicy::Newest, 0, 1), (DropPolicy::Oldest, 1, 0)] {
+            let stats = Arc::new(Stats::default());
+            let sink = BlockingSink {
+                logger: Arc::new(Capture::default()),
+                static_fields: Arc::default(),
+                max_in_flight: 2,
+                policy,
+                budget: None,
+                stats: stats.clone(),
+                runtime: runtime.handle().clone(),
+                queue: Mutex::new(SinkQueue {
+                    in_flight: 2,
+                    draining: true,
+                    ..Default::default()
+                }),
+            };
+            assert!(!sink.enqueue(record(3)));
+            assert_eq!(sink.queue.lock().unwrap().records.len(), queued);
+            assert_eq!(stats.dropped_records.load(Ordering::Relaxed), dropped);
+        }
+    }
+}
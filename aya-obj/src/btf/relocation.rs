@@ -2,6 +2,7 @@ use core::{mem, ptr, str::FromStr};
 
 use alloc::{
     borrow::ToOwned,
+    boxed::Box,
     format,
     string::{String, ToString},
     vec,
@@ -134,6 +135,34 @@ enum RelocationError {
     /// BTF error
     #[error("invalid BTF")]
     BtfError(#[from] BtfError),
+
+    /// Applying a relocation failed
+    #[error(transparent)]
+    RelocationFailed(Box<FailedRelocation>),
+}
+
+/// A relocation that couldn't be applied, with the context needed to debug it
+#[derive(thiserror::Error, Debug)]
+#[error(
+    "relocation #{relocation_number} of kind `{relocation_kind:?}` for type `{}` with access string `{access_str}` at instruction #{ins_index} failed, target candidates: {candidates:?}",
+    err_type_name(.type_name)
+)]
+struct FailedRelocation {
+    /// The relocation number
+    relocation_number: usize,
+    /// The relocation kind
+    relocation_kind: RelocationKind,
+    /// The index of the relocated instruction
+    ins_index: usize,
+    /// The local type name
+    type_name: Option<String>,
+    /// The access string
+    access_str: String,
+    /// The candidate target types, with their type ids
+    candidates: Vec<String>,
+    /// The original error
+    #[source]
+    error: RelocationError,
 }
 
 fn err_type_name(name: &Option<String>) -> String {
@@ -212,6 +241,19 @@ impl Relocation {
 impl Object {
     /// Relocates programs inside this object file with loaded BTF info.
     pub fn relocate_btf(&mut self, target_btf: &Btf) -> Result<(), BtfRelocationError> {
+        self.relocate_btf_programs(target_btf, None)
+    }
+
+    /// Relocates the programs called `programs` inside this object file with
+    /// loaded BTF info, or all of them if `programs` is `None`.
+    ///
+    /// The relocations of the other programs are skipped, so they can be
+    /// removed from [`Object::programs`] beforehand.
+    pub fn relocate_btf_programs(
+        &mut self,
+        target_btf: &Btf,
+        programs: Option<&[&str]>,
+    ) -> Result<(), BtfRelocationError> {
         let (local_btf, btf_ext) = match (&self.btf, &self.btf_ext) {
             (Some(btf), Some(btf_ext)) => (btf, btf_ext),
             _ => return Ok(()),
@@ -232,6 +274,11 @@ impl Object {
                 Err(_) => continue,
             };
             let section_name = program_section.name();
+            if let Some(programs) = programs {
+                if !programs.contains(&section_name) {
+                    continue;
+                }
+            }
 
             let program = self
                 .programs
@@ -274,71 +321,113 @@ fn relocate_btf_program<'target>(
             });
         }
 
-        let local_ty = local_btf.type_by_id(rel.type_id)?;
-        let local_name = &*local_btf.type_name(local_ty)?;
-        let access_str = &*local_btf.string_at(rel.access_str_offset)?;
-        let local_spec = AccessSpec::new(local_btf, rel.type_id, access_str, *rel)?;
+        relocate_btf_relocation(program, rel, local_btf, target_btf, candidates_cache).map_err(
+            |error| {
+                let type_name = local_btf
+                    .type_by_id(rel.type_id)
+                    .and_then(|ty| local_btf.type_name(ty))
+                    .ok()
+                    .map(|name| name.into_owned());
+                let access_str = local_btf
+                    .string_at(rel.access_str_offset)
+                    .map(|s| s.into_owned())
+                    .unwrap_or_default();
+                let candidates = candidates_cache
+                    .get(&rel.type_id)
+                    .map(|candidates| {
+                        candidates
+                            .iter()
+                            .map(|c| format!("{} [{}]", c.name, c.type_id))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                RelocationError::RelocationFailed(Box::new(FailedRelocation {
+                    relocation_number: rel.number,
+                    relocation_kind: rel.kind,
+                    ins_index,
+                    type_name,
+                    access_str,
+                    candidates,
+                    error,
+                }))
+            },
+        )?;
+    }
 
-        let matches = match rel.kind {
-            RelocationKind::TypeIdLocal => Vec::new(), // we don't need to look at target types to relocate this value
-            _ => {
-                let candidates = match candidates_cache.get(&rel.type_id) {
-                    Some(cands) => cands,
-                    None => {
-                        candidates_cache.insert(
-                            rel.type_id,
-                            find_candidates(local_ty, local_name, target_btf)?,
-                        );
-                        candidates_cache.get(&rel.type_id).unwrap()
-                    }
-                };
+    Ok(())
+}
 
-                let mut matches = Vec::new();
-                for candidate in candidates {
-                    if let Some(candidate_spec) = match_candidate(&local_spec, candidate)? {
-                        let comp_rel =
-                            ComputedRelocation::new(rel, &local_spec, Some(&candidate_spec))?;
-                        matches.push((candidate.name.clone(), candidate_spec, comp_rel));
-                    }
+fn relocate_btf_relocation<'target>(
+    program: &mut Program,
+    rel: &Relocation,
+    local_btf: &Btf,
+    target_btf: &'target Btf,
+    candidates_cache: &mut HashMap<u32, Vec<Candidate<'target>>>,
+) -> Result<(), RelocationError> {
+    let local_ty = local_btf.type_by_id(rel.type_id)?;
+    let local_name = &*local_btf.type_name(local_ty)?;
+    let access_str = &*local_btf.string_at(rel.access_str_offset)?;
+    let local_spec = AccessSpec::new(local_btf, rel.type_id, access_str, *rel)?;
+
+    let matches = match rel.kind {
+        RelocationKind::TypeIdLocal => Vec::new(), // we don't need to look at target types to relocate this value
+        _ => {
+            let candidates = match candidates_cache.get(&rel.type_id) {
+                Some(cands) => cands,
+                None => {
+                    candidates_cache.insert(
+                        rel.type_id,
+                        find_candidates(local_ty, local_name, target_btf)?,
+                    );
+                    candidates_cache.get(&rel.type_id).unwrap()
                 }
+            };
 
-                matches
+            let mut matches = Vec::new();
+            for candidate in candidates {
+                if let Some(candidate_spec) = match_candidate(&local_spec, candidate)? {
+                    let comp_rel =
+                        ComputedRelocation::new(rel, &local_spec, Some(&candidate_spec))?;
+                    matches.push((candidate.name.clone(), candidate_spec, comp_rel));
+                }
             }
-        };
 
-        let comp_rel = if !matches.is_empty() {
-            let mut matches = matches.into_iter();
-            let (_, target_spec, target_comp_rel) = matches.next().unwrap();
+            matches
+        }
+    };
 
-            // if there's more than one candidate, make sure that they all resolve to the
-            // same value, else the relocation is ambiguous and can't be applied
-            let conflicts = matches
-                .filter_map(|(cand_name, cand_spec, cand_comp_rel)| {
-                    if cand_spec.bit_offset != target_spec.bit_offset
-                        || cand_comp_rel.target.value != target_comp_rel.target.value
-                    {
-                        Some(cand_name)
-                    } else {
-                        None
-                    }
-                })
-                .collect::<Vec<_>>();
-            if !conflicts.is_empty() {
-                return Err(RelocationError::ConflictingCandidates {
-                    type_name: local_name.to_string(),
-                    candidates: conflicts,
-                });
-            }
-            target_comp_rel
-        } else {
-            // there are no candidate matches and therefore no target_spec. This might mean
-            // that matching failed, or that the relocation can be applied looking at local
-            // types only (eg with EnumVariantExists, FieldExists etc)
-            ComputedRelocation::new(rel, &local_spec, None)?
-        };
+    let comp_rel = if !matches.is_empty() {
+        let mut matches = matches.into_iter();
+        let (_, target_spec, target_comp_rel) = matches.next().unwrap();
+
+        // if there's more than one candidate, make sure that they all resolve to the
+        // same value, else the relocation is ambiguous and can't be applied
+        let conflicts = matches
+            .filter_map(|(cand_name, cand_spec, cand_comp_rel)| {
+                if cand_spec.bit_offset != target_spec.bit_offset
+                    || cand_comp_rel.target.value != target_comp_rel.target.value
+                {
+                    Some(cand_name)
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        if !conflicts.is_empty() {
+            return Err(RelocationError::ConflictingCandidates {
+                type_name: local_name.to_string(),
+                candidates: conflicts,
+            });
+        }
+        target_comp_rel
+    } else {
+        // there are no candidate matches and therefore no target_spec. This might mean
+        // that matching failed, or that the relocation can be applied looking at local
+        // types only (eg with EnumVariantExists, FieldExists etc)
+        ComputedRelocation::new(rel, &local_spec, None)?
+    };
 
-        comp_rel.apply(program, rel, local_btf, target_btf)?;
-    }
+    comp_rel.apply(program, rel, local_btf, target_btf)?;
 
     Ok(())
 }
@@ -1163,3 +1252,105 @@ impl ComputedRelocation {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::ffi::CString;
+    use object::SectionIndex;
+
+    use super::*;
+    use crate::{
+        btf::{Int, IntEncoding},
+        Function, KernelVersion,
+    };
+
+    fn new_program() -> Program {
+        Program {
+            license: CString::new("GPL").unwrap(),
+            kernel_version: KernelVersion::Any,
+            section: ProgramSection::KProbe {
+                name: "prog".to_owned(),
+            },
+            function: Function {
+                address: 0,
+                name: "prog".to_owned(),
+                section_index: SectionIndex(0),
+                section_offset: 0,
+                instructions: vec![unsafe { mem::zeroed::<bpf_insn>() }; 2],
+                func_info: Default::default(),
+                line_info: Default::default(),
+                func_info_rec_size: 0,
+                line_info_rec_size: 0,
+            },
+        }
+    }
+
+    // struct <name> { int a; int b; }, with `b` at `b_offset` bits.
+    fn add_struct(btf: &mut Btf, name: &str, b_offset: u32) -> u32 {
+        let int_name = btf.add_string("int".to_string());
+        let int = btf.add_type(BtfType::Int(Int::new(int_name, 4, IntEncoding::Signed, 0)));
+        let members = vec![
+            BtfMember::new(btf.add_string("a".to_string()), int, 0),
+            BtfMember::new(btf.add_string("b".to_string()), int, b_offset),
+        ];
+        let name = btf.add_string(name.to_string());
+        btf.add_type(BtfType::Struct(Struct::new(name, members, 16)))
+    }
+
+    #[test]
+    fn test_failed_relocation() {
+        let mut local_btf = Btf::new();
+        let type_id = add_struct(&mut local_btf, "foo", 32);
+        let access_str_offset = local_btf.add_string("0:1".to_string());
+
+        // Both candidates match `foo`, but `b` isn't at the same offset.
+        let mut target_btf = Btf::new();
+        let foo = add_struct(&mut target_btf, "foo", 32);
+        let foo_v2 = add_struct(&mut target_btf, "foo___v2", 64);
+
+        let rel = Relocation {
+            kind: RelocationKind::FieldByteOffset,
+            ins_offset: mem::size_of::<bpf_insn>(),
+            type_id,
+            access_str_offset,
+            number: 3,
+        };
+        let mut program = new_program();
+        let err = relocate_btf_program(
+            &mut program,
+            &[rel],
+            &local_btf,
+            &target_btf,
+            &mut HashMap::new(),
+        )
+        .unwrap_err();
+
+        let RelocationError::RelocationFailed(failed) = &err else {
+            panic!("unexpected error: {err:?}");
+        };
+        assert_eq!(failed.relocation_number, 3);
+        assert!(matches!(
+            failed.relocation_kind,
+            RelocationKind::FieldByteOffset
+        ));
+        assert_eq!(failed.ins_index, 1);
+        assert_eq!(failed.type_name.as_deref(), Some("foo"));
+        assert_eq!(failed.access_str, "0:1");
+        assert_eq!(
+            failed.candidates,
+            [format!("foo [{foo}]"), format!("foo___v2 [{foo_v2}]")]
+        );
+        assert!(matches!(
+            &failed.error,
+            RelocationError::ConflictingCandidates { type_name, candidates }
+                if type_name == "foo" && candidates == &["foo___v2"]
+        ));
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "relocation #3 of kind `FieldByteOffset` for type `foo` with access string `0:1` \
+                 at instruction #1 failed, target candidates: [\"foo [{foo}]\", \"foo___v2 [{foo_v2}]\"]"
+            )
+        );
+    }
+}
@@ -273,6 +273,11 @@ impl Func {
         (self.info & 0xFFF).into()
     }
 
+    /// Returns the type id of the function prototype
+    pub fn btf_type(&self) -> u32 {
+        self.btf_type
+    }
+
     pub(crate) fn set_linkage(&mut self, linkage: FuncLinkage) {
         self.info = (self.info & 0xFFFF0000) | (linkage as u32) & 0xFFFF;
     }
@@ -483,14 +488,38 @@ impl Enum64 {
     }
 }
 
+/// A member of a [Struct] or [Union]
 #[repr(C)]
 #[derive(Clone, Debug)]
-pub(crate) struct BtfMember {
+pub struct BtfMember {
     pub(crate) name_offset: u32,
     pub(crate) btf_type: u32,
     pub(crate) offset: u32,
 }
 
+impl BtfMember {
+    /// Creates a member named by `name_offset` of type `btf_type`, placed at
+    /// `offset` bits from the start of its struct or union
+    pub fn new(name_offset: u32, btf_type: u32, offset: u32) -> Self {
+        BtfMember {
+            name_offset,
+            btf_type,
+            offset,
+        }
+    }
+
+    /// Returns the offset of the member name in the string section, see
+    /// [Btf::string_at](crate::btf::Btf::string_at)
+    pub fn name_offset(&self) -> u32 {
+        self.name_offset
+    }
+
+    /// Returns the type id of the member
+    pub fn btf_type(&self) -> u32 {
+        self.btf_type
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Debug)]
 pub struct Struct {
@@ -522,7 +551,7 @@ impl Struct {
         mem::size_of::<Fwd>() + mem::size_of::<BtfMember>() * self.members.len()
     }
 
-    pub(crate) fn new(name_offset: u32, members: Vec<BtfMember>, size: u32) -> Self {
+    pub fn new(name_offset: u32, members: Vec<BtfMember>, size: u32) -> Self {
         let mut info = (BtfKind::Struct as u32) << 24;
         info |= (members.len() as u32) & 0xFFFF;
         Struct {
@@ -636,6 +665,16 @@ impl Array {
         mem::size_of::<Self>()
     }
 
+    /// Returns the type id of the elements
+    pub fn element_type(&self) -> u32 {
+        self.array.element_type
+    }
+
+    /// Returns the number of elements
+    pub fn nelems(&self) -> u32 {
+        self.array.len
+    }
+
     #[cfg(test)]
     pub(crate) fn new(name_offset: u32, element_type: u32, index_type: u32, len: u32) -> Self {
         let info = (BtfKind::Array as u32) << 24;
@@ -699,6 +738,17 @@ impl FuncProto {
             params,
         }
     }
+
+    /// Returns the parameters of the function. The last parameter of a
+    /// variadic function has type id `0`.
+    pub fn params(&self) -> &[BtfParam] {
+        &self.params
+    }
+
+    /// Returns the type id of the return value, `0` for `void`
+    pub fn return_type(&self) -> u32 {
+        self.return_type
+    }
 }
 
 #[repr(u32)]
@@ -1103,7 +1153,8 @@ impl BtfType {
         }
     }
 
-    pub(crate) fn size(&self) -> Option<u32> {
+    /// Returns the size in bytes of sized types
+    pub fn size(&self) -> Option<u32> {
         match self {
             BtfType::Int(t) => Some(t.size),
             BtfType::Float(t) => Some(t.size),
@@ -1117,7 +1168,8 @@ impl BtfType {
         }
     }
 
-    pub(crate) fn btf_type(&self) -> Option<u32> {
+    /// Returns the id of the type referenced by modifiers, pointers, typedefs and variables
+    pub fn btf_type(&self) -> Option<u32> {
         match self {
             BtfType::Const(t) => Some(t.btf_type),
             BtfType::Volatile(t) => Some(t.btf_type),
@@ -1158,7 +1210,8 @@ impl BtfType {
         }
     }
 
-    pub(crate) fn name_offset(&self) -> u32 {
+    /// Returns the offset of the type name in the string section
+    pub fn name_offset(&self) -> u32 {
         match self {
             BtfType::Unknown => 0,
             BtfType::Fwd(t) => t.name_offset,
@@ -1183,7 +1236,8 @@ impl BtfType {
         }
     }
 
-    pub(crate) fn kind(&self) -> BtfKind {
+    /// Returns the kind of the type
+    pub fn kind(&self) -> BtfKind {
         match self {
             BtfType::Unknown => BtfKind::Unknown,
             BtfType::Fwd(t) => t.kind(),
@@ -1212,7 +1266,8 @@ impl BtfType {
         matches!(self, BtfType::Struct(_) | BtfType::Union(_))
     }
 
-    pub(crate) fn members(&self) -> Option<impl Iterator<Item = &BtfMember>> {
+    /// Returns the members of structs and unions
+    pub fn members(&self) -> Option<impl Iterator<Item = &BtfMember>> {
         match self {
             BtfType::Struct(t) => Some(t.members.iter()),
             BtfType::Union(t) => Some(t.members.iter()),
@@ -1220,7 +1275,9 @@ impl BtfType {
         }
     }
 
-    pub(crate) fn member_bit_field_size(&self, member: &BtfMember) -> Option<usize> {
+    /// Returns the size in bits of a bitfield member of a struct or union, or
+    /// 0 if the member isn't a bitfield
+    pub fn member_bit_field_size(&self, member: &BtfMember) -> Option<usize> {
         match self {
             BtfType::Struct(t) => Some(t.member_bit_field_size(member)),
             BtfType::Union(t) => Some(t.member_bit_field_size(member)),
@@ -1228,7 +1285,8 @@ impl BtfType {
         }
     }
 
-    pub(crate) fn member_bit_offset(&self, member: &BtfMember) -> Option<usize> {
+    /// Returns the offset in bits of a member of a struct or union
+    pub fn member_bit_offset(&self, member: &BtfMember) -> Option<usize> {
         match self {
             BtfType::Struct(t) => Some(t.member_bit_offset(member)),
             BtfType::Union(t) => Some(t.member_bit_offset(member)),
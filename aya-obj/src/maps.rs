@@ -72,6 +72,7 @@ pub struct BtfMapDef {
     pub(crate) value_size: u32,
     pub(crate) max_entries: u32,
     pub(crate) map_flags: u32,
+    pub(crate) map_extra: u64,
     pub(crate) pinning: PinningType,
     /// BTF type id of the map key
     pub btf_key_type_id: u32,
@@ -200,6 +201,26 @@ impl Map {
         }
     }
 
+    /// Sets the map flags
+    pub fn set_map_flags(&mut self, v: u32) {
+        match self {
+            Map::Legacy(m) => m.def.map_flags = v,
+            Map::Btf(m) => m.def.map_flags = v,
+        }
+    }
+
+    /// Returns the type specific extra data of the map, like the number of
+    /// hash functions of a bloom filter.
+    ///
+    /// This can only be set by maps defined in the `.maps` section, it is
+    /// always 0 for legacy maps.
+    pub fn map_extra(&self) -> u64 {
+        match self {
+            Map::Legacy(_) => 0,
+            Map::Btf(m) => m.def.map_extra,
+        }
+    }
+
     /// Returns the pinning type of the map
     pub fn pinning(&self) -> PinningType {
         match self {
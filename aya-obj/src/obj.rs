@@ -29,7 +29,9 @@ use crate::{
     btf::{Btf, BtfError, BtfExt, BtfType},
     generated::{bpf_insn, bpf_map_info, bpf_map_type::BPF_MAP_TYPE_ARRAY, BPF_F_RDONLY_PROG},
     maps::{bpf_map_def, BtfMapDef, PinningType},
-    programs::{CgroupSockAddrAttachType, CgroupSockAttachType, CgroupSockoptAttachType},
+    programs::{
+        CgroupSockAddrAttachType, CgroupSockAttachType, CgroupSockoptAttachType, XdpAttachType,
+    },
 };
 use core::slice::from_raw_parts_mut;
 
@@ -112,6 +114,17 @@ pub struct Function {
     pub line_info_rec_size: usize,
 }
 
+/// The location of a global variable inside the map backing its section
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobalVariable {
+    /// The name of the `.bss`, `.data` or `.rodata` map holding the variable
+    pub map_name: String,
+    /// The offset of the variable in the map value
+    pub offset: usize,
+    /// The size of the variable
+    pub size: usize,
+}
+
 /// Section types containing eBPF programs
 ///
 /// # Section Name Parsing
@@ -159,21 +172,13 @@ pub struct Function {
 /// Currently, the following section names are not supported yet:
 /// - `flow_dissector`: `BPF_PROG_TYPE_FLOW_DISSECTOR`
 /// - `ksyscall+` or `kretsyscall+`
-/// - `uprobe.s+` or `uretprobe.s+`
-/// - `usdt+`
-/// - `kprobe.multi+` or `kretprobe.multi+`: `BPF_TRACE_KPROBE_MULTI`
 /// - `lsm_cgroup+`
 /// - `lwt_in`, `lwt_out`, `lwt_seg6local`, `lwt_xmit`
 /// - `raw_tp.w+`, `raw_tracepoint.w+`
 /// - `action`
 /// - `sk_reuseport/migrate`, `sk_reuseport`
 /// - `syscall`
-/// - `struct_ops+`
 /// - `fmod_ret+`, `fmod_ret.s+`
-/// - `fentry.s+`, `fexit.s+`
-/// - `iter+`, `iter.s+`
-/// - `xdp.frags/cpumap`, `xdp/cpumap`
-/// - `xdp.frags/devmap`, `xdp/devmap`
 #[derive(Debug, Clone)]
 #[allow(missing_docs)]
 pub enum ProgramSection {
@@ -183,11 +188,28 @@ pub enum ProgramSection {
     KProbe {
         name: String,
     },
+    KRetProbeMulti {
+        name: String,
+    },
+    KProbeMulti {
+        name: String,
+    },
     UProbe {
         name: String,
+        sleepable: bool,
     },
     URetProbe {
         name: String,
+        sleepable: bool,
+    },
+    UProbeMulti {
+        name: String,
+    },
+    URetProbeMulti {
+        name: String,
+    },
+    Usdt {
+        name: String,
     },
     TracePoint {
         name: String,
@@ -198,6 +220,7 @@ pub enum ProgramSection {
     Xdp {
         name: String,
         frags: bool,
+        attach_type: XdpAttachType,
     },
     SkMsg {
         name: String,
@@ -208,6 +231,9 @@ pub enum ProgramSection {
     SkSkbStreamVerdict {
         name: String,
     },
+    SkSkbVerdict {
+        name: String,
+    },
     SockOps {
         name: String,
     },
@@ -252,9 +278,15 @@ pub enum ProgramSection {
     },
     FEntry {
         name: String,
+        sleepable: bool,
+    },
+    Iter {
+        name: String,
+        sleepable: bool,
     },
     FExit {
         name: String,
+        sleepable: bool,
     },
     Extension {
         name: String,
@@ -269,6 +301,10 @@ pub enum ProgramSection {
     CgroupDevice {
         name: String,
     },
+    StructOps {
+        name: String,
+        sleepable: bool,
+    },
 }
 
 impl ProgramSection {
@@ -277,14 +313,20 @@ impl ProgramSection {
         match self {
             ProgramSection::KRetProbe { name } => name,
             ProgramSection::KProbe { name } => name,
-            ProgramSection::UProbe { name } => name,
-            ProgramSection::URetProbe { name } => name,
+            ProgramSection::KRetProbeMulti { name } => name,
+            ProgramSection::KProbeMulti { name } => name,
+            ProgramSection::UProbe { name, .. } => name,
+            ProgramSection::URetProbe { name, .. } => name,
+            ProgramSection::UProbeMulti { name } => name,
+            ProgramSection::URetProbeMulti { name } => name,
+            ProgramSection::Usdt { name } => name,
             ProgramSection::TracePoint { name } => name,
             ProgramSection::SocketFilter { name } => name,
             ProgramSection::Xdp { name, .. } => name,
             ProgramSection::SkMsg { name } => name,
             ProgramSection::SkSkbStreamParser { name } => name,
             ProgramSection::SkSkbStreamVerdict { name } => name,
+            ProgramSection::SkSkbVerdict { name } => name,
             ProgramSection::SockOps { name } => name,
             ProgramSection::SchedClassifier { name } => name,
             ProgramSection::CgroupSkb { name, .. } => name,
@@ -298,12 +340,14 @@ impl ProgramSection {
             ProgramSection::RawTracePoint { name } => name,
             ProgramSection::Lsm { name, .. } => name,
             ProgramSection::BtfTracePoint { name } => name,
-            ProgramSection::FEntry { name } => name,
-            ProgramSection::FExit { name } => name,
+            ProgramSection::FEntry { name, .. } => name,
+            ProgramSection::Iter { name, .. } => name,
+            ProgramSection::FExit { name, .. } => name,
             ProgramSection::Extension { name } => name,
             ProgramSection::SkLookup { name } => name,
             ProgramSection::CgroupSock { name, .. } => name,
             ProgramSection::CgroupDevice { name } => name,
+            ProgramSection::StructOps { name, .. } => name,
         }
     }
 }
@@ -326,10 +370,46 @@ impl FromStr for ProgramSection {
         Ok(match kind {
             "kprobe" => KProbe { name },
             "kretprobe" => KRetProbe { name },
-            "uprobe" => UProbe { name },
-            "uretprobe" => URetProbe { name },
-            "xdp" => Xdp { name, frags: false },
-            "xdp.frags" => Xdp { name, frags: true },
+            "kprobe.multi" => KProbeMulti { name },
+            "kretprobe.multi" => KRetProbeMulti { name },
+            "uprobe" => UProbe {
+                name,
+                sleepable: false,
+            },
+            "uprobe.s" => UProbe {
+                name,
+                sleepable: true,
+            },
+            "uretprobe" => URetProbe {
+                name,
+                sleepable: false,
+            },
+            "uretprobe.s" => URetProbe {
+                name,
+                sleepable: true,
+            },
+            "uprobe.multi" => UProbeMulti { name },
+            "uretprobe.multi" => URetProbeMulti { name },
+            "usdt" => Usdt { name },
+            "xdp" | "xdp.frags" => Xdp {
+                attach_type: match &*name {
+                    "cpumap" => XdpAttachType::CpuMap,
+                    "devmap" => XdpAttachType::DevMap,
+                    _ => XdpAttachType::Interface,
+                },
+                frags: kind == "xdp.frags",
+                name,
+            },
+            "xdp/cpumap" | "xdp.frags/cpumap" => Xdp {
+                name,
+                frags: kind == "xdp.frags/cpumap",
+                attach_type: XdpAttachType::CpuMap,
+            },
+            "xdp/devmap" | "xdp.frags/devmap" => Xdp {
+                name,
+                frags: kind == "xdp.frags/devmap",
+                attach_type: XdpAttachType::DevMap,
+            },
             "tp_btf" => BtfTracePoint { name },
             _ if kind.starts_with("tracepoint") || kind.starts_with("tp") => {
                 // tracepoint sections are named `tracepoint/category/event_name`,
@@ -342,6 +422,7 @@ impl FromStr for ProgramSection {
             "sk_skb" => match &*name {
                 "stream_parser" => SkSkbStreamParser { name },
                 "stream_verdict" => SkSkbStreamVerdict { name },
+                "verdict" => SkSkbVerdict { name },
                 _ => {
                     return Err(ParseError::InvalidProgramSection {
                         section: section.to_owned(),
@@ -350,6 +431,7 @@ impl FromStr for ProgramSection {
             },
             "sk_skb/stream_parser" => SkSkbStreamParser { name },
             "sk_skb/stream_verdict" => SkSkbStreamVerdict { name },
+            "sk_skb/verdict" => SkSkbVerdict { name },
             "sockops" => SockOps { name },
             "classifier" => SchedClassifier { name },
             "cgroup_skb" => match &*name {
@@ -489,10 +571,40 @@ impl FromStr for ProgramSection {
                 name,
                 sleepable: true,
             },
-            "fentry" => FEntry { name },
-            "fexit" => FExit { name },
+            "fentry" => FEntry {
+                name,
+                sleepable: false,
+            },
+            "fentry.s" => FEntry {
+                name,
+                sleepable: true,
+            },
+            "iter" => Iter {
+                name,
+                sleepable: false,
+            },
+            "iter.s" => Iter {
+                name,
+                sleepable: true,
+            },
+            "fexit" => FExit {
+                name,
+                sleepable: false,
+            },
+            "fexit.s" => FExit {
+                name,
+                sleepable: true,
+            },
             "freplace" => Extension { name },
             "sk_lookup" => SkLookup { name },
+            "struct_ops" => StructOps {
+                name,
+                sleepable: false,
+            },
+            "struct_ops.s" => StructOps {
+                name,
+                sleepable: true,
+            },
             _ => {
                 return Err(ParseError::InvalidProgramSection {
                     section: section.to_owned(),
@@ -615,6 +727,12 @@ impl Object {
                     })?;
                 let start = symbol.address as usize;
                 let end = start + symbol.size as usize;
+                // .bss sections have no data in the object file, fill them with
+                // zeroes so that individual variables can be patched
+                if map.section_kind() == BpfSectionKind::Bss && map.data().is_empty() {
+                    let size = map.value_size() as usize;
+                    map.data_mut().resize(size, 0);
+                }
                 if start > end || end > map.data().len() {
                     return Err(ParseError::InvalidGlobalData {
                         name: name.to_string(),
@@ -632,6 +750,52 @@ impl Object {
         Ok(())
     }
 
+    /// Returns the global variables stored in the `.bss`, `.data` and
+    /// `.rodata` maps, keyed by symbol name
+    ///
+    /// Returns [`ParseError::InvalidGlobalData`] if a variable doesn't fit in
+    /// the map holding it.
+    pub fn global_variables(&self) -> Result<HashMap<String, GlobalVariable>, ParseError> {
+        let mut globals = HashMap::new();
+        for symbol in self.symbol_table.values() {
+            if symbol.kind != SymbolKind::Data || symbol.size == 0 {
+                continue;
+            }
+            let (Some(name), Some(section_index)) = (&symbol.name, symbol.section_index) else {
+                continue;
+            };
+            let Some((map_name, map)) = self.maps.iter().find(|(_, m)| {
+                m.section_index() == section_index
+                    && matches!(
+                        m.section_kind(),
+                        BpfSectionKind::Bss | BpfSectionKind::Data | BpfSectionKind::Rodata
+                    )
+            }) else {
+                continue;
+            };
+            let map_size = map.value_size() as u64;
+            match symbol.address.checked_add(symbol.size) {
+                Some(end) if end <= map_size => {}
+                _ => {
+                    return Err(ParseError::InvalidGlobalData {
+                        name: name.clone(),
+                        sym_size: symbol.size,
+                        data_size: map_size as usize,
+                    })
+                }
+            }
+            globals.insert(
+                name.clone(),
+                GlobalVariable {
+                    map_name: map_name.clone(),
+                    offset: symbol.address as usize,
+                    size: symbol.size as usize,
+                },
+            );
+        }
+        Ok(globals)
+    }
+
     fn parse_btf(&mut self, section: &Section) -> Result<(), BtfError> {
         self.btf = Some(Btf::parse(section.data, self.endianness)?);
 
@@ -1363,6 +1527,9 @@ fn parse_btf_map_def(btf: &Btf, info: &DataSecEntry) -> Result<(String, BtfMapDe
             "map_flags" => {
                 map_def.map_flags = get_map_field(btf, m.btf_type)?;
             }
+            "map_extra" => {
+                map_def.map_extra = get_map_field(btf, m.btf_type)? as u64;
+            }
             "pinning" => {
                 let pinning = get_map_field(btf, m.btf_type)?;
                 map_def.pinning = PinningType::try_from(pinning).unwrap_or_else(|_| {
@@ -1389,6 +1556,7 @@ pub fn parse_map_info(info: bpf_map_info, pinned: PinningType) -> Map {
                 value_size: info.value_size,
                 max_entries: info.max_entries,
                 map_flags: info.map_flags,
+                map_extra: info.map_extra,
                 pinning: pinned,
                 btf_key_type_id: info.btf_key_type_id,
                 btf_value_type_id: info.btf_value_type_id,
@@ -1597,6 +1765,19 @@ mod tests {
         assert_eq!(parse_map_def("foo", &buf).unwrap(), def);
     }
 
+    #[test]
+    fn test_parse_map_info_map_extra() {
+        let mut info = unsafe { mem::zeroed::<bpf_map_info>() };
+        info.type_ = crate::generated::bpf_map_type::BPF_MAP_TYPE_BLOOM_FILTER as u32;
+        info.value_size = 4;
+        info.btf_key_type_id = 1;
+        info.map_extra = 3;
+        assert_eq!(parse_map_info(info, PinningType::None).map_extra(), 3);
+
+        info.btf_key_type_id = 0;
+        assert_eq!(parse_map_info(info, PinningType::None).map_extra(), 0);
+    }
+
     #[test]
     fn test_parse_map_data() {
         let map_data = b"map data";
@@ -1687,7 +1868,8 @@ mod tests {
             )),
             Ok(())
         );
-        assert!(obj.maps.get("foo").is_some());
+        // Legacy map definitions have no map_extra field.
+        assert_matches!(obj.maps.get("foo"), Some(map) if map.map_extra() == 0);
     }
 
     #[test]
@@ -1786,6 +1968,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_section_kprobe_multi() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "kretprobe.multi/foo",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("foo"),
+            Some(Program {
+                section: ProgramSection::KRetProbeMulti { .. },
+                ..
+            })
+        );
+    }
+
     #[test]
     fn test_parse_section_uprobe() {
         let mut obj = fake_obj();
@@ -1807,6 +2010,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_section_uprobe_multi() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "uretprobe.multi/foo",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("foo"),
+            Some(Program {
+                section: ProgramSection::URetProbeMulti { .. },
+                ..
+            })
+        );
+    }
+
     #[test]
     fn test_parse_section_trace_point() {
         let mut obj = fake_obj();
@@ -1907,6 +2131,55 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_section_xdp_chained() {
+        let mut obj = fake_obj();
+
+        for section in ["xdp/cpumap/foo", "xdp.frags/devmap/bar", "xdp/devmap"] {
+            assert_matches!(
+                obj.parse_section(fake_section(
+                    BpfSectionKind::Program,
+                    section,
+                    bytes_of(&fake_ins())
+                )),
+                Ok(())
+            );
+        }
+        assert_matches!(
+            obj.programs.get("foo"),
+            Some(Program {
+                section: ProgramSection::Xdp {
+                    frags: false,
+                    attach_type: XdpAttachType::CpuMap,
+                    ..
+                },
+                ..
+            })
+        );
+        assert_matches!(
+            obj.programs.get("bar"),
+            Some(Program {
+                section: ProgramSection::Xdp {
+                    frags: true,
+                    attach_type: XdpAttachType::DevMap,
+                    ..
+                },
+                ..
+            })
+        );
+        assert_matches!(
+            obj.programs.get("devmap"),
+            Some(Program {
+                section: ProgramSection::Xdp {
+                    frags: false,
+                    attach_type: XdpAttachType::DevMap,
+                    ..
+                },
+                ..
+            })
+        );
+    }
+
     #[test]
     fn test_parse_section_raw_tp() {
         let mut obj = fake_obj();
@@ -1992,6 +2265,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_section_struct_ops() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "struct_ops/foo",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("foo"),
+            Some(Program {
+                section: ProgramSection::StructOps {
+                    sleepable: false,
+                    ..
+                },
+                ..
+            })
+        );
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "struct_ops.s/bar",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("bar"),
+            Some(Program {
+                section: ProgramSection::StructOps {
+                    sleepable: true,
+                    ..
+                },
+                ..
+            })
+        );
+    }
+
     #[test]
     fn test_parse_section_btf_tracepoint() {
         let mut obj = fake_obj();
@@ -2055,6 +2371,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_section_skskb_verdict() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "sk_skb/verdict/my_verdict",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("my_verdict"),
+            Some(Program {
+                section: ProgramSection::SkSkbVerdict { .. },
+                ..
+            })
+        );
+    }
+
     #[test]
     fn test_parse_section_fentry() {
         let mut obj = fake_obj();
@@ -2076,6 +2413,99 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_section_iter() {
+        let mut obj = fake_obj();
+
+        assert_matches!(
+            obj.parse_section(fake_section(
+                BpfSectionKind::Program,
+                "iter/task",
+                bytes_of(&fake_ins())
+            )),
+            Ok(())
+        );
+        assert_matches!(
+            obj.programs.get("task"),
+            Some(Program {
+                section: ProgramSection::Iter { .. },
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_section_sleepable() {
+        let mut obj = fake_obj();
+
+        for section in [
+            "fentry.s/foo",
+            "fexit.s/bar",
+            "uprobe.s/baz",
+            "uretprobe.s/qux",
+            "iter.s/task",
+        ] {
+            assert_matches!(
+                obj.parse_section(fake_section(
+                    BpfSectionKind::Program,
+                    section,
+                    bytes_of(&fake_ins())
+                )),
+                Ok(())
+            );
+        }
+        assert_matches!(
+            obj.programs.get("foo"),
+            Some(Program {
+                section: ProgramSection::FEntry {
+                    sleepable: true,
+                    ..
+                },
+                ..
+            })
+        );
+        assert_matches!(
+            obj.programs.get("bar"),
+            Some(Program {
+                section: ProgramSection::FExit {
+                    sleepable: true,
+                    ..
+                },
+                ..
+            })
+        );
+        assert_matches!(
+            obj.programs.get("baz"),
+            Some(Program {
+                section: ProgramSection::UProbe {
+                    sleepable: true,
+                    ..
+                },
+                ..
+            })
+        );
+        assert_matches!(
+            obj.programs.get("qux"),
+            Some(Program {
+                section: ProgramSection::URetProbe {
+                    sleepable: true,
+                    ..
+                },
+                ..
+            })
+        );
+        assert_matches!(
+            obj.programs.get("task"),
+            Some(Program {
+                section: ProgramSection::Iter {
+                    sleepable: true,
+                    ..
+                },
+                ..
+            })
+        );
+    }
+
     #[test]
     fn test_parse_section_fexit() {
         let mut obj = fake_obj();
@@ -2319,6 +2749,70 @@ mod tests {
         assert_eq!(test_data, map.data());
     }
 
+    #[test]
+    fn test_patch_map_data_bss() {
+        let mut obj = fake_obj();
+        obj.maps.insert(
+            ".bss".to_string(),
+            Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_ARRAY as u32,
+                    key_size: mem::size_of::<u32>() as u32,
+                    value_size: 8,
+                    max_entries: 1,
+                    ..Default::default()
+                },
+                section_index: 1,
+                section_kind: BpfSectionKind::Bss,
+                symbol_index: None,
+                data: Vec::new(),
+            }),
+        );
+        fake_sym(&mut obj, 1, 4, "counter", 4);
+
+        let test_data: &[u8] = &[1, 2, 3, 4];
+        obj.patch_map_data(HashMap::from([("counter", test_data)]))
+            .unwrap();
+
+        let map = obj.maps.get(".bss").unwrap();
+        assert_eq!(map.data(), &[0, 0, 0, 0, 1, 2, 3, 4]);
+        assert_eq!(
+            obj.global_variables().unwrap().get("counter"),
+            Some(&GlobalVariable {
+                map_name: ".bss".to_string(),
+                offset: 4,
+                size: 4,
+            })
+        );
+    }
+
+    #[test]
+    fn test_global_variables_out_of_bounds() {
+        let mut obj = fake_obj();
+        obj.maps.insert(
+            ".data".to_string(),
+            Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_ARRAY as u32,
+                    key_size: mem::size_of::<u32>() as u32,
+                    value_size: 8,
+                    max_entries: 1,
+                    ..Default::default()
+                },
+                section_index: 1,
+                section_kind: BpfSectionKind::Data,
+                symbol_index: None,
+                data: vec![0; 8],
+            }),
+        );
+        fake_sym(&mut obj, 1, 6, "counter", 4);
+
+        assert_matches!(
+            obj.global_variables(),
+            Err(ParseError::InvalidGlobalData { name, sym_size: 4, data_size: 8 }) if name == "counter"
+        );
+    }
+
     #[test]
     fn test_parse_btf_map_section() {
         let mut obj = fake_obj();
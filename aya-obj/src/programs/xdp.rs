@@ -0,0 +1,26 @@
+//! XDP programs.
+
+use crate::generated::bpf_attach_type;
+
+/// Defines where to attach an `Xdp` program.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum XdpAttachType {
+    /// Attach to a network interface.
+    #[default]
+    Interface,
+    /// Attach to a `CPUMAP` entry, to run on the packets redirected to the CPU.
+    CpuMap,
+    /// Attach to a `DEVMAP` or `DEVMAP_HASH` entry, to run on the packets
+    /// redirected to the network interface.
+    DevMap,
+}
+
+impl From<XdpAttachType> for bpf_attach_type {
+    fn from(value: XdpAttachType) -> Self {
+        match value {
+            XdpAttachType::Interface => bpf_attach_type::BPF_XDP,
+            XdpAttachType::CpuMap => bpf_attach_type::BPF_XDP_CPUMAP,
+            XdpAttachType::DevMap => bpf_attach_type::BPF_XDP_DEVMAP,
+        }
+    }
+}
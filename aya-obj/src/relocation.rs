@@ -37,14 +37,16 @@ pub struct BpfRelocationError {
 #[derive(Debug, thiserror::Error)]
 pub enum RelocationError {
     /// Unknown symbol
-    #[error("unknown symbol, index `{index}`")]
+    #[error("unknown symbol, index `{index}`, referenced by instruction #{ins_index}")]
     UnknownSymbol {
         /// The symbol index
         index: usize,
+        /// The index of the instruction referencing the symbol
+        ins_index: usize,
     },
 
     /// Section not found
-    #[error("section `{section_index}` not found, referenced by symbol `{}` #{symbol_index}",
+    #[error("section `{section_index}` not found, referenced by symbol `{}` #{symbol_index} at instruction #{ins_index}",
             .symbol_name.clone().unwrap_or_default())]
     SectionNotFound {
         /// The section index
@@ -53,6 +55,8 @@ pub enum RelocationError {
         symbol_index: usize,
         /// The symbol name
         symbol_name: Option<String>,
+        /// The index of the instruction referencing the symbol
+        ins_index: usize,
     },
 
     /// Unknown function
@@ -65,12 +69,16 @@ pub enum RelocationError {
     },
 
     /// Referenced map not created yet
-    #[error("the map `{name}` at section `{section_index}` has not been created")]
+    #[error("the map `{name}` at section `{section_index}` has not been created, referenced by symbol #{symbol_index} at instruction #{ins_index}")]
     MapNotCreated {
         /// The section index
         section_index: usize,
         /// The map name
         name: String,
+        /// The symbol index
+        symbol_index: usize,
+        /// The index of the instruction referencing the map
+        ins_index: usize,
     },
 
     /// Invalid relocation offset
@@ -201,6 +209,7 @@ fn relocate_maps<'a, I: Iterator<Item = &'a Relocation>>(
             .get(&rel.symbol_index)
             .ok_or(RelocationError::UnknownSymbol {
                 index: rel.symbol_index,
+                ins_index,
             })?;
 
         let Some(section_index) = sym.section_index else {
@@ -225,14 +234,12 @@ fn relocate_maps<'a, I: Iterator<Item = &'a Relocation>>(
             m
         } else {
             let Some(m) = maps_by_section.get(&section_index) else {
-                debug!(
-                    "failed relocating map by section index {}",
-                    section_index
-                );
+                debug!("failed relocating map by section index {}", section_index);
                 return Err(RelocationError::SectionNotFound {
                     symbol_index: rel.symbol_index,
                     symbol_name: sym.name.clone(),
                     section_index,
+                    ins_index,
                 });
             };
             let map = &m.2;
@@ -255,6 +262,8 @@ fn relocate_maps<'a, I: Iterator<Item = &'a Relocation>>(
         let map_fd = fd.ok_or_else(|| RelocationError::MapNotCreated {
             name: (*name).into(),
             section_index,
+            symbol_index: rel.symbol_index,
+            ins_index,
         })?;
 
         if !map.data().is_empty() {
@@ -592,6 +601,58 @@ mod test {
         assert_eq!(fun.instructions[0].imm, 1);
     }
 
+    #[test]
+    fn test_map_relocation_errors() {
+        let ld = ins(&[
+            0x18, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00,
+        ]);
+        let mut fun = fake_func("test", vec![ld, ld]);
+
+        let symbol_table = HashMap::from([(1, fake_sym(1, 0, 0, "test_map", 0))]);
+
+        let relocations = [Relocation {
+            offset: 0x8,
+            symbol_index: 1,
+            size: 64,
+        }];
+
+        let err = relocate_maps(
+            &mut fun,
+            relocations.iter(),
+            &HashMap::new(),
+            &HashMap::new(),
+            &symbol_table,
+            &HashSet::new(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            RelocationError::SectionNotFound {
+                section_index: 0,
+                symbol_index: 1,
+                ins_index: 1,
+                ..
+            }
+        ));
+
+        let map = fake_legacy_map(1);
+        let maps_by_symbol = HashMap::from([(1, ("test_map", None, &map))]);
+        let err = relocate_maps(
+            &mut fun,
+            relocations.iter(),
+            &HashMap::new(),
+            &maps_by_symbol,
+            &symbol_table,
+            &HashSet::new(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the map `test_map` at section `0` has not been created, referenced by symbol #1 at instruction #1"
+        );
+    }
+
     #[test]
     fn test_multiple_legacy_map_relocation() {
         let mut fun = fake_func(
@@ -2,44 +2,55 @@ use std::{
     borrow::Cow,
     collections::{HashMap, HashSet},
     ffi::CString,
-    fs, io,
-    os::{raw::c_int, unix::io::RawFd},
+    fs, io, mem,
+    os::{
+        fd::{BorrowedFd, OwnedFd},
+        raw::c_int,
+        unix::io::{AsRawFd, RawFd},
+    },
     path::{Path, PathBuf},
+    ptr,
+    sync::Arc,
 };
 
 use aya_obj::{
     btf::{BtfFeatures, BtfRelocationError},
     generated::{BPF_F_SLEEPABLE, BPF_F_XDP_HAS_FRAGS},
     relocation::BpfRelocationError,
-    BpfSectionKind, Features,
+    BpfSectionKind, Features, GlobalVariable,
 };
 use log::debug;
 use thiserror::Error;
 
 use crate::{
     generated::{
-        bpf_map_type, bpf_map_type::*, AYA_PERF_EVENT_IOC_DISABLE, AYA_PERF_EVENT_IOC_ENABLE,
+        bpf_map_type::*, AYA_PERF_EVENT_IOC_DISABLE, AYA_PERF_EVENT_IOC_ENABLE,
         AYA_PERF_EVENT_IOC_SET_BPF,
     },
     maps::{Map, MapData, MapError},
     obj::{
+        self,
         btf::{Btf, BtfError},
         Object, ParseError, ProgramSection,
     },
     programs::{
-        BtfTracePoint, CgroupDevice, CgroupSkb, CgroupSkbAttachType, CgroupSock, CgroupSockAddr,
-        CgroupSockopt, CgroupSysctl, Extension, FEntry, FExit, KProbe, LircMode2, Lsm, PerfEvent,
-        ProbeKind, Program, ProgramData, ProgramError, RawTracePoint, SchedClassifier, SkLookup,
-        SkMsg, SkSkb, SkSkbKind, SockOps, SocketFilter, TracePoint, UProbe, Xdp,
+        usdt::USDT_SPECS_MAP, BtfTracePoint, CgroupDevice, CgroupSkb, CgroupSkbAttachType,
+        CgroupSock, CgroupSockAddr, CgroupSockopt, CgroupSysctl, Extension, FEntry, FExit, Iter,
+        KProbe, KProbeMulti, LircMode2, Lsm, PerfEvent, ProbeKind, Program, ProgramData,
+        ProgramError, RawTracePoint, SchedClassifier, SkLookup, SkMsg, SkSkb, SkSkbKind, SockOps,
+        SocketFilter, StructOps, TracePoint, UProbe, UProbeMulti, Usdt, Xdp,
     },
     sys::{
-        bpf_load_btf, bpf_map_freeze, bpf_map_update_elem_ptr, is_bpf_global_data_supported,
-        is_btf_datasec_supported, is_btf_decl_tag_supported, is_btf_float_supported,
-        is_btf_func_global_supported, is_btf_func_supported, is_btf_supported,
-        is_btf_type_tag_supported, is_perf_link_supported, is_probe_read_kernel_supported,
-        is_prog_name_supported, retry_with_verifier_logs,
+        bpf_load_btf, bpf_map_freeze, bpf_map_lookup_elem_ptr, bpf_map_update_elem_ptr,
+        bpf_token_create, is_bpf_global_data_supported, is_btf_datasec_supported,
+        is_btf_decl_tag_supported, is_btf_float_supported, is_btf_func_global_supported,
+        is_btf_func_supported, is_btf_supported, is_btf_type_tag_supported, is_perf_link_supported,
+        is_probe_read_kernel_supported, is_prog_name_supported, retry_with_verifier_logs,
+    },
+    util::{
+        annotate_verifier_log, bytes_of, bytes_of_slice, possible_cpus, raise_memlock_rlimit,
+        VerifierLog, POSSIBLE_CPUS,
     },
-    util::{bytes_of, bytes_of_slice, possible_cpus, VerifierLog, POSSIBLE_CPUS},
 };
 
 pub(crate) const BPF_OBJ_NAME_LEN: usize = 16;
@@ -67,27 +78,28 @@ unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
 pub use aya_obj::maps::{bpf_map_def, PinningType};
 
 lazy_static! {
-    pub(crate) static ref FEATURES: Features = detect_features();
+    pub(crate) static ref FEATURES: Features = detect_features(None);
 }
 
-fn detect_features() -> Features {
-    let btf = if is_btf_supported() {
+// Programs and BTF are loaded with the privileges of `token_fd` if set.
+fn detect_features(token_fd: Option<RawFd>) -> Features {
+    let btf = if is_btf_supported(token_fd) {
         Some(BtfFeatures {
-            btf_func: is_btf_func_supported(),
-            btf_func_global: is_btf_func_global_supported(),
-            btf_datasec: is_btf_datasec_supported(),
-            btf_float: is_btf_float_supported(),
-            btf_decl_tag: is_btf_decl_tag_supported(),
-            btf_type_tag: is_btf_type_tag_supported(),
+            btf_func: is_btf_func_supported(token_fd),
+            btf_func_global: is_btf_func_global_supported(token_fd),
+            btf_datasec: is_btf_datasec_supported(token_fd),
+            btf_float: is_btf_float_supported(token_fd),
+            btf_decl_tag: is_btf_decl_tag_supported(token_fd),
+            btf_type_tag: is_btf_type_tag_supported(token_fd),
         })
     } else {
         None
     };
     let f = Features {
-        bpf_name: is_prog_name_supported(),
-        bpf_probe_read_kernel: is_probe_read_kernel_supported(),
+        bpf_name: is_prog_name_supported(token_fd),
+        bpf_probe_read_kernel: is_probe_read_kernel_supported(token_fd),
         bpf_perf_link: is_perf_link_supported(),
-        bpf_global_data: is_bpf_global_data_supported(),
+        bpf_global_data: is_bpf_global_data_supported(token_fd),
         btf,
     };
     debug!("BPF Feature Detection: {:#?}", f);
@@ -121,8 +133,26 @@ pub struct BpfLoader<'a> {
     map_pin_path: Option<PathBuf>,
     globals: HashMap<&'a str, &'a [u8]>,
     max_entries: HashMap<&'a str, u32>,
+    min_max_entries: HashMap<&'a str, u32>,
+    map_flags: HashMap<&'a str, u32>,
+    map_ifindex: HashMap<&'a str, u32>,
     extensions: HashSet<&'a str>,
+    programs: Option<HashSet<&'a str>>,
+    inner_maps: HashMap<&'a str, &'a str>,
+    map_pins: HashMap<&'a str, MapPin>,
+    reused_maps: HashMap<&'a str, BorrowedFd<'a>>,
     verifier_log_level: VerifierLogLevel,
+    token: Option<&'a BpfToken>,
+    raise_memlock_rlimit: bool,
+}
+
+/// How a map is pinned at a custom path.
+#[derive(Debug)]
+enum MapPin {
+    /// Reuse the map pinned at the path, or create it and pin it there.
+    PinOrReuse(PathBuf),
+    /// Reuse the map pinned at the path, which must exist.
+    Reuse(PathBuf),
 }
 
 bitflags! {
@@ -154,8 +184,17 @@ impl<'a> BpfLoader<'a> {
             map_pin_path: None,
             globals: HashMap::new(),
             max_entries: HashMap::new(),
+            min_max_entries: HashMap::new(),
+            map_flags: HashMap::new(),
+            map_ifindex: HashMap::new(),
             extensions: HashSet::new(),
+            programs: None,
+            inner_maps: HashMap::new(),
+            map_pins: HashMap::new(),
+            reused_maps: HashMap::new(),
             verifier_log_level: VerifierLogLevel::default(),
+            token: None,
+            raise_memlock_rlimit: false,
         }
     }
 
@@ -202,6 +241,91 @@ impl<'a> BpfLoader<'a> {
         self
     }
 
+    /// Pins the map `name` at `path`.
+    ///
+    /// If a map is already pinned at `path`, it is reused instead of creating
+    /// a new map, so that its content survives restarts of the loading
+    /// program. Otherwise the map is created and pinned at `path`. This takes
+    /// precedence over the pinning declared by the eBPF program and over
+    /// [`map_pin_path`](BpfLoader::map_pin_path).
+    ///
+    /// # Errors
+    ///
+    /// Loading fails with [`MapError::IncompatiblePinnedMap`] if the map
+    /// pinned at `path` doesn't have the same type, key and value sizes,
+    /// maximum number of entries and flags as the map of the object file.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::BpfLoader;
+    ///
+    /// let bpf = BpfLoader::new()
+    ///     .pin_map("CONNECTIONS", "/sys/fs/bpf/agent/connections")
+    ///     .load_file("file.o")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    ///
+    pub fn pin_map<P: AsRef<Path>>(&mut self, name: &'a str, path: P) -> &mut BpfLoader<'a> {
+        self.map_pins
+            .insert(name, MapPin::PinOrReuse(path.as_ref().to_owned()));
+        self
+    }
+
+    /// Reuses the map pinned at `path` as the map `name`.
+    ///
+    /// Unlike [`pin_map`](BpfLoader::pin_map), loading fails if no map is
+    /// pinned at `path`. This is typically used to share a map created by
+    /// another program.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::BpfLoader;
+    ///
+    /// let bpf = BpfLoader::new()
+    ///     .reuse_pinned_map("CONFIG", "/sys/fs/bpf/agent/config")
+    ///     .load_file("file.o")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    ///
+    pub fn reuse_pinned_map<P: AsRef<Path>>(
+        &mut self,
+        name: &'a str,
+        path: P,
+    ) -> &mut BpfLoader<'a> {
+        self.map_pins
+            .insert(name, MapPin::Reuse(path.as_ref().to_owned()));
+        self
+    }
+
+    /// Reuses the map referenced by `map` as the map `name`.
+    ///
+    /// This shares a map with an object that was loaded separately, without
+    /// pinning it. The file descriptor is duplicated when the object is
+    /// loaded, so `map` only needs to stay open until then. Loading fails if
+    /// the map doesn't have the same type, key and value sizes, maximum
+    /// number of entries and flags as the map of the object file.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::{Bpf, BpfLoader};
+    /// use std::os::fd::AsFd;
+    ///
+    /// let agent = Bpf::load_file("agent.o")?;
+    /// let config = agent.map("CONFIG").unwrap().fd().unwrap();
+    /// let plugin = BpfLoader::new()
+    ///     .reuse_map("CONFIG", config.as_fd())
+    ///     .load_file("plugin.o")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    ///
+    pub fn reuse_map(&mut self, name: &'a str, map: BorrowedFd<'a>) -> &mut BpfLoader<'a> {
+        self.reused_maps.insert(name, map);
+        self
+    }
+
     /// Sets the value of a global variable.
     ///
     /// From Rust eBPF, a global variable can be defined as follows:
@@ -227,6 +351,9 @@ impl<'a> BpfLoader<'a> {
     ///
     /// From C eBPF, you would annotate a global variable as `volatile const`.
     ///
+    /// Variables in `.bss` and `.data` can also be read and updated after loading with
+    /// [`Bpf::global`] and [`Bpf::set_global`].
+    ///
     /// # Example
     ///
     /// ```no_run
@@ -269,6 +396,104 @@ impl<'a> BpfLoader<'a> {
         self
     }
 
+    /// Set the minimum max_entries for specified map.
+    ///
+    /// Raise the value of max_entries of the map that matches the provided
+    /// name to `size` if it is smaller, after any value set with
+    /// [`set_max_entries`](Self::set_max_entries) has been applied. A larger
+    /// size, set by the object or the loader, is kept.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::BpfLoader;
+    ///
+    /// let bpf = BpfLoader::new()
+    ///     .set_min_max_entries("map", 64)
+    ///     .load_file("file.o")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    ///
+    pub fn set_min_max_entries(&mut self, name: &'a str, size: u32) -> &mut BpfLoader<'a> {
+        self.min_max_entries.insert(name, size);
+        self
+    }
+
+    /// Set the map_flags for specified map.
+    ///
+    /// Overwrite the flags of the map that matches the provided name before
+    /// the map is created, for example to add `BPF_F_NO_PREALLOC` to a large
+    /// hash map. Together with [`set_max_entries`](Self::set_max_entries)
+    /// this allows sizing the maps of the same object file for the target.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::BpfLoader;
+    ///
+    /// const BPF_F_NO_PREALLOC: u32 = 1;
+    ///
+    /// let bpf = BpfLoader::new()
+    ///     .set_max_entries("CONNECTIONS", 1 << 20)
+    ///     .set_map_flags("CONNECTIONS", BPF_F_NO_PREALLOC)
+    ///     .load_file("file.o")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    ///
+    pub fn set_map_flags(&mut self, name: &'a str, flags: u32) -> &mut BpfLoader<'a> {
+        self.map_flags.insert(name, flags);
+        self
+    }
+
+    /// Binds the specified map to the network device `if_index`.
+    ///
+    /// The maps used by programs offloaded to a network card with
+    /// [`Xdp::load_offloaded`](crate::programs::Xdp::load_offloaded) must
+    /// live on the same device. Such maps can't be accessed by programs
+    /// running on the host.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::{util::ifindex_from_ifname, BpfLoader};
+    ///
+    /// let if_index = ifindex_from_ifname("eth0")?;
+    /// let bpf = BpfLoader::new()
+    ///     .set_map_ifindex("COUNTERS", if_index)
+    ///     .load_file("file.o")?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    pub fn set_map_ifindex(&mut self, name: &'a str, if_index: u32) -> &mut BpfLoader<'a> {
+        self.map_ifindex.insert(name, if_index);
+        self
+    }
+
+    /// Sets the inner map template of a map of maps.
+    ///
+    /// An [`ArrayOfMaps`](crate::maps::ArrayOfMaps) or a
+    /// [`HashOfMaps`](crate::maps::HashOfMaps) is created from the definition
+    /// of one of its inner maps. The `inner` map is created before the `outer`
+    /// one, and new inner maps can later be created from it with
+    /// [`Map::create_like`]. The `inner` map can itself be a map of maps with
+    /// its own template, for nested maps of maps.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::BpfLoader;
+    ///
+    /// let bpf = BpfLoader::new()
+    ///     .set_inner_map("TENANTS", "TENANT_TEMPLATE")
+    ///     .load_file("file.o")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    ///
+    pub fn set_inner_map(&mut self, outer: &'a str, inner: &'a str) -> &mut BpfLoader<'a> {
+        self.inner_maps.insert(outer, inner);
+        self
+    }
+
     /// Treat the provided program as an [`Extension`]
     ///
     /// When attempting to load the program with the provided `name`
@@ -291,6 +516,56 @@ impl<'a> BpfLoader<'a> {
         self
     }
 
+    /// Raises `RLIMIT_MEMLOCK` before loading if the kernel counts eBPF memory
+    /// against it.
+    ///
+    /// This is disabled by default. See [`raise_memlock_rlimit`](crate::util::raise_memlock_rlimit).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::BpfLoader;
+    ///
+    /// let bpf = BpfLoader::new()
+    ///     .raise_memlock_rlimit(true)
+    ///     .load_file("file.o")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    ///
+    pub fn raise_memlock_rlimit(&mut self, raise: bool) -> &mut BpfLoader<'a> {
+        self.raise_memlock_rlimit = raise;
+        self
+    }
+
+    /// Only keeps the programs called `names`.
+    ///
+    /// Programs aren't verified until their `load()` method is called, but
+    /// relocating them still has a cost. With this option the other programs
+    /// of the object are skipped entirely and won't be available from
+    /// [`Bpf::program`]. More programs of the same object can be added later
+    /// on with [`load_into`](BpfLoader::load_into).
+    ///
+    /// Loading fails with [`ProgramError::InvalidName`] if the object doesn't
+    /// contain one of the programs.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::BpfLoader;
+    ///
+    /// let bpf = BpfLoader::new()
+    ///     .programs(&["ingress", "egress"])
+    ///     .load_file("file.o")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    ///
+    pub fn programs(&mut self, names: &[&'a str]) -> &mut BpfLoader<'a> {
+        self.programs
+            .get_or_insert_with(HashSet::new)
+            .extend(names.iter().copied());
+        self
+    }
+
     /// Sets BPF verifier log level.
     ///
     /// # Example
@@ -309,6 +584,26 @@ impl<'a> BpfLoader<'a> {
         self
     }
 
+    /// Uses the privileges delegated by a [BpfToken] to load the object.
+    ///
+    /// The token is passed when loading the BTF, creating the maps and
+    /// loading the programs, so it must be kept open until all the programs
+    /// are loaded.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aya::{BpfLoader, BpfToken};
+    ///
+    /// let token = BpfToken::create("/sys/fs/bpf")?;
+    /// let bpf = BpfLoader::new().token(&token).load_file("file.o")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn token(&mut self, token: &'a BpfToken) -> &mut BpfLoader<'a> {
+        self.token = Some(token);
+        self
+    }
+
     /// Loads eBPF bytecode from a file.
     ///
     /// # Examples
@@ -340,14 +635,96 @@ impl<'a> BpfLoader<'a> {
     /// # Ok::<(), aya::BpfError>(())
     /// ```
     pub fn load(&mut self, data: &[u8]) -> Result<Bpf, BpfError> {
+        self.load_inner(data, None)
+    }
+
+    /// Loads more programs of an object into `bpf`, which was loaded from the
+    /// same object.
+    ///
+    /// The programs selected with [`programs`](BpfLoader::programs) are
+    /// added to `bpf`, and use the maps of `bpf` instead of creating new
+    /// ones. Programs that are already in `bpf` are kept as they are.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use aya::BpfLoader;
+    /// use std::fs;
+    ///
+    /// let data = fs::read("file.o").unwrap();
+    /// let mut bpf = BpfLoader::new().programs(&["ingress"]).load(&data)?;
+    /// // later on
+    /// BpfLoader::new()
+    ///     .programs(&["egress"])
+    ///     .load_into(&mut bpf, &data)?;
+    /// assert!(bpf.program("egress").is_some());
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn load_into(&mut self, bpf: &mut Bpf, data: &[u8]) -> Result<(), BpfError> {
+        let loaded = self.load_inner(data, Some(&bpf.maps))?;
+        for (name, program) in loaded.programs {
+            bpf.programs.entry(name).or_insert(program);
+        }
+        Ok(())
+    }
+
+    // Returns the number of map of maps levels below `name`, following the
+    // templates set with `set_inner_map`. Cycles stop after visiting every
+    // template once, creating the maps then fails with `MapError::InvalidName`.
+    fn inner_map_depth(&self, name: &str) -> usize {
+        let mut depth = 0;
+        let mut name = name;
+        while let Some(inner) = self.inner_maps.get(name) {
+            if depth == self.inner_maps.len() {
+                break;
+            }
+            depth += 1;
+            name = inner;
+        }
+        depth
+    }
+
+    fn load_inner(
+        &mut self,
+        data: &[u8],
+        shared_maps: Option<&HashMap<String, Map>>,
+    ) -> Result<Bpf, BpfError> {
+        if self.raise_memlock_rlimit {
+            raise_memlock_rlimit().map_err(|io_error| BpfError::MemlockRlimitError { io_error })?;
+        }
         let verifier_log_level = self.verifier_log_level.bits();
+        let token_fd = self.token.map(|token| token.fd);
+        // The programs can be loaded after the token is dropped, they keep
+        // their own copy of its fd.
+        let prog_token_fd = self
+            .token
+            .map(|token| token.try_clone_fd().map(Arc::new))
+            .transpose()?;
+        // Without the token, feature probes fail inside user namespaces.
+        let token_features;
+        let features = match token_fd {
+            Some(token_fd) => {
+                token_features = detect_features(Some(token_fd));
+                &token_features
+            }
+            None => &*FEATURES,
+        };
         let mut obj = Object::parse(data)?;
         obj.patch_map_data(self.globals.clone())?;
+        if let Some(names) = &self.programs {
+            if let Some(name) = names.iter().find(|name| !obj.programs.contains_key(**name)) {
+                return Err(ProgramError::InvalidName {
+                    name: name.to_string(),
+                }
+                .into());
+            }
+            obj.programs.retain(|name, _| names.contains(name.as_str()));
+        }
 
-        let btf_fd = if let Some(features) = &FEATURES.btf {
-            if let Some(btf) = obj.fixup_and_sanitize_btf(features)? {
+        let btf_fd = if let Some(btf_features) = &features.btf {
+            if let Some(btf) = obj.fixup_and_sanitize_btf(btf_features)? {
                 // load btf to the kernel
-                Some(load_btf(btf.to_bytes())?)
+                Some(load_btf(btf.to_bytes(), token_fd)?)
             } else {
                 None
             }
@@ -356,41 +733,90 @@ impl<'a> BpfLoader<'a> {
         };
 
         if let Some(btf) = &self.btf {
-            obj.relocate_btf(btf)?;
+            let programs = self
+                .programs
+                .as_ref()
+                .map(|names| names.iter().copied().collect::<Vec<_>>());
+            obj.relocate_btf_programs(btf, programs.as_deref())?;
         }
+        let btf = obj.btf.clone();
+        let globals = obj.global_variables()?;
         let mut maps = HashMap::new();
-        for (name, mut obj) in obj.maps.drain() {
+        let mut map_objs = obj.maps.drain().collect::<Vec<_>>();
+        // Inner map templates must be created before the maps of maps using
+        // them, which can themselves be the templates of other maps of maps.
+        map_objs.sort_by_cached_key(|(name, _)| self.inner_map_depth(name));
+        for (name, mut obj) in map_objs {
             if let (false, BpfSectionKind::Bss | BpfSectionKind::Data | BpfSectionKind::Rodata) =
-                (FEATURES.bpf_global_data, obj.section_kind())
+                (features.bpf_global_data, obj.section_kind())
             {
                 continue;
             }
 
-            match self.max_entries.get(name.as_str()) {
-                Some(size) => obj.set_max_entries(*size),
-                None => {
-                    if obj.map_type() == BPF_MAP_TYPE_PERF_EVENT_ARRAY as u32
-                        && obj.max_entries() == 0
-                    {
-                        obj.set_max_entries(
-                            possible_cpus()
-                                .map_err(|error| BpfError::FileError {
-                                    path: PathBuf::from(POSSIBLE_CPUS),
-                                    error,
-                                })?
-                                .len() as u32,
-                        );
-                    }
-                }
+            self.apply_max_entries(&name, &mut obj)?;
+            if let Some(flags) = self.map_flags.get(name.as_str()) {
+                obj.set_map_flags(*flags);
             }
+            let inner_map_fd = match self.inner_maps.get(name.as_str()) {
+                Some(inner) => Some(
+                    maps.get(*inner)
+                        .and_then(|map: &MapData| map.fd)
+                        .ok_or_else(|| MapError::InvalidName {
+                            name: inner.to_string(),
+                        })?,
+                ),
+                None => None,
+            };
+            let map_ifindex = self.map_ifindex.get(name.as_str()).copied();
             let mut map = MapData {
                 obj,
                 fd: None,
                 pinned: false,
                 btf_fd,
             };
-            let fd = match map.obj.pinning() {
-                PinningType::ByName => {
+            // Maps shared by file descriptor keep their content.
+            let shared_fd = shared_maps
+                .and_then(|maps| maps.get(&name))
+                .and_then(|map| map.fd())
+                // Safety: the maps are borrowed until the end of the load
+                .map(|fd| unsafe { BorrowedFd::borrow_raw(fd.as_raw_fd()) });
+            if let Some(fd) = shared_fd.or_else(|| self.reused_maps.get(name.as_str()).copied()) {
+                map.reuse_fd(&name, fd)?;
+                map.check_reused_compat(&name)?;
+                maps.insert(name, map);
+                continue;
+            }
+            let mut reused = false;
+            let fd = match (self.map_pins.get(name.as_str()), map.obj.pinning()) {
+                (Some(MapPin::Reuse(path)), _) => {
+                    let fd = map.open_pinned_path(&name, path)?;
+                    map.check_pinned_compat(&name)?;
+                    map.pinned = true;
+                    reused = true;
+                    fd
+                }
+                (Some(MapPin::PinOrReuse(path)), _) => match map.open_pinned_path(&name, path) {
+                    Ok(fd) => {
+                        map.check_pinned_compat(&name)?;
+                        map.pinned = true;
+                        reused = true;
+                        fd
+                    }
+                    Err(MapError::SyscallError { io_error, .. })
+                        if io_error.kind() == io::ErrorKind::NotFound =>
+                    {
+                        let fd =
+                            map.create_with_inner_map(&name, inner_map_fd, map_ifindex, token_fd)?;
+                        map.pin_to_path(&name, path)
+                            .map_err(|error| MapError::PinError {
+                                name: Some(name.to_string()),
+                                error,
+                            })?;
+                        fd
+                    }
+                    Err(e) => return Err(e.into()),
+                },
+                (None, PinningType::ByName) => {
                     let path = match &self.map_pin_path {
                         Some(p) => p,
                         None => return Err(BpfError::NoPinPath),
@@ -402,7 +828,12 @@ impl<'a> BpfLoader<'a> {
                             fd as RawFd
                         }
                         Err(_) => {
-                            let fd = map.create(&name)?;
+                            let fd = map.create_with_inner_map(
+                                &name,
+                                inner_map_fd,
+                                map_ifindex,
+                                token_fd,
+                            )?;
                             map.pin(&name, path).map_err(|error| MapError::PinError {
                                 name: Some(name.to_string()),
                                 error,
@@ -411,21 +842,16 @@ impl<'a> BpfLoader<'a> {
                         }
                     }
                 }
-                PinningType::None => map.create(&name)?,
+                (None, PinningType::None) => {
+                    map.create_with_inner_map(&name, inner_map_fd, map_ifindex, token_fd)?
+                }
             };
-            if !map.obj.data().is_empty() && map.obj.section_kind() != BpfSectionKind::Bss {
-                bpf_map_update_elem_ptr(fd, &0 as *const _, map.obj.data_mut().as_mut_ptr(), 0)
-                    .map_err(|(_, io_error)| MapError::SyscallError {
-                        call: "bpf_map_update_elem".to_owned(),
-                        io_error,
-                    })?;
-            }
-            if map.obj.section_kind() == BpfSectionKind::Rodata {
-                bpf_map_freeze(fd).map_err(|(_, io_error)| MapError::SyscallError {
-                    call: "bpf_map_freeze".to_owned(),
-                    io_error,
-                })?;
+            // The content of reused maps is preserved.
+            if reused {
+                maps.insert(name, map);
+                continue;
             }
+            init_map_data(fd, &mut map.obj)?;
             maps.insert(name, map);
         }
 
@@ -441,13 +867,13 @@ impl<'a> BpfLoader<'a> {
             &text_sections,
         )?;
         obj.relocate_calls(&text_sections)?;
-        obj.sanitize_programs(&FEATURES);
+        obj.sanitize_programs(features);
 
         let programs = obj
             .programs
             .drain()
             .map(|(name, obj)| {
-                let prog_name = if FEATURES.bpf_name {
+                let prog_name = if features.bpf_name {
                     Some(name.clone())
                 } else {
                     None
@@ -456,70 +882,241 @@ impl<'a> BpfLoader<'a> {
 
                 let program = if self.extensions.contains(name.as_str()) {
                     Program::Extension(Extension {
-                        data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                        data: ProgramData::new(
+                            prog_name,
+                            obj,
+                            btf_fd,
+                            verifier_log_level,
+                            prog_token_fd.clone(),
+                        ),
                     })
                 } else {
                     match &section {
                         ProgramSection::KProbe { .. } => Program::KProbe(KProbe {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            ),
                             kind: ProbeKind::KProbe,
                         }),
                         ProgramSection::KRetProbe { .. } => Program::KProbe(KProbe {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            ),
                             kind: ProbeKind::KRetProbe,
                         }),
-                        ProgramSection::UProbe { .. } => Program::UProbe(UProbe {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                        ProgramSection::KProbeMulti { .. } => Program::KProbeMulti(KProbeMulti {
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            ),
+                            kind: ProbeKind::KProbe,
+                        }),
+                        ProgramSection::KRetProbeMulti { .. } => {
+                            Program::KProbeMulti(KProbeMulti {
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    prog_token_fd.clone(),
+                                ),
+                                kind: ProbeKind::KRetProbe,
+                            })
+                        }
+                        ProgramSection::UProbe { sleepable, .. } => {
+                            let mut data = ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            );
+                            if *sleepable {
+                                data.flags = BPF_F_SLEEPABLE;
+                            }
+                            Program::UProbe(UProbe {
+                                data,
+                                kind: ProbeKind::UProbe,
+                            })
+                        }
+                        ProgramSection::URetProbe { sleepable, .. } => {
+                            let mut data = ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            );
+                            if *sleepable {
+                                data.flags = BPF_F_SLEEPABLE;
+                            }
+                            Program::UProbe(UProbe {
+                                data,
+                                kind: ProbeKind::URetProbe,
+                            })
+                        }
+                        ProgramSection::UProbeMulti { .. } => Program::UProbeMulti(UProbeMulti {
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            ),
                             kind: ProbeKind::UProbe,
                         }),
-                        ProgramSection::URetProbe { .. } => Program::UProbe(UProbe {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
-                            kind: ProbeKind::URetProbe,
+                        ProgramSection::URetProbeMulti { .. } => {
+                            Program::UProbeMulti(UProbeMulti {
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    prog_token_fd.clone(),
+                                ),
+                                kind: ProbeKind::URetProbe,
+                            })
+                        }
+                        ProgramSection::Usdt { .. } => Program::Usdt(Usdt {
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            ),
+                            specs: maps.get(USDT_SPECS_MAP).cloned(),
+                            spec_ids: HashMap::new(),
                         }),
                         ProgramSection::TracePoint { .. } => Program::TracePoint(TracePoint {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            ),
                         }),
                         ProgramSection::SocketFilter { .. } => {
                             Program::SocketFilter(SocketFilter {
-                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    prog_token_fd.clone(),
+                                ),
                             })
                         }
-                        ProgramSection::Xdp { frags, .. } => {
-                            let mut data =
-                                ProgramData::new(prog_name, obj, btf_fd, verifier_log_level);
+                        ProgramSection::Xdp {
+                            frags, attach_type, ..
+                        } => {
+                            let mut data = ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            );
                             if *frags {
                                 data.flags = BPF_F_XDP_HAS_FRAGS;
                             }
-                            Program::Xdp(Xdp { data })
+                            Program::Xdp(Xdp {
+                                data,
+                                attach_type: *attach_type,
+                            })
                         }
                         ProgramSection::SkMsg { .. } => Program::SkMsg(SkMsg {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            ),
                         }),
                         ProgramSection::CgroupSysctl { .. } => {
                             Program::CgroupSysctl(CgroupSysctl {
-                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    prog_token_fd.clone(),
+                                ),
                             })
                         }
                         ProgramSection::CgroupSockopt { attach_type, .. } => {
                             Program::CgroupSockopt(CgroupSockopt {
-                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    prog_token_fd.clone(),
+                                ),
                                 attach_type: *attach_type,
                             })
                         }
                         ProgramSection::SkSkbStreamParser { .. } => Program::SkSkb(SkSkb {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            ),
                             kind: SkSkbKind::StreamParser,
                         }),
                         ProgramSection::SkSkbStreamVerdict { .. } => Program::SkSkb(SkSkb {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            ),
                             kind: SkSkbKind::StreamVerdict,
                         }),
+                        ProgramSection::SkSkbVerdict { .. } => Program::SkSkb(SkSkb {
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            ),
+                            kind: SkSkbKind::Verdict,
+                        }),
                         ProgramSection::SockOps { .. } => Program::SockOps(SockOps {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            ),
                         }),
                         ProgramSection::SchedClassifier { .. } => {
                             Program::SchedClassifier(SchedClassifier {
-                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    prog_token_fd.clone(),
+                                ),
                                 name: unsafe {
                                     CString::from_vec_unchecked(Vec::from(name.clone()))
                                         .into_boxed_c_str()
@@ -527,37 +1124,84 @@ impl<'a> BpfLoader<'a> {
                             })
                         }
                         ProgramSection::CgroupSkb { .. } => Program::CgroupSkb(CgroupSkb {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            ),
                             expected_attach_type: None,
                         }),
                         ProgramSection::CgroupSkbIngress { .. } => Program::CgroupSkb(CgroupSkb {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            ),
                             expected_attach_type: Some(CgroupSkbAttachType::Ingress),
                         }),
                         ProgramSection::CgroupSkbEgress { .. } => Program::CgroupSkb(CgroupSkb {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            ),
                             expected_attach_type: Some(CgroupSkbAttachType::Egress),
                         }),
                         ProgramSection::CgroupSockAddr { attach_type, .. } => {
                             Program::CgroupSockAddr(CgroupSockAddr {
-                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    prog_token_fd.clone(),
+                                ),
                                 attach_type: *attach_type,
                             })
                         }
                         ProgramSection::LircMode2 { .. } => Program::LircMode2(LircMode2 {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            ),
                         }),
                         ProgramSection::PerfEvent { .. } => Program::PerfEvent(PerfEvent {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            ),
                         }),
                         ProgramSection::RawTracePoint { .. } => {
                             Program::RawTracePoint(RawTracePoint {
-                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    prog_token_fd.clone(),
+                                ),
                             })
                         }
                         ProgramSection::Lsm { sleepable, .. } => {
-                            let mut data =
-                                ProgramData::new(prog_name, obj, btf_fd, verifier_log_level);
+                            let mut data = ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            );
                             if *sleepable {
                                 data.flags = BPF_F_SLEEPABLE;
                             }
@@ -565,32 +1209,108 @@ impl<'a> BpfLoader<'a> {
                         }
                         ProgramSection::BtfTracePoint { .. } => {
                             Program::BtfTracePoint(BtfTracePoint {
-                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    prog_token_fd.clone(),
+                                ),
                             })
                         }
-                        ProgramSection::FEntry { .. } => Program::FEntry(FEntry {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
-                        }),
-                        ProgramSection::FExit { .. } => Program::FExit(FExit {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
-                        }),
+                        ProgramSection::FEntry { sleepable, .. } => {
+                            let mut data = ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            );
+                            if *sleepable {
+                                data.flags = BPF_F_SLEEPABLE;
+                            }
+                            Program::FEntry(FEntry { data })
+                        }
+                        ProgramSection::FExit { sleepable, .. } => {
+                            let mut data = ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            );
+                            if *sleepable {
+                                data.flags = BPF_F_SLEEPABLE;
+                            }
+                            Program::FExit(FExit { data })
+                        }
+                        ProgramSection::Iter { sleepable, .. } => {
+                            let mut data = ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            );
+                            if *sleepable {
+                                data.flags = BPF_F_SLEEPABLE;
+                            }
+                            Program::Iter(Iter { data })
+                        }
                         ProgramSection::Extension { .. } => Program::Extension(Extension {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            ),
                         }),
                         ProgramSection::SkLookup { .. } => Program::SkLookup(SkLookup {
-                            data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                            data: ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            ),
                         }),
                         ProgramSection::CgroupSock { attach_type, .. } => {
                             Program::CgroupSock(CgroupSock {
-                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    prog_token_fd.clone(),
+                                ),
                                 attach_type: *attach_type,
                             })
                         }
                         ProgramSection::CgroupDevice { .. } => {
                             Program::CgroupDevice(CgroupDevice {
-                                data: ProgramData::new(prog_name, obj, btf_fd, verifier_log_level),
+                                data: ProgramData::new(
+                                    prog_name,
+                                    obj,
+                                    btf_fd,
+                                    verifier_log_level,
+                                    prog_token_fd.clone(),
+                                ),
                             })
                         }
+                        ProgramSection::StructOps { sleepable, .. } => {
+                            let mut data = ProgramData::new(
+                                prog_name,
+                                obj,
+                                btf_fd,
+                                verifier_log_level,
+                                prog_token_fd.clone(),
+                            );
+                            if *sleepable {
+                                data.flags = BPF_F_SLEEPABLE;
+                            }
+                            Program::StructOps(StructOps { data })
+                        }
                     }
                 };
                 (name, program)
@@ -601,39 +1321,40 @@ impl<'a> BpfLoader<'a> {
         Ok(Bpf {
             maps: maps?,
             programs,
+            globals,
+            btf,
         })
     }
+
+    fn apply_max_entries(&self, name: &str, obj: &mut obj::Map) -> Result<(), BpfError> {
+        match self.max_entries.get(name) {
+            Some(size) => obj.set_max_entries(*size),
+            None => {
+                if obj.map_type() == BPF_MAP_TYPE_PERF_EVENT_ARRAY as u32 && obj.max_entries() == 0
+                {
+                    obj.set_max_entries(
+                        possible_cpus()
+                            .map_err(|error| BpfError::FileError {
+                                path: PathBuf::from(POSSIBLE_CPUS),
+                                error,
+                            })?
+                            .len() as u32,
+                    );
+                }
+            }
+        }
+        if let Some(size) = self.min_max_entries.get(name) {
+            if obj.max_entries() < *size {
+                obj.set_max_entries(*size);
+            }
+        }
+        Ok(())
+    }
 }
 
 fn parse_map(data: (String, MapData)) -> Result<(String, Map), BpfError> {
-    let name = data.0;
-    let map = data.1;
-    let map_type =
-        bpf_map_type::try_from(map.obj.map_type()).map_err(|e| MapError::InvalidMapType {
-            map_type: e.map_type,
-        })?;
-    let map = match map_type {
-        BPF_MAP_TYPE_ARRAY => Ok(Map::Array(map)),
-        BPF_MAP_TYPE_PERCPU_ARRAY => Ok(Map::PerCpuArray(map)),
-        BPF_MAP_TYPE_PROG_ARRAY => Ok(Map::ProgramArray(map)),
-        BPF_MAP_TYPE_HASH => Ok(Map::HashMap(map)),
-        BPF_MAP_TYPE_LRU_HASH => Ok(Map::LruHashMap(map)),
-        BPF_MAP_TYPE_PERCPU_HASH => Ok(Map::PerCpuHashMap(map)),
-        BPF_MAP_TYPE_LRU_PERCPU_HASH => Ok(Map::PerCpuLruHashMap(map)),
-        BPF_MAP_TYPE_PERF_EVENT_ARRAY => Ok(Map::PerfEventArray(map)),
-        BPF_MAP_TYPE_SOCKHASH => Ok(Map::SockHash(map)),
-        BPF_MAP_TYPE_SOCKMAP => Ok(Map::SockMap(map)),
-        BPF_MAP_TYPE_BLOOM_FILTER => Ok(Map::BloomFilter(map)),
-        BPF_MAP_TYPE_LPM_TRIE => Ok(Map::LpmTrie(map)),
-        BPF_MAP_TYPE_STACK => Ok(Map::Stack(map)),
-        BPF_MAP_TYPE_STACK_TRACE => Ok(Map::StackTraceMap(map)),
-        BPF_MAP_TYPE_QUEUE => Ok(Map::Queue(map)),
-        m => Err(BpfError::MapError(MapError::InvalidMapType {
-            map_type: m as u32,
-        })),
-    }?;
-
-    Ok((name, map))
+    let (name, map) = data;
+    Ok((name, Map::from_map_data(map)?))
 }
 
 impl<'a> Default for BpfLoader<'a> {
@@ -647,6 +1368,8 @@ impl<'a> Default for BpfLoader<'a> {
 pub struct Bpf {
     maps: HashMap<String, Map>,
     programs: HashMap<String, Program>,
+    globals: HashMap<String, GlobalVariable>,
+    btf: Option<Btf>,
 }
 
 impl Bpf {
@@ -697,6 +1420,37 @@ impl Bpf {
             .load(data)
     }
 
+    /// Loads eBPF bytecode from a file without stalling the async executor.
+    ///
+    /// The file is read and the maps are created on a blocking thread, see
+    /// [`Bpf::load_file`] and [`unblock`](crate::util::unblock).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async fn run() -> Result<(), aya::BpfError> {
+    /// use aya::Bpf;
+    ///
+    /// let bpf = Bpf::load_file_async("file.o").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub async fn load_file_async<P: AsRef<Path>>(path: P) -> Result<Bpf, BpfError> {
+        let path = path.as_ref().to_path_buf();
+        crate::util::unblock(move || Bpf::load_file(path)).await
+    }
+
+    /// Loads eBPF bytecode from a buffer without stalling the async executor.
+    ///
+    /// See [`Bpf::load`] and [`unblock`](crate::util::unblock).
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub async fn load_async(data: Vec<u8>) -> Result<Bpf, BpfError> {
+        crate::util::unblock(move || Bpf::load(&data)).await
+    }
+
     /// Returns a reference to the map with the given name.
     ///
     /// The returned type is mostly opaque. In order to do anything useful with it you need to
@@ -792,6 +1546,51 @@ impl Bpf {
         self.programs.get_mut(name)
     }
 
+    /// Runs `f` on the program with the given name without stalling the async
+    /// executor.
+    ///
+    /// Loading a program can take seconds while the verifier runs. The program
+    /// is moved to a blocking thread (see [`unblock`](crate::util::unblock))
+    /// where `f` loads or attaches it, and is put back once `f` returns.
+    /// Returns `None` if there's no program with the given name.
+    ///
+    /// If the returned future is dropped before it completes, the program is
+    /// dropped too once `f` returns.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async fn run(mut bpf: aya::Bpf) -> Result<(), aya::programs::ProgramError> {
+    /// use aya::programs::{Xdp, XdpFlags};
+    ///
+    /// bpf.program_mut_async("xdp", |program| {
+    ///     let program: &mut Xdp = program.try_into()?;
+    ///     program.load()?;
+    ///     program.attach("eth0", XdpFlags::default())?;
+    ///     Ok::<_, aya::programs::ProgramError>(())
+    /// })
+    /// .await
+    /// .unwrap()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub async fn program_mut_async<F, T>(&mut self, name: &str, f: F) -> Option<T>
+    where
+        F: FnOnce(&mut Program) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (name, mut program) = self.programs.remove_entry(name)?;
+        let (program, result) = crate::util::unblock(move || {
+            let result = f(&mut program);
+            (program, result)
+        })
+        .await;
+        self.programs.insert(name, program);
+        Some(result)
+    }
+
     /// An iterator over all the programs.
     ///
     /// # Examples
@@ -832,6 +1631,216 @@ impl Bpf {
     pub fn programs_mut(&mut self) -> impl Iterator<Item = (&str, &mut Program)> {
         self.programs.iter_mut().map(|(s, p)| (s.as_str(), p))
     }
+
+    /// An iterator over the programs of type `T`.
+    ///
+    /// Programs of other types are skipped. To filter on the low level
+    /// program type instead, see [`Program::prog_type`].
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # let bpf = aya::Bpf::load(&[])?;
+    /// use aya::programs::KProbe;
+    ///
+    /// for (name, kprobe) in bpf.programs_of::<KProbe>() {
+    ///     println!("found {:?} `{}`", kprobe.kind(), name);
+    /// }
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn programs_of<'a, T: 'a>(&'a self) -> impl Iterator<Item = (&'a str, &'a T)>
+    where
+        &'a T: TryFrom<&'a Program>,
+    {
+        self.programs()
+            .filter_map(|(name, program)| Some((name, program.try_into().ok()?)))
+    }
+
+    /// An iterator mutably referencing the programs of type `T`.
+    ///
+    /// Programs of other types are skipped.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::programs::{Xdp, XdpFlags};
+    ///
+    /// for (_, xdp) in bpf.programs_of_mut::<Xdp>() {
+    ///     xdp.load()?;
+    ///     xdp.attach("eth0", XdpFlags::default())?;
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn programs_of_mut<'a, T: 'a>(&'a mut self) -> impl Iterator<Item = (&'a str, &'a mut T)>
+    where
+        &'a mut T: TryFrom<&'a mut Program>,
+    {
+        self.programs_mut()
+            .filter_map(|(name, program)| Some((name, program.try_into().ok()?)))
+    }
+
+    /// Returns the BTF of the object, if it has any.
+    ///
+    /// The BTF describes the types used by the programs and maps, and can be
+    /// used to interpret map values without compile-time knowledge of their
+    /// layout, see [`Map::btf_key_type_id`] and [`Map::btf_value_type_id`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let bpf = aya::Bpf::load(&[])?;
+    /// if let (Some(btf), Some(map)) = (bpf.btf(), bpf.map("EVENTS")) {
+    ///     if let Some(type_id) = map.btf_value_type_id() {
+    ///         let ty = btf.type_by_id(type_id)?;
+    ///         println!("{}: {}", btf.type_name(ty)?, btf.type_size(type_id)?);
+    ///     }
+    /// }
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn btf(&self) -> Option<&Btf> {
+        self.btf.as_ref()
+    }
+
+    /// Annotates the verifier log of a program with its source lines.
+    ///
+    /// The verifier only prints the source lines of the instructions when
+    /// the program BTF was loaded in the kernel. This uses the line info of
+    /// the object instead, so the location of a verifier error can be found
+    /// on any kernel. Returns `None` if the program doesn't exist or the
+    /// object has no BTF.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::programs::{ProgramError, Xdp};
+    ///
+    /// let program: &mut Xdp = bpf.program_mut("xdp_prog").unwrap().try_into()?;
+    /// if let Err(ProgramError::LoadError { verifier_log, .. }) = program.load() {
+    ///     let log = bpf
+    ///         .annotate_verifier_log("xdp_prog", &verifier_log)
+    ///         .unwrap_or(verifier_log);
+    ///     eprintln!("{log}");
+    /// }
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn annotate_verifier_log(&self, program: &str, verifier_log: &str) -> Option<String> {
+        let btf = self.btf.as_ref()?;
+        let obj = self.programs.get(program)?.obj()?;
+        Some(annotate_verifier_log(
+            verifier_log,
+            &obj.function.line_info.line_info,
+            btf,
+        ))
+    }
+
+    /// Returns the current value of a global variable.
+    ///
+    /// The value is read from the array map backing the `.bss`, `.data` or
+    /// `.rodata` section holding the variable, so updates made by the eBPF
+    /// programs are visible.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::InvalidName`] if there is no global variable called
+    /// `name` or if its map was removed with [`Bpf::take_map`], and
+    /// [`MapError::InvalidValueSize`] if the size of `T` doesn't match the size
+    /// of the variable.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let bpf = aya::Bpf::load(&[])?;
+    /// let dropped: u64 = bpf.global("DROPPED_PACKETS")?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn global<T: Pod>(&self, name: &str) -> Result<T, MapError> {
+        let (map, var) = self.global_variable(name, mem::size_of::<T>())?;
+        let value = read_global_data(map)?;
+        let bytes = &value[var.offset..var.offset + var.size];
+        // Safety: T is Pod and the size has been checked
+        Ok(unsafe { ptr::read_unaligned(bytes.as_ptr() as *const T) })
+    }
+
+    /// Sets the value of a global variable in the `.bss` or `.data` section.
+    ///
+    /// The whole section is read and written back, so concurrent updates made
+    /// by eBPF programs to other variables of the same section can be lost.
+    /// Variables in `.rodata` are frozen once loaded and can only be set with
+    /// [`BpfLoader::set_global`].
+    ///
+    /// # Errors
+    ///
+    /// See [`Bpf::global`]. Returns [`MapError::SyscallError`] if the section
+    /// is read-only.
+    pub fn set_global<T: Pod>(&mut self, name: &str, value: &T) -> Result<(), MapError> {
+        let (map, var) = self.global_variable(name, mem::size_of::<T>())?;
+        let mut data = read_global_data(map)?;
+        // Safety: T is Pod
+        let bytes = unsafe { bytes_of(value) };
+        data[var.offset..var.offset + var.size].copy_from_slice(bytes);
+        let fd = map.fd_or_err()?;
+        bpf_map_update_elem_ptr(fd, &0u32 as *const _, data.as_mut_ptr(), 0).map_err(
+            |(_, io_error)| MapError::SyscallError {
+                call: "bpf_map_update_elem".to_owned(),
+                io_error,
+            },
+        )?;
+        Ok(())
+    }
+
+    fn global_variable(
+        &self,
+        name: &str,
+        size: usize,
+    ) -> Result<(&MapData, &GlobalVariable), MapError> {
+        let invalid_name = || MapError::InvalidName {
+            name: name.to_owned(),
+        };
+        let var = self.globals.get(name).ok_or_else(invalid_name)?;
+        let map = match self.maps.get(&var.map_name) {
+            Some(Map::Array(map)) => map,
+            _ => return Err(invalid_name()),
+        };
+        if size != var.size {
+            return Err(MapError::InvalidValueSize {
+                size,
+                expected: var.size,
+            });
+        }
+        Ok((map, var))
+    }
+}
+
+// Writes the initial content of a global data map. `.bss` maps only have data
+// when some of their variables were set with `BpfLoader::set_global`.
+fn init_map_data(fd: RawFd, obj: &mut obj::Map) -> Result<(), MapError> {
+    if !obj.data().is_empty() {
+        bpf_map_update_elem_ptr(fd, &0 as *const _, obj.data_mut().as_mut_ptr(), 0).map_err(
+            |(_, io_error)| MapError::SyscallError {
+                call: "bpf_map_update_elem".to_owned(),
+                io_error,
+            },
+        )?;
+    }
+    if obj.section_kind() == BpfSectionKind::Rodata {
+        bpf_map_freeze(fd).map_err(|(_, io_error)| MapError::SyscallError {
+            call: "bpf_map_freeze".to_owned(),
+            io_error,
+        })?;
+    }
+    Ok(())
+}
+
+fn read_global_data(map: &MapData) -> Result<Vec<u8>, MapError> {
+    let fd = map.fd_or_err()?;
+    let mut data = vec![0u8; map.obj.value_size() as usize];
+    bpf_map_lookup_elem_ptr(fd, Some(&0u32), data.as_mut_ptr(), 0).map_err(|(_, io_error)| {
+        MapError::SyscallError {
+            call: "bpf_map_lookup_elem".to_owned(),
+            io_error,
+        }
+    })?;
+    Ok(data)
 }
 
 /// The error type returned by [`Bpf::load_file`] and [`Bpf::load`].
@@ -892,12 +1901,91 @@ pub enum BpfError {
     #[error("program error: {0}")]
     /// A program error
     ProgramError(#[from] ProgramError),
+
+    /// Error raising `RLIMIT_MEMLOCK`
+    #[error("failed to raise RLIMIT_MEMLOCK")]
+    MemlockRlimitError {
+        #[source]
+        /// The original io::Error
+        io_error: io::Error,
+    },
+
+    /// Error creating a BPF token, or duplicating its fd
+    #[error("failed to create or duplicate the BPF token of {path}")]
+    TokenError {
+        /// The path of the BPF filesystem
+        path: PathBuf,
+        #[source]
+        /// The original io::Error
+        io_error: io::Error,
+    },
 }
 
-fn load_btf(raw_btf: Vec<u8>) -> Result<RawFd, BtfError> {
+/// A BPF token, delegating BPF privileges to unprivileged users.
+///
+/// Tokens are created from a BPF filesystem mounted with the `delegate_cmds`,
+/// `delegate_maps`, `delegate_progs` and `delegate_attachs` options, usually by
+/// a privileged container manager. They allow the user namespace owning the
+/// mount to create the delegated map types and load the delegated program
+/// types without `CAP_BPF`, see [BpfLoader::token].
+///
+/// The token is closed on drop.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 6.9.
+#[derive(Debug)]
+pub struct BpfToken {
+    fd: RawFd,
+    path: PathBuf,
+}
+
+impl BpfToken {
+    /// Creates a token from the BPF filesystem mounted at `path`.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<BpfToken, BpfError> {
+        let path = path.as_ref();
+        let token_error = |io_error| BpfError::TokenError {
+            path: path.to_path_buf(),
+            io_error,
+        };
+        let bpffs = fs::File::open(path).map_err(token_error)?;
+        let fd =
+            bpf_token_create(bpffs.as_raw_fd()).map_err(|(_, io_error)| token_error(io_error))?;
+        Ok(BpfToken {
+            fd: fd as RawFd,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl BpfToken {
+    fn try_clone_fd(&self) -> Result<OwnedFd, BpfError> {
+        // Safety: the fd is owned by the token
+        unsafe { BorrowedFd::borrow_raw(self.fd) }
+            .try_clone_to_owned()
+            .map_err(|io_error| BpfError::TokenError {
+                path: self.path.clone(),
+                io_error,
+            })
+    }
+}
+
+impl AsRawFd for BpfToken {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for BpfToken {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+fn load_btf(raw_btf: Vec<u8>, token_fd: Option<RawFd>) -> Result<RawFd, BtfError> {
     let mut logger = VerifierLog::new();
     let ret = retry_with_verifier_logs(10, &mut logger, |logger| {
-        bpf_load_btf(raw_btf.as_slice(), logger)
+        bpf_load_btf(raw_btf.as_slice(), logger, token_fd)
     });
     match ret {
         Ok(fd) => Ok(fd as RawFd),
@@ -938,3 +2026,227 @@ impl<'a, T: Pod> From<&'a T> for GlobalData<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use crate::{
+        generated::{
+            bpf_cmd,
+            bpf_map_type::{BPF_MAP_TYPE_ARRAY, BPF_MAP_TYPE_HASH},
+        },
+        obj::{
+            maps::{bpf_map_def, LegacyMap},
+            KernelVersion,
+        },
+        programs::ProgramSectionKind,
+        sys::{override_syscall, Syscall},
+    };
+
+    use super::*;
+
+    fn new_obj_map(max_entries: u32) -> obj::Map {
+        obj::Map::Legacy(LegacyMap {
+            def: bpf_map_def {
+                map_type: BPF_MAP_TYPE_HASH as u32,
+                key_size: 4,
+                value_size: 4,
+                max_entries,
+                ..Default::default()
+            },
+            section_index: 0,
+            section_kind: BpfSectionKind::Maps,
+            symbol_index: Some(0),
+            data: Vec::new(),
+        })
+    }
+
+    fn max_entries(loader: &BpfLoader<'_>, object_size: u32) -> u32 {
+        let mut obj = new_obj_map(object_size);
+        loader.apply_max_entries("map", &mut obj).unwrap();
+        obj.max_entries()
+    }
+
+    #[test]
+    fn test_min_max_entries() {
+        let mut loader = BpfLoader::new();
+        assert_eq!(max_entries(&loader, 16), 16);
+
+        loader.set_min_max_entries("map", 64);
+        assert_eq!(max_entries(&loader, 16), 64);
+        assert_eq!(max_entries(&loader, 128), 128);
+
+        loader.set_max_entries("map", 32);
+        assert_eq!(max_entries(&loader, 128), 64);
+        loader.set_max_entries("map", 256);
+        assert_eq!(max_entries(&loader, 16), 256);
+
+        let mut other = new_obj_map(16);
+        loader.apply_max_entries("other", &mut other).unwrap();
+        assert_eq!(other.max_entries(), 16);
+    }
+
+    #[test]
+    fn test_inner_map_depth() {
+        let mut loader = BpfLoader::new();
+        loader
+            .set_inner_map("OUTER", "MIDDLE")
+            .set_inner_map("MIDDLE", "INNER");
+        assert_eq!(loader.inner_map_depth("INNER"), 0);
+        assert_eq!(loader.inner_map_depth("MIDDLE"), 1);
+        assert_eq!(loader.inner_map_depth("OUTER"), 2);
+        assert_eq!(loader.inner_map_depth("OTHER"), 0);
+
+        let mut names = vec!["OUTER", "OTHER", "MIDDLE", "INNER"];
+        names.sort_by_key(|name| loader.inner_map_depth(name));
+        assert_eq!(names, ["OTHER", "INNER", "MIDDLE", "OUTER"]);
+
+        // Cycles terminate
+        loader.set_inner_map("INNER", "OUTER");
+        assert_eq!(loader.inner_map_depth("OUTER"), 3);
+    }
+
+    thread_local! {
+        static UPDATED_VALUE: Cell<Option<[u8; 8]>> = const { Cell::new(None) };
+    }
+
+    fn new_bss_map(data: Vec<u8>) -> obj::Map {
+        obj::Map::Legacy(LegacyMap {
+            def: bpf_map_def {
+                map_type: BPF_MAP_TYPE_ARRAY as u32,
+                key_size: 4,
+                value_size: 8,
+                max_entries: 1,
+                ..Default::default()
+            },
+            section_index: 0,
+            section_kind: BpfSectionKind::Bss,
+            symbol_index: None,
+            data,
+        })
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_init_map_data_bss() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                let value =
+                    unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.value } as *const [u8; 8];
+                UPDATED_VALUE.with(|v| v.set(Some(unsafe { *value })));
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EINVAL))),
+        });
+
+        // Nothing was set, the kernel zero-initializes the map
+        init_map_data(42, &mut new_bss_map(Vec::new())).unwrap();
+        assert_eq!(UPDATED_VALUE.with(Cell::take), None);
+
+        // Variables set with `BpfLoader::set_global` are written to the map
+        init_map_data(42, &mut new_bss_map(vec![0, 0, 0, 0, 1, 2, 3, 4])).unwrap();
+        assert_eq!(
+            UPDATED_VALUE.with(Cell::take),
+            Some([0, 0, 0, 0, 1, 2, 3, 4])
+        );
+    }
+
+    #[test]
+    fn test_programs_of() {
+        let program = |section| obj::Program {
+            license: CString::new("GPL").unwrap(),
+            kernel_version: KernelVersion::Any,
+            section,
+            function: obj::Function {
+                address: 0,
+                name: "prog".to_owned(),
+                section_index: object::SectionIndex(0),
+                section_offset: 0,
+                instructions: Vec::new(),
+                func_info: Default::default(),
+                line_info: Default::default(),
+                func_info_rec_size: 0,
+                line_info_rec_size: 0,
+            },
+        };
+        let kprobe = |section, kind| {
+            Program::KProbe(KProbe {
+                data: ProgramData::new(None, program(section), None, 0, None),
+                kind,
+            })
+        };
+        let mut bpf = Bpf {
+            maps: HashMap::new(),
+            programs: HashMap::from([
+                (
+                    "entry".to_owned(),
+                    kprobe(
+                        ProgramSection::KProbe {
+                            name: "entry".to_owned(),
+                        },
+                        ProbeKind::KProbe,
+                    ),
+                ),
+                (
+                    "exit".to_owned(),
+                    kprobe(
+                        ProgramSection::KRetProbe {
+                            name: "exit".to_owned(),
+                        },
+                        ProbeKind::KRetProbe,
+                    ),
+                ),
+                (
+                    "tp".to_owned(),
+                    Program::TracePoint(TracePoint {
+                        data: ProgramData::new(
+                            None,
+                            program(ProgramSection::TracePoint {
+                                name: "tp".to_owned(),
+                            }),
+                            None,
+                            0,
+                            None,
+                        ),
+                    }),
+                ),
+            ]),
+            globals: HashMap::new(),
+            btf: None,
+        };
+
+        let mut kprobes = bpf
+            .programs_of::<KProbe>()
+            .map(|(name, kprobe)| (name, kprobe.kind()))
+            .collect::<Vec<_>>();
+        kprobes.sort_by_key(|(name, _)| *name);
+        assert!(matches!(
+            kprobes[..],
+            [("entry", ProbeKind::KProbe), ("exit", ProbeKind::KRetProbe)]
+        ));
+        assert_eq!(bpf.programs_of::<Xdp>().count(), 0);
+
+        let tracepoints = bpf
+            .programs_of_mut::<TracePoint>()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(tracepoints, ["tp"]);
+
+        let sections = ["entry", "exit", "tp"].map(|name| bpf.program(name).unwrap().section());
+        assert_eq!(
+            sections,
+            [
+                Some(ProgramSectionKind::KProbe),
+                Some(ProgramSectionKind::KRetProbe),
+                Some(ProgramSectionKind::TracePoint),
+            ]
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! Probing of the eBPF features supported by the running kernel.
+//!
+//! The probes load minimal programs or create minimal maps, so applications
+//! can pick a fallback before loading their real objects. They need the same
+//! privileges as loading programs and creating maps.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use aya::features::{self, bpf_map_type, bpf_prog_type};
+//!
+//! let events = if features::is_map_supported(bpf_map_type::BPF_MAP_TYPE_RINGBUF) {
+//!     "EVENTS_RINGBUF"
+//! } else {
+//!     "EVENTS_PERF"
+//! };
+//! let has_fentry = features::is_program_supported(bpf_prog_type::BPF_PROG_TYPE_TRACING);
+//! ```
+use std::collections::{HashMap, HashSet};
+
+pub use crate::generated::{bpf_map_type, bpf_prog_type};
+
+use crate::sys;
+
+/// Returns `true` if programs of type `program_type` can be loaded.
+pub fn is_program_supported(program_type: bpf_prog_type) -> bool {
+    sys::is_prog_type_supported(program_type)
+}
+
+/// Returns `true` if maps of type `map_type` can be created.
+pub fn is_map_supported(map_type: bpf_map_type) -> bool {
+    sys::is_map_type_supported(map_type)
+}
+
+/// Returns `true` if programs of type `program_type` can call the helper
+/// function with id `helper_id`.
+///
+/// Helper ids are the `BPF_FUNC_*` values from the kernel's `bpf.h`, for
+/// example `1` for `bpf_map_lookup_elem`. A helper can exist in the kernel
+/// but not be available to all program types.
+///
+/// Helpers can't be probed for program types that attach to kernel BTF types
+/// (`BPF_PROG_TYPE_TRACING`, `BPF_PROG_TYPE_LSM`, `BPF_PROG_TYPE_EXT` and
+/// `BPF_PROG_TYPE_STRUCT_OPS`), and are reported as unsupported for them.
+pub fn is_helper_supported(program_type: bpf_prog_type, helper_id: u32) -> bool {
+    sys::is_helper_supported(program_type, helper_id)
+}
+
+/// Which helpers are available to which program types on the running kernel.
+///
+/// The matrix is probed once for the given program types and helper ids, and
+/// can then be queried without issuing any more syscalls. Helpers are only
+/// probed for the program types the kernel supports, and are reported as
+/// unsupported for program types that attach to kernel BTF types, see
+/// [`is_helper_supported`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya::features::{bpf_prog_type, HelperMatrix};
+///
+/// // BPF_FUNC_perf_event_output and BPF_FUNC_ringbuf_output
+/// const PERF_EVENT_OUTPUT: u32 = 25;
+/// const RINGBUF_OUTPUT: u32 = 130;
+///
+/// let matrix = HelperMatrix::probe(
+///     &[bpf_prog_type::BPF_PROG_TYPE_KPROBE, bpf_prog_type::BPF_PROG_TYPE_XDP],
+///     &[PERF_EVENT_OUTPUT, RINGBUF_OUTPUT],
+/// );
+/// let program = if matrix.is_helper_supported(bpf_prog_type::BPF_PROG_TYPE_KPROBE, RINGBUF_OUTPUT) {
+///     "log_ringbuf"
+/// } else {
+///     "log_perfbuf"
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct HelperMatrix {
+    kernel_version: Option<(u32, u32, u32)>,
+    programs: HashMap<bpf_prog_type, HashSet<u32>>,
+    helper_ids: Vec<u32>,
+}
+
+impl HelperMatrix {
+    /// Probes the availability of every helper in `helper_ids` for every
+    /// program type in `program_types`.
+    ///
+    /// Helper ids are the `BPF_FUNC_*` values from the kernel's `bpf.h`.
+    pub fn probe(program_types: &[bpf_prog_type], helper_ids: &[u32]) -> HelperMatrix {
+        let programs = program_types
+            .iter()
+            .filter(|prog_type| sys::is_prog_type_supported(**prog_type))
+            .map(|prog_type| {
+                let helpers = helper_ids
+                    .iter()
+                    .copied()
+                    .filter(|helper_id| sys::is_helper_supported(*prog_type, *helper_id))
+                    .collect();
+                (*prog_type, helpers)
+            })
+            .collect();
+
+        HelperMatrix {
+            kernel_version: sys::kernel_version().ok(),
+            programs,
+            helper_ids: helper_ids.to_vec(),
+        }
+    }
+
+    /// Returns the version of the kernel the matrix was probed on, if it
+    /// could be detected.
+    pub fn kernel_version(&self) -> Option<(u32, u32, u32)> {
+        self.kernel_version
+    }
+
+    /// Returns `true` if programs of type `program_type` can be loaded.
+    ///
+    /// Program types that weren't probed are reported as unsupported.
+    pub fn is_program_supported(&self, program_type: bpf_prog_type) -> bool {
+        self.programs.contains_key(&program_type)
+    }
+
+    /// Returns `true` if programs of type `program_type` can call the helper
+    /// function with id `helper_id`.
+    ///
+    /// Helpers and program types that weren't probed are reported as
+    /// unsupported.
+    pub fn is_helper_supported(&self, program_type: bpf_prog_type, helper_id: u32) -> bool {
+        self.programs
+            .get(&program_type)
+            .map(|helpers| helpers.contains(&helper_id))
+            .unwrap_or(false)
+    }
+
+    /// Returns the probed helpers that programs of type `program_type` can
+    /// call, in the order they were passed to [`HelperMatrix::probe`].
+    pub fn helpers(&self, program_type: bpf_prog_type) -> impl Iterator<Item = u32> + '_ {
+        let helpers = self.programs.get(&program_type);
+        self.helper_ids
+            .iter()
+            .copied()
+            .filter(move |helper_id| helpers.map(|h| h.contains(helper_id)).unwrap_or(false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, slice};
+
+    use libc::EINVAL;
+
+    use super::{bpf_prog_type::*, HelperMatrix};
+    use crate::{
+        generated::{bpf_cmd, bpf_insn},
+        sys::{override_syscall, Syscall},
+    };
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_helper_matrix() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_LOAD,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_3 };
+                let insns = unsafe {
+                    slice::from_raw_parts(u.insns as *const bpf_insn, u.insn_cnt as usize)
+                };
+                let supported = match (u.prog_type, insns[0].imm) {
+                    (t, _) if t == BPF_PROG_TYPE_XDP as u32 => false,
+                    (t, _) if t == BPF_PROG_TYPE_TRACING as u32 => {
+                        let msg = b"attach_btf_id 1 is not a function\n";
+                        let log_buf = unsafe {
+                            slice::from_raw_parts_mut(u.log_buf as *mut u8, u.log_size as usize)
+                        };
+                        log_buf[..msg.len()].copy_from_slice(msg);
+                        return Err((-1, io::Error::from_raw_os_error(EINVAL)));
+                    }
+                    // helper calls
+                    (_, 1) => true,
+                    (t, 130) if t == BPF_PROG_TYPE_KPROBE as u32 => true,
+                    (_, 130) => false,
+                    // mov64 r0 = 0
+                    (_, 0) => true,
+                    _ => false,
+                };
+                if supported {
+                    Ok(42)
+                } else {
+                    if u.log_size > 0 {
+                        let msg = b"invalid func unknown#130\n";
+                        let log_buf = unsafe {
+                            slice::from_raw_parts_mut(u.log_buf as *mut u8, u.log_size as usize)
+                        };
+                        log_buf[..msg.len()].copy_from_slice(msg);
+                    }
+                    Err((-1, io::Error::from_raw_os_error(EINVAL)))
+                }
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        let matrix = HelperMatrix::probe(
+            &[
+                BPF_PROG_TYPE_KPROBE,
+                BPF_PROG_TYPE_SOCKET_FILTER,
+                BPF_PROG_TYPE_XDP,
+                BPF_PROG_TYPE_TRACING,
+            ],
+            &[130, 1],
+        );
+        assert_eq!(matrix.kernel_version(), Some((0xff, 0xff, 0xff)));
+        assert!(matrix.is_program_supported(BPF_PROG_TYPE_KPROBE));
+        assert!(!matrix.is_program_supported(BPF_PROG_TYPE_XDP));
+        assert!(!matrix.is_program_supported(BPF_PROG_TYPE_LSM));
+        assert!(matrix.is_program_supported(BPF_PROG_TYPE_TRACING));
+        assert_eq!(matrix.helpers(BPF_PROG_TYPE_TRACING).count(), 0);
+        assert!(matrix.is_helper_supported(BPF_PROG_TYPE_KPROBE, 130));
+        assert!(!matrix.is_helper_supported(BPF_PROG_TYPE_SOCKET_FILTER, 130));
+        assert!(matrix.is_helper_supported(BPF_PROG_TYPE_SOCKET_FILTER, 1));
+        assert!(!matrix.is_helper_supported(BPF_PROG_TYPE_XDP, 1));
+        assert!(!matrix.is_helper_supported(BPF_PROG_TYPE_KPROBE, 2));
+        assert_eq!(
+            matrix.helpers(BPF_PROG_TYPE_KPROBE).collect::<Vec<_>>(),
+            vec![130, 1]
+        );
+        assert_eq!(
+            matrix
+                .helpers(BPF_PROG_TYPE_SOCKET_FILTER)
+                .collect::<Vec<_>>(),
+            vec![1]
+        );
+        assert_eq!(matrix.helpers(BPF_PROG_TYPE_XDP).count(), 0);
+    }
+}
@@ -4,7 +4,9 @@ use std::{
 };
 
 use crate::{
-    maps::{check_bounds, check_kv_size, IterableMap, MapData, MapError},
+    maps::{
+        check_bounds, check_kv_size, lookup_batch, update_batch, IterableMap, MapData, MapError,
+    },
     sys::{bpf_map_lookup_elem, bpf_map_update_elem},
     Pod,
 };
@@ -79,6 +81,17 @@ impl<T: Borrow<MapData>, V: Pod> Array<T, V> {
     pub fn iter(&self) -> impl Iterator<Item = Result<V, MapError>> + '_ {
         (0..self.len()).map(move |i| self.get(&i, 0))
     }
+
+    /// Returns all the elements of the array, reading `batch_size` of them per
+    /// syscall instead of one syscall per element like [`Array::iter`].
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 5.6.
+    pub fn get_batch(&self, batch_size: u32, flags: u64) -> Result<Vec<V>, MapError> {
+        let elements = lookup_batch::<u32, V>(self.inner.borrow(), batch_size, flags, false)?;
+        Ok(elements.into_iter().map(|(_, value)| value).collect())
+    }
 }
 
 impl<T: BorrowMut<MapData>, V: Pod> Array<T, V> {
@@ -100,6 +113,29 @@ impl<T: BorrowMut<MapData>, V: Pod> Array<T, V> {
         })?;
         Ok(())
     }
+
+    /// Sets the values of the elements at `indices` to `values` with a single
+    /// syscall.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::OutOfBounds`] if an index is out of bounds,
+    /// [`MapError::SyscallError`] if `bpf_map_update_batch` fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `indices` and `values` have different lengths.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 5.6.
+    pub fn set_batch(&mut self, indices: &[u32], values: &[V], flags: u64) -> Result<(), MapError> {
+        let data = self.inner.borrow_mut();
+        for index in indices {
+            check_bounds(data, *index)?;
+        }
+        update_batch(data, indices, values, flags).map_err(|(_, e)| e)
+    }
 }
 
 impl<T: Borrow<MapData>, V: Pod> IterableMap<u32, V> for Array<T, V> {
@@ -111,3 +147,121 @@ impl<T: Borrow<MapData>, V: Pod> IterableMap<u32, V> for Array<T, V> {
         self.get(index, 0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use libc::{EFAULT, ENOENT};
+
+    use crate::{
+        bpf_map_def,
+        generated::{bpf_attr, bpf_cmd, bpf_map_type::BPF_MAP_TYPE_ARRAY},
+        obj::{self, maps::LegacyMap, BpfSectionKind},
+        sys::{override_syscall, SysResult, Syscall},
+    };
+
+    use super::*;
+
+    fn new_map() -> MapData {
+        MapData {
+            obj: obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_ARRAY as u32,
+                    key_size: 4,
+                    value_size: 4,
+                    max_entries: 4,
+                    ..Default::default()
+                },
+                section_index: 0,
+                section_kind: BpfSectionKind::Maps,
+                symbol_index: None,
+                data: Vec::new(),
+            }),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        }
+    }
+
+    fn sys_error(value: i32) -> SysResult {
+        Err((-1, io::Error::from_raw_os_error(value)))
+    }
+
+    // The kernel writes the number of elements it copied back to the
+    // attributes.
+    fn set_batch_count(attr: &bpf_attr, count: u32) {
+        unsafe { *(std::ptr::addr_of!(attr.batch.count) as *mut u32) = count };
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_get_batch() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_BATCH,
+                attr,
+            } => {
+                let batch = unsafe { attr.batch };
+                // Arrays continue after the last key of the previous batch.
+                let start = match batch.in_batch as *const u32 {
+                    p if p.is_null() => 0,
+                    p => unsafe { *p + 1 },
+                };
+                let end = (start + batch.count).min(4);
+                for key in start..end {
+                    let i = (key - start) as usize;
+                    unsafe {
+                        *(batch.keys as *mut u32).add(i) = key;
+                        *(batch.values as *mut u32).add(i) = key * 10;
+                    }
+                }
+                set_batch_count(attr, end - start);
+                unsafe { *(batch.out_batch as *mut u32) = end - 1 };
+                match end {
+                    4 => sys_error(ENOENT),
+                    _ => Ok(0),
+                }
+            }
+            _ => sys_error(EFAULT),
+        });
+        let map = new_map();
+        let array = Array::<_, u32>::new(&map).unwrap();
+        assert_eq!(array.get_batch(3, 0).unwrap(), &[0, 10, 20, 30]);
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_set_batch() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_BATCH,
+                attr,
+            } => {
+                let batch = unsafe { attr.batch };
+                let values = unsafe {
+                    std::slice::from_raw_parts(batch.values as *const u32, batch.count as usize)
+                };
+                assert_eq!(values, &[10, 30]);
+                Ok(0)
+            }
+            _ => sys_error(EFAULT),
+        });
+        let mut map = new_map();
+        let mut array = Array::<_, u32>::new(&mut map).unwrap();
+        assert!(array.set_batch(&[1, 3], &[10, 30], 0).is_ok());
+        assert!(matches!(
+            array.set_batch(&[1, 4], &[10, 40], 0),
+            Err(MapError::OutOfBounds {
+                index: 4,
+                max_entries: 4
+            })
+        ));
+    }
+}
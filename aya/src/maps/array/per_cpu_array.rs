@@ -1,5 +1,6 @@
 use std::{
     borrow::{Borrow, BorrowMut},
+    iter::Sum,
     marker::PhantomData,
 };
 
@@ -31,12 +32,12 @@ use crate::{
 /// # }
 /// # let mut bpf = aya::Bpf::load(&[])?;
 /// use aya::maps::{PerCpuArray, PerCpuValues};
-/// use aya::util::nr_cpus;
+/// use aya::util::nr_possible_cpus;
 ///
 /// let mut array = PerCpuArray::try_from(bpf.map_mut("ARRAY").unwrap())?;
 ///
 /// // set array[1] = 42 for all cpus
-/// let nr_cpus = nr_cpus()?;
+/// let nr_cpus = nr_possible_cpus()?;
 /// array.set(1, PerCpuValues::try_from(vec![42u32; nr_cpus])?, 0)?;
 ///
 /// // retrieve the values at index 1 for all cpus
@@ -93,6 +94,38 @@ impl<T: Borrow<MapData>, V: Pod> PerCpuArray<T, V> {
         value.ok_or(MapError::KeyNotFound)
     }
 
+    /// Returns the sum of the values of all the CPUs stored at the given index.
+    ///
+    /// This is a shortcut for [`get`](PerCpuArray::get) followed by
+    /// [`PerCpuValues::sum`], typically used to read counters.
+    pub fn get_sum(&self, index: &u32, flags: u64) -> Result<V, MapError>
+    where
+        V: Sum,
+    {
+        self.get(index, flags).map(|values| values.sum())
+    }
+
+    /// Returns the maximum of the values of all the CPUs stored at the given index.
+    pub fn get_max(&self, index: &u32, flags: u64) -> Result<V, MapError>
+    where
+        V: Ord,
+    {
+        self.get(index, flags).map(|values| values.max())
+    }
+
+    /// Returns the values of all the CPUs stored at the given index, combined into one
+    /// with `f`.
+    ///
+    /// See [`PerCpuValues::reduce`].
+    pub fn get_reduced(
+        &self,
+        index: &u32,
+        flags: u64,
+        f: impl FnMut(V, V) -> V,
+    ) -> Result<V, MapError> {
+        self.get(index, flags).map(|values| values.reduce(f))
+    }
+
     /// An iterator over the elements of the array. The iterator item type is
     /// `Result<PerCpuValues<V>, MapError>`.
     pub fn iter(&self) -> impl Iterator<Item = Result<PerCpuValues<V>, MapError>> + '_ {
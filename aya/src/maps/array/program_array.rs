@@ -5,10 +5,13 @@ use std::{
     os::unix::prelude::{AsRawFd, RawFd},
 };
 
+use libc::ENOENT;
+
 use crate::{
     maps::{check_bounds, check_kv_size, MapData, MapError, MapKeys},
     programs::ProgramFd,
     sys::{bpf_map_delete_elem, bpf_map_update_elem},
+    Bpf,
 };
 
 /// An array of eBPF program file descriptors used as a jump table.
@@ -46,6 +49,24 @@ use crate::{
 /// prog_array.set(2, prog_2_fd, flags);
 /// # Ok::<(), aya::BpfError>(())
 /// ```
+///
+/// Programs can also be looked up by name among the programs loaded by
+/// [`Bpf`]:
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::ProgramArray;
+///
+/// let mut prog_array = ProgramArray::try_from(bpf.take_map("JUMP_TABLE").unwrap())?;
+///
+/// // bpf_tail_call(ctx, JUMP_TABLE, 0) will jump to parse_ipv4 and
+/// // bpf_tail_call(ctx, JUMP_TABLE, 1) will jump to parse_ipv6
+/// prog_array.populate(&bpf, &["parse_ipv4", "parse_ipv6"])?;
+///
+/// // replace parse_ipv6
+/// prog_array.set_program(1, "parse_ipv6_v2", &bpf, 0)?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
 #[doc(alias = "BPF_MAP_TYPE_PROG_ARRAY")]
 pub struct ProgramArray<T> {
     inner: T,
@@ -88,6 +109,49 @@ impl<T: BorrowMut<MapData>> ProgramArray<T> {
         Ok(())
     }
 
+    /// Sets the program with the given name as the target of the given index
+    /// in the jump table.
+    ///
+    /// The program is looked up among the programs of `bpf` and must have been
+    /// loaded.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::ProgramNotFound`] if `bpf` has no program called
+    /// `name`, and [`MapError::ProgramNotLoaded`] if the program isn't loaded.
+    pub fn set_program(
+        &mut self,
+        index: u32,
+        name: &str,
+        bpf: &Bpf,
+        flags: u64,
+    ) -> Result<(), MapError> {
+        check_bounds(self.inner.borrow(), index)?;
+        let program = program_fd(bpf, name)?;
+        self.set(index, program, flags)
+    }
+
+    /// Populates the jump table with the programs with the given names.
+    ///
+    /// The program at position `i` of `names` becomes the target of index
+    /// `i`. All the programs are looked up and the indexes checked against the
+    /// size of the map before the map is modified, so an unknown or unloaded
+    /// program leaves the jump table untouched.
+    pub fn populate(&mut self, bpf: &Bpf, names: &[&str]) -> Result<(), MapError> {
+        let programs = names
+            .iter()
+            .map(|name| program_fd(bpf, name))
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(last) = programs.len().checked_sub(1) {
+            check_bounds(self.inner.borrow(), last as u32)?;
+        }
+
+        for (index, program) in programs.into_iter().enumerate() {
+            self.set(index as u32, program, 0)?;
+        }
+        Ok(())
+    }
+
     /// Clears the value at index in the jump table.
     ///
     /// Calling `bpf_tail_call(ctx, prog_array, index)` on an index that has been cleared returns an
@@ -104,4 +168,134 @@ impl<T: BorrowMut<MapData>> ProgramArray<T> {
                 io_error,
             })
     }
+
+    /// Clears all the indexes of the jump table.
+    ///
+    /// The jump table isn't cleared atomically: the indexes are deleted one
+    /// at a time, so while this runs a concurrent `bpf_tail_call()` may still
+    /// jump to the programs of the indexes not cleared yet. If this returns an
+    /// error, the indexes cleared before the failure stay cleared.
+    pub fn clear(&mut self) -> Result<(), MapError> {
+        let indices = self.indices().collect::<Result<Vec<_>, _>>()?;
+        let fd = self.inner.borrow_mut().fd_or_err()?;
+
+        for index in indices {
+            match bpf_map_delete_elem(fd, &index) {
+                Ok(_) => {}
+                // the index was cleared concurrently
+                Err((_, io_error)) if io_error.raw_os_error() == Some(ENOENT) => {}
+                Err((_, io_error)) => {
+                    return Err(MapError::SyscallError {
+                        call: "bpf_map_delete_elem".to_owned(),
+                        io_error,
+                    })
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn program_fd(bpf: &Bpf, name: &str) -> Result<ProgramFd, MapError> {
+    bpf.program(name)
+        .ok_or_else(|| MapError::ProgramNotFound {
+            name: name.to_owned(),
+        })?
+        .fd()
+        .ok_or(MapError::ProgramNotLoaded)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::atomic::{AtomicU32, Ordering},
+    };
+
+    use libc::EFAULT;
+
+    use crate::{
+        bpf_map_def,
+        generated::{bpf_attr, bpf_cmd, bpf_map_type::BPF_MAP_TYPE_PROG_ARRAY},
+        obj::{self, maps::LegacyMap, BpfSectionKind},
+        sys::{override_syscall, SysResult, Syscall},
+    };
+
+    use super::*;
+
+    fn new_map() -> MapData {
+        MapData {
+            obj: obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_PROG_ARRAY as u32,
+                    key_size: 4,
+                    value_size: 4,
+                    max_entries: 4,
+                    ..Default::default()
+                },
+                section_index: 0,
+                section_kind: BpfSectionKind::Maps,
+                symbol_index: None,
+                data: Vec::new(),
+            }),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        }
+    }
+
+    fn sys_error(value: i32) -> SysResult {
+        Err((-1, io::Error::from_raw_os_error(value)))
+    }
+
+    fn bpf_key(attr: &bpf_attr) -> Option<u32> {
+        match unsafe { attr.__bindgen_anon_2.key } as *const u32 {
+            p if p.is_null() => None,
+            p => Some(unsafe { *p }),
+        }
+    }
+
+    fn set_next_key(attr: &bpf_attr, next: u32) -> SysResult {
+        let key = unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.next_key } as *mut u32;
+        unsafe { *key = next };
+        Ok(0)
+    }
+
+    static CLEARED: AtomicU32 = AtomicU32::new(0);
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_clear() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_GET_NEXT_KEY,
+                attr,
+            } => match bpf_key(attr) {
+                None => set_next_key(attr, 0),
+                Some(0) => set_next_key(attr, 2),
+                Some(_) => sys_error(ENOENT),
+            },
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_DELETE_ELEM,
+                attr,
+            } => match bpf_key(attr) {
+                Some(0) => {
+                    CLEARED.fetch_add(1, Ordering::SeqCst);
+                    Ok(0)
+                }
+                // cleared concurrently
+                Some(2) => sys_error(ENOENT),
+                _ => sys_error(EFAULT),
+            },
+            _ => sys_error(EFAULT),
+        });
+
+        let mut map = new_map();
+        let mut prog_array = ProgramArray::new(&mut map).unwrap();
+        assert!(prog_array.clear().is_ok());
+        assert_eq!(CLEARED.load(Ordering::SeqCst), 1);
+    }
 }
@@ -9,6 +9,16 @@ use crate::{
 
 /// A Bloom Filter.
 ///
+/// Values are pushed into the filter with [`insert`](BloomFilter::insert) and
+/// tested with [`contains`](BloomFilter::contains). A bloom filter can have
+/// false positives but no false negatives: `contains` may succeed for a value
+/// which was never inserted, but never fails for one which was.
+///
+/// The number of hash functions used for each value defaults to 5. It can be
+/// set between 1 and 15 by maps defined in the `.maps` section with the
+/// `map_extra` field, for example `__uint(map_extra, 3)`. More hash functions
+/// lower the false positive rate at the expense of slower operations.
+///
 /// # Minimum kernel version
 ///
 /// The minimum kernel version required to use this feature is 5.16.
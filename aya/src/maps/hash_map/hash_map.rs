@@ -4,7 +4,10 @@ use std::{
 };
 
 use crate::{
-    maps::{check_kv_size, hash_map, IterableMap, MapData, MapError, MapIter, MapKeys},
+    maps::{
+        check_kv_size, delete_batch, hash_map, lookup_batch, update_batch, IterableMap, MapData,
+        MapError, MapIter, MapKeys,
+    },
     sys::bpf_map_lookup_elem,
     Pod,
 };
@@ -74,6 +77,19 @@ impl<T: Borrow<MapData>, K: Pod, V: Pod> HashMap<T, K, V> {
     pub fn keys(&self) -> MapKeys<'_, K> {
         MapKeys::new(self.inner.borrow())
     }
+
+    /// Returns all the key-value pairs, in arbitrary order, reading
+    /// `batch_size` of them per syscall instead of two syscalls per pair like
+    /// [`HashMap::iter`].
+    ///
+    /// Elements inserted or removed concurrently may or may not be returned.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 5.6.
+    pub fn get_batch(&self, batch_size: u32, flags: u64) -> Result<Vec<(K, V)>, MapError> {
+        lookup_batch(self.inner.borrow(), batch_size, flags, false)
+    }
 }
 
 impl<T: BorrowMut<MapData>, K: Pod, V: Pod> HashMap<T, K, V> {
@@ -91,6 +107,39 @@ impl<T: BorrowMut<MapData>, K: Pod, V: Pod> HashMap<T, K, V> {
     pub fn remove(&mut self, key: &K) -> Result<(), MapError> {
         hash_map::remove(self.inner.borrow_mut(), key)
     }
+
+    /// Removes all the key-value pairs and returns them, `batch_size` of them
+    /// per syscall.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 5.6.
+    pub fn drain_batch(&mut self, batch_size: u32, flags: u64) -> Result<Vec<(K, V)>, MapError> {
+        lookup_batch(self.inner.borrow(), batch_size, flags, true)
+    }
+
+    /// Inserts the key-value pairs of `keys` and `values` with a single
+    /// syscall.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `keys` and `values` have different lengths.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 5.6.
+    pub fn insert_batch(&mut self, keys: &[K], values: &[V], flags: u64) -> Result<(), MapError> {
+        update_batch(self.inner.borrow_mut(), keys, values, flags).map_err(|(_, e)| e)
+    }
+
+    /// Removes `keys` from the map with a single syscall.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 5.6.
+    pub fn remove_batch(&mut self, keys: &[K]) -> Result<(), MapError> {
+        delete_batch(self.inner.borrow_mut(), keys)
+    }
 }
 
 impl<T: Borrow<MapData>, K: Pod, V: Pod> IterableMap<K, V> for HashMap<T, K, V> {
@@ -107,7 +156,7 @@ impl<T: Borrow<MapData>, K: Pod, V: Pod> IterableMap<K, V> for HashMap<T, K, V>
 mod tests {
     use std::io;
 
-    use libc::{EFAULT, ENOENT};
+    use libc::{EFAULT, EINVAL, ENOENT, ENOSPC};
 
     use crate::{
         bpf_map_def,
@@ -539,6 +588,224 @@ mod tests {
         assert!(matches!(keys.next(), None));
     }
 
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_get_batch() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_BATCH,
+                attr,
+            } => {
+                let batch = unsafe { attr.batch };
+                let in_batch = match batch.in_batch as *const u32 {
+                    p if p.is_null() => 0,
+                    p => unsafe { *p },
+                };
+                for i in 0..batch.count as usize {
+                    let key = (in_batch * batch.count + i as u32 + 1) * 10;
+                    unsafe {
+                        *(batch.keys as *mut u32).add(i) = key;
+                        *(batch.values as *mut u32).add(i) = key * 10;
+                    }
+                }
+                unsafe { *(batch.out_batch as *mut u32) = in_batch + 1 };
+                match in_batch {
+                    0 => Ok(0),
+                    _ => sys_error(ENOENT),
+                }
+            }
+            _ => sys_error(EFAULT),
+        });
+        let map = MapData {
+            obj: new_obj_map(),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        };
+        let hm = HashMap::<_, u32, u32>::new(&map).unwrap();
+        let items = hm.get_batch(2, 0).unwrap();
+        assert_eq!(&items, &[(10, 100), (20, 200), (30, 300), (40, 400)])
+    }
+
+    // The kernel writes the number of elements it copied back to the
+    // attributes.
+    fn set_batch_count(attr: &bpf_attr, count: u32) {
+        unsafe { *(std::ptr::addr_of!(attr.batch.count) as *mut u32) = count };
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_get_batch_bucket_too_large() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_BATCH,
+                attr,
+            } => {
+                let batch = unsafe { attr.batch };
+                // A bucket of 3 elements doesn't fit in smaller batches.
+                if batch.count < 3 {
+                    set_batch_count(attr, 0);
+                    return sys_error(ENOSPC);
+                }
+                for i in 0..3 {
+                    unsafe {
+                        *(batch.keys as *mut u32).add(i) = i as u32;
+                        *(batch.values as *mut u32).add(i) = i as u32 * 10;
+                    }
+                }
+                set_batch_count(attr, 3);
+                sys_error(ENOENT)
+            }
+            _ => sys_error(EFAULT),
+        });
+        let map = MapData {
+            obj: new_obj_map(),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        };
+        let hm = HashMap::<_, u32, u32>::new(&map).unwrap();
+        let items = hm.get_batch(1, 0).unwrap();
+        assert_eq!(&items, &[(0, 0), (1, 10), (2, 20)])
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_get_batch_error() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_BATCH,
+                attr,
+            } => {
+                set_batch_count(attr, 1);
+                sys_error(EFAULT)
+            }
+            _ => sys_error(EINVAL),
+        });
+        let map = MapData {
+            obj: new_obj_map(),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        };
+        let hm = HashMap::<_, u32, u32>::new(&map).unwrap();
+        assert!(matches!(
+            hm.get_batch(2, 0),
+            Err(MapError::SyscallError { call, io_error }) if call == "bpf_map_lookup_batch" && io_error.raw_os_error() == Some(EFAULT)
+        ));
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_drain_batch() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_AND_DELETE_BATCH,
+                attr,
+            } => {
+                let batch = unsafe { attr.batch };
+                unsafe {
+                    *(batch.keys as *mut u32) = 10;
+                    *(batch.values as *mut u32) = 100;
+                }
+                set_batch_count(attr, 1);
+                sys_error(ENOENT)
+            }
+            _ => sys_error(EFAULT),
+        });
+        let mut map = MapData {
+            obj: new_obj_map(),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        };
+        let mut hm = HashMap::<_, u32, u32>::new(&mut map).unwrap();
+        assert_eq!(&hm.drain_batch(4, 0).unwrap(), &[(10, 100)]);
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_insert_batch() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_BATCH,
+                attr,
+            } => {
+                let batch = unsafe { attr.batch };
+                let keys = unsafe {
+                    std::slice::from_raw_parts(batch.keys as *const u32, batch.count as usize)
+                };
+                let values = unsafe {
+                    std::slice::from_raw_parts(batch.values as *const u32, batch.count as usize)
+                };
+                assert_eq!(keys, &[1, 2]);
+                assert_eq!(values, &[10, 20]);
+                assert_eq!(batch.elem_flags, 2);
+                Ok(0)
+            }
+            _ => sys_error(EFAULT),
+        });
+        let mut map = MapData {
+            obj: new_obj_map(),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        };
+        let mut hm = HashMap::<_, u32, u32>::new(&mut map).unwrap();
+        assert!(hm.insert_batch(&[1, 2], &[10, 20], 2).is_ok());
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_remove_batch() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_DELETE_BATCH,
+                attr,
+            } => {
+                let batch = unsafe { attr.batch };
+                let keys = unsafe {
+                    std::slice::from_raw_parts(batch.keys as *const u32, batch.count as usize)
+                };
+                match keys {
+                    [1, 2] => Ok(0),
+                    _ => sys_error(ENOENT),
+                }
+            }
+            _ => sys_error(EFAULT),
+        });
+        let mut map = MapData {
+            obj: new_obj_map(),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        };
+        let mut hm = HashMap::<_, u32, u32>::new(&mut map).unwrap();
+        assert!(hm.remove_batch(&[1, 2]).is_ok());
+        assert!(matches!(
+            hm.remove_batch(&[3]),
+            Err(MapError::SyscallError { call, .. }) if call == "bpf_map_delete_batch"
+        ));
+    }
+
     #[test]
     // Syscall overrides are performing integer-to-pointer conversions, which
     // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
@@ -1,6 +1,7 @@
 //! Per-CPU hash map.
 use std::{
     borrow::{Borrow, BorrowMut},
+    iter::Sum,
     marker::PhantomData,
 };
 
@@ -73,6 +74,38 @@ impl<T: Borrow<MapData>, K: Pod, V: Pod> PerCpuHashMap<T, K, V> {
         values.ok_or(MapError::KeyNotFound)
     }
 
+    /// Returns the sum of the values of all the CPUs associated with the key.
+    ///
+    /// This is a shortcut for [`get`](PerCpuHashMap::get) followed by
+    /// [`PerCpuValues::sum`], typically used to read counters.
+    pub fn get_sum(&self, key: &K, flags: u64) -> Result<V, MapError>
+    where
+        V: Sum,
+    {
+        self.get(key, flags).map(|values| values.sum())
+    }
+
+    /// Returns the maximum of the values of all the CPUs associated with the key.
+    pub fn get_max(&self, key: &K, flags: u64) -> Result<V, MapError>
+    where
+        V: Ord,
+    {
+        self.get(key, flags).map(|values| values.max())
+    }
+
+    /// Returns the values of all the CPUs associated with the key, combined into one
+    /// with `f`.
+    ///
+    /// See [`PerCpuValues::reduce`].
+    pub fn get_reduced(
+        &self,
+        key: &K,
+        flags: u64,
+        f: impl FnMut(V, V) -> V,
+    ) -> Result<V, MapError> {
+        self.get(key, flags).map(|values| values.reduce(f))
+    }
+
     /// An iterator visiting all key-value pairs in arbitrary order. The
     /// iterator item type is `Result<(K, PerCpuValues<V>), MapError>`.
     pub fn iter(&self) -> MapIter<'_, K, PerCpuValues<V>, Self> {
@@ -103,14 +136,14 @@ impl<T: BorrowMut<MapData>, K: Pod, V: Pod> PerCpuHashMap<T, K, V> {
     /// # }
     /// # let mut bpf = aya::Bpf::load(&[])?;
     /// use aya::maps::{PerCpuHashMap, PerCpuValues};
-    /// use aya::util::nr_cpus;
+    /// use aya::util::nr_possible_cpus;
     ///
     /// const RETRIES: u8 = 1;
     ///
     /// let mut hm = PerCpuHashMap::<_, u8, u32>::try_from(bpf.map_mut("PER_CPU_STORAGE").unwrap())?;
     /// hm.insert(
     ///     RETRIES,
-    ///     PerCpuValues::try_from(vec![3u32; nr_cpus()?])?,
+    ///     PerCpuValues::try_from(vec![3u32; nr_possible_cpus()?])?,
     ///     0,
     /// )?;
     /// # Ok::<(), Error>(())
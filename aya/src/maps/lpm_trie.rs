@@ -4,14 +4,22 @@ use std::{
     marker::PhantomData,
 };
 
+use libc::EINVAL;
+
 use crate::{
-    maps::{check_kv_size, IterableMap, MapData, MapError, MapIter, MapKeys},
+    maps::{check_kv_size, update_batch, IterableMap, MapData, MapError, MapIter, MapKeys},
     sys::{bpf_map_delete_elem, bpf_map_get_next_key, bpf_map_lookup_elem, bpf_map_update_elem},
     Pod,
 };
 
+// Returned by the kernel when a map doesn't implement an operation.
+const ENOTSUPP: i32 = 524;
+
 /// A Longest Prefix Match Trie.
 ///
+/// Large prefix tables should be loaded with [`LpmTrie::insert_all`], which
+/// inserts them with as few syscalls as the kernel allows.
+///
 /// # Minimum kernel version
 ///
 /// The minimum kernel version required to use this feature is 4.20.
@@ -162,6 +170,61 @@ impl<T: BorrowMut<MapData>, K: Pod, V: Pod> LpmTrie<T, K, V> {
         Ok(())
     }
 
+    /// Inserts all the key value pairs of `entries` into the map.
+    ///
+    /// The entries are sorted by prefix length, shortest first, and inserted
+    /// with a single `BPF_MAP_UPDATE_BATCH` syscall. On kernels which don't
+    /// support batch operations on LPM tries, they are inserted one at a time
+    /// instead.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::maps::lpm_trie::{Key, LpmTrie};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let mut routes = LpmTrie::try_from(bpf.map_mut("ROUTES").unwrap())?;
+    /// let table = [
+    ///     (Ipv4Addr::new(10, 0, 0, 0), 8, 1u32),
+    ///     (Ipv4Addr::new(10, 1, 0, 0), 16, 2),
+    /// ];
+    /// routes.insert_all(
+    ///     table
+    ///         .iter()
+    ///         .map(|(addr, len, hop)| (Key::new(*len, u32::from(*addr).to_be()), *hop)),
+    ///     0,
+    /// )?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn insert_all(
+        &mut self,
+        entries: impl IntoIterator<Item = (Key<K>, V)>,
+        flags: u64,
+    ) -> Result<(), MapError> {
+        let mut entries = entries.into_iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(key, _)| key.prefix_len);
+        let (keys, values): (Vec<_>, Vec<_>) = entries.into_iter().unzip();
+
+        // Kernels without batch operations on LPM tries fail with ENOTSUPP,
+        // kernels without the command at all with EINVAL before inserting any
+        // entry. EINVAL after some entries were inserted is a bad entry.
+        let inserted = match update_batch(self.inner.borrow_mut(), &keys, &values, flags) {
+            Ok(()) => return Ok(()),
+            Err((inserted, MapError::SyscallError { io_error, .. }))
+                if io_error.raw_os_error() == Some(ENOTSUPP)
+                    || (inserted == 0 && io_error.raw_os_error() == Some(EINVAL)) =>
+            {
+                inserted
+            }
+            Err((_, e)) => return Err(e),
+        };
+        for (key, value) in keys.iter().zip(values).skip(inserted) {
+            self.insert(key, value, flags)?;
+        }
+        Ok(())
+    }
+
     /// Removes an element from the map.
     ///
     /// Both the prefix and data must match exactly - this method does not do a longest prefix match.
@@ -250,7 +313,7 @@ mod tests {
         sys::{override_syscall, SysResult, Syscall},
     };
     use libc::{EFAULT, ENOENT};
-    use std::{io, mem, net::Ipv4Addr};
+    use std::{cell::RefCell, io, mem, net::Ipv4Addr};
 
     fn new_obj_map() -> obj::Map {
         obj::Map::Legacy(LegacyMap {
@@ -417,6 +480,104 @@ mod tests {
         assert!(trie.insert(&key, 1, 0).is_ok());
     }
 
+    thread_local! {
+        static PREFIX_LENS: RefCell<Vec<u32>> = const { RefCell::new(Vec::new()) };
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_insert_all_fallback() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_BATCH,
+                ..
+            } => sys_error(ENOTSUPP),
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                let key = unsafe { attr.__bindgen_anon_2.key } as *const Key<u32>;
+                let prefix_len = unsafe { (*key).prefix_len };
+                PREFIX_LENS.with(|lens| lens.borrow_mut().push(prefix_len));
+                Ok(1)
+            }
+            _ => sys_error(EFAULT),
+        });
+
+        let mut map = MapData {
+            obj: new_obj_map(),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        };
+
+        let mut trie = LpmTrie::<_, u32, u32>::new(&mut map).unwrap();
+        let ipaddr = u32::from(Ipv4Addr::new(10, 1, 2, 0)).to_be();
+        let entries = [24, 8, 16].map(|len| (Key::new(len, ipaddr), len));
+        assert!(trie.insert_all(entries, 0).is_ok());
+        PREFIX_LENS.with(|lens| assert_eq!(*lens.borrow(), [8, 16, 24]));
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_insert_all_no_batch_command() {
+        override_syscall(|call| match call {
+            // Old kernels reject the command without touching the count.
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_BATCH,
+                ..
+            } => sys_error(EINVAL),
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                let key = unsafe { attr.__bindgen_anon_2.key } as *const Key<u32>;
+                let prefix_len = unsafe { (*key).prefix_len };
+                PREFIX_LENS.with(|lens| lens.borrow_mut().push(prefix_len));
+                Ok(1)
+            }
+            _ => sys_error(EFAULT),
+        });
+
+        let mut map = MapData {
+            obj: new_obj_map(),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        };
+
+        let mut trie = LpmTrie::<_, u32, u32>::new(&mut map).unwrap();
+        let ipaddr = u32::from(Ipv4Addr::new(10, 1, 2, 0)).to_be();
+        let entries = [16, 8].map(|len| (Key::new(len, ipaddr), len));
+        assert!(trie.insert_all(entries, 0).is_ok());
+        PREFIX_LENS.with(|lens| assert_eq!(*lens.borrow(), [8, 16]));
+    }
+
+    #[test]
+    fn test_insert_all_syscall_error() {
+        override_syscall(|_| sys_error(EFAULT));
+
+        let mut map = MapData {
+            obj: new_obj_map(),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        };
+
+        let mut trie = LpmTrie::<_, u32, u32>::new(&mut map).unwrap();
+        let ipaddr = u32::from(Ipv4Addr::new(10, 1, 2, 0)).to_be();
+        assert!(matches!(
+            trie.insert_all([(Key::new(8, ipaddr), 8)], 0),
+            Err(MapError::SyscallError { call, io_error }) if call == "bpf_map_update_batch" && io_error.raw_os_error() == Some(EFAULT)
+        ));
+    }
+
     #[test]
     fn test_remove_syscall_error() {
         override_syscall(|_| sys_error(EFAULT));
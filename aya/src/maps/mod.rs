@@ -39,26 +39,32 @@
 use std::{
     ffi::CString,
     fmt, io,
+    iter::Sum,
     marker::PhantomData,
     mem,
     ops::Deref,
-    os::unix::{io::RawFd, prelude::AsRawFd},
+    os::{
+        fd::{AsFd, BorrowedFd, IntoRawFd},
+        unix::{io::RawFd, prelude::AsRawFd},
+    },
     path::Path,
     ptr,
 };
 
-use libc::{getrlimit, rlimit, RLIMIT_MEMLOCK, RLIM_INFINITY};
+use libc::{getrlimit, rlimit, ENOENT, ENOSPC, RLIMIT_MEMLOCK, RLIM_INFINITY};
 use log::warn;
 use thiserror::Error;
 
 use crate::{
+    generated::bpf_map_type,
     obj::{self, parse_map_info},
     pin::PinError,
     sys::{
-        bpf_create_map, bpf_get_object, bpf_map_get_info_by_fd, bpf_map_get_next_key,
+        bpf_create_map, bpf_get_object, bpf_map_delete_batch, bpf_map_freeze,
+        bpf_map_get_info_by_fd, bpf_map_get_next_key, bpf_map_lookup_batch, bpf_map_update_batch,
         bpf_pin_object, kernel_version,
     },
-    util::nr_cpus,
+    util::{memory_accounting, nr_possible_cpus, MemoryAccounting},
     PinningType, Pod,
 };
 
@@ -66,24 +72,34 @@ pub mod array;
 pub mod bloom_filter;
 pub mod hash_map;
 pub mod lpm_trie;
+pub mod of_maps;
 pub mod perf;
 pub mod queue;
+pub mod ring_buf;
 pub mod sock;
 pub mod stack;
 pub mod stack_trace;
+pub mod storage;
+pub mod user_ring_buf;
+pub mod xdp;
 
 pub use array::{Array, PerCpuArray, ProgramArray};
 pub use bloom_filter::BloomFilter;
 pub use hash_map::{HashMap, PerCpuHashMap};
 pub use lpm_trie::LpmTrie;
+pub use of_maps::{ArrayOfMaps, HashOfMaps};
 #[cfg(feature = "async")]
 #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
 pub use perf::AsyncPerfEventArray;
 pub use perf::PerfEventArray;
 pub use queue::Queue;
+pub use ring_buf::RingBuf;
 pub use sock::{SockHash, SockMap};
 pub use stack::Stack;
 pub use stack_trace::StackTraceMap;
+pub use storage::{InodeStorage, SkStorage, TaskStorage};
+pub use user_ring_buf::UserRingBuf;
+pub use xdp::{CpuMap, DevMap, DevMapHash, XskMap};
 
 #[derive(Error, Debug)]
 /// Errors occuring from working with Maps
@@ -160,10 +176,28 @@ pub enum MapError {
     #[error("element not found")]
     ElementNotFound,
 
+    /// Not enough space left in the map
+    #[error("not enough space left for {size} bytes")]
+    NoSpace {
+        /// Size requested
+        size: usize,
+    },
+
     /// Progam Not Loaded
     #[error("the program is not loaded")]
     ProgramNotLoaded,
 
+    /// The map doesn't support chained programs
+    #[error("the map values don't support chained programs")]
+    ChainedProgramNotSupported,
+
+    /// Program not found
+    #[error("program `{name}` not found")]
+    ProgramNotFound {
+        /// The program name
+        name: String,
+    },
+
     /// Syscall failed
     #[error("the `{call}` syscall failed")]
     SyscallError {
@@ -173,6 +207,21 @@ pub enum MapError {
         io_error: io::Error,
     },
 
+    /// A pinned map doesn't match the definition of the map reusing it
+    #[error("the pinned map `{name}` doesn't match its definition")]
+    IncompatiblePinnedMap {
+        /// The map name
+        name: String,
+    },
+
+    /// A map passed to [`BpfLoader::reuse_map`](crate::BpfLoader::reuse_map)
+    /// doesn't match the definition of the map reusing it
+    #[error("the map reused as `{name}` doesn't match its definition")]
+    IncompatibleReusedMap {
+        /// The map name
+        name: String,
+    },
+
     /// Could not pin map by name
     #[error("map `{name:?}` requested pinning by name. pinning failed")]
     PinError {
@@ -193,6 +242,13 @@ impl AsRawFd for MapFd {
     }
 }
 
+impl AsFd for MapFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        // Safety: the fd is valid as long as the map it was returned by
+        unsafe { BorrowedFd::borrow_raw(self.0) }
+    }
+}
+
 #[derive(PartialEq, Eq, PartialOrd, Ord)]
 struct RlimitSize(usize);
 impl fmt::Display for RlimitSize {
@@ -210,6 +266,10 @@ impl fmt::Display for RlimitSize {
 /// Raises a warning about rlimit. Should be used only if creating a map was not
 /// successful.
 fn maybe_warn_rlimit() {
+    // the limit doesn't apply when the kernel uses memcg accounting
+    if memory_accounting() == MemoryAccounting::Memcg {
+        return;
+    }
     let mut limit = std::mem::MaybeUninit::<rlimit>::uninit();
     let ret = unsafe { getrlimit(RLIMIT_MEMLOCK, limit.as_mut_ptr()) };
     if ret == 0 {
@@ -261,6 +321,28 @@ pub enum Map {
     StackTraceMap(MapData),
     /// A [`Queue`] map
     Queue(MapData),
+    /// A [`RingBuf`] map
+    RingBuf(MapData),
+    /// A [`UserRingBuf`] map
+    UserRingBuf(MapData),
+    /// A [`ArrayOfMaps`] map
+    ArrayOfMaps(MapData),
+    /// A [`HashOfMaps`] map
+    HashOfMaps(MapData),
+    /// A [`SkStorage`] map
+    SkStorage(MapData),
+    /// A [`InodeStorage`] map
+    InodeStorage(MapData),
+    /// A [`TaskStorage`] map
+    TaskStorage(MapData),
+    /// A [`DevMap`] map
+    DevMap(MapData),
+    /// A [`DevMapHash`] map
+    DevMapHash(MapData),
+    /// A [`CpuMap`] map
+    CpuMap(MapData),
+    /// A [`XskMap`] map
+    XskMap(MapData),
 }
 
 impl Map {
@@ -282,8 +364,141 @@ impl Map {
             Map::Stack(map) => map.obj.map_type(),
             Map::StackTraceMap(map) => map.obj.map_type(),
             Map::Queue(map) => map.obj.map_type(),
+            Map::RingBuf(map) => map.obj.map_type(),
+            Map::UserRingBuf(map) => map.obj.map_type(),
+            Map::ArrayOfMaps(map) => map.obj.map_type(),
+            Map::HashOfMaps(map) => map.obj.map_type(),
+            Map::SkStorage(map) => map.obj.map_type(),
+            Map::InodeStorage(map) => map.obj.map_type(),
+            Map::TaskStorage(map) => map.obj.map_type(),
+            Map::DevMap(map) => map.obj.map_type(),
+            Map::DevMapHash(map) => map.obj.map_type(),
+            Map::CpuMap(map) => map.obj.map_type(),
+            Map::XskMap(map) => map.obj.map_type(),
+        }
+    }
+
+    fn data(&self) -> &MapData {
+        match self {
+            Map::Array(map) => map,
+            Map::PerCpuArray(map) => map,
+            Map::ProgramArray(map) => map,
+            Map::HashMap(map) => map,
+            Map::LruHashMap(map) => map,
+            Map::PerCpuHashMap(map) => map,
+            Map::PerCpuLruHashMap(map) => map,
+            Map::PerfEventArray(map) => map,
+            Map::SockHash(map) => map,
+            Map::SockMap(map) => map,
+            Map::BloomFilter(map) => map,
+            Map::LpmTrie(map) => map,
+            Map::Stack(map) => map,
+            Map::StackTraceMap(map) => map,
+            Map::Queue(map) => map,
+            Map::RingBuf(map) => map,
+            Map::UserRingBuf(map) => map,
+            Map::ArrayOfMaps(map) => map,
+            Map::HashOfMaps(map) => map,
+            Map::SkStorage(map) => map,
+            Map::InodeStorage(map) => map,
+            Map::TaskStorage(map) => map,
+            Map::DevMap(map) => map,
+            Map::DevMapHash(map) => map,
+            Map::CpuMap(map) => map,
+            Map::XskMap(map) => map,
         }
     }
+
+    pub(crate) fn from_map_data(map: MapData) -> Result<Map, MapError> {
+        use bpf_map_type::*;
+
+        let map_type =
+            bpf_map_type::try_from(map.obj.map_type()).map_err(|e| MapError::InvalidMapType {
+                map_type: e.map_type,
+            })?;
+        match map_type {
+            BPF_MAP_TYPE_ARRAY => Ok(Map::Array(map)),
+            BPF_MAP_TYPE_PERCPU_ARRAY => Ok(Map::PerCpuArray(map)),
+            BPF_MAP_TYPE_PROG_ARRAY => Ok(Map::ProgramArray(map)),
+            BPF_MAP_TYPE_HASH => Ok(Map::HashMap(map)),
+            BPF_MAP_TYPE_LRU_HASH => Ok(Map::LruHashMap(map)),
+            BPF_MAP_TYPE_PERCPU_HASH => Ok(Map::PerCpuHashMap(map)),
+            BPF_MAP_TYPE_LRU_PERCPU_HASH => Ok(Map::PerCpuLruHashMap(map)),
+            BPF_MAP_TYPE_PERF_EVENT_ARRAY => Ok(Map::PerfEventArray(map)),
+            BPF_MAP_TYPE_SOCKHASH => Ok(Map::SockHash(map)),
+            BPF_MAP_TYPE_SOCKMAP => Ok(Map::SockMap(map)),
+            BPF_MAP_TYPE_BLOOM_FILTER => Ok(Map::BloomFilter(map)),
+            BPF_MAP_TYPE_LPM_TRIE => Ok(Map::LpmTrie(map)),
+            BPF_MAP_TYPE_STACK => Ok(Map::Stack(map)),
+            BPF_MAP_TYPE_STACK_TRACE => Ok(Map::StackTraceMap(map)),
+            BPF_MAP_TYPE_QUEUE => Ok(Map::Queue(map)),
+            BPF_MAP_TYPE_RINGBUF => Ok(Map::RingBuf(map)),
+            BPF_MAP_TYPE_USER_RINGBUF => Ok(Map::UserRingBuf(map)),
+            BPF_MAP_TYPE_ARRAY_OF_MAPS => Ok(Map::ArrayOfMaps(map)),
+            BPF_MAP_TYPE_HASH_OF_MAPS => Ok(Map::HashOfMaps(map)),
+            BPF_MAP_TYPE_SK_STORAGE => Ok(Map::SkStorage(map)),
+            BPF_MAP_TYPE_INODE_STORAGE => Ok(Map::InodeStorage(map)),
+            BPF_MAP_TYPE_TASK_STORAGE => Ok(Map::TaskStorage(map)),
+            BPF_MAP_TYPE_DEVMAP => Ok(Map::DevMap(map)),
+            BPF_MAP_TYPE_DEVMAP_HASH => Ok(Map::DevMapHash(map)),
+            BPF_MAP_TYPE_CPUMAP => Ok(Map::CpuMap(map)),
+            BPF_MAP_TYPE_XSKMAP => Ok(Map::XskMap(map)),
+            m => Err(MapError::InvalidMapType { map_type: m as u32 }),
+        }
+    }
+
+    /// Returns the file descriptor of the map, or `None` if the map hasn't
+    /// been created.
+    pub fn fd(&self) -> Option<MapFd> {
+        self.data().fd.map(MapFd)
+    }
+
+    /// Returns the BTF type id of the map keys.
+    ///
+    /// Only maps defined in the `.maps` section have BTF type information,
+    /// see [`Bpf::btf`](crate::Bpf::btf).
+    pub fn btf_key_type_id(&self) -> Option<u32> {
+        match &self.data().obj {
+            obj::Map::Btf(m) if m.def.btf_key_type_id != 0 => Some(m.def.btf_key_type_id),
+            _ => None,
+        }
+    }
+
+    /// Returns the BTF type id of the map values.
+    ///
+    /// Only maps defined in the `.maps` section have BTF type information,
+    /// see [`Bpf::btf`](crate::Bpf::btf).
+    pub fn btf_value_type_id(&self) -> Option<u32> {
+        match &self.data().obj {
+            obj::Map::Btf(m) if m.def.btf_value_type_id != 0 => Some(m.def.btf_value_type_id),
+            _ => None,
+        }
+    }
+
+    /// Creates a new, empty map with the same definition as this one.
+    ///
+    /// This is typically used to create the inner maps of an [`ArrayOfMaps`]
+    /// or a [`HashOfMaps`] at runtime, using the inner map template declared
+    /// by the eBPF program. The new map isn't pinned, and is closed when
+    /// dropped unless it is referenced by an outer map.
+    pub fn create_like(&self, name: &str) -> Result<Map, MapError> {
+        let data = self.data();
+        let mut map = MapData {
+            obj: data.obj.clone(),
+            fd: None,
+            pinned: false,
+            btf_fd: data.btf_fd,
+        };
+        map.create(name)?;
+        Map::from_map_data(map)
+    }
+
+    /// Freezes the map, making it read-only from user space.
+    ///
+    /// See [`MapData::freeze`].
+    pub fn freeze(&self) -> Result<(), MapError> {
+        self.data().freeze()
+    }
 }
 
 macro_rules! impl_try_from_map {
@@ -336,6 +551,13 @@ impl_try_from_map!(
     SockMap from Map::SockMap,
     PerfEventArray from Map::PerfEventArray,
     StackTraceMap from Map::StackTraceMap,
+    RingBuf from Map::RingBuf,
+    UserRingBuf from Map::UserRingBuf,
+    ArrayOfMaps from Map::ArrayOfMaps,
+    DevMap from Map::DevMap,
+    DevMapHash from Map::DevMapHash,
+    CpuMap from Map::CpuMap,
+    XskMap from Map::XskMap,
 );
 
 #[cfg(feature = "async")]
@@ -389,7 +611,18 @@ macro_rules! impl_try_from_map_generic_key_or_value {
    }
 }
 
-impl_try_from_map_generic_key_or_value!(Array, PerCpuArray, SockHash, BloomFilter, Queue, Stack,);
+impl_try_from_map_generic_key_or_value!(
+    Array,
+    PerCpuArray,
+    SockHash,
+    BloomFilter,
+    Queue,
+    Stack,
+    HashOfMaps,
+    SkStorage,
+    InodeStorage,
+    TaskStorage,
+);
 
 macro_rules! impl_try_from_map_generic_key_and_value {
     ($($ty:ident),+ $(,)?) => {
@@ -468,6 +701,124 @@ pub(crate) fn check_v_size<V>(map: &MapData) -> Result<(), MapError> {
     Ok(())
 }
 
+/// Looks up all the elements of the map, `batch_size` elements per
+/// `BPF_MAP_LOOKUP_BATCH` syscall, or `BPF_MAP_LOOKUP_AND_DELETE_BATCH` when
+/// `delete` is set.
+///
+/// Hash maps return the elements bucket by bucket, the batch size is doubled
+/// when a bucket holds more elements than it.
+pub(crate) fn lookup_batch<K: Pod, V: Pod>(
+    map: &MapData,
+    batch_size: u32,
+    flags: u64,
+    delete: bool,
+) -> Result<Vec<(K, V)>, MapError> {
+    let fd = map.fd_or_err()?;
+    let mut batch_size = batch_size.max(1);
+    let mut keys = Vec::<K>::new();
+    let mut values = Vec::<V>::new();
+    // The position of the next batch, a bucket index for hash maps and a key
+    // for arrays.
+    let mut in_batch = None;
+    let mut out_batch = 0u32;
+    loop {
+        keys.reserve(batch_size as usize);
+        values.reserve(batch_size as usize);
+        let mut count = batch_size;
+        let ret = unsafe {
+            bpf_map_lookup_batch(
+                fd,
+                in_batch.as_ref(),
+                &mut out_batch,
+                keys.as_mut_ptr().add(keys.len()),
+                values.as_mut_ptr().add(values.len()),
+                &mut count,
+                flags,
+                delete,
+            )
+        };
+        // The kernel copies the last elements of the map along with ENOENT.
+        let done = match ret {
+            Ok(_) => false,
+            Err((_, ref io_error)) if io_error.raw_os_error() == Some(ENOENT) => true,
+            Err((_, io_error)) if io_error.raw_os_error() == Some(ENOSPC) && count == 0 => {
+                batch_size *= 2;
+                continue;
+            }
+            Err((_, io_error)) => {
+                return Err(MapError::SyscallError {
+                    call: if delete {
+                        "bpf_map_lookup_and_delete_batch"
+                    } else {
+                        "bpf_map_lookup_batch"
+                    }
+                    .to_owned(),
+                    io_error,
+                })
+            }
+        };
+        let count = count.min(batch_size) as usize;
+        // Safety: the kernel initialized the `count` elements it returned.
+        unsafe {
+            keys.set_len(keys.len() + count);
+            values.set_len(values.len() + count);
+        }
+        if done {
+            break;
+        }
+        in_batch = Some(out_batch);
+    }
+    Ok(keys.into_iter().zip(values).collect())
+}
+
+/// Updates the elements of `keys` to `values` with a single
+/// `BPF_MAP_UPDATE_BATCH` syscall.
+///
+/// On error, also returns the number of elements updated before the error.
+pub(crate) fn update_batch<K: Pod, V: Pod>(
+    map: &mut MapData,
+    keys: &[K],
+    values: &[V],
+    flags: u64,
+) -> Result<(), (usize, MapError)> {
+    assert_eq!(
+        keys.len(),
+        values.len(),
+        "the number of keys and values differ"
+    );
+    let fd = map.fd_or_err().map_err(|e| (0, e))?;
+    let mut count = keys.len() as u32;
+    bpf_map_update_batch(fd, keys, values, &mut count, flags).map_err(|(_, io_error)| {
+        // The kernel leaves the count untouched when it fails before updating
+        // any element, e.g. when it doesn't know the command.
+        let updated = if count as usize >= keys.len() {
+            0
+        } else {
+            count as usize
+        };
+        (
+            updated,
+            MapError::SyscallError {
+                call: "bpf_map_update_batch".to_owned(),
+                io_error,
+            },
+        )
+    })?;
+    Ok(())
+}
+
+/// Deletes the elements of `keys` with a single `BPF_MAP_DELETE_BATCH`
+/// syscall.
+pub(crate) fn delete_batch<K: Pod>(map: &mut MapData, keys: &[K]) -> Result<(), MapError> {
+    let fd = map.fd_or_err()?;
+    let mut count = keys.len() as u32;
+    bpf_map_delete_batch(fd, keys, &mut count).map_err(|(_, io_error)| MapError::SyscallError {
+        call: "bpf_map_delete_batch".to_owned(),
+        io_error,
+    })?;
+    Ok(())
+}
+
 /// A generic handle to a BPF map.
 ///
 /// You should never need to use this unless you're implementing a new map type.
@@ -483,13 +834,35 @@ pub struct MapData {
 impl MapData {
     /// Creates a new map with the provided `name`
     pub fn create(&mut self, name: &str) -> Result<RawFd, MapError> {
+        self.create_with_inner_map(name, None, None, None)
+    }
+
+    /// Creates a new map with the provided `name`. Maps of maps are created
+    /// with elements having the definition of the map `inner_map_fd`, maps
+    /// used by offloaded programs are bound to the device `map_ifindex`, and
+    /// the privileges delegated by the BPF token `token_fd` are used if set.
+    pub(crate) fn create_with_inner_map(
+        &mut self,
+        name: &str,
+        inner_map_fd: Option<RawFd>,
+        map_ifindex: Option<u32>,
+        token_fd: Option<RawFd>,
+    ) -> Result<RawFd, MapError> {
         if self.fd.is_some() {
             return Err(MapError::AlreadyCreated { name: name.into() });
         }
 
         let c_name = CString::new(name).map_err(|_| MapError::InvalidName { name: name.into() })?;
 
-        let fd = bpf_create_map(&c_name, &self.obj, self.btf_fd).map_err(|(code, io_error)| {
+        let fd = bpf_create_map(
+            &c_name,
+            &self.obj,
+            self.btf_fd,
+            inner_map_fd,
+            map_ifindex,
+            token_fd,
+        )
+        .map_err(|(code, io_error)| {
             let k_ver = kernel_version().unwrap();
             if k_ver < (5, 11, 0) {
                 maybe_warn_rlimit();
@@ -511,12 +884,21 @@ impl MapData {
         &mut self,
         name: &str,
         path: P,
+    ) -> Result<RawFd, MapError> {
+        self.open_pinned_path(name, path.as_ref().join(name))
+    }
+
+    /// Opens the map pinned at `path` instead of creating a new one.
+    pub(crate) fn open_pinned_path<P: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        path: P,
     ) -> Result<RawFd, MapError> {
         if self.fd.is_some() {
             return Err(MapError::AlreadyCreated { name: name.into() });
         }
-        let map_path = path.as_ref().join(name);
-        let path_string = CString::new(map_path.to_str().unwrap()).unwrap();
+        let path_string = CString::new(path.as_ref().to_string_lossy().into_owned())
+            .map_err(|_| MapError::InvalidName { name: name.into() })?;
         let fd = bpf_get_object(&path_string).map_err(|(_, io_error)| MapError::SyscallError {
             call: "BPF_OBJ_GET".to_string(),
             io_error,
@@ -527,6 +909,26 @@ impl MapData {
         Ok(fd)
     }
 
+    /// Reuses the map referenced by `fd`, which is duplicated so the caller
+    /// keeps ownership of it.
+    pub(crate) fn reuse_fd(&mut self, name: &str, fd: BorrowedFd<'_>) -> Result<RawFd, MapError> {
+        if self.fd.is_some() {
+            return Err(MapError::AlreadyCreated { name: name.into() });
+        }
+        // duplicated with F_DUPFD_CLOEXEC
+        let fd = fd
+            .try_clone_to_owned()
+            .map_err(|io_error| MapError::SyscallError {
+                call: "fcntl".to_owned(),
+                io_error,
+            })?
+            .into_raw_fd();
+
+        self.fd = Some(fd);
+
+        Ok(fd)
+    }
+
     /// Loads a map from a pinned path in bpffs.
     pub fn from_pin<P: AsRef<Path>>(path: P) -> Result<MapData, MapError> {
         let path_string =
@@ -580,11 +982,47 @@ impl MapData {
         self.fd.ok_or(MapError::NotCreated)
     }
 
+    /// Checks that the opened map has the type, key and value sizes, maximum
+    /// number of entries and flags of its definition.
+    pub(crate) fn check_pinned_compat(&self, name: &str) -> Result<(), MapError> {
+        self.check_compat(|| MapError::IncompatiblePinnedMap { name: name.into() })
+    }
+
+    pub(crate) fn check_reused_compat(&self, name: &str) -> Result<(), MapError> {
+        self.check_compat(|| MapError::IncompatibleReusedMap { name: name.into() })
+    }
+
+    fn check_compat(&self, incompatible: impl FnOnce() -> MapError) -> Result<(), MapError> {
+        let fd = self.fd_or_err()?;
+        let info = bpf_map_get_info_by_fd(fd).map_err(|io_error| MapError::SyscallError {
+            call: "BPF_MAP_GET_INFO_BY_FD".to_owned(),
+            io_error,
+        })?;
+        if info.type_ != self.obj.map_type()
+            || info.key_size != self.obj.key_size()
+            || info.value_size != self.obj.value_size()
+            || info.max_entries != self.obj.max_entries()
+            || info.map_flags != self.obj.map_flags()
+        {
+            return Err(incompatible());
+        }
+        Ok(())
+    }
+
     pub(crate) fn pin<P: AsRef<Path>>(&mut self, name: &str, path: P) -> Result<(), PinError> {
+        self.pin_to_path(name, path.as_ref().join(name))
+    }
+
+    /// Pins the map at `path`.
+    pub(crate) fn pin_to_path<P: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        path: P,
+    ) -> Result<(), PinError> {
         if self.pinned {
             return Err(PinError::AlreadyPinned { name: name.into() });
         }
-        let map_path = path.as_ref().join(name);
+        let map_path = path.as_ref();
         let fd = self.fd.ok_or(PinError::NoFd {
             name: name.to_string(),
         })?;
@@ -607,6 +1045,33 @@ impl MapData {
     pub fn fd(&self) -> Option<MapFd> {
         self.fd.map(MapFd)
     }
+
+    /// Returns the maximum number of entries of the map.
+    pub fn max_entries(&self) -> u32 {
+        self.obj.max_entries()
+    }
+
+    /// Freezes the map, making it read-only from user space.
+    ///
+    /// After the map is frozen, updates and deletes from user space fail with
+    /// `EPERM`, while eBPF programs can still modify it. Freezing can't be
+    /// undone. This is typically used to make configuration maps immutable
+    /// once they've been initialized.
+    ///
+    /// Maps created from `.rodata` sections are frozen automatically when
+    /// loaded.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 5.2.
+    pub fn freeze(&self) -> Result<(), MapError> {
+        let fd = self.fd_or_err()?;
+        bpf_map_freeze(fd).map_err(|(_, io_error)| MapError::SyscallError {
+            call: "bpf_map_freeze".to_owned(),
+            io_error,
+        })?;
+        Ok(())
+    }
 }
 
 impl Drop for MapData {
@@ -738,7 +1203,9 @@ impl PerCpuKernelMem {
 
 /// A slice of per-CPU values.
 ///
-/// Used by maps that implement per-CPU storage like [`PerCpuHashMap`].
+/// Used by maps that implement per-CPU storage like [`PerCpuHashMap`]. There
+/// is one value for each possible CPU, see
+/// [`nr_possible_cpus`](crate::util::nr_possible_cpus).
 ///
 /// # Examples
 ///
@@ -754,9 +1221,9 @@ impl PerCpuKernelMem {
 /// # }
 /// # let bpf = aya::Bpf::load(&[])?;
 /// use aya::maps::PerCpuValues;
-/// use aya::util::nr_cpus;
+/// use aya::util::nr_possible_cpus;
 ///
-/// let values = PerCpuValues::try_from(vec![42u32; nr_cpus()?])?;
+/// let values = PerCpuValues::try_from(vec![42u32; nr_possible_cpus()?])?;
 /// # Ok::<(), Error>(())
 /// ```
 #[derive(Debug)]
@@ -768,11 +1235,15 @@ impl<T: Pod> TryFrom<Vec<T>> for PerCpuValues<T> {
     type Error = io::Error;
 
     fn try_from(values: Vec<T>) -> Result<Self, Self::Error> {
-        let nr_cpus = nr_cpus()?;
+        let nr_cpus = nr_possible_cpus()?;
         if values.len() != nr_cpus {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
-                format!("not enough values ({}), nr_cpus: {}", values.len(), nr_cpus),
+                format!(
+                    "not enough values ({}), possible cpus: {}",
+                    values.len(),
+                    nr_cpus
+                ),
             ));
         }
         Ok(PerCpuValues {
@@ -785,7 +1256,7 @@ impl<T: Pod> PerCpuValues<T> {
     pub(crate) fn alloc_kernel_mem() -> Result<PerCpuKernelMem, io::Error> {
         let value_size = (mem::size_of::<T>() + 7) & !7;
         Ok(PerCpuKernelMem {
-            bytes: vec![0u8; nr_cpus()? * value_size],
+            bytes: vec![0u8; nr_possible_cpus()? * value_size],
         })
     }
 
@@ -814,6 +1285,42 @@ impl<T: Pod> PerCpuValues<T> {
 
         Ok(mem)
     }
+
+    /// Returns the sum of the values of all the CPUs.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let bpf = aya::Bpf::load(&[])?;
+    /// use aya::maps::PerCpuArray;
+    ///
+    /// let counters = PerCpuArray::<_, u64>::try_from(bpf.map("COUNTERS").unwrap())?;
+    /// let packets = counters.get(&0, 0)?.sum();
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn sum(&self) -> T
+    where
+        T: Sum,
+    {
+        self.values.iter().copied().sum()
+    }
+
+    /// Returns the maximum of the values of all the CPUs.
+    pub fn max(&self) -> T
+    where
+        T: Ord,
+    {
+        self.reduce(T::max)
+    }
+
+    /// Combines the values of all the CPUs into one with `f`.
+    ///
+    /// `f` is called with the value accumulated so far and the value of the
+    /// next CPU, starting with the values of the first two CPUs.
+    pub fn reduce(&self, f: impl FnMut(T, T) -> T) -> T {
+        // There is a value for every possible CPU, so at least one.
+        self.values.iter().copied().reduce(f).unwrap()
+    }
 }
 
 impl<T: Pod> Deref for PerCpuValues<T> {
@@ -826,11 +1333,16 @@ impl<T: Pod> Deref for PerCpuValues<T> {
 
 #[cfg(test)]
 mod tests {
+    use std::{env, fs::File};
+
     use libc::EFAULT;
 
     use crate::{
         bpf_map_def,
-        generated::{bpf_cmd, bpf_map_type::BPF_MAP_TYPE_HASH},
+        generated::{
+            bpf_cmd, bpf_map_info,
+            bpf_map_type::{BPF_MAP_TYPE_BLOOM_FILTER, BPF_MAP_TYPE_HASH},
+        },
         maps::MapData,
         obj::{maps::LegacyMap, BpfSectionKind},
         sys::{override_syscall, Syscall},
@@ -882,6 +1394,55 @@ mod tests {
         ));
     }
 
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_create_map_extra() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_CREATE,
+                attr,
+            } if unsafe { attr.__bindgen_anon_1.map_extra } == 3 => Ok(42),
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+
+        let mut info = unsafe { mem::zeroed::<bpf_map_info>() };
+        info.type_ = BPF_MAP_TYPE_BLOOM_FILTER as u32;
+        info.value_size = 4;
+        info.max_entries = 1024;
+        info.btf_key_type_id = 1;
+        info.map_extra = 3;
+        let mut map = MapData {
+            obj: parse_map_info(info, PinningType::None),
+            fd: None,
+            pinned: false,
+            btf_fd: None,
+        };
+        assert!(matches!(map.create("foo"), Ok(42)));
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_freeze() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_FREEZE,
+                attr,
+            } if unsafe { attr.__bindgen_anon_2.map_fd } == 42 => Ok(0),
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+
+        let mut map = new_map();
+        assert!(matches!(map.freeze(), Err(MapError::NotCreated)));
+        map.fd = Some(42);
+        assert!(Map::HashMap(map).freeze().is_ok());
+    }
+
     #[test]
     fn test_create_failed() {
         override_syscall(|_| Err((-42, io::Error::from_raw_os_error(EFAULT))));
@@ -901,4 +1462,69 @@ mod tests {
         }
         assert_eq!(map.fd, None);
     }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_check_pinned_compat() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET_INFO_BY_FD,
+                attr,
+            } => {
+                let info = unsafe { &mut *(attr.info.info as *mut bpf_map_info) };
+                info.type_ = BPF_MAP_TYPE_HASH as u32;
+                info.key_size = 4;
+                info.value_size = 4;
+                info.max_entries = 1024;
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+
+        let mut map = new_map();
+        map.fd = Some(42);
+        assert!(map.check_pinned_compat("foo").is_ok());
+
+        map.obj.set_max_entries(2048);
+        assert!(matches!(
+            map.check_pinned_compat("foo"),
+            Err(MapError::IncompatiblePinnedMap { name }) if name == "foo"
+        ));
+        assert!(matches!(
+            map.check_reused_compat("foo"),
+            Err(MapError::IncompatibleReusedMap { name }) if name == "foo"
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_reuse_fd() {
+        let file = File::create(env::temp_dir().join("reuse_fd"))
+            .expect("unable to create file in tmpdir");
+
+        let mut map = new_map();
+        let fd = map.reuse_fd("foo", file.as_fd()).unwrap();
+        assert_ne!(fd, file.as_raw_fd());
+        assert_eq!(map.fd, Some(fd));
+        // the duplicate isn't inherited by child processes
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        assert_eq!(flags & libc::FD_CLOEXEC, libc::FD_CLOEXEC);
+        assert!(matches!(
+            map.reuse_fd("foo", file.as_fd()),
+            Err(MapError::AlreadyCreated { name }) if name == "foo"
+        ));
+    }
+
+    #[test]
+    fn test_per_cpu_values_aggregation() {
+        let values = PerCpuValues {
+            values: vec![3u64, 7, 1, 5].into_boxed_slice(),
+        };
+        assert_eq!(values.sum(), 16);
+        assert_eq!(values.max(), 7);
+        assert_eq!(values.reduce(|a, b| a.min(b)), 1);
+    }
 }
@@ -0,0 +1,195 @@
+use std::{
+    borrow::{Borrow, BorrowMut},
+    os::unix::io::AsRawFd,
+};
+
+use crate::{
+    maps::{check_bounds, check_kv_size, MapData, MapError, MapFd, MapKeys},
+    sys::{bpf_map_delete_elem, bpf_map_lookup_elem, bpf_map_update_elem},
+};
+
+/// An array of maps.
+///
+/// The elements of the array are maps with the definition of the inner map
+/// template set with [`BpfLoader::set_inner_map`](crate::BpfLoader::set_inner_map),
+/// which eBPF programs look up with `bpf_map_lookup_elem` and then access like
+/// any other map. New inner maps are created with [`Map::create_like`](crate::maps::Map::create_like).
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.12.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya::{maps::{ArrayOfMaps, HashMap}, BpfLoader};
+///
+/// let mut bpf = BpfLoader::new()
+///     .set_inner_map("PER_IFACE", "IFACE_TEMPLATE")
+///     .load_file("file.o")?;
+///
+/// let inner = bpf.map("IFACE_TEMPLATE").unwrap().create_like("iface_2")?;
+/// let mut per_iface = ArrayOfMaps::try_from(bpf.map_mut("PER_IFACE").unwrap())?;
+/// per_iface.set(2, &inner.fd().unwrap(), 0)?;
+///
+/// let mut inner: HashMap<_, u32, u64> = HashMap::try_from(inner)?;
+/// inner.insert(80, 0, 0)?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_ARRAY_OF_MAPS")]
+pub struct ArrayOfMaps<T> {
+    inner: T,
+}
+
+impl<T: Borrow<MapData>> ArrayOfMaps<T> {
+    pub(crate) fn new(map: T) -> Result<ArrayOfMaps<T>, MapError> {
+        let data = map.borrow();
+        check_kv_size::<u32, u32>(data)?;
+
+        let _fd = data.fd_or_err()?;
+
+        Ok(ArrayOfMaps { inner: map })
+    }
+
+    /// Returns the number of elements in the array.
+    ///
+    /// This corresponds to the value of `bpf_map_def::max_entries` on the eBPF side.
+    pub fn len(&self) -> u32 {
+        self.inner.borrow().obj.max_entries()
+    }
+
+    /// Returns the id of the map stored at the given index.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::OutOfBounds`] if `index` is out of bounds,
+    /// [`MapError::KeyNotFound`] if no map is stored at `index`,
+    /// [`MapError::SyscallError`] if `bpf_map_lookup_elem` fails.
+    pub fn get(&self, index: &u32, flags: u64) -> Result<u32, MapError> {
+        let data = self.inner.borrow();
+        check_bounds(data, *index)?;
+        let fd = data.fd_or_err()?;
+
+        let value = bpf_map_lookup_elem(fd, index, flags).map_err(|(_, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_map_lookup_elem".to_owned(),
+                io_error,
+            }
+        })?;
+        value.ok_or(MapError::KeyNotFound)
+    }
+
+    /// An iterator over the indices of the array that hold a map. The iterator
+    /// item type is `Result<u32, MapError>`.
+    pub fn indices(&self) -> MapKeys<'_, u32> {
+        MapKeys::new(self.inner.borrow())
+    }
+}
+
+impl<T: BorrowMut<MapData>> ArrayOfMaps<T> {
+    /// Stores the map `map` at the given index.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::OutOfBounds`] if `index` is out of bounds,
+    /// [`MapError::SyscallError`] if `bpf_map_update_elem` fails, for example
+    /// because the definition of `map` doesn't match the inner map template.
+    pub fn set(&mut self, index: u32, map: &MapFd, flags: u64) -> Result<(), MapError> {
+        let data = self.inner.borrow_mut();
+        check_bounds(data, index)?;
+        let fd = data.fd_or_err()?;
+        let map_fd = map.as_raw_fd();
+
+        bpf_map_update_elem(fd, Some(&index), &map_fd, flags).map_err(|(_, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_map_update_elem".to_owned(),
+                io_error,
+            }
+        })?;
+        Ok(())
+    }
+
+    /// Removes the map stored at the given index.
+    pub fn clear_index(&mut self, index: &u32) -> Result<(), MapError> {
+        let data = self.inner.borrow_mut();
+        check_bounds(data, *index)?;
+        let fd = data.fd_or_err()?;
+
+        bpf_map_delete_elem(fd, index)
+            .map(|_| ())
+            .map_err(|(_, io_error)| MapError::SyscallError {
+                call: "bpf_map_delete_elem".to_owned(),
+                io_error,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use libc::EFAULT;
+
+    use crate::{
+        bpf_map_def,
+        generated::{bpf_cmd, bpf_map_type::BPF_MAP_TYPE_ARRAY_OF_MAPS},
+        maps::Map,
+        obj::{self, maps::LegacyMap, BpfSectionKind},
+        sys::{override_syscall, Syscall},
+    };
+
+    use super::*;
+
+    fn new_map() -> MapData {
+        MapData {
+            obj: obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_ARRAY_OF_MAPS as u32,
+                    key_size: 4,
+                    value_size: 4,
+                    max_entries: 4,
+                    ..Default::default()
+                },
+                section_index: 0,
+                section_kind: BpfSectionKind::Maps,
+                symbol_index: None,
+                data: Vec::new(),
+            }),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        }
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_set() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                let value = unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.value } as *const u32;
+                match unsafe { *value } {
+                    7 => Ok(1),
+                    _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+                }
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+
+        let mut map = Map::ArrayOfMaps(new_map());
+        let mut array = ArrayOfMaps::try_from(&mut map).unwrap();
+        assert!(array.set(1, &MapFd(7), 0).is_ok());
+        assert!(matches!(
+            array.set(4, &MapFd(7), 0),
+            Err(MapError::OutOfBounds {
+                index: 4,
+                max_entries: 4
+            })
+        ));
+    }
+}
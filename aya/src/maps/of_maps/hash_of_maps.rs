@@ -0,0 +1,256 @@
+use std::{
+    borrow::{Borrow, BorrowMut},
+    marker::PhantomData,
+    os::unix::io::AsRawFd,
+};
+
+use crate::{
+    maps::{check_kv_size, MapData, MapError, MapFd, MapKeys},
+    sys::{bpf_map_delete_elem, bpf_map_lookup_elem, bpf_map_update_elem},
+    Pod,
+};
+
+/// A hash map of maps.
+///
+/// The values of the hash map are maps with the definition of the inner map
+/// template set with [`BpfLoader::set_inner_map`](crate::BpfLoader::set_inner_map),
+/// which eBPF programs look up with `bpf_map_lookup_elem` and then access like
+/// any other map. This allows keeping separate state per tenant, cgroup or
+/// interface, created at runtime with [`Map::create_like`](crate::maps::Map::create_like).
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.12.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya::{maps::{HashMap, HashOfMaps}, BpfLoader};
+///
+/// let mut bpf = BpfLoader::new()
+///     .set_inner_map("TENANTS", "TENANT_TEMPLATE")
+///     .load_file("file.o")?;
+///
+/// let inner = bpf.map("TENANT_TEMPLATE").unwrap().create_like("tenant_42")?;
+/// let mut tenants = HashOfMaps::<_, u32>::try_from(bpf.map_mut("TENANTS").unwrap())?;
+/// tenants.insert(42, &inner.fd().unwrap(), 0)?;
+///
+/// let mut inner: HashMap<_, u32, u64> = HashMap::try_from(inner)?;
+/// inner.insert(1, 0, 0)?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_HASH_OF_MAPS")]
+pub struct HashOfMaps<T, K> {
+    inner: T,
+    _k: PhantomData<K>,
+}
+
+impl<T: Borrow<MapData>, K: Pod> HashOfMaps<T, K> {
+    pub(crate) fn new(map: T) -> Result<HashOfMaps<T, K>, MapError> {
+        let data = map.borrow();
+        check_kv_size::<K, u32>(data)?;
+        let _ = data.fd_or_err()?;
+
+        Ok(HashOfMaps {
+            inner: map,
+            _k: PhantomData,
+        })
+    }
+
+    /// Returns the id of the map associated with the key.
+    pub fn get(&self, key: &K, flags: u64) -> Result<u32, MapError> {
+        let fd = self.inner.borrow().fd_or_err()?;
+        let value = bpf_map_lookup_elem(fd, key, flags).map_err(|(_, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_map_lookup_elem".to_owned(),
+                io_error,
+            }
+        })?;
+        value.ok_or(MapError::KeyNotFound)
+    }
+
+    /// An iterator visiting all keys in arbitrary order. The iterator element
+    /// type is `Result<K, MapError>`.
+    pub fn keys(&self) -> MapKeys<'_, K> {
+        MapKeys::new(self.inner.borrow())
+    }
+}
+
+impl<T: BorrowMut<MapData>, K: Pod> HashOfMaps<T, K> {
+    /// Associates the map `map` with the key.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::SyscallError`] if `bpf_map_update_elem` fails, for
+    /// example because the definition of `map` doesn't match the inner map
+    /// template.
+    pub fn insert(&mut self, key: impl Borrow<K>, map: &MapFd, flags: u64) -> Result<(), MapError> {
+        let fd = self.inner.borrow_mut().fd_or_err()?;
+        let map_fd = map.as_raw_fd();
+        bpf_map_update_elem(fd, Some(key.borrow()), &map_fd, flags).map_err(|(_, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_map_update_elem".to_owned(),
+                io_error,
+            }
+        })?;
+        Ok(())
+    }
+
+    /// Removes a key from the map.
+    pub fn remove(&mut self, key: &K) -> Result<(), MapError> {
+        let fd = self.inner.borrow_mut().fd_or_err()?;
+        bpf_map_delete_elem(fd, key)
+            .map(|_| ())
+            .map_err(|(_, io_error)| MapError::SyscallError {
+                call: "bpf_map_delete_elem".to_owned(),
+                io_error,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use libc::{EFAULT, ENOENT};
+
+    use crate::{
+        bpf_map_def,
+        generated::{
+            bpf_attr, bpf_cmd,
+            bpf_map_type::{BPF_MAP_TYPE_HASH, BPF_MAP_TYPE_HASH_OF_MAPS},
+        },
+        maps::Map,
+        obj::{self, maps::LegacyMap, BpfSectionKind},
+        sys::{override_syscall, SysResult, Syscall},
+    };
+
+    use super::*;
+
+    fn new_map(map_type: u32) -> MapData {
+        MapData {
+            obj: obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type,
+                    key_size: 4,
+                    value_size: 4,
+                    max_entries: 1024,
+                    ..Default::default()
+                },
+                section_index: 0,
+                section_kind: BpfSectionKind::Maps,
+                symbol_index: None,
+                data: Vec::new(),
+            }),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        }
+    }
+
+    #[test]
+    fn test_try_from_wrong_map() {
+        let map = Map::HashMap(new_map(BPF_MAP_TYPE_HASH as u32));
+        assert!(matches!(
+            HashOfMaps::<_, u32>::try_from(&map),
+            Err(MapError::InvalidMapType { .. })
+        ));
+    }
+
+    #[test]
+    fn test_try_from_ok() {
+        let map = Map::HashOfMaps(new_map(BPF_MAP_TYPE_HASH_OF_MAPS as u32));
+        assert!(HashOfMaps::<_, u32>::try_from(&map).is_ok());
+    }
+
+    fn sys_error(value: i32) -> SysResult {
+        Err((-1, io::Error::from_raw_os_error(value)))
+    }
+
+    fn bpf_key(attr: &bpf_attr) -> u32 {
+        unsafe { *(attr.__bindgen_anon_2.key as *const u32) }
+    }
+
+    fn bpf_value(attr: &bpf_attr) -> *mut u32 {
+        unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.value as *mut u32 }
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_insert() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => match (bpf_key(attr), unsafe { *bpf_value(attr) }) {
+                (1, 7) => Ok(1),
+                _ => sys_error(EFAULT),
+            },
+            _ => sys_error(EFAULT),
+        });
+
+        let mut map = Map::HashOfMaps(new_map(BPF_MAP_TYPE_HASH_OF_MAPS as u32));
+        let mut hash = HashOfMaps::<_, u32>::try_from(&mut map).unwrap();
+        assert!(hash.insert(1, &MapFd(7), 0).is_ok());
+        assert!(matches!(
+            hash.insert(2, &MapFd(7), 0),
+            Err(MapError::SyscallError { call, io_error }) if call == "bpf_map_update_elem" && io_error.raw_os_error() == Some(EFAULT)
+        ));
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_get() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_ELEM,
+                attr,
+            } => match bpf_key(attr) {
+                1 => {
+                    // The kernel returns the id of the inner map
+                    unsafe { *bpf_value(attr) = 100 };
+                    Ok(1)
+                }
+                _ => sys_error(ENOENT),
+            },
+            _ => sys_error(EFAULT),
+        });
+
+        let map = Map::HashOfMaps(new_map(BPF_MAP_TYPE_HASH_OF_MAPS as u32));
+        let hash = HashOfMaps::<_, u32>::try_from(&map).unwrap();
+        assert_eq!(hash.get(&1, 0).unwrap(), 100);
+        assert!(matches!(hash.get(&2, 0), Err(MapError::KeyNotFound)));
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_remove() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_DELETE_ELEM,
+                attr,
+            } => match bpf_key(attr) {
+                1 => Ok(1),
+                _ => sys_error(ENOENT),
+            },
+            _ => sys_error(EFAULT),
+        });
+
+        let mut map = Map::HashOfMaps(new_map(BPF_MAP_TYPE_HASH_OF_MAPS as u32));
+        let mut hash = HashOfMaps::<_, u32>::try_from(&mut map).unwrap();
+        assert!(hash.remove(&1).is_ok());
+        assert!(matches!(
+            hash.remove(&2),
+            Err(MapError::SyscallError { call, io_error }) if call == "bpf_map_delete_elem" && io_error.raw_os_error() == Some(ENOENT)
+        ));
+    }
+}
@@ -0,0 +1,6 @@
+//! Maps of maps.
+mod array_of_maps;
+mod hash_of_maps;
+
+pub use array_of_maps::*;
+pub use hash_of_maps::*;
@@ -11,7 +11,7 @@ use async_io::Async;
 use tokio::io::unix::AsyncFd;
 
 use crate::maps::{
-    perf::{Events, PerfBufferError, PerfEventArray, PerfEventArrayBuffer},
+    perf::{Events, LostEvents, PerfBufferError, PerfEventArray, PerfEventArrayBuffer},
     MapData, MapError,
 };
 
@@ -165,6 +165,29 @@ impl<T: BorrowMut<MapData> + Borrow<MapData>> AsyncPerfEventArrayBuffer<T> {
             }
         }
     }
+
+    /// Reads events from the buffer, calling `on_lost` for each lost-sample
+    /// record found.
+    ///
+    /// See [`PerfEventArrayBuffer::read_events_with_lost`].
+    pub async fn read_events_with_lost(
+        &mut self,
+        buffers: &mut [BytesMut],
+        mut on_lost: impl FnMut(LostEvents),
+    ) -> Result<Events, PerfBufferError> {
+        loop {
+            let mut guard = self.async_fd.readable_mut().await?;
+
+            match self.buf.read_events_with_lost(buffers, &mut on_lost) {
+                Ok(events) if events.read > 0 || events.lost > 0 => return Ok(events),
+                Ok(_) => {
+                    guard.clear_ready();
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
 }
 
 #[cfg(all(not(feature = "async_tokio"), feature = "async_std"))]
@@ -194,4 +217,26 @@ impl<T: BorrowMut<MapData> + Borrow<MapData>> AsyncPerfEventArrayBuffer<T> {
             }
         }
     }
+
+    /// Reads events from the buffer, calling `on_lost` for each lost-sample
+    /// record found.
+    ///
+    /// See [`PerfEventArrayBuffer::read_events_with_lost`].
+    pub async fn read_events_with_lost(
+        &mut self,
+        buffers: &mut [BytesMut],
+        mut on_lost: impl FnMut(LostEvents),
+    ) -> Result<Events, PerfBufferError> {
+        loop {
+            if !self.buf.readable() {
+                let _ = self.async_fd.readable().await?;
+            }
+
+            match self.buf.read_events_with_lost(buffers, &mut on_lost) {
+                Ok(events) if events.read > 0 || events.lost > 0 => return Ok(events),
+                Ok(_) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
 }
@@ -83,6 +83,18 @@ pub struct Events {
     pub lost: usize,
 }
 
+/// A `PERF_RECORD_LOST` record, reported when the kernel drops samples because
+/// the ring buffer is full.
+///
+/// See [`PerfEventArrayBuffer::read_events_with_lost`](crate::maps::perf::PerfEventArrayBuffer::read_events_with_lost).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LostEvents {
+    /// The id of the perf event that lost the samples.
+    pub id: u64,
+    /// The number of samples lost.
+    pub count: u64,
+}
+
 pub(crate) struct PerfBuffer {
     buf: AtomicPtr<perf_event_mmap_page>,
     size: usize,
@@ -143,6 +155,14 @@ impl PerfBuffer {
     pub(crate) fn read_events(
         &mut self,
         buffers: &mut [BytesMut],
+    ) -> Result<Events, PerfBufferError> {
+        self.read_events_with_lost(buffers, |_| {})
+    }
+
+    pub(crate) fn read_events_with_lost(
+        &mut self,
+        buffers: &mut [BytesMut],
+        mut on_lost: impl FnMut(LostEvents),
     ) -> Result<Events, PerfBufferError> {
         if buffers.is_empty() {
             return Err(PerfBufferError::NoBuffers);
@@ -201,9 +221,16 @@ impl PerfBuffer {
 
                     fill_buf(sample_start, base, self.size, buf);
 
-                    Ok(Some((1, 0)))
+                    Ok(Some((1, None)))
                 }
                 x if x == PERF_RECORD_LOST as u32 => {
+                    let mut id = [0u8; mem::size_of::<u64>()];
+                    fill_buf(
+                        event_start + mem::size_of::<perf_event_header>(),
+                        base,
+                        self.size,
+                        &mut id,
+                    );
                     let mut count = [0u8; mem::size_of::<u64>()];
                     fill_buf(
                         event_start + mem::size_of::<perf_event_header>() + mem::size_of::<u64>(),
@@ -211,7 +238,13 @@ impl PerfBuffer {
                         self.size,
                         &mut count,
                     );
-                    Ok(Some((0, u64::from_ne_bytes(count) as usize)))
+                    Ok(Some((
+                        0,
+                        Some(LostEvents {
+                            id: u64::from_ne_bytes(id),
+                            count: u64::from_ne_bytes(count),
+                        }),
+                    )))
                 }
                 _ => Ok(None),
             }
@@ -237,7 +270,10 @@ impl PerfBuffer {
                         buf_n += 1;
                         events.read += read;
                     }
-                    events.lost += lost;
+                    if let Some(lost) = lost {
+                        events.lost += lost.count as usize;
+                        on_lost(lost);
+                    }
                 }
                 Ok(None) => { /* skip unknown event type */ }
                 Err(e) => {
@@ -406,6 +442,44 @@ mod tests {
         assert_eq!(events.lost, 0xCAFEBABE);
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_read_lost_callback() {
+        let mut mmapped_buf = MMappedBuf {
+            data: [0; PAGE_SIZE * 2],
+        };
+        fake_mmap(&mut mmapped_buf);
+
+        let lost = |id, count| LostSamples {
+            header: perf_event_header {
+                type_: PERF_RECORD_LOST as u32,
+                misc: 0,
+                size: mem::size_of::<LostSamples>() as u16,
+            },
+            id,
+            count,
+        };
+        let next = write(&mut mmapped_buf, 0, lost(1, 10));
+        let next = write_sample(&mut mmapped_buf, next, 0xCAFEBABEu32);
+        write(&mut mmapped_buf, next, lost(2, 5));
+
+        let mut buf = PerfBuffer::open(1, PAGE_SIZE, 1).unwrap();
+        let mut out_bufs = [BytesMut::with_capacity(4), BytesMut::with_capacity(4)];
+        let mut records = Vec::new();
+        let events = buf
+            .read_events_with_lost(&mut out_bufs, |lost| records.push(lost))
+            .unwrap();
+        assert_eq!(events, Events { lost: 15, read: 1 });
+        assert_eq!(
+            records,
+            vec![
+                LostEvents { id: 1, count: 10 },
+                LostEvents { id: 2, count: 5 }
+            ]
+        );
+        assert_eq!(u32_from_buf(&out_bufs[0]), 0xCAFEBABE);
+    }
+
     #[repr(C)]
     #[derive(Debug)]
     struct PerfSample<T: Debug> {
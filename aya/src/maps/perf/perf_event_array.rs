@@ -12,7 +12,7 @@ use bytes::BytesMut;
 
 use crate::{
     maps::{
-        perf::{Events, PerfBuffer, PerfBufferError},
+        perf::{Events, LostEvents, PerfBuffer, PerfBufferError},
         MapData, MapError,
     },
     sys::bpf_map_update_elem,
@@ -53,6 +53,25 @@ impl<T: BorrowMut<MapData> + Borrow<MapData>> PerfEventArrayBuffer<T> {
     pub fn read_events(&mut self, out_bufs: &mut [BytesMut]) -> Result<Events, PerfBufferError> {
         self.buf.read_events(out_bufs)
     }
+
+    /// Reads events from the buffer, calling `on_lost` for each lost-sample
+    /// record found.
+    ///
+    /// This behaves like [`read_events`](Self::read_events), but instead of
+    /// only reporting the total number of lost events, `on_lost` is called
+    /// with every `PERF_RECORD_LOST` record read in this batch, in the order
+    /// in which they were emitted relative to the samples.
+    ///
+    /// # Errors
+    ///
+    /// [`PerfBufferError::NoBuffers`] is returned when `out_bufs` is empty.
+    pub fn read_events_with_lost(
+        &mut self,
+        out_bufs: &mut [BytesMut],
+        on_lost: impl FnMut(LostEvents),
+    ) -> Result<Events, PerfBufferError> {
+        self.buf.read_events_with_lost(out_bufs, on_lost)
+    }
 }
 
 impl<T: BorrowMut<MapData> + Borrow<MapData>> AsRawFd for PerfEventArrayBuffer<T> {
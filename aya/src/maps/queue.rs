@@ -6,7 +6,7 @@ use std::{
 
 use crate::{
     maps::{check_kv_size, MapData, MapError},
-    sys::{bpf_map_lookup_and_delete_elem, bpf_map_push_elem},
+    sys::{bpf_map_lookup_and_delete_elem, bpf_map_peek_elem, bpf_map_push_elem},
     Pod,
 };
 
@@ -24,6 +24,7 @@ use crate::{
 /// let mut queue = Queue::try_from(bpf.map_mut("ARRAY").unwrap())?;
 /// queue.push(42, 0)?;
 /// queue.push(43, 0)?;
+/// assert_eq!(queue.peek(0)?, 42);
 /// assert_eq!(queue.pop(0)?, 42);
 /// # Ok::<(), aya::BpfError>(())
 /// ```
@@ -52,6 +53,23 @@ impl<T: Borrow<MapData>, V: Pod> Queue<T, V> {
     pub fn capacity(&self) -> u32 {
         self.inner.borrow().obj.max_entries()
     }
+
+    /// Returns the first element without removing it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::ElementNotFound`] if the queue is empty, [`MapError::SyscallError`]
+    /// if `bpf_map_lookup_elem` fails.
+    pub fn peek(&self, flags: u64) -> Result<V, MapError> {
+        let fd = self.inner.borrow().fd_or_err()?;
+
+        let value =
+            bpf_map_peek_elem(fd, flags).map_err(|(_, io_error)| MapError::SyscallError {
+                call: "bpf_map_lookup_elem".to_owned(),
+                io_error,
+            })?;
+        value.ok_or(MapError::ElementNotFound)
+    }
 }
 
 impl<T: BorrowMut<MapData>, V: Pod> Queue<T, V> {
@@ -89,3 +107,96 @@ impl<T: BorrowMut<MapData>, V: Pod> Queue<T, V> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use libc::{EFAULT, ENOENT};
+
+    use super::*;
+    use crate::{
+        bpf_map_def,
+        generated::{bpf_cmd, bpf_map_type::BPF_MAP_TYPE_QUEUE},
+        obj::{self, maps::LegacyMap, BpfSectionKind},
+        sys::{override_syscall, SysResult, Syscall},
+    };
+
+    fn new_obj_map() -> obj::Map {
+        obj::Map::Legacy(LegacyMap {
+            def: bpf_map_def {
+                map_type: BPF_MAP_TYPE_QUEUE as u32,
+                key_size: 0,
+                value_size: 4,
+                max_entries: 1024,
+                ..Default::default()
+            },
+            section_index: 0,
+            section_kind: BpfSectionKind::Maps,
+            symbol_index: None,
+            data: Vec::new(),
+        })
+    }
+
+    fn new_map() -> MapData {
+        MapData {
+            obj: new_obj_map(),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        }
+    }
+
+    fn sys_error(value: i32) -> SysResult {
+        Err((-1, io::Error::from_raw_os_error(value)))
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_peek() {
+        // Peeking looks the element up without a key, and doesn't delete it.
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_ELEM,
+                attr,
+            } if unsafe { attr.__bindgen_anon_2.key } == 0 => {
+                unsafe { *(attr.__bindgen_anon_2.__bindgen_anon_1.value as *mut u32) = 42 };
+                Ok(0)
+            }
+            _ => sys_error(EFAULT),
+        });
+
+        let map = new_map();
+        let queue = Queue::<_, u32>::new(&map).unwrap();
+        assert!(matches!(queue.peek(0), Ok(42)));
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_peek_empty() {
+        override_syscall(|_| sys_error(ENOENT));
+
+        let map = new_map();
+        let queue = Queue::<_, u32>::new(&map).unwrap();
+        assert!(matches!(queue.peek(0), Err(MapError::ElementNotFound)));
+    }
+
+    #[test]
+    fn test_peek_syscall_error() {
+        override_syscall(|_| sys_error(EFAULT));
+
+        let map = new_map();
+        let queue = Queue::<_, u32>::new(&map).unwrap();
+        assert!(matches!(
+            queue.peek(0),
+            Err(MapError::SyscallError { call, io_error })
+                if call == "bpf_map_lookup_elem" && io_error.raw_os_error() == Some(EFAULT)
+        ));
+    }
+}
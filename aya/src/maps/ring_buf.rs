@@ -0,0 +1,371 @@
+//! A ring buffer shared by all the CPUs.
+use std::{
+    borrow::Borrow,
+    ffi::c_void,
+    fmt::{self, Debug, Formatter},
+    io,
+    ops::Deref,
+    os::unix::io::{AsRawFd, RawFd},
+    ptr::{self, NonNull},
+    slice,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+};
+
+use libc::{munmap, off_t, MAP_FAILED, MAP_SHARED, PROT_READ, PROT_WRITE};
+
+use crate::{
+    generated::{BPF_RINGBUF_BUSY_BIT, BPF_RINGBUF_DISCARD_BIT, BPF_RINGBUF_HDR_SZ},
+    maps::{MapData, MapError},
+    util::page_size,
+};
+
+/// A ring buffer shared by all the CPUs, to which eBPF programs write records
+/// with `bpf_ringbuf_output` or `bpf_ringbuf_reserve`/`bpf_ringbuf_submit`.
+///
+/// Unlike [`PerfEventArray`](crate::maps::PerfEventArray), records from all the
+/// CPUs are read from a single buffer, in the order in which they were
+/// reserved, and programs know when a record couldn't be written because the
+/// buffer is full.
+///
+/// Records are read with [`RingBuf::next`], which doesn't block and returns
+/// `None` once all the submitted records have been read. The ring buffer can be
+/// busy-polled by calling it in a loop, or waited on with `epoll` or an async
+/// runtime through its file descriptor, which becomes readable when records are
+/// submitted, unless the programs pass `BPF_RB_NO_WAKEUP`.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.8.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::RingBuf;
+///
+/// let mut ring_buf = RingBuf::try_from(bpf.map_mut("EVENTS").unwrap())?;
+/// while let Some(item) = ring_buf.next() {
+///     println!("received {} bytes", item.len());
+/// }
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+///
+/// With tokio, the ring buffer can be wrapped in an
+/// [`AsyncFd`](https://docs.rs/tokio/latest/tokio/io/unix/struct.AsyncFd.html)
+/// to wait for records:
+///
+/// ```no_run
+/// # #[derive(thiserror::Error, Debug)]
+/// # enum Error {
+/// #    #[error(transparent)]
+/// #    IO(#[from] std::io::Error),
+/// #    #[error(transparent)]
+/// #    Map(#[from] aya::maps::MapError),
+/// #    #[error(transparent)]
+/// #    Bpf(#[from] aya::BpfError),
+/// # }
+/// # #[cfg(feature = "async_tokio")]
+/// # async fn try_main() -> Result<(), Error> {
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::RingBuf;
+/// use tokio::io::unix::AsyncFd;
+///
+/// let ring_buf = RingBuf::try_from(bpf.take_map("EVENTS").unwrap())?;
+/// let mut async_fd = AsyncFd::new(ring_buf)?;
+/// loop {
+///     let mut guard = async_fd.readable_mut().await?;
+///     let ring_buf = guard.get_inner_mut();
+///     while let Some(item) = ring_buf.next() {
+///         // process item
+///     }
+///     guard.clear_ready();
+/// }
+/// # }
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_RINGBUF")]
+pub struct RingBuf<T> {
+    map: T,
+    consumer: ConsumerPos,
+    producer: ProducerData,
+}
+
+impl<T: Borrow<MapData>> RingBuf<T> {
+    pub(crate) fn new(map: T) -> Result<RingBuf<T>, MapError> {
+        let data = map.borrow();
+        let fd = data.fd_or_err()?;
+        let page_size = page_size();
+        // The size of the data area, a power of two multiple of the page size.
+        let byte_size = data.obj.max_entries() as usize;
+
+        // The consumer position is the only part of the ring buffer userspace
+        // writes to.
+        let consumer = ConsumerPos::new(MMap::new(fd, page_size, PROT_READ | PROT_WRITE, 0)?);
+        // The data area is mapped twice after the producer position, so that
+        // records wrapping around the end of the buffer are contiguous.
+        let producer = ProducerData::new(
+            MMap::new(fd, page_size + 2 * byte_size, PROT_READ, page_size as off_t)?,
+            page_size,
+            byte_size,
+        );
+
+        Ok(RingBuf {
+            map,
+            consumer,
+            producer,
+        })
+    }
+}
+
+impl<T> RingBuf<T> {
+    /// Returns the next record, or `None` if there are no records left to read.
+    ///
+    /// The record is consumed, making room for new records in the ring buffer,
+    /// when the returned [`RingBufItem`] is dropped. Records are returned in
+    /// the order in which they were reserved: a record which was reserved but
+    /// not submitted yet holds back the records which follow it.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<RingBufItem<'_>> {
+        let RingBuf {
+            consumer, producer, ..
+        } = self;
+        producer.next(consumer)
+    }
+}
+
+impl<T: Borrow<MapData>> AsRawFd for RingBuf<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        // `new` checked that the map is created.
+        self.map.borrow().fd.unwrap()
+    }
+}
+
+/// A record read from a [`RingBuf`], consumed when dropped.
+pub struct RingBufItem<'a> {
+    data: &'a [u8],
+    consumer: &'a mut ConsumerPos,
+}
+
+impl Deref for RingBufItem<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.data
+    }
+}
+
+impl Drop for RingBufItem<'_> {
+    fn drop(&mut self) {
+        self.consumer.consume(self.data.len());
+    }
+}
+
+impl Debug for RingBufItem<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RingBufItem")
+            .field("len", &self.data.len())
+            .finish()
+    }
+}
+
+/// The page holding the position up to which the records have been read.
+struct ConsumerPos {
+    pos: usize,
+    mmap: MMap,
+}
+
+impl ConsumerPos {
+    fn new(mmap: MMap) -> ConsumerPos {
+        // The ring buffer may have been read from before, e.g. when pinned.
+        let pos = unsafe { &*(mmap.as_ptr() as *const AtomicUsize) }.load(Ordering::Acquire);
+        ConsumerPos { pos, mmap }
+    }
+
+    /// Moves past the record of `len` bytes at the current position.
+    fn consume(&mut self, len: usize) {
+        self.pos += (len + BPF_RINGBUF_HDR_SZ as usize + 7) & !7;
+        unsafe { &*(self.mmap.as_ptr() as *const AtomicUsize) }.store(self.pos, Ordering::Release);
+    }
+}
+
+/// The page holding the position up to which the records have been reserved,
+/// followed by the data area mapped twice.
+struct ProducerData {
+    mmap: MMap,
+    data_offset: usize,
+    mask: usize,
+}
+
+impl ProducerData {
+    fn new(mmap: MMap, data_offset: usize, byte_size: usize) -> ProducerData {
+        ProducerData {
+            mmap,
+            data_offset,
+            mask: byte_size - 1,
+        }
+    }
+
+    fn next<'a>(&'a self, consumer: &'a mut ConsumerPos) -> Option<RingBufItem<'a>> {
+        let base = self.mmap.as_ptr();
+        let producer_pos = unsafe { &*(base as *const AtomicUsize) }.load(Ordering::Acquire);
+        while consumer.pos != producer_pos {
+            let offset = self.data_offset + (consumer.pos & self.mask);
+            // Records are 8 bytes aligned, so is their header.
+            let header =
+                unsafe { &*(base.add(offset) as *const AtomicU32) }.load(Ordering::Acquire);
+            if header & BPF_RINGBUF_BUSY_BIT != 0 {
+                // Reserved but not submitted or discarded yet.
+                return None;
+            }
+            let len = (header & !(BPF_RINGBUF_BUSY_BIT | BPF_RINGBUF_DISCARD_BIT)) as usize;
+            if header & BPF_RINGBUF_DISCARD_BIT != 0 {
+                consumer.consume(len);
+                continue;
+            }
+            let data = unsafe {
+                slice::from_raw_parts(base.add(offset + BPF_RINGBUF_HDR_SZ as usize), len)
+            };
+            return Some(RingBufItem { data, consumer });
+        }
+        None
+    }
+}
+
+/// A shared memory mapping of a ring buffer, unmapped when dropped.
+pub(crate) struct MMap {
+    ptr: NonNull<c_void>,
+    len: usize,
+}
+
+// The mapping is only accessed through the atomics of the positions and the
+// slices of the records, whose lifetimes are tied to the ring buffers.
+unsafe impl Send for MMap {}
+unsafe impl Sync for MMap {}
+
+impl MMap {
+    pub(crate) fn new(
+        fd: RawFd,
+        len: usize,
+        prot: libc::c_int,
+        offset: off_t,
+    ) -> Result<MMap, MapError> {
+        let ptr = unsafe { libc::mmap(ptr::null_mut(), len, prot, MAP_SHARED, fd, offset) };
+        if ptr == MAP_FAILED {
+            return Err(MapError::SyscallError {
+                call: "mmap".to_owned(),
+                io_error: io::Error::last_os_error(),
+            });
+        }
+        Ok(MMap {
+            ptr: NonNull::new(ptr).unwrap(),
+            len,
+        })
+    }
+
+    /// Maps anonymous memory standing for the pages of a ring buffer.
+    #[cfg(test)]
+    pub(crate) fn anonymous(len: usize) -> MMap {
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                PROT_READ | PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(ptr, MAP_FAILED);
+        MMap {
+            ptr: NonNull::new(ptr).unwrap(),
+            len,
+        }
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut u8 {
+        self.ptr.as_ptr() as *mut u8
+    }
+}
+
+impl Drop for MMap {
+    fn drop(&mut self) {
+        unsafe { munmap(self.ptr.as_ptr(), self.len) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bpf_map_def,
+        generated::bpf_map_type::BPF_MAP_TYPE_PERF_EVENT_ARRAY,
+        maps::Map,
+        obj::{self, maps::LegacyMap, BpfSectionKind},
+    };
+
+    const DATA_SIZE: usize = 4096;
+
+    /// Writes a record header at `pos` and advances the producer position.
+    fn produce(producer: &ProducerData, pos: usize, header: u32, len: usize) -> usize {
+        let base = producer.mmap.as_ptr();
+        unsafe {
+            *(base.add(producer.data_offset + (pos & producer.mask)) as *mut u32) = header;
+            let next = pos + ((len + BPF_RINGBUF_HDR_SZ as usize + 7) & !7);
+            *(base as *mut usize) = next;
+            next
+        }
+    }
+
+    #[test]
+    fn test_next() {
+        let page_size = page_size();
+        let mut consumer = ConsumerPos::new(MMap::anonymous(page_size));
+        let producer =
+            ProducerData::new(MMap::anonymous(page_size + DATA_SIZE), page_size, DATA_SIZE);
+        assert!(producer.next(&mut consumer).is_none());
+
+        let pos = produce(&producer, 0, 3, 3);
+        let pos = produce(&producer, pos, BPF_RINGBUF_DISCARD_BIT | 5, 5);
+        produce(&producer, pos, BPF_RINGBUF_BUSY_BIT | 1, 1);
+
+        let item = producer.next(&mut consumer).unwrap();
+        assert_eq!(item.len(), 3);
+        drop(item);
+        assert_eq!(consumer.pos, 16);
+        assert_eq!(
+            unsafe { *(consumer.mmap.as_ptr() as *const usize) },
+            consumer.pos
+        );
+
+        // The discarded record is skipped, the busy one holds back the reader.
+        assert!(producer.next(&mut consumer).is_none());
+        assert_eq!(consumer.pos, 32);
+    }
+
+    #[test]
+    fn test_try_from_wrong_map() {
+        let map_data = MapData {
+            obj: obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_PERF_EVENT_ARRAY as u32,
+                    key_size: 4,
+                    value_size: 4,
+                    max_entries: 1024,
+                    ..Default::default()
+                },
+                section_index: 0,
+                section_kind: BpfSectionKind::Maps,
+                symbol_index: None,
+                data: Vec::new(),
+            }),
+            fd: None,
+            pinned: false,
+            btf_fd: None,
+        };
+
+        let map = Map::PerfEventArray(map_data);
+        assert!(matches!(
+            RingBuf::try_from(&map),
+            Err(MapError::InvalidMapType { .. })
+        ));
+    }
+}
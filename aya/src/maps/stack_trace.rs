@@ -1,11 +1,20 @@
 //! A hash map of kernel or user space stack traces.
 //!
 //! See [`StackTraceMap`] for documentation and examples.
-use std::{borrow::Borrow, collections::BTreeMap, fs, io, mem, path::Path, str::FromStr};
+use std::{
+    borrow::Borrow,
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader},
+    mem,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use crate::{
     maps::{IterableMap, MapData, MapError, MapIter, MapKeys},
     sys::bpf_map_lookup_elem_ptr,
+    util::KernelSymbols,
 };
 
 /// A hash map of kernel or user space stack traces.
@@ -44,8 +53,8 @@ use crate::{
 /// let mut stack_trace = stack_traces.get(&stack_id, 0)?;
 ///
 /// // here we resolve symbol names using kernel symbols. If this was a user space stack (for
-/// // example captured from a uprobe), you'd have to load the symbols using some other mechanism
-/// // (eg loading the target binary debuginfo)
+/// // example captured from a uprobe), see `StackTrace::symbolize_user` to locate the frames in
+/// // the binaries of the process and resolve them with their symbols.
 /// for frame in stack_trace.resolve(&ksyms).frames() {
 ///     println!(
 ///         "{:#x} {}",
@@ -171,7 +180,8 @@ pub struct StackTrace {
 impl StackTrace {
     /// Resolves symbol names using the given symbol map.
     ///
-    /// You can use [`util::kernel_symbols()`](crate::util::kernel_symbols) to load kernel symbols. For
+    /// You can use [`util::kernel_symbols()`](crate::util::kernel_symbols) or the cached
+    /// [`KernelSymbols::symbols`](crate::util::KernelSymbols::symbols) to load kernel symbols. For
     /// user-space traces you need to provide the symbols, for example loading
     /// them from debug info.
     pub fn resolve(&mut self, symbols: &BTreeMap<u64, String>) -> &StackTrace {
@@ -189,6 +199,95 @@ impl StackTrace {
     pub fn frames(&self) -> &[StackFrame] {
         &self.frames
     }
+
+    /// Symbolizes a kernel stack trace.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let bpf = aya::Bpf::load(&[])?;
+    /// use aya::{maps::StackTraceMap, util::KernelSymbols};
+    ///
+    /// let stack_traces = StackTraceMap::try_from(bpf.map("STACK_TRACES").unwrap())?;
+    /// let ksyms = KernelSymbols::get()?;
+    /// let stack = stack_traces.get(&1234, 0)?.symbolize_kernel(&ksyms);
+    /// for frame in stack.frames {
+    ///     match frame.symbol {
+    ///         Some(name) => println!("{:#x} {}+{:#x}", frame.ip, name, frame.symbol_offset),
+    ///         None => println!("{:#x} [unknown]", frame.ip),
+    ///     }
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn symbolize_kernel(&self, symbols: &KernelSymbols) -> SymbolizedStack {
+        let frames = self
+            .frames
+            .iter()
+            .map(|frame| {
+                let (symbol, symbol_offset) = match symbols.resolve(frame.ip) {
+                    Some((name, offset)) => (Some(name.to_owned()), offset),
+                    None => (None, 0),
+                };
+                SymbolizedFrame {
+                    ip: frame.ip,
+                    symbol,
+                    symbol_offset,
+                    object: None,
+                    object_offset: None,
+                }
+            })
+            .collect();
+        SymbolizedStack {
+            id: self.id,
+            frames,
+        }
+    }
+
+    /// Symbolizes a user space stack trace of the process whose memory
+    /// mappings are `maps`.
+    ///
+    /// Each frame is first located in the file mapped at its address. The
+    /// symbol names are then looked up in `source`, if given; otherwise only
+    /// the file and the offset in the file are reported, which can be
+    /// symbolized offline.
+    ///
+    /// The mappings must be read while the process is still running, see
+    /// [`ProcessMaps::read`].
+    pub fn symbolize_user(
+        &self,
+        maps: &ProcessMaps,
+        source: Option<&dyn SymbolSource>,
+    ) -> SymbolizedStack {
+        let frames = self
+            .frames
+            .iter()
+            .map(|frame| {
+                let mut symbolized = SymbolizedFrame {
+                    ip: frame.ip,
+                    symbol: None,
+                    symbol_offset: 0,
+                    object: None,
+                    object_offset: None,
+                };
+                if let Some(mapping) = maps.find(frame.ip) {
+                    let object_offset = mapping.file_offset(frame.ip);
+                    if let (Some(path), Some(source)) = (&mapping.path, source) {
+                        if let Some((name, offset)) = source.symbolize(path, object_offset) {
+                            symbolized.symbol = Some(name);
+                            symbolized.symbol_offset = offset;
+                        }
+                    }
+                    symbolized.object = mapping.path.clone();
+                    symbolized.object_offset = Some(object_offset);
+                }
+                symbolized
+            })
+            .collect();
+        SymbolizedStack {
+            id: self.id,
+            frames,
+        }
+    }
 }
 
 /// A stack frame.
@@ -202,9 +301,214 @@ pub struct StackFrame {
     pub symbol_name: Option<String>,
 }
 
+/// A stack trace whose frames have been resolved to symbols.
+///
+/// See [`StackTrace::symbolize_kernel`] and [`StackTrace::symbolize_user`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolizedStack {
+    /// The stack trace id as returned by `bpf_get_stackid()`.
+    pub id: u32,
+    /// The frames, innermost first.
+    pub frames: Vec<SymbolizedFrame>,
+}
+
+/// A symbolized stack frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolizedFrame {
+    /// The instruction pointer of this frame.
+    pub ip: u64,
+    /// The name of the symbol containing the instruction pointer.
+    pub symbol: Option<String>,
+    /// The offset of the instruction pointer from the start of the symbol, or
+    /// zero if the symbol is unknown.
+    pub symbol_offset: u64,
+    /// The file mapped at the instruction pointer, for user space frames.
+    pub object: Option<PathBuf>,
+    /// The offset of the instruction pointer in [`SymbolizedFrame::object`],
+    /// for user space frames.
+    pub object_offset: Option<u64>,
+}
+
+/// A source of symbols for user space stack traces.
+///
+/// Implementations typically read the symbol table or the debug info of the
+/// ELF file at `path`, for example with the `object` or `addr2line` crates.
+pub trait SymbolSource {
+    /// Returns the name of the symbol containing `file_offset` in the file at
+    /// `path`, and the offset from the start of the symbol.
+    fn symbolize(&self, path: &Path, file_offset: u64) -> Option<(String, u64)>;
+}
+
+/// The memory mappings of a process, as listed in `/proc/<pid>/maps`.
+///
+/// Used to locate the frames of user space stack traces in the executable and
+/// the shared libraries of the process, see [`StackTrace::symbolize_user`].
+#[derive(Debug, Clone)]
+pub struct ProcessMaps {
+    mappings: BTreeMap<u64, MemoryMapping>,
+}
+
+/// A memory mapping of a process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryMapping {
+    /// The start address of the mapping.
+    pub start: u64,
+    /// The end address of the mapping, exclusive.
+    pub end: u64,
+    /// The offset in the file of the start of the mapping.
+    pub offset: u64,
+    /// The mapped file, or a pseudo path like `[vdso]`. `None` for anonymous
+    /// mappings.
+    pub path: Option<PathBuf>,
+}
+
+impl MemoryMapping {
+    /// Returns the offset in the mapped file of the address `addr`.
+    pub fn file_offset(&self, addr: u64) -> u64 {
+        addr - self.start + self.offset
+    }
+}
+
+impl ProcessMaps {
+    /// Reads the memory mappings of the process `pid`.
+    pub fn read(pid: u32) -> Result<ProcessMaps, io::Error> {
+        let file = File::open(format!("/proc/{pid}/maps"))?;
+        ProcessMaps::parse(BufReader::new(file))
+    }
+
+    fn parse(reader: impl BufRead) -> Result<ProcessMaps, io::Error> {
+        let mut mappings = BTreeMap::new();
+        for line in reader.lines() {
+            let line = line?;
+            let mapping = parse_mapping(&line)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, line.clone()))?;
+            mappings.insert(mapping.start, mapping);
+        }
+        Ok(ProcessMaps { mappings })
+    }
+
+    /// Returns the mapping containing `addr`.
+    pub fn find(&self, addr: u64) -> Option<&MemoryMapping> {
+        self.mappings
+            .range(..=addr)
+            .next_back()
+            .map(|(_, mapping)| mapping)
+            .filter(|mapping| addr < mapping.end)
+    }
+
+    /// An iterator over the mappings, sorted by address.
+    pub fn mappings(&self) -> impl Iterator<Item = &MemoryMapping> {
+        self.mappings.values()
+    }
+}
+
+// Parses a `/proc/<pid>/maps` line like
+// `7f1c2a000000-7f1c2a1c5000 r-xp 00028000 08:01 1234  /usr/lib/libc.so.6`.
+fn parse_mapping(line: &str) -> Option<MemoryMapping> {
+    let mut parts = line.splitn(6, ' ');
+    let (start, end) = parts.next()?.split_once('-')?;
+    let offset = parts.nth(1)?;
+    // skip the device and the inode
+    let path = parts.nth(2).map(str::trim).filter(|path| !path.is_empty());
+    Some(MemoryMapping {
+        start: u64::from_str_radix(start, 16).ok()?,
+        end: u64::from_str_radix(end, 16).ok()?,
+        offset: u64::from_str_radix(offset, 16).ok()?,
+        path: path.map(PathBuf::from),
+    })
+}
+
 fn sysctl<T: FromStr>(key: &str) -> Result<T, io::Error> {
     let val = fs::read_to_string(Path::new("/proc/sys").join(key))?;
     val.trim()
         .parse::<T>()
         .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, val))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAPS: &str = "\
+55d5c7a0e000-55d5c7a10000 r--p 00000000 08:01 1234                       /usr/bin/cat
+55d5c7a10000-55d5c7a15000 r-xp 00002000 08:01 1234                       /usr/bin/cat
+7ffd0c1f0000-7ffd0c211000 rw-p 00000000 00:00 0                          [stack]
+7ffd0c3fe000-7ffd0c400000 rw-p 00000000 00:00 0
+";
+
+    struct FakeSource;
+
+    impl SymbolSource for FakeSource {
+        fn symbolize(&self, path: &Path, file_offset: u64) -> Option<(String, u64)> {
+            (path == Path::new("/usr/bin/cat") && file_offset >= 0x2100)
+                .then(|| ("main".to_owned(), file_offset - 0x2100))
+        }
+    }
+
+    fn stack_trace(ips: &[u64]) -> StackTrace {
+        StackTrace {
+            id: 1,
+            frames: ips
+                .iter()
+                .map(|ip| StackFrame {
+                    ip: *ip,
+                    symbol_name: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse_process_maps() {
+        let maps = ProcessMaps::parse(MAPS.as_bytes()).unwrap();
+        assert_eq!(maps.mappings().count(), 4);
+
+        let mapping = maps.find(0x55d5c7a10010).unwrap();
+        assert_eq!(mapping.path.as_deref(), Some(Path::new("/usr/bin/cat")));
+        assert_eq!(mapping.file_offset(0x55d5c7a10010), 0x2010);
+        assert_eq!(
+            maps.find(0x7ffd0c1f0000).unwrap().path.as_deref(),
+            Some(Path::new("[stack]"))
+        );
+        assert_eq!(maps.find(0x7ffd0c3fe000).unwrap().path, None);
+        assert!(maps.find(0x55d5c7a15000).is_none());
+        assert!(maps.find(0x1000).is_none());
+    }
+
+    #[test]
+    fn test_symbolize_user() {
+        let maps = ProcessMaps::parse(MAPS.as_bytes()).unwrap();
+        let trace = stack_trace(&[0x55d5c7a10110, 0x55d5c7a10010, 0x1000]);
+
+        let stack = trace.symbolize_user(&maps, Some(&FakeSource));
+        assert_eq!(stack.id, 1);
+        assert_eq!(
+            stack.frames[0],
+            SymbolizedFrame {
+                ip: 0x55d5c7a10110,
+                symbol: Some("main".to_owned()),
+                symbol_offset: 0x10,
+                object: Some(PathBuf::from("/usr/bin/cat")),
+                object_offset: Some(0x2110),
+            }
+        );
+        assert_eq!(stack.frames[1].symbol, None);
+        assert_eq!(stack.frames[1].object_offset, Some(0x2010));
+        assert_eq!(stack.frames[2].object, None);
+
+        let stack = trace.symbolize_user(&maps, None);
+        assert_eq!(stack.frames[0].symbol, None);
+        assert_eq!(stack.frames[0].object_offset, Some(0x2110));
+    }
+
+    #[test]
+    fn test_symbolize_kernel() {
+        let data = "ffffffff81000000 T _text\n\
+                    ffffffff81000100 t tcp_v4_connect\n";
+        let syms = KernelSymbols::parse(data.as_bytes()).unwrap();
+        let stack = stack_trace(&[0xffffffff81000110, 0x1000]).symbolize_kernel(&syms);
+        assert_eq!(stack.frames[0].symbol.as_deref(), Some("tcp_v4_connect"));
+        assert_eq!(stack.frames[0].symbol_offset, 0x10);
+        assert_eq!(stack.frames[1].symbol, None);
+    }
+}
@@ -0,0 +1,91 @@
+use std::{
+    borrow::{Borrow, BorrowMut},
+    marker::PhantomData,
+    os::unix::io::AsRawFd,
+};
+
+use crate::{
+    maps::{check_kv_size, storage, MapData, MapError},
+    Pod,
+};
+
+/// Inode local storage, holding a value per inode.
+///
+/// The storage of an inode is accessed from user space with a file descriptor
+/// of a file referring to the inode. The map is used by LSM programs.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.10.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[derive(Debug, thiserror::Error)]
+/// # enum Error {
+/// #     #[error(transparent)]
+/// #     IO(#[from] std::io::Error),
+/// #     #[error(transparent)]
+/// #     Map(#[from] aya::maps::MapError),
+/// #     #[error(transparent)]
+/// #     Bpf(#[from] aya::BpfError)
+/// # }
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::InodeStorage;
+/// use std::fs::File;
+///
+/// let mut storage = InodeStorage::<_, u32>::try_from(bpf.map_mut("PROTECTED").unwrap())?;
+///
+/// let file = File::open("/etc/shadow")?;
+/// storage.insert(&file, 1, 0)?;
+/// # Ok::<(), Error>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_INODE_STORAGE")]
+pub struct InodeStorage<T, V: Pod> {
+    inner: T,
+    _v: PhantomData<V>,
+}
+
+impl<T: Borrow<MapData>, V: Pod> InodeStorage<T, V> {
+    pub(crate) fn new(map: T) -> Result<InodeStorage<T, V>, MapError> {
+        let data = map.borrow();
+        check_kv_size::<i32, V>(data)?;
+        let _ = data.fd_or_err()?;
+
+        Ok(InodeStorage {
+            inner: map,
+            _v: PhantomData,
+        })
+    }
+
+    /// Returns a copy of the value stored for the inode of the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::KeyNotFound`] if no value is stored for the inode.
+    pub fn get(&self, file: &impl AsRawFd, flags: u64) -> Result<V, MapError> {
+        storage::get(self.inner.borrow(), file.as_raw_fd(), flags)
+    }
+}
+
+impl<T: BorrowMut<MapData>, V: Pod> InodeStorage<T, V> {
+    /// Stores a value for the inode of the file.
+    pub fn insert(
+        &mut self,
+        file: &impl AsRawFd,
+        value: impl Borrow<V>,
+        flags: u64,
+    ) -> Result<(), MapError> {
+        storage::insert(
+            self.inner.borrow_mut(),
+            file.as_raw_fd(),
+            value.borrow(),
+            flags,
+        )
+    }
+
+    /// Removes the value stored for the inode of the file.
+    pub fn remove(&mut self, file: &impl AsRawFd) -> Result<(), MapError> {
+        storage::remove(self.inner.borrow_mut(), file.as_raw_fd())
+    }
+}
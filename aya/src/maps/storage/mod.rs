@@ -0,0 +1,57 @@
+//! Local storage maps.
+//!
+//! Local storage maps attach a value to kernel objects like tasks, sockets and
+//! inodes, which is freed with the object. From user space, the storage of an
+//! object is accessed with a file descriptor referring to it.
+mod inode_storage;
+mod sk_storage;
+mod task_storage;
+
+pub use inode_storage::InodeStorage;
+pub use sk_storage::SkStorage;
+pub use task_storage::TaskStorage;
+
+use std::os::unix::io::RawFd;
+
+use crate::{
+    maps::{MapData, MapError},
+    sys::{bpf_map_delete_elem, bpf_map_lookup_elem, bpf_map_update_elem},
+    Pod,
+};
+
+pub(crate) fn get<V: Pod>(map: &MapData, fd: RawFd, flags: u64) -> Result<V, MapError> {
+    let map_fd = map.fd_or_err()?;
+    let value = bpf_map_lookup_elem(map_fd, &fd, flags).map_err(|(_, io_error)| {
+        MapError::SyscallError {
+            call: "bpf_map_lookup_elem".to_owned(),
+            io_error,
+        }
+    })?;
+    value.ok_or(MapError::KeyNotFound)
+}
+
+pub(crate) fn insert<V: Pod>(
+    map: &mut MapData,
+    fd: RawFd,
+    value: &V,
+    flags: u64,
+) -> Result<(), MapError> {
+    let map_fd = map.fd_or_err()?;
+    bpf_map_update_elem(map_fd, Some(&fd), value, flags).map_err(|(_, io_error)| {
+        MapError::SyscallError {
+            call: "bpf_map_update_elem".to_owned(),
+            io_error,
+        }
+    })?;
+    Ok(())
+}
+
+pub(crate) fn remove(map: &mut MapData, fd: RawFd) -> Result<(), MapError> {
+    let map_fd = map.fd_or_err()?;
+    bpf_map_delete_elem(map_fd, &fd)
+        .map(|_| ())
+        .map_err(|(_, io_error)| MapError::SyscallError {
+            call: "bpf_map_delete_elem".to_owned(),
+            io_error,
+        })
+}
@@ -0,0 +1,92 @@
+use std::{
+    borrow::{Borrow, BorrowMut},
+    marker::PhantomData,
+    os::unix::io::AsRawFd,
+};
+
+use crate::{
+    maps::{check_kv_size, storage, MapData, MapError},
+    Pod,
+};
+
+/// Socket local storage, holding a value per socket.
+///
+/// The storage of a socket is accessed from user space with a file descriptor
+/// of the socket, for example a [`TcpStream`](std::net::TcpStream).
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.2.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[derive(Debug, thiserror::Error)]
+/// # enum Error {
+/// #     #[error(transparent)]
+/// #     IO(#[from] std::io::Error),
+/// #     #[error(transparent)]
+/// #     Map(#[from] aya::maps::MapError),
+/// #     #[error(transparent)]
+/// #     Bpf(#[from] aya::BpfError)
+/// # }
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::SkStorage;
+/// use std::net::TcpStream;
+///
+/// let mut storage = SkStorage::<_, u64>::try_from(bpf.map_mut("SOCKET_BYTES").unwrap())?;
+///
+/// let stream = TcpStream::connect("127.0.0.1:1234")?;
+/// storage.insert(&stream, 0, 0)?;
+/// let bytes = storage.get(&stream, 0)?;
+/// # Ok::<(), Error>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_SK_STORAGE")]
+pub struct SkStorage<T, V: Pod> {
+    inner: T,
+    _v: PhantomData<V>,
+}
+
+impl<T: Borrow<MapData>, V: Pod> SkStorage<T, V> {
+    pub(crate) fn new(map: T) -> Result<SkStorage<T, V>, MapError> {
+        let data = map.borrow();
+        check_kv_size::<i32, V>(data)?;
+        let _ = data.fd_or_err()?;
+
+        Ok(SkStorage {
+            inner: map,
+            _v: PhantomData,
+        })
+    }
+
+    /// Returns a copy of the value stored for the socket.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::KeyNotFound`] if no value is stored for the socket.
+    pub fn get(&self, socket: &impl AsRawFd, flags: u64) -> Result<V, MapError> {
+        storage::get(self.inner.borrow(), socket.as_raw_fd(), flags)
+    }
+}
+
+impl<T: BorrowMut<MapData>, V: Pod> SkStorage<T, V> {
+    /// Stores a value for the socket.
+    pub fn insert(
+        &mut self,
+        socket: &impl AsRawFd,
+        value: impl Borrow<V>,
+        flags: u64,
+    ) -> Result<(), MapError> {
+        storage::insert(
+            self.inner.borrow_mut(),
+            socket.as_raw_fd(),
+            value.borrow(),
+            flags,
+        )
+    }
+
+    /// Removes the value stored for the socket.
+    pub fn remove(&mut self, socket: &impl AsRawFd) -> Result<(), MapError> {
+        storage::remove(self.inner.borrow_mut(), socket.as_raw_fd())
+    }
+}
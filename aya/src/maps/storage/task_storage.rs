@@ -0,0 +1,167 @@
+use std::{
+    borrow::{Borrow, BorrowMut},
+    marker::PhantomData,
+    os::unix::io::AsRawFd,
+};
+
+use crate::{
+    maps::{check_kv_size, storage, MapData, MapError},
+    Pod,
+};
+
+/// Task local storage, holding a value per task.
+///
+/// The storage of a task is accessed from user space with a pidfd of the
+/// task, as returned by `pidfd_open(2)`.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.11.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::TaskStorage;
+/// use std::os::unix::io::RawFd;
+///
+/// let storage = TaskStorage::<_, u64>::try_from(bpf.map("TASK_EVENTS").unwrap())?;
+///
+/// let pid = 1234;
+/// let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) } as RawFd;
+/// let events = storage.get(&pidfd, 0)?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_TASK_STORAGE")]
+pub struct TaskStorage<T, V: Pod> {
+    inner: T,
+    _v: PhantomData<V>,
+}
+
+impl<T: Borrow<MapData>, V: Pod> TaskStorage<T, V> {
+    pub(crate) fn new(map: T) -> Result<TaskStorage<T, V>, MapError> {
+        let data = map.borrow();
+        check_kv_size::<i32, V>(data)?;
+        let _ = data.fd_or_err()?;
+
+        Ok(TaskStorage {
+            inner: map,
+            _v: PhantomData,
+        })
+    }
+
+    /// Returns a copy of the value stored for the task.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::KeyNotFound`] if no value is stored for the task.
+    pub fn get(&self, pidfd: &impl AsRawFd, flags: u64) -> Result<V, MapError> {
+        storage::get(self.inner.borrow(), pidfd.as_raw_fd(), flags)
+    }
+}
+
+impl<T: BorrowMut<MapData>, V: Pod> TaskStorage<T, V> {
+    /// Stores a value for the task.
+    pub fn insert(
+        &mut self,
+        pidfd: &impl AsRawFd,
+        value: impl Borrow<V>,
+        flags: u64,
+    ) -> Result<(), MapError> {
+        storage::insert(
+            self.inner.borrow_mut(),
+            pidfd.as_raw_fd(),
+            value.borrow(),
+            flags,
+        )
+    }
+
+    /// Removes the value stored for the task.
+    pub fn remove(&mut self, pidfd: &impl AsRawFd) -> Result<(), MapError> {
+        storage::remove(self.inner.borrow_mut(), pidfd.as_raw_fd())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use libc::{EFAULT, ENOENT};
+
+    use crate::{
+        bpf_map_def,
+        generated::{
+            bpf_cmd,
+            bpf_map_type::{BPF_MAP_TYPE_HASH, BPF_MAP_TYPE_TASK_STORAGE},
+        },
+        maps::Map,
+        obj::{self, maps::LegacyMap, BpfSectionKind},
+        sys::{override_syscall, SysResult, Syscall},
+    };
+
+    use super::*;
+
+    fn new_map(map_type: u32) -> MapData {
+        MapData {
+            obj: obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type,
+                    key_size: 4,
+                    value_size: 8,
+                    max_entries: 0,
+                    ..Default::default()
+                },
+                section_index: 0,
+                section_kind: BpfSectionKind::Maps,
+                symbol_index: None,
+                data: Vec::new(),
+            }),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        }
+    }
+
+    fn sys_error(value: i32) -> SysResult {
+        Err((-1, io::Error::from_raw_os_error(value)))
+    }
+
+    #[test]
+    fn test_try_from_wrong_map() {
+        let map = Map::HashMap(new_map(BPF_MAP_TYPE_HASH as u32));
+        assert!(matches!(
+            TaskStorage::<_, u64>::try_from(&map),
+            Err(MapError::InvalidMapType { .. })
+        ));
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_get() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_ELEM,
+                attr,
+            } => {
+                let key = unsafe { attr.__bindgen_anon_2.key } as *const i32;
+                match unsafe { *key } {
+                    7 => {
+                        let value = unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.value };
+                        unsafe { *(value as *mut u64) = 42 };
+                        Ok(0)
+                    }
+                    _ => sys_error(ENOENT),
+                }
+            }
+            _ => sys_error(EFAULT),
+        });
+
+        let map = Map::TaskStorage(new_map(BPF_MAP_TYPE_TASK_STORAGE as u32));
+        let storage = TaskStorage::<_, u64>::try_from(&map).unwrap();
+        assert_eq!(storage.get(&7, 0).unwrap(), 42);
+        assert!(matches!(storage.get(&8, 0), Err(MapError::KeyNotFound)));
+    }
+}
@@ -0,0 +1,274 @@
+//! A ring buffer written by userspace and drained by eBPF programs.
+use std::{
+    borrow::Borrow,
+    fmt::{self, Debug, Formatter},
+    ops::{Deref, DerefMut},
+    os::unix::io::{AsRawFd, RawFd},
+    slice,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+};
+
+use libc::{off_t, PROT_READ, PROT_WRITE};
+
+use crate::{
+    generated::{BPF_RINGBUF_BUSY_BIT, BPF_RINGBUF_DISCARD_BIT, BPF_RINGBUF_HDR_SZ},
+    maps::{ring_buf::MMap, MapData, MapError},
+    util::page_size,
+};
+
+/// A ring buffer to which userspace writes records, which eBPF programs read
+/// with `bpf_user_ringbuf_drain`.
+///
+/// This is the kernel supported way to send commands or configuration, like
+/// dynamic log filters, down to eBPF programs: unlike updates of a hash map,
+/// the records are queued and read in order, each exactly once.
+///
+/// Records are written with [`UserRingBuf::push`], or reserved with
+/// [`UserRingBuf::reserve`] and filled in place. The ring buffer has a single
+/// producer: it must not be written to by several processes at the same time.
+/// The file descriptor becomes writable when programs drain records, which
+/// can be waited on with `epoll` when the ring buffer is full.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 6.1.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::UserRingBuf;
+///
+/// let mut commands = UserRingBuf::try_from(bpf.map_mut("COMMANDS").unwrap())?;
+/// commands.push(&1u32.to_ne_bytes())?;
+///
+/// let mut entry = commands.reserve(8)?;
+/// entry.copy_from_slice(&42u64.to_ne_bytes());
+/// entry.submit();
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_USER_RINGBUF")]
+pub struct UserRingBuf<T> {
+    map: T,
+    /// The page holding the position up to which programs drained the records,
+    /// read-only.
+    consumer: MMap,
+    /// The page holding the position up to which records have been reserved,
+    /// followed by the data area mapped twice.
+    producer: MMap,
+    data_offset: usize,
+    mask: usize,
+}
+
+impl<T: Borrow<MapData>> UserRingBuf<T> {
+    pub(crate) fn new(map: T) -> Result<UserRingBuf<T>, MapError> {
+        let data = map.borrow();
+        let fd = data.fd_or_err()?;
+        let page_size = page_size();
+        let byte_size = data.obj.max_entries() as usize;
+
+        let consumer = MMap::new(fd, page_size, PROT_READ, 0)?;
+        let producer = MMap::new(
+            fd,
+            page_size + 2 * byte_size,
+            PROT_READ | PROT_WRITE,
+            page_size as off_t,
+        )?;
+
+        Ok(UserRingBuf {
+            map,
+            consumer,
+            producer,
+            data_offset: page_size,
+            mask: byte_size - 1,
+        })
+    }
+}
+
+impl<T> UserRingBuf<T> {
+    /// Reserves a record of `size` bytes, which is sent to the programs when
+    /// [`submitted`](UserRingBufEntry::submit).
+    ///
+    /// The record is zero-initialized. Records reserved after this one are
+    /// only drained once this one is submitted or discarded.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::NoSpace`] if the ring buffer doesn't have room for
+    /// the record until the programs drain some records.
+    pub fn reserve(&mut self, size: usize) -> Result<UserRingBufEntry<'_>, MapError> {
+        // The length of records is limited to the bits left by the flags.
+        if size > BPF_RINGBUF_DISCARD_BIT as usize - 1 {
+            return Err(MapError::NoSpace { size });
+        }
+        let base = self.producer.as_ptr();
+        let total = (size + BPF_RINGBUF_HDR_SZ as usize + 7) & !7;
+
+        let consumer_pos =
+            unsafe { &*(self.consumer.as_ptr() as *const AtomicUsize) }.load(Ordering::Acquire);
+        let producer_pos = unsafe { &*(base as *const AtomicUsize) };
+        // Only this ring buffer moves the producer position.
+        let pos = producer_pos.load(Ordering::Relaxed);
+        if pos - consumer_pos + total > self.mask + 1 {
+            return Err(MapError::NoSpace { size });
+        }
+
+        let offset = self.data_offset + (pos & self.mask);
+        let header = unsafe { &*(base.add(offset) as *const AtomicU32) };
+        header.store(size as u32 | BPF_RINGBUF_BUSY_BIT, Ordering::Relaxed);
+        // The padding following the length in the header.
+        unsafe { *(base.add(offset + 4) as *mut u32) = 0 };
+        producer_pos.store(pos + total, Ordering::Release);
+
+        let data = unsafe {
+            slice::from_raw_parts_mut(base.add(offset + BPF_RINGBUF_HDR_SZ as usize), size)
+        };
+        data.fill(0);
+        Ok(UserRingBufEntry {
+            header,
+            data,
+            done: false,
+        })
+    }
+
+    /// Writes a record holding `data` and submits it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::NoSpace`] if the ring buffer doesn't have room for
+    /// the record until the programs drain some records.
+    pub fn push(&mut self, data: &[u8]) -> Result<(), MapError> {
+        let mut entry = self.reserve(data.len())?;
+        entry.copy_from_slice(data);
+        entry.submit();
+        Ok(())
+    }
+}
+
+impl<T: Borrow<MapData>> AsRawFd for UserRingBuf<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        // `new` checked that the map is created.
+        self.map.borrow().fd.unwrap()
+    }
+}
+
+/// A record reserved in a [`UserRingBuf`], discarded if dropped without being
+/// submitted.
+pub struct UserRingBufEntry<'a> {
+    header: &'a AtomicU32,
+    data: &'a mut [u8],
+    done: bool,
+}
+
+impl UserRingBufEntry<'_> {
+    /// Makes the record available to the programs.
+    pub fn submit(mut self) {
+        self.commit(0);
+    }
+
+    /// Releases the record without sending it to the programs.
+    pub fn discard(mut self) {
+        self.commit(BPF_RINGBUF_DISCARD_BIT);
+    }
+
+    fn commit(&mut self, flags: u32) {
+        self.header
+            .store(self.data.len() as u32 | flags, Ordering::SeqCst);
+        self.done = true;
+    }
+}
+
+impl Deref for UserRingBufEntry<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.data
+    }
+}
+
+impl DerefMut for UserRingBufEntry<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.data
+    }
+}
+
+impl Drop for UserRingBufEntry<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.commit(BPF_RINGBUF_DISCARD_BIT);
+        }
+    }
+}
+
+impl Debug for UserRingBufEntry<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UserRingBufEntry")
+            .field("len", &self.data.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA_SIZE: usize = 4096;
+
+    fn new_ring_buf() -> UserRingBuf<()> {
+        let page_size = page_size();
+        UserRingBuf {
+            map: (),
+            consumer: MMap::anonymous(page_size),
+            producer: MMap::anonymous(page_size + DATA_SIZE),
+            data_offset: page_size,
+            mask: DATA_SIZE - 1,
+        }
+    }
+
+    fn header(producer: *const u8, pos: usize) -> u32 {
+        unsafe { *(producer.add(page_size() + pos) as *const u32) }
+    }
+
+    #[test]
+    fn test_push() {
+        let mut ring_buf = new_ring_buf();
+        let producer = ring_buf.producer.as_ptr();
+        ring_buf.push(b"abc").unwrap();
+        assert_eq!(header(producer, 0), 3);
+        let data = unsafe {
+            slice::from_raw_parts(producer.add(page_size() + BPF_RINGBUF_HDR_SZ as usize), 3)
+        };
+        assert_eq!(data, b"abc");
+
+        // Records are 8 bytes aligned, unsubmitted ones are discarded.
+        let entry = ring_buf.reserve(5).unwrap();
+        assert_eq!(header(producer, 16), 5 | BPF_RINGBUF_BUSY_BIT);
+        drop(entry);
+        assert_eq!(header(producer, 16), 5 | BPF_RINGBUF_DISCARD_BIT);
+        assert_eq!(unsafe { *(producer as *const usize) }, 32);
+    }
+
+    #[test]
+    fn test_no_space() {
+        let mut ring_buf = new_ring_buf();
+        assert!(matches!(
+            ring_buf.reserve(DATA_SIZE),
+            Err(MapError::NoSpace { size: DATA_SIZE })
+        ));
+        assert!(matches!(
+            ring_buf.reserve(usize::MAX),
+            Err(MapError::NoSpace { size: usize::MAX })
+        ));
+        ring_buf
+            .push(&[0; DATA_SIZE - BPF_RINGBUF_HDR_SZ as usize])
+            .unwrap();
+        assert!(matches!(
+            ring_buf.reserve(0),
+            Err(MapError::NoSpace { size: 0 })
+        ));
+
+        // Drained by a program.
+        unsafe { *(ring_buf.consumer.as_ptr() as *mut usize) = DATA_SIZE };
+        ring_buf.push(&[]).unwrap();
+    }
+}
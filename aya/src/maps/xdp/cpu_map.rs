@@ -0,0 +1,255 @@
+use std::{
+    borrow::{Borrow, BorrowMut},
+    mem,
+    os::unix::io::AsRawFd,
+};
+
+use crate::{
+    generated::{bpf_cpumap_val, bpf_cpumap_val__bindgen_ty_1},
+    maps::{check_bounds, MapData, MapError},
+    programs::ProgramFd,
+    sys::{bpf_map_lookup_elem, bpf_map_update_elem},
+    Pod,
+};
+
+unsafe impl Pod for bpf_cpumap_val {}
+
+/// An array of CPUs.
+///
+/// XDP programs can redirect packets to the CPUs of the array with
+/// `bpf_redirect_map()`, the kernel then builds the `sk_buff` of the packets
+/// and passes them to the network stack on the target CPU. The entries of the
+/// array can also hold a program, which then runs on the redirected packets on
+/// the target CPU. Such programs are defined in the `xdp/cpumap` section, see
+/// [`XdpAttachType::CpuMap`](crate::programs::XdpAttachType::CpuMap).
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.15. Chained
+/// programs require kernel 5.9.
+///
+/// # Examples
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::CpuMap;
+/// use aya::util::nr_possible_cpus;
+///
+/// let mut cpumap = CpuMap::try_from(bpf.map_mut("CPUS").unwrap())?;
+/// let queue_size = 2048;
+/// for cpu in 0..nr_possible_cpus()? as u32 {
+///     cpumap.set(cpu, queue_size, None, 0)?;
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_CPUMAP")]
+pub struct CpuMap<T> {
+    inner: T,
+}
+
+/// An entry of a [`CpuMap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuMapValue {
+    /// The size of the queue of packets redirected to the CPU.
+    pub queue_size: u32,
+    /// The id of the program run on the redirected packets, if any.
+    pub prog_id: Option<u32>,
+}
+
+impl<T: Borrow<MapData>> CpuMap<T> {
+    pub(crate) fn new(map: T) -> Result<CpuMap<T>, MapError> {
+        let data = map.borrow();
+        // the value is either the queue size, or since kernel 5.9 a
+        // `bpf_cpumap_val` which also holds the chained program
+        let size = mem::size_of::<u32>();
+        let expected = data.obj.key_size() as usize;
+        if size != expected {
+            return Err(MapError::InvalidKeySize { size, expected });
+        }
+        let size = mem::size_of::<bpf_cpumap_val>();
+        let expected = data.obj.value_size() as usize;
+        if expected != size && expected != mem::size_of::<u32>() {
+            return Err(MapError::InvalidValueSize { size, expected });
+        }
+
+        let _fd = data.fd_or_err()?;
+
+        Ok(CpuMap { inner: map })
+    }
+
+    /// Returns the number of elements in the array.
+    ///
+    /// This corresponds to the value of `bpf_map_def::max_entries` on the eBPF side.
+    pub fn len(&self) -> u32 {
+        self.inner.borrow().obj.max_entries()
+    }
+
+    /// Returns the entry stored at the given CPU index.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::OutOfBounds`] if `cpu_index` is out of bounds, and
+    /// [`MapError::KeyNotFound`] if the CPU isn't set.
+    pub fn get(&self, cpu_index: u32, flags: u64) -> Result<CpuMapValue, MapError> {
+        let data = self.inner.borrow();
+        check_bounds(data, cpu_index)?;
+        let fd = data.fd_or_err()?;
+
+        let value = if has_chained_programs(data) {
+            bpf_map_lookup_elem::<_, bpf_cpumap_val>(fd, &cpu_index, flags).map(|value| {
+                value.map(|value| CpuMapValue {
+                    queue_size: value.qsize,
+                    // the kernel returns the id of the program on lookups
+                    prog_id: match unsafe { value.bpf_prog.id } {
+                        0 => None,
+                        id => Some(id),
+                    },
+                })
+            })
+        } else {
+            bpf_map_lookup_elem::<_, u32>(fd, &cpu_index, flags).map(|value| {
+                value.map(|queue_size| CpuMapValue {
+                    queue_size,
+                    prog_id: None,
+                })
+            })
+        };
+        value
+            .map_err(|(_, io_error)| MapError::SyscallError {
+                call: "bpf_map_lookup_elem".to_owned(),
+                io_error,
+            })?
+            .ok_or(MapError::KeyNotFound)
+    }
+
+    /// An iterator over the entries of the array. The iterator item type is
+    /// `Result<CpuMapValue, MapError>`.
+    pub fn iter(&self) -> impl Iterator<Item = Result<CpuMapValue, MapError>> + '_ {
+        (0..self.len()).map(move |i| self.get(i, 0))
+    }
+}
+
+impl<T: BorrowMut<MapData>> CpuMap<T> {
+    /// Sets the queue size of the given CPU, enabling redirects to it.
+    ///
+    /// If `program` is set, it runs on the packets redirected to the CPU. The
+    /// program must have been loaded from the `xdp/cpumap` section.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::OutOfBounds`] if `cpu_index` is out of bounds, and
+    /// [`MapError::ChainedProgramNotSupported`] if `program` is set but the
+    /// values of the map only hold a queue size.
+    pub fn set(
+        &mut self,
+        cpu_index: u32,
+        queue_size: u32,
+        program: Option<ProgramFd>,
+        flags: u64,
+    ) -> Result<(), MapError> {
+        let data = self.inner.borrow_mut();
+        check_bounds(data, cpu_index)?;
+        let fd = data.fd_or_err()?;
+
+        let res = if has_chained_programs(data) {
+            let value = bpf_cpumap_val {
+                qsize: queue_size,
+                // the kernel only attaches a program for fds greater than zero
+                bpf_prog: bpf_cpumap_val__bindgen_ty_1 {
+                    fd: program.map(|prog| prog.as_raw_fd()).unwrap_or_default(),
+                },
+            };
+            bpf_map_update_elem(fd, Some(&cpu_index), &value, flags)
+        } else {
+            if program.is_some() {
+                return Err(MapError::ChainedProgramNotSupported);
+            }
+            bpf_map_update_elem(fd, Some(&cpu_index), &queue_size, flags)
+        };
+        res.map_err(|(_, io_error)| MapError::SyscallError {
+            call: "bpf_map_update_elem".to_owned(),
+            io_error,
+        })?;
+        Ok(())
+    }
+}
+
+fn has_chained_programs(map: &MapData) -> bool {
+    map.obj.value_size() as usize == mem::size_of::<bpf_cpumap_val>()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use libc::EFAULT;
+
+    use crate::{
+        bpf_map_def,
+        generated::{bpf_cmd, bpf_map_type::BPF_MAP_TYPE_CPUMAP},
+        obj::{self, maps::LegacyMap, BpfSectionKind},
+        sys::{override_syscall, Syscall},
+    };
+
+    use super::*;
+
+    fn new_map(value_size: u32) -> MapData {
+        MapData {
+            obj: obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_CPUMAP as u32,
+                    key_size: 4,
+                    value_size,
+                    max_entries: 4,
+                    ..Default::default()
+                },
+                section_index: 0,
+                section_kind: BpfSectionKind::Maps,
+                symbol_index: None,
+                data: Vec::new(),
+            }),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        }
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_set_chained() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                let value = unsafe {
+                    *(attr.__bindgen_anon_2.__bindgen_anon_1.value as *const bpf_cpumap_val)
+                };
+                assert_eq!(value.qsize, 2048);
+                assert_eq!(unsafe { value.bpf_prog.fd }, 7);
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+
+        let mut map = new_map(8);
+        let mut cpumap = CpuMap::new(&mut map).unwrap();
+        assert!(cpumap.set(1, 2048, Some(ProgramFd(7)), 0).is_ok());
+        assert!(matches!(
+            cpumap.set(4, 2048, None, 0),
+            Err(MapError::OutOfBounds { index: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn test_set_chained_not_supported() {
+        let mut map = new_map(4);
+        let mut cpumap = CpuMap::new(&mut map).unwrap();
+        assert!(matches!(
+            cpumap.set(1, 2048, Some(ProgramFd(7)), 0),
+            Err(MapError::ChainedProgramNotSupported)
+        ));
+    }
+}
@@ -0,0 +1,269 @@
+use std::borrow::{Borrow, BorrowMut};
+
+use crate::{
+    maps::{
+        check_bounds,
+        xdp::{check_devmap_kv_size, devmap_get, devmap_set},
+        MapData, MapError,
+    },
+    programs::ProgramFd,
+};
+
+/// An array of network devices.
+///
+/// XDP programs can redirect packets to the network devices of the array with
+/// `bpf_redirect_map()`. The entries of the array can also hold a program,
+/// which then runs on the redirected packets before they are transmitted. Such
+/// programs are defined in the `xdp/devmap` section, see
+/// [`XdpAttachType::DevMap`](crate::programs::XdpAttachType::DevMap).
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.14. Chained
+/// programs require kernel 5.8.
+///
+/// # Examples
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::DevMap;
+/// use aya::programs::Xdp;
+///
+/// let mut devmap = DevMap::try_from(bpf.take_map("IFACES").unwrap())?;
+/// let chained: &Xdp = bpf.program("egress_rewrite").unwrap().try_into()?;
+///
+/// // bpf_redirect_map(&IFACES, 0, 0) transmits packets on interface 3
+/// devmap.set(0, 3, None, 0)?;
+/// // bpf_redirect_map(&IFACES, 1, 0) runs `egress_rewrite`, then transmits
+/// // packets on interface 4
+/// devmap.set(1, 4, chained.fd(), 0)?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_DEVMAP")]
+pub struct DevMap<T> {
+    inner: T,
+}
+
+/// An entry of a [`DevMap`] or a [`DevMapHash`](crate::maps::DevMapHash).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DevMapValue {
+    /// The index of the interface the packets are redirected to.
+    pub if_index: u32,
+    /// The id of the program run on the redirected packets, if any.
+    pub prog_id: Option<u32>,
+}
+
+impl<T: Borrow<MapData>> DevMap<T> {
+    pub(crate) fn new(map: T) -> Result<DevMap<T>, MapError> {
+        let data = map.borrow();
+        check_devmap_kv_size(data)?;
+
+        let _fd = data.fd_or_err()?;
+
+        Ok(DevMap { inner: map })
+    }
+
+    /// Returns the number of elements in the array.
+    ///
+    /// This corresponds to the value of `bpf_map_def::max_entries` on the eBPF side.
+    pub fn len(&self) -> u32 {
+        self.inner.borrow().obj.max_entries()
+    }
+
+    /// Returns the entry stored at the given index.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::OutOfBounds`] if `index` is out of bounds, and
+    /// [`MapError::KeyNotFound`] if no interface is set at `index`.
+    pub fn get(&self, index: u32, flags: u64) -> Result<DevMapValue, MapError> {
+        let data = self.inner.borrow();
+        check_bounds(data, index)?;
+        devmap_get(data, &index, flags)
+    }
+
+    /// An iterator over the entries of the array. The iterator item type is
+    /// `Result<DevMapValue, MapError>`.
+    pub fn iter(&self) -> impl Iterator<Item = Result<DevMapValue, MapError>> + '_ {
+        (0..self.len()).map(move |i| self.get(i, 0))
+    }
+}
+
+impl<T: BorrowMut<MapData>> DevMap<T> {
+    /// Sets the interface packets redirected to `index` are transmitted on.
+    ///
+    /// If `program` is set, it runs on the packets before they are
+    /// transmitted. The program must have been loaded from the `xdp/devmap`
+    /// section.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::OutOfBounds`] if `index` is out of bounds, and
+    /// [`MapError::ChainedProgramNotSupported`] if `program` is set but the
+    /// values of the map only hold an interface index.
+    pub fn set(
+        &mut self,
+        index: u32,
+        target_if_index: u32,
+        program: Option<ProgramFd>,
+        flags: u64,
+    ) -> Result<(), MapError> {
+        let data = self.inner.borrow_mut();
+        check_bounds(data, index)?;
+        devmap_set(data, &index, target_if_index, program, flags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use libc::{EFAULT, ENOENT};
+
+    use crate::{
+        bpf_map_def,
+        generated::{bpf_cmd, bpf_devmap_val, bpf_map_type::BPF_MAP_TYPE_DEVMAP},
+        obj::{self, maps::LegacyMap, BpfSectionKind},
+        sys::{override_syscall, SysResult, Syscall},
+    };
+
+    use super::*;
+
+    fn new_map(value_size: u32) -> MapData {
+        MapData {
+            obj: obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_DEVMAP as u32,
+                    key_size: 4,
+                    value_size,
+                    max_entries: 4,
+                    ..Default::default()
+                },
+                section_index: 0,
+                section_kind: BpfSectionKind::Maps,
+                symbol_index: None,
+                data: Vec::new(),
+            }),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        }
+    }
+
+    fn sys_error(value: i32) -> SysResult {
+        Err((-1, io::Error::from_raw_os_error(value)))
+    }
+
+    #[test]
+    fn test_wrong_value_size() {
+        assert!(matches!(
+            DevMap::new(&new_map(12)),
+            Err(MapError::InvalidValueSize {
+                size: 8,
+                expected: 12
+            })
+        ));
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        let map = new_map(8);
+        let devmap = DevMap::new(&map).unwrap();
+        assert!(matches!(
+            devmap.get(4, 0),
+            Err(MapError::OutOfBounds {
+                index: 4,
+                max_entries: 4
+            })
+        ));
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_get_chained() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_LOOKUP_ELEM,
+                attr,
+            } => {
+                let key = unsafe { *(attr.__bindgen_anon_2.key as *const u32) };
+                let value =
+                    unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.value } as *mut bpf_devmap_val;
+                match key {
+                    0 => {
+                        unsafe {
+                            (*value).ifindex = 3;
+                            (*value).bpf_prog.id = 0;
+                        }
+                        Ok(0)
+                    }
+                    1 => {
+                        unsafe {
+                            (*value).ifindex = 4;
+                            (*value).bpf_prog.id = 7;
+                        }
+                        Ok(0)
+                    }
+                    _ => sys_error(ENOENT),
+                }
+            }
+            _ => sys_error(EFAULT),
+        });
+
+        let map = new_map(8);
+        let devmap = DevMap::new(&map).unwrap();
+        assert_eq!(
+            devmap.get(0, 0).unwrap(),
+            DevMapValue {
+                if_index: 3,
+                prog_id: None
+            }
+        );
+        assert_eq!(
+            devmap.get(1, 0).unwrap(),
+            DevMapValue {
+                if_index: 4,
+                prog_id: Some(7)
+            }
+        );
+        assert!(matches!(devmap.get(2, 0), Err(MapError::KeyNotFound)));
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_set_chained() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                let value = unsafe {
+                    *(attr.__bindgen_anon_2.__bindgen_anon_1.value as *const bpf_devmap_val)
+                };
+                assert_eq!(value.ifindex, 4);
+                assert_eq!(unsafe { value.bpf_prog.fd }, 42);
+                Ok(0)
+            }
+            _ => sys_error(EFAULT),
+        });
+
+        let mut map = new_map(8);
+        let mut devmap = DevMap::new(&mut map).unwrap();
+        assert!(devmap.set(1, 4, Some(ProgramFd(42)), 0).is_ok());
+    }
+
+    #[test]
+    fn test_set_chained_not_supported() {
+        let mut map = new_map(4);
+        let mut devmap = DevMap::new(&mut map).unwrap();
+        assert!(matches!(
+            devmap.set(1, 4, Some(ProgramFd(42)), 0),
+            Err(MapError::ChainedProgramNotSupported)
+        ));
+    }
+}
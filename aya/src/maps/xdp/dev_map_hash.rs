@@ -0,0 +1,112 @@
+use std::borrow::{Borrow, BorrowMut};
+
+use crate::{
+    maps::{
+        hash_map,
+        xdp::{check_devmap_kv_size, devmap_get, devmap_set, DevMapValue},
+        IterableMap, MapData, MapError, MapIter, MapKeys,
+    },
+    programs::ProgramFd,
+};
+
+/// A hash map of network devices.
+///
+/// Like a [`DevMap`](crate::maps::DevMap), but the keys are arbitrary `u32`,
+/// usually the interface indexes themselves, so that sparse sets of interfaces
+/// don't need a large array.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.4. Chained
+/// programs require kernel 5.8.
+///
+/// # Examples
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::maps::DevMapHash;
+///
+/// let mut devmap = DevMapHash::try_from(bpf.map_mut("IFACES").unwrap())?;
+///
+/// // bpf_redirect_map(&IFACES, 3, 0) transmits packets on interface 3
+/// devmap.insert(3, 3, None, 0)?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_DEVMAP_HASH")]
+pub struct DevMapHash<T> {
+    inner: T,
+}
+
+impl<T: Borrow<MapData>> DevMapHash<T> {
+    pub(crate) fn new(map: T) -> Result<DevMapHash<T>, MapError> {
+        let data = map.borrow();
+        check_devmap_kv_size(data)?;
+
+        let _fd = data.fd_or_err()?;
+
+        Ok(DevMapHash { inner: map })
+    }
+
+    /// Returns the entry stored under the given key.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::KeyNotFound`] if the key doesn't exist.
+    pub fn get(&self, key: u32, flags: u64) -> Result<DevMapValue, MapError> {
+        devmap_get(self.inner.borrow(), &key, flags)
+    }
+
+    /// An iterator visiting all key-value pairs in arbitrary order. The
+    /// iterator item type is `Result<(u32, DevMapValue), MapError>`.
+    pub fn iter(&self) -> MapIter<'_, u32, DevMapValue, Self> {
+        MapIter::new(self)
+    }
+
+    /// An iterator visiting all keys in arbitrary order. The iterator element
+    /// type is `Result<u32, MapError>`.
+    pub fn keys(&self) -> MapKeys<'_, u32> {
+        MapKeys::new(self.inner.borrow())
+    }
+}
+
+impl<T: BorrowMut<MapData>> DevMapHash<T> {
+    /// Sets the interface packets redirected to `key` are transmitted on.
+    ///
+    /// If `program` is set, it runs on the packets before they are
+    /// transmitted. The program must have been loaded from the `xdp/devmap`
+    /// section.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::ChainedProgramNotSupported`] if `program` is set
+    /// but the values of the map only hold an interface index.
+    pub fn insert(
+        &mut self,
+        key: u32,
+        target_if_index: u32,
+        program: Option<ProgramFd>,
+        flags: u64,
+    ) -> Result<(), MapError> {
+        devmap_set(
+            self.inner.borrow_mut(),
+            &key,
+            target_if_index,
+            program,
+            flags,
+        )
+    }
+
+    /// Removes an interface from the map.
+    pub fn remove(&mut self, key: &u32) -> Result<(), MapError> {
+        hash_map::remove(self.inner.borrow_mut(), key)
+    }
+}
+
+impl<T: Borrow<MapData>> IterableMap<u32, DevMapValue> for DevMapHash<T> {
+    fn map(&self) -> &MapData {
+        self.inner.borrow()
+    }
+
+    fn get(&self, key: &u32) -> Result<DevMapValue, MapError> {
+        DevMapHash::get(self, *key, 0)
+    }
+}
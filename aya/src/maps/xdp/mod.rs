@@ -0,0 +1,101 @@
+//! XDP maps.
+mod cpu_map;
+mod dev_map;
+mod dev_map_hash;
+mod xsk_map;
+
+pub use cpu_map::{CpuMap, CpuMapValue};
+pub use dev_map::{DevMap, DevMapValue};
+pub use dev_map_hash::DevMapHash;
+pub use xsk_map::XskMap;
+
+use std::{mem, os::unix::io::AsRawFd};
+
+use crate::{
+    generated::{bpf_devmap_val, bpf_devmap_val__bindgen_ty_1},
+    maps::{MapData, MapError},
+    programs::ProgramFd,
+    sys::{bpf_map_lookup_elem, bpf_map_update_elem},
+    Pod,
+};
+
+unsafe impl Pod for bpf_devmap_val {}
+
+// The value of devmaps is either the target interface index, or since kernel
+// 5.8 a `bpf_devmap_val` which also holds the chained program.
+fn check_devmap_kv_size(map: &MapData) -> Result<(), MapError> {
+    let size = mem::size_of::<u32>();
+    let expected = map.obj.key_size() as usize;
+    if size != expected {
+        return Err(MapError::InvalidKeySize { size, expected });
+    }
+    let size = mem::size_of::<bpf_devmap_val>();
+    let expected = map.obj.value_size() as usize;
+    if expected != size && expected != mem::size_of::<u32>() {
+        return Err(MapError::InvalidValueSize { size, expected });
+    }
+    Ok(())
+}
+
+fn devmap_get(map: &MapData, key: &u32, flags: u64) -> Result<DevMapValue, MapError> {
+    let fd = map.fd_or_err()?;
+    let value = if has_chained_programs(map) {
+        bpf_map_lookup_elem::<_, bpf_devmap_val>(fd, key, flags).map(|value| {
+            value.map(|value| DevMapValue {
+                if_index: value.ifindex,
+                // the kernel returns the id of the program on lookups
+                prog_id: match unsafe { value.bpf_prog.id } {
+                    0 => None,
+                    id => Some(id),
+                },
+            })
+        })
+    } else {
+        bpf_map_lookup_elem::<_, u32>(fd, key, flags).map(|value| {
+            value.map(|if_index| DevMapValue {
+                if_index,
+                prog_id: None,
+            })
+        })
+    };
+    value
+        .map_err(|(_, io_error)| MapError::SyscallError {
+            call: "bpf_map_lookup_elem".to_owned(),
+            io_error,
+        })?
+        .ok_or(MapError::KeyNotFound)
+}
+
+fn devmap_set(
+    map: &mut MapData,
+    key: &u32,
+    target_if_index: u32,
+    program: Option<ProgramFd>,
+    flags: u64,
+) -> Result<(), MapError> {
+    let fd = map.fd_or_err()?;
+    let res = if has_chained_programs(map) {
+        let value = bpf_devmap_val {
+            ifindex: target_if_index,
+            // the kernel only attaches a program for fds greater than zero
+            bpf_prog: bpf_devmap_val__bindgen_ty_1 {
+                fd: program.map(|prog| prog.as_raw_fd()).unwrap_or_default(),
+            },
+        };
+        bpf_map_update_elem(fd, Some(key), &value, flags)
+    } else {
+        if program.is_some() {
+            return Err(MapError::ChainedProgramNotSupported);
+        }
+        bpf_map_update_elem(fd, Some(key), &target_if_index, flags)
+    };
+    res.map_err(|(_, io_error)| MapError::SyscallError {
+        call: "bpf_map_update_elem".to_owned(),
+        io_error,
+    })?;
+    Ok(())
+}
+
+fn has_chained_programs(map: &MapData) -> bool {
+    map.obj.value_size() as usize == mem::size_of::<bpf_devmap_val>()
+}
@@ -0,0 +1,192 @@
+use std::{
+    borrow::{Borrow, BorrowMut},
+    os::unix::io::{AsRawFd, RawFd},
+};
+
+use crate::{
+    maps::{check_bounds, check_kv_size, MapData, MapError, MapKeys},
+    sys::{bpf_map_delete_elem, bpf_map_update_elem},
+};
+
+/// An array of AF_XDP sockets.
+///
+/// XDP programs can redirect packets to the AF_XDP sockets of the array with
+/// `bpf_redirect_map()`, bypassing the network stack. The packets are then
+/// received in the memory area shared with user space (the UMEM) of the
+/// socket, without copies when the network driver supports zero-copy mode.
+///
+/// The sockets are created, bound to a queue of an interface and their rings
+/// set up by user space, for example with an AF_XDP library. Each socket only
+/// receives the packets of the queue it's bound to, so the XDP program
+/// usually redirects packets to the index of their receive queue, with
+/// `bpf_redirect_map(&XSKS, ctx->rx_queue_index, XDP_PASS)`.
+///
+/// Lookups from user space aren't supported by the kernel.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.18.
+///
+/// # Examples
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// # let socket: std::os::unix::io::RawFd = 0;
+/// use aya::maps::XskMap;
+///
+/// let mut xsks = XskMap::try_from(bpf.map_mut("XSKS").unwrap())?;
+/// // `socket` is an AF_XDP socket bound to the queue 0 of the interface
+/// xsks.set(0, &socket, 0)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[doc(alias = "BPF_MAP_TYPE_XSKMAP")]
+pub struct XskMap<T> {
+    inner: T,
+}
+
+impl<T: Borrow<MapData>> XskMap<T> {
+    pub(crate) fn new(map: T) -> Result<XskMap<T>, MapError> {
+        let data = map.borrow();
+        check_kv_size::<u32, RawFd>(data)?;
+
+        let _fd = data.fd_or_err()?;
+
+        Ok(XskMap { inner: map })
+    }
+
+    /// Returns the number of elements in the array.
+    ///
+    /// This corresponds to the value of `bpf_map_def::max_entries` on the eBPF side.
+    pub fn len(&self) -> u32 {
+        self.inner.borrow().obj.max_entries()
+    }
+
+    /// An iterator over all the indices of the array, from `0` to
+    /// [`XskMap::len`], whether or not they point to a socket. The iterator
+    /// item type is `Result<u32, MapError>`.
+    pub fn indices(&self) -> MapKeys<'_, u32> {
+        MapKeys::new(self.inner.borrow())
+    }
+}
+
+impl<T: BorrowMut<MapData>> XskMap<T> {
+    /// Stores an AF_XDP socket into the map.
+    ///
+    /// The socket must already be bound to a queue of an interface.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::OutOfBounds`] if `index` is out of bounds, and
+    /// [`MapError::SyscallError`] if `bpf_map_update_elem` fails, for example
+    /// because `socket` isn't a bound AF_XDP socket.
+    pub fn set<I: AsRawFd>(&mut self, index: u32, socket: &I, flags: u64) -> Result<(), MapError> {
+        let data = self.inner.borrow_mut();
+        check_bounds(data, index)?;
+        let fd = data.fd_or_err()?;
+        bpf_map_update_elem(fd, Some(&index), &socket.as_raw_fd(), flags).map_err(
+            |(_, io_error)| MapError::SyscallError {
+                call: "bpf_map_update_elem".to_owned(),
+                io_error,
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Removes the socket stored at `index` from the map.
+    pub fn clear_index(&mut self, index: &u32) -> Result<(), MapError> {
+        let data = self.inner.borrow_mut();
+        check_bounds(data, *index)?;
+        let fd = data.fd_or_err()?;
+        bpf_map_delete_elem(fd, index)
+            .map(|_| ())
+            .map_err(|(_, io_error)| MapError::SyscallError {
+                call: "bpf_map_delete_elem".to_owned(),
+                io_error,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use libc::EFAULT;
+
+    use crate::{
+        bpf_map_def,
+        generated::{
+            bpf_cmd,
+            bpf_map_type::{BPF_MAP_TYPE_HASH, BPF_MAP_TYPE_XSKMAP},
+        },
+        maps::Map,
+        obj::{self, maps::LegacyMap, BpfSectionKind},
+        sys::{override_syscall, SysResult, Syscall},
+    };
+
+    use super::*;
+
+    fn new_map(map_type: u32) -> MapData {
+        MapData {
+            obj: obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type,
+                    key_size: 4,
+                    value_size: 4,
+                    max_entries: 2,
+                    ..Default::default()
+                },
+                section_index: 0,
+                section_kind: BpfSectionKind::Maps,
+                symbol_index: None,
+                data: Vec::new(),
+            }),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        }
+    }
+
+    fn sys_error(value: i32) -> SysResult {
+        Err((-1, io::Error::from_raw_os_error(value)))
+    }
+
+    #[test]
+    fn test_try_from_wrong_map() {
+        let map = Map::HashMap(new_map(BPF_MAP_TYPE_HASH as u32));
+        assert!(matches!(
+            XskMap::try_from(&map),
+            Err(MapError::InvalidMapType { .. })
+        ));
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_set() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                let value = unsafe { attr.__bindgen_anon_2.__bindgen_anon_1.value };
+                match unsafe { *(value as *const RawFd) } {
+                    7 => Ok(0),
+                    _ => sys_error(EFAULT),
+                }
+            }
+            _ => sys_error(EFAULT),
+        });
+
+        let mut map = Map::XskMap(new_map(BPF_MAP_TYPE_XSKMAP as u32));
+        let mut xsks = XskMap::try_from(&mut map).unwrap();
+        assert!(xsks.set(1, &7, 0).is_ok());
+        assert!(matches!(
+            xsks.set(2, &7, 0),
+            Err(MapError::OutOfBounds {
+                index: 2,
+                max_entries: 2
+            })
+        ));
+    }
+}
@@ -4,7 +4,8 @@ use std::os::unix::prelude::{AsRawFd, RawFd};
 use crate::{
     generated::{bpf_attach_type::BPF_CGROUP_DEVICE, bpf_prog_type::BPF_PROG_TYPE_CGROUP_DEVICE},
     programs::{
-        define_link_wrapper, load_program, FdLink, Link, ProgAttachLink, ProgramData, ProgramError,
+        define_link_wrapper, impl_try_from_fd_link, load_program, FdLink, Link, ProgAttachLink,
+        ProgramData, ProgramError,
     },
     sys::{bpf_link_create, bpf_prog_attach, kernel_version},
 };
@@ -147,3 +148,9 @@ define_link_wrapper!(
     CgroupDeviceLinkInner,
     CgroupDeviceLinkIdInner
 );
+
+impl_try_from_fd_link!(
+    CgroupDeviceLink,
+    CgroupDeviceLinkInner,
+    BPF_LINK_TYPE_CGROUP
+);
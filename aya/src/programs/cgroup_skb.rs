@@ -7,11 +7,12 @@ use std::{
 
 use crate::{
     generated::{
-        bpf_attach_type::{BPF_CGROUP_INET_EGRESS, BPF_CGROUP_INET_INGRESS},
+        bpf_attach_type::{self, BPF_CGROUP_INET_EGRESS, BPF_CGROUP_INET_INGRESS},
         bpf_prog_type::BPF_PROG_TYPE_CGROUP_SKB,
     },
     programs::{
-        define_link_wrapper, load_program, FdLink, Link, ProgAttachLink, ProgramData, ProgramError,
+        define_link_wrapper, impl_try_from_fd_link, load_program, FdLink, Link, ProgAttachLink,
+        ProgramData, ProgramError,
     },
     sys::{bpf_link_create, bpf_prog_attach, kernel_version},
 };
@@ -62,12 +63,7 @@ pub struct CgroupSkb {
 impl CgroupSkb {
     /// Loads the program inside the kernel.
     pub fn load(&mut self) -> Result<(), ProgramError> {
-        self.data.expected_attach_type =
-            self.expected_attach_type
-                .map(|attach_type| match attach_type {
-                    CgroupSkbAttachType::Ingress => BPF_CGROUP_INET_INGRESS,
-                    CgroupSkbAttachType::Egress => BPF_CGROUP_INET_EGRESS,
-                });
+        self.data.expected_attach_type = self.expected_attach_type.map(Into::into);
         load_program(BPF_PROG_TYPE_CGROUP_SKB, &mut self.data)
     }
 
@@ -92,10 +88,7 @@ impl CgroupSkb {
         let prog_fd = self.data.fd_or_err()?;
         let cgroup_fd = cgroup.as_raw_fd();
 
-        let attach_type = match attach_type {
-            CgroupSkbAttachType::Ingress => BPF_CGROUP_INET_INGRESS,
-            CgroupSkbAttachType::Egress => BPF_CGROUP_INET_EGRESS,
-        };
+        let attach_type = attach_type.into();
         let k_ver = kernel_version().unwrap();
         if k_ver >= (5, 7, 0) {
             let link_fd = bpf_link_create(prog_fd, cgroup_fd, attach_type, None, 0).map_err(
@@ -197,6 +190,8 @@ define_link_wrapper!(
     CgroupSkbLinkIdInner
 );
 
+impl_try_from_fd_link!(CgroupSkbLink, CgroupSkbLinkInner, BPF_LINK_TYPE_CGROUP);
+
 /// Defines where to attach a [`CgroupSkb`] program.
 #[derive(Copy, Clone, Debug)]
 pub enum CgroupSkbAttachType {
@@ -205,3 +200,12 @@ pub enum CgroupSkbAttachType {
     /// Attach to egress.
     Egress,
 }
+
+impl From<CgroupSkbAttachType> for bpf_attach_type {
+    fn from(s: CgroupSkbAttachType) -> bpf_attach_type {
+        match s {
+            CgroupSkbAttachType::Ingress => BPF_CGROUP_INET_INGRESS,
+            CgroupSkbAttachType::Egress => BPF_CGROUP_INET_EGRESS,
+        }
+    }
+}
@@ -7,7 +7,8 @@ use std::{
 use crate::{
     generated::{bpf_attach_type::BPF_CGROUP_SYSCTL, bpf_prog_type::BPF_PROG_TYPE_CGROUP_SYSCTL},
     programs::{
-        define_link_wrapper, load_program, FdLink, Link, ProgAttachLink, ProgramData, ProgramError,
+        define_link_wrapper, impl_try_from_fd_link, load_program, FdLink, Link, ProgAttachLink,
+        ProgramData, ProgramError,
     },
     sys::{bpf_link_create, bpf_prog_attach, kernel_version},
 };
@@ -150,3 +151,9 @@ define_link_wrapper!(
     CgroupSysctlLinkInner,
     CgroupSysctlLinkIdInner
 );
+
+impl_try_from_fd_link!(
+    CgroupSysctlLink,
+    CgroupSysctlLinkInner,
+    BPF_LINK_TYPE_CGROUP
+);
@@ -2,10 +2,11 @@
 
 use crate::{
     generated::{bpf_attach_type::BPF_TRACE_FEXIT, bpf_prog_type::BPF_PROG_TYPE_TRACING},
-    obj::btf::{Btf, BtfKind},
+    obj::btf::Btf,
     programs::{
-        define_link_wrapper, load_program, utils::attach_raw_tracepoint, FdLink, FdLinkId,
-        ProgramData, ProgramError,
+        define_link_wrapper, load_program,
+        utils::{attach_raw_tracepoint, tracing_target_btf_id},
+        FdLink, FdLinkId, ProgramData, ProgramError,
     },
 };
 
@@ -17,6 +18,9 @@ use crate::{
 /// before kernel function. Fexit programs can be also attached to other eBPF
 /// programs.
 ///
+/// Programs defined in `fexit.s` sections are loaded as sleepable, see
+/// [`FEntry`](crate::programs::FEntry).
+///
 /// # Minimum kernel version
 ///
 /// The minimum kernel version required to use this feature is 5.5.
@@ -55,9 +59,16 @@ impl FExit {
     /// Loads the program so it's executed when the kernel function `fn_name`
     /// is exited. The `btf` argument must contain the BTF info for the running
     /// kernel.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProgramError::UnsupportedTracingTarget`] if the signature of
+    /// `fn_name` can't be traced, for example because it's variadic or takes
+    /// a large struct by value. Before kernel 6.1, functions with more than 6
+    /// arguments or with struct arguments can't be traced.
     pub fn load(&mut self, fn_name: &str, btf: &Btf) -> Result<(), ProgramError> {
         self.data.expected_attach_type = Some(BPF_TRACE_FEXIT);
-        self.data.attach_btf_id = Some(btf.id_by_type_name_kind(fn_name, BtfKind::Func)?);
+        self.data.attach_btf_id = Some(tracing_target_btf_id(btf, fn_name)?);
         load_program(BPF_PROG_TYPE_TRACING, &mut self.data)
     }
 
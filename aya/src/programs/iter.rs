@@ -0,0 +1,249 @@
+//! Iterators.
+#[cfg(feature = "async")]
+use std::io::{self, Read};
+use std::{
+    fs::File,
+    os::unix::io::{AsRawFd, FromRawFd, RawFd},
+};
+
+use crate::{
+    generated::{bpf_attach_type::BPF_TRACE_ITER, bpf_prog_type::BPF_PROG_TYPE_TRACING},
+    obj::btf::{Btf, BtfKind},
+    programs::{
+        define_link_wrapper, load_program, FdLink, FdLinkId, LinkError, ProgramData, ProgramError,
+    },
+    sys::{bpf_create_iter, bpf_link_create_iter},
+};
+
+/// A BPF iterator.
+///
+/// [`Iter`] programs walk kernel objects, such as tasks, TCP sockets or the
+/// elements of a map, and write their output to a seq_file. Each time the
+/// iterator file is read from the beginning, the program runs again over the
+/// current state of the kernel.
+///
+/// On the eBPF side, iterator programs are written with the `#[iter]` macro
+/// of `aya-bpf`.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.8.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[derive(thiserror::Error, Debug)]
+/// # enum Error {
+/// #     #[error(transparent)]
+/// #     IO(#[from] std::io::Error),
+/// #     #[error(transparent)]
+/// #     BtfError(#[from] aya::BtfError),
+/// #     #[error(transparent)]
+/// #     Link(#[from] aya::programs::links::LinkError),
+/// #     #[error(transparent)]
+/// #     Program(#[from] aya::programs::ProgramError),
+/// #     #[error(transparent)]
+/// #     Bpf(#[from] aya::BpfError),
+/// # }
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::{programs::Iter, Btf};
+/// use std::io::Read;
+///
+/// let btf = Btf::from_sys_fs()?;
+/// let program: &mut Iter = bpf.program_mut("dump_tasks").unwrap().try_into()?;
+/// program.load("task", &btf)?;
+/// let link_id = program.attach()?;
+///
+/// let mut file = program.take_link(link_id)?.into_file()?;
+/// let mut output = String::new();
+/// file.read_to_string(&mut output)?;
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_TRACE_ITER")]
+#[doc(alias = "BPF_PROG_TYPE_TRACING")]
+pub struct Iter {
+    pub(crate) data: ProgramData<IterLink>,
+}
+
+impl Iter {
+    /// Loads the program inside the kernel.
+    ///
+    /// `iter_type` is the kind of objects to iterate over, for example `task`,
+    /// `tcp` or `bpf_map_elem`. The `btf` argument must contain the BTF info
+    /// for the running kernel.
+    pub fn load(&mut self, iter_type: &str, btf: &Btf) -> Result<(), ProgramError> {
+        self.data.expected_attach_type = Some(BPF_TRACE_ITER);
+        let type_name = format!("bpf_iter_{iter_type}");
+        self.data.attach_btf_id = Some(btf.id_by_type_name_kind(&type_name, BtfKind::Func)?);
+        load_program(BPF_PROG_TYPE_TRACING, &mut self.data)
+    }
+
+    /// Attaches the program.
+    ///
+    /// The returned value can be used to detach, see [Iter::detach]. To read
+    /// the output of the iterator, take the link with [Iter::take_link] and
+    /// call [IterLink::into_file].
+    pub fn attach(&mut self) -> Result<IterLinkId, ProgramError> {
+        self.attach_inner(None)
+    }
+
+    /// Attaches the program to iterate over the elements of the given map.
+    ///
+    /// This must be used for programs loaded with the `bpf_map_elem` and
+    /// `bpf_sk_storage_map` iterator types.
+    pub fn attach_to_map<T: AsRawFd>(&mut self, map: T) -> Result<IterLinkId, ProgramError> {
+        self.attach_inner(Some(map.as_raw_fd()))
+    }
+
+    fn attach_inner(&mut self, map_fd: Option<RawFd>) -> Result<IterLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        let link_fd = bpf_link_create_iter(prog_fd, map_fd).map_err(|(_, io_error)| {
+            ProgramError::SyscallError {
+                call: "bpf_link_create".to_owned(),
+                io_error,
+            }
+        })? as RawFd;
+        self.data.links.insert(IterLink::new(FdLink::new(link_fd)))
+    }
+
+    /// Detaches the program.
+    ///
+    /// See [Iter::attach].
+    pub fn detach(&mut self, link_id: IterLinkId) -> Result<(), ProgramError> {
+        self.data.links.remove(link_id)
+    }
+
+    /// Takes ownership of the link referenced by the provided link_id.
+    ///
+    /// The link will be detached on `Drop` and the caller is now responsible
+    /// for managing its lifetime.
+    pub fn take_link(&mut self, link_id: IterLinkId) -> Result<IterLink, ProgramError> {
+        self.data.take_link(link_id)
+    }
+}
+
+define_link_wrapper!(
+    /// The link used by [Iter] programs.
+    IterLink,
+    /// The type returned by [Iter::attach]. Can be passed to [Iter::detach].
+    IterLinkId,
+    FdLink,
+    FdLinkId
+);
+
+impl IterLink {
+    /// Creates an iterator and returns the file its output can be read from.
+    ///
+    /// The file keeps the iterator alive, so the link is consumed and doesn't
+    /// need to be kept around. The file implements [`std::io::Read`]. To read
+    /// it asynchronously, use [IterLink::into_stream].
+    pub fn into_file(self) -> Result<File, LinkError> {
+        let fd = bpf_create_iter(self.inner().fd).map_err(|(code, io_error)| {
+            LinkError::SyscallError {
+                call: "BPF_ITER_CREATE".to_string(),
+                code,
+                io_error,
+            }
+        })? as RawFd;
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    /// Creates an iterator and returns a stream over its output.
+    ///
+    /// Iterator files can't be polled, so each read runs on a blocking thread
+    /// (see [crate::util::unblock]).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async fn run(link: aya::programs::iter::IterLink) -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut stream = link.into_stream()?;
+    /// let mut output = Vec::new();
+    /// while let Some(chunk) = stream.next().await {
+    ///     output.extend_from_slice(&chunk?);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub fn into_stream(self) -> Result<IterStream, LinkError> {
+        Ok(IterStream {
+            file: Some(self.into_file()?),
+        })
+    }
+}
+
+/// An asynchronous stream over the output of an iterator.
+///
+/// Created with [IterLink::into_stream].
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+#[derive(Debug)]
+pub struct IterStream {
+    file: Option<File>,
+}
+
+#[cfg(feature = "async")]
+impl IterStream {
+    const CHUNK_SIZE: usize = 4096;
+
+    /// Reads the next chunk of output.
+    ///
+    /// Returns `None` once the iterator is exhausted or after an error. If the
+    /// returned future is dropped before it completes, the iterator is closed
+    /// and the stream ends.
+    pub async fn next(&mut self) -> Option<Result<Vec<u8>, io::Error>> {
+        let mut file = self.file.take()?;
+        let (file, result) = crate::util::unblock(move || {
+            let mut buf = vec![0; Self::CHUNK_SIZE];
+            let result = file.read(&mut buf).map(|len| {
+                buf.truncate(len);
+                buf
+            });
+            (file, result)
+        })
+        .await;
+        match result {
+            Ok(buf) if buf.is_empty() => None,
+            Ok(buf) => {
+                self.file = Some(file);
+                Some(Ok(buf))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "async", not(feature = "async_tokio")))]
+mod tests {
+    use std::{fs, io::Write};
+
+    use super::*;
+
+    #[test]
+    fn test_stream() {
+        let path = std::env::temp_dir().join(format!("aya-iter-{}", std::process::id()));
+        let data = (0..IterStream::CHUNK_SIZE + 10)
+            .map(|i| i as u8)
+            .collect::<Vec<_>>();
+        fs::File::create(&path).unwrap().write_all(&data).unwrap();
+        let mut stream = IterStream {
+            file: Some(File::open(&path).unwrap()),
+        };
+        fs::remove_file(&path).unwrap();
+
+        let mut output = Vec::new();
+        let mut chunks = 0;
+        futures::executor::block_on(async {
+            while let Some(chunk) = stream.next().await {
+                output.extend_from_slice(&chunk.unwrap());
+                chunks += 1;
+            }
+        });
+        assert_eq!(output, data);
+        assert_eq!(chunks, 2);
+        assert!(futures::executor::block_on(stream.next()).is_none());
+    }
+}
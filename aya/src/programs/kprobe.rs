@@ -5,11 +5,12 @@ use thiserror::Error;
 use crate::{
     generated::bpf_prog_type::BPF_PROG_TYPE_KPROBE,
     programs::{
-        define_link_wrapper, load_program,
+        load_program,
         perf_attach::{PerfLinkIdInner, PerfLinkInner},
-        probe::{attach, ProbeKind},
-        ProgramData, ProgramError,
+        probe::{create_link, ProbeKind},
+        Link, ProgramData, ProgramError,
     },
+    util::KernelSymbols,
 };
 
 /// A kernel probe.
@@ -42,6 +43,20 @@ pub struct KProbe {
     pub(crate) kind: ProbeKind,
 }
 
+/// The location a [`KProbe`] link is attached to.
+///
+/// Returned by [`KProbe::target`] and [`KProbeLink::target`] for debugging.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KProbeTarget {
+    /// The name of the kernel function.
+    pub symbol: String,
+    /// The offset from the start of the function.
+    pub offset: u64,
+    /// The address the probe is attached to, if the offset is non-zero and
+    /// the kernel symbol addresses are visible to the current user.
+    pub address: Option<u64>,
+}
+
 impl KProbe {
     /// Loads the program inside the kernel.
     pub fn load(&mut self) -> Result<(), ProgramError> {
@@ -58,7 +73,14 @@ impl KProbe {
     ///
     /// Attaches the probe to the given function name inside the kernel. If
     /// `offset` is non-zero, it is added to the address of the target
-    /// function.
+    /// function. The target can also be given as `symbol+offset`, for example
+    /// `tcp_v4_connect+0x2c`, in which case both offsets are added.
+    ///
+    /// Non-zero offsets are checked against the size of the function in
+    /// `/proc/kallsyms` when the kernel addresses are visible, and are
+    /// rejected with [`KProbeError::AmbiguousSymbol`] if several functions
+    /// share the name. The resolved address can then be retrieved with
+    /// [KProbe::target].
     ///
     /// If the program is a `kprobe`, it is attached to the *start* address of the target function.
     /// Conversely if the program is a `kretprobe`, it is attached to the return address of the
@@ -66,7 +88,65 @@ impl KProbe {
     ///
     /// The returned value can be used to detach from the given function, see [KProbe::detach].
     pub fn attach(&mut self, fn_name: &str, offset: u64) -> Result<KProbeLinkId, ProgramError> {
-        attach(&mut self.data, self.kind, fn_name, offset, None)
+        self.attach_inner(fn_name, offset, None)
+    }
+
+    /// Attaches the program with a cookie.
+    ///
+    /// Like [KProbe::attach], but the program can retrieve `cookie` with the
+    /// `bpf_get_attach_cookie` helper. This allows the same program to be
+    /// attached to many functions and find out which one it was called from.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 5.15. On
+    /// older kernels [`ProgramError::AttachCookieNotSupported`] is returned.
+    pub fn attach_with_cookie(
+        &mut self,
+        fn_name: &str,
+        offset: u64,
+        cookie: u64,
+    ) -> Result<KProbeLinkId, ProgramError> {
+        self.attach_inner(fn_name, offset, Some(cookie))
+    }
+
+    fn attach_inner(
+        &mut self,
+        fn_name: &str,
+        offset: u64,
+        cookie: Option<u64>,
+    ) -> Result<KProbeLinkId, ProgramError> {
+        let (symbol, offset) = parse_target(fn_name, offset)?;
+        // kallsyms is only needed to validate non-zero offsets, and the
+        // addresses are only used for debugging, so don't fail if it can't be
+        // read
+        let syms = if offset != 0 {
+            KernelSymbols::get().ok()
+        } else {
+            None
+        };
+        let target = resolve_target(syms.as_deref(), symbol, offset)?;
+        let prog_fd = self.data.fd_or_err()?;
+        let link = create_link(
+            prog_fd,
+            self.kind,
+            &target.symbol,
+            target.offset,
+            0,
+            None,
+            cookie,
+        )?;
+        self.data.links.insert(KProbeLink {
+            inner: Some(link),
+            target,
+        })
+    }
+
+    /// Returns where the given link is attached.
+    ///
+    /// See [KProbe::attach].
+    pub fn target(&self, link_id: &KProbeLinkId) -> Option<&KProbeTarget> {
+        self.data.links.get(link_id).map(KProbeLink::target)
     }
 
     /// Detaches the program.
@@ -96,14 +176,43 @@ impl KProbe {
     }
 }
 
-define_link_wrapper!(
-    /// The link used by [KProbe] programs.
-    KProbeLink,
-    /// The type returned by [KProbe::attach]. Can be passed to [KProbe::detach].
-    KProbeLinkId,
-    PerfLinkInner,
-    PerfLinkIdInner
-);
+/// The type returned by [KProbe::attach]. Can be passed to [KProbe::detach].
+#[derive(Debug, Hash, Eq, PartialEq)]
+pub struct KProbeLinkId(PerfLinkIdInner);
+
+/// The link used by [KProbe] programs.
+#[derive(Debug)]
+pub struct KProbeLink {
+    inner: Option<PerfLinkInner>,
+    target: KProbeTarget,
+}
+
+impl KProbeLink {
+    /// Returns where the link is attached.
+    pub fn target(&self) -> &KProbeTarget {
+        &self.target
+    }
+}
+
+impl Drop for KProbeLink {
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.take() {
+            let _ = inner.detach();
+        }
+    }
+}
+
+impl Link for KProbeLink {
+    type Id = KProbeLinkId;
+
+    fn id(&self) -> Self::Id {
+        KProbeLinkId(self.inner.as_ref().unwrap().id())
+    }
+
+    fn detach(mut self) -> Result<(), ProgramError> {
+        self.inner.take().unwrap().detach()
+    }
+}
 
 /// The type returned when attaching a [`KProbe`] fails.
 #[derive(Debug, Error)]
@@ -117,4 +226,159 @@ pub enum KProbeError {
         #[source]
         io_error: io::Error,
     },
+
+    /// No kernel function matches the pattern
+    #[error("no kernel function matches `{pattern}`")]
+    NoMatchingFunctions {
+        /// The pattern
+        pattern: String,
+    },
+
+    /// The target isn't a function name or `symbol+offset`
+    #[error("invalid kprobe target `{target}`")]
+    InvalidTarget {
+        /// The target
+        target: String,
+    },
+
+    /// Several kernel functions have the name, so the offset can't be checked
+    #[error("several kernel functions are called `{symbol}`, can't attach at an offset")]
+    AmbiguousSymbol {
+        /// The name of the functions
+        symbol: String,
+    },
+
+    /// The offset is past the end of the function
+    #[error("offset {offset:#x} is outside of `{symbol}`, which is {size:#x} bytes")]
+    InvalidOffset {
+        /// The name of the function
+        symbol: String,
+        /// The offset
+        offset: u64,
+        /// The size of the function according to `/proc/kallsyms`
+        size: u64,
+    },
+}
+
+// Splits `symbol+offset` targets and adds `offset`.
+fn parse_target(target: &str, offset: u64) -> Result<(&str, u64), KProbeError> {
+    let invalid = || KProbeError::InvalidTarget {
+        target: target.to_owned(),
+    };
+    let (symbol, offset) = match target.split_once('+') {
+        Some((symbol, extra)) => {
+            let extra = match extra.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => extra.parse(),
+            }
+            .map_err(|_| invalid())?;
+            (symbol, offset.checked_add(extra).ok_or_else(invalid)?)
+        }
+        None => (target, offset),
+    };
+    if symbol.is_empty() {
+        return Err(invalid());
+    }
+    Ok((symbol, offset))
+}
+
+// Checks a non-zero offset against the size of the symbol.
+fn resolve_target(
+    syms: Option<&KernelSymbols>,
+    symbol: &str,
+    offset: u64,
+) -> Result<KProbeTarget, KProbeError> {
+    if offset != 0 {
+        if syms.is_some_and(|syms| syms.is_ambiguous(symbol)) {
+            return Err(KProbeError::AmbiguousSymbol {
+                symbol: symbol.to_owned(),
+            });
+        }
+        if let Some(size) = syms.and_then(|syms| syms.size(symbol)) {
+            if offset >= size {
+                return Err(KProbeError::InvalidOffset {
+                    symbol: symbol.to_owned(),
+                    offset,
+                    size,
+                });
+            }
+        }
+    }
+
+    Ok(KProbeTarget {
+        symbol: symbol.to_owned(),
+        offset,
+        address: syms
+            .and_then(|syms| syms.address(symbol))
+            .map(|addr| addr + offset),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(
+            parse_target("tcp_v4_connect+0x2c", 0).unwrap(),
+            ("tcp_v4_connect", 0x2c)
+        );
+        assert_eq!(
+            parse_target("tcp_v4_connect+16", 4).unwrap(),
+            ("tcp_v4_connect", 20)
+        );
+        assert_eq!(
+            parse_target("try_to_wake_up", 0).unwrap(),
+            ("try_to_wake_up", 0)
+        );
+        for target in ["tcp_v4_connect+", "tcp_v4_connect+0xzz", "+0x10"] {
+            assert!(matches!(
+                parse_target(target, 0),
+                Err(KProbeError::InvalidTarget { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_resolve_target() {
+        let data = "ffffffff81000100 t tcp_v4_connect\n\
+                    ffffffff81000200 t dup\n\
+                    ffffffff81000280 t dup\n\
+                    ffffffff81000300 t helper\n"
+            .as_bytes();
+        let syms = KernelSymbols::parse(BufReader::new(data)).unwrap();
+
+        assert_eq!(
+            resolve_target(Some(&syms), "tcp_v4_connect", 0x2c).unwrap(),
+            KProbeTarget {
+                symbol: "tcp_v4_connect".to_owned(),
+                offset: 0x2c,
+                address: Some(0xffffffff8100012c),
+            }
+        );
+        assert!(matches!(
+            resolve_target(Some(&syms), "tcp_v4_connect", 0x100),
+            Err(KProbeError::InvalidOffset { size: 0x100, .. })
+        ));
+        // the size of the last symbol is unknown
+        assert_eq!(
+            resolve_target(Some(&syms), "helper", 0x1000)
+                .unwrap()
+                .address,
+            Some(0xffffffff81001300)
+        );
+        // the kernel picks one of the functions without an offset
+        assert!(matches!(
+            resolve_target(Some(&syms), "dup", 0x10),
+            Err(KProbeError::AmbiguousSymbol { .. })
+        ));
+        assert!(resolve_target(Some(&syms), "dup", 0).is_ok());
+        assert_eq!(
+            resolve_target(None, "try_to_wake_up", 0).unwrap().address,
+            None
+        );
+    }
 }
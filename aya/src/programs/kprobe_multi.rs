@@ -0,0 +1,299 @@
+//! Kernel space probes attached to many functions at once.
+use std::{collections::BTreeSet, ffi::CString, fs, os::unix::io::RawFd, path::Path};
+
+use crate::{
+    generated::{bpf_attach_type::BPF_TRACE_KPROBE_MULTI, bpf_prog_type::BPF_PROG_TYPE_KPROBE},
+    programs::{
+        define_link_wrapper, kprobe::KProbeError, load_program, utils::find_tracefs_path, FdLink,
+        FdLinkId, ProbeKind, ProgramData, ProgramError,
+    },
+    sys::bpf_link_create_kprobe_multi,
+};
+
+/// A kernel probe attached to multiple functions with a single link.
+///
+/// [`KProbeMulti`] programs behave like [kprobes](crate::programs::KProbe),
+/// but instead of creating one perf event per target function, all the target
+/// functions are attached with one `BPF_LINK_CREATE` syscall. This makes
+/// attaching to thousands of functions practical.
+///
+/// The programs are defined in the `kprobe.multi` and `kretprobe.multi`
+/// sections.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.18.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = Bpf::load_file("ebpf_programs.o")?;
+/// use aya::{Bpf, programs::KProbeMulti};
+///
+/// let program: &mut KProbeMulti = bpf.program_mut("count_tcp_calls").unwrap().try_into()?;
+/// program.load()?;
+/// program.attach_pattern("tcp_*")?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_TRACE_KPROBE_MULTI")]
+#[doc(alias = "BPF_PROG_TYPE_KPROBE")]
+pub struct KProbeMulti {
+    pub(crate) data: ProgramData<KProbeMultiLink>,
+    pub(crate) kind: ProbeKind,
+}
+
+impl KProbeMulti {
+    /// Loads the program inside the kernel.
+    pub fn load(&mut self) -> Result<(), ProgramError> {
+        self.data.expected_attach_type = Some(BPF_TRACE_KPROBE_MULTI);
+        load_program(BPF_PROG_TYPE_KPROBE, &mut self.data)
+    }
+
+    /// Returns `KProbe` if the program is a `kprobe.multi`, or `KRetProbe` if
+    /// the program is a `kretprobe.multi`.
+    pub fn kind(&self) -> ProbeKind {
+        self.kind
+    }
+
+    /// Attaches the program to the given kernel functions.
+    ///
+    /// The returned value can be used to detach from all the functions at
+    /// once, see [KProbeMulti::detach].
+    pub fn attach(&mut self, fn_names: &[&str]) -> Result<KProbeMultiLinkId, ProgramError> {
+        let syms = fn_names
+            .iter()
+            .map(|name| {
+                CString::new(*name).map_err(|_| ProgramError::InvalidName {
+                    name: name.to_string(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.attach_syms(&syms, None)
+    }
+
+    /// Attaches the program to the given kernel functions, with a cookie for
+    /// each of them.
+    ///
+    /// The program can retrieve the cookie of the function it was called from
+    /// with the `bpf_get_attach_cookie` helper. This allows tracing many
+    /// functions with a single program while still telling the call sites
+    /// apart.
+    ///
+    /// The returned value can be used to detach from all the functions at
+    /// once, see [KProbeMulti::detach].
+    pub fn attach_with_cookies(
+        &mut self,
+        fns: &[(&str, u64)],
+    ) -> Result<KProbeMultiLinkId, ProgramError> {
+        let (syms, cookies): (Vec<_>, Vec<_>) = fns
+            .iter()
+            .map(|(name, cookie)| {
+                CString::new(*name).map(|sym| (sym, *cookie)).map_err(|_| {
+                    ProgramError::InvalidName {
+                        name: name.to_string(),
+                    }
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .unzip();
+        self.attach_syms(&syms, Some(&cookies))
+    }
+
+    /// Attaches the program to all the kernel functions matching `pattern`.
+    ///
+    /// The pattern is matched against the functions listed in
+    /// `available_filter_functions` of tracefs. `*` matches any sequence of
+    /// characters and `?` matches any single character.
+    ///
+    /// Functions which can't be probed are skipped: `notrace` functions
+    /// aren't listed in `available_filter_functions`, and the functions
+    /// listed in the kprobes blacklist of debugfs are left out, as the kernel
+    /// would otherwise reject the whole attachment.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KProbeError::NoMatchingFunctions`] if no function matches
+    /// `pattern`.
+    pub fn attach_pattern(&mut self, pattern: &str) -> Result<KProbeMultiLinkId, ProgramError> {
+        let path = find_tracefs_path()?.join("available_filter_functions");
+        let functions = fs::read_to_string(&path).map_err(|io_error| KProbeError::FileError {
+            filename: path.display().to_string(),
+            io_error,
+        })?;
+
+        // debugfs may not be mounted, in which case the kernel still rejects
+        // blacklisted functions when attaching
+        let blacklist = fs::read_to_string(KPROBES_BLACKLIST).unwrap_or_default();
+
+        let syms = matching_functions(&functions, &blacklist, pattern)
+            .into_iter()
+            .filter_map(|name| CString::new(name).ok())
+            .collect::<Vec<_>>();
+        if syms.is_empty() {
+            return Err(KProbeError::NoMatchingFunctions {
+                pattern: pattern.to_owned(),
+            }
+            .into());
+        }
+        self.attach_syms(&syms, None)
+    }
+
+    fn attach_syms(
+        &mut self,
+        syms: &[CString],
+        cookies: Option<&[u64]>,
+    ) -> Result<KProbeMultiLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        let retprobe = matches!(self.kind, ProbeKind::KRetProbe);
+
+        let link_fd = bpf_link_create_kprobe_multi(prog_fd, syms, cookies, retprobe).map_err(
+            |(_, io_error)| ProgramError::SyscallError {
+                call: "bpf_link_create".to_owned(),
+                io_error,
+            },
+        )? as RawFd;
+        self.data
+            .links
+            .insert(KProbeMultiLink::new(FdLink::new(link_fd)))
+    }
+
+    /// Detaches the program.
+    ///
+    /// See [KProbeMulti::attach].
+    pub fn detach(&mut self, link_id: KProbeMultiLinkId) -> Result<(), ProgramError> {
+        self.data.links.remove(link_id)
+    }
+
+    /// Takes ownership of the link referenced by the provided link_id.
+    ///
+    /// The link will be detached on `Drop` and the caller is now responsible
+    /// for managing its lifetime.
+    pub fn take_link(
+        &mut self,
+        link_id: KProbeMultiLinkId,
+    ) -> Result<KProbeMultiLink, ProgramError> {
+        self.data.take_link(link_id)
+    }
+
+    /// Creates a program from a pinned entry on a bpffs.
+    ///
+    /// Existing links will not be populated. To work with existing links you should use [`crate::programs::links::PinnedLink`].
+    ///
+    /// On drop, any managed links are detached and the program is unloaded. This will not result in
+    /// the program being unloaded from the kernel if it is still pinned.
+    pub fn from_pin<P: AsRef<Path>>(path: P, kind: ProbeKind) -> Result<Self, ProgramError> {
+        let data = ProgramData::from_pinned_path(path)?;
+        Ok(Self { data, kind })
+    }
+}
+
+define_link_wrapper!(
+    /// The link used by [KProbeMulti] programs.
+    KProbeMultiLink,
+    /// The type returned by [KProbeMulti::attach]. Can be passed to [KProbeMulti::detach].
+    KProbeMultiLinkId,
+    FdLink,
+    FdLinkId
+);
+
+const KPROBES_BLACKLIST: &str = "/sys/kernel/debug/kprobes/blacklist";
+
+// Returns the sorted, deduplicated names of the functions in
+// `available_filter_functions` matching `pattern`, leaving out the ones in
+// the kprobes `blacklist`. Lines of `available_filter_functions` look like
+// `tcp_v4_connect` or `nf_conntrack_in [nf_conntrack]`, and lines of the
+// blacklist like `0xffffffff81000000-0xffffffff81000010\tnmi_handle`.
+fn matching_functions<'a>(functions: &'a str, blacklist: &str, pattern: &str) -> BTreeSet<&'a str> {
+    let blacklist = blacklist
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .collect::<BTreeSet<_>>();
+    functions
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        // placeholders left by the kernel for functions it couldn't resolve
+        .filter(|name| !name.starts_with("__ftrace_invalid_address__"))
+        .filter(|name| !blacklist.contains(name))
+        .filter(|name| glob_match(pattern.as_bytes(), name.as_bytes()))
+        .collect()
+}
+
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` in the pattern, and of the name when it was seen
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"tcp_*", b"tcp_v4_connect"));
+        assert!(glob_match(b"*_connect", b"tcp_v4_connect"));
+        assert!(glob_match(b"tcp_v?_connect", b"tcp_v6_connect"));
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"tcp*conn*", b"tcp_v4_connect"));
+        assert!(!glob_match(b"tcp_*", b"udp_sendmsg"));
+        assert!(!glob_match(b"tcp_v?_connect", b"tcp_v46_connect"));
+        assert!(!glob_match(b"tcp", b"tcp_v4_connect"));
+    }
+
+    #[test]
+    fn test_matching_functions() {
+        let functions =
+            "tcp_v4_connect\nudp_sendmsg\ntcp_close\nnf_conntrack_in [nf_conntrack]\ntcp_close\n";
+        assert_eq!(
+            matching_functions(functions, "", "tcp_*")
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec!["tcp_close", "tcp_v4_connect"]
+        );
+        assert_eq!(
+            matching_functions(functions, "", "nf_*")
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec!["nf_conntrack_in"]
+        );
+    }
+
+    #[test]
+    fn test_matching_functions_not_attachable() {
+        let functions = "tcp_v4_connect\ntcp_close\n__ftrace_invalid_address___84\n\
+                         tcp_fastopen_ctx_free\nnf_conntrack_in [nf_conntrack]\n";
+        let blacklist = "0xffffffff81a2b3c0-0xffffffff81a2b420\ttcp_close\n\
+                         0xffffffffc0a01000-0xffffffffc0a01080\tnf_conntrack_in [nf_conntrack]\n";
+        assert_eq!(
+            matching_functions(functions, blacklist, "*")
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec!["tcp_fastopen_ctx_free", "tcp_v4_connect"]
+        );
+    }
+}
@@ -6,15 +6,17 @@ use std::{
     collections::{hash_map::Entry, HashMap},
     ffi::CString,
     io,
-    os::unix::prelude::RawFd,
+    os::unix::prelude::{AsRawFd, RawFd},
     path::{Path, PathBuf},
 };
 
 use crate::{
-    generated::bpf_attach_type,
+    generated::{bpf_attach_type, bpf_link_type},
     pin::PinError,
-    programs::ProgramError,
-    sys::{bpf_get_object, bpf_pin_object, bpf_prog_detach},
+    programs::{ProgramError, ProgramFd},
+    sys::{
+        bpf_get_object, bpf_link_get_info_by_fd, bpf_link_update, bpf_pin_object, bpf_prog_detach,
+    },
 };
 
 /// A Link.
@@ -27,6 +29,44 @@ pub trait Link: std::fmt::Debug + 'static {
 
     /// Detaches the LinkOwnedLink is gone... but this doesn't work :(
     fn detach(self) -> Result<(), ProgramError>;
+
+    /// Forgets the link, leaving the program attached.
+    ///
+    /// Links detach their program when dropped. Forgetting a link instead
+    /// releases it without detaching, so that the process which attached the
+    /// program can exit and leave the program running. Obtain the link with
+    /// the `take_link()` method of the program first.
+    ///
+    /// Programs attached with netlink (XDP and TC programs on older kernels)
+    /// or with `bpf_prog_attach` stay attached until they are explicitly
+    /// detached. Programs attached with an [`FdLink`] stay attached only as
+    /// long as the link file descriptor is open, which is until the process
+    /// exits, so the link should be pinned with [`FdLink::pin`] as well.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use aya::programs::{Link, Xdp, XdpFlags};
+    /// # #[derive(thiserror::Error, Debug)]
+    /// # enum Error {
+    /// #     #[error(transparent)]
+    /// #     Bpf(#[from] aya::BpfError),
+    /// #     #[error(transparent)]
+    /// #     Program(#[from] aya::programs::ProgramError)
+    /// # }
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// let program: &mut Xdp = bpf.program_mut("xdp").unwrap().try_into()?;
+    /// program.load()?;
+    /// let link_id = program.attach("eth0", XdpFlags::SKB_MODE)?;
+    /// program.take_link(link_id)?.forget();
+    /// // the program stays attached to eth0 after the process exits
+    /// # Ok::<(), Error>(())
+    /// ```
+    fn forget(self)
+    where
+        Self: Sized,
+    {
+        std::mem::forget(self)
+    }
 }
 
 #[derive(Debug)]
@@ -66,6 +106,10 @@ impl<T: Link> LinkMap<T> {
         Ok(())
     }
 
+    pub(crate) fn get(&self, link_id: &T::Id) -> Option<&T> {
+        self.links.get(link_id)
+    }
+
     pub(crate) fn forget(&mut self, link_id: T::Id) -> Result<T, ProgramError> {
         self.links.remove(&link_id).ok_or(ProgramError::NotAttached)
     }
@@ -134,6 +178,73 @@ impl FdLink {
         })?;
         Ok(PinnedLink::new(PathBuf::from(path.as_ref()), self))
     }
+
+    /// Atomically replaces the program attached through this link.
+    ///
+    /// The attach point keeps running the old program until `program` is in
+    /// place, so there is no window in which no program is attached. This
+    /// works for any link created with `BPF_LINK_CREATE`, including cgroup
+    /// and XDP links.
+    ///
+    /// If `expected` is provided, the kernel only performs the update if the
+    /// program currently attached through the link is `expected`, and fails
+    /// with `EPERM` otherwise. This can be used to avoid clobbering a
+    /// concurrent update.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use aya::programs::{links::FdLink, CgroupSkb, CgroupSkbAttachType};
+    /// # use std::{convert::TryInto, fs::File};
+    /// # #[derive(thiserror::Error, Debug)]
+    /// # enum Error {
+    /// #     #[error(transparent)]
+    /// #     IO(#[from] std::io::Error),
+    /// #     #[error(transparent)]
+    /// #     Bpf(#[from] aya::BpfError),
+    /// #     #[error(transparent)]
+    /// #     Link(#[from] aya::programs::links::LinkError),
+    /// #     #[error(transparent)]
+    /// #     Program(#[from] aya::programs::ProgramError)
+    /// # }
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// let cgroup = File::open("/sys/fs/cgroup/unified")?;
+    /// let old: &mut CgroupSkb = bpf.program_mut("old_policy").unwrap().try_into()?;
+    /// let link_id = old.attach(cgroup, CgroupSkbAttachType::Egress)?;
+    /// let old_fd = old.fd();
+    /// let fd_link: FdLink = old.take_link(link_id)?.try_into()?;
+    /// let new: &mut CgroupSkb = bpf.program_mut("new_policy").unwrap().try_into()?;
+    /// fd_link.update(new.fd().unwrap(), old_fd)?;
+    /// # Ok::<(), Error>(())
+    /// ```
+    pub fn update(&self, program: ProgramFd, expected: Option<ProgramFd>) -> Result<(), LinkError> {
+        bpf_link_update(
+            self.fd,
+            program.as_raw_fd(),
+            expected.map(|fd| fd.as_raw_fd()),
+            0,
+        )
+        .map_err(|(code, io_error)| LinkError::SyscallError {
+            call: "bpf_link_update".to_string(),
+            code,
+            io_error,
+        })?;
+        Ok(())
+    }
+
+    /// Checks that the kernel link is of type `link_type`.
+    pub(crate) fn check_type(&self, link_type: bpf_link_type) -> Result<(), LinkError> {
+        let info =
+            bpf_link_get_info_by_fd(self.fd).map_err(|io_error| LinkError::SyscallError {
+                call: "BPF_OBJ_GET_INFO_BY_FD".to_string(),
+                code: 0,
+                io_error,
+            })?;
+        if info.type_ == link_type as u32 {
+            Ok(())
+        } else {
+            Err(LinkError::InvalidLink)
+        }
+    }
 }
 
 impl Link for FdLink {
@@ -182,6 +293,27 @@ impl PinnedLink {
     }
 
     /// Creates a [`crate::programs::links::PinnedLink`] from a valid path on bpffs.
+    ///
+    /// The typed link of the program can then be reconstructed, for example
+    /// to detach the program after the process which attached it restarted.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use aya::programs::links::{FdLink, PinnedLink};
+    /// # use aya::programs::xdp::XdpLink;
+    /// # use std::convert::TryFrom;
+    /// # #[derive(thiserror::Error, Debug)]
+    /// # enum Error {
+    /// #     #[error(transparent)]
+    /// #     Link(#[from] aya::programs::links::LinkError),
+    /// #     #[error(transparent)]
+    /// #     IO(#[from] std::io::Error),
+    /// # }
+    /// let pinned_link = PinnedLink::from_pin("/sys/fs/bpf/example")?;
+    /// let link = XdpLink::try_from(pinned_link.unpin()?)?;
+    /// // the program is detached when `link` is dropped.
+    /// # Ok::<(), Error>(())
+    /// ```
     pub fn from_pin<P: AsRef<Path>>(path: P) -> Result<Self, LinkError> {
         let path_string = CString::new(path.as_ref().to_string_lossy().to_string()).unwrap();
         let fd =
@@ -210,9 +342,9 @@ pub struct ProgAttachLinkId(RawFd, RawFd, bpf_attach_type);
 /// The Link type used by programs that are attached with `bpf_prog_attach`.
 #[derive(Debug)]
 pub struct ProgAttachLink {
-    prog_fd: RawFd,
-    target_fd: RawFd,
-    attach_type: bpf_attach_type,
+    pub(crate) prog_fd: RawFd,
+    pub(crate) target_fd: RawFd,
+    pub(crate) attach_type: bpf_attach_type,
 }
 
 impl ProgAttachLink {
@@ -308,6 +440,35 @@ macro_rules! define_link_wrapper {
 
 pub(crate) use define_link_wrapper;
 
+// Implements the conversions between a link whose inner enum has an `Fd`
+// variant and `FdLink`, checking the type of the kernel link.
+macro_rules! impl_try_from_fd_link {
+    ($wrapper:ident, $inner:ident, $link_type:ident) => {
+        impl TryFrom<$wrapper> for crate::programs::FdLink {
+            type Error = crate::programs::links::LinkError;
+
+            fn try_from(value: $wrapper) -> Result<Self, Self::Error> {
+                if let $inner::Fd(fd) = value.into_inner() {
+                    Ok(fd)
+                } else {
+                    Err(crate::programs::links::LinkError::InvalidLink)
+                }
+            }
+        }
+
+        impl TryFrom<crate::programs::FdLink> for $wrapper {
+            type Error = crate::programs::links::LinkError;
+
+            fn try_from(fd_link: crate::programs::FdLink) -> Result<Self, Self::Error> {
+                fd_link.check_type(crate::generated::bpf_link_type::$link_type)?;
+                Ok($wrapper::new($inner::Fd(fd_link)))
+            }
+        }
+    };
+}
+
+pub(crate) use impl_try_from_fd_link;
+
 #[derive(Error, Debug)]
 /// Errors from operations on links.
 pub enum LinkError {
@@ -329,11 +490,24 @@ pub enum LinkError {
 
 #[cfg(test)]
 mod tests {
-    use std::{cell::RefCell, env, fs::File, mem, os::unix::io::AsRawFd, rc::Rc};
+    use std::{
+        cell::RefCell,
+        env,
+        fs::File,
+        io, mem,
+        os::unix::io::{AsRawFd, IntoRawFd},
+        rc::Rc,
+    };
+
+    use libc::{EFAULT, EPERM};
 
-    use crate::{programs::ProgramError, sys::override_syscall};
+    use crate::{
+        generated::{bpf_cmd, bpf_link_info, bpf_link_type, BPF_F_REPLACE},
+        programs::{ProgramError, ProgramFd},
+        sys::{override_syscall, Syscall},
+    };
 
-    use super::{FdLink, Link, LinkMap};
+    use super::{FdLink, Link, LinkError, LinkMap};
 
     #[derive(Debug, Hash, Eq, PartialEq)]
     struct TestLinkId(u8, u8);
@@ -464,6 +638,93 @@ mod tests {
         assert!(*l2_detached.borrow() == 1);
     }
 
+    define_link_wrapper!(
+        /// A wrapped test link.
+        TestWrapperLink,
+        /// A wrapped test link id.
+        TestWrapperLinkId,
+        TestLink,
+        TestLinkId
+    );
+
+    #[test]
+    fn test_forget() {
+        let l1 = TestLink::new(1, 2);
+        let l1_detached = Rc::clone(&l1.detached);
+        let l2 = TestLink::new(1, 3);
+        let l2_detached = Rc::clone(&l2.detached);
+
+        drop(TestWrapperLink::new(l1));
+        assert!(*l1_detached.borrow() == 1);
+
+        TestWrapperLink::new(l2).forget();
+        assert!(*l2_detached.borrow() == 0);
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_check_type() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET_INFO_BY_FD,
+                attr,
+            } => {
+                let info = unsafe { &mut *(attr.info.info as *mut bpf_link_info) };
+                info.type_ = bpf_link_type::BPF_LINK_TYPE_CGROUP as u32;
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+
+        let file = File::create(env::temp_dir().join("check_type"))
+            .expect("unable to create file in tmpdir");
+        let fd_link = FdLink::new(file.into_raw_fd());
+        assert!(fd_link
+            .check_type(bpf_link_type::BPF_LINK_TYPE_CGROUP)
+            .is_ok());
+        assert!(matches!(
+            fd_link.check_type(bpf_link_type::BPF_LINK_TYPE_XDP),
+            Err(LinkError::InvalidLink)
+        ));
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_update() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_LINK_UPDATE,
+                attr,
+            } => {
+                let update = unsafe { &attr.link_update };
+                if update.new_prog_fd != 42 {
+                    return Err((-1, io::Error::from_raw_os_error(EFAULT)));
+                }
+                if update.flags & BPF_F_REPLACE != 0 && update.old_prog_fd != 41 {
+                    return Err((-1, io::Error::from_raw_os_error(EPERM)));
+                }
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EFAULT))),
+        });
+
+        let file =
+            File::create(env::temp_dir().join("update")).expect("unable to create file in tmpdir");
+        let fd_link = FdLink::new(file.into_raw_fd());
+        assert!(fd_link.update(ProgramFd(42), None).is_ok());
+        assert!(fd_link.update(ProgramFd(42), Some(ProgramFd(41))).is_ok());
+        assert!(matches!(
+            fd_link.update(ProgramFd(42), Some(ProgramFd(40))),
+            Err(LinkError::SyscallError { io_error, .. }) if io_error.raw_os_error() == Some(EPERM)
+        ));
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn test_pin() {
@@ -44,32 +44,43 @@ pub mod cgroup_sysctl;
 pub mod extension;
 pub mod fentry;
 pub mod fexit;
+pub mod iter;
 pub mod kprobe;
+pub mod kprobe_multi;
 pub mod links;
 pub mod lirc_mode2;
 pub mod lsm;
 pub mod perf_attach;
 pub mod perf_event;
 mod probe;
+pub mod query;
 mod raw_trace_point;
 mod sk_lookup;
 mod sk_msg;
 mod sk_skb;
 mod sock_ops;
 mod socket_filter;
+pub mod struct_ops;
 pub mod tc;
 pub mod tp_btf;
 pub mod trace_point;
 pub mod uprobe;
+pub mod uprobe_multi;
+pub mod usdt;
 mod utils;
 pub mod xdp;
 
-use libc::ENOSPC;
+use libc::{EINVAL, ENOSPC};
 use std::{
     ffi::CString,
     io,
-    os::unix::io::{AsRawFd, RawFd},
+    os::{
+        fd::OwnedFd,
+        unix::io::{AsRawFd, RawFd},
+    },
     path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
 };
 use thiserror::Error;
 
@@ -82,7 +93,9 @@ pub use cgroup_sysctl::CgroupSysctl;
 pub use extension::{Extension, ExtensionError};
 pub use fentry::FEntry;
 pub use fexit::FExit;
-pub use kprobe::{KProbe, KProbeError};
+pub use iter::Iter;
+pub use kprobe::{KProbe, KProbeError, KProbeTarget};
+pub use kprobe_multi::KProbeMulti;
 pub use links::Link;
 use links::*;
 pub use lirc_mode2::LircMode2;
@@ -95,22 +108,26 @@ pub use sk_lookup::SkLookup;
 pub use sk_msg::SkMsg;
 pub use sk_skb::{SkSkb, SkSkbKind};
 pub use sock_ops::SockOps;
-pub use socket_filter::{SocketFilter, SocketFilterError};
-pub use tc::{SchedClassifier, TcAttachType, TcError};
+pub use socket_filter::{AttachedSocketFilter, SocketFilter, SocketFilterError};
+pub use struct_ops::{StructOps, StructOpsError, StructOpsMap};
+pub use tc::{SchedClassifier, TcAttachType, TcError, TcxOrder};
 pub use tp_btf::BtfTracePoint;
 pub use trace_point::{TracePoint, TracePointError};
 pub use uprobe::{UProbe, UProbeError};
-pub use xdp::{Xdp, XdpError, XdpFlags};
+pub use uprobe_multi::UProbeMulti;
+pub use usdt::{Usdt, UsdtError};
+pub use xdp::{Xdp, XdpAttachType, XdpError, XdpFlags};
 
 use crate::{
     generated::{bpf_attach_type, bpf_prog_info, bpf_prog_type},
     maps::MapError,
-    obj::{self, btf::BtfError, Function, KernelVersion},
+    obj::{self, btf::BtfError, Function, KernelVersion, ProgramSection},
     pin::PinError,
     sys::{
-        bpf_btf_get_fd_by_id, bpf_get_object, bpf_load_program, bpf_pin_object,
-        bpf_prog_get_fd_by_id, bpf_prog_get_info_by_fd, bpf_prog_get_next_id, bpf_prog_query,
-        retry_with_verifier_logs, BpfLoadProgramAttrs,
+        bpf_btf_get_fd_by_id, bpf_enable_stats, bpf_get_object, bpf_load_program, bpf_pin_object,
+        bpf_prog_get_fd_by_id, bpf_prog_get_info_by_fd, bpf_prog_get_line_info,
+        bpf_prog_get_next_id, bpf_prog_query, bpf_prog_test_run, retry_with_verifier_logs,
+        BpfLoadProgramAttrs,
     },
     util::VerifierLog,
 };
@@ -177,6 +194,10 @@ pub enum ProgramError {
     #[error(transparent)]
     UProbeError(#[from] UProbeError),
 
+    /// An error occurred while working with a [`Usdt`] program.
+    #[error(transparent)]
+    UsdtError(#[from] UsdtError),
+
     /// An error occurred while working with a [`TracePoint`].
     #[error(transparent)]
     TracePointError(#[from] TracePointError),
@@ -197,6 +218,10 @@ pub enum ProgramError {
     #[error(transparent)]
     ExtensionError(#[from] ExtensionError),
 
+    /// An error occurred while working with a [`StructOps`] program.
+    #[error(transparent)]
+    StructOpsError(#[from] StructOpsError),
+
     /// An error occurred while working with BTF.
     #[error(transparent)]
     Btf(#[from] BtfError),
@@ -208,6 +233,33 @@ pub enum ProgramError {
         name: String,
     },
 
+    /// The kernel function can't be traced by fentry and fexit programs.
+    #[error("kernel function `{function}` can't be traced: {reason}")]
+    UnsupportedTracingTarget {
+        /// The name of the function.
+        function: String,
+        /// Why the function can't be traced.
+        reason: String,
+    },
+
+    /// The output buffers of a test run are too small.
+    #[error(
+        "the output of the test run doesn't fit, it needs {data_len} bytes of data and {ctx_len} bytes of context"
+    )]
+    TestRunOutputTooSmall {
+        /// The size of the data after the run.
+        data_len: usize,
+        /// The size of the context after the run.
+        ctx_len: usize,
+    },
+
+    /// Attach cookies are not supported by the kernel.
+    ///
+    /// Cookies are set when creating a perf event link, which requires kernel
+    /// 5.15.
+    #[error("attach cookies are not supported by the kernel")]
+    AttachCookieNotSupported,
+
     /// An error occurred while working with IO.
     #[error(transparent)]
     IOError(#[from] io::Error),
@@ -215,7 +267,7 @@ pub enum ProgramError {
 
 /// A [`Program`] file descriptor.
 #[derive(Copy, Clone)]
-pub struct ProgramFd(RawFd);
+pub struct ProgramFd(pub(crate) RawFd);
 
 impl AsRawFd for ProgramFd {
     fn as_raw_fd(&self) -> RawFd {
@@ -228,8 +280,14 @@ impl AsRawFd for ProgramFd {
 pub enum Program {
     /// A [`KProbe`] program
     KProbe(KProbe),
+    /// A [`KProbeMulti`] program
+    KProbeMulti(KProbeMulti),
     /// A [`UProbe`] program
     UProbe(UProbe),
+    /// A [`UProbeMulti`] program
+    UProbeMulti(UProbeMulti),
+    /// A [`Usdt`] program
+    Usdt(Usdt),
     /// A [`TracePoint`] program
     TracePoint(TracePoint),
     /// A [`SocketFilter`] program
@@ -266,6 +324,8 @@ pub enum Program {
     FEntry(FEntry),
     /// A [`FExit`] program
     FExit(FExit),
+    /// A [`Iter`] program
+    Iter(Iter),
     /// A [`Extension`] program
     Extension(Extension),
     /// A [`SkLookup`] program
@@ -274,6 +334,101 @@ pub enum Program {
     CgroupSock(CgroupSock),
     /// A [`CgroupDevice`] program
     CgroupDevice(CgroupDevice),
+    /// A [`StructOps`] program
+    StructOps(StructOps),
+}
+
+/// The kind of ELF section a [`Program`] was parsed from, see
+/// [`Program::section`].
+///
+/// The variants follow the section prefixes, for example `kretprobe` for
+/// [`ProgramSectionKind::KRetProbe`]. Variants are added as new sections are
+/// supported.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+#[allow(missing_docs)]
+pub enum ProgramSectionKind {
+    KProbe,
+    KRetProbe,
+    KProbeMulti,
+    KRetProbeMulti,
+    UProbe,
+    URetProbe,
+    UProbeMulti,
+    URetProbeMulti,
+    Usdt,
+    TracePoint,
+    SocketFilter,
+    Xdp,
+    SkMsg,
+    SkSkbStreamParser,
+    SkSkbStreamVerdict,
+    SkSkbVerdict,
+    SockOps,
+    SchedClassifier,
+    CgroupSkb,
+    CgroupSkbIngress,
+    CgroupSkbEgress,
+    CgroupSockAddr,
+    CgroupSysctl,
+    CgroupSockopt,
+    LircMode2,
+    PerfEvent,
+    RawTracePoint,
+    Lsm,
+    BtfTracePoint,
+    FEntry,
+    FExit,
+    Iter,
+    Extension,
+    SkLookup,
+    CgroupSock,
+    CgroupDevice,
+    StructOps,
+}
+
+impl ProgramSectionKind {
+    fn new(section: &ProgramSection) -> Self {
+        match section {
+            ProgramSection::KProbe { .. } => ProgramSectionKind::KProbe,
+            ProgramSection::KRetProbe { .. } => ProgramSectionKind::KRetProbe,
+            ProgramSection::KProbeMulti { .. } => ProgramSectionKind::KProbeMulti,
+            ProgramSection::KRetProbeMulti { .. } => ProgramSectionKind::KRetProbeMulti,
+            ProgramSection::UProbe { .. } => ProgramSectionKind::UProbe,
+            ProgramSection::URetProbe { .. } => ProgramSectionKind::URetProbe,
+            ProgramSection::UProbeMulti { .. } => ProgramSectionKind::UProbeMulti,
+            ProgramSection::URetProbeMulti { .. } => ProgramSectionKind::URetProbeMulti,
+            ProgramSection::Usdt { .. } => ProgramSectionKind::Usdt,
+            ProgramSection::TracePoint { .. } => ProgramSectionKind::TracePoint,
+            ProgramSection::SocketFilter { .. } => ProgramSectionKind::SocketFilter,
+            ProgramSection::Xdp { .. } => ProgramSectionKind::Xdp,
+            ProgramSection::SkMsg { .. } => ProgramSectionKind::SkMsg,
+            ProgramSection::SkSkbStreamParser { .. } => ProgramSectionKind::SkSkbStreamParser,
+            ProgramSection::SkSkbStreamVerdict { .. } => ProgramSectionKind::SkSkbStreamVerdict,
+            ProgramSection::SkSkbVerdict { .. } => ProgramSectionKind::SkSkbVerdict,
+            ProgramSection::SockOps { .. } => ProgramSectionKind::SockOps,
+            ProgramSection::SchedClassifier { .. } => ProgramSectionKind::SchedClassifier,
+            ProgramSection::CgroupSkb { .. } => ProgramSectionKind::CgroupSkb,
+            ProgramSection::CgroupSkbIngress { .. } => ProgramSectionKind::CgroupSkbIngress,
+            ProgramSection::CgroupSkbEgress { .. } => ProgramSectionKind::CgroupSkbEgress,
+            ProgramSection::CgroupSockAddr { .. } => ProgramSectionKind::CgroupSockAddr,
+            ProgramSection::CgroupSysctl { .. } => ProgramSectionKind::CgroupSysctl,
+            ProgramSection::CgroupSockopt { .. } => ProgramSectionKind::CgroupSockopt,
+            ProgramSection::LircMode2 { .. } => ProgramSectionKind::LircMode2,
+            ProgramSection::PerfEvent { .. } => ProgramSectionKind::PerfEvent,
+            ProgramSection::RawTracePoint { .. } => ProgramSectionKind::RawTracePoint,
+            ProgramSection::Lsm { .. } => ProgramSectionKind::Lsm,
+            ProgramSection::BtfTracePoint { .. } => ProgramSectionKind::BtfTracePoint,
+            ProgramSection::FEntry { .. } => ProgramSectionKind::FEntry,
+            ProgramSection::FExit { .. } => ProgramSectionKind::FExit,
+            ProgramSection::Iter { .. } => ProgramSectionKind::Iter,
+            ProgramSection::Extension { .. } => ProgramSectionKind::Extension,
+            ProgramSection::SkLookup { .. } => ProgramSectionKind::SkLookup,
+            ProgramSection::CgroupSock { .. } => ProgramSectionKind::CgroupSock,
+            ProgramSection::CgroupDevice { .. } => ProgramSectionKind::CgroupDevice,
+            ProgramSection::StructOps { .. } => ProgramSectionKind::StructOps,
+        }
+    }
 }
 
 impl Program {
@@ -282,7 +437,10 @@ impl Program {
         use crate::generated::bpf_prog_type::*;
         match self {
             Program::KProbe(_) => BPF_PROG_TYPE_KPROBE,
+            Program::KProbeMulti(_) => BPF_PROG_TYPE_KPROBE,
             Program::UProbe(_) => BPF_PROG_TYPE_KPROBE,
+            Program::UProbeMulti(_) => BPF_PROG_TYPE_KPROBE,
+            Program::Usdt(_) => BPF_PROG_TYPE_KPROBE,
             Program::TracePoint(_) => BPF_PROG_TYPE_TRACEPOINT,
             Program::SocketFilter(_) => BPF_PROG_TYPE_SOCKET_FILTER,
             Program::Xdp(_) => BPF_PROG_TYPE_XDP,
@@ -300,11 +458,13 @@ impl Program {
             Program::BtfTracePoint(_) => BPF_PROG_TYPE_TRACING,
             Program::FEntry(_) => BPF_PROG_TYPE_TRACING,
             Program::FExit(_) => BPF_PROG_TYPE_TRACING,
+            Program::Iter(_) => BPF_PROG_TYPE_TRACING,
             Program::Extension(_) => BPF_PROG_TYPE_EXT,
             Program::CgroupSockAddr(_) => BPF_PROG_TYPE_CGROUP_SOCK_ADDR,
             Program::SkLookup(_) => BPF_PROG_TYPE_SK_LOOKUP,
             Program::CgroupSock(_) => BPF_PROG_TYPE_CGROUP_SOCK,
             Program::CgroupDevice(_) => BPF_PROG_TYPE_CGROUP_DEVICE,
+            Program::StructOps(_) => BPF_PROG_TYPE_STRUCT_OPS,
         }
     }
 
@@ -312,7 +472,10 @@ impl Program {
     pub fn pin<P: AsRef<Path>>(&mut self, path: P) -> Result<(), PinError> {
         match self {
             Program::KProbe(p) => p.pin(path),
+            Program::KProbeMulti(p) => p.pin(path),
             Program::UProbe(p) => p.pin(path),
+            Program::UProbeMulti(p) => p.pin(path),
+            Program::Usdt(p) => p.pin(path),
             Program::TracePoint(p) => p.pin(path),
             Program::SocketFilter(p) => p.pin(path),
             Program::Xdp(p) => p.pin(path),
@@ -330,11 +493,13 @@ impl Program {
             Program::BtfTracePoint(p) => p.pin(path),
             Program::FEntry(p) => p.pin(path),
             Program::FExit(p) => p.pin(path),
+            Program::Iter(p) => p.pin(path),
             Program::Extension(p) => p.pin(path),
             Program::CgroupSockAddr(p) => p.pin(path),
             Program::SkLookup(p) => p.pin(path),
             Program::CgroupSock(p) => p.pin(path),
             Program::CgroupDevice(p) => p.pin(path),
+            Program::StructOps(p) => p.pin(path),
         }
     }
 
@@ -342,7 +507,10 @@ impl Program {
     pub fn unload(self) -> Result<(), ProgramError> {
         match self {
             Program::KProbe(mut p) => p.unload(),
+            Program::KProbeMulti(mut p) => p.unload(),
             Program::UProbe(mut p) => p.unload(),
+            Program::UProbeMulti(mut p) => p.unload(),
+            Program::Usdt(mut p) => p.unload(),
             Program::TracePoint(mut p) => p.unload(),
             Program::SocketFilter(mut p) => p.unload(),
             Program::Xdp(mut p) => p.unload(),
@@ -360,11 +528,13 @@ impl Program {
             Program::BtfTracePoint(mut p) => p.unload(),
             Program::FEntry(mut p) => p.unload(),
             Program::FExit(mut p) => p.unload(),
+            Program::Iter(mut p) => p.unload(),
             Program::Extension(mut p) => p.unload(),
             Program::CgroupSockAddr(mut p) => p.unload(),
             Program::SkLookup(mut p) => p.unload(),
             Program::CgroupSock(mut p) => p.unload(),
             Program::CgroupDevice(mut p) => p.unload(),
+            Program::StructOps(mut p) => p.unload(),
         }
     }
 
@@ -375,7 +545,10 @@ impl Program {
     pub fn fd(&self) -> Option<ProgramFd> {
         match self {
             Program::KProbe(p) => p.fd(),
+            Program::KProbeMulti(p) => p.fd(),
             Program::UProbe(p) => p.fd(),
+            Program::UProbeMulti(p) => p.fd(),
+            Program::Usdt(p) => p.fd(),
             Program::TracePoint(p) => p.fd(),
             Program::SocketFilter(p) => p.fd(),
             Program::Xdp(p) => p.fd(),
@@ -393,15 +566,244 @@ impl Program {
             Program::BtfTracePoint(p) => p.fd(),
             Program::FEntry(p) => p.fd(),
             Program::FExit(p) => p.fd(),
+            Program::Iter(p) => p.fd(),
             Program::Extension(p) => p.fd(),
             Program::CgroupSockAddr(p) => p.fd(),
             Program::SkLookup(p) => p.fd(),
             Program::CgroupSock(p) => p.fd(),
             Program::CgroupDevice(p) => p.fd(),
+            Program::StructOps(p) => p.fd(),
+        }
+    }
+
+    /// Returns the kind of ELF section the program was parsed from.
+    ///
+    /// This can be used to tell apart programs of the same type, for example
+    /// kprobes from kretprobes, or ingress from egress [`CgroupSkb`]
+    /// programs. Returns `None` for programs created with `from_pin`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # let bpf = aya::Bpf::load(&[])?;
+    /// use aya::programs::ProgramSectionKind;
+    ///
+    /// let kretprobes = bpf
+    ///     .programs()
+    ///     .filter(|(_, p)| p.section() == Some(ProgramSectionKind::KRetProbe));
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn section(&self) -> Option<ProgramSectionKind> {
+        self.obj().map(|obj| ProgramSectionKind::new(&obj.section))
+    }
+
+    pub(crate) fn obj(&self) -> Option<&obj::Program> {
+        match self {
+            Program::KProbe(p) => p.data.obj.as_ref(),
+            Program::KProbeMulti(p) => p.data.obj.as_ref(),
+            Program::UProbe(p) => p.data.obj.as_ref(),
+            Program::UProbeMulti(p) => p.data.obj.as_ref(),
+            Program::Usdt(p) => p.data.obj.as_ref(),
+            Program::TracePoint(p) => p.data.obj.as_ref(),
+            Program::SocketFilter(p) => p.data.obj.as_ref(),
+            Program::Xdp(p) => p.data.obj.as_ref(),
+            Program::SkMsg(p) => p.data.obj.as_ref(),
+            Program::SkSkb(p) => p.data.obj.as_ref(),
+            Program::SockOps(p) => p.data.obj.as_ref(),
+            Program::SchedClassifier(p) => p.data.obj.as_ref(),
+            Program::CgroupSkb(p) => p.data.obj.as_ref(),
+            Program::CgroupSysctl(p) => p.data.obj.as_ref(),
+            Program::CgroupSockopt(p) => p.data.obj.as_ref(),
+            Program::LircMode2(p) => p.data.obj.as_ref(),
+            Program::PerfEvent(p) => p.data.obj.as_ref(),
+            Program::RawTracePoint(p) => p.data.obj.as_ref(),
+            Program::Lsm(p) => p.data.obj.as_ref(),
+            Program::BtfTracePoint(p) => p.data.obj.as_ref(),
+            Program::FEntry(p) => p.data.obj.as_ref(),
+            Program::FExit(p) => p.data.obj.as_ref(),
+            Program::Iter(p) => p.data.obj.as_ref(),
+            Program::Extension(p) => p.data.obj.as_ref(),
+            Program::CgroupSockAddr(p) => p.data.obj.as_ref(),
+            Program::SkLookup(p) => p.data.obj.as_ref(),
+            Program::CgroupSock(p) => p.data.obj.as_ref(),
+            Program::CgroupDevice(p) => p.data.obj.as_ref(),
+            Program::StructOps(p) => p.data.obj.as_ref(),
+        }
+    }
+
+    /// Runs the program in the kernel on the given input, without attaching it.
+    ///
+    /// For programs that process packets, like [`Xdp`], [`SchedClassifier`],
+    /// [`CgroupSkb`] and [`SocketFilter`], `input` is a packet starting at the
+    /// ethernet header, and the returned data is the packet as modified by the
+    /// program. For [`RawTracePoint`] programs, `input` holds the arguments of
+    /// the tracepoint as native endian `u64`s, and `repeat` must be `0`.
+    ///
+    /// The program runs `repeat` times, or once if `repeat` is `0`, which can
+    /// be used to benchmark it. The returned duration is the average duration
+    /// of one run.
+    ///
+    /// The returned packet can be up to 256 bytes larger than `input`, to
+    /// make room for programs that grow it, e.g. with `bpf_xdp_adjust_head()`.
+    /// Use [`Program::test_run_with_options`] to set the size of the output
+    /// buffers, or to pass a context to the program.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 4.12.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProgramError::TestRunOutputTooSmall`] if the packet grew by
+    /// more than 256 bytes, [`ProgramError::SyscallError`] if the kernel can't
+    /// test the program, for example because test runs aren't supported for
+    /// its type.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::programs::Xdp;
+    ///
+    /// let program: &mut Xdp = bpf.program_mut("drop_udp").unwrap().try_into()?;
+    /// program.load()?;
+    ///
+    /// let packet = [0u8; 64];
+    /// let output = bpf.program("drop_udp").unwrap().test_run(&packet, 1)?;
+    /// println!("returned {} in {:?}", output.return_value, output.duration);
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn test_run(&self, input: &[u8], repeat: u32) -> Result<TestRunOutput, ProgramError> {
+        let options = match self {
+            Program::RawTracePoint(_) => TestRunOptions {
+                ctx_in: input,
+                repeat,
+                ..Default::default()
+            },
+            _ => TestRunOptions {
+                data_in: input,
+                data_out_len: if input.is_empty() {
+                    0
+                } else {
+                    input.len() + 256
+                },
+                repeat,
+                ..Default::default()
+            },
+        };
+        self.test_run_with_options(&options)
+    }
+
+    /// Runs the program in the kernel with the given options, without
+    /// attaching it.
+    ///
+    /// Unlike [`Program::test_run`], the program can be given a context, e.g.
+    /// a `struct __sk_buff` or a `struct xdp_md` setting the metadata of the
+    /// packet, and the context as modified by the program is returned.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 4.12, 5.3
+    /// for contexts.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProgramError::TestRunOutputTooSmall`] with the sizes the
+    /// outputs need if they don't fit in the buffers. The program isn't run
+    /// again, as it may have side effects, the caller can run it again with
+    /// larger buffers.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::programs::TestRunOptions;
+    ///
+    /// let packet = [0u8; 64];
+    /// // struct __sk_buff, with the mark set
+    /// let mut skb = [0u8; 192];
+    /// skb[8..12].copy_from_slice(&42u32.to_ne_bytes());
+    /// let output = bpf.program("classifier").unwrap().test_run_with_options(&TestRunOptions {
+    ///     data_in: &packet,
+    ///     data_out_len: packet.len(),
+    ///     ctx_in: &skb,
+    ///     ctx_out_len: skb.len(),
+    ///     ..Default::default()
+    /// })?;
+    /// println!("mark: {:?}", &output.ctx[8..12]);
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn test_run_with_options(
+        &self,
+        options: &TestRunOptions<'_>,
+    ) -> Result<TestRunOutput, ProgramError> {
+        let prog_fd = self.fd().ok_or(ProgramError::NotLoaded)?.as_raw_fd();
+        let mut data = vec![0u8; options.data_out_len];
+        let mut ctx = vec![0u8; options.ctx_out_len];
+        let mut data_size_out = 0;
+        let mut ctx_size_out = 0;
+        match bpf_prog_test_run(
+            prog_fd,
+            options.data_in,
+            options.ctx_in,
+            &mut data,
+            &mut data_size_out,
+            &mut ctx,
+            &mut ctx_size_out,
+            options.repeat,
+        ) {
+            Ok((return_value, duration)) => {
+                data.truncate(data_size_out as usize);
+                ctx.truncate(ctx_size_out as usize);
+                Ok(TestRunOutput {
+                    return_value,
+                    data,
+                    ctx,
+                    duration: Duration::from_nanos(duration as u64),
+                })
+            }
+            Err((_, io_error)) if io_error.raw_os_error() == Some(ENOSPC) => {
+                Err(ProgramError::TestRunOutputTooSmall {
+                    data_len: data_size_out as usize,
+                    ctx_len: ctx_size_out as usize,
+                })
+            }
+            Err((_, io_error)) => Err(ProgramError::SyscallError {
+                call: "bpf_prog_test_run".to_owned(),
+                io_error,
+            }),
         }
     }
 }
 
+/// The input of a test run of a program, see
+/// [`Program::test_run_with_options`].
+#[derive(Debug, Default)]
+pub struct TestRunOptions<'a> {
+    /// The data the program runs on, e.g. a packet.
+    pub data_in: &'a [u8],
+    /// The size of the buffer receiving the data after the program ran.
+    pub data_out_len: usize,
+    /// The context the program runs with.
+    pub ctx_in: &'a [u8],
+    /// The size of the buffer receiving the context after the program ran.
+    pub ctx_out_len: usize,
+    /// How many times the program runs, once if `0`.
+    pub repeat: u32,
+}
+
+/// The output of a test run of a program, see [`Program::test_run`].
+#[derive(Debug)]
+pub struct TestRunOutput {
+    /// The value returned by the program.
+    pub return_value: u32,
+    /// The data after the program ran, for example the modified packet.
+    pub data: Vec<u8>,
+    /// The context after the program ran.
+    pub ctx: Vec<u8>,
+    /// The average duration of one run of the program.
+    pub duration: Duration,
+}
+
 #[derive(Debug)]
 pub(crate) struct ProgramData<T: Link> {
     pub(crate) name: Option<String>,
@@ -409,13 +811,16 @@ pub(crate) struct ProgramData<T: Link> {
     pub(crate) fd: Option<RawFd>,
     pub(crate) links: LinkMap<T>,
     pub(crate) expected_attach_type: Option<bpf_attach_type>,
+    pub(crate) raw_attach_type: Option<u32>,
     pub(crate) attach_btf_obj_fd: Option<u32>,
     pub(crate) attach_btf_id: Option<u32>,
     pub(crate) attach_prog_fd: Option<RawFd>,
+    pub(crate) prog_ifindex: Option<u32>,
     pub(crate) btf_fd: Option<RawFd>,
     pub(crate) verifier_log_level: u32,
     pub(crate) path: Option<PathBuf>,
     pub(crate) flags: u32,
+    pub(crate) token_fd: Option<Arc<OwnedFd>>,
 }
 
 impl<T: Link> ProgramData<T> {
@@ -424,6 +829,7 @@ impl<T: Link> ProgramData<T> {
         obj: obj::Program,
         btf_fd: Option<RawFd>,
         verifier_log_level: u32,
+        token_fd: Option<Arc<OwnedFd>>,
     ) -> ProgramData<T> {
         ProgramData {
             name,
@@ -431,13 +837,16 @@ impl<T: Link> ProgramData<T> {
             fd: None,
             links: LinkMap::new(),
             expected_attach_type: None,
+            raw_attach_type: None,
             attach_btf_obj_fd: None,
             attach_btf_id: None,
             attach_prog_fd: None,
+            prog_ifindex: None,
             btf_fd,
             verifier_log_level,
             path: None,
             flags: 0,
+            token_fd,
         }
     }
 
@@ -470,13 +879,16 @@ impl<T: Link> ProgramData<T> {
             fd: Some(fd),
             links: LinkMap::new(),
             expected_attach_type: None,
+            raw_attach_type: None,
             attach_btf_obj_fd,
             attach_btf_id,
             attach_prog_fd: None,
+            prog_ifindex: None,
             btf_fd: None,
             verifier_log_level: 0,
             path: Some(path.to_path_buf()),
             flags: 0,
+            token_fd: None,
         })
     }
 
@@ -601,15 +1013,18 @@ fn load_program<T: Link>(
         license,
         kernel_version: target_kernel_version,
         expected_attach_type: data.expected_attach_type,
+        raw_attach_type: data.raw_attach_type,
         prog_btf_fd: data.btf_fd,
         attach_btf_obj_fd: data.attach_btf_obj_fd,
         attach_btf_id: data.attach_btf_id,
         attach_prog_fd: data.attach_prog_fd,
+        prog_ifindex: data.prog_ifindex,
         func_info_rec_size: *func_info_rec_size,
         func_info: func_info.clone(),
         line_info_rec_size: *line_info_rec_size,
         line_info: line_info.clone(),
         flags: data.flags,
+        token_fd: data.token_fd.as_ref().map(|fd| fd.as_raw_fd()),
     };
 
     let verifier_log_level = data.verifier_log_level;
@@ -641,28 +1056,61 @@ pub(crate) fn query<T: AsRawFd>(
     query_flags: u32,
     attach_flags: &mut Option<u32>,
 ) -> Result<Vec<u32>, ProgramError> {
+    let (prog_ids, _) = query_with_flags(
+        target_fd.as_raw_fd(),
+        attach_type as u32,
+        query_flags,
+        attach_flags,
+        false,
+    )?;
+    Ok(prog_ids)
+}
+
+// Like `query`, also returning the attach flags of each program if
+// `prog_attach_flags` is set. Kernels older than 6.2 reject the query with
+// EINVAL, in which case it's retried without them and `None` is returned.
+pub(crate) fn query_with_flags(
+    target_fd: RawFd,
+    attach_type: u32,
+    query_flags: u32,
+    attach_flags: &mut Option<u32>,
+    mut prog_attach_flags: bool,
+) -> Result<(Vec<u32>, Option<Vec<u32>>), ProgramError> {
     let mut prog_ids = vec![0u32; 64];
+    let mut prog_flags = vec![0u32; if prog_attach_flags { 64 } else { 0 }];
     let mut prog_cnt = prog_ids.len() as u32;
 
     let mut retries = 0;
 
     loop {
         match bpf_prog_query(
-            target_fd.as_raw_fd(),
+            target_fd,
             attach_type,
             query_flags,
             attach_flags.as_mut(),
             &mut prog_ids,
+            prog_attach_flags.then_some(prog_flags.as_mut_slice()),
             &mut prog_cnt,
         ) {
             Ok(_) => {
                 prog_ids.resize(prog_cnt as usize, 0);
-                return Ok(prog_ids);
+                if !prog_attach_flags {
+                    return Ok((prog_ids, None));
+                }
+                prog_flags.resize(prog_cnt as usize, 0);
+                return Ok((prog_ids, Some(prog_flags)));
             }
             Err((_, io_error)) if retries == 0 && io_error.raw_os_error() == Some(ENOSPC) => {
                 prog_ids.resize(prog_cnt as usize, 0);
+                if prog_attach_flags {
+                    prog_flags.resize(prog_cnt as usize, 0);
+                }
                 retries += 1;
             }
+            Err((_, io_error)) if prog_attach_flags && io_error.raw_os_error() == Some(EINVAL) => {
+                prog_attach_flags = false;
+                prog_flags = Vec::new();
+            }
             Err((_, io_error)) => {
                 return Err(ProgramError::SyscallError {
                     call: "bpf_prog_query".to_owned(),
@@ -698,7 +1146,10 @@ macro_rules! impl_program_unload {
 
 impl_program_unload!(
     KProbe,
+    KProbeMulti,
     UProbe,
+    UProbeMulti,
+    Usdt,
     TracePoint,
     SocketFilter,
     Xdp,
@@ -715,12 +1166,14 @@ impl_program_unload!(
     BtfTracePoint,
     FEntry,
     FExit,
+    Iter,
     Extension,
     CgroupSockAddr,
     SkLookup,
     SockOps,
     CgroupSock,
     CgroupDevice,
+    StructOps,
 );
 
 macro_rules! impl_fd {
@@ -738,7 +1191,10 @@ macro_rules! impl_fd {
 
 impl_fd!(
     KProbe,
+    KProbeMulti,
     UProbe,
+    UProbeMulti,
+    Usdt,
     TracePoint,
     SocketFilter,
     Xdp,
@@ -755,12 +1211,67 @@ impl_fd!(
     BtfTracePoint,
     FEntry,
     FExit,
+    Iter,
     Extension,
     CgroupSockAddr,
     SkLookup,
     SockOps,
     CgroupSock,
     CgroupDevice,
+    StructOps,
+);
+
+macro_rules! impl_info {
+    ($($struct_name:ident),+ $(,)?) => {
+        $(
+            impl $struct_name {
+                /// Returns the kernel's information about this program, including
+                /// its runtime statistics.
+                pub fn info(&self) -> Result<ProgramInfo, ProgramError> {
+                    let fd = self.data.fd_or_err()?;
+                    let info = bpf_prog_get_info_by_fd(fd).map_err(|io_error| {
+                        ProgramError::SyscallError {
+                            call: "bpf_prog_get_info_by_fd".to_owned(),
+                            io_error,
+                        }
+                    })?;
+                    Ok(ProgramInfo(info))
+                }
+            }
+        )+
+    }
+}
+
+impl_info!(
+    KProbe,
+    KProbeMulti,
+    UProbe,
+    UProbeMulti,
+    Usdt,
+    TracePoint,
+    SocketFilter,
+    Xdp,
+    SkMsg,
+    SkSkb,
+    SchedClassifier,
+    CgroupSkb,
+    CgroupSysctl,
+    CgroupSockopt,
+    LircMode2,
+    PerfEvent,
+    Lsm,
+    RawTracePoint,
+    BtfTracePoint,
+    FEntry,
+    FExit,
+    Iter,
+    Extension,
+    CgroupSockAddr,
+    SkLookup,
+    SockOps,
+    CgroupSock,
+    CgroupDevice,
+    StructOps,
 );
 
 macro_rules! impl_program_pin{
@@ -792,7 +1303,10 @@ macro_rules! impl_program_pin{
 
 impl_program_pin!(
     KProbe,
+    KProbeMulti,
     UProbe,
+    UProbeMulti,
+    Usdt,
     TracePoint,
     SocketFilter,
     Xdp,
@@ -809,12 +1323,14 @@ impl_program_pin!(
     BtfTracePoint,
     FEntry,
     FExit,
+    Iter,
     Extension,
     CgroupSockAddr,
     SkLookup,
     SockOps,
     CgroupSock,
     CgroupDevice,
+    StructOps,
 );
 
 macro_rules! impl_from_pin {
@@ -840,7 +1356,6 @@ macro_rules! impl_from_pin {
 impl_from_pin!(
     TracePoint,
     SocketFilter,
-    Xdp,
     SkMsg,
     CgroupSysctl,
     LircMode2,
@@ -850,10 +1365,12 @@ impl_from_pin!(
     BtfTracePoint,
     FEntry,
     FExit,
+    Iter,
     Extension,
     SkLookup,
     SockOps,
     CgroupDevice,
+    StructOps,
 );
 
 macro_rules! impl_try_from_program {
@@ -886,7 +1403,10 @@ macro_rules! impl_try_from_program {
 
 impl_try_from_program!(
     KProbe,
+    KProbeMulti,
     UProbe,
+    UProbeMulti,
+    Usdt,
     TracePoint,
     SocketFilter,
     Xdp,
@@ -904,11 +1424,13 @@ impl_try_from_program!(
     BtfTracePoint,
     FEntry,
     FExit,
+    Iter,
     Extension,
     CgroupSockAddr,
     SkLookup,
     CgroupSock,
     CgroupDevice,
+    StructOps,
 );
 
 /// Provides information about a loaded program, like name, id and statistics
@@ -941,6 +1463,74 @@ impl ProgramInfo {
         self.0.id
     }
 
+    /// The total time the program spent running.
+    ///
+    /// This is only accounted while statistics are enabled, see
+    /// [`enable_stats`].
+    pub fn run_time(&self) -> Duration {
+        Duration::from_nanos(self.0.run_time_ns)
+    }
+
+    /// The number of times the program ran.
+    ///
+    /// This is only accounted while statistics are enabled, see
+    /// [`enable_stats`].
+    pub fn run_count(&self) -> u64 {
+        self.0.run_cnt
+    }
+
+    /// The id of the BTF of the program, if it was loaded with BTF.
+    pub fn btf_id(&self) -> Option<u32> {
+        (self.0.btf_id != 0).then_some(self.0.btf_id)
+    }
+
+    /// Returns the source line information of the program.
+    ///
+    /// The records map the instructions of the program to lines of its source
+    /// code. They are empty when the program was loaded without BTF.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let bpf = aya::Bpf::load(&[])?;
+    /// use aya::programs::Xdp;
+    ///
+    /// let program: &Xdp = bpf.program("xdp").unwrap().try_into()?;
+    /// let btf = bpf.btf().unwrap();
+    /// for line in program.info()?.line_info()? {
+    ///     println!(
+    ///         "insn {}: {}:{}:{}",
+    ///         line.insn_off,
+    ///         btf.string_at(line.file_name_offset)?,
+    ///         line.line,
+    ///         line.column
+    ///     );
+    /// }
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    pub fn line_info(&self) -> Result<Vec<LineInfo>, ProgramError> {
+        if self.0.nr_line_info == 0 {
+            return Ok(Vec::new());
+        }
+        let fd = self.fd()?;
+        let line_info = bpf_prog_get_line_info(fd, self.0.nr_line_info);
+        unsafe { libc::close(fd) };
+        let line_info = line_info.map_err(|io_error| ProgramError::SyscallError {
+            call: "bpf_prog_get_info_by_fd".to_owned(),
+            io_error,
+        })?;
+        Ok(line_info
+            .into_iter()
+            .map(|info| LineInfo {
+                insn_off: info.insn_off,
+                file_name_offset: info.file_name_off,
+                line_offset: info.line_off,
+                line: info.line_col >> 10,
+                column: info.line_col & 0x3ff,
+            })
+            .collect())
+    }
+
     /// Returns the fd associated with the program.
     ///
     /// The returned fd must be closed when no longer needed.
@@ -973,6 +1563,89 @@ impl ProgramInfo {
     }
 }
 
+/// A source line of a loaded program, see [`ProgramInfo::line_info`].
+///
+/// The offsets point into the string section of the program's BTF. For
+/// programs loaded by this process, the strings can be resolved with
+/// [`Btf::string_at`](crate::Btf::string_at) on [`Bpf::btf`](crate::Bpf::btf).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineInfo {
+    /// The offset of the first instruction of the line, in instructions.
+    pub insn_off: u32,
+    /// The offset of the source file name.
+    pub file_name_offset: u32,
+    /// The offset of the source line.
+    pub line_offset: u32,
+    /// The line number.
+    pub line: u32,
+    /// The column number.
+    pub column: u32,
+}
+
+/// The kind of statistics to collect, see [`enable_stats`].
+#[derive(Debug, Clone, Copy)]
+pub enum StatsType {
+    /// Collect the run time and run count of the programs, see
+    /// [`ProgramInfo::run_time`] and [`ProgramInfo::run_count`].
+    RunTime,
+}
+
+impl StatsType {
+    fn raw(self) -> u32 {
+        match self {
+            // BPF_STATS_RUN_TIME, bpf_stats_type isn't in the generated bindings
+            StatsType::RunTime => 0,
+        }
+    }
+}
+
+/// Keeps statistics enabled, see [`enable_stats`].
+///
+/// Statistics are collected until the guard is dropped.
+#[derive(Debug)]
+pub struct StatsGuard {
+    fd: RawFd,
+}
+
+impl Drop for StatsGuard {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+/// Enables the collection of statistics for all the programs loaded in the
+/// kernel.
+///
+/// Collecting statistics adds some overhead to each program run, so it's
+/// disabled by default. It stays enabled while the returned guard, or a guard
+/// obtained by another process, is alive.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.8.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = aya::Bpf::load(&[])?;
+/// use aya::programs::{enable_stats, StatsType, Xdp};
+///
+/// let _stats = enable_stats(StatsType::RunTime)?;
+///
+/// let program: &Xdp = bpf.program("xdp_counter").unwrap().try_into()?;
+/// let info = program.info()?;
+/// println!("ran {} times in {:?}", info.run_count(), info.run_time());
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+pub fn enable_stats(stats_type: StatsType) -> Result<StatsGuard, ProgramError> {
+    let fd =
+        bpf_enable_stats(stats_type.raw()).map_err(|(_, io_error)| ProgramError::SyscallError {
+            call: "bpf_enable_stats".to_owned(),
+            io_error,
+        })? as RawFd;
+    Ok(StatsGuard { fd })
+}
+
 /// ProgramsIter is an Iterator over loaded eBPF programs.
 pub struct ProgramsIter {
     current: u32,
@@ -1050,3 +1723,287 @@ pub fn loaded_programs() -> ProgramsIter {
         error: false,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, ffi::CString, io, mem, ptr};
+
+    use object::SectionIndex;
+
+    use super::*;
+    use crate::{
+        generated::{bpf_attr, bpf_cmd, bpf_line_info},
+        obj::KernelVersion,
+        sys::{override_syscall, Syscall},
+    };
+
+    fn new_obj_program(section: ProgramSection) -> obj::Program {
+        obj::Program {
+            license: CString::new("GPL").unwrap(),
+            kernel_version: KernelVersion::Any,
+            section,
+            function: obj::Function {
+                address: 0,
+                name: "prog".to_owned(),
+                section_index: SectionIndex(0),
+                section_offset: 0,
+                instructions: Vec::new(),
+                func_info: Default::default(),
+                line_info: Default::default(),
+                func_info_rec_size: 0,
+                line_info_rec_size: 0,
+            },
+        }
+    }
+
+    fn new_xdp() -> Program {
+        let obj = new_obj_program(ProgramSection::Xdp {
+            name: "prog".to_owned(),
+            frags: false,
+            attach_type: obj::programs::XdpAttachType::Interface,
+        });
+        let mut data = ProgramData::new(Some("prog".to_owned()), obj, None, 0, None);
+        data.fd = Some(42);
+        Program::Xdp(Xdp {
+            data,
+            attach_type: XdpAttachType::Interface,
+        })
+    }
+
+    // The kernel writes the results of the run back to the attributes.
+    fn set_test_results(attr: &bpf_attr, retval: u32, data_size_out: u32, ctx_size_out: u32) {
+        unsafe {
+            *(ptr::addr_of!(attr.test.retval) as *mut u32) = retval;
+            *(ptr::addr_of!(attr.test.duration) as *mut u32) = 10;
+            *(ptr::addr_of!(attr.test.data_size_out) as *mut u32) = data_size_out;
+            *(ptr::addr_of!(attr.test.ctx_size_out) as *mut u32) = ctx_size_out;
+        }
+    }
+
+    thread_local! {
+        static RUNS: Cell<u32> = const { Cell::new(0) };
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_test_run() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_TEST_RUN,
+                attr,
+            } => {
+                let test = unsafe { attr.test };
+                assert_eq!(test.prog_fd, 42);
+                assert_eq!(test.repeat, 3);
+                assert_eq!(test.ctx_in, 0);
+                assert_eq!(test.ctx_out, 0);
+                let data_in = unsafe {
+                    std::slice::from_raw_parts(
+                        test.data_in as *const u8,
+                        test.data_size_in as usize,
+                    )
+                };
+                assert_eq!(data_in, &[1, 2, 3, 4]);
+                assert_eq!(test.data_size_out, 4 + 256);
+                // the program strips the first byte
+                unsafe {
+                    ptr::copy_nonoverlapping(data_in[1..].as_ptr(), test.data_out as *mut u8, 3)
+                };
+                set_test_results(attr, 2, 3, 0);
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EFAULT))),
+        });
+
+        let output = new_xdp().test_run(&[1, 2, 3, 4], 3).unwrap();
+        assert_eq!(output.return_value, 2);
+        assert_eq!(output.data, &[2, 3, 4]);
+        assert!(output.ctx.is_empty());
+        assert_eq!(output.duration, Duration::from_nanos(10));
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_test_run_raw_tracepoint() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_TEST_RUN,
+                attr,
+            } => {
+                let test = unsafe { attr.test };
+                assert_eq!(test.data_in, 0);
+                assert_eq!(test.data_out, 0);
+                assert_eq!(test.ctx_out, 0);
+                assert_eq!(test.ctx_size_in, 8);
+                set_test_results(attr, 1, 0, 0);
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EFAULT))),
+        });
+
+        let obj = new_obj_program(ProgramSection::RawTracePoint {
+            name: "prog".to_owned(),
+        });
+        let mut data = ProgramData::new(Some("prog".to_owned()), obj, None, 0, None);
+        data.fd = Some(42);
+        let program = Program::RawTracePoint(RawTracePoint { data });
+        let output = program.test_run(&7u64.to_ne_bytes(), 0).unwrap();
+        assert_eq!(output.return_value, 1);
+        assert!(output.data.is_empty());
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_test_run_ctx() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_TEST_RUN,
+                attr,
+            } => {
+                let test = unsafe { attr.test };
+                assert_eq!(test.ctx_size_in, 8);
+                assert_eq!(test.ctx_size_out, 16);
+                // the program sets the second field of the context
+                unsafe {
+                    ptr::copy_nonoverlapping(test.ctx_in as *const u8, test.ctx_out as *mut u8, 8);
+                    *(test.ctx_out as *mut u32).add(2) = 5;
+                }
+                set_test_results(attr, 0, 0, 12);
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EFAULT))),
+        });
+
+        let ctx = [1u8; 8];
+        let output = new_xdp()
+            .test_run_with_options(&TestRunOptions {
+                ctx_in: &ctx,
+                ctx_out_len: 16,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(output.ctx, &[1, 1, 1, 1, 1, 1, 1, 1, 5, 0, 0, 0]);
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_test_run_output_too_small() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_TEST_RUN,
+                attr,
+            } => {
+                RUNS.with(|runs| runs.set(runs.get() + 1));
+                set_test_results(attr, 0, 400, 0);
+                Err((-1, io::Error::from_raw_os_error(ENOSPC)))
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(libc::EFAULT))),
+        });
+
+        assert!(matches!(
+            new_xdp().test_run(&[0; 64], 1),
+            Err(ProgramError::TestRunOutputTooSmall {
+                data_len: 400,
+                ctx_len: 0
+            })
+        ));
+        // the program isn't run again
+        assert_eq!(RUNS.with(Cell::get), 1);
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_line_info() {
+        override_syscall(|call| match call {
+            // Not a valid fd, closing it has no effect
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_GET_FD_BY_ID,
+                ..
+            } => Ok(i32::MAX as i64),
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_OBJ_GET_INFO_BY_FD,
+                attr,
+            } => {
+                let info = unsafe { &mut *(attr.info.info as *mut bpf_prog_info) };
+                assert_eq!(info.nr_line_info, 2);
+                assert_eq!(
+                    info.line_info_rec_size as usize,
+                    mem::size_of::<bpf_line_info>()
+                );
+                let records = info.line_info as *mut bpf_line_info;
+                for i in 0..2 {
+                    unsafe {
+                        *records.add(i) = bpf_line_info {
+                            insn_off: i as u32 * 4,
+                            file_name_off: 1,
+                            line_off: 10 + i as u32,
+                            line_col: (42 + i as u32) << 10 | 5,
+                        }
+                    };
+                }
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        let info = ProgramInfo(bpf_prog_info {
+            id: 1,
+            nr_line_info: 2,
+            ..unsafe { mem::zeroed() }
+        });
+        assert_eq!(
+            info.line_info().unwrap(),
+            [
+                LineInfo {
+                    insn_off: 0,
+                    file_name_offset: 1,
+                    line_offset: 10,
+                    line: 42,
+                    column: 5,
+                },
+                LineInfo {
+                    insn_off: 4,
+                    file_name_offset: 1,
+                    line_offset: 11,
+                    line: 43,
+                    column: 5,
+                },
+            ]
+        );
+
+        // Programs loaded without BTF have no line info
+        let info = ProgramInfo(bpf_prog_info {
+            id: 1,
+            ..unsafe { mem::zeroed() }
+        });
+        assert!(info.line_info().unwrap().is_empty());
+        assert_eq!(info.btf_id(), None);
+    }
+
+    #[test]
+    fn test_test_run_not_loaded() {
+        let mut program = new_xdp();
+        if let Program::Xdp(xdp) = &mut program {
+            xdp.data.fd = None;
+        }
+        assert!(matches!(
+            program.test_run(&[0; 64], 1),
+            Err(ProgramError::NotLoaded)
+        ));
+    }
+}
@@ -3,9 +3,8 @@ use libc::close;
 use std::os::unix::io::RawFd;
 
 use crate::{
-    generated::bpf_attach_type::BPF_PERF_EVENT,
     programs::{probe::detach_debug_fs, FdLink, Link, ProbeKind, ProgramError},
-    sys::{bpf_link_create, perf_event_ioctl},
+    sys::{bpf_link_create_perf_event, perf_event_ioctl},
     FEATURES, PERF_EVENT_IOC_DISABLE, PERF_EVENT_IOC_ENABLE, PERF_EVENT_IOC_SET_BPF,
 };
 
@@ -72,16 +71,23 @@ impl Link for PerfLink {
     }
 }
 
-pub(crate) fn perf_attach(prog_fd: RawFd, fd: RawFd) -> Result<PerfLinkInner, ProgramError> {
+pub(crate) fn perf_attach(
+    prog_fd: RawFd,
+    fd: RawFd,
+    cookie: Option<u64>,
+) -> Result<PerfLinkInner, ProgramError> {
     if FEATURES.bpf_perf_link {
-        let link_fd =
-            bpf_link_create(prog_fd, fd, BPF_PERF_EVENT, None, 0).map_err(|(_, io_error)| {
-                ProgramError::SyscallError {
-                    call: "bpf_link_create".to_owned(),
-                    io_error,
-                }
-            })? as RawFd;
+        let link_fd = bpf_link_create_perf_event(prog_fd, fd, cookie.unwrap_or(0)).map_err(
+            |(_, io_error)| ProgramError::SyscallError {
+                call: "bpf_link_create".to_owned(),
+                io_error,
+            },
+        )? as RawFd;
         Ok(PerfLinkInner::FdLink(FdLink::new(link_fd)))
+    } else if cookie.is_some() {
+        // cookies can only be set through a bpf_link
+        unsafe { close(fd) };
+        Err(ProgramError::AttachCookieNotSupported)
     } else {
         perf_attach_either(prog_fd, fd, None, None)
     }
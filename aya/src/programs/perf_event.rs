@@ -169,10 +169,76 @@ impl PerfEvent {
             io_error,
         })? as i32;
 
-        let link = perf_attach(self.data.fd_or_err()?, fd)?;
+        let link = perf_attach(self.data.fd_or_err()?, fd, None)?;
         self.data.links.insert(PerfEventLink::new(link))
     }
 
+    /// Attaches to the given perf event once for each CPU in `cpus`.
+    ///
+    /// This is what profilers usually do: sample either `pid` or, if `pid` is
+    /// `None`, all the processes, on every CPU of the set. See
+    /// [PerfEvent::attach] for the meaning of `config`.
+    ///
+    /// The returned values can be used to detach, see [PerfEvent::detach]. If
+    /// attaching to one of the CPUs fails, the events already attached by this
+    /// call are detached before returning the error.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[derive(Debug, thiserror::Error)]
+    /// # enum Error {
+    /// #     #[error(transparent)]
+    /// #     IO(#[from] std::io::Error),
+    /// #     #[error(transparent)]
+    /// #     Program(#[from] aya::programs::ProgramError),
+    /// #     #[error(transparent)]
+    /// #     Bpf(#[from] aya::BpfError)
+    /// # }
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::util::online_cpus;
+    /// use aya::programs::perf_event::{
+    ///     perf_hw_id::PERF_COUNT_HW_CPU_CYCLES, PerfEvent, PerfTypeId, SamplePolicy,
+    /// };
+    ///
+    /// let prog: &mut PerfEvent = bpf.program_mut("profile_cpu").unwrap().try_into()?;
+    /// prog.load()?;
+    /// prog.attach_to_cpus(
+    ///     PerfTypeId::Hardware,
+    ///     PERF_COUNT_HW_CPU_CYCLES as u64,
+    ///     &online_cpus()?,
+    ///     None,
+    ///     SamplePolicy::Frequency(99),
+    /// )?;
+    /// # Ok::<(), Error>(())
+    /// ```
+    pub fn attach_to_cpus(
+        &mut self,
+        perf_type: PerfTypeId,
+        config: u64,
+        cpus: &[u32],
+        pid: Option<u32>,
+        sample_policy: SamplePolicy,
+    ) -> Result<Vec<PerfEventLinkId>, ProgramError> {
+        let mut link_ids = Vec::with_capacity(cpus.len());
+        for &cpu in cpus {
+            let scope = match pid {
+                Some(pid) => PerfEventScope::OneProcessOneCpu { cpu, pid },
+                None => PerfEventScope::AllProcessesOneCpu { cpu },
+            };
+            match self.attach(perf_type.clone(), config, scope, sample_policy.clone()) {
+                Ok(link_id) => link_ids.push(link_id),
+                Err(e) => {
+                    for link_id in link_ids {
+                        let _ = self.detach(link_id);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(link_ids)
+    }
+
     /// Detaches the program.
     ///
     /// See [PerfEvent::attach].
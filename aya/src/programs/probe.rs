@@ -2,6 +2,7 @@ use libc::pid_t;
 use std::{
     fs::{self, OpenOptions},
     io::{self, Write},
+    os::unix::io::RawFd,
     path::Path,
     process,
 };
@@ -43,24 +44,35 @@ pub(crate) fn attach<T: Link + From<PerfLinkInner>>(
     fn_name: &str,
     offset: u64,
     pid: Option<pid_t>,
+    cookie: Option<u64>,
 ) -> Result<T::Id, ProgramError> {
+    let prog_fd = program_data.fd_or_err()?;
+    let link = T::from(create_link(prog_fd, kind, fn_name, offset, 0, pid, cookie)?);
+    program_data.links.insert(link)
+}
+
+pub(crate) fn create_link(
+    prog_fd: RawFd,
+    kind: ProbeKind,
+    fn_name: &str,
+    offset: u64,
+    ref_ctr_offset: u64,
+    pid: Option<pid_t>,
+    cookie: Option<u64>,
+) -> Result<PerfLinkInner, ProgramError> {
     // https://github.com/torvalds/linux/commit/e12f03d7031a977356e3d7b75a68c2185ff8d155
     // Use debugfs to create probe
     let k_ver = kernel_version().unwrap();
     if k_ver < (4, 17, 0) {
+        if cookie.is_some() {
+            return Err(ProgramError::AttachCookieNotSupported);
+        }
         let (fd, event_alias) = create_as_trace_point(kind, fn_name, offset, pid)?;
-        let link = T::from(perf_attach_debugfs(
-            program_data.fd_or_err()?,
-            fd,
-            kind,
-            event_alias,
-        )?);
-        return program_data.links.insert(link);
+        return perf_attach_debugfs(prog_fd, fd, kind, event_alias);
     };
 
-    let fd = create_as_probe(kind, fn_name, offset, pid)?;
-    let link = T::from(perf_attach(program_data.fd_or_err()?, fd)?);
-    program_data.links.insert(link)
+    let fd = create_as_probe(kind, fn_name, offset, ref_ctr_offset, pid)?;
+    perf_attach(prog_fd, fd, cookie)
 }
 
 pub(crate) fn detach_debug_fs(kind: ProbeKind, event_alias: &str) -> Result<(), ProgramError> {
@@ -82,6 +94,7 @@ fn create_as_probe(
     kind: ProbeKind,
     fn_name: &str,
     offset: u64,
+    ref_ctr_offset: u64,
     pid: Option<pid_t>,
 ) -> Result<i32, ProgramError> {
     use ProbeKind::*;
@@ -105,7 +118,7 @@ fn create_as_probe(
         _ => None,
     };
 
-    let fd = perf_event_open_probe(perf_ty, ret_bit, fn_name, offset, pid).map_err(
+    let fd = perf_event_open_probe(perf_ty, ret_bit, fn_name, offset, ref_ctr_offset, pid).map_err(
         |(_code, io_error)| ProgramError::SyscallError {
             call: "perf_event_open".to_owned(),
             io_error,
@@ -0,0 +1,234 @@
+//! Querying the programs attached to a hook.
+//!
+//! The functions in this module list the programs currently attached to a
+//! cgroup or a network interface, including programs attached by other
+//! processes. They can be used to detect conflicting attachments before
+//! attaching a program, or to find stale attachments left behind by a
+//! previous run.
+use std::os::unix::io::AsRawFd;
+
+pub use crate::generated::bpf_attach_type;
+use crate::{
+    generated::BPF_F_QUERY_EFFECTIVE,
+    programs::{query_with_flags, ProgramError, TcAttachType, TcError, XdpError, XdpFlags},
+    sys::{netlink_find_filters, netlink_xdp_prog_ids},
+    util::ifindex_from_ifname,
+};
+
+/// A program attached to a hook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttachedProgram {
+    /// The id of the program, see [`ProgramInfo::id`](crate::programs::ProgramInfo::id).
+    pub id: u32,
+    /// The flags the program was attached with, for example `BPF_F_ALLOW_MULTI`,
+    /// or `None` if they aren't reported by the kernel.
+    pub attach_flags: Option<u32>,
+}
+
+/// A program attached to an interface as a `cls_bpf` traffic control filter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcFilter {
+    /// The id of the program, if reported by the kernel.
+    pub id: Option<u32>,
+    /// The name of the filter.
+    pub name: Option<String>,
+    /// The priority of the filter.
+    pub priority: u16,
+    /// The handle of the filter.
+    pub handle: u32,
+}
+
+/// Returns the programs attached to a cgroup.
+///
+/// If `effective` is `true`, the programs that will run for the cgroup are
+/// returned, including the ones inherited from the parent cgroups. In that case
+/// the attach flags of the programs aren't reported.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.15. The attach
+/// flags of each program are reported since kernel 6.2; on older kernels all
+/// the programs of a hook share its attach flags, which are returned instead.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use aya::programs::{query::cgroup_programs, CgroupSkbAttachType};
+///
+/// let cgroup = File::open("/sys/fs/cgroup/unified")?;
+/// for prog in cgroup_programs(&cgroup, CgroupSkbAttachType::Egress, false)? {
+///     println!("program {} attached with flags {:?}", prog.id, prog.attach_flags);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn cgroup_programs(
+    cgroup: &impl AsRawFd,
+    attach_type: impl Into<bpf_attach_type>,
+    effective: bool,
+) -> Result<Vec<AttachedProgram>, ProgramError> {
+    let query_flags = if effective { BPF_F_QUERY_EFFECTIVE } else { 0 };
+    let mut attach_flags = Some(0);
+    let (prog_ids, prog_flags) = query_with_flags(
+        cgroup.as_raw_fd(),
+        attach_type.into() as u32,
+        query_flags,
+        &mut attach_flags,
+        !effective,
+    )?;
+    let hook_flags = if effective { None } else { attach_flags };
+
+    Ok(prog_ids
+        .into_iter()
+        .enumerate()
+        .map(|(i, id)| AttachedProgram {
+            id,
+            attach_flags: match prog_flags.as_ref().and_then(|flags| flags.get(i)) {
+                Some(&flags) if flags != 0 => Some(flags),
+                _ => hook_flags,
+            },
+        })
+        .collect())
+}
+
+/// Returns the programs attached to a network interface with tcx, in the order
+/// in which they run.
+///
+/// Programs attached as `cls_bpf` filters aren't included, see
+/// [`tc_programs`].
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 6.6.
+pub fn tcx_programs(
+    interface: &str,
+    attach_type: TcAttachType,
+) -> Result<Vec<AttachedProgram>, ProgramError> {
+    let if_index = if_index(interface)?;
+    let mut attach_flags = Some(0);
+    let (prog_ids, prog_flags) = query_with_flags(
+        if_index as i32,
+        attach_type.tcx_attach_type()?,
+        0,
+        &mut attach_flags,
+        true,
+    )?;
+
+    Ok(prog_ids
+        .into_iter()
+        .enumerate()
+        .map(|(i, id)| AttachedProgram {
+            id,
+            attach_flags: prog_flags.as_ref().and_then(|flags| flags.get(i).copied()),
+        })
+        .collect())
+}
+
+/// Returns the programs attached to a network interface as `cls_bpf` filters
+/// of the `clsact` qdisc.
+///
+/// The filters are returned in the order reported by the kernel, which sorts
+/// them by priority.
+pub fn tc_programs(
+    interface: &str,
+    attach_type: TcAttachType,
+) -> Result<Vec<TcFilter>, ProgramError> {
+    let if_index = if_index(interface)?;
+    let filters = unsafe { netlink_find_filters(if_index as i32, attach_type) }
+        .map_err(|io_error| TcError::NetlinkError { io_error })?;
+
+    Ok(filters
+        .into_iter()
+        .map(|filter| TcFilter {
+            id: filter.prog_id,
+            name: filter.name.map(|name| name.to_string_lossy().into_owned()),
+            priority: filter.priority,
+            handle: filter.handle,
+        })
+        .collect())
+}
+
+/// Returns the XDP programs attached to a network interface.
+///
+/// A program can be attached in each of the skb, driver and hardware modes,
+/// so up to three programs are returned. The mode of each program is returned
+/// along with its id.
+pub fn xdp_programs(interface: &str) -> Result<Vec<(XdpFlags, u32)>, ProgramError> {
+    let if_index = if_index(interface)?;
+    let prog_ids = unsafe { netlink_xdp_prog_ids(if_index as i32) }
+        .map_err(|io_error| XdpError::NetlinkError { io_error })?;
+
+    Ok(prog_ids
+        .into_iter()
+        .map(|(mode, id)| (XdpFlags::from_bits_truncate(mode), id))
+        .collect())
+}
+
+fn if_index(interface: &str) -> Result<u32, ProgramError> {
+    ifindex_from_ifname(interface).map_err(|_| ProgramError::UnknownInterface {
+        name: interface.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, ptr};
+
+    use libc::EINVAL;
+
+    use super::*;
+    use crate::{
+        generated::{bpf_cmd, BPF_F_ALLOW_MULTI},
+        programs::CgroupSkbAttachType,
+        sys::{override_syscall, Syscall},
+    };
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_cgroup_programs_without_prog_attach_flags() {
+        // Kernels older than 6.2 don't know about `prog_attach_flags`
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_QUERY,
+                attr,
+            } => {
+                let query = unsafe { &attr.query };
+                if query.prog_attach_flags != 0 {
+                    return Err((-1, io::Error::from_raw_os_error(EINVAL)));
+                }
+                let prog_ids = query.prog_ids as *mut u32;
+                // the kernel writes the number of programs and the attach
+                // flags of the hook back to `attr`
+                unsafe {
+                    *prog_ids = 1;
+                    *prog_ids.add(1) = 2;
+                    *(ptr::addr_of!(query.prog_cnt) as *mut u32) = 2;
+                    *(ptr::addr_of!(query.attach_flags) as *mut u32) = BPF_F_ALLOW_MULTI;
+                }
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        let programs = cgroup_programs(&42, CgroupSkbAttachType::Egress, false).unwrap();
+        assert_eq!(
+            programs,
+            [
+                AttachedProgram {
+                    id: 1,
+                    attach_flags: Some(BPF_F_ALLOW_MULTI),
+                },
+                AttachedProgram {
+                    id: 2,
+                    attach_flags: Some(BPF_F_ALLOW_MULTI),
+                },
+            ]
+        );
+
+        let programs = cgroup_programs(&42, CgroupSkbAttachType::Egress, true).unwrap();
+        assert!(programs.iter().all(|prog| prog.attach_flags.is_none()));
+    }
+}
@@ -4,7 +4,9 @@ use std::{os::unix::io::AsRawFd, path::Path};
 
 use crate::{
     generated::{
-        bpf_attach_type::{BPF_SK_SKB_STREAM_PARSER, BPF_SK_SKB_STREAM_VERDICT},
+        bpf_attach_type::{
+            BPF_SK_SKB_STREAM_PARSER, BPF_SK_SKB_STREAM_VERDICT, BPF_SK_SKB_VERDICT,
+        },
         bpf_prog_type::BPF_PROG_TYPE_SK_SKB,
     },
     maps::sock::SockMapFd,
@@ -16,12 +18,16 @@ use crate::{
 };
 
 /// The kind of [`SkSkb`] program.
+#[non_exhaustive]
 #[derive(Copy, Clone, Debug)]
 pub enum SkSkbKind {
     /// A Stream Parser
     StreamParser,
     /// A Stream Verdict
     StreamVerdict,
+    /// A Verdict, which unlike [`SkSkbKind::StreamVerdict`] also handles UDP
+    /// sockets and doesn't need a stream parser. Requires kernel 5.13.
+    Verdict,
 }
 
 /// A program used to intercept ingress socket buffers.
@@ -77,6 +83,7 @@ impl SkSkb {
         let attach_type = match self.kind {
             SkSkbKind::StreamParser => BPF_SK_SKB_STREAM_PARSER,
             SkSkbKind::StreamVerdict => BPF_SK_SKB_STREAM_VERDICT,
+            SkSkbKind::Verdict => BPF_SK_SKB_VERDICT,
         };
         bpf_prog_attach(prog_fd, map_fd, attach_type).map_err(|(_, io_error)| {
             ProgramError::SyscallError {
@@ -1,13 +1,16 @@
 //! Socket option programs.
-use std::os::unix::io::AsRawFd;
+use std::{
+    mem,
+    os::unix::io::{AsRawFd, RawFd},
+};
 
 use crate::{
     generated::{bpf_attach_type::BPF_CGROUP_SOCK_OPS, bpf_prog_type::BPF_PROG_TYPE_SOCK_OPS},
     programs::{
-        define_link_wrapper, load_program, ProgAttachLink, ProgAttachLinkId, ProgramData,
-        ProgramError,
+        define_link_wrapper, impl_try_from_fd_link, load_program, FdLink, Link, ProgAttachLink,
+        ProgramData, ProgramError,
     },
-    sys::bpf_prog_attach,
+    sys::{bpf_link_create, bpf_link_update, bpf_prog_attach, kernel_version},
 };
 
 /// A program used to work with sockets.
@@ -43,6 +46,7 @@ use crate::{
 /// prog.load()?;
 /// prog.attach(file)?;
 /// # Ok::<(), Error>(())
+/// ```
 #[derive(Debug)]
 #[doc(alias = "BPF_PROG_TYPE_SOCK_OPS")]
 pub struct SockOps {
@@ -61,18 +65,77 @@ impl SockOps {
     pub fn attach<T: AsRawFd>(&mut self, cgroup: T) -> Result<SockOpsLinkId, ProgramError> {
         let prog_fd = self.data.fd_or_err()?;
         let cgroup_fd = cgroup.as_raw_fd();
+        let k_ver = kernel_version().unwrap();
+        if k_ver >= (5, 7, 0) {
+            let link_fd = bpf_link_create(prog_fd, cgroup_fd, BPF_CGROUP_SOCK_OPS, None, 0)
+                .map_err(|(_, io_error)| ProgramError::SyscallError {
+                    call: "bpf_link_create".to_owned(),
+                    io_error,
+                })? as RawFd;
+            self.data
+                .links
+                .insert(SockOpsLink::new(SockOpsLinkInner::Fd(FdLink::new(link_fd))))
+        } else {
+            bpf_prog_attach(prog_fd, cgroup_fd, BPF_CGROUP_SOCK_OPS).map_err(|(_, io_error)| {
+                ProgramError::SyscallError {
+                    call: "bpf_prog_attach".to_owned(),
+                    io_error,
+                }
+            })?;
+
+            self.data
+                .links
+                .insert(SockOpsLink::new(SockOpsLinkInner::ProgAttach(
+                    ProgAttachLink::new(prog_fd, cgroup_fd, BPF_CGROUP_SOCK_OPS),
+                )))
+        }
+    }
 
-        bpf_prog_attach(prog_fd, cgroup_fd, BPF_CGROUP_SOCK_OPS).map_err(|(_, io_error)| {
-            ProgramError::SyscallError {
-                call: "bpf_prog_attach".to_owned(),
-                io_error,
+    /// Atomically replaces the program referenced by the provided link.
+    ///
+    /// The cgroup keeps running the old program until the new one is in
+    /// place. Ownership of the link will transfer to this program.
+    pub fn attach_to_link(&mut self, link: SockOpsLink) -> Result<SockOpsLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        match link.inner() {
+            SockOpsLinkInner::Fd(fd_link) => {
+                let link_fd = fd_link.fd;
+                bpf_link_update(link_fd, prog_fd, None, 0).map_err(|(_, io_error)| {
+                    ProgramError::SyscallError {
+                        call: "bpf_link_update".to_owned(),
+                        io_error,
+                    }
+                })?;
+                // dispose of link and avoid detach on drop
+                mem::forget(link);
+                self.data
+                    .links
+                    .insert(SockOpsLink::new(SockOpsLinkInner::Fd(FdLink::new(link_fd))))
             }
-        })?;
-        self.data.links.insert(SockOpsLink::new(ProgAttachLink::new(
-            prog_fd,
-            cgroup_fd,
-            BPF_CGROUP_SOCK_OPS,
-        )))
+            SockOpsLinkInner::ProgAttach(prog_attach_link) => {
+                let target_fd = prog_attach_link.target_fd;
+                // attaching without BPF_F_ALLOW_MULTI replaces the program
+                // attached to the cgroup
+                bpf_prog_attach(prog_fd, target_fd, BPF_CGROUP_SOCK_OPS).map_err(
+                    |(_, io_error)| ProgramError::SyscallError {
+                        call: "bpf_prog_attach".to_owned(),
+                        io_error,
+                    },
+                )?;
+                // dispose of link and avoid detach on drop, the new link takes
+                // over the cgroup fd
+                mem::forget(link);
+                self.data
+                    .links
+                    .insert(SockOpsLink::new(SockOpsLinkInner::ProgAttach(
+                        ProgAttachLink {
+                            prog_fd,
+                            target_fd,
+                            attach_type: BPF_CGROUP_SOCK_OPS,
+                        },
+                    )))
+            }
+        }
     }
 
     /// Detaches the program.
@@ -91,11 +154,175 @@ impl SockOps {
     }
 }
 
+#[derive(Debug, Hash, Eq, PartialEq)]
+enum SockOpsLinkIdInner {
+    Fd(<FdLink as Link>::Id),
+    ProgAttach(<ProgAttachLink as Link>::Id),
+}
+
+#[derive(Debug)]
+enum SockOpsLinkInner {
+    Fd(FdLink),
+    ProgAttach(ProgAttachLink),
+}
+
+impl Link for SockOpsLinkInner {
+    type Id = SockOpsLinkIdInner;
+
+    fn id(&self) -> Self::Id {
+        match self {
+            SockOpsLinkInner::Fd(fd) => SockOpsLinkIdInner::Fd(fd.id()),
+            SockOpsLinkInner::ProgAttach(p) => SockOpsLinkIdInner::ProgAttach(p.id()),
+        }
+    }
+
+    fn detach(self) -> Result<(), ProgramError> {
+        match self {
+            SockOpsLinkInner::Fd(fd) => fd.detach(),
+            SockOpsLinkInner::ProgAttach(p) => p.detach(),
+        }
+    }
+}
+
 define_link_wrapper!(
     /// The link used by [SockOps] programs.
     SockOpsLink,
     /// The type returned by [SockOps::attach]. Can be passed to [SockOps::detach].
     SockOpsLinkId,
-    ProgAttachLink,
-    ProgAttachLinkId
+    SockOpsLinkInner,
+    SockOpsLinkIdInner
 );
+
+impl_try_from_fd_link!(SockOpsLink, SockOpsLinkInner, BPF_LINK_TYPE_CGROUP);
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, env, ffi::CString, fs::File, io, os::unix::io::IntoRawFd};
+
+    use libc::EINVAL;
+    use object::SectionIndex;
+
+    use super::*;
+    use crate::{
+        generated::bpf_cmd,
+        obj::{self, KernelVersion, ProgramSection},
+        programs::links::ProgAttachLink,
+        sys::{override_syscall, Syscall},
+    };
+
+    thread_local! {
+        static CGROUP_FD: Cell<RawFd> = const { Cell::new(-1) };
+        static LINK_FD: Cell<RawFd> = const { Cell::new(-1) };
+    }
+
+    fn tmp_fd(name: &str) -> RawFd {
+        File::create(env::temp_dir().join(name))
+            .expect("unable to create file in tmpdir")
+            .into_raw_fd()
+    }
+
+    fn new_sock_ops(fd: RawFd) -> SockOps {
+        let obj = obj::Program {
+            license: CString::new("GPL").unwrap(),
+            kernel_version: KernelVersion::Any,
+            section: ProgramSection::SockOps {
+                name: "sock_ops".to_owned(),
+            },
+            function: obj::Function {
+                address: 0,
+                name: "sock_ops".to_owned(),
+                section_index: SectionIndex(0),
+                section_offset: 0,
+                instructions: Vec::new(),
+                func_info: Default::default(),
+                line_info: Default::default(),
+                func_info_rec_size: 0,
+                line_info_rec_size: 0,
+            },
+        };
+        let mut data = ProgramData::new(Some("sock_ops".to_owned()), obj, None, 0, None);
+        data.fd = Some(fd);
+        SockOps { data }
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_attach_to_fd_link() {
+        let cgroup = File::create(env::temp_dir().join("sock_ops_cgroup")).unwrap();
+        CGROUP_FD.with(|fd| fd.set(cgroup.as_raw_fd()));
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_LINK_CREATE,
+                attr,
+            } => {
+                let link_create = unsafe { &attr.link_create };
+                assert_eq!(link_create.prog_fd, 42);
+                assert_eq!(
+                    unsafe { link_create.__bindgen_anon_1.target_fd } as RawFd,
+                    CGROUP_FD.with(|fd| fd.get())
+                );
+                assert_eq!(link_create.attach_type, BPF_CGROUP_SOCK_OPS as u32);
+                let link_fd = tmp_fd("sock_ops_link");
+                LINK_FD.with(|fd| fd.set(link_fd));
+                Ok(link_fd as i64)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_LINK_UPDATE,
+                attr,
+            } => {
+                let link_update = unsafe { &attr.link_update };
+                assert_eq!(link_update.link_fd as RawFd, LINK_FD.with(|fd| fd.get()));
+                assert_eq!(link_update.new_prog_fd, 43);
+                assert_eq!(link_update.flags, 0);
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        let mut prog = new_sock_ops(42);
+        let link_id = prog.attach(cgroup).unwrap();
+        let link = prog.take_link(link_id).unwrap();
+
+        let mut new_prog = new_sock_ops(43);
+        let link_id = new_prog.attach_to_link(link).unwrap();
+        new_prog.detach(link_id).unwrap();
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_attach_to_prog_attach_link() {
+        let cgroup_fd = tmp_fd("sock_ops_cgroup");
+        CGROUP_FD.with(|fd| fd.set(cgroup_fd));
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_ATTACH | bpf_cmd::BPF_PROG_DETACH,
+                attr,
+            } => {
+                // the new program replaces the old one, and is the one
+                // detached afterwards
+                let attach = unsafe { &attr.__bindgen_anon_5 };
+                assert_eq!(attach.attach_bpf_fd, 43);
+                assert_eq!(attach.target_fd as RawFd, CGROUP_FD.with(|fd| fd.get()));
+                assert_eq!(attach.attach_type, BPF_CGROUP_SOCK_OPS as u32);
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        let link = SockOpsLink::new(SockOpsLinkInner::ProgAttach(ProgAttachLink {
+            prog_fd: 42,
+            target_fd: cgroup_fd,
+            attach_type: BPF_CGROUP_SOCK_OPS,
+        }));
+
+        let mut prog = new_sock_ops(43);
+        let link_id = prog.attach_to_link(link).unwrap();
+        prog.detach(link_id).unwrap();
+    }
+}
@@ -1,5 +1,5 @@
 //! Socket filter programs.
-use libc::{setsockopt, SOL_SOCKET};
+use libc::{getsockopt, setsockopt, EACCES, SOL_SOCKET, SO_ATTACH_FILTER};
 use std::{
     io, mem,
     os::unix::prelude::{AsRawFd, RawFd},
@@ -21,6 +21,39 @@ pub enum SocketFilterError {
         #[source]
         io_error: io::Error,
     },
+
+    /// Setting the `SO_DETACH_BPF` socket option failed.
+    #[error("setsockopt SO_DETACH_BPF failed")]
+    SoDetachBpfError {
+        /// original [`io::Error`]
+        #[source]
+        io_error: io::Error,
+    },
+
+    /// Getting the `SO_GET_FILTER` socket option failed.
+    #[error("getsockopt SO_GET_FILTER failed")]
+    SoGetFilterError {
+        /// original [`io::Error`]
+        #[source]
+        io_error: io::Error,
+    },
+}
+
+// SO_GET_FILTER is an alias of SO_ATTACH_FILTER, whose value depends on the
+// architecture
+const SO_GET_FILTER: i32 = SO_ATTACH_FILTER;
+
+/// The kind of filter attached to a socket, see [`SocketFilter::attached_filter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachedSocketFilter {
+    /// An eBPF program, attached with `SO_ATTACH_BPF`.
+    Ebpf,
+    /// A classic BPF program with the given number of instructions, attached
+    /// with `SO_ATTACH_FILTER`.
+    Classic {
+        /// The number of instructions of the program.
+        instructions: u32,
+    },
 }
 
 /// A program used to inspect and filter incoming packets on a socket.
@@ -71,6 +104,10 @@ impl SocketFilter {
 
     /// Attaches the filter on the given socket.
     ///
+    /// The socket can be any socket, including ones owned by code that isn't
+    /// aware of aya. Attaching replaces any filter already attached to the
+    /// socket.
+    ///
     /// The returned value can be used to detach from the socket, see [SocketFilter::detach].
     pub fn attach<T: AsRawFd>(&mut self, socket: T) -> Result<SocketFilterLinkId, ProgramError> {
         let prog_fd = self.data.fd_or_err()?;
@@ -102,6 +139,46 @@ impl SocketFilter {
         self.data.links.remove(link_id)
     }
 
+    /// Detaches the filter attached to `socket`, whether or not it was attached
+    /// by this program.
+    ///
+    /// Fails with `ENOENT` if no filter is attached to the socket.
+    pub fn detach_from_socket<T: AsRawFd>(socket: T) -> Result<(), ProgramError> {
+        detach_socket_filter(socket.as_raw_fd())
+            .map_err(|io_error| SocketFilterError::SoDetachBpfError { io_error }.into())
+    }
+
+    /// Returns the filter currently attached to `socket`, if any.
+    pub fn attached_filter<T: AsRawFd>(
+        socket: T,
+    ) -> Result<Option<AttachedSocketFilter>, ProgramError> {
+        // with a zero length the kernel returns the number of instructions of
+        // the classic filter attached to the socket, and EACCES for eBPF
+        // programs which can't be dumped
+        let mut len = 0u32;
+        let ret = unsafe {
+            getsockopt(
+                socket.as_raw_fd(),
+                SOL_SOCKET,
+                SO_GET_FILTER,
+                std::ptr::null_mut(),
+                &mut len as *mut _,
+            )
+        };
+        if ret < 0 {
+            let io_error = io::Error::last_os_error();
+            if io_error.raw_os_error() == Some(EACCES) {
+                return Ok(Some(AttachedSocketFilter::Ebpf));
+            }
+            return Err(SocketFilterError::SoGetFilterError { io_error }.into());
+        }
+
+        Ok(match len {
+            0 => None,
+            instructions => Some(AttachedSocketFilter::Classic { instructions }),
+        })
+    }
+
     /// Takes ownership of the link referenced by the provided link_id.
     ///
     /// The link will be detached on `Drop` and the caller is now responsible
@@ -133,15 +210,83 @@ impl Link for SocketFilterLink {
     }
 
     fn detach(self) -> Result<(), ProgramError> {
-        unsafe {
+        detach_socket_filter(self.socket)
+            .map_err(|io_error| SocketFilterError::SoDetachBpfError { io_error }.into())
+    }
+}
+
+fn detach_socket_filter(socket: RawFd) -> Result<(), io::Error> {
+    // SO_DETACH_BPF ignores its value and detaches whatever filter is
+    // attached to the socket, but the kernel still requires an int
+    let value = 0i32;
+    let ret = unsafe {
+        setsockopt(
+            socket,
+            SOL_SOCKET,
+            SO_DETACH_BPF as i32,
+            &value as *const _ as *const _,
+            mem::size_of::<i32>() as u32,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, mem, net::UdpSocket, os::unix::io::AsRawFd};
+
+    use libc::{setsockopt, sock_filter, sock_fprog, ENOENT, SOL_SOCKET, SO_ATTACH_FILTER};
+
+    use super::{AttachedSocketFilter, SocketFilter, SocketFilterError};
+    use crate::programs::ProgramError;
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_attached_filter_and_detach() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        assert_eq!(
+            SocketFilter::attached_filter(socket.as_raw_fd()).unwrap(),
+            None
+        );
+
+        // ret #0xffffffff
+        let mut insns = [sock_filter {
+            code: 0x06,
+            jt: 0,
+            jf: 0,
+            k: u32::MAX,
+        }];
+        let prog = sock_fprog {
+            len: insns.len() as u16,
+            filter: insns.as_mut_ptr(),
+        };
+        let ret = unsafe {
             setsockopt(
-                self.socket,
+                socket.as_raw_fd(),
                 SOL_SOCKET,
-                SO_DETACH_BPF as i32,
-                &self.prog_fd as *const _ as *const _,
-                mem::size_of::<RawFd>() as u32,
-            );
-        }
-        Ok(())
+                SO_ATTACH_FILTER,
+                &prog as *const _ as *const _,
+                mem::size_of::<sock_fprog>() as u32,
+            )
+        };
+        assert_eq!(ret, 0, "{}", io::Error::last_os_error());
+        assert_eq!(
+            SocketFilter::attached_filter(socket.as_raw_fd()).unwrap(),
+            Some(AttachedSocketFilter::Classic { instructions: 1 })
+        );
+
+        SocketFilter::detach_from_socket(socket.as_raw_fd()).unwrap();
+        assert_eq!(
+            SocketFilter::attached_filter(socket.as_raw_fd()).unwrap(),
+            None
+        );
+        assert!(matches!(
+            SocketFilter::detach_from_socket(socket.as_raw_fd()),
+            Err(ProgramError::SocketFilterError(SocketFilterError::SoDetachBpfError { io_error }))
+                if io_error.raw_os_error() == Some(ENOENT)
+        ));
     }
 }
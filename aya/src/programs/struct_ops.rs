@@ -0,0 +1,421 @@
+//! Struct ops programs.
+
+use std::{
+    ffi::CString,
+    os::unix::io::{AsRawFd, RawFd},
+};
+
+use libc::close;
+use thiserror::Error;
+
+use crate::{
+    generated::bpf_prog_type::BPF_PROG_TYPE_STRUCT_OPS,
+    obj::btf::{Btf, BtfKind, BtfType},
+    programs::{load_program, FdLink, ProgramData, ProgramError},
+    sys::{bpf_create_struct_ops_map, bpf_map_delete_elem, bpf_map_update_elem_ptr},
+};
+
+/// The type returned when loading or registering struct_ops fails.
+#[derive(Debug, Error)]
+pub enum StructOpsError {
+    /// The struct doesn't have a member with the given name.
+    #[error("`{struct_name}` has no member named `{member_name}`")]
+    MemberNotFound {
+        /// The name of the struct.
+        struct_name: String,
+        /// The name of the member.
+        member_name: String,
+    },
+
+    /// A program was assigned to a member that isn't a function pointer.
+    #[error("member `{member_name}` of `{struct_name}` is not a function pointer")]
+    NotFunctionPointer {
+        /// The name of the struct.
+        struct_name: String,
+        /// The name of the member.
+        member_name: String,
+    },
+
+    /// The data written to a member doesn't fit in it.
+    #[error("member `{member_name}` is {size} bytes, got {len} bytes of data")]
+    InvalidDataSize {
+        /// The name of the member.
+        member_name: String,
+        /// The size of the member.
+        size: usize,
+        /// The size of the data.
+        len: usize,
+    },
+}
+
+/// A program that implements a member of a kernel struct_ops type.
+///
+/// Struct ops programs let eBPF provide the functions of kernel operation
+/// tables such as `tcp_congestion_ops`. Each program implements one member of
+/// the table. Programs are loaded individually, then put in a [`StructOpsMap`]
+/// which registers the whole table with the kernel.
+///
+/// The tables declared in the `.struct_ops` section of the object file are not
+/// created, the table must be filled from userspace instead, as shown below.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 5.6.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[derive(thiserror::Error, Debug)]
+/// # enum Error {
+/// #     #[error(transparent)]
+/// #     BtfError(#[from] aya::BtfError),
+/// #     #[error(transparent)]
+/// #     Program(#[from] aya::programs::ProgramError),
+/// #     #[error(transparent)]
+/// #     Bpf(#[from] aya::BpfError),
+/// # }
+/// # let mut bpf = aya::Bpf::load_file("ebpf_programs.o")?;
+/// use aya::{Btf, programs::{StructOps, StructOpsMap}};
+///
+/// let btf = Btf::from_sys_fs()?;
+/// let mut ops = StructOpsMap::new("tcp_congestion_ops", &btf)?;
+/// for member in ["ssthresh", "cong_avoid", "undo_cwnd"] {
+///     let program: &mut StructOps = bpf.program_mut(member).unwrap().try_into()?;
+///     program.load("tcp_congestion_ops", member, &btf)?;
+///     ops.set_program(member, program)?;
+/// }
+/// ops.set_data("name", b"aya_cc\0")?;
+/// // the congestion control algorithm is available until `ops` is dropped
+/// ops.register()?;
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_PROG_TYPE_STRUCT_OPS")]
+pub struct StructOps {
+    // Struct ops programs are never attached, they are registered through a
+    // `StructOpsMap`.
+    pub(crate) data: ProgramData<FdLink>,
+}
+
+impl StructOps {
+    /// Loads the program inside the kernel.
+    ///
+    /// # Arguments
+    ///
+    /// * `struct_name` - name of the kernel struct_ops type, for example
+    ///   `tcp_congestion_ops`
+    /// * `member_name` - name of the member of `struct_name` that the program
+    ///   implements
+    pub fn load(
+        &mut self,
+        struct_name: &str,
+        member_name: &str,
+        btf: &Btf,
+    ) -> Result<(), ProgramError> {
+        let struct_id = btf.id_by_type_name_kind(struct_name, BtfKind::Struct)?;
+        let member = find_member(btf, struct_id, struct_name, member_name)?;
+        self.data.attach_btf_id = Some(struct_id);
+        self.data.raw_attach_type = Some(member.index);
+        load_program(BPF_PROG_TYPE_STRUCT_OPS, &mut self.data)
+    }
+}
+
+/// A kernel struct_ops table backed by a `BPF_MAP_TYPE_STRUCT_OPS` map.
+///
+/// The table is filled with [`StructOpsMap::set_program`] and
+/// [`StructOpsMap::set_data`], then handed to the kernel with
+/// [`StructOpsMap::register`]. The table is unregistered when the map is
+/// dropped.
+///
+/// See [`StructOps`] for an example.
+#[derive(Debug)]
+#[doc(alias = "BPF_MAP_TYPE_STRUCT_OPS")]
+pub struct StructOpsMap {
+    struct_name: String,
+    struct_id: u32,
+    btf: Btf,
+    data_offset: usize,
+    value: Vec<u8>,
+    fd: RawFd,
+    registered: bool,
+}
+
+impl StructOpsMap {
+    /// Creates the map for the kernel struct_ops type `struct_name`.
+    pub fn new(struct_name: &str, btf: &Btf) -> Result<StructOpsMap, ProgramError> {
+        let struct_id = btf.id_by_type_name_kind(struct_name, BtfKind::Struct)?;
+
+        // the kernel wraps each table in `struct bpf_struct_ops_<name>`, with the
+        // table itself stored in the `data` member
+        let value_name = format!("bpf_struct_ops_{struct_name}");
+        let value_id = btf.id_by_type_name_kind(&value_name, BtfKind::Struct)?;
+        let value_size = btf.type_size(value_id)?;
+        let data_offset = find_member(btf, value_id, &value_name, "data")?.offset;
+
+        let name = CString::new(struct_name).map_err(|_| ProgramError::InvalidName {
+            name: struct_name.to_owned(),
+        })?;
+        let fd = bpf_create_struct_ops_map(&name, value_size as u32, value_id).map_err(
+            |(_, io_error)| ProgramError::SyscallError {
+                call: "bpf_map_create".to_owned(),
+                io_error,
+            },
+        )? as RawFd;
+
+        Ok(StructOpsMap {
+            struct_name: struct_name.to_owned(),
+            struct_id,
+            btf: btf.clone(),
+            data_offset,
+            value: vec![0; value_size],
+            fd,
+            registered: false,
+        })
+    }
+
+    /// Sets the function pointer `member_name` to `program`.
+    ///
+    /// The program must have been loaded for the same member with
+    /// [`StructOps::load`].
+    pub fn set_program(
+        &mut self,
+        member_name: &str,
+        program: &StructOps,
+    ) -> Result<(), ProgramError> {
+        let prog_fd = program.data.fd_or_err()?;
+        let member = find_member(&self.btf, self.struct_id, &self.struct_name, member_name)?;
+        let ty = self
+            .btf
+            .type_by_id(self.btf.resolve_type(member.btf_type)?)?;
+        let is_func_ptr = match ty {
+            BtfType::Ptr(_) => {
+                let pointee = self.btf.resolve_type(ty.btf_type().unwrap_or_default())?;
+                matches!(self.btf.type_by_id(pointee)?, BtfType::FuncProto(_))
+            }
+            _ => false,
+        };
+        if !is_func_ptr {
+            return Err(StructOpsError::NotFunctionPointer {
+                struct_name: self.struct_name.clone(),
+                member_name: member_name.to_owned(),
+            }
+            .into());
+        }
+
+        // the kernel expects the program fd in place of the function pointer
+        let start = self.data_offset + member.offset;
+        let bytes = (prog_fd as usize).to_ne_bytes();
+        self.value[start..start + bytes.len()].copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    /// Sets the non-function member `member_name` to `data`.
+    ///
+    /// `data` is copied to the start of the member, and must not be larger
+    /// than it. This is used for example to set the `name` of a
+    /// `tcp_congestion_ops`.
+    pub fn set_data(&mut self, member_name: &str, data: &[u8]) -> Result<(), ProgramError> {
+        let member = find_member(&self.btf, self.struct_id, &self.struct_name, member_name)?;
+        let size = self.btf.type_size(member.btf_type)?;
+        if data.len() > size {
+            return Err(StructOpsError::InvalidDataSize {
+                member_name: member_name.to_owned(),
+                size,
+                len: data.len(),
+            }
+            .into());
+        }
+
+        let start = self.data_offset + member.offset;
+        self.value[start..start + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Registers the table with the kernel.
+    ///
+    /// Once registered, the kernel starts using the table, for example by
+    /// making a congestion control algorithm available to sockets.
+    pub fn register(&mut self) -> Result<(), ProgramError> {
+        let key = 0u32;
+        bpf_map_update_elem_ptr(self.fd, &key, self.value.as_mut_ptr(), 0).map_err(
+            |(_, io_error)| ProgramError::SyscallError {
+                call: "bpf_map_update_elem".to_owned(),
+                io_error,
+            },
+        )?;
+        self.registered = true;
+        Ok(())
+    }
+
+    /// Unregisters the table from the kernel.
+    ///
+    /// This is done automatically when the map is dropped.
+    pub fn unregister(&mut self) -> Result<(), ProgramError> {
+        if !self.registered {
+            return Ok(());
+        }
+        bpf_map_delete_elem(self.fd, &0u32).map_err(|(_, io_error)| {
+            ProgramError::SyscallError {
+                call: "bpf_map_delete_elem".to_owned(),
+                io_error,
+            }
+        })?;
+        self.registered = false;
+        Ok(())
+    }
+}
+
+impl AsRawFd for StructOpsMap {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for StructOpsMap {
+    fn drop(&mut self) {
+        let _ = self.unregister();
+        unsafe { close(self.fd) };
+    }
+}
+
+struct StructMember {
+    index: u32,
+    offset: usize,
+    btf_type: u32,
+}
+
+fn find_member(
+    btf: &Btf,
+    struct_id: u32,
+    struct_name: &str,
+    member_name: &str,
+) -> Result<StructMember, ProgramError> {
+    let ty = btf.type_by_id(struct_id)?;
+    let members = ty.members().into_iter().flatten();
+    for (index, member) in members.enumerate() {
+        if btf.string_at(member.name_offset())? == member_name {
+            return Ok(StructMember {
+                index: index as u32,
+                // struct_ops members are never bitfields
+                offset: ty.member_bit_offset(member).unwrap_or_default() / 8,
+                btf_type: member.btf_type(),
+            });
+        }
+    }
+
+    Err(StructOpsError::MemberNotFound {
+        struct_name: struct_name.to_owned(),
+        member_name: member_name.to_owned(),
+    }
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::EINVAL;
+
+    use super::*;
+    use crate::{
+        generated::{bpf_cmd, bpf_map_type::BPF_MAP_TYPE_STRUCT_OPS},
+        obj::btf::{BtfMember, FuncProto, Int, IntEncoding, Ptr, Struct},
+        sys::{override_syscall, Syscall},
+    };
+
+    // struct test_ops { int flags; void (*init)(void); int name; };
+    // struct bpf_struct_ops_test_ops { int refcnt; int state; struct test_ops data; };
+    fn test_btf() -> Btf {
+        let mut btf = Btf::new();
+        let int_name = btf.add_string("int".to_string());
+        let int = btf.add_type(BtfType::Int(Int::new(int_name, 4, IntEncoding::Signed, 0)));
+        let proto = btf.add_type(BtfType::FuncProto(FuncProto::new(vec![], 0)));
+        let func_ptr = btf.add_type(BtfType::Ptr(Ptr::new(0, proto)));
+
+        let members = vec![
+            BtfMember::new(btf.add_string("flags".to_string()), int, 0),
+            BtfMember::new(btf.add_string("init".to_string()), func_ptr, 64),
+            BtfMember::new(btf.add_string("name".to_string()), int, 128),
+        ];
+        let ops_name = btf.add_string("test_ops".to_string());
+        let ops = btf.add_type(BtfType::Struct(Struct::new(ops_name, members, 24)));
+
+        let members = vec![
+            BtfMember::new(btf.add_string("refcnt".to_string()), int, 0),
+            BtfMember::new(btf.add_string("state".to_string()), int, 32),
+            BtfMember::new(btf.add_string("data".to_string()), ops, 64),
+        ];
+        let value_name = btf.add_string("bpf_struct_ops_test_ops".to_string());
+        btf.add_type(BtfType::Struct(Struct::new(value_name, members, 32)));
+        btf
+    }
+
+    #[test]
+    fn test_find_member() {
+        let btf = test_btf();
+        let struct_id = btf
+            .id_by_type_name_kind("test_ops", BtfKind::Struct)
+            .unwrap();
+
+        let member = find_member(&btf, struct_id, "test_ops", "name").unwrap();
+        assert_eq!(member.index, 2);
+        assert_eq!(member.offset, 16);
+        assert!(matches!(
+            find_member(&btf, struct_id, "test_ops", "missing"),
+            Err(ProgramError::StructOpsError(
+                StructOpsError::MemberNotFound { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_struct_ops_map() {
+        let btf = test_btf();
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_CREATE,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_1 };
+                assert_eq!(u.map_type, BPF_MAP_TYPE_STRUCT_OPS as u32);
+                assert_eq!(u.value_size, 32);
+                Ok(42)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_2 };
+                let value = unsafe {
+                    std::slice::from_raw_parts(u.__bindgen_anon_1.value as *const u8, 32)
+                };
+                // the name member sits 16 bytes into `data`, at offset 8
+                assert_eq!(&value[24..28], b"ops\0");
+                Ok(0)
+            }
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_DELETE_ELEM,
+                ..
+            } => Ok(0),
+            _ => Err((-1, std::io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        let mut map = StructOpsMap::new("test_ops", &btf).unwrap();
+        assert!(matches!(
+            map.set_data("name", b"too long"),
+            Err(ProgramError::StructOpsError(
+                StructOpsError::InvalidDataSize {
+                    size: 4,
+                    len: 8,
+                    ..
+                }
+            ))
+        ));
+        map.set_data("name", b"ops\0").unwrap();
+        map.register().unwrap();
+        map.unregister().unwrap();
+        assert!(!map.registered);
+    }
+}
@@ -4,6 +4,7 @@ use thiserror::Error;
 use std::{
     ffi::{CStr, CString},
     io,
+    os::unix::io::RawFd,
     path::Path,
 };
 
@@ -11,10 +12,12 @@ use crate::{
     generated::{
         bpf_prog_type::BPF_PROG_TYPE_SCHED_CLS, TC_H_CLSACT, TC_H_MIN_EGRESS, TC_H_MIN_INGRESS,
     },
-    programs::{define_link_wrapper, load_program, Link, ProgramData, ProgramError},
+    programs::{
+        define_link_wrapper, load_program, FdLink, Link, LinkError, ProgramData, ProgramError,
+    },
     sys::{
-        netlink_find_filter_with_name, netlink_qdisc_add_clsact, netlink_qdisc_attach,
-        netlink_qdisc_detach,
+        bpf_link_create_tcx, netlink_find_filter_with_name, netlink_qdisc_add_clsact,
+        netlink_qdisc_attach, netlink_qdisc_detach,
     },
     util::{ifindex_from_ifname, tc_handler_make},
 };
@@ -30,6 +33,15 @@ pub enum TcAttachType {
     Custom(u32),
 }
 
+// The tcx uapi was added in kernel 6.6 and is not in the generated bindings
+// yet, see include/uapi/linux/bpf.h.
+const BPF_TCX_INGRESS: u32 = 46;
+const BPF_TCX_EGRESS: u32 = 47;
+const BPF_F_BEFORE: u32 = 1 << 3;
+const BPF_F_AFTER: u32 = 1 << 4;
+const BPF_F_ID: u32 = 1 << 5;
+const BPF_F_LINK: u32 = 1 << 13;
+
 /// A network traffic control classifier.
 ///
 /// [`SchedClassifier`] programs can be used to inspect, filter or redirect
@@ -88,6 +100,9 @@ pub enum TcError {
     /// the clsact qdisc is already attached
     #[error("the clsact qdisc is already attached")]
     AlreadyAttached,
+    /// tcx links can only be attached to ingress or egress
+    #[error("tcx links can only be attached to ingress or egress")]
+    InvalidTcxAttachType,
 }
 
 impl TcAttachType {
@@ -98,6 +113,49 @@ impl TcAttachType {
             TcAttachType::Egress => tc_handler_make(TC_H_CLSACT, TC_H_MIN_EGRESS),
         }
     }
+
+    pub(crate) fn tcx_attach_type(&self) -> Result<u32, TcError> {
+        match self {
+            TcAttachType::Ingress => Ok(BPF_TCX_INGRESS),
+            TcAttachType::Egress => Ok(BPF_TCX_EGRESS),
+            TcAttachType::Custom(_) => Err(TcError::InvalidTcxAttachType),
+        }
+    }
+}
+
+/// Position of a program attached with [`SchedClassifier::attach_tcx`],
+/// relative to the programs already attached to the same hook.
+///
+/// Programs and links are referred to by their kernel ids, see
+/// [`ProgramInfo::id`](crate::programs::ProgramInfo::id).
+#[derive(Debug, Default, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum TcxOrder {
+    /// Run before all the attached programs.
+    First,
+    /// Run after all the attached programs.
+    #[default]
+    Last,
+    /// Run right before the program with the given id.
+    BeforeProgram(u32),
+    /// Run right after the program with the given id.
+    AfterProgram(u32),
+    /// Run right before the program attached by the link with the given id.
+    BeforeLink(u32),
+    /// Run right after the program attached by the link with the given id.
+    AfterLink(u32),
+}
+
+impl TcxOrder {
+    fn flags_and_relative_id(&self) -> (u32, u32) {
+        match *self {
+            TcxOrder::First => (BPF_F_BEFORE, 0),
+            TcxOrder::Last => (BPF_F_AFTER, 0),
+            TcxOrder::BeforeProgram(id) => (BPF_F_BEFORE | BPF_F_ID, id),
+            TcxOrder::AfterProgram(id) => (BPF_F_AFTER | BPF_F_ID, id),
+            TcxOrder::BeforeLink(id) => (BPF_F_BEFORE | BPF_F_LINK | BPF_F_ID, id),
+            TcxOrder::AfterLink(id) => (BPF_F_AFTER | BPF_F_LINK | BPF_F_ID, id),
+        }
+    }
 }
 
 /// Options for SchedClassifier attach
@@ -166,12 +224,75 @@ impl SchedClassifier {
         }
         .map_err(|io_error| TcError::NetlinkError { io_error })?;
 
-        self.data.links.insert(SchedClassifierLink::new(TcLink {
-            if_index: if_index as i32,
-            attach_type,
-            priority,
-            handle,
-        }))
+        self.data
+            .links
+            .insert(SchedClassifierLink::new(TcLinkInner::NlLink(TcLink {
+                if_index: if_index as i32,
+                attach_type,
+                priority,
+                handle,
+            })))
+    }
+
+    /// Attaches the program to the given `interface` with a tcx link.
+    ///
+    /// Unlike [SchedClassifier::attach], tcx links don't go through netlink and
+    /// don't need the `clsact` qdisc. Multiple programs attached to the same
+    /// hook run in the order requested with `order`, and the program is
+    /// detached automatically when its link is dropped.
+    ///
+    /// The returned value can be used to detach, see [SchedClassifier::detach].
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 6.6.
+    ///
+    /// # Errors
+    ///
+    /// [`TcError::InvalidTcxAttachType`] is returned if `attach_type` is
+    /// [`TcAttachType::Custom`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[derive(Debug, thiserror::Error)]
+    /// # enum Error {
+    /// #     #[error(transparent)]
+    /// #     Program(#[from] aya::programs::ProgramError),
+    /// #     #[error(transparent)]
+    /// #     Bpf(#[from] aya::BpfError)
+    /// # }
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::programs::{SchedClassifier, TcAttachType, TcxOrder};
+    ///
+    /// let prog: &mut SchedClassifier = bpf.program_mut("redirect_ingress").unwrap().try_into()?;
+    /// prog.load()?;
+    /// prog.attach_tcx("eth0", TcAttachType::Ingress, TcxOrder::First)?;
+    ///
+    /// # Ok::<(), Error>(())
+    /// ```
+    pub fn attach_tcx(
+        &mut self,
+        interface: &str,
+        attach_type: TcAttachType,
+        order: TcxOrder,
+    ) -> Result<SchedClassifierLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        let if_index = ifindex_from_ifname(interface)
+            .map_err(|io_error| TcError::NetlinkError { io_error })?;
+        let tcx_attach_type = attach_type.tcx_attach_type()?;
+        let (flags, relative_id) = order.flags_and_relative_id();
+
+        let link_fd = bpf_link_create_tcx(prog_fd, if_index, tcx_attach_type, flags, relative_id)
+            .map_err(|(_, io_error)| ProgramError::SyscallError {
+                call: "bpf_link_create".to_owned(),
+                io_error,
+            })? as RawFd;
+        self.data
+            .links
+            .insert(SchedClassifierLink::new(TcLinkInner::FdLink(FdLink::new(
+                link_fd,
+            ))))
     }
 
     /// Detaches the program.
@@ -211,7 +332,7 @@ impl SchedClassifier {
 pub(crate) struct TcLinkId(i32, TcAttachType, u16, u32);
 
 #[derive(Debug)]
-struct TcLink {
+pub(crate) struct TcLink {
     if_index: i32,
     attach_type: TcAttachType,
     priority: u16,
@@ -234,13 +355,55 @@ impl Link for TcLink {
     }
 }
 
+#[derive(Debug, Hash, Eq, PartialEq)]
+pub(crate) enum TcLinkIdInner {
+    FdLinkId(<FdLink as Link>::Id),
+    NlLinkId(<TcLink as Link>::Id),
+}
+
+#[derive(Debug)]
+pub(crate) enum TcLinkInner {
+    FdLink(FdLink),
+    NlLink(TcLink),
+}
+
+impl Link for TcLinkInner {
+    type Id = TcLinkIdInner;
+
+    fn id(&self) -> Self::Id {
+        match self {
+            TcLinkInner::FdLink(link) => TcLinkIdInner::FdLinkId(link.id()),
+            TcLinkInner::NlLink(link) => TcLinkIdInner::NlLinkId(link.id()),
+        }
+    }
+
+    fn detach(self) -> Result<(), ProgramError> {
+        match self {
+            TcLinkInner::FdLink(link) => link.detach(),
+            TcLinkInner::NlLink(link) => link.detach(),
+        }
+    }
+}
+
+impl TryFrom<SchedClassifierLink> for FdLink {
+    type Error = LinkError;
+
+    fn try_from(value: SchedClassifierLink) -> Result<Self, Self::Error> {
+        if let TcLinkInner::FdLink(fd) = value.into_inner() {
+            Ok(fd)
+        } else {
+            Err(LinkError::InvalidLink)
+        }
+    }
+}
+
 define_link_wrapper!(
     /// The link used by [SchedClassifier] programs.
     SchedClassifierLink,
     /// The type returned by [SchedClassifier::attach]. Can be passed to [SchedClassifier::detach].
     SchedClassifierLinkId,
-    TcLink,
-    TcLinkId
+    TcLinkInner,
+    TcLinkIdInner
 );
 
 impl SchedClassifierLink {
@@ -282,22 +445,42 @@ impl SchedClassifierLink {
         handle: u32,
     ) -> Result<SchedClassifierLink, io::Error> {
         let if_index = ifindex_from_ifname(if_name)?;
-        Ok(SchedClassifierLink(Some(TcLink {
+        Ok(SchedClassifierLink(Some(TcLinkInner::NlLink(TcLink {
             if_index: if_index as i32,
             attach_type,
             priority,
             handle,
-        })))
+        }))))
     }
 
     /// Returns the allocated priority. If none was provided at attach time, this was allocated for you.
+    ///
+    /// Links created with [SchedClassifier::attach_tcx] have no priority and return 0, see
+    /// [SchedClassifierLink::is_tcx].
     pub fn priority(&self) -> u16 {
-        self.inner().priority
+        match self.inner() {
+            TcLinkInner::NlLink(link) => link.priority,
+            TcLinkInner::FdLink(_) => 0,
+        }
     }
 
     /// Returns the assigned handle. If none was provided at attach time, this was allocated for you.
+    ///
+    /// Links created with [SchedClassifier::attach_tcx] have no handle and return 0, see
+    /// [SchedClassifierLink::is_tcx].
     pub fn handle(&self) -> u32 {
-        self.inner().handle
+        match self.inner() {
+            TcLinkInner::NlLink(link) => link.handle,
+            TcLinkInner::FdLink(_) => 0,
+        }
+    }
+
+    /// Returns whether the link was created with [SchedClassifier::attach_tcx].
+    ///
+    /// tcx links are not netlink filters, so they have no priority or handle
+    /// and can be converted into an [`FdLink`].
+    pub fn is_tcx(&self) -> bool {
+        matches!(self.inner(), TcLinkInner::FdLink(_))
     }
 }
 
@@ -356,3 +539,103 @@ fn qdisc_detach_program_fast(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs::File, os::unix::io::IntoRawFd};
+
+    use libc::EINVAL;
+    use object::SectionIndex;
+
+    use super::*;
+    use crate::{
+        generated::bpf_cmd,
+        obj::{self, KernelVersion, ProgramSection},
+        sys::{override_syscall, Syscall},
+    };
+
+    fn tmp_fd(name: &str) -> RawFd {
+        File::create(env::temp_dir().join(name))
+            .expect("unable to create file in tmpdir")
+            .into_raw_fd()
+    }
+
+    fn new_classifier() -> SchedClassifier {
+        let obj = obj::Program {
+            license: CString::new("GPL").unwrap(),
+            kernel_version: KernelVersion::Any,
+            section: ProgramSection::SchedClassifier {
+                name: "classifier".to_owned(),
+            },
+            function: obj::Function {
+                address: 0,
+                name: "classifier".to_owned(),
+                section_index: SectionIndex(0),
+                section_offset: 0,
+                instructions: Vec::new(),
+                func_info: Default::default(),
+                line_info: Default::default(),
+                func_info_rec_size: 0,
+                line_info_rec_size: 0,
+            },
+        };
+        let mut data = ProgramData::new(Some("classifier".to_owned()), obj, None, 0, None);
+        data.fd = Some(tmp_fd("tcx_prog"));
+        SchedClassifier {
+            data,
+            name: CString::new("classifier").unwrap().into_boxed_c_str(),
+        }
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_attach_tcx() {
+        let mut prog = new_classifier();
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_LINK_CREATE,
+                attr,
+            } => {
+                let link_create = unsafe { &attr.link_create };
+                assert_eq!(unsafe { link_create.__bindgen_anon_1.target_ifindex }, 1);
+                assert_eq!(link_create.attach_type, BPF_TCX_EGRESS);
+                assert_eq!(link_create.flags, BPF_F_BEFORE | BPF_F_LINK | BPF_F_ID);
+                assert_eq!(unsafe { link_create.__bindgen_anon_2.target_btf_id }, 7);
+                Ok(tmp_fd("tcx_link") as i64)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        assert!(matches!(
+            prog.attach_tcx("lo", TcAttachType::Custom(1), TcxOrder::Last),
+            Err(ProgramError::TcError(TcError::InvalidTcxAttachType))
+        ));
+
+        let link_id = prog
+            .attach_tcx("lo", TcAttachType::Egress, TcxOrder::BeforeLink(7))
+            .unwrap();
+        let link = prog.take_link(link_id).unwrap();
+        assert!(link.is_tcx());
+        assert_eq!(link.priority(), 0);
+        assert_eq!(link.handle(), 0);
+        let link_id = prog.data.links.insert(link).unwrap();
+        prog.detach(link_id).unwrap();
+    }
+
+    #[test]
+    fn test_netlink_link() {
+        let link = SchedClassifierLink::attached("lo", TcAttachType::Ingress, 50, 1).unwrap();
+        assert!(!link.is_tcx());
+        assert_eq!(link.priority(), 50);
+        assert_eq!(link.handle(), 1);
+        // consuming the link takes the netlink filter out of it, so nothing
+        // is detached on drop
+        assert!(matches!(
+            FdLink::try_from(link),
+            Err(LinkError::InvalidLink)
+        ));
+    }
+}
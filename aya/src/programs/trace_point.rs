@@ -78,6 +78,34 @@ impl TracePoint {
     ///
     /// The returned value can be used to detach, see [TracePoint::detach].
     pub fn attach(&mut self, category: &str, name: &str) -> Result<TracePointLinkId, ProgramError> {
+        self.attach_inner(category, name, None)
+    }
+
+    /// Attaches to a given trace point with a cookie.
+    ///
+    /// Like [TracePoint::attach], but the program can retrieve `cookie` with
+    /// the `bpf_get_attach_cookie` helper. This allows the same program to be
+    /// attached to several trace points and tell them apart.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 5.15. On
+    /// older kernels [`ProgramError::AttachCookieNotSupported`] is returned.
+    pub fn attach_with_cookie(
+        &mut self,
+        category: &str,
+        name: &str,
+        cookie: u64,
+    ) -> Result<TracePointLinkId, ProgramError> {
+        self.attach_inner(category, name, Some(cookie))
+    }
+
+    fn attach_inner(
+        &mut self,
+        category: &str,
+        name: &str,
+        cookie: Option<u64>,
+    ) -> Result<TracePointLinkId, ProgramError> {
         let tracefs = find_tracefs_path()?;
         let id = read_sys_fs_trace_point_id(tracefs, category, name)?;
         let fd = perf_event_open_trace_point(id, None).map_err(|(_code, io_error)| {
@@ -87,7 +115,7 @@ impl TracePoint {
             }
         })? as i32;
 
-        let link = perf_attach(self.data.fd_or_err()?, fd)?;
+        let link = perf_attach(self.data.fd_or_err()?, fd, cookie)?;
         self.data.links.insert(TracePointLink::new(link))
     }
 
@@ -2,13 +2,15 @@
 use libc::pid_t;
 use object::{Object, ObjectSection, ObjectSymbol};
 use std::{
+    env,
     error::Error,
-    ffi::CStr,
+    ffi::{CStr, OsStr},
     fs,
     io::{self, BufRead, Cursor, Read},
     mem,
-    os::raw::c_char,
+    os::{raw::c_char, unix::ffi::OsStrExt},
     path::{Path, PathBuf},
+    process::{Command, Stdio},
     sync::Arc,
 };
 use thiserror::Error;
@@ -31,6 +33,8 @@ lazy_static! {
 }
 const LD_SO_CACHE_HEADER: &str = "glibc-ld.so.cache1.1";
 
+const DEBUG_FILE_DIRECTORY: &str = "/usr/lib/debug";
+
 /// An user space probe.
 ///
 /// User probes are eBPF programs that can be attached to any userspace
@@ -38,6 +42,10 @@ const LD_SO_CACHE_HEADER: &str = "glibc-ld.so.cache1.1";
 ///
 /// - `uprobe`: get attached to the *start* of the target functions
 /// - `uretprobe`: get attached to the *return address* of the target functions
+///
+/// Programs defined in `uprobe.s` and `uretprobe.s` sections are loaded as
+/// sleepable, so they can read user memory that isn't paged in with
+/// `bpf_copy_from_user`. Sleepable uprobes require kernel 6.0.
 #[derive(Debug)]
 #[doc(alias = "BPF_PROG_TYPE_KPROBE")]
 pub struct UProbe {
@@ -67,6 +75,12 @@ impl UProbe {
     /// The `target` argument can be an absolute path to a binary or library, or
     /// a library name (eg: `"libc"`).
     ///
+    /// If `fn_name` is not found in a stripped `target`, it is looked up in
+    /// the separate debug file of the target, found by build-id or
+    /// `.gnu_debuglink` under `/usr/lib/debug`, or in the cache of the
+    /// debuginfod client. When `DEBUGINFOD_URLS` is set, the debug file is
+    /// otherwise downloaded from the debuginfod servers with `debuginfod-find`.
+    ///
     /// If the program is an `uprobe`, it is attached to the *start* address of the target
     /// function.  Instead if the program is a `uretprobe`, it is attached to the return address of
     /// the target function.
@@ -79,36 +93,39 @@ impl UProbe {
         target: T,
         pid: Option<pid_t>,
     ) -> Result<UProbeLinkId, ProgramError> {
-        let target = target.as_ref();
-        let target_str = &*target.as_os_str().to_string_lossy();
-
-        let mut path = if let Some(pid) = pid {
-            find_lib_in_proc_maps(pid, target_str).map_err(|io_error| UProbeError::FileError {
-                filename: format!("/proc/{pid}/maps"),
-                io_error,
-            })?
-        } else {
-            None
-        };
+        self.attach_inner(fn_name, offset, target.as_ref(), pid, None)
+    }
 
-        if path.is_none() {
-            path = if target.is_absolute() {
-                Some(target_str)
-            } else {
-                let cache =
-                    LD_SO_CACHE
-                        .as_ref()
-                        .map_err(|error| UProbeError::InvalidLdSoCache {
-                            io_error: error.clone(),
-                        })?;
-                cache.resolve(target_str)
-            }
-            .map(String::from)
-        };
+    /// Attaches the program with a cookie.
+    ///
+    /// Like [UProbe::attach], but the program can retrieve `cookie` with the
+    /// `bpf_get_attach_cookie` helper, for example to know which of the
+    /// functions it's attached to is being called.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 5.15. On
+    /// older kernels [`ProgramError::AttachCookieNotSupported`] is returned.
+    pub fn attach_with_cookie<T: AsRef<Path>>(
+        &mut self,
+        fn_name: Option<&str>,
+        offset: u64,
+        target: T,
+        pid: Option<pid_t>,
+        cookie: u64,
+    ) -> Result<UProbeLinkId, ProgramError> {
+        self.attach_inner(fn_name, offset, target.as_ref(), pid, Some(cookie))
+    }
 
-        let path = path.ok_or(UProbeError::InvalidTarget {
-            path: target.to_owned(),
-        })?;
+    fn attach_inner(
+        &mut self,
+        fn_name: Option<&str>,
+        offset: u64,
+        target: &Path,
+        pid: Option<pid_t>,
+        cookie: Option<u64>,
+    ) -> Result<UProbeLinkId, ProgramError> {
+        let path = resolve_target(target, pid)?;
 
         let sym_offset = if let Some(fn_name) = fn_name {
             resolve_symbol(&path, fn_name).map_err(|error| UProbeError::SymbolError {
@@ -119,7 +136,14 @@ impl UProbe {
             0
         };
 
-        attach(&mut self.data, self.kind, &path, sym_offset + offset, pid)
+        attach(
+            &mut self.data,
+            self.kind,
+            &path,
+            sym_offset + offset,
+            pid,
+            cookie,
+        )
     }
 
     /// Detaches the program.
@@ -197,6 +221,39 @@ pub enum UProbeError {
     },
 }
 
+/// Resolves the path of a uprobe target, which can be an absolute path or a
+/// library name looked up in the maps of `pid` and in `/etc/ld.so.cache`.
+pub(crate) fn resolve_target(target: &Path, pid: Option<pid_t>) -> Result<String, UProbeError> {
+    let target_str = &*target.as_os_str().to_string_lossy();
+
+    let mut path = if let Some(pid) = pid {
+        find_lib_in_proc_maps(pid, target_str).map_err(|io_error| UProbeError::FileError {
+            filename: format!("/proc/{pid}/maps"),
+            io_error,
+        })?
+    } else {
+        None
+    };
+
+    if path.is_none() {
+        path = if target.is_absolute() {
+            Some(target_str)
+        } else {
+            let cache = LD_SO_CACHE
+                .as_ref()
+                .map_err(|error| UProbeError::InvalidLdSoCache {
+                    io_error: error.clone(),
+                })?;
+            cache.resolve(target_str)
+        }
+        .map(String::from)
+    };
+
+    path.ok_or(UProbeError::InvalidTarget {
+        path: target.to_owned(),
+    })
+}
+
 fn proc_maps_libs(pid: pid_t) -> Result<Vec<(String, String)>, io::Error> {
     let maps_file = format!("/proc/{pid}/maps");
     let data = fs::read_to_string(maps_file)?;
@@ -321,7 +378,7 @@ impl LdSoCache {
 }
 
 #[derive(Error, Debug)]
-enum ResolveSymbolError {
+pub(crate) enum ResolveSymbolError {
     #[error(transparent)]
     Io(#[from] io::Error),
 
@@ -336,18 +393,52 @@ enum ResolveSymbolError {
 
     #[error("symbol `{0}` in section `{1:?}` which has no offset")]
     SectionFileRangeNone(String, Result<String, object::Error>),
+
+    #[error("the build-id of debug file `{0}` doesn't match the target")]
+    BuildIdMismatch(PathBuf),
 }
 
-fn resolve_symbol(path: &str, symbol: &str) -> Result<u64, ResolveSymbolError> {
+pub(crate) fn resolve_symbol(path: &str, symbol: &str) -> Result<u64, ResolveSymbolError> {
     let data = fs::read(path)?;
     let obj = object::read::File::parse(&*data)?;
 
-    let sym = obj
-        .dynamic_symbols()
+    if let Some(sym) = find_symbol(&obj, symbol) {
+        return symbol_file_offset(&obj, None, &sym, symbol);
+    }
+
+    // the target may be stripped, look the symbol up in its debug file
+    let debug_path = find_debug_file(Path::new(path), &obj)
+        .ok_or_else(|| ResolveSymbolError::Unknown(symbol.to_string()))?;
+    let debug_data = fs::read(&debug_path)?;
+    let debug_obj = object::read::File::parse(&*debug_data)?;
+    if let (Some(build_id), Some(debug_build_id)) = (obj.build_id()?, debug_obj.build_id()?) {
+        if build_id != debug_build_id {
+            return Err(ResolveSymbolError::BuildIdMismatch(debug_path));
+        }
+    }
+
+    let sym = find_symbol(&debug_obj, symbol)
+        .ok_or_else(|| ResolveSymbolError::Unknown(symbol.to_string()))?;
+    symbol_file_offset(&obj, Some(&debug_obj), &sym, symbol)
+}
+
+fn find_symbol<'data, 'file>(
+    obj: &'file object::File<'data>,
+    symbol: &str,
+) -> Option<object::Symbol<'data, 'file>> {
+    obj.dynamic_symbols()
         .chain(obj.symbols())
         .find(|sym| sym.name().map(|name| name == symbol).unwrap_or(false))
-        .ok_or_else(|| ResolveSymbolError::Unknown(symbol.to_string()))?;
+}
 
+// Returns the offset in the file of `obj` of `sym`, a symbol of `debug_obj`,
+// the debug file of `obj`, if set or of `obj` otherwise.
+fn symbol_file_offset(
+    obj: &object::File,
+    debug_obj: Option<&object::File>,
+    sym: &object::Symbol,
+    symbol: &str,
+) -> Result<u64, ResolveSymbolError> {
     let needs_addr_translation = matches!(
         obj.kind(),
         object::ObjectKind::Dynamic | object::ObjectKind::Executable
@@ -358,7 +449,14 @@ fn resolve_symbol(path: &str, symbol: &str) -> Result<u64, ResolveSymbolError> {
         let index = sym
             .section_index()
             .ok_or_else(|| ResolveSymbolError::NotInSection(symbol.to_string()))?;
-        let section = obj.section_by_index(index)?;
+        let section = match debug_obj {
+            None => obj.section_by_index(index)?,
+            // the sections of debug files hold no code, use the section of the
+            // target with the same name
+            Some(debug_obj) => obj
+                .section_by_name_bytes(debug_obj.section_by_index(index)?.name_bytes()?)
+                .ok_or_else(|| ResolveSymbolError::NotInSection(symbol.to_string()))?,
+        };
         let (offset, _size) = section.file_range().ok_or_else(|| {
             ResolveSymbolError::SectionFileRangeNone(
                 symbol.to_string(),
@@ -368,3 +466,273 @@ fn resolve_symbol(path: &str, symbol: &str) -> Result<u64, ResolveSymbolError> {
         Ok(sym.address() - section.address() + offset)
     }
 }
+
+fn find_debug_file(target: &Path, obj: &object::File) -> Option<PathBuf> {
+    let build_id = obj.build_id().ok().flatten();
+
+    if let Some(build_id) = build_id {
+        let path = build_id_debug_path(Path::new(DEBUG_FILE_DIRECTORY), build_id);
+        if path.is_file() {
+            return Some(path);
+        }
+    }
+
+    if let Ok(Some((debuglink, _crc))) = obj.gnu_debuglink() {
+        let debuglink = OsStr::from_bytes(debuglink);
+        if let Some(dir) = target.parent() {
+            let candidates = [
+                dir.join(debuglink),
+                dir.join(".debug").join(debuglink),
+                Path::new(DEBUG_FILE_DIRECTORY)
+                    .join(dir.strip_prefix("/").unwrap_or(dir))
+                    .join(debuglink),
+            ];
+            if let Some(path) = candidates
+                .into_iter()
+                .find(|path| path != target && path.is_file())
+            {
+                return Some(path);
+            }
+        }
+    }
+
+    let build_id = build_id?;
+    let cache = match env::var_os("DEBUGINFOD_CACHE_PATH") {
+        Some(path) => PathBuf::from(path),
+        None => match env::var_os("XDG_CACHE_HOME") {
+            Some(path) => PathBuf::from(path).join("debuginfod_client"),
+            None => PathBuf::from(env::var_os("HOME")?).join(".cache/debuginfod_client"),
+        },
+    };
+    let path = cache.join(hex(build_id)).join("debuginfo");
+    if path.is_file() {
+        return Some(path);
+    }
+
+    debuginfod_find(build_id)
+}
+
+// Asks the debuginfod servers listed in DEBUGINFOD_URLS for the debug file,
+// which the client downloads to its cache before printing its path.
+fn debuginfod_find(build_id: &[u8]) -> Option<PathBuf> {
+    match env::var_os("DEBUGINFOD_URLS") {
+        Some(urls) if !urls.is_empty() => {}
+        _ => return None,
+    }
+    let output = Command::new("debuginfod-find")
+        .arg("debuginfo")
+        .arg(hex(build_id))
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = output.stdout.strip_suffix(b"\n").unwrap_or(&output.stdout);
+    let path = PathBuf::from(OsStr::from_bytes(stdout));
+    path.is_file().then_some(path)
+}
+
+// Debug files are installed as /usr/lib/debug/.build-id/xx/yyyy.debug, where
+// xx is the first byte of the build-id and yyyy the rest of it.
+fn build_id_debug_path(debug_dir: &Path, build_id: &[u8]) -> PathBuf {
+    let (first, rest) = build_id.split_at(1.min(build_id.len()));
+    debug_dir
+        .join(".build-id")
+        .join(hex(first))
+        .join(format!("{}.debug", hex(rest)))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{
+        elf,
+        write::elf::{FileHeader, SectionHeader, Sym, Writer},
+        Endianness,
+    };
+
+    use super::*;
+
+    const TEXT_ADDRESS: u64 = 0x1000;
+
+    // Builds a shared object whose `.text` section is loaded at
+    // `TEXT_ADDRESS` and holds `code`, or no data in debug files, and which
+    // defines `symbols` as offsets in `.text`.
+    fn shared_object(
+        code: Option<&[u8]>,
+        symbols: &[(&[u8], u64)],
+        debuglink: Option<&[u8]>,
+    ) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut w = Writer::new(Endianness::Little, true, &mut buf);
+        // the name of the debug file followed by the padded CRC of its content
+        let debuglink = debuglink.map(|name| {
+            let mut data = name.to_vec();
+            data.resize((name.len() + 4) & !3, 0);
+            data.extend_from_slice(&[0; 4]);
+            data
+        });
+
+        w.reserve_file_header();
+        let text_name = w.add_section_name(b".text");
+        let text_index = w.reserve_section_index();
+        let text_size = code.map_or(0x100, <[u8]>::len);
+        let text_offset = w.reserve(code.map_or(0, <[u8]>::len), 16);
+        let debuglink_section = debuglink.as_ref().map(|data| {
+            let name = w.add_section_name(b".gnu_debuglink");
+            w.reserve_section_index();
+            (name, w.reserve(data.len(), 4), data)
+        });
+        w.reserve_null_symbol_index();
+        let symbols = symbols
+            .iter()
+            .map(|(name, offset)| {
+                let name = w.add_string(name);
+                w.reserve_symbol_index(Some(text_index));
+                (name, offset)
+            })
+            .collect::<Vec<_>>();
+        w.reserve_symtab_section_index();
+        w.reserve_symtab();
+        w.reserve_strtab_section_index();
+        w.reserve_strtab();
+        w.reserve_shstrtab_section_index();
+        w.reserve_shstrtab();
+        w.reserve_section_headers();
+
+        w.write_file_header(&FileHeader {
+            os_abi: elf::ELFOSABI_NONE,
+            abi_version: 0,
+            e_type: elf::ET_DYN,
+            e_machine: elf::EM_X86_64,
+            e_entry: 0,
+            e_flags: 0,
+        })
+        .unwrap();
+        if let Some(code) = code {
+            w.write_align(16);
+            w.write(code);
+        }
+        if let Some((_, _, data)) = debuglink_section {
+            w.write_align(4);
+            w.write(data);
+        }
+        w.write_null_symbol();
+        for (name, offset) in symbols {
+            w.write_symbol(&Sym {
+                name: Some(name),
+                section: Some(text_index),
+                st_info: (elf::STB_GLOBAL << 4) | elf::STT_FUNC,
+                st_other: elf::STV_DEFAULT,
+                st_shndx: 0,
+                st_value: TEXT_ADDRESS + offset,
+                st_size: 0,
+            });
+        }
+        w.write_strtab();
+        w.write_shstrtab();
+
+        w.write_null_section_header();
+        w.write_section_header(&SectionHeader {
+            name: Some(text_name),
+            sh_type: if code.is_some() {
+                elf::SHT_PROGBITS
+            } else {
+                elf::SHT_NOBITS
+            },
+            sh_flags: (elf::SHF_ALLOC | elf::SHF_EXECINSTR).into(),
+            sh_addr: TEXT_ADDRESS,
+            sh_offset: text_offset as u64,
+            sh_size: text_size as u64,
+            sh_link: 0,
+            sh_info: 0,
+            sh_addralign: 16,
+            sh_entsize: 0,
+        });
+        if let Some((name, offset, data)) = debuglink_section {
+            w.write_section_header(&SectionHeader {
+                name: Some(name),
+                sh_type: elf::SHT_PROGBITS,
+                sh_flags: 0,
+                sh_addr: 0,
+                sh_offset: offset as u64,
+                sh_size: data.len() as u64,
+                sh_link: 0,
+                sh_info: 0,
+                sh_addralign: 4,
+                sh_entsize: 0,
+            });
+        }
+        w.write_symtab_section_header(1);
+        w.write_strtab_section_header();
+        w.write_shstrtab_section_header();
+        buf
+    }
+
+    #[test]
+    fn test_resolve_symbol() {
+        let dir = env::temp_dir().join(format!("aya-uprobe-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("libfoo.so");
+        let code = [0u8; 0x40];
+        fs::write(&target, shared_object(Some(&code), &[(b"foo", 0x10)], None)).unwrap();
+
+        let offset = resolve_symbol(target.to_str().unwrap(), "foo").unwrap();
+        let data = fs::read(&target).unwrap();
+        let obj = object::read::File::parse(&*data).unwrap();
+        let (text_offset, _) = obj.section_by_name(".text").unwrap().file_range().unwrap();
+        assert_eq!(offset, text_offset + 0x10);
+        assert!(matches!(
+            resolve_symbol(target.to_str().unwrap(), "bar"),
+            Err(ResolveSymbolError::Unknown(symbol)) if symbol == "bar"
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_symbol_debug_file() {
+        let dir = env::temp_dir().join(format!("aya-uprobe-debug-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("libfoo.so");
+        // the stripped target only points to its debug file
+        let code = [0u8; 0x40];
+        fs::write(
+            &target,
+            shared_object(Some(&code), &[], Some(b"libfoo.so.debug")),
+        )
+        .unwrap();
+        assert!(matches!(
+            resolve_symbol(target.to_str().unwrap(), "foo"),
+            Err(ResolveSymbolError::Unknown(_))
+        ));
+
+        // the debug file has a `.text` section without data, at another file
+        // offset than in the target
+        fs::write(
+            dir.join("libfoo.so.debug"),
+            shared_object(None, &[(b"bar", 0x8), (b"foo", 0x20)], None),
+        )
+        .unwrap();
+        let offset = resolve_symbol(target.to_str().unwrap(), "foo").unwrap();
+        let data = fs::read(&target).unwrap();
+        let obj = object::read::File::parse(&*data).unwrap();
+        let (text_offset, _) = obj.section_by_name(".text").unwrap().file_range().unwrap();
+        assert_eq!(offset, text_offset + 0x20);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_build_id_debug_path() {
+        assert_eq!(
+            build_id_debug_path(Path::new("/usr/lib/debug"), &[0xab, 0x01, 0xcd, 0xef]),
+            Path::new("/usr/lib/debug/.build-id/ab/01cdef.debug")
+        );
+    }
+}
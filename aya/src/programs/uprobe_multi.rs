@@ -0,0 +1,300 @@
+//! User space probes attached to many locations at once.
+use std::{ffi::CString, os::unix::io::RawFd, path::Path};
+
+use libc::pid_t;
+
+use crate::{
+    generated::bpf_prog_type::BPF_PROG_TYPE_KPROBE,
+    programs::{
+        define_link_wrapper, load_program,
+        uprobe::{resolve_symbol, resolve_target, UProbeError},
+        FdLink, FdLinkId, ProbeKind, ProgramData, ProgramError,
+    },
+    sys::bpf_link_create_uprobe_multi,
+};
+
+// The generated bindings predate uprobe.multi links (Linux 6.6).
+const BPF_TRACE_UPROBE_MULTI: u32 = 48;
+const BPF_F_UPROBE_MULTI_RETURN: u32 = 1 << 0;
+
+/// A user space probe attached to multiple locations of a binary with a single
+/// link.
+///
+/// [`UProbeMulti`] programs behave like [uprobes](crate::programs::UProbe),
+/// but instead of creating one perf event per location, all the locations are
+/// attached with one `BPF_LINK_CREATE` syscall.
+///
+/// The programs are defined in the `uprobe.multi` and `uretprobe.multi`
+/// sections.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 6.6.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = Bpf::load_file("ebpf_programs.o")?;
+/// use aya::{Bpf, programs::UProbeMulti};
+///
+/// let program: &mut UProbeMulti = bpf.program_mut("count_allocs").unwrap().try_into()?;
+/// program.load()?;
+/// program.attach("libc", &["malloc", "calloc", "realloc"], None)?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_TRACE_UPROBE_MULTI")]
+#[doc(alias = "BPF_PROG_TYPE_KPROBE")]
+pub struct UProbeMulti {
+    pub(crate) data: ProgramData<UProbeMultiLink>,
+    pub(crate) kind: ProbeKind,
+}
+
+impl UProbeMulti {
+    /// Loads the program inside the kernel.
+    pub fn load(&mut self) -> Result<(), ProgramError> {
+        self.data.raw_attach_type = Some(BPF_TRACE_UPROBE_MULTI);
+        load_program(BPF_PROG_TYPE_KPROBE, &mut self.data)
+    }
+
+    /// Returns `UProbe` if the program is a `uprobe.multi`, or `URetProbe` if
+    /// the program is a `uretprobe.multi`.
+    pub fn kind(&self) -> ProbeKind {
+        self.kind
+    }
+
+    /// Attaches the program to the given functions of `target`.
+    ///
+    /// The `target` argument can be an absolute path to a binary or library,
+    /// or a library name (eg: `"libc"`). If `pid` is not `None`, the program
+    /// executes only when the functions are executed by the given `pid`.
+    ///
+    /// The returned value can be used to detach from all the functions at
+    /// once, see [UProbeMulti::detach].
+    pub fn attach<T: AsRef<Path>>(
+        &mut self,
+        target: T,
+        fn_names: &[&str],
+        pid: Option<pid_t>,
+    ) -> Result<UProbeMultiLinkId, ProgramError> {
+        let path = resolve_target(target.as_ref(), pid)?;
+        let offsets = fn_names
+            .iter()
+            .map(|name| symbol_offset(&path, name))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.attach_offsets_inner(&path, &offsets, None, pid)
+    }
+
+    /// Attaches the program to the given functions of `target`, with a cookie
+    /// for each of them.
+    ///
+    /// The program can retrieve the cookie of the function it was called from
+    /// with the `bpf_get_attach_cookie` helper.
+    ///
+    /// See [UProbeMulti::attach].
+    pub fn attach_with_cookies<T: AsRef<Path>>(
+        &mut self,
+        target: T,
+        fns: &[(&str, u64)],
+        pid: Option<pid_t>,
+    ) -> Result<UProbeMultiLinkId, ProgramError> {
+        let path = resolve_target(target.as_ref(), pid)?;
+        let (offsets, cookies): (Vec<_>, Vec<_>) = fns
+            .iter()
+            .map(|(name, cookie)| symbol_offset(&path, name).map(|offset| (offset, *cookie)))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .unzip();
+        self.attach_offsets_inner(&path, &offsets, Some(&cookies), pid)
+    }
+
+    /// Attaches the program to the given file offsets of `target`, with an
+    /// optional cookie for each of them.
+    ///
+    /// See [UProbeMulti::attach] and [UProbeMulti::attach_with_cookies].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cookies` and `offsets` have different lengths.
+    pub fn attach_offsets<T: AsRef<Path>>(
+        &mut self,
+        target: T,
+        offsets: &[u64],
+        cookies: Option<&[u64]>,
+        pid: Option<pid_t>,
+    ) -> Result<UProbeMultiLinkId, ProgramError> {
+        if let Some(cookies) = cookies {
+            assert_eq!(
+                offsets.len(),
+                cookies.len(),
+                "the number of offsets and cookies differ"
+            );
+        }
+        let path = resolve_target(target.as_ref(), pid)?;
+        self.attach_offsets_inner(&path, offsets, cookies, pid)
+    }
+
+    fn attach_offsets_inner(
+        &mut self,
+        path: &str,
+        offsets: &[u64],
+        cookies: Option<&[u64]>,
+        pid: Option<pid_t>,
+    ) -> Result<UProbeMultiLinkId, ProgramError> {
+        let prog_fd = self.data.fd_or_err()?;
+        let path =
+            CString::new(path).map_err(|_| UProbeError::InvalidTarget { path: path.into() })?;
+        let flags = match self.kind {
+            ProbeKind::URetProbe => BPF_F_UPROBE_MULTI_RETURN,
+            _ => 0,
+        };
+
+        let link_fd = bpf_link_create_uprobe_multi(
+            prog_fd,
+            BPF_TRACE_UPROBE_MULTI,
+            &path,
+            offsets,
+            cookies,
+            pid.map(|pid| pid as u32),
+            flags,
+        )
+        .map_err(|(_, io_error)| ProgramError::SyscallError {
+            call: "bpf_link_create".to_owned(),
+            io_error,
+        })? as RawFd;
+        self.data
+            .links
+            .insert(UProbeMultiLink::new(FdLink::new(link_fd)))
+    }
+
+    /// Detaches the program.
+    ///
+    /// See [UProbeMulti::attach].
+    pub fn detach(&mut self, link_id: UProbeMultiLinkId) -> Result<(), ProgramError> {
+        self.data.links.remove(link_id)
+    }
+
+    /// Takes ownership of the link referenced by the provided link_id.
+    ///
+    /// The link will be detached on `Drop` and the caller is now responsible
+    /// for managing its lifetime.
+    pub fn take_link(
+        &mut self,
+        link_id: UProbeMultiLinkId,
+    ) -> Result<UProbeMultiLink, ProgramError> {
+        self.data.take_link(link_id)
+    }
+
+    /// Creates a program from a pinned entry on a bpffs.
+    ///
+    /// Existing links will not be populated. To work with existing links you should use [`crate::programs::links::PinnedLink`].
+    ///
+    /// On drop, any managed links are detached and the program is unloaded. This will not result in
+    /// the program being unloaded from the kernel if it is still pinned.
+    pub fn from_pin<P: AsRef<Path>>(path: P, kind: ProbeKind) -> Result<Self, ProgramError> {
+        let data = ProgramData::from_pinned_path(path)?;
+        Ok(Self { data, kind })
+    }
+}
+
+define_link_wrapper!(
+    /// The link used by [UProbeMulti] programs.
+    UProbeMultiLink,
+    /// The type returned by [UProbeMulti::attach]. Can be passed to [UProbeMulti::detach].
+    UProbeMultiLinkId,
+    FdLink,
+    FdLinkId
+);
+
+fn symbol_offset(path: &str, symbol: &str) -> Result<u64, UProbeError> {
+    resolve_symbol(path, symbol).map_err(|error| UProbeError::SymbolError {
+        symbol: symbol.to_string(),
+        error: Box::new(error),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, ffi::CStr, fs::File, io, os::unix::io::IntoRawFd, slice};
+
+    use libc::EINVAL;
+    use object::SectionIndex;
+
+    use super::*;
+    use crate::{
+        generated::bpf_cmd,
+        obj::{self, KernelVersion, ProgramSection},
+        sys::{override_syscall, Syscall},
+    };
+
+    fn tmp_fd(name: &str) -> RawFd {
+        File::create(env::temp_dir().join(name))
+            .expect("unable to create file in tmpdir")
+            .into_raw_fd()
+    }
+
+    fn new_uretprobe_multi() -> UProbeMulti {
+        let obj = obj::Program {
+            license: CString::new("GPL").unwrap(),
+            kernel_version: KernelVersion::Any,
+            section: ProgramSection::URetProbeMulti {
+                name: "probe".to_owned(),
+            },
+            function: obj::Function {
+                address: 0,
+                name: "probe".to_owned(),
+                section_index: SectionIndex(0),
+                section_offset: 0,
+                instructions: Vec::new(),
+                func_info: Default::default(),
+                line_info: Default::default(),
+                func_info_rec_size: 0,
+                line_info_rec_size: 0,
+            },
+        };
+        let mut data = ProgramData::new(Some("probe".to_owned()), obj, None, 0, None);
+        data.fd = Some(tmp_fd("uprobe_multi_prog"));
+        UProbeMulti {
+            data,
+            kind: ProbeKind::URetProbe,
+        }
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_attach_offsets() {
+        let mut prog = new_uretprobe_multi();
+        override_syscall(|call| match call {
+            Syscall::BpfRaw { cmd, attr } if cmd == bpf_cmd::BPF_LINK_CREATE as u32 => {
+                let u32_at = |offset: usize| {
+                    u32::from_ne_bytes(attr[offset..offset + 4].try_into().unwrap())
+                };
+                let u64_at = |offset: usize| {
+                    u64::from_ne_bytes(attr[offset..offset + 8].try_into().unwrap())
+                };
+                assert_eq!(u32_at(8), BPF_TRACE_UPROBE_MULTI);
+                let path = unsafe { CStr::from_ptr(u64_at(16) as *const _) };
+                assert_eq!(path.to_str().unwrap(), "/usr/bin/true");
+                let cnt = u32_at(48) as usize;
+                let offsets = unsafe { slice::from_raw_parts(u64_at(24) as *const u64, cnt) };
+                assert_eq!(offsets, [0x10, 0x20]);
+                // ref_ctr_offsets
+                assert_eq!(u64_at(32), 0);
+                let cookies = unsafe { slice::from_raw_parts(u64_at(40) as *const u64, cnt) };
+                assert_eq!(cookies, [1, 2]);
+                assert_eq!(u32_at(52), BPF_F_UPROBE_MULTI_RETURN);
+                assert_eq!(u32_at(56), 0);
+                Ok(tmp_fd("uprobe_multi_link") as i64)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        let link_id = prog
+            .attach_offsets("/usr/bin/true", &[0x10, 0x20], Some(&[1, 2]), None)
+            .unwrap();
+        prog.detach(link_id).unwrap();
+    }
+}
@@ -0,0 +1,986 @@
+//! User statically-defined tracepoints.
+use libc::pid_t;
+use object::{Object, ObjectSection, ObjectSegment};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs, mem,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+use crate::{
+    generated::bpf_prog_type::BPF_PROG_TYPE_KPROBE,
+    maps::{MapData, MapError},
+    programs::{
+        define_link_wrapper, load_program,
+        perf_attach::{PerfLinkIdInner, PerfLinkInner},
+        probe::{create_link, ProbeKind},
+        uprobe::resolve_target,
+        Link, ProgramData, ProgramError,
+    },
+    sys::bpf_map_update_elem,
+    Pod,
+};
+
+const NT_STAPSDT: u32 = 3;
+const STAPSDT_NOTE_NAME: &[u8] = b"stapsdt\0";
+
+/// The name of the map holding the argument specs of the attached probes.
+///
+/// The map is defined by `aya_bpf::programs::UsdtContext` when the program
+/// reads the arguments of the probe.
+pub(crate) const USDT_SPECS_MAP: &str = "AYA_USDT_SPECS";
+
+// Must match aya_bpf::programs::usdt
+const USDT_MAX_ARGS: usize = 12;
+const USDT_ARG_CONST: u32 = 0;
+const USDT_ARG_REG: u32 = 1;
+const USDT_ARG_REG_DEREF: u32 = 2;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+struct UsdtArgSpec {
+    val_off: u64,
+    arg_type: u32,
+    reg_off: i16,
+    signed: bool,
+    bitshift: u8,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct UsdtSpec {
+    args: [UsdtArgSpec; USDT_MAX_ARGS],
+    arg_count: u16,
+}
+
+unsafe impl Pod for UsdtSpec {}
+
+impl UsdtSpec {
+    fn new(args: &[UsdtArg]) -> Result<UsdtSpec, UsdtError> {
+        if args.len() > USDT_MAX_ARGS {
+            return Err(UsdtError::TooManyArgs { count: args.len() });
+        }
+        let mut spec = UsdtSpec {
+            args: [UsdtArgSpec::default(); USDT_MAX_ARGS],
+            arg_count: args.len() as u16,
+        };
+        for (arg, arg_spec) in args.iter().zip(spec.args.iter_mut()) {
+            let reg_off = |register: &str| {
+                register_offset(register).ok_or_else(|| UsdtError::UnsupportedRegister {
+                    register: register.to_owned(),
+                })
+            };
+            (arg_spec.arg_type, arg_spec.reg_off, arg_spec.val_off) = match &arg.location {
+                UsdtArgLocation::Constant(value) => (USDT_ARG_CONST, 0, *value as u64),
+                UsdtArgLocation::Register(register) => (USDT_ARG_REG, reg_off(register)?, 0),
+                UsdtArgLocation::Memory { register, offset } => {
+                    (USDT_ARG_REG_DEREF, reg_off(register)?, *offset as u64)
+                }
+            };
+            arg_spec.signed = arg.signed;
+            // the program reads 8 bytes and shifts them to keep `size` bytes
+            arg_spec.bitshift = 64 - arg.size * 8;
+        }
+        Ok(spec)
+    }
+}
+
+// Offset of the register in the `pt_regs` passed to the probe.
+#[cfg(target_arch = "x86_64")]
+fn register_offset(register: &str) -> Option<i16> {
+    let offset = match register {
+        "rip" => 128,
+        "rax" | "eax" | "ax" | "al" => 80,
+        "rbx" | "ebx" | "bx" | "bl" => 40,
+        "rcx" | "ecx" | "cx" | "cl" => 88,
+        "rdx" | "edx" | "dx" | "dl" => 96,
+        "rsi" | "esi" | "si" | "sil" => 104,
+        "rdi" | "edi" | "di" | "dil" => 112,
+        "rbp" | "ebp" | "bp" | "bpl" => 32,
+        "rsp" | "esp" | "sp" | "spl" => 152,
+        _ => {
+            let register = register.strip_prefix('r')?;
+            let register = register.strip_suffix(['d', 'w', 'b']).unwrap_or(register);
+            match register.parse::<u8>().ok()? {
+                8 => 72,
+                9 => 64,
+                10 => 56,
+                11 => 48,
+                12 => 24,
+                13 => 16,
+                14 => 8,
+                15 => 0,
+                _ => return None,
+            }
+        }
+    };
+    Some(offset)
+}
+
+// Offset of the register in the `user_pt_regs` passed to the probe.
+#[cfg(target_arch = "aarch64")]
+fn register_offset(register: &str) -> Option<i16> {
+    if register == "sp" {
+        return Some(31 * 8);
+    }
+    let n = register
+        .strip_prefix('x')
+        .or_else(|| register.strip_prefix('w'))?
+        .parse::<i16>()
+        .ok()?;
+    (0..=30).contains(&n).then_some(n * 8)
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn register_offset(_register: &str) -> Option<i16> {
+    None
+}
+
+/// A user statically-defined tracepoint.
+///
+/// USDT probes are markers compiled into user space binaries, for example
+/// by the Python interpreter or PostgreSQL, which describe themselves in the
+/// `.note.stapsdt` ELF section. [`Usdt`] programs are attached at the
+/// location of the markers, and the semaphore of the probe, if any, is
+/// incremented by the kernel while the program is attached.
+///
+/// The argument specs of the probes, describing where each argument is found
+/// in the registers or the stack, are listed by [`probes`]. When the program
+/// reads the arguments of the probe with `aya_bpf::programs::UsdtContext`, the
+/// specs are stored in the `AYA_USDT_SPECS` map on attach and the index of the
+/// spec of each location is passed to the program as the attach cookie.
+///
+/// # Minimum kernel version
+///
+/// The minimum kernel version required to use this feature is 4.20, or 5.15
+/// when the program reads the arguments of the probe.
+///
+/// # Examples
+///
+/// ```no_run
+/// # let mut bpf = Bpf::load_file("ebpf_programs.o")?;
+/// use aya::{Bpf, programs::Usdt};
+///
+/// let program: &mut Usdt = bpf.program_mut("function_entry").unwrap().try_into()?;
+/// program.load()?;
+/// program.attach("python", "function__entry", "/usr/bin/python3", None)?;
+/// # Ok::<(), aya::BpfError>(())
+/// ```
+#[derive(Debug)]
+#[doc(alias = "BPF_PROG_TYPE_KPROBE")]
+pub struct Usdt {
+    pub(crate) data: ProgramData<UsdtLink>,
+    // the AYA_USDT_SPECS map, if the program reads the probe arguments
+    pub(crate) specs: Option<MapData>,
+    // the index of the specs already stored in `specs`
+    pub(crate) spec_ids: HashMap<UsdtSpec, u32>,
+}
+
+impl Usdt {
+    /// Loads the program inside the kernel.
+    pub fn load(&mut self) -> Result<(), ProgramError> {
+        load_program(BPF_PROG_TYPE_KPROBE, &mut self.data)
+    }
+
+    /// Attaches the program.
+    ///
+    /// Attaches the program to every location of the probe `name` of
+    /// `provider` defined in the `target`. If `pid` is not `None`, the program
+    /// executes only when the probe is hit by the given `pid`.
+    ///
+    /// The `target` argument can be an absolute path to a binary or library, or
+    /// a library name (eg: `"libc"`).
+    ///
+    /// The returned value can be used to detach, see [Usdt::detach].
+    ///
+    /// # Errors
+    ///
+    /// When the program reads the probe arguments, returns
+    /// [`UsdtError::InvalidArgSpec`] or [`UsdtError::UnsupportedRegister`] if
+    /// the arguments of the probe can't be described to the program.
+    pub fn attach<T: AsRef<Path>>(
+        &mut self,
+        provider: &str,
+        name: &str,
+        target: T,
+        pid: Option<pid_t>,
+    ) -> Result<UsdtLinkId, ProgramError> {
+        let path = resolve_target(target.as_ref(), pid)?;
+        let probes = probes(&path)?
+            .into_iter()
+            .filter(|probe| probe.provider == provider && probe.name == name)
+            .collect::<Vec<_>>();
+        if probes.is_empty() {
+            return Err(UsdtError::ProbeNotFound {
+                provider: provider.to_owned(),
+                name: name.to_owned(),
+                path: PathBuf::from(path),
+            }
+            .into());
+        }
+
+        let prog_fd = self.data.fd_or_err()?;
+        let cookies = probes
+            .iter()
+            .map(|probe| self.spec_id(probe))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut links = Vec::with_capacity(probes.len());
+        for (probe, cookie) in probes.into_iter().zip(cookies) {
+            let ref_ctr_offset = probe.semaphore_offset.unwrap_or(0);
+            match create_link(
+                prog_fd,
+                ProbeKind::UProbe,
+                &path,
+                probe.offset,
+                ref_ctr_offset,
+                pid,
+                cookie,
+            ) {
+                Ok(link) => links.push(link),
+                Err(e) => {
+                    for link in links {
+                        let _ = link.detach();
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        self.data
+            .links
+            .insert(UsdtLink::new(UsdtLinkInner { links }))
+    }
+
+    // Stores the arg specs of the probe in the specs map, returning the index
+    // to pass as the attach cookie. Identical specs share the same index.
+    fn spec_id(&mut self, probe: &UsdtProbe) -> Result<Option<u64>, ProgramError> {
+        let specs = match &self.specs {
+            Some(specs) => specs,
+            None => return Ok(None),
+        };
+        let spec = UsdtSpec::new(probe.args()?)?;
+        if let Some(id) = self.spec_ids.get(&spec) {
+            return Ok(Some(u64::from(*id)));
+        }
+
+        let expected = mem::size_of::<UsdtSpec>();
+        let size = specs.obj.value_size() as usize;
+        if size != expected {
+            return Err(MapError::InvalidValueSize { size, expected }.into());
+        }
+        let id = self.spec_ids.len() as u32;
+        if id >= specs.obj.max_entries() {
+            return Err(UsdtError::TooManySpecs {
+                max_entries: specs.obj.max_entries(),
+            }
+            .into());
+        }
+        bpf_map_update_elem(specs.fd_or_err()?, Some(&id), &spec, 0).map_err(|(_, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_map_update_elem".to_owned(),
+                io_error,
+            }
+        })?;
+        self.spec_ids.insert(spec, id);
+        Ok(Some(u64::from(id)))
+    }
+
+    /// Detaches the program.
+    ///
+    /// See [Usdt::attach].
+    pub fn detach(&mut self, link_id: UsdtLinkId) -> Result<(), ProgramError> {
+        self.data.links.remove(link_id)
+    }
+
+    /// Takes ownership of the link referenced by the provided link_id.
+    ///
+    /// The link will be detached on `Drop` and the caller is now responsible
+    /// for managing its lifetime.
+    pub fn take_link(&mut self, link_id: UsdtLinkId) -> Result<UsdtLink, ProgramError> {
+        self.data.take_link(link_id)
+    }
+
+    /// Creates a program from a pinned entry on a bpffs.
+    ///
+    /// Existing links will not be populated. To work with existing links you should use [`crate::programs::links::PinnedLink`].
+    ///
+    /// The `AYA_USDT_SPECS` map of the program is not known, so the program
+    /// can't read the arguments of the probes it is attached to.
+    ///
+    /// On drop, any managed links are detached and the program is unloaded. This will not result in
+    /// the program being unloaded from the kernel if it is still pinned.
+    pub fn from_pin<P: AsRef<Path>>(path: P) -> Result<Self, ProgramError> {
+        let data = ProgramData::from_pinned_path(path)?;
+        Ok(Self {
+            data,
+            specs: None,
+            spec_ids: HashMap::new(),
+        })
+    }
+}
+
+#[derive(Debug, Hash, Eq, PartialEq)]
+pub(crate) struct UsdtLinkIdInner(Vec<PerfLinkIdInner>);
+
+// A probe can be inlined at several locations, each of them needing its own
+// perf event.
+#[derive(Debug)]
+pub(crate) struct UsdtLinkInner {
+    links: Vec<PerfLinkInner>,
+}
+
+impl Link for UsdtLinkInner {
+    type Id = UsdtLinkIdInner;
+
+    fn id(&self) -> Self::Id {
+        UsdtLinkIdInner(self.links.iter().map(|link| link.id()).collect())
+    }
+
+    fn detach(self) -> Result<(), ProgramError> {
+        let mut ret = Ok(());
+        for link in self.links {
+            if let Err(e) = link.detach() {
+                if ret.is_ok() {
+                    ret = Err(e);
+                }
+            }
+        }
+        ret
+    }
+}
+
+define_link_wrapper!(
+    /// The link used by [Usdt] programs.
+    UsdtLink,
+    /// The type returned by [Usdt::attach]. Can be passed to [Usdt::detach].
+    UsdtLinkId,
+    UsdtLinkInner,
+    UsdtLinkIdInner
+);
+
+/// A USDT probe defined in a binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsdtProbe {
+    provider: String,
+    name: String,
+    offset: u64,
+    semaphore_offset: Option<u64>,
+    // the first spec which couldn't be parsed on error
+    args: Result<Vec<UsdtArg>, String>,
+}
+
+impl UsdtProbe {
+    /// The provider of the probe, eg `python`.
+    pub fn provider(&self) -> &str {
+        &self.provider
+    }
+
+    /// The name of the probe, eg `function__entry`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The file offset of the probe location.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The file offset of the semaphore guarding the probe, if any.
+    pub fn semaphore_offset(&self) -> Option<u64> {
+        self.semaphore_offset
+    }
+
+    /// The arguments of the probe.
+    ///
+    /// Returns [`UsdtError::InvalidArgSpec`] if one of the argument specs of the
+    /// probe is not supported, for example a scaled index like
+    /// `8@(%rax,%rdx,8)`. This doesn't prevent attaching to the probe.
+    pub fn args(&self) -> Result<&[UsdtArg], UsdtError> {
+        self.args
+            .as_deref()
+            .map_err(|spec| UsdtError::InvalidArgSpec { spec: spec.clone() })
+    }
+}
+
+/// An argument of a USDT probe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsdtArg {
+    size: u8,
+    signed: bool,
+    location: UsdtArgLocation,
+}
+
+impl UsdtArg {
+    /// The size of the argument in bytes.
+    pub fn size(&self) -> u8 {
+        self.size
+    }
+
+    /// Returns `true` if the argument is a signed integer.
+    pub fn is_signed(&self) -> bool {
+        self.signed
+    }
+
+    /// Where the value of the argument is found when the probe is hit.
+    pub fn location(&self) -> &UsdtArgLocation {
+        &self.location
+    }
+}
+
+/// Location of the value of a [`UsdtArg`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UsdtArgLocation {
+    /// The value is a constant.
+    Constant(i64),
+    /// The value is held by a register, eg `rdi` or `x0`.
+    Register(String),
+    /// The value is stored in memory at `offset` from the address held by
+    /// `register`.
+    Memory {
+        /// The base register
+        register: String,
+        /// The offset from the base register
+        offset: i64,
+    },
+}
+
+/// Returns the USDT probes defined in the binary or library at `path`.
+pub fn probes<T: AsRef<Path>>(path: T) -> Result<Vec<UsdtProbe>, UsdtError> {
+    let path = path.as_ref();
+    let data = fs::read(path).map_err(|io_error| UsdtError::FileError {
+        filename: path.display().to_string(),
+        io_error,
+    })?;
+    let obj = object::File::parse(&*data).map_err(|error| UsdtError::ElfError {
+        path: path.to_owned(),
+        error: Box::new(error),
+    })?;
+
+    let section = match obj.section_by_name(".note.stapsdt") {
+        Some(section) => section,
+        None => return Ok(Vec::new()),
+    };
+    let notes = section.data().map_err(|error| UsdtError::ElfError {
+        path: path.to_owned(),
+        error: Box::new(error),
+    })?;
+    let invalid_note = || UsdtError::InvalidNote {
+        path: path.to_owned(),
+    };
+    let notes = parse_notes(notes, obj.is_little_endian(), obj.is_64()).ok_or_else(invalid_note)?;
+
+    // prelink can move the binary after the notes are generated, which is
+    // detected by comparing the address of .stapsdt.base with the one
+    // recorded in the notes
+    let base = obj.section_by_name(".stapsdt.base").map(|s| s.address());
+
+    let file_offset = |address: u64| {
+        obj.segments().find_map(|segment| {
+            let (offset, size) = segment.file_range();
+            (address >= segment.address() && address < segment.address() + size)
+                .then(|| address - segment.address() + offset)
+        })
+    };
+
+    notes
+        .into_iter()
+        .map(|note| {
+            let pc = match base {
+                Some(base) if note.base != 0 => note.pc.wrapping_add(base.wrapping_sub(note.base)),
+                _ => note.pc,
+            };
+            let semaphore_offset = match note.semaphore {
+                0 => None,
+                semaphore => Some(file_offset(semaphore).ok_or_else(invalid_note)?),
+            };
+            Ok(UsdtProbe {
+                provider: note.provider,
+                name: note.name,
+                offset: file_offset(pc).ok_or_else(invalid_note)?,
+                semaphore_offset,
+                args: parse_args(&note.args),
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct UsdtNote {
+    pc: u64,
+    base: u64,
+    semaphore: u64,
+    provider: String,
+    name: String,
+    args: String,
+}
+
+fn parse_notes(mut data: &[u8], little_endian: bool, is_64: bool) -> Option<Vec<UsdtNote>> {
+    let read_u32 = |data: &[u8]| -> Option<u32> {
+        let bytes = data.get(..4)?.try_into().ok()?;
+        Some(if little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    };
+    let read_addr = |data: &[u8]| -> Option<u64> {
+        if !is_64 {
+            return read_u32(data).map(u64::from);
+        }
+        let bytes = data.get(..8)?.try_into().ok()?;
+        Some(if little_endian {
+            u64::from_le_bytes(bytes)
+        } else {
+            u64::from_be_bytes(bytes)
+        })
+    };
+    let align = |len: usize| (len + 3) & !3;
+
+    let mut notes = Vec::new();
+    while !data.is_empty() {
+        let name_size = read_u32(data)? as usize;
+        let desc_size = read_u32(data.get(4..)?)? as usize;
+        let note_type = read_u32(data.get(8..)?)?;
+        let name = data.get(12..12 + name_size)?;
+        let desc_start = 12 + align(name_size);
+        let desc = data.get(desc_start..desc_start + desc_size)?;
+        data = data
+            .get(desc_start + align(desc_size)..)
+            .unwrap_or_default();
+
+        if note_type != NT_STAPSDT || name != STAPSDT_NOTE_NAME {
+            continue;
+        }
+
+        let addr_size = if is_64 { 8 } else { 4 };
+        let pc = read_addr(desc)?;
+        let base = read_addr(desc.get(addr_size..)?)?;
+        let semaphore = read_addr(desc.get(2 * addr_size..)?)?;
+        let mut strings = desc
+            .get(3 * addr_size..)?
+            .split(|b| *b == 0)
+            .map(|s| String::from_utf8_lossy(s).into_owned());
+
+        notes.push(UsdtNote {
+            pc,
+            base,
+            semaphore,
+            provider: strings.next()?,
+            name: strings.next()?,
+            args: strings.next().unwrap_or_default(),
+        });
+    }
+
+    Some(notes)
+}
+
+// Parses the space separated argument specs of a probe, returning the first
+// spec which couldn't be parsed on error.
+fn parse_args(specs: &str) -> Result<Vec<UsdtArg>, String> {
+    specs
+        .split_whitespace()
+        .map(|spec| parse_arg(spec).ok_or_else(|| spec.to_owned()))
+        .collect()
+}
+
+// Parses an argument spec like `-4@%edi`, `8@-8(%rbp)` or `4@$5` on x86, or
+// `8@x0`, `-4@[sp, 12]` or `4@5` on aarch64.
+fn parse_arg(spec: &str) -> Option<UsdtArg> {
+    let (size, location) = spec.split_once('@')?;
+    let size = size.parse::<i8>().ok()?;
+    if ![1, 2, 4, 8].contains(&size.unsigned_abs()) {
+        return None;
+    }
+
+    let location = if let Some(value) = location.strip_prefix('$') {
+        UsdtArgLocation::Constant(parse_int(value)?)
+    } else if let Some(register) = location.strip_prefix('%') {
+        UsdtArgLocation::Register(parse_register(register)?)
+    } else if let Some(memory) = location.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+        let (register, offset) = match memory.split_once(',') {
+            Some((register, offset)) => (register, parse_int(offset.trim())?),
+            None => (memory, 0),
+        };
+        UsdtArgLocation::Memory {
+            register: parse_register(register.trim())?,
+            offset,
+        }
+    } else if let Some((offset, register)) =
+        location.strip_suffix(')').and_then(|l| l.split_once("(%"))
+    {
+        let offset = match offset {
+            "" => 0,
+            offset => parse_int(offset)?,
+        };
+        UsdtArgLocation::Memory {
+            register: parse_register(register)?,
+            offset,
+        }
+    } else if let Some(value) = parse_int(location) {
+        UsdtArgLocation::Constant(value)
+    } else {
+        UsdtArgLocation::Register(parse_register(location)?)
+    };
+
+    Some(UsdtArg {
+        size: size.unsigned_abs(),
+        signed: size < 0,
+        location,
+    })
+}
+
+fn parse_register(register: &str) -> Option<String> {
+    (!register.is_empty() && register.bytes().all(|b| b.is_ascii_alphanumeric()))
+        .then(|| register.to_owned())
+}
+
+fn parse_int(value: &str) -> Option<i64> {
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value),
+    };
+    let value = match value.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => value.parse::<i64>().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// The type returned when attaching a [`Usdt`] fails.
+#[derive(Debug, Error)]
+pub enum UsdtError {
+    /// There was an error accessing `filename`.
+    #[error("`{filename}`")]
+    FileError {
+        /// The file name
+        filename: String,
+        /// The [`io::Error`](std::io::Error) returned from the file operation
+        #[source]
+        io_error: std::io::Error,
+    },
+
+    /// There was an error parsing the target binary.
+    #[error("error parsing ELF `{path}`")]
+    ElfError {
+        /// path to the target
+        path: PathBuf,
+        /// the original error
+        #[source]
+        error: Box<dyn Error + Send + Sync>,
+    },
+
+    /// The `.note.stapsdt` section of the target is malformed.
+    #[error("invalid USDT note in `{path}`")]
+    InvalidNote {
+        /// path to the target
+        path: PathBuf,
+    },
+
+    /// An argument spec of a probe could not be parsed.
+    #[error("invalid USDT argument spec `{spec}`")]
+    InvalidArgSpec {
+        /// The argument spec
+        spec: String,
+    },
+
+    /// An argument of a probe is held by a register the program can't read.
+    #[error("unsupported register `{register}` in USDT argument spec")]
+    UnsupportedRegister {
+        /// The register
+        register: String,
+    },
+
+    /// The probe has more arguments than the program can read.
+    #[error("USDT probe has {count} arguments, at most 12 are supported")]
+    TooManyArgs {
+        /// The number of arguments of the probe
+        count: usize,
+    },
+
+    /// The specs map is too small to hold the argument specs of all the
+    /// attached probes.
+    #[error("the `AYA_USDT_SPECS` map is full ({max_entries} specs)")]
+    TooManySpecs {
+        /// The size of the specs map
+        max_entries: u32,
+    },
+
+    /// The probe is not defined in the target.
+    #[error("USDT probe `{provider}:{name}` not found in `{path}`")]
+    ProbeNotFound {
+        /// The provider of the probe
+        provider: String,
+        /// The name of the probe
+        name: String,
+        /// path to the target
+        path: PathBuf,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, ffi::CString, io};
+
+    use libc::EINVAL;
+    use object::SectionIndex;
+
+    use super::*;
+    use crate::{
+        bpf_map_def,
+        generated::{bpf_cmd, bpf_map_type::BPF_MAP_TYPE_ARRAY},
+        obj::{self, maps::LegacyMap, BpfSectionKind, KernelVersion, ProgramSection},
+        sys::{override_syscall, Syscall},
+    };
+
+    fn fake_note(desc: &[u8]) -> Vec<u8> {
+        let mut note = Vec::new();
+        note.extend_from_slice(&(STAPSDT_NOTE_NAME.len() as u32).to_le_bytes());
+        note.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+        note.extend_from_slice(&NT_STAPSDT.to_le_bytes());
+        note.extend_from_slice(STAPSDT_NOTE_NAME);
+        note.extend_from_slice(desc);
+        note.resize((note.len() + 3) & !3, 0);
+        note
+    }
+
+    #[test]
+    fn test_parse_notes() {
+        let mut desc = Vec::new();
+        desc.extend_from_slice(&0x1234u64.to_le_bytes());
+        desc.extend_from_slice(&0x4000u64.to_le_bytes());
+        desc.extend_from_slice(&0x8000u64.to_le_bytes());
+        desc.extend_from_slice(b"python\0function__entry\08@%rbx -4@%eax\0");
+
+        let mut data = fake_note(&desc);
+        data.extend(fake_note(&desc[..24]));
+
+        assert_eq!(parse_notes(&data[..data.len() - 1], true, true), None);
+        assert_eq!(parse_notes(&data, true, true), None);
+        assert_eq!(
+            parse_notes(&fake_note(&desc), true, true),
+            Some(vec![UsdtNote {
+                pc: 0x1234,
+                base: 0x4000,
+                semaphore: 0x8000,
+                provider: "python".to_owned(),
+                name: "function__entry".to_owned(),
+                args: "8@%rbx -4@%eax".to_owned(),
+            }])
+        );
+    }
+
+    #[test]
+    fn test_parse_arg() {
+        let arg = |spec| parse_arg(spec).unwrap();
+        let register = |r: &str| UsdtArgLocation::Register(r.to_owned());
+        let memory = |r: &str, offset| UsdtArgLocation::Memory {
+            register: r.to_owned(),
+            offset,
+        };
+
+        assert_eq!(
+            arg("-4@%edi"),
+            UsdtArg {
+                size: 4,
+                signed: true,
+                location: register("edi"),
+            }
+        );
+        assert_eq!(arg("8@$-5").location, UsdtArgLocation::Constant(-5));
+        assert_eq!(arg("8@-8(%rbp)").location, memory("rbp", -8));
+        assert_eq!(arg("2@0x10(%rax)").location, memory("rax", 16));
+        assert_eq!(arg("8@(%rsi)").location, memory("rsi", 0));
+
+        assert_eq!(arg("8@x0").location, register("x0"));
+        assert_eq!(arg("-4@[sp, 12]").location, memory("sp", 12));
+        assert_eq!(arg("8@[x1]").location, memory("x1", 0));
+        assert_eq!(arg("4@5").location, UsdtArgLocation::Constant(5));
+
+        assert!(parse_arg("8%rax").is_none());
+        assert!(parse_arg("3@%rax").is_none());
+        assert!(parse_arg("8@%fs:0x28").is_none());
+        assert!(parse_arg("8@(%rax,%rbx,8)").is_none());
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse_args(""), Ok(Vec::new()));
+        assert_eq!(
+            parse_args("8@%rbx -4@%eax")
+                .unwrap()
+                .iter()
+                .map(UsdtArg::size)
+                .collect::<Vec<_>>(),
+            [8, 4]
+        );
+        assert_eq!(
+            parse_args("8@%rbx 8@(%rax,%rdx,8) 3@%rcx"),
+            Err("8@(%rax,%rdx,8)".to_owned())
+        );
+
+        let probe = UsdtProbe {
+            provider: "python".to_owned(),
+            name: "function__entry".to_owned(),
+            offset: 0x1234,
+            semaphore_offset: None,
+            args: parse_args("8@(%rax,%rdx,8)"),
+        };
+        assert!(matches!(
+            probe.args(),
+            Err(UsdtError::InvalidArgSpec { spec }) if spec == "8@(%rax,%rdx,8)"
+        ));
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn test_usdt_spec() {
+        let spec = UsdtSpec::new(&parse_args("-4@%edi 8@-8(%rbp) 2@$5 1@%r9b").unwrap()).unwrap();
+        assert_eq!(spec.arg_count, 4);
+        assert_eq!(
+            spec.args[..4],
+            [
+                UsdtArgSpec {
+                    val_off: 0,
+                    arg_type: USDT_ARG_REG,
+                    reg_off: 112,
+                    signed: true,
+                    bitshift: 32,
+                },
+                UsdtArgSpec {
+                    val_off: -8i64 as u64,
+                    arg_type: USDT_ARG_REG_DEREF,
+                    reg_off: 32,
+                    signed: false,
+                    bitshift: 0,
+                },
+                UsdtArgSpec {
+                    val_off: 5,
+                    arg_type: USDT_ARG_CONST,
+                    reg_off: 0,
+                    signed: false,
+                    bitshift: 48,
+                },
+                UsdtArgSpec {
+                    val_off: 0,
+                    arg_type: USDT_ARG_REG,
+                    reg_off: 64,
+                    signed: false,
+                    bitshift: 56,
+                },
+            ]
+        );
+
+        assert!(matches!(
+            UsdtSpec::new(&parse_args("8@%xmm0").unwrap()),
+            Err(UsdtError::UnsupportedRegister { register }) if register == "xmm0"
+        ));
+        assert!(matches!(
+            UsdtSpec::new(&parse_args(&["8@$1"; 13].join(" ")).unwrap()),
+            Err(UsdtError::TooManyArgs { count: 13 })
+        ));
+    }
+
+    thread_local! {
+        static SPEC_UPDATES: Cell<u32> = const { Cell::new(0) };
+    }
+
+    fn new_usdt() -> Usdt {
+        let obj = obj::Program {
+            license: CString::new("GPL").unwrap(),
+            kernel_version: KernelVersion::Any,
+            section: ProgramSection::Usdt {
+                name: "probe".to_owned(),
+            },
+            function: obj::Function {
+                address: 0,
+                name: "probe".to_owned(),
+                section_index: SectionIndex(0),
+                section_offset: 0,
+                instructions: Vec::new(),
+                func_info: Default::default(),
+                line_info: Default::default(),
+                func_info_rec_size: 0,
+                line_info_rec_size: 0,
+            },
+        };
+        let specs = MapData {
+            obj: obj::Map::Legacy(LegacyMap {
+                def: bpf_map_def {
+                    map_type: BPF_MAP_TYPE_ARRAY as u32,
+                    key_size: 4,
+                    value_size: mem::size_of::<UsdtSpec>() as u32,
+                    max_entries: 2,
+                    ..Default::default()
+                },
+                section_index: 0,
+                section_kind: BpfSectionKind::Maps,
+                symbol_index: None,
+                data: Vec::new(),
+            }),
+            fd: Some(42),
+            pinned: false,
+            btf_fd: None,
+        };
+        Usdt {
+            data: ProgramData::new(Some("probe".to_owned()), obj, None, 0, None),
+            specs: Some(specs),
+            spec_ids: HashMap::new(),
+        }
+    }
+
+    fn new_probe(args: &str) -> UsdtProbe {
+        UsdtProbe {
+            provider: "python".to_owned(),
+            name: "function__entry".to_owned(),
+            offset: 0x1234,
+            semaphore_offset: None,
+            args: parse_args(args),
+        }
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_spec_id() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_UPDATE_ELEM,
+                attr,
+            } => {
+                let key = unsafe { *(attr.__bindgen_anon_2.key as *const u32) };
+                let spec =
+                    unsafe { &*(attr.__bindgen_anon_2.__bindgen_anon_1.value as *const UsdtSpec) };
+                assert_eq!(u32::from(spec.arg_count), key + 1);
+                SPEC_UPDATES.with(|updates| updates.set(updates.get() + 1));
+                Ok(0)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        let mut usdt = new_usdt();
+        assert_eq!(usdt.spec_id(&new_probe("8@$1")).unwrap(), Some(0));
+        assert_eq!(usdt.spec_id(&new_probe("8@$1 8@$2")).unwrap(), Some(1));
+        // identical specs are stored once
+        assert_eq!(usdt.spec_id(&new_probe("8@$1")).unwrap(), Some(0));
+        assert_eq!(SPEC_UPDATES.with(Cell::get), 2);
+
+        assert!(matches!(
+            usdt.spec_id(&new_probe("8@$1 8@$2 8@$3")),
+            Err(ProgramError::UsdtError(UsdtError::TooManySpecs {
+                max_entries: 2
+            }))
+        ));
+        assert!(matches!(
+            usdt.spec_id(&new_probe("8@(%rax,%rdx,8)")),
+            Err(ProgramError::UsdtError(UsdtError::InvalidArgSpec { .. }))
+        ));
+
+        // without the specs map, no cookie is passed to the program
+        usdt.specs = None;
+        assert_eq!(usdt.spec_id(&new_probe("8@(%rax,%rdx,8)")).unwrap(), None);
+    }
+}
@@ -2,10 +2,37 @@
 use std::{ffi::CStr, io, os::unix::io::RawFd, path::Path};
 
 use crate::{
+    obj::btf::{Btf, BtfKind, BtfType},
     programs::{FdLink, Link, ProgramData, ProgramError},
-    sys::bpf_raw_tracepoint_open,
+    sys::{bpf_raw_tracepoint_open, kernel_version},
 };
 
+// The arguments BPF trampolines can pass to tracing programs.
+#[derive(Debug, Clone, Copy)]
+struct TracingLimits {
+    max_args: usize,
+    // The maximum size of struct and union arguments, 0 if they aren't
+    // supported.
+    max_struct_size: u32,
+}
+
+impl TracingLimits {
+    fn for_kernel(k_ver: (u32, u32, u32)) -> TracingLimits {
+        if k_ver >= (6, 1, 0) {
+            // MAX_BPF_FUNC_ARGS, struct arguments were added in 6.1
+            TracingLimits {
+                max_args: 12,
+                max_struct_size: 16,
+            }
+        } else {
+            TracingLimits {
+                max_args: 6,
+                max_struct_size: 0,
+            }
+        }
+    }
+}
+
 /// Attaches the program to a raw tracepoint.
 pub(crate) fn attach_raw_tracepoint<T: Link + From<FdLink>>(
     program_data: &mut ProgramData<T>,
@@ -23,6 +50,95 @@ pub(crate) fn attach_raw_tracepoint<T: Link + From<FdLink>>(
     program_data.links.insert(FdLink::new(pfd).into())
 }
 
+/// Returns the BTF id of a kernel function to be traced by fentry or fexit
+/// programs, checking that its signature is supported by BPF trampolines.
+///
+/// This mirrors the checks done by the kernel in `btf_distill_func_proto()`,
+/// which otherwise only show up as a verifier error when loading.
+pub(crate) fn tracing_target_btf_id(btf: &Btf, fn_name: &str) -> Result<u32, ProgramError> {
+    // If the version can't be read, let the kernel do the checks.
+    let k_ver = kernel_version().unwrap_or((u32::MAX, 0, 0));
+    check_tracing_target(btf, fn_name, TracingLimits::for_kernel(k_ver))
+}
+
+fn check_tracing_target(
+    btf: &Btf,
+    fn_name: &str,
+    limits: TracingLimits,
+) -> Result<u32, ProgramError> {
+    let unsupported = |reason: String| ProgramError::UnsupportedTracingTarget {
+        function: fn_name.to_owned(),
+        reason,
+    };
+
+    let func_id = btf.id_by_type_name_kind(fn_name, BtfKind::Func)?;
+    let proto = match btf.type_by_id(func_id)? {
+        BtfType::Func(func) => match btf.type_by_id(func.btf_type())? {
+            BtfType::FuncProto(proto) => proto,
+            _ => return Err(unsupported("it has no prototype".to_owned())),
+        },
+        _ => unreachable!(),
+    };
+
+    let params = proto.params();
+    if params.len() > limits.max_args {
+        return Err(unsupported(format!(
+            "it has {} arguments, at most {} are supported",
+            params.len(),
+            limits.max_args
+        )));
+    }
+    for (i, param) in params.iter().enumerate() {
+        if param.btf_type == 0 {
+            return Err(unsupported(
+                "variadic functions aren't supported".to_owned(),
+            ));
+        }
+        if let Some(type_name) = unsupported_tracing_type(btf, param.btf_type, limits)? {
+            let name = btf.string_at(param.name_offset)?;
+            return Err(unsupported(format!(
+                "argument {i} `{name}` has unsupported type `{type_name}`"
+            )));
+        }
+    }
+    if proto.return_type() != 0 {
+        if let Some(type_name) = unsupported_tracing_type(btf, proto.return_type(), limits)? {
+            return Err(unsupported(format!(
+                "the return type `{type_name}` isn't supported"
+            )));
+        }
+    }
+
+    Ok(func_id)
+}
+
+// Returns the name of the type if it can't be passed to a BPF trampoline.
+// Arguments must be integers, enums, pointers, or structs and unions small
+// enough for the kernel.
+fn unsupported_tracing_type(
+    btf: &Btf,
+    type_id: u32,
+    limits: TracingLimits,
+) -> Result<Option<String>, ProgramError> {
+    let ty = btf.type_by_id(btf.resolve_type(type_id)?)?;
+    let supported = match ty {
+        BtfType::Int(_) | BtfType::Enum(_) | BtfType::Enum64(_) | BtfType::Ptr(_) => true,
+        BtfType::Struct(_) | BtfType::Union(_) => {
+            ty.size().is_some_and(|size| size <= limits.max_struct_size)
+        }
+        _ => false,
+    };
+    if supported {
+        return Ok(None);
+    }
+    let name = btf.type_name(ty)?;
+    Ok(Some(if name.is_empty() {
+        ty.kind().to_string()
+    } else {
+        name.into_owned()
+    }))
+}
+
 /// Find tracefs filesystem path
 pub(crate) fn find_tracefs_path() -> Result<&'static Path, ProgramError> {
     lazy_static::lazy_static! {
@@ -51,3 +167,113 @@ pub(crate) fn find_tracefs_path() -> Result<&'static Path, ProgramError> {
         .as_deref()
         .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "tracefs not found").into())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::obj::btf::{
+        BtfParam, Float, Func, FuncLinkage, FuncProto, Int, IntEncoding, Ptr, Struct,
+    };
+
+    use super::*;
+
+    fn add_func(btf: &mut Btf, name: &str, params: &[u32], return_type: u32) -> u32 {
+        let params = params
+            .iter()
+            .map(|btf_type| BtfParam {
+                name_offset: btf.add_string("arg".to_string()),
+                btf_type: *btf_type,
+            })
+            .collect();
+        let proto = btf.add_type(BtfType::FuncProto(FuncProto::new(params, return_type)));
+        let name_offset = btf.add_string(name.to_string());
+        btf.add_type(BtfType::Func(Func::new(
+            name_offset,
+            proto,
+            FuncLinkage::Global,
+        )))
+    }
+
+    #[test]
+    fn test_tracing_target_btf_id() {
+        let mut btf = Btf::new();
+        let int_name = btf.add_string("int".to_string());
+        let int = btf.add_type(BtfType::Int(Int::new(int_name, 4, IntEncoding::Signed, 0)));
+        let ptr = btf.add_type(BtfType::Ptr(Ptr::new(0, int)));
+        let double_name = btf.add_string("double".to_string());
+        let double = btf.add_type(BtfType::Float(Float::new(double_name, 8)));
+
+        let ok = add_func(&mut btf, "ok", &[int, ptr], ptr);
+        add_func(&mut btf, "variadic", &[int, 0], int);
+        add_func(&mut btf, "float_arg", &[int, double], int);
+        add_func(&mut btf, "float_ret", &[], double);
+        add_func(&mut btf, "many_args", &[int; 13], 0);
+
+        let limits = TracingLimits::for_kernel((6, 1, 0));
+        assert_eq!(check_tracing_target(&btf, "ok", limits).unwrap(), ok);
+        assert!(matches!(
+            check_tracing_target(&btf, "missing", limits),
+            Err(ProgramError::Btf(_))
+        ));
+        for (function, expected) in [
+            ("variadic", "variadic functions aren't supported"),
+            (
+                "float_arg",
+                "argument 1 `arg` has unsupported type `double`",
+            ),
+            ("float_ret", "the return type `double` isn't supported"),
+            ("many_args", "it has 13 arguments, at most 12 are supported"),
+        ] {
+            match check_tracing_target(&btf, function, limits) {
+                Err(ProgramError::UnsupportedTracingTarget { reason, .. }) => {
+                    assert_eq!(reason, expected)
+                }
+                r => panic!("unexpected result for {function}: {r:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_tracing_limits() {
+        let mut btf = Btf::new();
+        let int_name = btf.add_string("int".to_string());
+        let int = btf.add_type(BtfType::Int(Int::new(int_name, 4, IntEncoding::Signed, 0)));
+        let pair_name = btf.add_string("pair".to_string());
+        let pair = btf.add_type(BtfType::Struct(Struct::new(pair_name, Vec::new(), 8)));
+        let big_name = btf.add_string("big".to_string());
+        let big = btf.add_type(BtfType::Struct(Struct::new(big_name, Vec::new(), 24)));
+
+        let seven_args = add_func(&mut btf, "seven_args", &[int; 7], 0);
+        let struct_arg = add_func(&mut btf, "struct_arg", &[pair], 0);
+        add_func(&mut btf, "big_struct_arg", &[big], 0);
+
+        let reason = |function, limits| match check_tracing_target(&btf, function, limits) {
+            Err(ProgramError::UnsupportedTracingTarget { reason, .. }) => reason,
+            r => panic!("unexpected result for {function}: {r:?}"),
+        };
+
+        // Before 6.1, at most 6 arguments and no structs
+        let old = TracingLimits::for_kernel((6, 0, 19));
+        assert_eq!(
+            reason("seven_args", old),
+            "it has 7 arguments, at most 6 are supported"
+        );
+        assert_eq!(
+            reason("struct_arg", old),
+            "argument 0 `arg` has unsupported type `pair`"
+        );
+
+        let new = TracingLimits::for_kernel((6, 1, 0));
+        assert_eq!(
+            check_tracing_target(&btf, "seven_args", new).unwrap(),
+            seven_args
+        );
+        assert_eq!(
+            check_tracing_target(&btf, "struct_arg", new).unwrap(),
+            struct_arg
+        );
+        assert_eq!(
+            reason("big_struct_arg", new),
+            "argument 0 `arg` has unsupported type `big`"
+        );
+    }
+}
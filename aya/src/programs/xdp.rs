@@ -1,26 +1,37 @@
 //! eXpress Data Path (XDP) programs.
+pub use aya_obj::programs::XdpAttachType;
 use bitflags;
 use libc::if_nametoindex;
-use std::{convert::TryFrom, ffi::CString, hash::Hash, io, mem, os::unix::io::RawFd};
+use std::{
+    convert::TryFrom,
+    ffi::CString,
+    hash::Hash,
+    io, mem,
+    os::unix::io::{AsRawFd, RawFd},
+    path::Path,
+};
 use thiserror::Error;
 
 use crate::{
     generated::{
-        bpf_attach_type::{self, BPF_XDP},
-        bpf_link_type,
-        bpf_prog_type::BPF_PROG_TYPE_XDP,
-        XDP_FLAGS_DRV_MODE, XDP_FLAGS_HW_MODE, XDP_FLAGS_REPLACE, XDP_FLAGS_SKB_MODE,
-        XDP_FLAGS_UPDATE_IF_NOEXIST,
+        bpf_attach_type::BPF_XDP, bpf_link_type, bpf_prog_type::BPF_PROG_TYPE_XDP,
+        BPF_F_XDP_HAS_FRAGS, XDP_FLAGS_DRV_MODE, XDP_FLAGS_HW_MODE, XDP_FLAGS_REPLACE,
+        XDP_FLAGS_SKB_MODE, XDP_FLAGS_UPDATE_IF_NOEXIST,
     },
     programs::{
         define_link_wrapper, load_program, FdLink, Link, LinkError, ProgramData, ProgramError,
+        ProgramFd,
     },
     sys::{
-        bpf_link_create, bpf_link_get_info_by_fd, bpf_link_update, kernel_version,
-        netlink_set_xdp_fd,
+        bpf_link_create, bpf_link_update, kernel_version, netlink_set_xdp_fd, netlink_xdp_features,
     },
 };
 
+// NETDEV_XDP_ACT_* features reported by the netdev generic netlink family
+const NETDEV_XDP_ACT_BASIC: u64 = 1 << 0;
+const NETDEV_XDP_ACT_HW_OFFLOAD: u64 = 1 << 4;
+const NETDEV_XDP_ACT_RX_SG: u64 = 1 << 5;
+
 /// The type returned when attaching an [`Xdp`] program fails on kernels `< 5.9`.
 #[derive(Debug, Error)]
 pub enum XdpError {
@@ -41,7 +52,8 @@ bitflags! {
         const SKB_MODE = XDP_FLAGS_SKB_MODE;
         /// Driver mode.
         const DRV_MODE = XDP_FLAGS_DRV_MODE;
-        /// Hardware mode.
+        /// Hardware mode. The program must have been loaded with
+        /// [`Xdp::load_offloaded()`].
         const HW_MODE = XDP_FLAGS_HW_MODE;
         /// Replace a previously attached XDP program.
         const REPLACE = XDP_FLAGS_REPLACE;
@@ -57,6 +69,16 @@ bitflags! {
 /// underlying network driver, XDP programs can execute directly on network cards, greatly
 /// reducing CPU load.
 ///
+/// Programs declared with `#[xdp(frags = "true")]`, placed in the
+/// `xdp.frags` section, are loaded with `BPF_F_XDP_HAS_FRAGS` and can process
+/// multi-buffer packets, as received with jumbo frames or hardware GRO.
+///
+/// Programs placed in the `xdp/cpumap` and `xdp/devmap` sections, or declared
+/// with `#[xdp(map = "cpumap")]` and `#[xdp(map = "devmap")]`, are not attached
+/// to an interface but to the entries of a [`CpuMap`](crate::maps::CpuMap) or
+/// a [`DevMap`](crate::maps::DevMap), and run on the packets redirected to
+/// them.
+///
 /// # Minimum kernel version
 ///
 /// The minimum kernel version required to use this feature is 4.8.
@@ -75,15 +97,129 @@ bitflags! {
 #[doc(alias = "BPF_PROG_TYPE_XDP")]
 pub struct Xdp {
     pub(crate) data: ProgramData<XdpLink>,
+    pub(crate) attach_type: XdpAttachType,
 }
 
 impl Xdp {
     /// Loads the program inside the kernel.
     pub fn load(&mut self) -> Result<(), ProgramError> {
-        self.data.expected_attach_type = Some(bpf_attach_type::BPF_XDP);
+        self.data.expected_attach_type = Some(self.attach_type.into());
         load_program(BPF_PROG_TYPE_XDP, &mut self.data)
     }
 
+    /// Returns where the program is meant to be attached, as defined by its
+    /// section.
+    pub fn attach_type(&self) -> XdpAttachType {
+        self.attach_type
+    }
+
+    /// Returns `true` if the program supports multi-buffer packets.
+    pub fn frags(&self) -> bool {
+        self.data.flags & BPF_F_XDP_HAS_FRAGS != 0
+    }
+
+    /// Sets whether the program supports multi-buffer packets.
+    ///
+    /// This is set from the `xdp.frags` section of the program, and can be
+    /// overridden before calling [`Xdp::load`], for example for programs
+    /// compiled with other toolchains. A program supporting multi-buffer
+    /// packets must access the packet with `bpf_xdp_load_bytes` and
+    /// `bpf_xdp_store_bytes` past the first fragment.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 5.18.
+    pub fn set_frags(&mut self, frags: bool) {
+        if frags {
+            self.data.flags |= BPF_F_XDP_HAS_FRAGS;
+        } else {
+            self.data.flags &= !BPF_F_XDP_HAS_FRAGS;
+        }
+    }
+
+    /// Loads the program inside the kernel, to be offloaded to the network
+    /// card of `interface`.
+    ///
+    /// The program can then only be attached to `interface`, with
+    /// [`XdpFlags::HW_MODE`]. Whether the network card supports offloading
+    /// can be checked with [`Xdp::supported_modes`].
+    ///
+    /// The maps used by the program must be bound to the same network card
+    /// when they are created, with
+    /// [`BpfLoader::set_map_ifindex`](crate::BpfLoader::set_map_ifindex).
+    ///
+    /// # Errors
+    ///
+    /// If the given `interface` does not exist
+    /// [`ProgramError::UnknownInterface`] is returned.
+    pub fn load_offloaded(&mut self, interface: &str) -> Result<(), ProgramError> {
+        self.data.prog_ifindex = Some(if_index(interface)?);
+        self.load()
+    }
+
+    /// Returns the modes XDP programs can be attached with to `interface`.
+    ///
+    /// [`XdpFlags::SKB_MODE`] is always supported, [`XdpFlags::DRV_MODE`] is
+    /// returned if the driver of `interface` supports XDP and
+    /// [`XdpFlags::HW_MODE`] if the network card supports offloading XDP
+    /// programs.
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 6.3.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::programs::{Xdp, XdpFlags};
+    ///
+    /// let modes = Xdp::supported_modes("eth0")?;
+    /// let flags = if modes.contains(XdpFlags::DRV_MODE) {
+    ///     XdpFlags::DRV_MODE
+    /// } else {
+    ///     XdpFlags::SKB_MODE
+    /// };
+    /// let program: &mut Xdp = bpf.program_mut("intercept_packets").unwrap().try_into()?;
+    /// program.load()?;
+    /// program.attach("eth0", flags)?;
+    /// # Ok::<(), aya::BpfError>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If the given `interface` does not exist
+    /// [`ProgramError::UnknownInterface`] is returned. If the query fails,
+    /// for example on older kernels, [`XdpError::NetlinkError`] is returned.
+    pub fn supported_modes(interface: &str) -> Result<XdpFlags, ProgramError> {
+        let features = xdp_features(interface)?;
+
+        let mut modes = XdpFlags::SKB_MODE;
+        if features & NETDEV_XDP_ACT_BASIC != 0 {
+            modes |= XdpFlags::DRV_MODE;
+        }
+        if features & NETDEV_XDP_ACT_HW_OFFLOAD != 0 {
+            modes |= XdpFlags::HW_MODE;
+        }
+        Ok(modes)
+    }
+
+    /// Returns `true` if the driver of `interface` supports multi-buffer
+    /// packets in driver mode, see [`Xdp::set_frags`].
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 6.3.
+    ///
+    /// # Errors
+    ///
+    /// If the given `interface` does not exist
+    /// [`ProgramError::UnknownInterface`] is returned. If the query fails,
+    /// for example on older kernels, [`XdpError::NetlinkError`] is returned.
+    pub fn supports_frags(interface: &str) -> Result<bool, ProgramError> {
+        Ok(xdp_features(interface)? & NETDEV_XDP_ACT_RX_SG != 0)
+    }
+
     /// Attaches the program to the given `interface`.
     ///
     /// The returned value can be used to detach, see [Xdp::detach].
@@ -98,13 +234,7 @@ impl Xdp {
     /// [`XdpError::NetlinkError`] is returned for older
     /// kernels.
     pub fn attach(&mut self, interface: &str, flags: XdpFlags) -> Result<XdpLinkId, ProgramError> {
-        let c_interface = CString::new(interface).unwrap();
-        let if_index = unsafe { if_nametoindex(c_interface.as_ptr()) };
-        if if_index == 0 {
-            return Err(ProgramError::UnknownInterface {
-                name: interface.to_string(),
-            });
-        }
+        let if_index = if_index(interface)?;
         self.attach_to_if_index(if_index, flags)
     }
 
@@ -151,6 +281,81 @@ impl Xdp {
         }
     }
 
+    /// Atomically replaces the program attached to `interface` with this
+    /// program.
+    ///
+    /// The replacement only happens if `old_program` is the program currently
+    /// attached to `interface`, so there is no window where no program, or a
+    /// program installed concurrently by someone else, is attached. This is
+    /// meant for upgrades where the previous program was attached by another
+    /// process, and is for example retrieved with [`Xdp::from_pin`]. To
+    /// replace the program of a link owned by this process, use
+    /// [`Xdp::attach_to_link`].
+    ///
+    /// `flags` must contain the same mode as the one `old_program` was
+    /// attached with.
+    ///
+    /// The returned value can be used to detach, see [Xdp::detach].
+    ///
+    /// # Minimum kernel version
+    ///
+    /// The minimum kernel version required to use this feature is 5.7.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[derive(Debug, thiserror::Error)]
+    /// # enum Error {
+    /// #     #[error(transparent)]
+    /// #     Program(#[from] aya::programs::ProgramError),
+    /// #     #[error(transparent)]
+    /// #     Bpf(#[from] aya::BpfError)
+    /// # }
+    /// # let mut bpf = aya::Bpf::load(&[])?;
+    /// use aya::programs::{Xdp, XdpFlags};
+    ///
+    /// let old = Xdp::from_pin("/sys/fs/bpf/firewall")?;
+    /// let program: &mut Xdp = bpf.program_mut("firewall").unwrap().try_into()?;
+    /// program.load()?;
+    /// program.attach_replacing("eth0", &old.fd().unwrap(), XdpFlags::DRV_MODE)?;
+    /// # Ok::<(), Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If the given `interface` does not exist
+    /// [`ProgramError::UnknownInterface`] is returned. If `old_program` is
+    /// not attached to `interface` or the replacement fails,
+    /// [`XdpError::NetlinkError`] is returned.
+    pub fn attach_replacing(
+        &mut self,
+        interface: &str,
+        old_program: &ProgramFd,
+        flags: XdpFlags,
+    ) -> Result<XdpLinkId, ProgramError> {
+        let if_index = if_index(interface)? as RawFd;
+        let prog_fd = self.data.fd_or_err()?;
+        let flags = flags - XdpFlags::REPLACE;
+
+        unsafe {
+            netlink_set_xdp_fd(
+                if_index,
+                prog_fd,
+                Some(old_program.as_raw_fd()),
+                (flags | XdpFlags::REPLACE).bits(),
+            )
+        }
+        .map_err(|io_error| XdpError::NetlinkError { io_error })?;
+
+        self.data
+            .links
+            .insert(XdpLink::new(XdpLinkInner::NlLink(NlLink {
+                if_index,
+                prog_fd,
+                flags,
+            })))
+    }
+
     /// Detaches the program.
     ///
     /// See [Xdp::attach].
@@ -166,6 +371,31 @@ impl Xdp {
         self.data.take_link(link_id)
     }
 
+    /// Creates a program from a pinned entry on a bpffs.
+    ///
+    /// Existing links will not be populated. To work with existing links you should use [`crate::programs::links::PinnedLink`].
+    ///
+    /// On drop, any managed links are detached and the program is unloaded. This will not result in
+    /// the program being unloaded from the kernel if it is still pinned.
+    ///
+    /// The program is assumed to be attached to interfaces. For programs meant for a device or
+    /// CPU map, use [`Xdp::from_pin_with_attach_type`].
+    pub fn from_pin<P: AsRef<Path>>(path: P) -> Result<Self, ProgramError> {
+        Self::from_pin_with_attach_type(path, XdpAttachType::Interface)
+    }
+
+    /// Creates a program from a pinned entry on a bpffs, which is meant to be attached as
+    /// `attach_type`.
+    ///
+    /// See [`Xdp::from_pin`].
+    pub fn from_pin_with_attach_type<P: AsRef<Path>>(
+        path: P,
+        attach_type: XdpAttachType,
+    ) -> Result<Self, ProgramError> {
+        let data = ProgramData::from_pinned_path(path)?;
+        Ok(Self { data, attach_type })
+    }
+
     /// Atomically replaces the program referenced by the provided link.
     ///
     /// Ownership of the link will transfer to this program.
@@ -209,6 +439,24 @@ impl Xdp {
     }
 }
 
+fn xdp_features(interface: &str) -> Result<u64, ProgramError> {
+    let if_index = if_index(interface)?;
+    let features = unsafe { netlink_xdp_features(if_index as i32) }
+        .map_err(|io_error| XdpError::NetlinkError { io_error })?;
+    Ok(features)
+}
+
+fn if_index(interface: &str) -> Result<u32, ProgramError> {
+    let c_interface = CString::new(interface).unwrap();
+    let if_index = unsafe { if_nametoindex(c_interface.as_ptr()) };
+    if if_index == 0 {
+        return Err(ProgramError::UnknownInterface {
+            name: interface.to_string(),
+        });
+    }
+    Ok(if_index)
+}
+
 #[derive(Debug)]
 pub(crate) struct NlLink {
     if_index: i32,
@@ -281,17 +529,8 @@ impl TryFrom<FdLink> for XdpLink {
     type Error = LinkError;
 
     fn try_from(fd_link: FdLink) -> Result<Self, Self::Error> {
-        // unwrap of fd_link.fd will not panic since it's only None when being dropped.
-        let info =
-            bpf_link_get_info_by_fd(fd_link.fd).map_err(|io_error| LinkError::SyscallError {
-                call: "BPF_OBJ_GET_INFO_BY_FD".to_string(),
-                code: 0,
-                io_error,
-            })?;
-        if info.type_ == (bpf_link_type::BPF_LINK_TYPE_XDP as u32) {
-            return Ok(XdpLink::new(XdpLinkInner::FdLink(fd_link)));
-        }
-        Err(LinkError::InvalidLink)
+        fd_link.check_type(bpf_link_type::BPF_LINK_TYPE_XDP)?;
+        Ok(XdpLink::new(XdpLinkInner::FdLink(fd_link)))
     }
 }
 
@@ -303,3 +542,110 @@ define_link_wrapper!(
     XdpLinkInner,
     XdpLinkIdInner
 );
+
+#[cfg(test)]
+mod tests {
+    use libc::EINVAL;
+    use object::SectionIndex;
+
+    use super::*;
+    use crate::{
+        generated::bpf_cmd,
+        obj::{self, KernelVersion, ProgramSection},
+        sys::{override_syscall, Syscall},
+    };
+
+    fn new_xdp(frags: bool) -> Xdp {
+        let obj = obj::Program {
+            license: CString::new("GPL").unwrap(),
+            kernel_version: KernelVersion::Any,
+            section: ProgramSection::Xdp {
+                name: "xdp".to_owned(),
+                frags,
+                attach_type: XdpAttachType::Interface,
+            },
+            function: obj::Function {
+                address: 0,
+                name: "xdp".to_owned(),
+                section_index: SectionIndex(0),
+                section_offset: 0,
+                instructions: Vec::new(),
+                func_info: Default::default(),
+                line_info: Default::default(),
+                func_info_rec_size: 0,
+                line_info_rec_size: 0,
+            },
+        };
+        let mut data = ProgramData::new(Some("xdp".to_owned()), obj, None, 0, None);
+        if frags {
+            data.flags = BPF_F_XDP_HAS_FRAGS;
+        }
+        Xdp {
+            data,
+            attach_type: XdpAttachType::Interface,
+        }
+    }
+
+    #[test]
+    fn test_set_frags() {
+        let mut prog = new_xdp(true);
+        assert!(prog.frags());
+        prog.set_frags(false);
+        assert!(!prog.frags());
+        assert_eq!(prog.data.flags, 0);
+
+        let mut prog = new_xdp(false);
+        assert!(!prog.frags());
+        prog.set_frags(true);
+        assert!(prog.frags());
+        assert_eq!(prog.data.flags, BPF_F_XDP_HAS_FRAGS);
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_load_frags() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_LOAD,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_3 };
+                assert_eq!(u.prog_type, BPF_PROG_TYPE_XDP as u32);
+                assert_eq!(u.prog_flags, BPF_F_XDP_HAS_FRAGS);
+                Ok(42)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        let mut prog = new_xdp(false);
+        prog.set_frags(true);
+        prog.load().unwrap();
+        assert_eq!(prog.data.fd, Some(42));
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_load_no_frags() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_LOAD,
+                attr,
+            } => {
+                assert_eq!(unsafe { attr.__bindgen_anon_3.prog_flags }, 0);
+                Ok(42)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        let mut prog = new_xdp(true);
+        prog.set_frags(false);
+        prog.load().unwrap();
+        assert_eq!(prog.data.fd, Some(42));
+    }
+}
@@ -15,8 +15,11 @@ use obj::{
 
 use crate::{
     generated::{
-        bpf_attach_type, bpf_attr, bpf_btf_info, bpf_cmd, bpf_insn, bpf_link_info, bpf_map_info,
-        bpf_map_type, bpf_prog_info, bpf_prog_type, BPF_F_REPLACE,
+        bpf_attach_type,
+        bpf_attach_type::{BPF_PERF_EVENT, BPF_TRACE_ITER, BPF_TRACE_KPROBE_MULTI},
+        bpf_attr, bpf_btf_info, bpf_cmd, bpf_insn, bpf_line_info, bpf_link_info, bpf_map_info,
+        bpf_map_type, bpf_prog_info, bpf_prog_type, BPF_F_KPROBE_MULTI_RETURN, BPF_F_NO_PREALLOC,
+        BPF_F_REPLACE, BPF_F_SLEEPABLE,
     },
     maps::{MapData, PerCpuValues},
     obj::{
@@ -28,11 +31,89 @@ use crate::{
         copy_instructions,
     },
     sys::{kernel_version, syscall, SysResult, Syscall},
-    util::VerifierLog,
+    util::{page_size, VerifierLog},
     Btf, Pod, BPF_OBJ_NAME_LEN,
 };
 
-pub(crate) fn bpf_create_map(name: &CStr, def: &obj::Map, btf_fd: Option<RawFd>) -> SysResult {
+// The generated bindings predate BPF tokens (Linux 6.9): the command, the flag
+// and the offsets of the token fds in `bpf_attr` are defined here.
+const BPF_TOKEN_CREATE: u32 = 36;
+const BPF_F_TOKEN_FD: u32 = 1 << 16;
+// `map_create.map_token_fd`
+const MAP_TOKEN_FD_OFFSET: usize = 76;
+// `prog_load.prog_token_fd`, past the end of the generated `bpf_attr`
+const PROG_TOKEN_FD_OFFSET: usize = 144;
+// `btf_load.btf_flags` and `btf_load.btf_token_fd`
+const BTF_FLAGS_OFFSET: usize = 32;
+const BTF_TOKEN_FD_OFFSET: usize = 36;
+
+pub(crate) fn bpf_token_create(bpffs_fd: RawFd) -> SysResult {
+    // struct { __u32 flags; __u32 bpffs_fd; } token_create
+    let mut attr = [0u8; 8];
+    attr[4..].copy_from_slice(&(bpffs_fd as u32).to_ne_bytes());
+    syscall(Syscall::BpfRaw {
+        cmd: BPF_TOKEN_CREATE,
+        attr: &attr,
+    })
+}
+
+// Calls `cmd` with `attr` extended with the u32 `fields`, given as (offset,
+// value) pairs.
+fn sys_bpf_ext(cmd: bpf_cmd, attr: &bpf_attr, fields: &[(usize, u32)]) -> SysResult {
+    let attr_size = mem::size_of::<bpf_attr>();
+    let size = fields
+        .iter()
+        .map(|(offset, _)| offset + mem::size_of::<u32>())
+        .fold(attr_size, cmp::max);
+    let mut buf = vec![0u8; size];
+    buf[..attr_size].copy_from_slice(unsafe {
+        slice::from_raw_parts(attr as *const _ as *const u8, attr_size)
+    });
+    for (offset, value) in fields {
+        buf[*offset..*offset + mem::size_of::<u32>()].copy_from_slice(&value.to_ne_bytes());
+    }
+    syscall(Syscall::BpfRaw {
+        cmd: cmd as u32,
+        attr: &buf,
+    })
+}
+
+// Calls `cmd` with the privileges delegated by `token_fd`, if set. Only
+// BPF_MAP_CREATE, BPF_PROG_LOAD and BPF_BTF_LOAD accept a token.
+fn sys_bpf_token(cmd: bpf_cmd, attr: &mut bpf_attr, token_fd: Option<RawFd>) -> SysResult {
+    let token_fd = match token_fd {
+        Some(token_fd) => token_fd as u32,
+        None => return sys_bpf(cmd, attr),
+    };
+    match cmd {
+        bpf_cmd::BPF_MAP_CREATE => {
+            unsafe { attr.__bindgen_anon_1.map_flags |= BPF_F_TOKEN_FD };
+            sys_bpf_ext(cmd, attr, &[(MAP_TOKEN_FD_OFFSET, token_fd)])
+        }
+        bpf_cmd::BPF_PROG_LOAD => {
+            unsafe { attr.__bindgen_anon_3.prog_flags |= BPF_F_TOKEN_FD };
+            sys_bpf_ext(cmd, attr, &[(PROG_TOKEN_FD_OFFSET, token_fd)])
+        }
+        bpf_cmd::BPF_BTF_LOAD => sys_bpf_ext(
+            cmd,
+            attr,
+            &[
+                (BTF_FLAGS_OFFSET, BPF_F_TOKEN_FD),
+                (BTF_TOKEN_FD_OFFSET, token_fd),
+            ],
+        ),
+        _ => sys_bpf(cmd, attr),
+    }
+}
+
+pub(crate) fn bpf_create_map(
+    name: &CStr,
+    def: &obj::Map,
+    btf_fd: Option<RawFd>,
+    inner_map_fd: Option<RawFd>,
+    map_ifindex: Option<u32>,
+    token_fd: Option<RawFd>,
+) -> SysResult {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
 
     let u = unsafe { &mut attr.__bindgen_anon_1 };
@@ -41,6 +122,13 @@ pub(crate) fn bpf_create_map(name: &CStr, def: &obj::Map, btf_fd: Option<RawFd>)
     u.value_size = def.value_size();
     u.max_entries = def.max_entries();
     u.map_flags = def.map_flags();
+    u.map_extra = def.map_extra();
+    if let Some(fd) = inner_map_fd {
+        u.inner_map_fd = fd as u32;
+    }
+    if let Some(if_index) = map_ifindex {
+        u.map_ifindex = if_index;
+    }
 
     if let obj::Map::Btf(m) = def {
         use bpf_map_type::*;
@@ -86,6 +174,28 @@ pub(crate) fn bpf_create_map(name: &CStr, def: &obj::Map, btf_fd: Option<RawFd>)
             .copy_from_slice(unsafe { slice::from_raw_parts(name.as_ptr(), name_len) });
     }
 
+    sys_bpf_token(bpf_cmd::BPF_MAP_CREATE, &mut attr, token_fd)
+}
+
+pub(crate) fn bpf_create_struct_ops_map(
+    name: &CStr,
+    value_size: u32,
+    btf_vmlinux_value_type_id: u32,
+) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    let u = unsafe { &mut attr.__bindgen_anon_1 };
+    u.map_type = bpf_map_type::BPF_MAP_TYPE_STRUCT_OPS as u32;
+    u.key_size = mem::size_of::<u32>() as u32;
+    u.value_size = value_size;
+    u.max_entries = 1;
+    u.btf_vmlinux_value_type_id = btf_vmlinux_value_type_id;
+
+    // struct_ops maps need kernel 5.6+, so the map name is always supported
+    let name_len = cmp::min(name.to_bytes().len(), BPF_OBJ_NAME_LEN - 1);
+    u.map_name[..name_len]
+        .copy_from_slice(unsafe { slice::from_raw_parts(name.as_ptr(), name_len) });
+
     sys_bpf(bpf_cmd::BPF_MAP_CREATE, &attr)
 }
 
@@ -111,15 +221,21 @@ pub(crate) struct BpfLoadProgramAttrs<'a> {
     pub(crate) license: &'a CStr,
     pub(crate) kernel_version: u32,
     pub(crate) expected_attach_type: Option<bpf_attach_type>,
+    // attach types which the generated bindings predate, or the index of the
+    // struct member struct_ops programs implement, which they pass in place of
+    // an attach type
+    pub(crate) raw_attach_type: Option<u32>,
     pub(crate) prog_btf_fd: Option<RawFd>,
     pub(crate) attach_btf_obj_fd: Option<u32>,
     pub(crate) attach_btf_id: Option<u32>,
     pub(crate) attach_prog_fd: Option<RawFd>,
+    pub(crate) prog_ifindex: Option<u32>,
     pub(crate) func_info_rec_size: usize,
     pub(crate) func_info: FuncSecInfo,
     pub(crate) line_info_rec_size: usize,
     pub(crate) line_info: LineSecInfo,
     pub(crate) flags: u32,
+    pub(crate) token_fd: Option<RawFd>,
 }
 
 pub(crate) fn bpf_load_program(
@@ -146,6 +262,9 @@ pub(crate) fn bpf_load_program(
     if let Some(v) = aya_attr.expected_attach_type {
         u.expected_attach_type = v as u32;
     }
+    if let Some(v) = aya_attr.raw_attach_type {
+        u.expected_attach_type = v;
+    }
     u.insns = aya_attr.insns.as_ptr() as u64;
     u.insn_cnt = aya_attr.insns.len() as u32;
     u.license = aya_attr.license.as_ptr() as u64;
@@ -185,7 +304,11 @@ pub(crate) fn bpf_load_program(
     if let Some(v) = aya_attr.attach_btf_id {
         u.attach_btf_id = v;
     }
-    sys_bpf(bpf_cmd::BPF_PROG_LOAD, &attr)
+
+    if let Some(v) = aya_attr.prog_ifindex {
+        u.prog_ifindex = v;
+    }
+    sys_bpf_token(bpf_cmd::BPF_PROG_LOAD, &mut attr, aya_attr.token_fd)
 }
 
 fn lookup<K: Pod, V: Pod>(
@@ -220,6 +343,13 @@ pub(crate) fn bpf_map_lookup_elem<K: Pod, V: Pod>(
     lookup(fd, Some(key), flags, bpf_cmd::BPF_MAP_LOOKUP_ELEM)
 }
 
+pub(crate) fn bpf_map_peek_elem<V: Pod>(
+    fd: RawFd,
+    flags: u64,
+) -> Result<Option<V>, (c_long, io::Error)> {
+    lookup::<u32, _>(fd, None, flags, bpf_cmd::BPF_MAP_LOOKUP_ELEM)
+}
+
 pub(crate) fn bpf_map_lookup_and_delete_elem<K: Pod, V: Pod>(
     fd: RawFd,
     key: Option<&K>,
@@ -352,6 +482,86 @@ pub(crate) fn bpf_map_get_next_key<K: Pod>(
     }
 }
 
+// since kernel 5.6
+//
+// The batch position is a bucket index for hash maps and the key of the next
+// element for arrays, both a native endian u32.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn bpf_map_lookup_batch<K: Pod, V: Pod>(
+    fd: RawFd,
+    in_batch: Option<&u32>,
+    out_batch: &mut u32,
+    keys: *mut K,
+    values: *mut V,
+    count: &mut u32,
+    flags: u64,
+    delete: bool,
+) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    let b = unsafe { &mut attr.batch };
+    b.map_fd = fd as u32;
+    if let Some(in_batch) = in_batch {
+        b.in_batch = in_batch as *const _ as u64;
+    }
+    b.out_batch = out_batch as *mut _ as u64;
+    b.keys = keys as u64;
+    b.values = values as u64;
+    b.count = *count;
+    b.elem_flags = flags;
+
+    let cmd = if delete {
+        bpf_cmd::BPF_MAP_LOOKUP_AND_DELETE_BATCH
+    } else {
+        bpf_cmd::BPF_MAP_LOOKUP_BATCH
+    };
+    let ret = sys_bpf(cmd, &attr);
+
+    *count = unsafe { attr.batch.count };
+
+    ret
+}
+
+// since kernel 5.6
+pub(crate) fn bpf_map_update_batch<K: Pod, V: Pod>(
+    fd: RawFd,
+    keys: &[K],
+    values: &[V],
+    count: &mut u32,
+    flags: u64,
+) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    let b = unsafe { &mut attr.batch };
+    b.map_fd = fd as u32;
+    b.keys = keys.as_ptr() as u64;
+    b.values = values.as_ptr() as u64;
+    b.count = *count;
+    b.elem_flags = flags;
+
+    let ret = sys_bpf(bpf_cmd::BPF_MAP_UPDATE_BATCH, &attr);
+
+    *count = unsafe { attr.batch.count };
+
+    ret
+}
+
+// since kernel 5.6
+pub(crate) fn bpf_map_delete_batch<K: Pod>(fd: RawFd, keys: &[K], count: &mut u32) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    let b = unsafe { &mut attr.batch };
+    b.map_fd = fd as u32;
+    b.keys = keys.as_ptr() as u64;
+    b.count = *count;
+
+    let ret = sys_bpf(bpf_cmd::BPF_MAP_DELETE_BATCH, &attr);
+
+    *count = unsafe { attr.batch.count };
+
+    ret
+}
+
 // since kernel 5.2
 pub(crate) fn bpf_map_freeze(fd: RawFd) -> SysResult {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
@@ -381,6 +591,146 @@ pub(crate) fn bpf_link_create(
     sys_bpf(bpf_cmd::BPF_LINK_CREATE, &attr)
 }
 
+// since kernel 5.15
+pub(crate) fn bpf_link_create_perf_event(
+    prog_fd: RawFd,
+    perf_fd: RawFd,
+    bpf_cookie: u64,
+) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    attr.link_create.prog_fd = prog_fd as u32;
+    attr.link_create.__bindgen_anon_1.target_fd = perf_fd as u32;
+    attr.link_create.attach_type = BPF_PERF_EVENT as u32;
+    attr.link_create.__bindgen_anon_2.perf_event.bpf_cookie = bpf_cookie;
+
+    sys_bpf(bpf_cmd::BPF_LINK_CREATE, &attr)
+}
+
+// since kernel 5.8
+pub(crate) fn bpf_link_create_iter(prog_fd: RawFd, map_fd: Option<RawFd>) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    // `union bpf_iter_link_info` isn't in the generated bindings. Its `map`
+    // member, the only one used here, is a single `__u32 map_fd`.
+    let map_fd = map_fd.map(|fd| fd as u32);
+
+    attr.link_create.prog_fd = prog_fd as u32;
+    attr.link_create.attach_type = BPF_TRACE_ITER as u32;
+    if let Some(map_fd) = &map_fd {
+        attr.link_create.__bindgen_anon_2.__bindgen_anon_1.iter_info = map_fd as *const u32 as u64;
+        attr.link_create
+            .__bindgen_anon_2
+            .__bindgen_anon_1
+            .iter_info_len = mem::size_of::<u32>() as u32;
+    }
+
+    sys_bpf(bpf_cmd::BPF_LINK_CREATE, &attr)
+}
+
+// since kernel 5.8
+pub(crate) fn bpf_create_iter(link_fd: RawFd) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    attr.iter_create.link_fd = link_fd as u32;
+
+    sys_bpf(bpf_cmd::BPF_ITER_CREATE, &attr)
+}
+
+// since kernel 6.6
+pub(crate) fn bpf_link_create_tcx(
+    prog_fd: RawFd,
+    if_index: u32,
+    attach_type: u32,
+    flags: u32,
+    relative_id: u32,
+) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    attr.link_create.prog_fd = prog_fd as u32;
+    attr.link_create.__bindgen_anon_1.target_ifindex = if_index;
+    attr.link_create.attach_type = attach_type;
+    attr.link_create.flags = flags;
+    // The generated bindings predate `link_create.tcx`. Its first member,
+    // `relative_fd`/`relative_id`, lives at the same offset as `target_btf_id`.
+    attr.link_create.__bindgen_anon_2.target_btf_id = relative_id;
+
+    sys_bpf(bpf_cmd::BPF_LINK_CREATE, &attr)
+}
+
+// since kernel 5.18
+pub(crate) fn bpf_link_create_kprobe_multi(
+    prog_fd: RawFd,
+    syms: &[CString],
+    cookies: Option<&[u64]>,
+    retprobe: bool,
+) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    let sym_ptrs = syms.iter().map(|s| s.as_ptr()).collect::<Vec<_>>();
+
+    attr.link_create.prog_fd = prog_fd as u32;
+    attr.link_create.attach_type = BPF_TRACE_KPROBE_MULTI as u32;
+    let u = unsafe { &mut attr.link_create.__bindgen_anon_2.kprobe_multi };
+    u.flags = if retprobe {
+        BPF_F_KPROBE_MULTI_RETURN
+    } else {
+        0
+    };
+    u.cnt = sym_ptrs.len() as u32;
+    u.syms = sym_ptrs.as_ptr() as u64;
+    if let Some(cookies) = cookies {
+        u.cookies = cookies.as_ptr() as u64;
+    }
+
+    sys_bpf(bpf_cmd::BPF_LINK_CREATE, &attr)
+}
+
+// since kernel 6.6
+pub(crate) fn bpf_link_create_uprobe_multi(
+    prog_fd: RawFd,
+    attach_type: u32,
+    path: &CStr,
+    offsets: &[u64],
+    cookies: Option<&[u64]>,
+    pid: Option<u32>,
+    flags: u32,
+) -> SysResult {
+    // The generated bindings predate `link_create.uprobe_multi`, which follows
+    // the prog_fd, target_fd, attach_type and flags of `link_create`:
+    //
+    // struct {
+    //     __aligned_u64 path;
+    //     __aligned_u64 offsets;
+    //     __aligned_u64 ref_ctr_offsets;
+    //     __aligned_u64 cookies;
+    //     __u32 cnt;
+    //     __u32 flags;
+    //     __u32 pid;
+    // } uprobe_multi;
+    let mut attr = [0u8; mem::size_of::<bpf_attr>()];
+    let mut put = |offset: usize, bytes: &[u8]| {
+        attr[offset..offset + bytes.len()].copy_from_slice(bytes);
+    };
+    put(0, &(prog_fd as u32).to_ne_bytes());
+    put(8, &attach_type.to_ne_bytes());
+    put(16, &(path.as_ptr() as u64).to_ne_bytes());
+    put(24, &(offsets.as_ptr() as u64).to_ne_bytes());
+    if let Some(cookies) = cookies {
+        put(40, &(cookies.as_ptr() as u64).to_ne_bytes());
+    }
+    put(48, &(offsets.len() as u32).to_ne_bytes());
+    put(52, &flags.to_ne_bytes());
+    if let Some(pid) = pid {
+        put(56, &pid.to_ne_bytes());
+    }
+
+    syscall(Syscall::BpfRaw {
+        cmd: bpf_cmd::BPF_LINK_CREATE as u32,
+        attr: &attr,
+    })
+}
+
 // since kernel 5.7
 pub(crate) fn bpf_link_update(
     link_fd: RawFd,
@@ -430,21 +780,77 @@ pub(crate) fn bpf_prog_detach(
     sys_bpf(bpf_cmd::BPF_PROG_DETACH, &attr)
 }
 
+// since kernel 5.8
+pub(crate) fn bpf_enable_stats(stats_type: u32) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    attr.enable_stats.type_ = stats_type;
+
+    sys_bpf(bpf_cmd::BPF_ENABLE_STATS, &attr)
+}
+
+// since kernel 4.12, 5.3 for contexts
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn bpf_prog_test_run(
+    prog_fd: RawFd,
+    data_in: &[u8],
+    ctx_in: &[u8],
+    data_out: &mut [u8],
+    data_size_out: &mut u32,
+    ctx_out: &mut [u8],
+    ctx_size_out: &mut u32,
+    repeat: u32,
+) -> Result<(u32, u32), (c_long, io::Error)> {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+
+    let test = unsafe { &mut attr.test };
+    test.prog_fd = prog_fd as u32;
+    if !data_in.is_empty() {
+        test.data_in = data_in.as_ptr() as u64;
+        test.data_size_in = data_in.len() as u32;
+    }
+    if !ctx_in.is_empty() {
+        test.ctx_in = ctx_in.as_ptr() as u64;
+        test.ctx_size_in = ctx_in.len() as u32;
+    }
+    if !data_out.is_empty() {
+        test.data_out = data_out.as_mut_ptr() as u64;
+        test.data_size_out = data_out.len() as u32;
+    }
+    if !ctx_out.is_empty() {
+        test.ctx_out = ctx_out.as_mut_ptr() as u64;
+        test.ctx_size_out = ctx_out.len() as u32;
+    }
+    test.repeat = repeat;
+
+    let ret = sys_bpf(bpf_cmd::BPF_PROG_TEST_RUN, &attr);
+
+    // set on ENOSPC too, to the size the outputs need
+    *data_size_out = unsafe { attr.test.data_size_out };
+    *ctx_size_out = unsafe { attr.test.ctx_size_out };
+
+    ret.map(|_| unsafe { (attr.test.retval, attr.test.duration) })
+}
+
 pub(crate) fn bpf_prog_query(
     target_fd: RawFd,
-    attach_type: bpf_attach_type,
+    attach_type: u32,
     query_flags: u32,
     attach_flags: Option<&mut u32>,
     prog_ids: &mut [u32],
+    prog_attach_flags: Option<&mut [u32]>,
     prog_cnt: &mut u32,
 ) -> SysResult {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
 
     attr.query.target_fd = target_fd as u32;
-    attr.query.attach_type = attach_type as u32;
+    attr.query.attach_type = attach_type;
     attr.query.query_flags = query_flags;
     attr.query.prog_cnt = prog_ids.len() as u32;
     attr.query.prog_ids = prog_ids.as_mut_ptr() as u64;
+    if let Some(prog_attach_flags) = prog_attach_flags {
+        attr.query.prog_attach_flags = prog_attach_flags.as_mut_ptr() as u64;
+    }
 
     let ret = sys_bpf(bpf_cmd::BPF_PROG_QUERY, &attr);
 
@@ -483,6 +889,31 @@ pub(crate) fn bpf_prog_get_info_by_fd(prog_fd: RawFd) -> Result<bpf_prog_info, i
     }
 }
 
+pub(crate) fn bpf_prog_get_line_info(
+    prog_fd: RawFd,
+    count: u32,
+) -> Result<Vec<bpf_line_info>, io::Error> {
+    let mut line_info = vec![unsafe { mem::zeroed::<bpf_line_info>() }; count as usize];
+    let mut info = unsafe { mem::zeroed::<bpf_prog_info>() };
+    info.nr_line_info = count;
+    info.line_info_rec_size = mem::size_of::<bpf_line_info>() as u32;
+    info.line_info = line_info.as_mut_ptr() as u64;
+
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+    attr.info.bpf_fd = prog_fd as u32;
+    attr.info.info = &mut info as *mut _ as u64;
+    attr.info.info_len = mem::size_of::<bpf_prog_info>() as u32;
+
+    match sys_bpf(bpf_cmd::BPF_OBJ_GET_INFO_BY_FD, &attr) {
+        Ok(_) => {
+            // The program may have been replaced by one with fewer records.
+            line_info.truncate(info.nr_line_info.min(count) as usize);
+            Ok(line_info)
+        }
+        Err((_, err)) => Err(err),
+    }
+}
+
 pub(crate) fn bpf_map_get_info_by_fd(prog_fd: RawFd) -> Result<bpf_map_info, io::Error> {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
     // info gets entirely populated by the kernel
@@ -501,14 +932,14 @@ pub(crate) fn bpf_map_get_info_by_fd(prog_fd: RawFd) -> Result<bpf_map_info, io:
 pub(crate) fn bpf_link_get_info_by_fd(link_fd: RawFd) -> Result<bpf_link_info, io::Error> {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
     // info gets entirely populated by the kernel
-    let info = unsafe { MaybeUninit::zeroed().assume_init() };
+    let info = MaybeUninit::zeroed();
 
     attr.info.bpf_fd = link_fd as u32;
-    attr.info.info = &info as *const _ as u64;
+    attr.info.info = info.as_ptr() as *const _ as u64;
     attr.info.info_len = mem::size_of::<bpf_link_info>() as u32;
 
     match sys_bpf(bpf_cmd::BPF_OBJ_GET_INFO_BY_FD, &attr) {
-        Ok(_) => Ok(info),
+        Ok(_) => Ok(unsafe { info.assume_init() }),
         Err((_, err)) => Err(err),
     }
 }
@@ -544,7 +975,11 @@ pub(crate) fn bpf_raw_tracepoint_open(name: Option<&CStr>, prog_fd: RawFd) -> Sy
     sys_bpf(bpf_cmd::BPF_RAW_TRACEPOINT_OPEN, &attr)
 }
 
-pub(crate) fn bpf_load_btf(raw_btf: &[u8], log: &mut VerifierLog) -> SysResult {
+pub(crate) fn bpf_load_btf(
+    raw_btf: &[u8],
+    log: &mut VerifierLog,
+    token_fd: Option<RawFd>,
+) -> SysResult {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
     let u = unsafe { &mut attr.__bindgen_anon_7 };
     u.btf = raw_btf.as_ptr() as *const _ as u64;
@@ -555,7 +990,7 @@ pub(crate) fn bpf_load_btf(raw_btf: &[u8], log: &mut VerifierLog) -> SysResult {
         u.btf_log_buf = log_buf.as_mut_ptr() as u64;
         u.btf_log_size = log_buf.capacity() as u32;
     }
-    sys_bpf(bpf_cmd::BPF_BTF_LOAD, &attr)
+    sys_bpf_token(bpf_cmd::BPF_BTF_LOAD, &mut attr, token_fd)
 }
 
 pub(crate) fn bpf_btf_get_fd_by_id(id: u32) -> Result<RawFd, io::Error> {
@@ -568,7 +1003,7 @@ pub(crate) fn bpf_btf_get_fd_by_id(id: u32) -> Result<RawFd, io::Error> {
     }
 }
 
-pub(crate) fn is_prog_name_supported() -> bool {
+pub(crate) fn is_prog_name_supported(token_fd: Option<RawFd>) -> bool {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
     let u = unsafe { &mut attr.__bindgen_anon_3 };
     let mut name: [c_char; 16] = [0; 16];
@@ -593,7 +1028,7 @@ pub(crate) fn is_prog_name_supported() -> bool {
     u.insns = insns.as_ptr() as u64;
     u.prog_type = bpf_prog_type::BPF_PROG_TYPE_SOCKET_FILTER as u32;
 
-    match sys_bpf(bpf_cmd::BPF_PROG_LOAD, &attr) {
+    match sys_bpf_token(bpf_cmd::BPF_PROG_LOAD, &mut attr, token_fd) {
         Ok(v) => {
             let fd = v as RawFd;
             unsafe { close(fd) };
@@ -603,7 +1038,7 @@ pub(crate) fn is_prog_name_supported() -> bool {
     }
 }
 
-pub(crate) fn is_probe_read_kernel_supported() -> bool {
+pub(crate) fn is_probe_read_kernel_supported(token_fd: Option<RawFd>) -> bool {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
     let u = unsafe { &mut attr.__bindgen_anon_3 };
 
@@ -624,7 +1059,7 @@ pub(crate) fn is_probe_read_kernel_supported() -> bool {
     u.insns = insns.as_ptr() as u64;
     u.prog_type = bpf_prog_type::BPF_PROG_TYPE_TRACEPOINT as u32;
 
-    match sys_bpf(bpf_cmd::BPF_PROG_LOAD, &attr) {
+    match sys_bpf_token(bpf_cmd::BPF_PROG_LOAD, &mut attr, token_fd) {
         Ok(v) => {
             let fd = v as RawFd;
             unsafe { close(fd) };
@@ -665,7 +1100,7 @@ pub(crate) fn is_perf_link_supported() -> bool {
     false
 }
 
-pub(crate) fn is_bpf_global_data_supported() -> bool {
+pub(crate) fn is_bpf_global_data_supported(token_fd: Option<RawFd>) -> bool {
     let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
     let u = unsafe { &mut attr.__bindgen_anon_3 };
 
@@ -698,7 +1133,7 @@ pub(crate) fn is_bpf_global_data_supported() -> bool {
         btf_fd: None,
     };
 
-    if let Ok(map_fd) = map_data.create("aya_global") {
+    if let Ok(map_fd) = map_data.create_with_inner_map("aya_global", None, None, token_fd) {
         insns[0].imm = map_fd;
 
         let gpl = b"GPL\0";
@@ -707,7 +1142,7 @@ pub(crate) fn is_bpf_global_data_supported() -> bool {
         u.insns = insns.as_ptr() as u64;
         u.prog_type = bpf_prog_type::BPF_PROG_TYPE_SOCKET_FILTER as u32;
 
-        if let Ok(v) = sys_bpf(bpf_cmd::BPF_PROG_LOAD, &attr) {
+        if let Ok(v) = sys_bpf_token(bpf_cmd::BPF_PROG_LOAD, &mut attr, token_fd) {
             let fd = v as RawFd;
 
             unsafe { close(fd) };
@@ -719,7 +1154,7 @@ pub(crate) fn is_bpf_global_data_supported() -> bool {
     false
 }
 
-pub(crate) fn is_btf_supported() -> bool {
+pub(crate) fn is_btf_supported(token_fd: Option<RawFd>) -> bool {
     let mut btf = Btf::new();
     let name_offset = btf.add_string("int".to_string());
     let int_type = BtfType::Int(Int::new(name_offset, 4, IntEncoding::Signed, 0));
@@ -731,7 +1166,7 @@ pub(crate) fn is_btf_supported() -> bool {
     u.btf = btf_bytes.as_ptr() as u64;
     u.btf_size = btf_bytes.len() as u32;
 
-    match sys_bpf(bpf_cmd::BPF_BTF_LOAD, &attr) {
+    match sys_bpf_token(bpf_cmd::BPF_BTF_LOAD, &mut attr, token_fd) {
         Ok(v) => {
             let fd = v as RawFd;
             unsafe { close(fd) };
@@ -741,7 +1176,7 @@ pub(crate) fn is_btf_supported() -> bool {
     }
 }
 
-pub(crate) fn is_btf_func_supported() -> bool {
+pub(crate) fn is_btf_func_supported(token_fd: Option<RawFd>) -> bool {
     let mut btf = Btf::new();
     let name_offset = btf.add_string("int".to_string());
     let int_type = BtfType::Int(Int::new(name_offset, 4, IntEncoding::Signed, 0));
@@ -773,7 +1208,7 @@ pub(crate) fn is_btf_func_supported() -> bool {
     u.btf = btf_bytes.as_ptr() as u64;
     u.btf_size = btf_bytes.len() as u32;
 
-    match sys_bpf(bpf_cmd::BPF_BTF_LOAD, &attr) {
+    match sys_bpf_token(bpf_cmd::BPF_BTF_LOAD, &mut attr, token_fd) {
         Ok(v) => {
             let fd = v as RawFd;
             unsafe { close(fd) };
@@ -783,7 +1218,7 @@ pub(crate) fn is_btf_func_supported() -> bool {
     }
 }
 
-pub(crate) fn is_btf_func_global_supported() -> bool {
+pub(crate) fn is_btf_func_global_supported(token_fd: Option<RawFd>) -> bool {
     let mut btf = Btf::new();
     let name_offset = btf.add_string("int".to_string());
     let int_type = BtfType::Int(Int::new(name_offset, 4, IntEncoding::Signed, 0));
@@ -815,7 +1250,7 @@ pub(crate) fn is_btf_func_global_supported() -> bool {
     u.btf = btf_bytes.as_ptr() as u64;
     u.btf_size = btf_bytes.len() as u32;
 
-    match sys_bpf(bpf_cmd::BPF_BTF_LOAD, &attr) {
+    match sys_bpf_token(bpf_cmd::BPF_BTF_LOAD, &mut attr, token_fd) {
         Ok(v) => {
             let fd = v as RawFd;
             unsafe { close(fd) };
@@ -825,7 +1260,7 @@ pub(crate) fn is_btf_func_global_supported() -> bool {
     }
 }
 
-pub(crate) fn is_btf_datasec_supported() -> bool {
+pub(crate) fn is_btf_datasec_supported(token_fd: Option<RawFd>) -> bool {
     let mut btf = Btf::new();
     let name_offset = btf.add_string("int".to_string());
     let int_type = BtfType::Int(Int::new(name_offset, 4, IntEncoding::Signed, 0));
@@ -851,7 +1286,7 @@ pub(crate) fn is_btf_datasec_supported() -> bool {
     u.btf = btf_bytes.as_ptr() as u64;
     u.btf_size = btf_bytes.len() as u32;
 
-    match sys_bpf(bpf_cmd::BPF_BTF_LOAD, &attr) {
+    match sys_bpf_token(bpf_cmd::BPF_BTF_LOAD, &mut attr, token_fd) {
         Ok(v) => {
             let fd = v as RawFd;
             unsafe { close(fd) };
@@ -861,7 +1296,7 @@ pub(crate) fn is_btf_datasec_supported() -> bool {
     }
 }
 
-pub(crate) fn is_btf_float_supported() -> bool {
+pub(crate) fn is_btf_float_supported(token_fd: Option<RawFd>) -> bool {
     let mut btf = Btf::new();
     let name_offset = btf.add_string("float".to_string());
     let float_type = BtfType::Float(Float::new(name_offset, 16));
@@ -874,7 +1309,7 @@ pub(crate) fn is_btf_float_supported() -> bool {
     u.btf = btf_bytes.as_ptr() as u64;
     u.btf_size = btf_bytes.len() as u32;
 
-    match sys_bpf(bpf_cmd::BPF_BTF_LOAD, &attr) {
+    match sys_bpf_token(bpf_cmd::BPF_BTF_LOAD, &mut attr, token_fd) {
         Ok(v) => {
             let fd = v as RawFd;
             unsafe { close(fd) };
@@ -884,7 +1319,7 @@ pub(crate) fn is_btf_float_supported() -> bool {
     }
 }
 
-pub(crate) fn is_btf_decl_tag_supported() -> bool {
+pub(crate) fn is_btf_decl_tag_supported(token_fd: Option<RawFd>) -> bool {
     let mut btf = Btf::new();
     let name_offset = btf.add_string("int".to_string());
     let int_type = BtfType::Int(Int::new(name_offset, 4, IntEncoding::Signed, 0));
@@ -905,7 +1340,7 @@ pub(crate) fn is_btf_decl_tag_supported() -> bool {
     u.btf = btf_bytes.as_ptr() as u64;
     u.btf_size = btf_bytes.len() as u32;
 
-    match sys_bpf(bpf_cmd::BPF_BTF_LOAD, &attr) {
+    match sys_bpf_token(bpf_cmd::BPF_BTF_LOAD, &mut attr, token_fd) {
         Ok(v) => {
             let fd = v as RawFd;
             unsafe { close(fd) };
@@ -915,7 +1350,7 @@ pub(crate) fn is_btf_decl_tag_supported() -> bool {
     }
 }
 
-pub(crate) fn is_btf_type_tag_supported() -> bool {
+pub(crate) fn is_btf_type_tag_supported(token_fd: Option<RawFd>) -> bool {
     let mut btf = Btf::new();
 
     let int_type = BtfType::Int(Int::new(0, 4, IntEncoding::Signed, 0));
@@ -934,7 +1369,7 @@ pub(crate) fn is_btf_type_tag_supported() -> bool {
     u.btf = btf_bytes.as_ptr() as u64;
     u.btf_size = btf_bytes.len() as u32;
 
-    match sys_bpf(bpf_cmd::BPF_BTF_LOAD, &attr) {
+    match sys_bpf_token(bpf_cmd::BPF_BTF_LOAD, &mut attr, token_fd) {
         Ok(v) => {
             let fd = v as RawFd;
             unsafe { close(fd) };
@@ -944,6 +1379,289 @@ pub(crate) fn is_btf_type_tag_supported() -> bool {
     }
 }
 
+pub(crate) fn is_prog_type_supported(prog_type: bpf_prog_type) -> bool {
+    let prog: &[u8] = &[
+        0xb7, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // mov64 r0 = 0
+        0x95, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // exit
+    ];
+
+    // Programs that attach to BTF types fail to load without a valid
+    // attach_btf_id. Like libbpf, pass a bogus id and check that the load
+    // failed because of it.
+    let expected_msg = bogus_attach_btf_id_error(prog_type);
+    let mut log_buf = if expected_msg.is_some() {
+        vec![0u8; 4096]
+    } else {
+        Vec::new()
+    };
+
+    match probe_prog_load(prog_type, prog, &mut log_buf) {
+        Ok(fd) => {
+            unsafe { close(fd as RawFd) };
+            true
+        }
+        Err(_) => match expected_msg {
+            Some(msg) => log_contains(&log_buf, msg),
+            None => false,
+        },
+    }
+}
+
+// The error of loading a program of a type that attaches to BTF types with
+// the bogus attach_btf_id set by probe_prog_load.
+fn bogus_attach_btf_id_error(prog_type: bpf_prog_type) -> Option<&'static str> {
+    use bpf_prog_type::*;
+
+    match prog_type {
+        BPF_PROG_TYPE_TRACING | BPF_PROG_TYPE_LSM => Some("attach_btf_id 1 is not a function"),
+        BPF_PROG_TYPE_EXT => Some("Cannot replace kernel functions"),
+        BPF_PROG_TYPE_STRUCT_OPS => Some("attach_btf_id 1 is not a supported struct"),
+        _ => None,
+    }
+}
+
+pub(crate) fn is_helper_supported(prog_type: bpf_prog_type, helper_id: u32) -> bool {
+    // Programs that attach to BTF types are rejected because of the bogus
+    // attach_btf_id before the verifier looks at the helper call, so helpers
+    // can't be probed for them.
+    if bogus_attach_btf_id_error(prog_type).is_some() {
+        return false;
+    }
+
+    // If the program type itself can't be loaded, every probe below fails
+    // without telling anything about the helper.
+    if !is_prog_type_supported(prog_type) {
+        return false;
+    }
+
+    let id = helper_id.to_le_bytes();
+    let prog: &[u8] = &[
+        0x85, 0x00, 0x00, 0x00, id[0], id[1], id[2], id[3], // call helper_id
+        0x95, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // exit
+    ];
+
+    let mut log_buf = vec![0u8; 4096];
+    match probe_prog_load(prog_type, prog, &mut log_buf) {
+        Ok(fd) => {
+            unsafe { close(fd as RawFd) };
+            true
+        }
+        // The program can fail to load for reasons unrelated to the helper,
+        // for example because of the arguments it's called with. Only these
+        // messages mean that the helper itself isn't available. Without a
+        // verifier log, for example on EPERM, the helper wasn't checked at all.
+        Err(_) => {
+            log_buf[0] != 0
+                && !log_contains(&log_buf, "invalid func ")
+                && !log_contains(&log_buf, "unknown func ")
+        }
+    }
+}
+
+// Since 5.11 the memory of eBPF objects is charged to the memory cgroup of
+// the process instead of RLIMIT_MEMLOCK. Like libbpf, detect it with the
+// bpf_ktime_get_coarse_ns helper which was added in the same release.
+pub(crate) fn is_bpf_memcg_accounting_supported() -> bool {
+    const BPF_FUNC_KTIME_GET_COARSE_NS: u8 = 160;
+    let prog: &[u8] = &[
+        0x85,
+        0x00,
+        0x00,
+        0x00,
+        BPF_FUNC_KTIME_GET_COARSE_NS,
+        0x00,
+        0x00,
+        0x00, // call bpf_ktime_get_coarse_ns
+        0x95,
+        0x00,
+        0x00,
+        0x00,
+        0x00,
+        0x00,
+        0x00,
+        0x00, // exit
+    ];
+
+    match probe_prog_load(bpf_prog_type::BPF_PROG_TYPE_SOCKET_FILTER, prog, &mut []) {
+        Ok(fd) => {
+            unsafe { close(fd as RawFd) };
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+fn probe_prog_load(prog_type: bpf_prog_type, prog: &[u8], log_buf: &mut [u8]) -> SysResult {
+    use bpf_attach_type::*;
+    use bpf_prog_type::*;
+
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+    let u = unsafe { &mut attr.__bindgen_anon_3 };
+
+    let gpl = b"GPL\0";
+    u.license = gpl.as_ptr() as u64;
+
+    let insns = copy_instructions(prog).unwrap();
+    u.insn_cnt = insns.len() as u32;
+    u.insns = insns.as_ptr() as u64;
+    u.prog_type = prog_type as u32;
+
+    match prog_type {
+        BPF_PROG_TYPE_KPROBE => {
+            if let Ok((major, minor, patch)) = kernel_version() {
+                u.kern_version = (major << 16) + (minor << 8) + patch;
+            }
+        }
+        BPF_PROG_TYPE_CGROUP_SOCK_ADDR => {
+            u.expected_attach_type = BPF_CGROUP_INET4_CONNECT as u32;
+        }
+        BPF_PROG_TYPE_CGROUP_SOCKOPT => u.expected_attach_type = BPF_CGROUP_GETSOCKOPT as u32,
+        BPF_PROG_TYPE_SK_LOOKUP => u.expected_attach_type = BPF_SK_LOOKUP as u32,
+        BPF_PROG_TYPE_TRACING => {
+            u.expected_attach_type = BPF_TRACE_FENTRY as u32;
+            u.attach_btf_id = 1;
+        }
+        BPF_PROG_TYPE_LSM => {
+            u.expected_attach_type = BPF_LSM_MAC as u32;
+            u.attach_btf_id = 1;
+        }
+        BPF_PROG_TYPE_EXT | BPF_PROG_TYPE_STRUCT_OPS => u.attach_btf_id = 1,
+        BPF_PROG_TYPE_SYSCALL => u.prog_flags = BPF_F_SLEEPABLE,
+        _ => {}
+    }
+
+    if !log_buf.is_empty() {
+        u.log_level = 1;
+        u.log_buf = log_buf.as_mut_ptr() as u64;
+        u.log_size = log_buf.len() as u32;
+    }
+
+    sys_bpf(bpf_cmd::BPF_PROG_LOAD, &attr)
+}
+
+fn log_contains(log_buf: &[u8], msg: &str) -> bool {
+    log_buf
+        .windows(msg.len())
+        .any(|window| window == msg.as_bytes())
+}
+
+pub(crate) fn is_map_type_supported(map_type: bpf_map_type) -> bool {
+    use bpf_map_type::*;
+
+    let mut key_size = 4;
+    let mut value_size = 4;
+    let mut max_entries = 1;
+    let mut map_flags = 0;
+    let mut inner_map_fd = None;
+    let mut btf_fd = None;
+    let mut btf_vmlinux_value_type_id = 0;
+
+    match map_type {
+        BPF_MAP_TYPE_LPM_TRIE => {
+            key_size = 8;
+            value_size = 8;
+            map_flags = BPF_F_NO_PREALLOC;
+        }
+        BPF_MAP_TYPE_QUEUE | BPF_MAP_TYPE_STACK | BPF_MAP_TYPE_BLOOM_FILTER => key_size = 0,
+        BPF_MAP_TYPE_RINGBUF | BPF_MAP_TYPE_USER_RINGBUF => {
+            key_size = 0;
+            value_size = 0;
+            max_entries = page_size() as u32;
+        }
+        BPF_MAP_TYPE_CGROUP_STORAGE_DEPRECATED | BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE => {
+            // sizeof(struct bpf_cgroup_storage_key)
+            key_size = 16;
+            max_entries = 0;
+        }
+        BPF_MAP_TYPE_SK_STORAGE
+        | BPF_MAP_TYPE_INODE_STORAGE
+        | BPF_MAP_TYPE_TASK_STORAGE
+        | BPF_MAP_TYPE_CGRP_STORAGE => {
+            // local storage maps need BTF for their key and value
+            let mut btf = Btf::new();
+            let name_offset = btf.add_string("int".to_string());
+            btf.add_type(BtfType::Int(Int::new(
+                name_offset,
+                4,
+                IntEncoding::Signed,
+                0,
+            )));
+            match bpf_load_btf(&btf.to_bytes(), &mut VerifierLog::new(), None) {
+                Ok(fd) => btf_fd = Some(fd as RawFd),
+                Err(_) => return false,
+            }
+            max_entries = 0;
+            map_flags = BPF_F_NO_PREALLOC;
+        }
+        BPF_MAP_TYPE_ARRAY_OF_MAPS | BPF_MAP_TYPE_HASH_OF_MAPS => {
+            match probe_map_create(BPF_MAP_TYPE_ARRAY, 4, 4, 1, 0, None, None, 0) {
+                Ok(fd) => inner_map_fd = Some(fd as RawFd),
+                Err(_) => return false,
+            }
+        }
+        // struct_ops maps need the BTF id of a kernel struct, a bogus one
+        // fails with ENOTSUPP when the map type is supported
+        BPF_MAP_TYPE_STRUCT_OPS => btf_vmlinux_value_type_id = 1,
+        _ => {}
+    }
+
+    let ret = probe_map_create(
+        map_type,
+        key_size,
+        value_size,
+        max_entries,
+        map_flags,
+        inner_map_fd,
+        btf_fd,
+        btf_vmlinux_value_type_id,
+    );
+    for fd in inner_map_fd.into_iter().chain(btf_fd) {
+        unsafe { close(fd) };
+    }
+
+    match ret {
+        Ok(fd) => {
+            unsafe { close(fd as RawFd) };
+            true
+        }
+        // ENOTSUPP, which is kernel internal and not in libc
+        Err((_, e)) if map_type == BPF_MAP_TYPE_STRUCT_OPS => e.raw_os_error() == Some(524),
+        Err(_) => false,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn probe_map_create(
+    map_type: bpf_map_type,
+    key_size: u32,
+    value_size: u32,
+    max_entries: u32,
+    map_flags: u32,
+    inner_map_fd: Option<RawFd>,
+    btf_fd: Option<RawFd>,
+    btf_vmlinux_value_type_id: u32,
+) -> SysResult {
+    let mut attr = unsafe { mem::zeroed::<bpf_attr>() };
+    let u = unsafe { &mut attr.__bindgen_anon_1 };
+
+    u.map_type = map_type as u32;
+    u.key_size = key_size;
+    u.value_size = value_size;
+    u.max_entries = max_entries;
+    u.map_flags = map_flags;
+    if let Some(fd) = inner_map_fd {
+        u.inner_map_fd = fd as u32;
+    }
+    if let Some(fd) = btf_fd {
+        u.btf_fd = fd as u32;
+        u.btf_key_type_id = 1;
+        u.btf_value_type_id = 1;
+    }
+    u.btf_vmlinux_value_type_id = btf_vmlinux_value_type_id;
+
+    sys_bpf(bpf_cmd::BPF_MAP_CREATE, &attr)
+}
+
 pub fn sys_bpf(cmd: bpf_cmd, attr: &bpf_attr) -> SysResult {
     syscall(Syscall::Bpf { cmd, attr })
 }
@@ -1021,4 +1739,304 @@ mod tests {
         let supported = is_perf_link_supported();
         assert!(!supported);
     }
+
+    #[test]
+    fn test_feature_probes_with_token() {
+        override_syscall(|call| match call {
+            Syscall::BpfRaw { cmd, attr } => {
+                let u32_at = |offset: usize| {
+                    u32::from_ne_bytes(attr[offset..offset + 4].try_into().unwrap())
+                };
+                if cmd == bpf_cmd::BPF_PROG_LOAD as u32 {
+                    let prog_load =
+                        unsafe { &(*(attr.as_ptr() as *const bpf_attr)).__bindgen_anon_3 };
+                    assert_eq!(prog_load.prog_flags & BPF_F_TOKEN_FD, BPF_F_TOKEN_FD);
+                    assert_eq!(u32_at(PROG_TOKEN_FD_OFFSET), 7);
+                } else if cmd == bpf_cmd::BPF_BTF_LOAD as u32 {
+                    assert_eq!(u32_at(BTF_FLAGS_OFFSET), BPF_F_TOKEN_FD);
+                    assert_eq!(u32_at(BTF_TOKEN_FD_OFFSET), 7);
+                } else {
+                    return Err((-1, io::Error::from_raw_os_error(EINVAL)));
+                }
+                Ok(-1)
+            }
+            // without the token, the probes fail in a user namespace
+            Syscall::Bpf { .. } => Err((-1, io::Error::from_raw_os_error(libc::EPERM))),
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+        assert!(!is_prog_name_supported(None));
+        assert!(!is_btf_supported(None));
+        assert!(is_prog_name_supported(Some(7)));
+        assert!(is_btf_supported(Some(7)));
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_link_create_perf_event_cookie() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_LINK_CREATE,
+                attr,
+            } => {
+                let link_create = unsafe { &attr.link_create };
+                assert_eq!(link_create.prog_fd, 1);
+                assert_eq!(unsafe { link_create.__bindgen_anon_1.target_fd }, 2);
+                assert_eq!(link_create.attach_type, BPF_PERF_EVENT as u32);
+                assert_eq!(
+                    unsafe { link_create.__bindgen_anon_2.perf_event.bpf_cookie },
+                    0xCAFE
+                );
+                Ok(42)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+        assert_eq!(bpf_link_create_perf_event(1, 2, 0xCAFE).unwrap(), 42);
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_helper_supported() {
+        fn fail_load_with_log(call: Syscall, msg: &str) -> SysResult {
+            if let Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_LOAD,
+                attr,
+            } = call
+            {
+                let u = unsafe { &attr.__bindgen_anon_3 };
+                let insn = unsafe { &*(u.insns as *const bpf_insn) };
+                // the program type probe
+                if insn.code != 0x85 {
+                    return Ok(42);
+                }
+                let log_buf =
+                    unsafe { slice::from_raw_parts_mut(u.log_buf as *mut u8, u.log_size as usize) };
+                log_buf[..msg.len()].copy_from_slice(msg.as_bytes());
+            }
+            Err((-1, io::Error::from_raw_os_error(EINVAL)))
+        }
+
+        override_syscall(|call| {
+            fail_load_with_log(call, "0: (85) call unknown#999\ninvalid func unknown#999\n")
+        });
+        assert!(!is_helper_supported(
+            bpf_prog_type::BPF_PROG_TYPE_SOCKET_FILTER,
+            999
+        ));
+
+        override_syscall(|call| {
+            fail_load_with_log(
+                call,
+                "0: (85) call bpf_map_lookup_elem#1\nR1 type=ctx expected=map_ptr\n",
+            )
+        });
+        assert!(is_helper_supported(
+            bpf_prog_type::BPF_PROG_TYPE_SOCKET_FILTER,
+            1
+        ));
+
+        // no verifier log, the helper wasn't checked
+        override_syscall(|call| fail_load_with_log(call, ""));
+        assert!(!is_helper_supported(
+            bpf_prog_type::BPF_PROG_TYPE_SOCKET_FILTER,
+            1
+        ));
+
+        // the load fails on the attach_btf_id before the helper is checked
+        override_syscall(|call| fail_load_with_log(call, "attach_btf_id 1 is not a function\n"));
+        assert!(!is_helper_supported(
+            bpf_prog_type::BPF_PROG_TYPE_TRACING,
+            1
+        ));
+
+        // the program type itself isn't supported
+        override_syscall(|_| Err((-1, io::Error::from_raw_os_error(EINVAL))));
+        assert!(!is_helper_supported(
+            bpf_prog_type::BPF_PROG_TYPE_SOCKET_FILTER,
+            1
+        ));
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_prog_type_supported() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_LOAD,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_3 };
+                match u.prog_type {
+                    t if t == bpf_prog_type::BPF_PROG_TYPE_XDP as u32 => Ok(42),
+                    t if t == bpf_prog_type::BPF_PROG_TYPE_TRACING as u32 => {
+                        assert_eq!(u.attach_btf_id, 1);
+                        assert_eq!(
+                            u.expected_attach_type,
+                            bpf_attach_type::BPF_TRACE_FENTRY as u32
+                        );
+                        let msg = b"attach_btf_id 1 is not a function\n";
+                        let log_buf = unsafe {
+                            slice::from_raw_parts_mut(u.log_buf as *mut u8, u.log_size as usize)
+                        };
+                        log_buf[..msg.len()].copy_from_slice(msg);
+                        Err((-1, io::Error::from_raw_os_error(EINVAL)))
+                    }
+                    _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+                }
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        assert!(is_prog_type_supported(bpf_prog_type::BPF_PROG_TYPE_XDP));
+        assert!(is_prog_type_supported(bpf_prog_type::BPF_PROG_TYPE_TRACING));
+        assert!(!is_prog_type_supported(bpf_prog_type::BPF_PROG_TYPE_LSM));
+        assert!(!is_prog_type_supported(
+            bpf_prog_type::BPF_PROG_TYPE_SK_LOOKUP
+        ));
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_map_type_supported() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_CREATE,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_1 };
+                match u.map_type {
+                    t if t == bpf_map_type::BPF_MAP_TYPE_HASH as u32 => Ok(42),
+                    t if t == bpf_map_type::BPF_MAP_TYPE_LPM_TRIE as u32 => {
+                        if u.map_flags & BPF_F_NO_PREALLOC == 0 {
+                            return Err((-1, io::Error::from_raw_os_error(EINVAL)));
+                        }
+                        Ok(42)
+                    }
+                    // ENOTSUPP because of the bogus vmlinux type id
+                    t if t == bpf_map_type::BPF_MAP_TYPE_STRUCT_OPS as u32 => {
+                        assert_eq!(u.btf_vmlinux_value_type_id, 1);
+                        Err((-1, io::Error::from_raw_os_error(524)))
+                    }
+                    _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+                }
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        assert!(is_map_type_supported(bpf_map_type::BPF_MAP_TYPE_HASH));
+        assert!(is_map_type_supported(bpf_map_type::BPF_MAP_TYPE_LPM_TRIE));
+        assert!(is_map_type_supported(bpf_map_type::BPF_MAP_TYPE_STRUCT_OPS));
+        assert!(!is_map_type_supported(bpf_map_type::BPF_MAP_TYPE_RINGBUF));
+        // the inner map can't be created either
+        assert!(!is_map_type_supported(
+            bpf_map_type::BPF_MAP_TYPE_HASH_OF_MAPS
+        ));
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_memcg_accounting_supported() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_PROG_LOAD,
+                attr,
+            } => {
+                let u = unsafe { &attr.__bindgen_anon_3 };
+                let insn = unsafe { &*(u.insns as *const bpf_insn) };
+                assert_eq!(insn.imm, 160);
+                Ok(42)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+        assert!(is_bpf_memcg_accounting_supported());
+
+        override_syscall(|_| Err((-1, io::Error::from_raw_os_error(EINVAL))));
+        assert!(!is_bpf_memcg_accounting_supported());
+    }
+
+    #[test]
+    fn test_create_map_with_token() {
+        override_syscall(|call| match call {
+            Syscall::BpfRaw { cmd, attr } if cmd == bpf_cmd::BPF_MAP_CREATE as u32 => {
+                let u32_at = |offset: usize| {
+                    u32::from_ne_bytes(attr[offset..offset + 4].try_into().unwrap())
+                };
+                // map_flags
+                assert_eq!(u32_at(16) & BPF_F_TOKEN_FD, BPF_F_TOKEN_FD);
+                assert_eq!(u32_at(MAP_TOKEN_FD_OFFSET), 7);
+                Ok(42)
+            }
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+        let map = obj::Map::Legacy(LegacyMap {
+            def: bpf_map_def {
+                map_type: bpf_map_type::BPF_MAP_TYPE_ARRAY as u32,
+                key_size: 4,
+                value_size: 4,
+                max_entries: 1,
+                ..Default::default()
+            },
+            section_index: 0,
+            section_kind: BpfSectionKind::Maps,
+            symbol_index: None,
+            data: Vec::new(),
+        });
+        let name = CString::new("map").unwrap();
+        assert!(matches!(
+            bpf_create_map(&name, &map, None, None, None, Some(7)),
+            Ok(42)
+        ));
+    }
+
+    #[test]
+    // Syscall overrides are performing integer-to-pointer conversions, which
+    // should be done with `ptr::from_exposed_addr` in Rust nightly, but we have
+    // to support stable as well.
+    #[cfg_attr(miri, ignore)]
+    fn test_create_map_ifindex() {
+        override_syscall(|call| match call {
+            Syscall::Bpf {
+                cmd: bpf_cmd::BPF_MAP_CREATE,
+                attr,
+            } => Ok(unsafe { attr.__bindgen_anon_1.map_ifindex }.into()),
+            _ => Err((-1, io::Error::from_raw_os_error(EINVAL))),
+        });
+
+        let map = obj::Map::Legacy(LegacyMap {
+            def: bpf_map_def {
+                map_type: bpf_map_type::BPF_MAP_TYPE_ARRAY as u32,
+                key_size: 4,
+                value_size: 4,
+                max_entries: 1,
+                ..Default::default()
+            },
+            section_index: 0,
+            section_kind: BpfSectionKind::Maps,
+            symbol_index: None,
+            data: Vec::new(),
+        });
+        let name = CString::new("map").unwrap();
+        assert!(matches!(
+            bpf_create_map(&name, &map, None, None, Some(3), None),
+            Ok(3)
+        ));
+        assert!(matches!(
+            bpf_create_map(&name, &map, None, None, None, None),
+            Ok(0)
+        ));
+    }
 }
@@ -31,6 +31,12 @@ pub(crate) enum Syscall<'a> {
         cmd: bpf_cmd,
         attr: &'a bpf_attr,
     },
+    // bpf() calls using commands or attributes added after the generated
+    // bindings, `attr` can be larger than `bpf_attr`
+    BpfRaw {
+        cmd: u32,
+        attr: &'a [u8],
+    },
     PerfEventOpen {
         attr: perf_event_attr,
         pid: pid_t,
@@ -60,6 +66,7 @@ unsafe fn syscall_impl(call: Syscall) -> SysResult {
     use Syscall::*;
     let ret = match call {
         Bpf { cmd, attr } => libc::syscall(SYS_bpf, cmd, attr, mem::size_of::<bpf_attr>()),
+        BpfRaw { cmd, attr } => libc::syscall(SYS_bpf, cmd, attr.as_ptr(), attr.len()),
         PerfEventOpen {
             attr,
             pid,
@@ -1,19 +1,29 @@
-use std::{collections::HashMap, ffi::CStr, io, mem, os::unix::io::RawFd, ptr, slice};
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    io, mem,
+    os::unix::io::{AsRawFd, RawFd},
+    ptr, slice,
+};
 use thiserror::Error;
 
 use libc::{
-    close, getsockname, nlattr, nlmsgerr, nlmsghdr, recv, send, setsockopt, sockaddr_nl, socket,
-    AF_NETLINK, AF_UNSPEC, ETH_P_ALL, IFLA_XDP, NETLINK_EXT_ACK, NETLINK_ROUTE, NLA_ALIGNTO,
-    NLA_F_NESTED, NLA_TYPE_MASK, NLMSG_DONE, NLMSG_ERROR, NLM_F_ACK, NLM_F_CREATE, NLM_F_DUMP,
-    NLM_F_ECHO, NLM_F_EXCL, NLM_F_MULTI, NLM_F_REQUEST, RTM_DELTFILTER, RTM_GETTFILTER,
-    RTM_NEWQDISC, RTM_NEWTFILTER, RTM_SETLINK, SOCK_RAW, SOL_NETLINK,
+    bind, close, genlmsghdr, getsockname, nlattr, nlmsgerr, nlmsghdr, recv, send, setsockopt,
+    sockaddr_nl, socket, AF_NETLINK, AF_UNSPEC, CTRL_ATTR_FAMILY_ID, CTRL_ATTR_FAMILY_NAME,
+    CTRL_CMD_GETFAMILY, ETH_P_ALL, GENL_ID_CTRL, IFLA_IFNAME, IFLA_XDP, MSG_PEEK, MSG_TRUNC,
+    NETLINK_EXT_ACK, NETLINK_ROUTE, NLA_ALIGNTO, NLA_F_NESTED, NLA_TYPE_MASK, NLMSG_DONE,
+    NLMSG_ERROR, NLM_F_ACK, NLM_F_CREATE, NLM_F_DUMP, NLM_F_ECHO, NLM_F_EXCL, NLM_F_MULTI,
+    NLM_F_REQUEST, RTMGRP_LINK, RTM_DELLINK, RTM_DELTFILTER, RTM_GETLINK, RTM_GETTFILTER,
+    RTM_NEWLINK, RTM_NEWQDISC, RTM_NEWTFILTER, RTM_SETLINK, SOCK_RAW, SOL_NETLINK,
 };
 
 use crate::{
     generated::{
-        ifinfomsg, tcmsg, IFLA_XDP_EXPECTED_FD, IFLA_XDP_FD, IFLA_XDP_FLAGS, NLMSG_ALIGNTO,
-        TCA_BPF_FD, TCA_BPF_FLAGS, TCA_BPF_FLAG_ACT_DIRECT, TCA_BPF_NAME, TCA_KIND, TCA_OPTIONS,
-        TC_H_CLSACT, TC_H_INGRESS, TC_H_MAJ_MASK, TC_H_UNSPEC, XDP_FLAGS_REPLACE,
+        ifinfomsg, tcmsg, IFLA_XDP_ATTACHED, IFLA_XDP_DRV_PROG_ID, IFLA_XDP_EXPECTED_FD,
+        IFLA_XDP_FD, IFLA_XDP_FLAGS, IFLA_XDP_HW_PROG_ID, IFLA_XDP_PROG_ID, IFLA_XDP_SKB_PROG_ID,
+        NLMSG_ALIGNTO, TCA_BPF_FD, TCA_BPF_FLAGS, TCA_BPF_FLAG_ACT_DIRECT, TCA_BPF_NAME, TCA_KIND,
+        TCA_OPTIONS, TC_H_CLSACT, TC_H_INGRESS, TC_H_MAJ_MASK, TC_H_UNSPEC, XDP_FLAGS_DRV_MODE,
+        XDP_FLAGS_HW_MODE, XDP_FLAGS_REPLACE, XDP_FLAGS_SKB_MODE,
     },
     programs::TcAttachType,
     util::tc_handler_make,
@@ -21,6 +31,21 @@ use crate::{
 
 const NLA_HDR_LEN: usize = align_to(mem::size_of::<nlattr>(), NLA_ALIGNTO as usize);
 
+const NETLINK_GENERIC: i32 = 16;
+
+// generic netlink family of the netdev subsystem, since kernel 6.3
+const NETDEV_FAMILY_NAME: &[u8] = b"netdev\0";
+const NETDEV_CMD_DEV_GET: u8 = 1;
+const NETDEV_A_DEV_IFINDEX: u16 = 1;
+const NETDEV_A_DEV_XDP_FEATURES: u16 = 3;
+
+// not in the generated bindings
+const TCA_BPF_ID: u16 = 11;
+const XDP_ATTACHED_DRV: u8 = 1;
+const XDP_ATTACHED_SKB: u8 = 2;
+const XDP_ATTACHED_HW: u8 = 3;
+const XDP_ATTACHED_MULTI: u8 = 4;
+
 // Safety: marking this as unsafe overall because of all the pointer math required to comply with
 // netlink alignments
 pub(crate) unsafe fn netlink_set_xdp_fd(
@@ -31,6 +56,22 @@ pub(crate) unsafe fn netlink_set_xdp_fd(
 ) -> Result<(), io::Error> {
     let sock = NetlinkSocket::open()?;
 
+    let req = xdp_set_fd_request(if_index, fd, old_fd, flags)?;
+    sock.send(&bytes_of(&req)[..req.header.nlmsg_len as usize])?;
+
+    sock.recv()?;
+
+    Ok(())
+}
+
+/// Builds the request attaching `fd` to `if_index`, replacing `old_fd` if
+/// `flags` contains `XDP_FLAGS_REPLACE`.
+unsafe fn xdp_set_fd_request(
+    if_index: i32,
+    fd: RawFd,
+    old_fd: Option<RawFd>,
+    flags: u32,
+) -> Result<Request, io::Error> {
     // Safety: Request is POD so this is safe
     let mut req = mem::zeroed::<Request>();
 
@@ -61,11 +102,7 @@ pub(crate) unsafe fn netlink_set_xdp_fd(
     let nla_len = attrs.finish()?;
     req.header.nlmsg_len += align_to(nla_len, NLA_ALIGNTO as usize) as u32;
 
-    sock.send(&bytes_of(&req)[..req.header.nlmsg_len as usize])?;
-
-    sock.recv()?;
-
-    Ok(())
+    Ok(req)
 }
 
 pub(crate) unsafe fn netlink_qdisc_add_clsact(if_index: i32) -> Result<(), io::Error> {
@@ -197,6 +234,25 @@ pub(crate) unsafe fn netlink_find_filter_with_name(
     attach_type: TcAttachType,
     name: &CStr,
 ) -> Result<Vec<(u16, u32)>, io::Error> {
+    Ok(netlink_find_filters(if_index, attach_type)?
+        .into_iter()
+        .filter(|filter| filter.name.as_deref() == Some(name))
+        .map(|filter| (filter.priority, filter.handle))
+        .collect())
+}
+
+pub(crate) struct TcFilterInfo {
+    pub(crate) priority: u16,
+    pub(crate) handle: u32,
+    pub(crate) name: Option<CString>,
+    pub(crate) prog_id: Option<u32>,
+}
+
+// Returns the bpf filters attached to the clsact qdisc of the interface
+pub(crate) unsafe fn netlink_find_filters(
+    if_index: i32,
+    attach_type: TcAttachType,
+) -> Result<Vec<TcFilterInfo>, io::Error> {
     let mut req = mem::zeroed::<TcRequest>();
 
     let nlmsg_len = mem::size_of::<nlmsghdr>() + mem::size_of::<tcmsg>();
@@ -227,12 +283,18 @@ pub(crate) unsafe fn netlink_find_filter_with_name(
 
         if let Some(opts) = attrs.get(&(TCA_OPTIONS as u16)) {
             let opts = parse_attrs(opts.data)?;
-            if let Some(f_name) = opts.get(&(TCA_BPF_NAME as u16)) {
-                if let Ok(f_name) = CStr::from_bytes_with_nul(f_name.data) {
-                    if name == f_name {
-                        filter_info.push((priority, tc_msg.tcm_handle));
-                    }
-                }
+            let name = opts
+                .get(&(TCA_BPF_NAME as u16))
+                .and_then(|name| CStr::from_bytes_with_nul(name.data).ok())
+                .map(CStr::to_owned);
+            let prog_id = opts.get(&TCA_BPF_ID).and_then(|id| attr_u32(id.data));
+            if name.is_some() || prog_id.is_some() {
+                filter_info.push(TcFilterInfo {
+                    priority,
+                    handle: tc_msg.tcm_handle,
+                    name,
+                    prog_id,
+                });
             }
         }
     }
@@ -240,6 +302,239 @@ pub(crate) unsafe fn netlink_find_filter_with_name(
     Ok(filter_info)
 }
 
+// Returns the (XDP_FLAGS_*_MODE, program id) pairs of the XDP programs attached
+// to the interface
+pub(crate) unsafe fn netlink_xdp_prog_ids(if_index: i32) -> Result<Vec<(u32, u32)>, io::Error> {
+    let sock = NetlinkSocket::open()?;
+
+    // Safety: Request is POD so this is safe
+    let mut req = mem::zeroed::<Request>();
+
+    let nlmsg_len = mem::size_of::<nlmsghdr>() + mem::size_of::<ifinfomsg>();
+    req.header = nlmsghdr {
+        nlmsg_len: nlmsg_len as u32,
+        nlmsg_flags: NLM_F_REQUEST as u16,
+        nlmsg_type: RTM_GETLINK,
+        nlmsg_pid: 0,
+        nlmsg_seq: 1,
+    };
+    req.if_info.ifi_family = AF_UNSPEC as u8;
+    req.if_info.ifi_index = if_index;
+
+    sock.send(&bytes_of(&req)[..req.header.nlmsg_len as usize])?;
+
+    for msg in sock.recv()? {
+        if msg.header.nlmsg_type != RTM_NEWLINK {
+            continue;
+        }
+        let attrs = parse_attrs(&msg.data[mem::size_of::<ifinfomsg>()..])?;
+        return match attrs.get(&IFLA_XDP) {
+            Some(xdp) => Ok(xdp_prog_ids(&parse_attrs(xdp.data)?)),
+            None => Ok(Vec::new()),
+        };
+    }
+
+    Err(io::ErrorKind::NotFound.into())
+}
+
+fn xdp_prog_ids(attrs: &HashMap<u16, NlAttr>) -> Vec<(u32, u32)> {
+    let get = |attr: u32| attrs.get(&(attr as u16)).and_then(|a| attr_u32(a.data));
+    let mode = match attrs
+        .get(&(IFLA_XDP_ATTACHED as u16))
+        .and_then(|a| a.data.first())
+    {
+        Some(&mode) => mode,
+        None => return Vec::new(),
+    };
+    let single_mode = match mode {
+        XDP_ATTACHED_DRV => XDP_FLAGS_DRV_MODE,
+        XDP_ATTACHED_SKB => XDP_FLAGS_SKB_MODE,
+        XDP_ATTACHED_HW => XDP_FLAGS_HW_MODE,
+        XDP_ATTACHED_MULTI => {
+            return [
+                (XDP_FLAGS_DRV_MODE, IFLA_XDP_DRV_PROG_ID),
+                (XDP_FLAGS_SKB_MODE, IFLA_XDP_SKB_PROG_ID),
+                (XDP_FLAGS_HW_MODE, IFLA_XDP_HW_PROG_ID),
+            ]
+            .into_iter()
+            .filter_map(|(flags, attr)| get(attr).map(|id| (flags, id)))
+            .collect()
+        }
+        _ => return Vec::new(),
+    };
+    get(IFLA_XDP_PROG_ID)
+        .map(|id| vec![(single_mode, id)])
+        .unwrap_or_default()
+}
+
+fn attr_u32(data: &[u8]) -> Option<u32> {
+    Some(u32::from_ne_bytes(data.get(..4)?.try_into().ok()?))
+}
+
+// Returns the NETDEV_XDP_ACT_* features supported by the interface
+pub(crate) unsafe fn netlink_xdp_features(if_index: i32) -> Result<u64, io::Error> {
+    let sock = NetlinkSocket::open_protocol(NETLINK_GENERIC)?;
+    let family_id = genl_family_id(&sock, NETDEV_FAMILY_NAME)?;
+
+    let mut req = mem::zeroed::<GenlRequest>();
+
+    let nlmsg_len = mem::size_of::<nlmsghdr>() + mem::size_of::<genlmsghdr>();
+    req.header = nlmsghdr {
+        nlmsg_len: nlmsg_len as u32,
+        nlmsg_flags: NLM_F_REQUEST as u16,
+        nlmsg_type: family_id,
+        nlmsg_pid: 0,
+        nlmsg_seq: 1,
+    };
+    req.genl_hdr.cmd = NETDEV_CMD_DEV_GET;
+    req.genl_hdr.version = 1;
+
+    let attrs_buf = request_attributes(&mut req, nlmsg_len);
+    let attr_len = write_attr(attrs_buf, 0, NETDEV_A_DEV_IFINDEX, if_index as u32)?;
+    req.header.nlmsg_len += align_to(attr_len, NLA_ALIGNTO as usize) as u32;
+
+    sock.send(&bytes_of(&req)[..req.header.nlmsg_len as usize])?;
+
+    for msg in sock.recv()? {
+        if msg.header.nlmsg_type != family_id {
+            continue;
+        }
+
+        if let Some(features) = xdp_features(&msg)? {
+            return Ok(features);
+        }
+    }
+
+    Err(io::Error::other("missing NETDEV_A_DEV_XDP_FEATURES"))
+}
+
+fn xdp_features(msg: &NetlinkMessage) -> Result<Option<u64>, io::Error> {
+    match genl_attrs(msg)?.get(&NETDEV_A_DEV_XDP_FEATURES) {
+        Some(features) => {
+            let features = features
+                .data
+                .try_into()
+                .map_err(|_| io::Error::other("invalid NETDEV_A_DEV_XDP_FEATURES"))?;
+            Ok(Some(u64::from_ne_bytes(features)))
+        }
+        None => Ok(None),
+    }
+}
+
+// A network interface was created or removed
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct LinkEvent {
+    pub(crate) removed: bool,
+    pub(crate) if_index: u32,
+    pub(crate) if_name: Option<String>,
+}
+
+// Receives the link notifications broadcast to the RTMGRP_LINK group
+pub(crate) struct NetlinkLinkMonitor {
+    sock: NetlinkSocket,
+}
+
+impl NetlinkLinkMonitor {
+    pub(crate) fn open() -> Result<NetlinkLinkMonitor, io::Error> {
+        let sock = NetlinkSocket::open()?;
+        sock.bind_groups(RTMGRP_LINK as u32)?;
+        Ok(NetlinkLinkMonitor { sock })
+    }
+
+    // Blocks until at least one notification is received. Fails with ENOBUFS
+    // if notifications were dropped because the socket buffer was full.
+    pub(crate) fn recv(&self) -> Result<Vec<LinkEvent>, io::Error> {
+        Ok(self.sock.recv()?.iter().filter_map(link_event).collect())
+    }
+}
+
+impl AsRawFd for NetlinkLinkMonitor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.sock.sock
+    }
+}
+
+fn link_event(msg: &NetlinkMessage) -> Option<LinkEvent> {
+    let removed = match msg.header.nlmsg_type {
+        RTM_NEWLINK => false,
+        RTM_DELLINK => true,
+        _ => return None,
+    };
+    if msg.data.len() < mem::size_of::<ifinfomsg>() {
+        return None;
+    }
+    // Safety: ifinfomsg is POD so read is safe
+    let if_info = unsafe { ptr::read_unaligned(msg.data.as_ptr() as *const ifinfomsg) };
+    let attrs = parse_attrs(&msg.data[mem::size_of::<ifinfomsg>()..]).ok()?;
+    let if_name = attrs.get(&IFLA_IFNAME).and_then(|attr| {
+        attr.data
+            .split(|b| *b == 0)
+            .next()
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+    });
+
+    Some(LinkEvent {
+        removed,
+        if_index: if_info.ifi_index as u32,
+        if_name,
+    })
+}
+
+unsafe fn genl_family_id(sock: &NetlinkSocket, name: &[u8]) -> Result<u16, io::Error> {
+    let mut req = mem::zeroed::<GenlRequest>();
+
+    let nlmsg_len = mem::size_of::<nlmsghdr>() + mem::size_of::<genlmsghdr>();
+    req.header = nlmsghdr {
+        nlmsg_len: nlmsg_len as u32,
+        nlmsg_flags: NLM_F_REQUEST as u16,
+        nlmsg_type: GENL_ID_CTRL as u16,
+        nlmsg_pid: 0,
+        nlmsg_seq: 1,
+    };
+    req.genl_hdr.cmd = CTRL_CMD_GETFAMILY as u8;
+    req.genl_hdr.version = 1;
+
+    let attrs_buf = request_attributes(&mut req, nlmsg_len);
+    let attr_len = write_attr_bytes(attrs_buf, 0, CTRL_ATTR_FAMILY_NAME as u16, name)?;
+    req.header.nlmsg_len += align_to(attr_len, NLA_ALIGNTO as usize) as u32;
+
+    sock.send(&bytes_of(&req)[..req.header.nlmsg_len as usize])?;
+
+    for msg in sock.recv()? {
+        if msg.header.nlmsg_type != GENL_ID_CTRL as u16 {
+            continue;
+        }
+
+        if let Some(id) = family_id(&msg)? {
+            return Ok(id);
+        }
+    }
+
+    Err(io::Error::other("missing CTRL_ATTR_FAMILY_ID"))
+}
+
+fn family_id(msg: &NetlinkMessage) -> Result<Option<u16>, io::Error> {
+    match genl_attrs(msg)?.get(&(CTRL_ATTR_FAMILY_ID as u16)) {
+        Some(id) => {
+            let id = id
+                .data
+                .try_into()
+                .map_err(|_| io::Error::other("invalid CTRL_ATTR_FAMILY_ID"))?;
+            Ok(Some(u16::from_ne_bytes(id)))
+        }
+        None => Ok(None),
+    }
+}
+
+// Parses the attributes following the genlmsghdr of a generic netlink message
+fn genl_attrs(msg: &NetlinkMessage) -> Result<HashMap<u16, NlAttr<'_>>, io::Error> {
+    let attrs = msg
+        .data
+        .get(mem::size_of::<genlmsghdr>()..)
+        .ok_or_else(|| io::Error::other("buffer smaller than genlmsghdr"))?;
+    Ok(parse_attrs(attrs)?)
+}
+
 #[repr(C)]
 struct Request {
     header: nlmsghdr,
@@ -254,6 +549,13 @@ struct TcRequest {
     attrs: [u8; 64],
 }
 
+#[repr(C)]
+struct GenlRequest {
+    header: nlmsghdr,
+    genl_hdr: genlmsghdr,
+    attrs: [u8; 64],
+}
+
 struct NetlinkSocket {
     sock: RawFd,
     _nl_pid: u32,
@@ -261,8 +563,12 @@ struct NetlinkSocket {
 
 impl NetlinkSocket {
     fn open() -> Result<NetlinkSocket, io::Error> {
+        Self::open_protocol(NETLINK_ROUTE)
+    }
+
+    fn open_protocol(protocol: i32) -> Result<NetlinkSocket, io::Error> {
         // Safety: libc wrapper
-        let sock = unsafe { socket(AF_NETLINK, SOCK_RAW, NETLINK_ROUTE) };
+        let sock = unsafe { socket(AF_NETLINK, SOCK_RAW, protocol) };
         if sock < 0 {
             return Err(io::Error::last_os_error());
         }
@@ -295,6 +601,26 @@ impl NetlinkSocket {
         })
     }
 
+    // Subscribes the socket to the given multicast groups
+    fn bind_groups(&self, groups: u32) -> Result<(), io::Error> {
+        // Safety: sockaddr_nl is POD so this is safe
+        let mut addr = unsafe { mem::zeroed::<sockaddr_nl>() };
+        addr.nl_family = AF_NETLINK as u16;
+        addr.nl_groups = groups;
+        // Safety: libc wrapper
+        if unsafe {
+            bind(
+                self.sock,
+                &addr as *const _ as *const _,
+                mem::size_of::<sockaddr_nl>() as u32,
+            )
+        } < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
     fn send(&self, msg: &[u8]) -> Result<(), io::Error> {
         if unsafe { send(self.sock, msg.as_ptr() as *const _, msg.len(), 0) } < 0 {
             return Err(io::Error::last_os_error());
@@ -303,11 +629,30 @@ impl NetlinkSocket {
     }
 
     fn recv(&self) -> Result<Vec<NetlinkMessage>, io::Error> {
-        let mut buf = [0u8; 4096];
+        let mut buf = vec![0u8; 4096];
         let mut messages = Vec::new();
         let mut multipart = true;
         'out: while multipart {
             multipart = false;
+            // Peek at the size of the next datagram first: a datagram larger
+            // than the buffer would be truncated, and dumps of interfaces with
+            // many attributes easily exceed a page.
+            // Safety: libc wrapper
+            let len = unsafe {
+                recv(
+                    self.sock,
+                    buf.as_mut_ptr() as *mut _,
+                    buf.len(),
+                    MSG_PEEK | MSG_TRUNC,
+                )
+            };
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if len as usize > buf.len() {
+                buf.resize(len as usize, 0);
+            }
+
             // Safety: libc wrapper
             let len = unsafe { recv(self.sock, buf.as_mut_ptr() as *mut _, buf.len(), 0) };
             if len < 0 {
@@ -635,6 +980,40 @@ mod tests {
         assert_eq!(fd, 24);
     }
 
+    #[test]
+    fn test_xdp_set_fd_request() {
+        let xdp_attrs = |req: &Request| {
+            let start = mem::size_of::<nlmsghdr>() + mem::size_of::<ifinfomsg>();
+            let buf = &bytes_of(req)[start..req.header.nlmsg_len as usize];
+            let attrs = parse_attrs(buf).unwrap();
+            parse_attrs(attrs[&IFLA_XDP].data)
+                .unwrap()
+                .into_iter()
+                .map(|(ty, attr)| (ty as u32, u32::from_ne_bytes(attr.data.try_into().unwrap())))
+                .collect::<HashMap<_, _>>()
+        };
+
+        let req = unsafe { xdp_set_fd_request(3, 42, None, XDP_FLAGS_SKB_MODE) }.unwrap();
+        assert_eq!(req.header.nlmsg_type, RTM_SETLINK);
+        assert_eq!(req.if_info.ifi_index, 3);
+        assert_eq!(
+            xdp_attrs(&req),
+            HashMap::from([(IFLA_XDP_FD, 42), (IFLA_XDP_FLAGS, XDP_FLAGS_SKB_MODE)])
+        );
+
+        // The program is only replaced if the expected one is still attached.
+        let flags = XDP_FLAGS_SKB_MODE | XDP_FLAGS_REPLACE;
+        let req = unsafe { xdp_set_fd_request(3, 42, Some(24), flags) }.unwrap();
+        assert_eq!(
+            xdp_attrs(&req),
+            HashMap::from([
+                (IFLA_XDP_FD, 42),
+                (IFLA_XDP_FLAGS, flags),
+                (IFLA_XDP_EXPECTED_FD, 24),
+            ])
+        );
+    }
+
     #[test]
     fn test_nlattr_iterator_empty() {
         let mut iter = NlAttrsIterator::new(&[]);
@@ -684,6 +1063,128 @@ mod tests {
         assert!(iter.next().is_none());
     }
 
+    #[test]
+    fn test_xdp_prog_ids() {
+        let mut buf = [0; 64];
+
+        let mut offset =
+            write_attr_bytes(&mut buf, 0, IFLA_XDP_ATTACHED as u16, &[XDP_ATTACHED_SKB]).unwrap();
+        offset += write_attr(&mut buf, offset, IFLA_XDP_PROG_ID as u16, 42u32).unwrap();
+        let attrs = parse_attrs(&buf[..offset]).unwrap();
+        assert_eq!(xdp_prog_ids(&attrs), vec![(XDP_FLAGS_SKB_MODE, 42)]);
+
+        let mut offset =
+            write_attr_bytes(&mut buf, 0, IFLA_XDP_ATTACHED as u16, &[XDP_ATTACHED_MULTI]).unwrap();
+        offset += write_attr(&mut buf, offset, IFLA_XDP_DRV_PROG_ID as u16, 42u32).unwrap();
+        offset += write_attr(&mut buf, offset, IFLA_XDP_HW_PROG_ID as u16, 24u32).unwrap();
+        let attrs = parse_attrs(&buf[..offset]).unwrap();
+        assert_eq!(
+            xdp_prog_ids(&attrs),
+            vec![(XDP_FLAGS_DRV_MODE, 42), (XDP_FLAGS_HW_MODE, 24)]
+        );
+
+        let offset = write_attr_bytes(&mut buf, 0, IFLA_XDP_ATTACHED as u16, &[0]).unwrap();
+        let attrs = parse_attrs(&buf[..offset]).unwrap();
+        assert!(xdp_prog_ids(&attrs).is_empty());
+    }
+
+    #[test]
+    fn test_link_event() {
+        let mut buf = [0u8; 128];
+        let header_len = align_to(mem::size_of::<nlmsghdr>(), NLMSG_ALIGNTO as usize);
+        let info_len = mem::size_of::<ifinfomsg>();
+        let attr_len = write_attr_bytes(
+            &mut buf[header_len + info_len..],
+            0,
+            IFLA_IFNAME,
+            b"veth0\0",
+        )
+        .unwrap();
+        let mut if_info = unsafe { mem::zeroed::<ifinfomsg>() };
+        if_info.ifi_index = 7;
+        buf[header_len..header_len + info_len].copy_from_slice(bytes_of(&if_info));
+
+        let msg_len = header_len + info_len + attr_len;
+        let mut read = |nlmsg_type| {
+            let header = nlmsghdr {
+                nlmsg_len: msg_len as u32,
+                nlmsg_type,
+                nlmsg_flags: 0,
+                nlmsg_seq: 0,
+                nlmsg_pid: 0,
+            };
+            buf[..mem::size_of::<nlmsghdr>()].copy_from_slice(bytes_of(&header));
+            link_event(&NetlinkMessage::read(&buf[..msg_len]).unwrap())
+        };
+
+        assert_eq!(
+            read(RTM_NEWLINK),
+            Some(LinkEvent {
+                removed: false,
+                if_index: 7,
+                if_name: Some("veth0".to_owned()),
+            })
+        );
+        assert_eq!(
+            read(RTM_DELLINK),
+            Some(LinkEvent {
+                removed: true,
+                if_index: 7,
+                if_name: Some("veth0".to_owned()),
+            })
+        );
+        assert_eq!(read(RTM_NEWTFILTER), None);
+    }
+
+    fn genl_message(attr: Option<(u16, &[u8])>) -> NetlinkMessage {
+        let header_len = mem::size_of::<genlmsghdr>();
+        let mut data = vec![0u8; header_len + 16];
+        let attr_len = match attr {
+            Some((attr_type, value)) => {
+                write_attr_bytes(&mut data[header_len..], 0, attr_type, value).unwrap()
+            }
+            None => 0,
+        };
+        data.truncate(header_len + attr_len);
+        NetlinkMessage {
+            header: unsafe { mem::zeroed() },
+            data,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_xdp_features() {
+        let features = 0x1fu64.to_ne_bytes();
+        let msg = genl_message(Some((NETDEV_A_DEV_XDP_FEATURES, &features)));
+        assert_eq!(xdp_features(&msg).unwrap(), Some(0x1f));
+
+        assert_eq!(xdp_features(&genl_message(None)).unwrap(), None);
+
+        let msg = genl_message(Some((NETDEV_A_DEV_XDP_FEATURES, &[1, 2])));
+        assert!(xdp_features(&msg).is_err());
+
+        let mut msg = genl_message(None);
+        msg.data.truncate(2);
+        assert!(xdp_features(&msg).is_err());
+    }
+
+    #[test]
+    fn test_family_id() {
+        let id = 42u16.to_ne_bytes();
+        let msg = genl_message(Some((CTRL_ATTR_FAMILY_ID as u16, &id)));
+        assert_eq!(family_id(&msg).unwrap(), Some(42));
+
+        assert_eq!(family_id(&genl_message(None)).unwrap(), None);
+
+        let msg = genl_message(Some((CTRL_ATTR_FAMILY_ID as u16, &[1, 2, 3, 4])));
+        assert!(family_id(&msg).is_err());
+
+        let mut msg = genl_message(None);
+        msg.data.clear();
+        assert!(family_id(&msg).is_err());
+    }
+
     #[test]
     fn test_nlattr_iterator_nested() {
         let mut buf = [0; 1024];
@@ -12,6 +12,10 @@ use crate::generated::{
 
 use super::{syscall, SysResult, Syscall};
 
+// the uprobe PMU takes the offset of the reference counter (USDT semaphore)
+// in bits 32-63 of `config`
+const PERF_UPROBE_REF_CTR_OFFSET_SHIFT: u32 = 32;
+
 #[allow(clippy::too_many_arguments)]
 pub(crate) fn perf_event_open(
     perf_type: u32,
@@ -66,12 +70,14 @@ pub(crate) fn perf_event_open_probe(
     ret_bit: Option<u32>,
     name: &str,
     offset: u64,
+    ref_ctr_offset: u64,
     pid: Option<pid_t>,
 ) -> SysResult {
     let mut attr = unsafe { mem::zeroed::<perf_event_attr>() };
 
+    attr.config = ref_ctr_offset << PERF_UPROBE_REF_CTR_OFFSET_SHIFT;
     if let Some(ret_bit) = ret_bit {
-        attr.config = 1 << ret_bit;
+        attr.config |= 1 << ret_bit;
     }
 
     let c_name = CString::new(name).unwrap();
@@ -1,26 +1,48 @@
 //! Utility functions.
 use std::{
-    collections::BTreeMap,
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
     ffi::{CStr, CString},
     fs::{self, File},
     io::{self, BufReader},
-    mem, slice,
+    mem,
+    os::unix::io::{AsRawFd, RawFd},
+    slice,
     str::FromStr,
+    sync::{Arc, Mutex},
 };
 
 use crate::{
-    generated::{TC_H_MAJ_MASK, TC_H_MIN_MASK},
+    generated::{bpf_line_info, TC_H_MAJ_MASK, TC_H_MIN_MASK},
+    obj::btf::Btf,
+    sys::{is_bpf_memcg_accounting_supported, NetlinkLinkMonitor},
     Pod,
 };
 
-use libc::{if_nametoindex, sysconf, _SC_PAGESIZE};
+use libc::{
+    c_char, getrlimit, if_freenameindex, if_indextoname, if_nameindex, if_nametoindex, rlimit,
+    setrlimit, sysconf, _SC_PAGESIZE, ENOBUFS, IF_NAMESIZE, RLIMIT_MEMLOCK, RLIM_INFINITY,
+};
 
 use io::BufRead;
 
 const ONLINE_CPUS: &str = "/sys/devices/system/cpu/online";
 pub(crate) const POSSIBLE_CPUS: &str = "/sys/devices/system/cpu/possible";
+const KALLSYMS: &str = "/proc/kallsyms";
+
+lazy_static! {
+    static ref KERNEL_SYMBOLS: Mutex<Option<Arc<KernelSymbols>>> = Mutex::new(None);
+    static ref MEMORY_ACCOUNTING: MemoryAccounting = if is_bpf_memcg_accounting_supported() {
+        MemoryAccounting::Memcg
+    } else {
+        MemoryAccounting::Rlimit
+    };
+}
 
 /// Returns the numeric IDs of the CPUs currently online.
+///
+/// Use this to pick the CPUs to read from, for example when opening the
+/// buffers of a [`PerfEventArray`](crate::maps::PerfEventArray). CPUs can go
+/// online and offline at runtime, see [`possible_cpus`].
 pub fn online_cpus() -> Result<Vec<u32>, io::Error> {
     let data = fs::read_to_string(ONLINE_CPUS)?;
     parse_cpu_ranges(data.trim()).map_err(|_| {
@@ -34,14 +56,30 @@ pub fn online_cpus() -> Result<Vec<u32>, io::Error> {
 /// Get the number of possible cpus.
 ///
 /// See `/sys/devices/system/cpu/possible`.
+#[deprecated(note = "use `nr_possible_cpus` instead")]
 pub fn nr_cpus() -> Result<usize, io::Error> {
+    nr_possible_cpus()
+}
+
+/// Returns the number of possible CPUs.
+///
+/// Per-CPU maps hold one value for every possible CPU, including the CPUs
+/// currently offline, so this is the number of values of a
+/// [`PerCpuValues`](crate::maps::PerCpuValues). Use it to size the buffers
+/// and maps indexed by CPU as well.
+///
+/// See `/sys/devices/system/cpu/possible`.
+pub fn nr_possible_cpus() -> Result<usize, io::Error> {
     Ok(possible_cpus()?.len())
 }
 
-/// Get the list of possible cpus.
+/// Returns the numeric IDs of the possible CPUs.
+///
+/// The possible CPUs are the CPUs that can ever be online while the system is
+/// running, see [`nr_possible_cpus`] and [`online_cpus`].
 ///
 /// See `/sys/devices/system/cpu/possible`.
-pub(crate) fn possible_cpus() -> Result<Vec<u32>, io::Error> {
+pub fn possible_cpus() -> Result<Vec<u32>, io::Error> {
     let data = fs::read_to_string(POSSIBLE_CPUS)?;
     parse_cpu_ranges(data.trim()).map_err(|_| {
         io::Error::new(
@@ -76,7 +114,7 @@ fn parse_cpu_ranges(data: &str) -> Result<Vec<u32>, ()> {
 ///
 /// The symbols can be passed to [`StackTrace::resolve`](crate::maps::stack_trace::StackTrace::resolve).
 pub fn kernel_symbols() -> Result<BTreeMap<u64, String>, io::Error> {
-    let mut reader = BufReader::new(File::open("/proc/kallsyms")?);
+    let mut reader = BufReader::new(File::open(KALLSYMS)?);
     parse_kernel_symbols(&mut reader)
 }
 
@@ -85,17 +123,380 @@ fn parse_kernel_symbols(reader: impl BufRead) -> Result<BTreeMap<u64, String>, i
 
     for line in reader.lines() {
         let line = line?;
-        let parts = line.splitn(4, ' ').collect::<Vec<_>>();
-        let addr = u64::from_str_radix(parts[0], 16)
-            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, line.clone()))?;
-        let name = parts[2].to_owned();
-        syms.insert(addr, name);
+        let (addr, name) = parse_kernel_symbol(&line)?;
+        syms.insert(addr, name.to_owned());
     }
 
     Ok(syms)
 }
 
-pub(crate) fn ifindex_from_ifname(if_name: &str) -> Result<u32, io::Error> {
+// Parses a `/proc/kallsyms` line like `ffffffffc0a01000 t foo\t[module]`.
+fn parse_kernel_symbol(line: &str) -> Result<(u64, &str), io::Error> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, line.to_owned());
+    let mut parts = line.split_whitespace();
+    let addr = parts
+        .next()
+        .and_then(|addr| u64::from_str_radix(addr, 16).ok())
+        .ok_or_else(invalid)?;
+    let name = parts.nth(1).ok_or_else(invalid)?;
+    Ok((addr, name))
+}
+
+/// Kernel symbols from `/proc/kallsyms`, indexed by address and by name.
+///
+/// The symbols are parsed once and shared, see [`KernelSymbols::get`].
+///
+/// When `kptr_restrict` hides kernel addresses from the current user, all the
+/// addresses are zero: names can still be looked up with
+/// [`KernelSymbols::contains`], but addresses can't be resolved.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya::util::KernelSymbols;
+///
+/// let syms = KernelSymbols::get()?;
+/// if !syms.contains("tcp_v4_connect") {
+///     // the function doesn't exist or was inlined, don't attach a kprobe to it
+/// }
+/// if let Some((name, offset)) = syms.resolve(0xffffffff81b0e8f5) {
+///     println!("{name}+{offset:#x}");
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct KernelSymbols {
+    by_addr: BTreeMap<u64, String>,
+    by_name: HashMap<String, u64>,
+    duplicates: HashSet<String>,
+}
+
+impl KernelSymbols {
+    /// Returns the kernel symbols, parsing `/proc/kallsyms` on first use.
+    ///
+    /// Later calls return the cached symbols. Use [`KernelSymbols::refresh`]
+    /// to pick up the symbols of modules loaded since.
+    pub fn get() -> Result<Arc<KernelSymbols>, io::Error> {
+        let mut cached = KERNEL_SYMBOLS.lock().unwrap();
+        match &*cached {
+            Some(syms) => Ok(syms.clone()),
+            None => {
+                let syms = Arc::new(KernelSymbols::load()?);
+                *cached = Some(syms.clone());
+                Ok(syms)
+            }
+        }
+    }
+
+    /// Parses `/proc/kallsyms` again and replaces the cached symbols.
+    pub fn refresh() -> Result<Arc<KernelSymbols>, io::Error> {
+        let syms = Arc::new(KernelSymbols::load()?);
+        *KERNEL_SYMBOLS.lock().unwrap() = Some(syms.clone());
+        Ok(syms)
+    }
+
+    fn load() -> Result<KernelSymbols, io::Error> {
+        let reader = BufReader::new(File::open(KALLSYMS)?);
+        KernelSymbols::parse(reader)
+    }
+
+    pub(crate) fn parse(reader: impl BufRead) -> Result<KernelSymbols, io::Error> {
+        let mut by_addr = BTreeMap::new();
+        let mut by_name = HashMap::new();
+        let mut duplicates = HashSet::new();
+        for line in reader.lines() {
+            let line = line?;
+            let (addr, name) = parse_kernel_symbol(&line)?;
+            // static functions can share a name, keep the first one like the
+            // kernel does when resolving kprobe targets
+            match by_name.entry(name.to_owned()) {
+                Entry::Occupied(_) => {
+                    duplicates.insert(name.to_owned());
+                }
+                Entry::Vacant(e) => {
+                    e.insert(addr);
+                }
+            }
+            if addr != 0 {
+                by_addr.insert(addr, name.to_owned());
+            }
+        }
+        Ok(KernelSymbols {
+            by_addr,
+            by_name,
+            duplicates,
+        })
+    }
+
+    /// Returns true if the kernel has a symbol called `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.by_name.contains_key(name)
+    }
+
+    /// Returns true if the kernel has more than one symbol called `name`.
+    ///
+    /// Static functions of different files can share a name. The address and
+    /// size of such symbols are the ones of the first of them.
+    pub fn is_ambiguous(&self, name: &str) -> bool {
+        self.duplicates.contains(name)
+    }
+
+    /// Returns the address of the symbol called `name`.
+    ///
+    /// Returns `None` if there's no such symbol or if its address is hidden.
+    pub fn address(&self, name: &str) -> Option<u64> {
+        self.by_name.get(name).copied().filter(|addr| *addr != 0)
+    }
+
+    /// Returns the size of the symbol called `name`.
+    ///
+    /// `/proc/kallsyms` doesn't record sizes, so this is the distance to the
+    /// next symbol, which may include padding. Returns `None` if there's no
+    /// such symbol, if its address is hidden, or if it's the last symbol.
+    pub fn size(&self, name: &str) -> Option<u64> {
+        let addr = self.address(name)?;
+        self.by_addr
+            .range(addr + 1..)
+            .next()
+            .map(|(next, _)| next - addr)
+    }
+
+    /// Returns the name of the symbol containing `addr`, and the offset of
+    /// `addr` from the start of the symbol.
+    pub fn resolve(&self, addr: u64) -> Option<(&str, u64)> {
+        self.by_addr
+            .range(..=addr)
+            .next_back()
+            .map(|(start, name)| (name.as_str(), addr - start))
+    }
+
+    /// Returns the symbols indexed by address.
+    ///
+    /// The map can be passed to
+    /// [`StackTrace::resolve`](crate::maps::stack_trace::StackTrace::resolve).
+    pub fn symbols(&self) -> &BTreeMap<u64, String> {
+        &self.by_addr
+    }
+}
+
+/// Runs a blocking function without stalling the async executor.
+///
+/// Loading an object can take seconds since every program goes through the
+/// verifier, and attaching programs performs blocking syscalls. With the
+/// `async_tokio` feature `f` runs on the tokio blocking thread pool, otherwise
+/// it runs on a dedicated thread.
+///
+/// # Panics
+///
+/// Resumes the panic if `f` panics. With the `async_tokio` feature, also panics
+/// if the blocking task is cancelled before it completes, which happens when
+/// the runtime shuts down.
+///
+/// # Examples
+///
+/// ```no_run
+/// # async fn run() -> Result<(), aya::BpfError> {
+/// use aya::{programs::{Xdp, XdpFlags}, util::unblock, Bpf};
+///
+/// let bpf = unblock(|| -> Result<_, aya::BpfError> {
+///     let mut bpf = Bpf::load_file("xdp.o")?;
+///     let program: &mut Xdp = bpf.program_mut("xdp").unwrap().try_into()?;
+///     program.load()?;
+///     program.attach("eth0", XdpFlags::default())?;
+///     Ok(bpf)
+/// })
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub async fn unblock<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    #[cfg(feature = "async_tokio")]
+    let result = tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| match e.try_into_panic() {
+            Ok(panic) => panic,
+            Err(e) => panic!("the blocking task didn't complete: {e}"),
+        });
+    #[cfg(not(feature = "async_tokio"))]
+    let result = unblock_thread::Unblock::spawn(f).await;
+
+    result.unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+#[cfg(all(feature = "async", not(feature = "async_tokio")))]
+mod unblock_thread {
+    use std::{
+        future::Future,
+        panic::{self, AssertUnwindSafe},
+        pin::Pin,
+        sync::{Arc, Mutex},
+        task::{Context, Poll, Waker},
+        thread,
+    };
+
+    struct State<T> {
+        result: Option<thread::Result<T>>,
+        waker: Option<Waker>,
+    }
+
+    // Resolves with the result of a function running on its own thread.
+    pub(super) struct Unblock<T> {
+        state: Arc<Mutex<State<T>>>,
+    }
+
+    impl<T: Send + 'static> Unblock<T> {
+        pub(super) fn spawn<F: FnOnce() -> T + Send + 'static>(f: F) -> Unblock<T> {
+            let state = Arc::new(Mutex::new(State {
+                result: None,
+                waker: None,
+            }));
+            let thread_state = state.clone();
+            thread::spawn(move || {
+                let result = panic::catch_unwind(AssertUnwindSafe(f));
+                let mut state = thread_state.lock().unwrap();
+                state.result = Some(result);
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            });
+            Unblock { state }
+        }
+    }
+
+    impl<T> Future for Unblock<T> {
+        type Output = thread::Result<T>;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let mut state = self.state.lock().unwrap();
+            match state.result.take() {
+                Some(result) => Poll::Ready(result),
+                None => {
+                    state.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::sync::mpsc;
+
+        use futures::{
+            executor::block_on,
+            task::{waker, ArcWake},
+        };
+
+        use super::*;
+
+        #[test]
+        fn test_unblock() {
+            assert_eq!(block_on(Unblock::spawn(|| 42)).unwrap(), 42);
+        }
+
+        #[test]
+        fn test_unblock_panic() {
+            let panic = block_on(Unblock::spawn(|| panic!("oops"))).unwrap_err();
+            assert_eq!(panic.downcast_ref::<&str>(), Some(&"oops"));
+        }
+
+        struct Woken(Mutex<mpsc::Sender<()>>);
+
+        impl ArcWake for Woken {
+            fn wake_by_ref(arc_self: &Arc<Self>) {
+                arc_self.0.lock().unwrap().send(()).unwrap();
+            }
+        }
+
+        #[test]
+        fn test_unblock_wakes() {
+            let (woken_tx, woken_rx) = mpsc::channel();
+            let waker = waker(Arc::new(Woken(Mutex::new(woken_tx))));
+            let mut cx = Context::from_waker(&waker);
+
+            let (tx, rx) = mpsc::channel();
+            let mut unblock = Unblock::spawn(move || rx.recv().unwrap());
+            // The function is blocked until it receives the value
+            assert!(Pin::new(&mut unblock).poll(&mut cx).is_pending());
+            tx.send(7).unwrap();
+            woken_rx.recv().unwrap();
+            assert!(matches!(
+                Pin::new(&mut unblock).poll(&mut cx),
+                Poll::Ready(Ok(7))
+            ));
+        }
+    }
+}
+
+/// How the kernel accounts the memory used by eBPF maps and programs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryAccounting {
+    /// The memory is charged to the memory cgroup of the process, and
+    /// `RLIMIT_MEMLOCK` is ignored. This is the case since kernel 5.11.
+    Memcg,
+    /// The memory is counted against the `RLIMIT_MEMLOCK` limit of the process.
+    Rlimit,
+}
+
+/// Returns how the running kernel accounts the memory used by eBPF maps and
+/// programs.
+///
+/// The result is probed the first time this is called and cached.
+pub fn memory_accounting() -> MemoryAccounting {
+    *MEMORY_ACCOUNTING
+}
+
+/// Raises `RLIMIT_MEMLOCK` to `RLIM_INFINITY` if the kernel counts eBPF memory
+/// against it.
+///
+/// On kernels that use memcg accounting this does nothing. Raising the limit
+/// requires `CAP_SYS_RESOURCE` unless the hard limit is already infinite.
+/// Returns `true` if the limit was raised.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya::{util::raise_memlock_rlimit, Bpf};
+///
+/// raise_memlock_rlimit()?;
+/// let bpf = Bpf::load_file("file.o")?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn raise_memlock_rlimit() -> Result<bool, io::Error> {
+    if memory_accounting() == MemoryAccounting::Memcg {
+        return Ok(false);
+    }
+
+    let mut limit = mem::MaybeUninit::<rlimit>::uninit();
+    // Safety: libc wrapper
+    if unsafe { getrlimit(RLIMIT_MEMLOCK, limit.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // Safety: getrlimit succeeded so limit is initialized
+    let limit = unsafe { limit.assume_init() };
+    if limit.rlim_cur == RLIM_INFINITY {
+        return Ok(false);
+    }
+
+    let limit = rlimit {
+        rlim_cur: RLIM_INFINITY,
+        rlim_max: RLIM_INFINITY,
+    };
+    // Safety: libc wrapper
+    if unsafe { setrlimit(RLIMIT_MEMLOCK, &limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(true)
+}
+
+/// Returns the index of the network interface named `if_name`.
+pub fn ifindex_from_ifname(if_name: &str) -> Result<u32, io::Error> {
     let c_str_if_name = CString::new(if_name)?;
     let c_if_name = c_str_if_name.as_ptr();
     // Safety: libc wrapper
@@ -106,6 +507,192 @@ pub(crate) fn ifindex_from_ifname(if_name: &str) -> Result<u32, io::Error> {
     Ok(if_index)
 }
 
+/// Returns the name of the network interface with index `if_index`.
+pub fn ifname_from_ifindex(if_index: u32) -> Result<String, io::Error> {
+    let mut buf = [0 as c_char; IF_NAMESIZE];
+    // Safety: libc wrapper, buf is IF_NAMESIZE bytes long
+    if unsafe { if_indextoname(if_index, buf.as_mut_ptr()) }.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    // Safety: if_indextoname nul terminates the name on success
+    let if_name = unsafe { CStr::from_ptr(buf.as_ptr()) };
+    Ok(if_name.to_string_lossy().into_owned())
+}
+
+/// Returns the index and name of all the network interfaces.
+pub fn interfaces() -> Result<Vec<(u32, String)>, io::Error> {
+    // Safety: libc wrapper
+    let list = unsafe { if_nameindex() };
+    if list.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut interfaces = Vec::new();
+    let mut entry = list;
+    // Safety: the list is terminated by an entry with index 0 and a null name
+    unsafe {
+        while (*entry).if_index != 0 && !(*entry).if_name.is_null() {
+            let if_name = CStr::from_ptr((*entry).if_name);
+            interfaces.push(((*entry).if_index, if_name.to_string_lossy().into_owned()));
+            entry = entry.add(1);
+        }
+        if_freenameindex(list);
+    }
+
+    Ok(interfaces)
+}
+
+/// A change to the set of network interfaces, see [`InterfaceMonitor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterfaceEvent {
+    /// A network interface was created.
+    Added {
+        /// The interface index.
+        index: u32,
+        /// The interface name.
+        name: String,
+    },
+    /// A network interface was removed.
+    Removed {
+        /// The interface index.
+        index: u32,
+        /// The interface name.
+        name: String,
+    },
+}
+
+/// Watches for network interfaces being created and removed.
+///
+/// This can be used to attach [`Xdp`](crate::programs::Xdp) or
+/// [`SchedClassifier`](crate::programs::SchedClassifier) programs to
+/// interfaces that appear after the program has started. The monitor
+/// subscribes to the kernel's netlink link notifications, and only reports
+/// interfaces that didn't exist when it was created.
+///
+/// # Examples
+///
+/// ```no_run
+/// use aya::util::{InterfaceEvent, InterfaceMonitor};
+///
+/// let mut monitor = InterfaceMonitor::new()?;
+/// loop {
+///     for event in monitor.events()? {
+///         if let InterfaceEvent::Added { name, .. } = event {
+///             println!("attaching to {name}");
+///         }
+///     }
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct InterfaceMonitor {
+    monitor: NetlinkLinkMonitor,
+    known: HashMap<u32, String>,
+}
+
+impl InterfaceMonitor {
+    /// Creates a new monitor.
+    pub fn new() -> Result<InterfaceMonitor, io::Error> {
+        // subscribe before listing the interfaces so that no interface can
+        // be missed in between
+        let monitor = NetlinkLinkMonitor::open()?;
+        let known = interfaces()?.into_iter().collect();
+        Ok(InterfaceMonitor { monitor, known })
+    }
+
+    /// Returns the interfaces that are currently known to the monitor.
+    pub fn interfaces(&self) -> impl Iterator<Item = (u32, &str)> {
+        self.known
+            .iter()
+            .map(|(index, name)| (*index, name.as_str()))
+    }
+
+    /// Waits for interfaces to be created or removed.
+    ///
+    /// This blocks until the kernel sends a link notification, and can return
+    /// an empty list if the notification didn't add or remove an interface,
+    /// for example when an existing interface changed state. The monitor's
+    /// file descriptor can be polled to wait for notifications without
+    /// blocking.
+    ///
+    /// If notifications arrive faster than they are read, the kernel drops
+    /// some of them. The monitor then lists the interfaces again and reports
+    /// the ones added and removed since the last call.
+    pub fn events(&mut self) -> Result<Vec<InterfaceEvent>, io::Error> {
+        let link_events = match self.monitor.recv() {
+            Ok(link_events) => link_events,
+            Err(e) if e.raw_os_error() == Some(ENOBUFS) => {
+                return Ok(resync_interfaces(&mut self.known, interfaces()?));
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut events = Vec::new();
+        for event in link_events {
+            let index = event.if_index;
+            if event.removed {
+                let name = self.known.remove(&index).or(event.if_name);
+                if let Some(name) = name {
+                    events.push(InterfaceEvent::Removed { index, name });
+                }
+                continue;
+            }
+
+            let name = match event.if_name {
+                Some(name) => name,
+                None => match ifname_from_ifindex(index) {
+                    Ok(name) => name,
+                    Err(_) => continue,
+                },
+            };
+            // existing interfaces also get RTM_NEWLINK on state changes and
+            // renames
+            if self.known.insert(index, name.clone()).is_none() {
+                events.push(InterfaceEvent::Added { index, name });
+            }
+        }
+        Ok(events)
+    }
+}
+
+impl AsRawFd for InterfaceMonitor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.monitor.as_raw_fd()
+    }
+}
+
+// Replaces the `known` interfaces with the `current` ones, and returns the
+// interfaces removed and added in between, ordered by index
+fn resync_interfaces(
+    known: &mut HashMap<u32, String>,
+    current: Vec<(u32, String)>,
+) -> Vec<InterfaceEvent> {
+    let current = current.into_iter().collect::<HashMap<_, _>>();
+
+    let mut removed = known
+        .iter()
+        .filter(|(index, _)| !current.contains_key(index))
+        .map(|(index, name)| (*index, name.clone()))
+        .collect::<Vec<_>>();
+    removed.sort();
+    let mut added = current
+        .iter()
+        .filter(|(index, _)| !known.contains_key(index))
+        .map(|(index, name)| (*index, name.clone()))
+        .collect::<Vec<_>>();
+    added.sort();
+    *known = current;
+
+    removed
+        .into_iter()
+        .map(|(index, name)| InterfaceEvent::Removed { index, name })
+        .chain(
+            added
+                .into_iter()
+                .map(|(index, name)| InterfaceEvent::Added { index, name }),
+        )
+        .collect()
+}
+
 pub(crate) fn tc_handler_make(major: u32, minor: u32) -> u32 {
     (major & TC_H_MAJ_MASK) | (minor & TC_H_MIN_MASK)
 }
@@ -218,6 +805,55 @@ impl VerifierLog {
     }
 }
 
+/// Annotates a verifier log with the source lines of the program.
+///
+/// A `; <source> @ <file>:<line>` line is inserted before each instruction
+/// that starts a new source line, unless the kernel already printed it.
+pub(crate) fn annotate_verifier_log(log: &str, line_info: &[bpf_line_info], btf: &Btf) -> String {
+    let line_info = line_info
+        .iter()
+        .map(|info| (info.insn_off, info))
+        .collect::<HashMap<_, _>>();
+
+    let mut annotated = String::with_capacity(log.len());
+    let mut last_source = None;
+    let mut prev_is_source = false;
+    for line in log.lines() {
+        let info = verifier_log_insn(line).and_then(|insn| line_info.get(&insn));
+        if let Some(info) = info {
+            let source = (info.file_name_off, info.line_off, info.line_col >> 10);
+            if !prev_is_source && last_source != Some(source) {
+                if let Some(text) = source_line(btf, info) {
+                    annotated.push_str(&text);
+                    annotated.push('\n');
+                }
+            }
+            last_source = Some(source);
+        }
+        prev_is_source = line.starts_with("; ");
+        annotated.push_str(line);
+        annotated.push('\n');
+    }
+    annotated
+}
+
+// Returns the index of the instruction of a `12: (85) call bpf_foo#1` line.
+fn verifier_log_insn(line: &str) -> Option<u32> {
+    let (insn, _) = line.split_once(": (")?;
+    insn.parse().ok()
+}
+
+fn source_line(btf: &Btf, info: &bpf_line_info) -> Option<String> {
+    let text = btf.string_at(info.line_off).ok()?;
+    let file = btf.string_at(info.file_name_off).ok()?;
+    Some(format!(
+        "; {} @ {}:{}",
+        text.trim_start(),
+        file,
+        info.line_col >> 10
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -257,4 +893,108 @@ mod tests {
         );
         assert_eq!(syms.get(&0x6000u64).unwrap().as_str(), "cpu_tss_rw");
     }
+
+    #[test]
+    fn test_kernel_symbols_lookup() {
+        let data = "ffffffff81000000 T _text\n\
+                    ffffffff81000100 t tcp_v4_connect\n\
+                    ffffffff81000200 t helper\n\
+                    ffffffff81000300 t helper\n\
+                    ffffffffc0a01000 t nf_conntrack_in\t[nf_conntrack]\n"
+            .as_bytes();
+        let syms = KernelSymbols::parse(BufReader::new(data)).unwrap();
+        assert!(syms.contains("tcp_v4_connect"));
+        assert!(!syms.contains("udp_sendmsg"));
+        assert_eq!(syms.address("helper"), Some(0xffffffff81000200));
+        assert!(syms.is_ambiguous("helper"));
+        assert!(!syms.is_ambiguous("tcp_v4_connect"));
+        assert_eq!(syms.address("nf_conntrack_in"), Some(0xffffffffc0a01000));
+        assert_eq!(
+            syms.resolve(0xffffffff81000142),
+            Some(("tcp_v4_connect", 0x42))
+        );
+        assert_eq!(syms.resolve(0x1000), None);
+        assert_eq!(syms.size("tcp_v4_connect"), Some(0x100));
+        assert_eq!(syms.size("nf_conntrack_in"), None);
+        assert_eq!(syms.size("udp_sendmsg"), None);
+    }
+
+    #[test]
+    fn test_kernel_symbols_hidden_addresses() {
+        let data = "0000000000000000 T _text\n\
+                    0000000000000000 t tcp_v4_connect\n"
+            .as_bytes();
+        let syms = KernelSymbols::parse(BufReader::new(data)).unwrap();
+        assert!(syms.contains("tcp_v4_connect"));
+        assert_eq!(syms.address("tcp_v4_connect"), None);
+        assert_eq!(syms.resolve(0xffffffff81000142), None);
+        assert_eq!(syms.size("tcp_v4_connect"), None);
+    }
+
+    #[test]
+    fn test_annotate_verifier_log() {
+        let mut btf = Btf::new();
+        let file_name_off = btf.add_string("prog.c".to_string());
+        let first = btf.add_string("    int x = 0;".to_string());
+        let second = btf.add_string("return *p;".to_string());
+        let line_info = [
+            bpf_line_info {
+                insn_off: 0,
+                file_name_off,
+                line_off: first,
+                line_col: 3 << 10 | 5,
+            },
+            bpf_line_info {
+                insn_off: 2,
+                file_name_off,
+                line_off: second,
+                line_col: 4 << 10 | 5,
+            },
+        ];
+        let log = "0: (b7) r0 = 0\n\
+                   1: (bf) r1 = r10\n\
+                   ; return *p;\n\
+                   2: (79) r0 = *(u64 *)(r1 +0)\n\
+                   invalid read from stack off 0+0 size 8\n";
+        assert_eq!(
+            annotate_verifier_log(log, &line_info, &btf),
+            "; int x = 0; @ prog.c:3\n\
+             0: (b7) r0 = 0\n\
+             1: (bf) r1 = r10\n\
+             ; return *p;\n\
+             2: (79) r0 = *(u64 *)(r1 +0)\n\
+             invalid read from stack off 0+0 size 8\n"
+        );
+    }
+
+    #[test]
+    fn test_resync_interfaces() {
+        let mut known = [(1, "lo"), (2, "eth0"), (5, "veth0")]
+            .map(|(index, name)| (index, name.to_owned()))
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        let current = [(1, "lo"), (2, "eth0"), (7, "veth2"), (6, "veth1")]
+            .map(|(index, name)| (index, name.to_owned()))
+            .to_vec();
+
+        assert_eq!(
+            resync_interfaces(&mut known, current.clone()),
+            vec![
+                InterfaceEvent::Removed {
+                    index: 5,
+                    name: "veth0".to_owned()
+                },
+                InterfaceEvent::Added {
+                    index: 6,
+                    name: "veth1".to_owned()
+                },
+                InterfaceEvent::Added {
+                    index: 7,
+                    name: "veth2".to_owned()
+                },
+            ]
+        );
+        assert_eq!(known, current.iter().cloned().collect());
+        assert!(resync_interfaces(&mut known, current).is_empty());
+    }
 }
@@ -1,5 +1,7 @@
 use core::{cell::UnsafeCell, marker::PhantomData, mem};
 
+use aya_bpf_cty::c_long;
+
 use crate::{
     bindings::{bpf_map_def, bpf_map_type::BPF_MAP_TYPE_PERF_EVENT_ARRAY, BPF_F_CURRENT_CPU},
     helpers::bpf_perf_event_output,
@@ -55,15 +57,34 @@ impl<T> PerfEventArray<T> {
     }
 
     pub fn output_at_index<C: BpfContext>(&self, ctx: &C, index: u32, data: &T, flags: u32) {
+        let _ = self.try_output_at_index(ctx, index, data, flags);
+    }
+
+    /// Like [`output`](Self::output), but returns the error of
+    /// `bpf_perf_event_output`, for example when the perf buffer is full.
+    pub fn try_output<C: BpfContext>(&self, ctx: &C, data: &T, flags: u32) -> Result<(), c_long> {
+        self.try_output_at_index(ctx, BPF_F_CURRENT_CPU as u32, data, flags)
+    }
+
+    /// Like [`output_at_index`](Self::output_at_index), but returns the error
+    /// of `bpf_perf_event_output`.
+    pub fn try_output_at_index<C: BpfContext>(
+        &self,
+        ctx: &C,
+        index: u32,
+        data: &T,
+        flags: u32,
+    ) -> Result<(), c_long> {
         let flags = (flags as u64) << 32 | index as u64;
-        unsafe {
+        let ret = unsafe {
             bpf_perf_event_output(
                 ctx.as_ptr(),
                 self.def.get() as *mut _,
                 flags,
                 data as *const _ as *mut _,
                 mem::size_of::<T>() as u64,
-            );
-        }
+            )
+        };
+        (ret == 0).then_some(()).ok_or(ret)
     }
 }
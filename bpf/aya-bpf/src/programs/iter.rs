@@ -0,0 +1,129 @@
+use core::{ffi::c_void, mem};
+
+use crate::{
+    args::FromBtfArgument,
+    bindings::seq_file,
+    cty::c_long,
+    helpers::{bpf_seq_printf, bpf_seq_write},
+    BpfContext,
+};
+
+// Must match struct bpf_iter_meta in the kernel.
+#[repr(C)]
+struct IterMeta {
+    seq: *mut seq_file,
+    session_id: u64,
+    seq_num: u64,
+}
+
+pub struct IterContext {
+    ctx: *mut c_void,
+}
+
+impl IterContext {
+    pub fn new(ctx: *mut c_void) -> IterContext {
+        IterContext { ctx }
+    }
+
+    fn meta(&self) -> &IterMeta {
+        // The first field of every iterator context is the meta data of the
+        // iteration.
+        unsafe { &**(self.ctx as *const *const IterMeta) }
+    }
+
+    /// Returns the `n`th object of the iteration, starting from 0, e.g. the
+    /// `struct task_struct` of `task` iterators.
+    ///
+    /// The program runs once more after the last object with a null pointer,
+    /// which can be used to write a summary of the iteration.
+    ///
+    /// SAFETY: This function is deeply unsafe, as we are reading raw pointers into kernel memory.
+    /// In particular, the value of `n` must not exceed the number of objects of the iterator
+    /// type. Luckily, the BPF verifier will catch this for us.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #![allow(non_camel_case_types)]
+    /// # #![allow(dead_code)]
+    /// # use aya_bpf::{cty::c_int, programs::IterContext};
+    /// # type pid_t = c_int;
+    /// # struct task_struct {
+    /// #     pid: pid_t,
+    /// # }
+    /// unsafe fn try_dump_task(ctx: IterContext) -> Result<i32, i32> {
+    ///     let task: *const task_struct = ctx.arg(0);
+    ///     if task.is_null() {
+    ///         return Ok(0);
+    ///     }
+    ///     ctx.write(&(*task).pid.to_ne_bytes()).map_err(|_| 1)?;
+    ///
+    ///     Ok(0)
+    /// }
+    /// ```
+    pub unsafe fn arg<T: FromBtfArgument>(&self, n: usize) -> T {
+        T::from_argument(self.ctx as *const _, n + 1)
+    }
+
+    /// Returns the number of objects the program has been run on since the
+    /// iterator file was opened, starting from 0.
+    pub fn seq_num(&self) -> u64 {
+        self.meta().seq_num
+    }
+
+    /// Returns the output of the iterator, to be passed to helpers such as
+    /// `bpf_seq_printf_btf`.
+    pub fn seq_file(&self) -> *mut seq_file {
+        self.meta().seq
+    }
+
+    /// Writes `data` to the output of the iterator, read by userspace.
+    pub fn write(&self, data: &[u8]) -> Result<(), c_long> {
+        let ret = unsafe {
+            bpf_seq_write(
+                self.seq_file(),
+                data.as_ptr() as *const c_void,
+                data.len() as u32,
+            )
+        };
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(ret)
+        }
+    }
+
+    /// Writes `fmt`, a NUL terminated format string, formatted with `args` to
+    /// the output of the iterator, like `seq_printf` in the kernel.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use aya_bpf::programs::IterContext;
+    /// fn write_seq_num(ctx: &IterContext) {
+    ///     let _ = ctx.printf(b"object %llu\n\0", &[ctx.seq_num()]);
+    /// }
+    /// ```
+    pub fn printf(&self, fmt: &[u8], args: &[u64]) -> Result<(), c_long> {
+        let ret = unsafe {
+            bpf_seq_printf(
+                self.seq_file(),
+                fmt.as_ptr() as *const _,
+                fmt.len() as u32,
+                args.as_ptr() as *const c_void,
+                mem::size_of_val(args) as u32,
+            )
+        };
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(ret)
+        }
+    }
+}
+
+impl BpfContext for IterContext {
+    fn as_ptr(&self) -> *mut c_void {
+        self.ctx
+    }
+}
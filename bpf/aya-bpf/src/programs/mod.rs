@@ -1,6 +1,7 @@
 pub mod device;
 pub mod fentry;
 pub mod fexit;
+pub mod iter;
 pub mod lsm;
 pub mod perf_event;
 pub mod probe;
@@ -16,11 +17,13 @@ pub mod sysctl;
 pub mod tc;
 pub mod tp_btf;
 pub mod tracepoint;
+pub mod usdt;
 pub mod xdp;
 
 pub use device::DeviceContext;
 pub use fentry::FEntryContext;
 pub use fexit::FExitContext;
+pub use iter::IterContext;
 pub use lsm::LsmContext;
 pub use perf_event::PerfEventContext;
 pub use probe::ProbeContext;
@@ -36,4 +39,5 @@ pub use sysctl::SysctlContext;
 pub use tc::TcContext;
 pub use tp_btf::BtfTracePointContext;
 pub use tracepoint::TracePointContext;
+pub use usdt::UsdtContext;
 pub use xdp::XdpContext;
@@ -1,6 +1,9 @@
 use core::ffi::c_void;
 
-use crate::{bindings::bpf_sockopt, BpfContext};
+use crate::{
+    bindings::{bpf_sock, bpf_sockopt},
+    BpfContext,
+};
 
 pub struct SockoptContext {
     pub sockopt: *mut bpf_sockopt,
@@ -10,6 +13,54 @@ impl SockoptContext {
     pub fn new(sockopt: *mut bpf_sockopt) -> SockoptContext {
         SockoptContext { sockopt }
     }
+
+    pub fn sk(&self) -> *mut bpf_sock {
+        unsafe { (*self.sockopt).__bindgen_anon_1.sk }
+    }
+
+    pub fn level(&self) -> i32 {
+        unsafe { (*self.sockopt).level }
+    }
+
+    pub fn set_level(&mut self, level: i32) {
+        unsafe { (*self.sockopt).level = level }
+    }
+
+    pub fn optname(&self) -> i32 {
+        unsafe { (*self.sockopt).optname }
+    }
+
+    pub fn set_optname(&mut self, optname: i32) {
+        unsafe { (*self.sockopt).optname = optname }
+    }
+
+    pub fn optlen(&self) -> i32 {
+        unsafe { (*self.sockopt).optlen }
+    }
+
+    pub fn set_optlen(&mut self, optlen: i32) {
+        unsafe { (*self.sockopt).optlen = optlen }
+    }
+
+    /// Returns the value the `getsockopt` syscall returned. Always zero for
+    /// `setsockopt` programs.
+    pub fn retval(&self) -> i32 {
+        unsafe { (*self.sockopt).retval }
+    }
+
+    pub fn set_retval(&mut self, retval: i32) {
+        unsafe { (*self.sockopt).retval = retval }
+    }
+
+    /// Returns the start of the option value buffer.
+    pub fn optval(&self) -> usize {
+        unsafe { (*self.sockopt).__bindgen_anon_2.optval as usize }
+    }
+
+    /// Returns the end of the option value buffer.
+    pub fn optval_end(&self) -> usize {
+        unsafe { (*self.sockopt).__bindgen_anon_3.optval_end as usize }
+    }
 }
 
 impl BpfContext for SockoptContext {
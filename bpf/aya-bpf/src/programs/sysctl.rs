@@ -1,6 +1,14 @@
 use core::ffi::c_void;
 
-use crate::{bindings::bpf_sysctl, BpfContext};
+use aya_bpf_bindings::helpers::{
+    bpf_sysctl_get_current_value, bpf_sysctl_get_name, bpf_sysctl_get_new_value,
+    bpf_sysctl_set_new_value,
+};
+
+use crate::{
+    bindings::{bpf_sysctl, BPF_F_SYSCTL_BASE_NAME},
+    BpfContext,
+};
 
 pub struct SysctlContext {
     pub sysctl: *mut bpf_sysctl,
@@ -10,6 +18,93 @@ impl SysctlContext {
     pub fn new(sysctl: *mut bpf_sysctl) -> SysctlContext {
         SysctlContext { sysctl }
     }
+
+    /// Returns `true` if the sysctl is being written, `false` if it's being read.
+    pub fn is_write(&self) -> bool {
+        unsafe { (*self.sysctl).write != 0 }
+    }
+
+    pub fn file_pos(&self) -> u32 {
+        unsafe { (*self.sysctl).file_pos }
+    }
+
+    pub fn set_file_pos(&mut self, pos: u32) {
+        unsafe { (*self.sysctl).file_pos = pos }
+    }
+
+    /// Copies the full name of the sysctl, e.g. `net/ipv4/tcp_mem`, into `buf`.
+    ///
+    /// On success returns the length of the name, not counting the trailing
+    /// NUL byte.
+    pub fn name(&self, buf: &mut [u8]) -> Result<usize, i64> {
+        self.read_name(buf, 0)
+    }
+
+    /// Copies the base name of the sysctl, e.g. `tcp_mem`, into `buf`.
+    ///
+    /// On success returns the length of the name, not counting the trailing
+    /// NUL byte.
+    pub fn base_name(&self, buf: &mut [u8]) -> Result<usize, i64> {
+        self.read_name(buf, BPF_F_SYSCTL_BASE_NAME as u64)
+    }
+
+    fn read_name(&self, buf: &mut [u8], flags: u64) -> Result<usize, i64> {
+        let ret = unsafe {
+            bpf_sysctl_get_name(
+                self.sysctl,
+                buf.as_mut_ptr() as *mut _,
+                buf.len() as _,
+                flags,
+            )
+        };
+        if ret < 0 {
+            Err(ret)
+        } else {
+            Ok(ret as usize)
+        }
+    }
+
+    /// Copies the current value of the sysctl, as a string, into `buf`.
+    ///
+    /// On success returns the length of the value, not counting the trailing
+    /// NUL byte.
+    pub fn current_value(&self, buf: &mut [u8]) -> Result<usize, i64> {
+        let ret = unsafe {
+            bpf_sysctl_get_current_value(self.sysctl, buf.as_mut_ptr() as *mut _, buf.len() as _)
+        };
+        if ret < 0 {
+            Err(ret)
+        } else {
+            Ok(ret as usize)
+        }
+    }
+
+    /// Copies the value being written to the sysctl, as a string, into `buf`.
+    ///
+    /// On success returns the length of the value, not counting the trailing
+    /// NUL byte. Fails with `-EINVAL` if the sysctl is being read.
+    pub fn new_value(&self, buf: &mut [u8]) -> Result<usize, i64> {
+        let ret = unsafe {
+            bpf_sysctl_get_new_value(self.sysctl, buf.as_mut_ptr() as *mut _, buf.len() as _)
+        };
+        if ret < 0 {
+            Err(ret)
+        } else {
+            Ok(ret as usize)
+        }
+    }
+
+    /// Overrides the value being written to the sysctl.
+    pub fn set_new_value(&mut self, value: &[u8]) -> Result<(), i64> {
+        let ret = unsafe {
+            bpf_sysctl_set_new_value(self.sysctl, value.as_ptr() as *const _, value.len() as _)
+        };
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(ret)
+        }
+    }
 }
 
 impl BpfContext for SysctlContext {
@@ -0,0 +1,127 @@
+use core::ffi::c_void;
+
+use crate::{
+    helpers::{bpf_get_attach_cookie, bpf_probe_read_kernel, bpf_probe_read_user},
+    maps::Array,
+    BpfContext,
+};
+
+// aarch64 uses user_pt_regs instead of pt_regs
+#[cfg(not(bpf_target_arch = "aarch64"))]
+use crate::bindings::pt_regs;
+#[cfg(bpf_target_arch = "aarch64")]
+use crate::bindings::user_pt_regs as pt_regs;
+
+// Must match aya::programs::usdt
+const USDT_MAX_ARGS: usize = 12;
+const USDT_MAX_SPECS: u32 = 256;
+const USDT_ARG_CONST: u32 = 0;
+const USDT_ARG_REG: u32 = 1;
+const USDT_ARG_REG_DEREF: u32 = 2;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct UsdtArgSpec {
+    val_off: u64,
+    arg_type: u32,
+    reg_off: i16,
+    signed: bool,
+    bitshift: u8,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct UsdtSpec {
+    args: [UsdtArgSpec; USDT_MAX_ARGS],
+    arg_count: u16,
+}
+
+// Filled by user space when the program is attached, the attach cookie of
+// each probe location is the index of its spec.
+#[link_section = "maps"]
+#[export_name = "AYA_USDT_SPECS"]
+static AYA_USDT_SPECS: Array<UsdtSpec> = Array::with_max_entries(USDT_MAX_SPECS, 0);
+
+pub struct UsdtContext {
+    pub regs: *mut pt_regs,
+}
+
+impl UsdtContext {
+    pub fn new(ctx: *mut c_void) -> UsdtContext {
+        UsdtContext {
+            regs: ctx as *mut pt_regs,
+        }
+    }
+
+    /// Returns the number of arguments of the probe.
+    ///
+    /// Returns `None` if the arguments of the probe were not described to the
+    /// program when it was attached.
+    pub fn arg_count(&self) -> Option<usize> {
+        self.spec().map(|spec| spec.arg_count as usize)
+    }
+
+    /// Returns the `n`th argument of the probe, starting from 0.
+    ///
+    /// The value is sign or zero extended to 64 bits, depending on the
+    /// argument spec of the probe.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #![allow(dead_code)]
+    /// # use aya_bpf::programs::UsdtContext;
+    /// fn try_function_entry(ctx: UsdtContext) -> Result<u32, u32> {
+    ///     let lineno = ctx.arg(2).ok_or(1u32)? as i32;
+    ///
+    ///     // Do something with lineno
+    ///
+    ///     Ok(0)
+    /// }
+    /// ```
+    pub fn arg(&self, n: usize) -> Option<i64> {
+        let spec = self.spec()?;
+        if n >= spec.arg_count as usize {
+            return None;
+        }
+        let arg = spec.args.get(n)?;
+        let reg = || unsafe {
+            bpf_probe_read_kernel(
+                (self.regs as *const u8).offset(arg.reg_off as isize) as *const u64
+            )
+            .ok()
+        };
+        let mut val = match arg.arg_type {
+            USDT_ARG_CONST => arg.val_off,
+            USDT_ARG_REG => reg()?,
+            USDT_ARG_REG_DEREF => {
+                let addr = reg()?.wrapping_add(arg.val_off);
+                let val: u64 = unsafe { bpf_probe_read_user(addr as *const u64).ok()? };
+                // the value is in the most significant bytes read on big endian
+                if cfg!(target_endian = "big") {
+                    val >> arg.bitshift
+                } else {
+                    val
+                }
+            }
+            _ => return None,
+        };
+        val <<= arg.bitshift;
+        Some(if arg.signed {
+            (val as i64) >> arg.bitshift
+        } else {
+            (val >> arg.bitshift) as i64
+        })
+    }
+
+    fn spec(&self) -> Option<&UsdtSpec> {
+        let id = unsafe { bpf_get_attach_cookie(self.as_ptr()) } as u32;
+        AYA_USDT_SPECS.get(id)
+    }
+}
+
+impl BpfContext for UsdtContext {
+    fn as_ptr(&self) -> *mut c_void {
+        self.regs as *mut c_void
+    }
+}
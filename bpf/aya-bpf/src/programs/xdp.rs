@@ -1,4 +1,7 @@
-use core::ffi::c_void;
+use core::{cmp, ffi::c_void};
+
+use aya_bpf_bindings::helpers::{bpf_xdp_get_buff_len, bpf_xdp_load_bytes, bpf_xdp_store_bytes};
+use aya_bpf_cty::c_long;
 
 use crate::{bindings::xdp_md, BpfContext};
 
@@ -32,6 +35,71 @@ impl XdpContext {
     pub fn metadata_end(&self) -> usize {
         self.data()
     }
+
+    /// Returns the total length of the packet, including the fragments of
+    /// multi-buffer packets which aren't accessible between `data()` and
+    /// `data_end()`.
+    #[allow(clippy::len_without_is_empty)]
+    #[inline]
+    pub fn len(&self) -> usize {
+        unsafe { bpf_xdp_get_buff_len(self.ctx) as usize }
+    }
+
+    /// Reads some bytes from the packet into the specified buffer, returning
+    /// how many bytes were read.
+    ///
+    /// Unlike direct packet access, this works across the fragments of
+    /// multi-buffer packets, which are received by programs declared with
+    /// `#[xdp(frags = "true")]`. Starts reading at `offset` and reads at
+    /// most `dst.len()` or `self.len() - offset` bytes, depending on which
+    /// one is smaller.
+    #[inline(always)]
+    pub fn load_bytes(&self, offset: usize, dst: &mut [u8]) -> Result<usize, c_long> {
+        if offset >= self.len() {
+            return Err(-1);
+        }
+        let len = cmp::min(self.len() as isize - offset as isize, dst.len() as isize);
+        // The verifier rejects the program if it can't see that `len > 0`.
+        if len <= 0 {
+            return Err(-1);
+        }
+        // This is only needed to ensure the verifier can see the upper bound.
+        if len > dst.len() as isize {
+            return Err(-1);
+        }
+        let ret = unsafe {
+            bpf_xdp_load_bytes(
+                self.ctx,
+                offset as u32,
+                dst.as_mut_ptr() as *mut _,
+                len as u32,
+            )
+        };
+        if ret == 0 {
+            Ok(len as usize)
+        } else {
+            Err(ret)
+        }
+    }
+
+    /// Writes the bytes of `src` into the packet at `offset`, across the
+    /// fragments of multi-buffer packets.
+    #[inline(always)]
+    pub fn store_bytes(&mut self, offset: usize, src: &[u8]) -> Result<(), c_long> {
+        let ret = unsafe {
+            bpf_xdp_store_bytes(
+                self.ctx,
+                offset as u32,
+                src.as_ptr() as *mut _,
+                src.len() as u32,
+            )
+        };
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(ret)
+        }
+    }
 }
 
 impl BpfContext for XdpContext {
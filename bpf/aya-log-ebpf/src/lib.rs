@@ -1,10 +1,150 @@
+//! A logging framework for eBPF programs.
+//!
+//! This is the eBPF side of the [Aya] logging framework. For the user space
+//! side, see the `aya-log` crate.
+//!
+//! # Display hints
+//!
+//! Format parameters accept an optional display hint selecting how the argument
+//! is formatted by userspace:
+//!
+//! * `{}` - the default representation of the argument.
+//! * `{:x}`, `{:X}` - lower/upper case hexadecimal integers or byte slices.
+//! * `{:i}` - an IP address: IPv4 for 32 bit arguments like `u32`, IPv6 for
+//!   128 bit arrays like `[u8; 16]`, `[u16; 8]` or `[u32; 4]`.
+//! * `{:ipv4}`, `{:ipv6}` - an IPv4 or IPv6 address.
+//! * `{:mac}`, `{:MAC}` - a lower/upper case MAC address from `[u8; 6]`.
+//!
+//! Fixed-length arrays of `u8`, `u16`, `u32` and `u64` of any length are
+//! supported. They are printed as a list of their elements, except for byte
+//! arrays formatted with `{:x}` or `{:X}`, which are printed like byte slices.
+//!
+//! # Strings in kernel or user memory
+//!
+//! Strings which the program can only access through `bpf_probe_read_*`, like
+//! file names and paths captured in kprobes, can be logged with [`kernel_str`]
+//! and [`user_str`]. They're read straight into the log record, without an
+//! intermediate map:
+//!
+//! ```ignore
+//! use aya_log_ebpf::{info, kernel_str};
+//!
+//! let filename: *const u8 = ctx.arg(1).ok_or(1)?;
+//! info!(&ctx, "opening {}", kernel_str(filename));
+//! ```
+//!
+//! # Enums
+//!
+//! Fieldless enums with an integer `#[repr]` can be logged by deriving
+//! [`LogEnum`]. Only the discriminant is sent, userspace formats it with the
+//! name of the variant:
+//!
+//! ```ignore
+//! use aya_log_ebpf::{info, LogEnum};
+//!
+//! #[derive(LogEnum)]
+//! #[repr(u8)]
+//! pub enum TcpState {
+//!     Established = 1,
+//!     SynSent,
+//! }
+//!
+//! info!(&ctx, "state: {}", TcpState::Established);
+//! ```
+//!
+//! The enum has to be registered in userspace with
+//! `aya_log::BpfLogger::register_enum::<TcpState>()`, so it is usually defined
+//! in a crate shared by the eBPF program and userspace, which must depend on
+//! `aya-log-common`. Unregistered enums are formatted as their discriminant.
+//!
+//! # Features
+//!
+//! * `disable` - turns every logging macro into a no-op and removes the maps
+//!   used to transfer log records, so that production builds can keep their
+//!   log statements at no cost. The format strings are still checked at compile
+//!   time.
+//! * `printk` - formats all log messages in the eBPF program and writes them to the
+//!   kernel trace pipe (`/sys/kernel/debug/tracing/trace_pipe`) with
+//!   `bpf_trace_printk`, which is useful while the userspace reader isn't
+//!   running yet. Arguments that can't be rendered in eBPF, like floats or
+//!   display hints not supported by the argument type, are printed as `?`, and
+//!   IPv6 addresses aren't compressed. Older kernels truncate messages to 64
+//!   bytes.
+//! * `interned-formats` - sends only an identifier of the log statement and the
+//!   raw arguments instead of the target, module, file, line and literal parts
+//!   of the format string, which shrinks the records and the work done by the
+//!   program. The statements are stored in the `aya_log_fmt` section of the
+//!   object file, which userspace has to load with
+//!   `aya_log::BpfLogger::register_formats`. Statements with a target which
+//!   isn't a string literal are sent in full.
+//! * `checksum` - appends a checksum to every record, so that userspace reports
+//!   records corrupted or misread from the perf buffers as such instead of
+//!   logging garbled messages. Checksumming walks the whole record in the eBPF
+//!   program, which requires bounded loop support (Linux 5.3).
+//! * `pid` - sends the id of the current process with every record. Userspace
+//!   attaches it to the records as the `pid` key-value, and can resolve the
+//!   name of the process, see `aya_log::BpfLoggerBuilder::process_names`.
+//! * `cgroup` - sends the id of the cgroup v2 of the current process with every
+//!   record (Linux 4.18). Userspace attaches it to the records as the
+//!   `cgroup_id` key-value, and can resolve the container and Kubernetes pod it
+//!   belongs to, see `aya_log::BpfLoggerBuilder::cgroups`.
+//! * `netns` - sends the cookie of the network namespace of the program context
+//!   with every record, with `bpf_get_netns_cookie`. Userspace attaches it to
+//!   the records as the `netns_cookie` key-value, and can resolve the name of
+//!   the namespace, see `aya_log::BpfLoggerBuilder::netns_names`. The helper is
+//!   only available to socket related programs, like `cgroup/sock_addr`,
+//!   `sock_ops` and `sk_msg` programs, the verifier rejects the others.
+//! * `timestamp` - sends the time at which the record was logged, read from
+//!   `CLOCK_MONOTONIC` with `bpf_ktime_get_ns`, or from `CLOCK_BOOTTIME` with
+//!   `bpf_ktime_get_boot_ns` when selected with
+//!   `aya_log::BpfLoggerBuilder::clock`. The clock is read from the
+//!   `AYA_LOG_CLOCK` map, and the program calls both helpers, so it requires
+//!   Linux 5.8. Userspace converts the timestamps to wall clock time and
+//!   attaches them to the records as the `timestamp_ns` key-value.
+//! * `program-id` - sends an identifier of the program instance with every
+//!   record, which userspace sets when loading the object with
+//!   `aya_log::BpfLogger::set_program_id`, for example to tell apart the
+//!   copies of an object attached to several interfaces. Userspace attaches it
+//!   to the records as the `program_id` key-value. It defaults to 0.
+//! * `sequence` - sends the number of records logged before each record on
+//!   the same CPU, counted in the `AYA_LOG_SEQUENCE` map. Userspace attaches
+//!   it to the records as the `sequence` key-value, and logs a warning with the
+//!   number of records missing before a record whenever the sequence skips,
+//!   whether the records were dropped in the kernel or lost in the perf
+//!   buffers. Records logged by programs preempted while logging, like
+//!   sleepable programs, may be numbered out of order.
+//!
+//! # Logging without a context
+//!
+//! Sending records to userspace requires the program context, which is passed
+//! as the first argument of the macros. Some program types, like `struct_ops`
+//! programs or iterators, don't have a convenient context to pass. Those
+//! programs can pass `printk` instead of the context:
+//!
+//! ```ignore
+//! use aya_log_ebpf::{info, log, Level};
+//!
+//! info!(printk, "no context available: {}", 42);
+//! log!(printk, target: "my_target", Level::Warn, "with a target");
+//! ```
+//!
+//! These records aren't sent to userspace: they are formatted in the eBPF
+//! program and written to the kernel trace pipe, as with the `printk` feature.
+//!
+//! [Aya]: https://docs.rs/aya
 #![no_std]
+use aya_bpf::helpers::{bpf_get_current_cgroup_id, bpf_get_current_pid_tgid, bpf_get_netns_cookie};
+#[cfg(all(feature = "timestamp", not(feature = "disable")))]
 use aya_bpf::{
-    macros::map,
-    maps::{PerCpuArray, PerfEventByteArray},
+    helpers::{bpf_ktime_get_boot_ns, bpf_ktime_get_ns},
+    maps::Array,
 };
-pub use aya_log_common::{write_record_header, Level, WriteToBuf, LOG_BUF_CAPACITY};
-pub use aya_log_ebpf_macros::{debug, error, info, log, trace, warn};
+#[cfg(not(feature = "disable"))]
+use aya_bpf::{macros::map, maps::PerCpuArray};
+#[cfg(not(any(feature = "disable", feature = "printk")))]
+use aya_bpf::{maps::PerfEventByteArray, BpfContext};
+pub use aya_log_common::{write_record_header, Clock, Level, WriteToBuf, LOG_BUF_CAPACITY};
+pub use aya_log_ebpf_macros::{debug, error, info, log, trace, warn, LogEnum};
 
 #[doc(hidden)]
 #[repr(C)]
@@ -12,16 +152,127 @@ pub struct LogBuf {
     pub buf: [u8; LOG_BUF_CAPACITY],
 }
 
+#[cfg(not(feature = "disable"))]
 #[doc(hidden)]
 #[map]
 pub static mut AYA_LOG_BUF: PerCpuArray<LogBuf> = PerCpuArray::with_max_entries(1, 0);
 
+#[cfg(not(any(feature = "disable", feature = "printk")))]
 #[doc(hidden)]
 #[map]
 pub static mut AYA_LOGS: PerfEventByteArray = PerfEventByteArray::new(0);
 
+/// Number of records, per CPU, which couldn't be submitted to [`AYA_LOGS`],
+/// for example because the perf buffer was full. Reported by `aya-log`.
+#[cfg(not(any(feature = "disable", feature = "printk")))]
+#[doc(hidden)]
+#[map]
+pub static mut AYA_LOG_DROPS: PerCpuArray<u64> = PerCpuArray::with_max_entries(1, 0);
+
+#[cfg(not(any(feature = "disable", feature = "printk")))]
+#[doc(hidden)]
+#[inline(always)]
+pub fn output_record<C: BpfContext>(ctx: &C, record: &[u8]) {
+    if unsafe { AYA_LOGS.try_output(ctx, record, 0) }.is_err() {
+        if let Some(drops) = unsafe { AYA_LOG_DROPS.get_ptr_mut(0) } {
+            unsafe { *drops += 1 };
+        }
+    }
+}
+
+/// Number of records logged on each CPU, sent with the records by the
+/// `sequence` feature.
+#[cfg(all(feature = "sequence", not(feature = "disable")))]
+#[doc(hidden)]
+#[map]
+pub static mut AYA_LOG_SEQUENCE: PerCpuArray<u64> = PerCpuArray::with_max_entries(1, 0);
+
+/// Clock the timestamps sent by the `timestamp` feature are read from, set by
+/// `aya_log::BpfLoggerBuilder::clock`. Defaults to [`Clock::Monotonic`].
+#[cfg(all(feature = "timestamp", not(feature = "disable")))]
+#[doc(hidden)]
+#[map]
+pub static mut AYA_LOG_CLOCK: Array<u8> = Array::with_max_entries(1, 0);
+
+/// Identifier of the program instance sent with the records by the
+/// `program-id` feature, set by `aya_log::BpfLogger::set_program_id`.
+#[cfg(feature = "program-id")]
+#[doc(hidden)]
+#[no_mangle]
+pub static AYA_LOG_PROGRAM_ID: u32 = 0;
+
+/// Appends the fields enabled by the features of the crate to the header of
+/// `header_len` bytes written to `buf`, returning its final length.
+#[doc(hidden)]
+#[inline(always)]
+pub fn write_record_context<C: aya_bpf::BpfContext>(
+    ctx: &C,
+    buf: &mut [u8],
+    header_len: usize,
+) -> usize {
+    let mut len = header_len;
+    if cfg!(feature = "pid") {
+        let pid = (bpf_get_current_pid_tgid() >> 32) as u32;
+        len = aya_log_common::write_record_pid(buf, len, pid);
+    }
+    if cfg!(feature = "cgroup") {
+        let cgroup_id = unsafe { bpf_get_current_cgroup_id() };
+        len = aya_log_common::write_record_cgroup_id(buf, len, cgroup_id);
+    }
+    if cfg!(feature = "netns") {
+        let cookie = unsafe { bpf_get_netns_cookie(ctx.as_ptr()) };
+        len = aya_log_common::write_record_netns_cookie(buf, len, cookie);
+    }
+    #[cfg(all(feature = "timestamp", not(feature = "disable")))]
+    {
+        let (clock, ns) = match unsafe { AYA_LOG_CLOCK.get(0) } {
+            Some(&clock) if clock == u8::from(Clock::Boottime) => {
+                (Clock::Boottime, unsafe { bpf_ktime_get_boot_ns() })
+            }
+            _ => (Clock::Monotonic, unsafe { bpf_ktime_get_ns() }),
+        };
+        len = aya_log_common::write_record_timestamp(buf, len, clock, ns);
+    }
+    #[cfg(feature = "program-id")]
+    {
+        // Read at runtime so that the value set by userspace isn't
+        // constant-folded.
+        let id = unsafe { core::ptr::read_volatile(&AYA_LOG_PROGRAM_ID) };
+        len = aya_log_common::write_record_program_id(buf, len, id);
+    }
+    #[cfg(all(feature = "sequence", not(feature = "disable")))]
+    if let Some(sequence) = unsafe { AYA_LOG_SEQUENCE.get_ptr_mut(0) } {
+        let sequence = unsafe { &mut *sequence };
+        len = aya_log_common::write_record_sequence(buf, len, *sequence);
+        *sequence += 1;
+    }
+    len
+}
+
+/// Completes the record of `record_len` bytes written to `buf` before it is
+/// sent, returning its final length.
+#[doc(hidden)]
+#[inline(always)]
+pub fn finish_record(buf: &mut [u8], record_len: usize) -> usize {
+    if cfg!(feature = "checksum") {
+        aya_log_common::write_record_checksum(buf, record_len)
+    } else {
+        record_len
+    }
+}
+
+mod printk;
+mod probe_str;
+
+pub use probe_str::{kernel_str, user_str, KernelStr, UserStr};
+
 #[doc(hidden)]
 pub mod macro_support {
-    pub use aya_log_common::{DisplayHint, Level, LOG_BUF_CAPACITY};
+    pub use crate::{finish_record, printk::output_printk, write_record_context};
+    pub use aya_log_common::{
+        format_entry, format_entry_len, format_id, set_record_flags, set_record_num_args,
+        write_interned_record_header, DisplayHint, Level, PrintkArg, PrintkWriter,
+        FORMAT_ENTRY_FIELDS, LOG_BUF_CAPACITY, RECORD_FLAG_TRUNCATED,
+    };
     pub use aya_log_ebpf_macros::log;
 }
@@ -0,0 +1,12 @@
+use aya_bpf::helpers::bpf_printk;
+use aya_log_common::PrintkWriter;
+
+/// Writes the message to the kernel trace pipe.
+#[doc(hidden)]
+#[inline(always)]
+pub fn output_printk(w: PrintkWriter) {
+    let msg = w.finish();
+    unsafe {
+        bpf_printk!(b"%s", msg.as_ptr());
+    }
+}
@@ -0,0 +1,97 @@
+//! String arguments read from kernel or user memory while the record is
+//! written, so that they don't have to be copied to a map first.
+use aya_bpf::{
+    cty::{c_long, c_void},
+    helpers::gen::{bpf_probe_read_kernel_str, bpf_probe_read_user_str},
+};
+use aya_log_common::{write_str_with, DisplayHint, PrintkArg, PrintkWriter, WriteToBuf};
+
+/// A NUL terminated string in kernel memory. See [`kernel_str`].
+#[derive(Copy, Clone)]
+pub struct KernelStr(*const u8);
+
+/// A NUL terminated string in user memory. See [`user_str`].
+#[derive(Copy, Clone)]
+pub struct UserStr(*const u8);
+
+/// Logs the NUL terminated string at `src` in kernel memory, for example a file
+/// name passed to a kprobe.
+///
+/// The string is read with `bpf_probe_read_kernel_str` directly into the log
+/// record, and is truncated to the space left in it. Strings which can't be
+/// read are logged as empty strings.
+#[inline(always)]
+pub fn kernel_str(src: *const u8) -> KernelStr {
+    KernelStr(src)
+}
+
+/// Logs the NUL terminated string at `src` in user memory, for example a path
+/// passed to a system call.
+///
+/// The string is read with `bpf_probe_read_user_str` directly into the log
+/// record, and is truncated to the space left in it. Strings which can't be
+/// read are logged as empty strings.
+#[inline(always)]
+pub fn user_str(src: *const u8) -> UserStr {
+    UserStr(src)
+}
+
+/// Turns the value returned by the `bpf_probe_read_*_str` helpers into the
+/// length of the string, which excludes the NUL byte, and whether it was
+/// truncated.
+#[inline(always)]
+fn read_len(ret: c_long, dest: &[u8]) -> (usize, bool) {
+    if ret <= 0 {
+        return (0, false);
+    }
+    let len = ret as usize;
+    if len > dest.len() {
+        // Can't happen, tells the verifier that `len` is bounded.
+        return (0, false);
+    }
+    // A string filling all of `dest` is assumed to have been cut short.
+    (len - 1, len == dest.len())
+}
+
+macro_rules! impl_probe_str {
+    ($type:ident, $read:ident) => {
+        impl $type {
+            #[inline(always)]
+            fn read(self, dest: &mut [u8]) -> (usize, bool) {
+                if dest.is_empty() {
+                    return (0, true);
+                }
+                let ret = unsafe {
+                    $read(
+                        dest.as_mut_ptr() as *mut c_void,
+                        dest.len() as u32,
+                        self.0 as *const c_void,
+                    )
+                };
+                read_len(ret, dest)
+            }
+        }
+
+        impl WriteToBuf for $type {
+            #[inline(always)]
+            fn write(self, buf: &mut [u8]) -> Result<usize, ()> {
+                write_str_with(buf, |dest| self.read(dest)).map(|(len, _)| len)
+            }
+
+            #[inline(always)]
+            fn write_or_truncate(self, buf: &mut [u8]) -> Result<(usize, bool), ()> {
+                write_str_with(buf, |dest| self.read(dest))
+            }
+        }
+
+        impl PrintkArg for $type {
+            #[inline(always)]
+            fn write_printk(self, _hint: DisplayHint, w: &mut PrintkWriter) {
+                w.write_with(|dest| self.read(dest).0)
+            }
+        }
+    };
+}
+
+impl_probe_str!(KernelStr, bpf_probe_read_kernel_str);
+impl_probe_str!(UserStr, bpf_probe_read_user_str);
@@ -0,0 +1,28 @@
+#![no_std]
+#![no_main]
+
+use aya_bpf::{bindings::xdp_action, macros::xdp, programs::XdpContext};
+use aya_log_ebpf::{debug, error, info, trace, warn};
+
+// Logs from an XDP program so that userspace can run it with BPF_PROG_TEST_RUN
+// instead of attaching it and triggering it.
+#[xdp(name = "log_test_run")]
+pub fn log_test_run(ctx: XdpContext) -> u32 {
+    let len = ctx.data_end() - ctx.data();
+    info!(&ctx, "packet of {} bytes", len);
+    debug!(&ctx, "{} {} {} {}", 1u8, -2i16, 3u64, "four");
+    let ipv4 = 0x7f000001u32; // 127.0.0.1
+    warn!(
+        &ctx,
+        "ipv4: {:i}, hex: {:x}, HEX: {:X}", ipv4, 0xbeefu32, 0xbeefu32
+    );
+    let mac = [0u8, 0x11, 0x22, 0x33, 0x44, 0x55];
+    trace!(&ctx, "mac: {:mac}", mac);
+    error!(&ctx, "bytes: {:x}", [0xde, 0xad, 0xbe, 0xef].as_slice());
+    xdp_action::XDP_PASS
+}
+
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    unsafe { core::hint::unreachable_unchecked() }
+}
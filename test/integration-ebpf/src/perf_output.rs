@@ -0,0 +1,37 @@
+#![no_std]
+#![no_main]
+
+use aya_bpf::{
+    bindings::xdp_action,
+    macros::{map, xdp},
+    maps::{Array, PerfEventArray},
+    programs::XdpContext,
+};
+
+#[map]
+static EVENTS: PerfEventArray<u32> = PerfEventArray::with_max_entries(1, 0);
+
+#[map]
+static RESULTS: Array<i64> = Array::with_max_entries(2, 0);
+
+// Nothing opens a perf buffer on EVENTS, so the first output fails with ENOENT
+// and the second one, past the end of the array, with E2BIG.
+#[xdp(name = "perf_output")]
+pub fn perf_output(ctx: XdpContext) -> u32 {
+    EVENTS.output_at_index(&ctx, 0, &1, 0);
+    for (index, cpu) in [(0, 0), (1, 4)] {
+        let ret = match EVENTS.try_output_at_index(&ctx, cpu, &1, 0) {
+            Ok(()) => 0,
+            Err(ret) => ret,
+        };
+        if let Some(result) = RESULTS.get_ptr_mut(index) {
+            unsafe { *result = ret };
+        }
+    }
+    xdp_action::XDP_PASS
+}
+
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    unsafe { core::hint::unreachable_unchecked() }
+}
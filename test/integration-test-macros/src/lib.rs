@@ -3,8 +3,25 @@ use proc_macro2::Span;
 use quote::quote;
 use syn::{parse_macro_input, Ident, ItemFn};
 
+/// Parses `#[integration_test(ignore)]`, for tests which only run when asked
+/// with `--ignored` or `--include-ignored`.
+fn parse_ignored(attr: TokenStream) -> syn::Result<bool> {
+    if attr.is_empty() {
+        return Ok(false);
+    }
+    let ident: Ident = syn::parse(attr)?;
+    if ident != "ignore" {
+        return Err(syn::Error::new(ident.span(), "expected `ignore`"));
+    }
+    Ok(true)
+}
+
 #[proc_macro_attribute]
-pub fn integration_test(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn integration_test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let ignored = match parse_ignored(attr) {
+        Ok(ignored) => ignored,
+        Err(e) => return e.to_compile_error().into(),
+    };
     let item = parse_macro_input!(item as ItemFn);
     let name = &item.sig.ident;
     let name_str = &item.sig.ident.to_string();
@@ -14,13 +31,18 @@ pub fn integration_test(_attr: TokenStream, item: TokenStream) -> TokenStream {
         inventory::submit!(crate::IntegrationTest {
             name: concat!(module_path!(), "::", #name_str),
             test_fn: #name,
+            ignored: #ignored,
         });
     };
     TokenStream::from(expanded)
 }
 
 #[proc_macro_attribute]
-pub fn tokio_integration_test(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn tokio_integration_test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let ignored = match parse_ignored(attr) {
+        Ok(ignored) => ignored,
+        Err(e) => return e.to_compile_error().into(),
+    };
     let item = parse_macro_input!(item as ItemFn);
     let name = &item.sig.ident;
     let name_str = &item.sig.ident.to_string();
@@ -40,6 +62,7 @@ pub fn tokio_integration_test(_attr: TokenStream, item: TokenStream) -> TokenStr
         inventory::submit!(crate::IntegrationTest {
             name: concat!(module_path!(), "::", #sync_name_str),
             test_fn: #sync_name,
+            ignored: #ignored,
         });
     };
     TokenStream::from(expanded)
@@ -15,6 +15,7 @@ fn main() {
                 (test.test_fn)();
                 Ok(())
             })
+            .with_ignored_flag(test.ignored)
         })
         .collect();
     libtest_mimic::run(&args, tests).exit();
@@ -1,6 +1,10 @@
 use std::sync::{Arc, LockResult, Mutex, MutexGuard};
 
-use aya::{include_bytes_aligned, programs::UProbe, Bpf};
+use aya::{
+    include_bytes_aligned,
+    programs::{UProbe, Xdp},
+    Bpf,
+};
 use aya_log::BpfLogger;
 use log::{Level, Log, Record};
 use tokio::time::{sleep, Duration};
@@ -104,12 +108,12 @@ async fn log() {
 
     // Call the function that the uprobe is attached to, so it starts logging.
     trigger_ebpf_program();
-    captured_logs.wait_expected_len(6).await;
+    captured_logs.wait_expected_len(7).await;
 
     let records = captured_logs
         .lock()
         .expect("Failed to acquire a lock for reading logs");
-    assert_eq!(records.len(), 6);
+    assert_eq!(records.len(), 7);
 
     assert_eq!(records[0].body, "Hello from eBPF!");
     assert_eq!(records[0].level, Level::Debug);
@@ -137,4 +141,63 @@ async fn log() {
     assert_eq!(records[5].body, "hex lc: deadbeef, hex uc: DEADBEEF");
     assert_eq!(records[5].level, Level::Debug);
     assert_eq!(records[5].target, "log");
+
+    assert_eq!(records[6].body, "1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16");
+    assert_eq!(records[6].level, Level::Info);
+    assert_eq!(records[6].target, "log");
+}
+
+/// Runs the program with `BPF_PROG_TEST_RUN` rather than attaching it, so that
+/// the records go through the same eBPF encoder and userspace decoder as in
+/// production without depending on any hook firing.
+///
+/// Run it with `--include-ignored`, as root on a kernel which can test run XDP
+/// programs.
+#[tokio_integration_test(ignore)]
+async fn log_test_run() {
+    assert_eq!(
+        unsafe { libc::geteuid() },
+        0,
+        "the test needs to run as root"
+    );
+    let bytes =
+        include_bytes_aligned!("../../../../target/bpfel-unknown-none/release/log_test_run");
+    let mut bpf = Bpf::load(bytes).unwrap();
+
+    let (logger, captured_logs) = TestingLogger::with_capacity(5);
+    let bpf_logger = BpfLogger::init_with_logger(&mut bpf, logger).unwrap();
+
+    let prog: &mut Xdp = bpf.program_mut("log_test_run").unwrap().try_into().unwrap();
+    prog.load().unwrap();
+    let output = bpf
+        .program("log_test_run")
+        .unwrap()
+        .test_run(&[0u8; 64], 1)
+        .unwrap();
+    // XDP_PASS
+    assert_eq!(output.return_value, 2);
+
+    captured_logs.wait_expected_len(5).await;
+    let records = captured_logs
+        .lock()
+        .expect("Failed to acquire a lock for reading logs");
+
+    let expected = [
+        (Level::Info, "packet of 64 bytes"),
+        (Level::Debug, "1 -2 3 four"),
+        (Level::Warn, "ipv4: 127.0.0.1, hex: beef, HEX: BEEF"),
+        (Level::Trace, "mac: 00:11:22:33:44:55"),
+        (Level::Error, "bytes: deadbeef"),
+    ];
+    assert_eq!(records.len(), expected.len());
+    for (record, (level, body)) in records.iter().zip(expected) {
+        assert_eq!(record.body, body);
+        assert_eq!(record.level, level);
+        assert_eq!(record.target, "log_test_run");
+    }
+
+    let stats = bpf_logger.stats();
+    assert_eq!(stats.records, expected.len() as u64);
+    assert_eq!(stats.decode_errors, 0);
+    assert_eq!(stats.lost_records, 0);
 }
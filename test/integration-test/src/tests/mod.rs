@@ -8,6 +8,7 @@ pub mod btf_relocations;
 pub mod elf;
 pub mod load;
 pub mod log;
+pub mod perf_output;
 pub mod rbpf;
 pub mod relocations;
 pub mod smoke;
@@ -18,6 +19,8 @@ pub use integration_test_macros::{integration_test, tokio_integration_test};
 pub struct IntegrationTest {
     pub name: &'static str,
     pub test_fn: fn(),
+    /// Set by `#[integration_test(ignore)]`.
+    pub ignored: bool,
 }
 
 pub(crate) fn kernel_version() -> anyhow::Result<(u8, u8, u8)> {
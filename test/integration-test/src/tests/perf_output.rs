@@ -0,0 +1,22 @@
+use aya::{include_bytes_aligned, maps::Array, programs::Xdp, Bpf};
+
+use super::integration_test;
+
+#[integration_test]
+fn try_output() {
+    let bytes = include_bytes_aligned!("../../../../target/bpfel-unknown-none/release/perf_output");
+    let mut bpf = Bpf::load(bytes).unwrap();
+    let prog: &mut Xdp = bpf.program_mut("perf_output").unwrap().try_into().unwrap();
+    prog.load().unwrap();
+    let output = bpf
+        .program("perf_output")
+        .unwrap()
+        .test_run(&[0u8; 64], 1)
+        .unwrap();
+    // XDP_PASS
+    assert_eq!(output.return_value, 2);
+
+    let results: Array<_, i64> = Array::try_from(bpf.map("RESULTS").unwrap()).unwrap();
+    assert_eq!(results.get(&0, 0).unwrap(), -libc::ENOENT as i64);
+    assert_eq!(results.get(&1, 0).unwrap(), -libc::E2BIG as i64);
+}
@@ -1,7 +1,10 @@
+use std::os::fd::AsFd;
+
 use aya::{
     include_bytes_aligned,
-    programs::{Extension, Xdp, XdpFlags},
-    Bpf, BpfLoader,
+    maps::{Array, MapError},
+    programs::{Extension, KProbe, ProgramError, Xdp, XdpError, XdpFlags},
+    Bpf, BpfError, BpfLoader,
 };
 use log::info;
 
@@ -12,10 +15,44 @@ fn xdp() {
     let bytes = include_bytes_aligned!("../../../../target/bpfel-unknown-none/release/pass");
     let mut bpf = Bpf::load(bytes).unwrap();
     let dispatcher: &mut Xdp = bpf.program_mut("pass").unwrap().try_into().unwrap();
+    // `pass` is declared with `frags = "true"`
+    assert!(dispatcher.frags());
     dispatcher.load().unwrap();
     dispatcher.attach("lo", XdpFlags::default()).unwrap();
 }
 
+#[integration_test]
+fn xdp_attach_replacing() {
+    let (major, minor, _) = kernel_version().unwrap();
+    if major < 5 || (major == 5 && minor < 7) {
+        info!(
+            "skipping as {}.{} does not meet version requirement of 5.7",
+            major, minor
+        );
+        return;
+    }
+    let bytes = include_bytes_aligned!("../../../../target/bpfel-unknown-none/release/test");
+    let mut bpf = Bpf::load(bytes).unwrap();
+    let old: &mut Xdp = bpf
+        .program_mut("test_unload_xdp")
+        .unwrap()
+        .try_into()
+        .unwrap();
+    old.load().unwrap();
+    let old_fd = old.fd().unwrap();
+
+    let bytes = include_bytes_aligned!("../../../../target/bpfel-unknown-none/release/pass");
+    let mut bpf = Bpf::load(bytes).unwrap();
+    let pass: &mut Xdp = bpf.program_mut("pass").unwrap().try_into().unwrap();
+    pass.load().unwrap();
+
+    // `old` isn't attached to the interface, so it can't be replaced.
+    assert!(matches!(
+        pass.attach_replacing("lo", &old_fd, XdpFlags::SKB_MODE),
+        Err(ProgramError::XdpError(XdpError::NetlinkError { .. }))
+    ));
+}
+
 #[integration_test]
 fn extension() {
     let (major, minor, _) = kernel_version().unwrap();
@@ -40,3 +77,67 @@ fn extension() {
     let drop_: &mut Extension = bpf.program_mut("drop").unwrap().try_into().unwrap();
     drop_.load(pass.fd().unwrap(), "xdp_pass").unwrap();
 }
+
+#[integration_test]
+fn load_programs_subset() {
+    let bytes = include_bytes_aligned!("../../../../target/bpfel-unknown-none/release/test");
+    let mut bpf = BpfLoader::new()
+        .programs(&["test_unload_xdp"])
+        .load(bytes)
+        .unwrap();
+    assert!(bpf.program("test_unload_xdp").is_some());
+    assert!(bpf.program("test_unload_kpr").is_none());
+
+    let xdp: &mut Xdp = bpf
+        .program_mut("test_unload_xdp")
+        .unwrap()
+        .try_into()
+        .unwrap();
+    xdp.load().unwrap();
+
+    BpfLoader::new()
+        .programs(&["test_unload_kpr"])
+        .load_into(&mut bpf, bytes)
+        .unwrap();
+    let kprobe: &mut KProbe = bpf
+        .program_mut("test_unload_kpr")
+        .unwrap()
+        .try_into()
+        .unwrap();
+    kprobe.load().unwrap();
+    // the program loaded first is kept
+    let xdp: &Xdp = bpf.program("test_unload_xdp").unwrap().try_into().unwrap();
+    assert!(xdp.fd().is_some());
+
+    assert!(matches!(
+        BpfLoader::new().programs(&["missing"]).load(bytes),
+        Err(BpfError::ProgramError(ProgramError::InvalidName { name })) if name == "missing"
+    ));
+}
+
+#[integration_test]
+fn reuse_map() {
+    let bytes =
+        include_bytes_aligned!("../../../../target/bpfel-unknown-none/release/multimap-btf.bpf.o");
+    let bpf = Bpf::load(bytes).unwrap();
+    let map_1 = bpf.map("map_1").unwrap().fd().unwrap();
+
+    let mut other = BpfLoader::new()
+        .reuse_map("map_1", map_1.as_fd())
+        .load(bytes)
+        .unwrap();
+    let mut shared: Array<_, u64> = Array::try_from(other.map_mut("map_1").unwrap()).unwrap();
+    shared.set(0, 42, 0).unwrap();
+    let original: Array<_, u64> = Array::try_from(bpf.map("map_1").unwrap()).unwrap();
+    assert_eq!(original.get(&0, 0).unwrap(), 42);
+
+    // the RESULTS map of this object has 2 entries instead of 1
+    let relocations =
+        include_bytes_aligned!("../../../../target/bpfel-unknown-none/release/text_64_64_reloc.o");
+    assert!(matches!(
+        BpfLoader::new()
+            .reuse_map("RESULTS", map_1.as_fd())
+            .load(relocations),
+        Err(BpfError::MapError(MapError::IncompatibleReusedMap { name })) if name == "RESULTS"
+    ));
+}